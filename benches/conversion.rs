@@ -0,0 +1,162 @@
+//! Conversion latency through the HTTP API, measured with criterion: the
+//! server binary is started on a free port and every self-test fixture is
+//! posted to `/convert` repeatedly. Engines not installed on the machine
+//! are skipped. The server's peak memory is printed after each fixture
+//! (Linux only); `/admin/bench` reports the same numbers from inside a
+//! running deployment.
+//!
+//! ```sh
+//! cargo bench --bench conversion
+//! cargo bench --bench conversion -- --save-baseline before
+//! cargo bench --bench conversion -- --baseline before
+//! ```
+//!
+//! The server inherits the environment, so e.g. `PDFMILL_ENGINES_FILE`
+//! benchmarks a custom engine.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const FIXTURES: &[(&str, &[u8])] = &[
+    (
+        "selftest.html",
+        include_bytes!("../src/selftest/fixtures/selftest.html"),
+    ),
+    (
+        "selftest.docx",
+        include_bytes!("../src/selftest/fixtures/selftest.docx"),
+    ),
+    (
+        "selftest.png",
+        include_bytes!("../src/selftest/fixtures/selftest.png"),
+    ),
+];
+
+/// How long the server may take to start its engines
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The server under test, stopped when dropped
+struct Server {
+    process: Child,
+    url: String,
+    _work_dir: tempfile::TempDir,
+}
+
+impl Server {
+    fn start(runtime: &tokio::runtime::Runtime) -> Self {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("free port")
+            .port();
+        let work_dir = tempfile::tempdir().expect("work directory");
+        let process = Command::new(env!("CARGO_BIN_EXE_pdfmill"))
+            .env("PDFMILL_ADDR", format!("127.0.0.1:{}", port))
+            .env("PDFMILL_WORK_DIR", work_dir.path())
+            .env("PDFMILL_ADMIN_ADDR", "127.0.0.1:0")
+            .env_remove("PDFMILL_ADMIN_PUBLIC")
+            .env("RUST_LOG", "warn")
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("start pdfmill");
+        let server = Self {
+            process,
+            url: format!("http://127.0.0.1:{}", port),
+            _work_dir: work_dir,
+        };
+
+        let started = Instant::now();
+        runtime.block_on(async {
+            let client = reqwest::Client::new();
+            while client
+                .get(format!("{}/health", server.url))
+                .send()
+                .await
+                .map_or(true, |response| !response.status().is_success())
+            {
+                assert!(started.elapsed() < STARTUP_TIMEOUT, "pdfmill did not start");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        });
+        server
+    }
+
+    /// Peak and current resident memory of the server process, in MB
+    fn memory(&self) -> Option<(u64, u64)> {
+        let status = std::fs::read_to_string(format!("/proc/{}/status", self.process.id())).ok()?;
+        let field = |name: &str| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix(name))?
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .map(|kb| kb / 1024)
+        };
+        Some((field("VmHWM:")?, field("VmRSS:")?))
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+async fn convert(
+    client: &reqwest::Client,
+    url: &str,
+    name: &str,
+    data: &[u8],
+) -> reqwest::Result<reqwest::Response> {
+    let part = reqwest::multipart::Part::bytes(data.to_vec()).file_name(name.to_string());
+    client
+        .post(format!("{}/convert", url))
+        .multipart(reqwest::multipart::Form::new().part("file", part))
+        .send()
+        .await
+}
+
+fn conversion(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("runtime");
+    let server = Server::start(&runtime);
+    let client = reqwest::Client::new();
+
+    let mut group = c.benchmark_group("convert");
+    // Conversions take tens to hundreds of milliseconds
+    group.sample_size(10);
+    for (name, data) in FIXTURES {
+        // Also the warm-up that starts the engine
+        let response = runtime
+            .block_on(convert(&client, &server.url, name, data))
+            .expect("pdfmill answers");
+        if !response.status().is_success() {
+            eprintln!("Skipping {}: {}", name, response.status());
+            continue;
+        }
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let response = convert(&client, &server.url, name, data)
+                        .await
+                        .and_then(|response| response.error_for_status())
+                        .expect("conversion");
+                    response.bytes().await.expect("output")
+                })
+            })
+        });
+        if let Some((peak, current)) = server.memory() {
+            eprintln!(
+                "{}: server peak memory {} MB, now {} MB",
+                name, peak, current
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, conversion);
+criterion_main!(benches);
@@ -0,0 +1,407 @@
+//! Typed client for the pdfmill conversion API. Requests go to the `/v1`
+//! paths, and options are sent as the same form fields the server parses,
+//! through the [`ConvertOptions`] type both sides share.
+//!
+//! ```no_run
+//! # async fn example() -> pdfmill_client::Result<()> {
+//! use pdfmill_client::{Client, ConvertOptions};
+//!
+//! let client = Client::new("http://localhost:3000")?;
+//! let options = ConvertOptions {
+//!     landscape: true,
+//!     page_width: Some("210mm".to_string()),
+//!     ..Default::default()
+//! };
+//! let pdf = client.convert("report.docx", std::fs::read("report.docx")?, &options).await?;
+//! std::fs::write("report.pdf", &pdf.data)?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub use pdfmill_core::{
+    Comments, ConvertOptions, DrawingScale, Geolocation, MediaType, OptionError, TrackChanges,
+    WaitUntil,
+};
+
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_TYPE};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Response, StatusCode, Url};
+use serde::Deserialize;
+
+/// Path prefix of the API version this client speaks
+const API_PREFIX: &str = "/v1";
+
+/// Header naming the engine (and its version) that produced the output
+const ENGINE_HEADER: &str = "x-pdfmill-engine";
+
+/// Header carrying the SHA-256 of the output
+const SHA256_HEADER: &str = "x-content-sha256";
+
+/// Header listing problems that did not fail the conversion
+const WARNINGS_HEADER: &str = "x-pdfmill-warnings";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid base URL: {0}")]
+    InvalidUrl(String),
+
+    #[error("Request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The server answered with an error, e.g. 400 for a rejected option
+    #[error("{status}: {message}")]
+    Api { status: StatusCode, message: String },
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A converted file as returned by /convert or a job's result
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub data: Bytes,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    /// Engine label (name/version) that produced the file
+    pub engine: Option<String>,
+    /// Lowercase hex SHA-256 of `data`, when the server sent it
+    pub sha256: Option<String>,
+    /// Problems that did not fail the conversion, e.g. an option that does
+    /// not apply to the input
+    pub warnings: Vec<String>,
+}
+
+/// Status of a background job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    /// Failed too many times to be retried
+    #[serde(rename = "dead_letter")]
+    DeadLetter,
+    /// A status added by a newer server
+    #[serde(other)]
+    Unknown,
+}
+
+impl JobStatus {
+    /// Whether the job will not change any more without a retry
+    pub fn is_finished(self) -> bool {
+        !matches!(self, Self::Queued | Self::Running)
+    }
+}
+
+/// A background job as reported by the server
+#[derive(Debug, Clone, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub status: JobStatus,
+    pub filename: String,
+    /// Engine label (name/version) once the job started
+    pub engine: Option<String>,
+    pub error: Option<String>,
+    /// Number of times the job was started, counting retries
+    pub attempts: u32,
+    /// SHA-256 of the result, once the job succeeded
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Problems that did not fail the conversion, once the job succeeded
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// When the job's files were deleted on request (RFC 3339)
+    #[serde(default)]
+    pub data_deleted_at: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    base: Url,
+    http: reqwest::Client,
+}
+
+impl Client {
+    /// Client for the server at `base_url`, e.g. "http://localhost:3000"
+    pub fn new(base_url: &str) -> Result<Self> {
+        Self::with_http_client(base_url, reqwest::Client::new())
+    }
+
+    /// Client using a configured `reqwest::Client` (timeouts, proxies, TLS)
+    pub fn with_http_client(base_url: &str, http: reqwest::Client) -> Result<Self> {
+        let base = Url::parse(base_url).map_err(|e| Error::InvalidUrl(e.to_string()))?;
+        if base.cannot_be_a_base() || !matches!(base.scheme(), "http" | "https") {
+            return Err(Error::InvalidUrl(format!(
+                "expected an http(s) URL, got '{}'",
+                base_url
+            )));
+        }
+        Ok(Self { base, http })
+    }
+
+    /// Convert a file; the server picks the engine by `filename`'s extension
+    pub async fn convert(
+        &self,
+        filename: &str,
+        data: impl Into<Vec<u8>>,
+        options: &ConvertOptions,
+    ) -> Result<Document> {
+        let response = self
+            .http
+            .post(self.url("/convert"))
+            .multipart(form(filename, data.into(), options))
+            .send()
+            .await?;
+        document(check(response).await?).await
+    }
+
+    /// Convert an HTML document given as a string
+    pub async fn convert_html(&self, html: &str, options: &ConvertOptions) -> Result<Document> {
+        let body = serde_json::json!({ "html": html, "options": json_options(options) });
+        let response = self
+            .http
+            .post(self.url("/convert/html"))
+            .json(&body)
+            .send()
+            .await?;
+        document(check(response).await?).await
+    }
+
+    /// Print a remote http(s) page, which the server loads itself
+    pub async fn convert_url(&self, url: &str, options: &ConvertOptions) -> Result<Document> {
+        let body = serde_json::json!({ "url": url, "options": json_options(options) });
+        let response = self
+            .http
+            .post(self.url("/convert/url"))
+            .json(&body)
+            .send()
+            .await?;
+        document(check(response).await?).await
+    }
+
+    /// Submit a conversion as a background job, see [`job`](Self::job)
+    pub async fn submit_job(
+        &self,
+        filename: &str,
+        data: impl Into<Vec<u8>>,
+        options: &ConvertOptions,
+    ) -> Result<Job> {
+        let response = self
+            .http
+            .post(self.url("/jobs"))
+            .multipart(form(filename, data.into(), options))
+            .send()
+            .await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    pub async fn job(&self, id: &str) -> Result<Job> {
+        let response = self
+            .http
+            .get(self.url(&format!("/jobs/{}", id)))
+            .send()
+            .await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Output of a succeeded job
+    pub async fn job_result(&self, id: &str) -> Result<Document> {
+        let response = self
+            .http
+            .get(self.url(&format!("/jobs/{}/result", id)))
+            .send()
+            .await?;
+        document(check(response).await?).await
+    }
+
+    pub async fn cancel_job(&self, id: &str) -> Result<Job> {
+        let response = self
+            .http
+            .delete(self.url(&format!("/jobs/{}", id)))
+            .send()
+            .await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Delete a job's input and output before they expire, cancelling the
+    /// job if it is unfinished
+    pub async fn delete_job_data(&self, id: &str) -> Result<Job> {
+        let response = self
+            .http
+            .delete(self.url(&format!("/jobs/{}/data", id)))
+            .send()
+            .await?;
+        Ok(check(response).await?.json().await?)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}{}{}",
+            self.base.as_str().trim_end_matches('/'),
+            API_PREFIX,
+            path
+        )
+    }
+}
+
+fn form(filename: &str, data: Vec<u8>, options: &ConvertOptions) -> Form {
+    let mut form = Form::new();
+    for (name, value) in options.form_fields() {
+        form = form.text(name, value);
+    }
+    form.part("file", Part::bytes(data).file_name(filename.to_string()))
+}
+
+/// Options as the JSON object of the JSON endpoints
+fn json_options(options: &ConvertOptions) -> serde_json::Map<String, serde_json::Value> {
+    options
+        .form_fields()
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.into()))
+        .collect()
+}
+
+/// Turn error statuses into [`Error::Api`] with the server's message
+async fn check(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await?;
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|v| v["error"].as_str().map(String::from))
+        .unwrap_or(body);
+    Err(Error::Api { status, message })
+}
+
+async fn document(response: Response) -> Result<Document> {
+    let headers = response.headers().clone();
+    Ok(Document {
+        data: response.bytes().await?,
+        filename: header(&headers, CONTENT_DISPOSITION.as_str())
+            .as_deref()
+            .and_then(attachment_filename),
+        content_type: header(&headers, CONTENT_TYPE.as_str()),
+        engine: header(&headers, ENGINE_HEADER),
+        sha256: header(&headers, SHA256_HEADER),
+        warnings: header(&headers, WARNINGS_HEADER)
+            .as_deref()
+            .map(parse_warnings)
+            .unwrap_or_default(),
+    })
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+}
+
+/// The quoted strings of a structured field list, e.g. `"a", "b \"c\""`
+fn parse_warnings(value: &str) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut chars = value.chars();
+    while chars.any(|c| c == '"') {
+        let mut warning = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => warning.extend(chars.next()),
+                c => warning.push(c),
+            }
+        }
+        warnings.push(warning);
+    }
+    warnings
+}
+
+/// `report.pdf` from `attachment; filename="report.pdf"`
+fn attachment_filename(disposition: &str) -> Option<String> {
+    let (_, rest) = disposition.split_once("filename=")?;
+    Some(rest.trim().trim_matches('"').to_string()).filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Multipart;
+    use axum::http::header;
+    use axum::response::IntoResponse;
+    use axum::routing::post;
+    use axum::Router;
+
+    /// Echoes the form fields back as the "PDF", or rejects the request
+    async fn convert(mut multipart: Multipart) -> axum::response::Response {
+        let mut fields = Vec::new();
+        while let Some(field) = multipart.next_field().await.unwrap() {
+            let name = field.name().unwrap().to_string();
+            let file_name = field.file_name().map(String::from);
+            let value = field.text().await.unwrap();
+            fields.push(format!("{}={}", name, file_name.unwrap_or(value)));
+        }
+        if fields.iter().any(|f| f == "pageWidth=big") {
+            let body = axum::Json(serde_json::json!({"error": "Invalid request: pageWidth"}));
+            return (axum::http::StatusCode::BAD_REQUEST, body).into_response();
+        }
+        (
+            [
+                (header::CONTENT_TYPE, "application/pdf"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"a.pdf\"",
+                ),
+                (header::HeaderName::from_static(ENGINE_HEADER), "chromium/1"),
+                (
+                    header::HeaderName::from_static(WARNINGS_HEADER),
+                    r#""selector does not apply", "Font \"A\" was substituted""#,
+                ),
+            ],
+            fields.join("&"),
+        )
+            .into_response()
+    }
+
+    #[tokio::test]
+    async fn test_convert() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/v1/convert", post(convert));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = Client::new(&format!("http://{}/", addr)).unwrap();
+        let options = ConvertOptions {
+            landscape: true,
+            viewport_width: Some(800),
+            ..Default::default()
+        };
+        let pdf = client.convert("a.html", "<p>", &options).await.unwrap();
+        assert_eq!(pdf.data, "landscape=true&viewportWidth=800&file=a.html");
+        assert_eq!(pdf.filename.as_deref(), Some("a.pdf"));
+        assert_eq!(pdf.engine.as_deref(), Some("chromium/1"));
+        assert_eq!(
+            pdf.warnings,
+            vec!["selector does not apply", "Font \"A\" was substituted"]
+        );
+
+        let options = ConvertOptions {
+            page_width: Some("big".to_string()),
+            ..Default::default()
+        };
+        match client.convert("a.html", "<p>", &options).await {
+            Err(Error::Api { status, message }) => {
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+                assert_eq!(message, "Invalid request: pageWidth");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert!(Client::new("localhost:3000").is_err());
+    }
+}
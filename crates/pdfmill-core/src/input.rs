@@ -0,0 +1,124 @@
+//! Validation of client-supplied strings that are not conversion options:
+//! upload file names and page ranges. Like the option parsers these are
+//! pure functions of their input, fuzzed by the targets in `fuzz/`.
+
+use crate::OptionError;
+use std::ops::RangeInclusive;
+
+type Result<T> = std::result::Result<T, OptionError>;
+
+/// Longest upload file name, in bytes; file systems allow no more
+pub const MAX_FILENAME_BYTES: usize = 255;
+
+/// Largest page number in a page range
+const MAX_PAGE: u32 = 1_000_000;
+
+/// The name an upload is stored under: the last component of the name the
+/// client sent (browsers may send a full Windows path), which has to be a
+/// plain file name
+pub fn upload_filename(raw: &str) -> Result<String> {
+    let name = raw.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    let error = |message: &str| Err(OptionError(format!("file: {}", message)));
+    if name.is_empty() || name == "." || name == ".." {
+        return error(&format!("'{}' is not a file name", raw.escape_debug()));
+    }
+    if name.chars().any(char::is_control) {
+        return error(&format!(
+            "'{}' contains control characters",
+            name.escape_debug()
+        ));
+    }
+    if name.len() > MAX_FILENAME_BYTES {
+        return error(&format!(
+            "name is {} bytes long, at most {} are allowed",
+            name.len(),
+            MAX_FILENAME_BYTES
+        ));
+    }
+    Ok(name.to_string())
+}
+
+/// Parse 1-based page ranges such as "1-5, 8, 11-13", in the order given
+pub fn parse_page_ranges(name: &str, value: &str) -> Result<Vec<RangeInclusive<u32>>> {
+    let invalid = || {
+        OptionError(format!(
+            "{}: expected pages and ranges such as '1-5, 8, 11-13', got '{}'",
+            name, value
+        ))
+    };
+    let page = |s: &str| {
+        let s = s.trim();
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse::<u32>().ok().filter(|n| (1..=MAX_PAGE).contains(n))
+    };
+
+    let mut ranges = Vec::new();
+    for part in value.split(',') {
+        let range = match part.split_once('-') {
+            Some((start, end)) => page(start).zip(page(end)),
+            None => page(part).map(|n| (n, n)),
+        };
+        match range {
+            Some((start, end)) if start <= end => ranges.push(start..=end),
+            Some(_) => {
+                return Err(OptionError(format!(
+                    "{}: range '{}' ends before it starts",
+                    name,
+                    part.trim()
+                )))
+            }
+            None => return Err(invalid()),
+        }
+    }
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_filename() {
+        assert_eq!(upload_filename("report.docx").unwrap(), "report.docx");
+        assert_eq!(upload_filename("C:\\Users\\me\\a.pdf").unwrap(), "a.pdf");
+        assert_eq!(
+            upload_filename("../../etc/cron.d/x.html").unwrap(),
+            "x.html"
+        );
+        for invalid in ["", "/", "..", "dir/", "a\0.html", "a\n.html"] {
+            assert!(upload_filename(invalid).is_err(), "{:?}", invalid);
+        }
+        let long = format!("{}.html", "a".repeat(MAX_FILENAME_BYTES));
+        assert!(upload_filename(&long)
+            .unwrap_err()
+            .0
+            .contains("at most 255"));
+    }
+
+    #[test]
+    fn test_parse_page_ranges() {
+        assert_eq!(
+            parse_page_ranges("pageRanges", "1-5, 8,11 - 13").unwrap(),
+            vec![1..=5, 8..=8, 11..=13]
+        );
+        for invalid in [
+            "",
+            "0",
+            "1-",
+            "-3",
+            "5-2",
+            "1,,2",
+            "+1",
+            "1-2-3",
+            "99999999999",
+        ] {
+            assert!(
+                parse_page_ranges("pageRanges", invalid).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! Types shared by the pdfmill server and its clients, so both read and
+//! write conversion options the same way.
+
+// The options schema is one large `json!` literal
+#![recursion_limit = "256"]
+
+mod input;
+mod options;
+
+pub use input::{parse_page_ranges, upload_filename, MAX_FILENAME_BYTES};
+pub use options::{options_schema, parse_integer, parse_to_inches};
+
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Slides per page the presentation handout layouts support
+pub const HANDOUT_LAYOUTS: &[u32] = &[1, 2, 4, 6];
+
+/// A rejected option value, e.g. "pageWidth: expected e.g. '210mm', '21cm'
+/// or '8.5in', got 'big'"
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{0}")]
+pub struct OptionError(pub String);
+
+/// Conversion options passed to engines
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConvertOptions {
+    /// Page width (e.g., "8.5in", "210mm")
+    pub page_width: Option<String>,
+    /// Page height (e.g., "11in", "297mm")
+    pub page_height: Option<String>,
+    /// Top margin
+    pub margin_top: Option<String>,
+    /// Bottom margin
+    pub margin_bottom: Option<String>,
+    /// Left margin
+    pub margin_left: Option<String>,
+    /// Right margin
+    pub margin_right: Option<String>,
+    /// Landscape orientation
+    pub landscape: bool,
+    /// Print background
+    pub print_background: bool,
+    /// Print a header and footer on every page of browser renders
+    pub display_header_footer: bool,
+    /// HTML of the page header; elements with the classes `date`, `title`,
+    /// `url`, `pageNumber` and `totalPages` are filled in by the browser
+    pub header_template: Option<String>,
+    /// HTML of the page footer, with the same classes as the header
+    pub footer_template: Option<String>,
+    /// Pages of browser renders to print, e.g. "1-5,8"; all when unset
+    pub page_ranges: Option<String>,
+    /// Zoom of browser renders when printing, 0.1 to 2
+    pub scale: Option<f64>,
+    /// Let the document's CSS `@page` size win over the page size options
+    #[serde(rename = "preferCSSPageSize")]
+    pub prefer_css_page_size: bool,
+    /// PDF/A format (e.g., "PDF/A-1b")
+    pub pdf_format: Option<String>,
+    /// Output format (file extension) when the engine supports more than PDF
+    pub output_format: Option<String>,
+    /// Slides per page for presentation handouts (1, 2, 4 or 6)
+    pub handout: Option<u32>,
+    /// Draw ruled note lines next to each handout slide
+    pub handout_notes: bool,
+    /// Export presentations as notes pages (slide with speaker notes below)
+    pub speaker_notes: bool,
+    /// Print cell gridlines of spreadsheets (unset keeps the workbook's setting)
+    pub gridlines: Option<bool>,
+    /// Print spreadsheet row and column headers (A/B/C, 1/2/3)
+    pub sheet_headers: Option<bool>,
+    /// Spreadsheet print area: "used" for each sheet's used range, or the
+    /// name of a named range
+    pub print_area: Option<String>,
+    /// Print spreadsheets too wide for a portrait page in landscape
+    pub auto_landscape: bool,
+    /// Tracked changes in Word documents: accepted unless asked to show them
+    pub track_changes: TrackChanges,
+    /// Comments in Word documents: hidden unless asked to print them
+    pub comments: Comments,
+    /// Size of CAD drawings on the page; unset keeps the imported size
+    /// unless a page size is given, which the drawing is then fitted to
+    pub drawing_scale: Option<DrawingScale>,
+    /// Browser viewport width in CSS pixels
+    pub viewport_width: Option<i64>,
+    /// Browser viewport height in CSS pixels
+    pub viewport_height: Option<i64>,
+    /// Pixel density of browser renders: sharper canvas content in PDFs
+    /// and larger screenshots; CSS pixels stay 1/96in
+    pub dpi: Option<u32>,
+    /// Chart width in CSS pixels, overriding the specification's
+    pub chart_width: Option<i64>,
+    /// Chart height in CSS pixels, overriding the specification's
+    pub chart_height: Option<i64>,
+    /// CSS selector of the only element to print (e.g., "#invoice")
+    pub selector: Option<String>,
+    /// CSS selector of an element to wait for before printing, for pages
+    /// that render after they load
+    pub wait_for_selector: Option<String>,
+    /// Which page event navigation waits for before printing
+    pub wait_until: WaitUntil,
+    /// Milliseconds to wait after the page is ready, before printing
+    pub wait_for_delay_ms: Option<u32>,
+    /// Stylesheet added to browser renders before printing, after the
+    /// page's own
+    pub custom_css: Option<String>,
+    /// Script run in browser renders before printing, once the page is
+    /// ready; a returned promise is awaited
+    pub custom_js: Option<String>,
+    /// Tera template of the HTML document Markdown is placed in, instead
+    /// of the default one
+    pub markdown_template: Option<String>,
+    /// CSS media type browser renders are laid out for; PDFs use `print`
+    /// and screenshots `screen` unless set
+    pub emulate_media: Option<MediaType>,
+    /// Check that the source's hyperlinks survived into the PDF and make
+    /// plain-text URLs clickable
+    pub link_audit: bool,
+    /// Convert the files attached to an email with their own engines and
+    /// append them after the message, each behind a separator page
+    pub convert_attachments: bool,
+    /// Embed a JSON record of the conversion (input, options, engine,
+    /// time) in the output PDF as an attached file
+    pub embed_provenance: bool,
+    /// Override dark themes with a white background and dark text
+    pub force_light_background: bool,
+    /// Start browser-rendered documents with a linked table of contents of
+    /// their `<h1>`–`<h3>` headings
+    pub toc: bool,
+    /// Add PDF bookmarks (the document outline) for the `<h1>`–`<h3>`
+    /// headings of browser-rendered documents
+    pub bookmarks: bool,
+    /// Emulated browser locale (e.g., "de_DE")
+    pub locale: Option<String>,
+    /// Locale of number and date formats in office documents that follow
+    /// the system's, as a BCP 47 tag (e.g., "de-DE")
+    pub document_locale: Option<String>,
+    /// IANA timezone (e.g., "Europe/Berlin") of the dates a conversion
+    /// prints: emulated in browsers, used for office date fields and
+    /// mailbox dates. Servers default to UTC.
+    pub timezone: Option<String>,
+    /// Emulated geolocation; when unset, location access is denied
+    pub geolocation: Option<Geolocation>,
+    /// Username answering HTTP authentication challenges
+    pub http_username: Option<String>,
+    /// Password answering HTTP authentication challenges
+    #[serde(skip_serializing)]
+    pub http_password: Option<String>,
+    /// Origin (e.g., "https://intranet.example") an uploaded document's
+    /// resources get the HTTP credentials from; remote pages send them to
+    /// their own origin
+    pub http_auth_origin: Option<String>,
+    /// Proxy for this request (e.g., "http://proxy:3128", "socks5://proxy:1080"),
+    /// overriding `CHROME_PROXY_SERVER`
+    pub proxy: Option<String>,
+    /// Cookies (name, value) a remote page is loaded with, set for its host
+    #[serde(skip_serializing)]
+    pub cookies: Vec<(String, String)>,
+    /// Headers (name, value) sent with a remote page's requests to its own
+    /// origin, e.g. a bearer token
+    #[serde(skip_serializing)]
+    pub extra_headers: Vec<(String, String)>,
+}
+
+/// Emulated device position
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Geolocation {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Accuracy in meters
+    pub accuracy: f64,
+}
+
+impl FromStr for Geolocation {
+    type Err = String;
+
+    /// Parse "lat,lon" or "lat,lon,accuracy"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<f64> = s
+            .split(',')
+            .map(|p| p.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("expected 'latitude,longitude[,accuracy]', got '{}'", s))?;
+
+        let (latitude, longitude, accuracy) = match parts[..] {
+            [lat, lon] => (lat, lon, 1.0),
+            [lat, lon, acc] => (lat, lon, acc),
+            _ => {
+                return Err(format!(
+                    "expected 'latitude,longitude[,accuracy]', got '{}'",
+                    s
+                ))
+            }
+        };
+
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return Err(format!("coordinates out of range: '{}'", s));
+        }
+        if !(accuracy >= 0.0 && accuracy.is_finite()) {
+            return Err(format!("accuracy must be a non-negative number: '{}'", s));
+        }
+
+        Ok(Self {
+            latitude,
+            longitude,
+            accuracy,
+        })
+    }
+}
+
+/// How tracked changes appear in the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackChanges {
+    /// Render the document as if every change was accepted
+    #[default]
+    Accept,
+    /// Render insertions and deletions as the document marks them
+    Show,
+}
+
+impl FromStr for TrackChanges {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "accept" => Ok(Self::Accept),
+            "show" => Ok(Self::Show),
+            other => Err(format!("expected 'accept' or 'show', got '{}'", other)),
+        }
+    }
+}
+
+/// How comments appear in the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Comments {
+    /// Leave comments out
+    #[default]
+    Hide,
+    /// Print comments in the page margin
+    Margin,
+}
+
+impl FromStr for Comments {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "hide" => Ok(Self::Hide),
+            "margin" => Ok(Self::Margin),
+            other => Err(format!("expected 'hide' or 'margin', got '{}'", other)),
+        }
+    }
+}
+
+/// When a browser page counts as loaded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WaitUntil {
+    /// The load event: the document and its subresources are loaded
+    #[default]
+    Load,
+    /// After loading, no network connections for 500ms, so requests that
+    /// scripts made (chart data) have been answered
+    NetworkIdle,
+}
+
+impl FromStr for WaitUntil {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "load" => Ok(Self::Load),
+            "networkidle" => Ok(Self::NetworkIdle),
+            other => Err(format!("expected 'load' or 'networkidle', got '{}'", other)),
+        }
+    }
+}
+
+/// CSS media type a browser page is rendered with
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaType {
+    /// `@media print` rules apply, as when printing from a browser
+    Print,
+    /// `@media screen` rules apply, so the page looks as it does on screen
+    Screen,
+}
+
+impl FromStr for MediaType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "print" => Ok(Self::Print),
+            "screen" => Ok(Self::Screen),
+            other => Err(format!("expected 'print' or 'screen', got '{}'", other)),
+        }
+    }
+}
+
+/// How a CAD drawing is sized on the page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DrawingScale {
+    /// Enlarge or shrink the drawing to fill the page inside the margins
+    Fit,
+    /// Multiply the imported drawing's size, e.g. 0.5 for half size
+    Factor(f64),
+}
+
+impl FromStr for DrawingScale {
+    type Err = String;
+
+    /// Parse "fit", a factor ("0.5") or a percentage ("50%")
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("fit") {
+            return Ok(Self::Fit);
+        }
+        let factor = match s.strip_suffix('%') {
+            Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+            None => s.parse::<f64>(),
+        };
+        match factor {
+            Ok(factor) if (0.01..=100.0).contains(&factor) => Ok(Self::Factor(factor)),
+            _ => Err(format!(
+                "expected 'fit' or a factor between 0.01 and 100 (e.g. '0.5' or '50%'), got '{}'",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DrawingScale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Fit => f.write_str("fit"),
+            Self::Factor(factor) => write!(f, "{}", factor),
+        }
+    }
+}
+
+impl Serialize for DrawingScale {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
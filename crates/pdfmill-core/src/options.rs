@@ -0,0 +1,1019 @@
+//! Parsing and validation of conversion options. Multipart fields, JSON
+//! objects and query strings all go through [`ConvertOptions::set_field`],
+//! so a bad value is rejected with the same field-level message whichever
+//! way it arrives; [`ConvertOptions::form_fields`] is its inverse for
+//! clients.
+
+use crate::{parse_page_ranges, ConvertOptions, Geolocation, OptionError, HANDOUT_LAYOUTS};
+use serde::de::{self, Deserialize, Deserializer, MapAccess, Visitor};
+use serde_json::{json, Value};
+use std::ops::RangeInclusive;
+
+type Result<T> = std::result::Result<T, OptionError>;
+
+/// Accepted `pdfFormat` values
+const PDF_FORMATS: &[&str] = &["PDF/A-1b", "PDF/A-2b", "PDF/A-3b"];
+
+/// Smallest page side, in inches
+const MIN_PAGE_INCHES: f64 = 1.0;
+
+/// Largest page side, in inches
+const MAX_PAGE_INCHES: f64 = 200.0;
+
+/// Page size when none is given (US Letter), as Chromium prints
+const DEFAULT_PAGE: (&str, &str) = ("8.5in", "11in");
+
+/// Options whose value is a JSON object, which JSON requests may also send
+/// unencoded
+const OBJECT_OPTIONS: &[&str] = &["cookies", "extraHeaders"];
+
+/// Headers `extraHeaders` may not set: the browser frames requests itself,
+/// and cookies have their own option
+const RESERVED_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "cookie",
+    "host",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Largest viewport side, in CSS pixels
+const MAX_VIEWPORT: i64 = 16384;
+
+/// Accepted `dpi` values
+const DPI_RANGE: RangeInclusive<i64> = 48..=600;
+
+/// Accepted `scale` factors, as Chromium prints them
+const SCALE_RANGE: RangeInclusive<f64> = 0.1..=2.0;
+
+/// Longest `waitForDelayMs`
+const MAX_WAIT_DELAY_MS: i64 = 30_000;
+
+/// Lengths as [`parse_to_inches`] reads them
+const LENGTH_PATTERN: &str = r"^\s*[0-9]*\.?[0-9]+\s*(in|cm|mm)?\s*$";
+
+/// Parse a dimension (e.g., "8.5in", "210mm", "21cm"; bare numbers are
+/// inches) to inches. Accepts exactly the lengths the schema's pattern
+/// matches: no sign, exponent or spelled-out infinity.
+pub fn parse_to_inches(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let (number, per_inch) = if let Some(val) = s.strip_suffix("in") {
+        (val, 1.0)
+    } else if let Some(val) = s.strip_suffix("mm") {
+        (val, 25.4)
+    } else if let Some(val) = s.strip_suffix("cm") {
+        (val, 2.54)
+    } else {
+        (s, 1.0)
+    };
+    let number = number.trim_end();
+    let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    let well_formed = match number.split_once('.') {
+        Some((whole, fraction)) => digits(whole) && !fraction.is_empty() && digits(fraction),
+        None => !number.is_empty() && digits(number),
+    };
+    if !well_formed {
+        return None;
+    }
+    number
+        .parse::<f64>()
+        .ok()
+        .map(|v| v / per_inch)
+        .filter(|v| v.is_finite())
+}
+
+impl ConvertOptions {
+    /// Set the option named by a form field (e.g. "pageWidth"); returns
+    /// false for fields that are not conversion options
+    pub fn set_field(&mut self, name: &str, value: &str) -> Result<bool> {
+        match name {
+            "landscape" => self.landscape = parse_bool(name, value)?,
+            "printBackground" => self.print_background = parse_bool(name, value)?,
+            "displayHeaderFooter" => self.display_header_footer = parse_bool(name, value)?,
+            "headerTemplate" => {
+                self.header_template = Some(value.to_string()).filter(|v| !v.trim().is_empty());
+            }
+            "footerTemplate" => {
+                self.footer_template = Some(value.to_string()).filter(|v| !v.trim().is_empty());
+            }
+            "pageRanges" => {
+                let ranges = parse_page_ranges(name, value)?;
+                let ranges: Vec<String> = ranges
+                    .iter()
+                    .map(|r| {
+                        if r.start() == r.end() {
+                            r.start().to_string()
+                        } else {
+                            format!("{}-{}", r.start(), r.end())
+                        }
+                    })
+                    .collect();
+                self.page_ranges = Some(ranges.join(","));
+            }
+            "scale" => {
+                let scale = value
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|s| SCALE_RANGE.contains(s))
+                    .ok_or_else(|| invalid(name, "a factor from 0.1 to 2", value))?;
+                self.scale = Some(scale);
+            }
+            "preferCSSPageSize" => self.prefer_css_page_size = parse_bool(name, value)?,
+            "pageWidth" => self.page_width = Some(page_length(name, value)?),
+            "pageHeight" => self.page_height = Some(page_length(name, value)?),
+            "marginTop" => self.margin_top = Some(margin(name, value)?),
+            "marginBottom" => self.margin_bottom = Some(margin(name, value)?),
+            "marginLeft" => self.margin_left = Some(margin(name, value)?),
+            "marginRight" => self.margin_right = Some(margin(name, value)?),
+            "pdfFormat" => {
+                let format = PDF_FORMATS
+                    .iter()
+                    .find(|f| f.eq_ignore_ascii_case(value.trim()))
+                    .ok_or_else(|| {
+                        invalid(name, &format!("one of {}", PDF_FORMATS.join(", ")), value)
+                    })?;
+                self.pdf_format = Some(format.to_string());
+            }
+            "viewportWidth" => self.viewport_width = Some(viewport(name, value)?),
+            "viewportHeight" => self.viewport_height = Some(viewport(name, value)?),
+            "dpi" => self.dpi = Some(parse_integer(name, value, DPI_RANGE)? as u32),
+            "chartWidth" => self.chart_width = Some(viewport(name, value)?),
+            "chartHeight" => self.chart_height = Some(viewport(name, value)?),
+            "forceLightBackground" => self.force_light_background = parse_bool(name, value)?,
+            "toc" => self.toc = parse_bool(name, value)?,
+            "bookmarks" => self.bookmarks = parse_bool(name, value)?,
+            "to" => {
+                let format = value.trim().trim_start_matches('.').to_lowercase();
+                if !format.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    return Err(invalid(name, "a file extension such as 'docx'", value));
+                }
+                self.output_format = Some(format).filter(|v| !v.is_empty());
+            }
+            "handout" => {
+                let per_page = parse_integer(name, value, 1..=6)
+                    .ok()
+                    .map(|n| n as u32)
+                    .filter(|n| HANDOUT_LAYOUTS.contains(n))
+                    .ok_or_else(|| invalid(name, "1, 2, 4 or 6 slides per page", value))?;
+                self.handout = Some(per_page);
+            }
+            "handoutNotes" => self.handout_notes = parse_bool(name, value)?,
+            "speakerNotes" => self.speaker_notes = parse_bool(name, value)?,
+            "gridlines" => self.gridlines = Some(parse_bool(name, value)?),
+            "sheetHeaders" => self.sheet_headers = Some(parse_bool(name, value)?),
+            "printArea" => {
+                let value = value.trim();
+                if !value.is_empty() {
+                    self.print_area = Some(value.to_string());
+                }
+            }
+            "autoLandscape" => self.auto_landscape = parse_bool(name, value)?,
+            "linkAudit" => self.link_audit = parse_bool(name, value)?,
+            "convertAttachments" => self.convert_attachments = parse_bool(name, value)?,
+            "embedProvenance" => self.embed_provenance = parse_bool(name, value)?,
+            "trackChanges" => {
+                self.track_changes = value.parse().map_err(|e| field_error(name, e))?;
+            }
+            "comments" => {
+                self.comments = value.parse().map_err(|e| field_error(name, e))?;
+            }
+            "drawingScale" => {
+                self.drawing_scale = Some(value.parse().map_err(|e| field_error(name, e))?);
+            }
+            "locale" => {
+                let locale = value.trim();
+                if !locale.is_empty() && !is_locale(locale) {
+                    return Err(invalid(name, "e.g. 'de_DE' or 'en-US'", value));
+                }
+                self.locale = Some(locale.to_string()).filter(|v| !v.is_empty());
+            }
+            "documentLocale" => {
+                let locale = value.trim();
+                if !locale.is_empty() && !is_locale(locale) {
+                    return Err(invalid(name, "e.g. 'de-DE' or 'fr_FR'", value));
+                }
+                // LibreOffice reads BCP 47 tags; one spelling per locale, as
+                // each gets a profile of its own
+                self.document_locale = Some(canonical_locale(locale)).filter(|v| !v.is_empty());
+            }
+            "timezone" => {
+                let timezone = value.trim();
+                if !timezone.is_empty() && timezone.parse::<chrono_tz::Tz>().is_err() {
+                    return Err(invalid(
+                        name,
+                        "an IANA timezone such as 'Europe/Berlin'",
+                        value,
+                    ));
+                }
+                self.timezone = Some(timezone.to_string()).filter(|v| !v.is_empty());
+            }
+            "geolocation" => {
+                let geo = value
+                    .parse::<Geolocation>()
+                    .map_err(|e| field_error(name, e))?;
+                self.geolocation = Some(geo);
+            }
+            "httpUsername" => {
+                self.http_username = Some(value.to_string()).filter(|v| !v.is_empty());
+            }
+            "httpPassword" => {
+                self.http_password = Some(value.to_string());
+            }
+            "httpAuthOrigin" => {
+                let origin = value.trim();
+                self.http_auth_origin = if origin.is_empty() {
+                    None
+                } else {
+                    match url::Url::parse(origin) {
+                        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+                            Some(url.origin().ascii_serialization())
+                        }
+                        _ => {
+                            return Err(invalid(
+                                name,
+                                "an http(s) origin such as 'https://intranet.example'",
+                                value,
+                            ))
+                        }
+                    }
+                };
+            }
+            "cookies" => {
+                self.cookies = string_map(name, value)?;
+                for (cookie, value) in &self.cookies {
+                    if !is_token(cookie) {
+                        return Err(field_error(
+                            name,
+                            format!("'{}' is not a cookie name", cookie),
+                        ));
+                    }
+                    if !value.bytes().all(is_cookie_octet) {
+                        return Err(field_error(
+                            name,
+                            format!("the value of '{}' needs URL encoding", cookie),
+                        ));
+                    }
+                }
+            }
+            "extraHeaders" => {
+                self.extra_headers = string_map(name, value)?;
+                let mut seen = std::collections::HashSet::new();
+                for (header, value) in &self.extra_headers {
+                    let lowercase = header.to_ascii_lowercase();
+                    if !is_token(header) {
+                        return Err(field_error(
+                            name,
+                            format!("'{}' is not a header name", header),
+                        ));
+                    }
+                    if RESERVED_HEADERS.contains(&lowercase.as_str()) {
+                        return Err(field_error(name, format!("{} cannot be set", header)));
+                    }
+                    if !seen.insert(lowercase) {
+                        return Err(field_error(name, format!("{} is given twice", header)));
+                    }
+                    if value.chars().any(|c| c.is_control() && c != '\t') {
+                        return Err(field_error(
+                            name,
+                            format!("the value of {} contains control characters", header),
+                        ));
+                    }
+                }
+            }
+            "proxy" => {
+                let proxy = value.trim();
+                if !proxy.is_empty() {
+                    let scheme = url::Url::parse(proxy).map(|url| url.scheme().to_string());
+                    if !matches!(
+                        scheme.as_deref(),
+                        Ok("http" | "https" | "socks4" | "socks5")
+                    ) {
+                        return Err(invalid(
+                            name,
+                            "e.g. 'http://proxy:3128' or 'socks5://proxy:1080'",
+                            value,
+                        ));
+                    }
+                }
+                self.proxy = Some(proxy.to_string()).filter(|v| !v.is_empty());
+            }
+            "selector" => {
+                self.selector = Some(value.to_string()).filter(|v| !v.trim().is_empty());
+            }
+            "waitForSelector" => {
+                self.wait_for_selector = Some(value.to_string()).filter(|v| !v.trim().is_empty());
+            }
+            "emulateMedia" => {
+                self.emulate_media = match value.trim() {
+                    "" => None,
+                    value => Some(value.parse().map_err(|e| field_error(name, e))?),
+                };
+            }
+            "customCss" => {
+                self.custom_css = Some(value.to_string()).filter(|v| !v.trim().is_empty());
+            }
+            "customJs" => {
+                self.custom_js = Some(value.to_string()).filter(|v| !v.trim().is_empty());
+            }
+            "markdownTemplate" => {
+                self.markdown_template = Some(value.to_string()).filter(|v| !v.trim().is_empty());
+            }
+            "waitUntil" => {
+                self.wait_until = value.parse().map_err(|e| field_error(name, e))?;
+            }
+            "waitForDelayMs" => {
+                let delay = parse_integer(name, value, 0..=MAX_WAIT_DELAY_MS)?;
+                self.wait_for_delay_ms = Some(delay as u32);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// The options differing from the defaults, as the form fields
+    /// [`set_field`](Self::set_field) reads back into the same options
+    pub fn form_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        let mut flag = |name: &'static str, value: bool| {
+            if value {
+                fields.push((name, "true".to_string()));
+            }
+        };
+        flag("landscape", self.landscape);
+        flag("printBackground", self.print_background);
+        flag("displayHeaderFooter", self.display_header_footer);
+        flag("preferCSSPageSize", self.prefer_css_page_size);
+        flag("handoutNotes", self.handout_notes);
+        flag("speakerNotes", self.speaker_notes);
+        flag("autoLandscape", self.auto_landscape);
+        flag("linkAudit", self.link_audit);
+        flag("convertAttachments", self.convert_attachments);
+        flag("embedProvenance", self.embed_provenance);
+        flag("forceLightBackground", self.force_light_background);
+        flag("toc", self.toc);
+        flag("bookmarks", self.bookmarks);
+
+        let strings = [
+            ("pageWidth", &self.page_width),
+            ("pageHeight", &self.page_height),
+            ("marginTop", &self.margin_top),
+            ("marginBottom", &self.margin_bottom),
+            ("marginLeft", &self.margin_left),
+            ("marginRight", &self.margin_right),
+            ("pdfFormat", &self.pdf_format),
+            ("to", &self.output_format),
+            ("printArea", &self.print_area),
+            ("headerTemplate", &self.header_template),
+            ("footerTemplate", &self.footer_template),
+            ("pageRanges", &self.page_ranges),
+            ("selector", &self.selector),
+            ("waitForSelector", &self.wait_for_selector),
+            ("customCss", &self.custom_css),
+            ("customJs", &self.custom_js),
+            ("markdownTemplate", &self.markdown_template),
+            ("locale", &self.locale),
+            ("documentLocale", &self.document_locale),
+            ("timezone", &self.timezone),
+            ("httpUsername", &self.http_username),
+            ("httpPassword", &self.http_password),
+            ("httpAuthOrigin", &self.http_auth_origin),
+            ("proxy", &self.proxy),
+        ];
+        for (name, value) in strings {
+            if let Some(value) = value {
+                fields.push((name, value.clone()));
+            }
+        }
+
+        let mut push = |name: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                fields.push((name, value));
+            }
+        };
+        push("handout", self.handout.map(|n| n.to_string()));
+        push("gridlines", self.gridlines.map(|b| b.to_string()));
+        push("sheetHeaders", self.sheet_headers.map(|b| b.to_string()));
+        push("drawingScale", self.drawing_scale.map(|s| s.to_string()));
+        push("dpi", self.dpi.map(|n| n.to_string()));
+        push("scale", self.scale.map(|s| s.to_string()));
+        push(
+            "waitForDelayMs",
+            self.wait_for_delay_ms.map(|n| n.to_string()),
+        );
+        push("chartWidth", self.chart_width.map(|n| n.to_string()));
+        push("chartHeight", self.chart_height.map(|n| n.to_string()));
+        push("viewportWidth", self.viewport_width.map(|n| n.to_string()));
+        push(
+            "viewportHeight",
+            self.viewport_height.map(|n| n.to_string()),
+        );
+        push(
+            "geolocation",
+            self.geolocation
+                .as_ref()
+                .map(|g| format!("{},{},{}", g.latitude, g.longitude, g.accuracy)),
+        );
+        if self.track_changes != Default::default() {
+            push("trackChanges", Some("show".to_string()));
+        }
+        if self.comments != Default::default() {
+            push("comments", Some("margin".to_string()));
+        }
+        if self.wait_until != Default::default() {
+            push("waitUntil", Some("networkidle".to_string()));
+        }
+        if let Some(media) = self.emulate_media {
+            let media = match media {
+                crate::MediaType::Print => "print",
+                crate::MediaType::Screen => "screen",
+            };
+            push("emulateMedia", Some(media.to_string()));
+        }
+        let object = |pairs: &[(String, String)]| {
+            let map: serde_json::Map<_, _> = pairs
+                .iter()
+                .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+                .collect();
+            Some(Value::Object(map).to_string()).filter(|_| !pairs.is_empty())
+        };
+        push("cookies", object(&self.cookies));
+        push("extraHeaders", object(&self.extra_headers));
+        fields
+    }
+
+    /// Check the options that only fail together: the margins have to
+    /// leave room on the page, of the given size or US Letter
+    pub fn check_layout(&self) -> Result<()> {
+        let mut width = self.page_width.as_deref().unwrap_or(DEFAULT_PAGE.0);
+        let mut height = self.page_height.as_deref().unwrap_or(DEFAULT_PAGE.1);
+        if self.landscape {
+            std::mem::swap(&mut width, &mut height);
+        }
+        let across = [
+            ("marginLeft", &self.margin_left),
+            ("marginRight", &self.margin_right),
+        ];
+        let down = [
+            ("marginTop", &self.margin_top),
+            ("marginBottom", &self.margin_bottom),
+        ];
+        for (size, direction, margins) in [(width, "wide", across), (height, "high", down)] {
+            let total: f64 = margins
+                .iter()
+                .filter_map(|(_, value)| value.as_deref().and_then(parse_to_inches))
+                .sum();
+            if total < parse_to_inches(size).unwrap_or(0.0) {
+                continue;
+            }
+            let set: Vec<String> = margins
+                .iter()
+                .filter_map(|(name, value)| value.as_ref().map(|v| format!("{} {}", name, v)))
+                .collect();
+            return Err(OptionError(format!(
+                "{} {} no room on a page {} {}",
+                set.join(" and "),
+                if set.len() == 1 { "leaves" } else { "leave" },
+                size,
+                direction
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Options as a JSON object or query string, keyed like the form fields.
+/// Scalars may be given as strings or as JSON booleans and numbers;
+/// unknown names are rejected.
+impl<'de> Deserialize<'de> for ConvertOptions {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct OptionsVisitor;
+
+        impl<'de> Visitor<'de> for OptionsVisitor {
+            type Value = ConvertOptions;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a map of conversion options")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                let mut options = ConvertOptions::default();
+                while let Some((name, value)) = map.next_entry::<String, Value>()? {
+                    let value = match value {
+                        Value::Null => continue,
+                        Value::String(s) => s,
+                        Value::Bool(_) | Value::Number(_) => value.to_string(),
+                        Value::Object(_) if OBJECT_OPTIONS.contains(&name.as_str()) => {
+                            value.to_string()
+                        }
+                        _ => {
+                            return Err(de::Error::custom(format!(
+                                "{}: expected a string, number or boolean",
+                                name
+                            )))
+                        }
+                    };
+                    let known = options
+                        .set_field(&name, &value)
+                        .map_err(de::Error::custom)?;
+                    if !known {
+                        return Err(de::Error::custom(format!("unknown option '{}'", name)));
+                    }
+                }
+                options.check_layout().map_err(de::Error::custom)?;
+                Ok(options)
+            }
+        }
+
+        deserializer.deserialize_map(OptionsVisitor)
+    }
+}
+
+/// A language with optional subtags, e.g. "de", "de_DE" or "zh-Hant-TW"
+fn is_locale(locale: &str) -> bool {
+    locale.split(['_', '-']).enumerate().all(|(i, part)| {
+        let len_ok = if i == 0 {
+            (2..=3).contains(&part.len())
+        } else {
+            (1..=8).contains(&part.len())
+        };
+        len_ok && part.chars().all(|c| c.is_ascii_alphanumeric())
+    })
+}
+
+/// A checked locale as a BCP 47 tag in its usual case: `de_de` is `de-DE`,
+/// `ZH-hant-tw` is `zh-Hant-TW`
+fn canonical_locale(locale: &str) -> String {
+    locale
+        .split(['_', '-'])
+        .enumerate()
+        .map(|(i, part)| match part.len() {
+            _ if i == 0 => part.to_ascii_lowercase(),
+            2 => part.to_ascii_uppercase(),
+            4 if part.chars().all(|c| c.is_ascii_alphabetic()) => {
+                let (first, rest) = part.split_at(1);
+                first.to_ascii_uppercase() + &rest.to_ascii_lowercase()
+            }
+            _ => part.to_ascii_lowercase(),
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// A JSON object of strings, e.g. `{"session": "abc"}`; empty clears it
+fn string_map(name: &str, value: &str) -> Result<Vec<(String, String)>> {
+    if value.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let object = serde_json::from_str::<serde_json::Map<String, Value>>(value)
+        .map_err(|_| invalid(name, "a JSON object such as {\"name\": \"value\"}", value))?;
+    object
+        .into_iter()
+        .map(|(key, value)| match value {
+            Value::String(value) => Ok((key, value)),
+            _ => Err(field_error(
+                name,
+                format!("the value of '{}' must be a string", key),
+            )),
+        })
+        .collect()
+}
+
+/// An HTTP token (RFC 9110), as header and cookie names are
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// A byte a cookie value may contain unquoted (RFC 6265)
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+}
+
+fn field_error(name: &str, message: impl std::fmt::Display) -> OptionError {
+    OptionError(format!("{}: {}", name, message))
+}
+
+/// "pageWidth: expected e.g. '210mm', got 'big'"
+fn invalid(name: &str, expected: &str, value: &str) -> OptionError {
+    field_error(name, format!("expected {}, got '{}'", expected, value))
+}
+
+/// Coerce a boolean option: true/false, 1/0 or yes/no, case-insensitive.
+/// An empty value is false, as sent by unchecked form inputs.
+fn parse_bool(name: &str, value: &str) -> Result<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" => Ok(true),
+        "false" | "0" | "no" | "" => Ok(false),
+        _ => Err(invalid(name, "true/false, 1/0 or yes/no", value)),
+    }
+}
+
+/// Coerce an integer option within `range`. Whole numbers written with a
+/// fraction ("800.0", as some JSON encoders do) are accepted.
+pub fn parse_integer(name: &str, value: &str, range: RangeInclusive<i64>) -> Result<i64> {
+    let trimmed = value.trim();
+    let number = trimmed.parse::<i64>().ok().or_else(|| {
+        trimmed
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.fract() == 0.0 && v.abs() < i64::MAX as f64)
+            .map(|v| v as i64)
+    });
+    number.filter(|n| range.contains(n)).ok_or_else(|| {
+        invalid(
+            name,
+            &format!("a whole number from {} to {}", range.start(), range.end()),
+            value,
+        )
+    })
+}
+
+fn page_length(name: &str, value: &str) -> Result<String> {
+    let range = || {
+        invalid(
+            name,
+            &format!("a size from {}in to {}in", MIN_PAGE_INCHES, MAX_PAGE_INCHES),
+            value,
+        )
+    };
+    match parse_to_inches(value) {
+        Some(inches) if (MIN_PAGE_INCHES..=MAX_PAGE_INCHES).contains(&inches) => {
+            Ok(value.trim().to_string())
+        }
+        Some(_) => Err(range()),
+        None if is_negative(value) => Err(range()),
+        None => Err(invalid(name, "e.g. '210mm', '21cm' or '8.5in'", value)),
+    }
+}
+
+fn margin(name: &str, value: &str) -> Result<String> {
+    match parse_to_inches(value) {
+        Some(inches) if (0.0..MAX_PAGE_INCHES).contains(&inches) => Ok(value.trim().to_string()),
+        Some(_) => Err(invalid(name, "a non-negative margin", value)),
+        None if is_negative(value) => Err(invalid(name, "a non-negative margin", value)),
+        None => Err(invalid(name, "e.g. '10mm', '1cm' or '0.5in'", value)),
+    }
+}
+
+/// A length written with a minus sign, ASCII or typographic
+fn is_negative(value: &str) -> bool {
+    value.trim_start().starts_with(['-', '\u{2212}'])
+}
+
+fn viewport(name: &str, value: &str) -> Result<i64> {
+    parse_integer(name, value, 1..=MAX_VIEWPORT)
+}
+
+/// JSON Schema of the conversion options, documenting the accepted
+/// spellings so client libraries coerce values the same way the server does
+pub fn options_schema() -> Value {
+    let boolean = |description: &str| {
+        json!({
+            "type": ["boolean", "string", "integer"],
+            "description": format!("{} (true/false, 1/0 or yes/no, case-insensitive)", description),
+        })
+    };
+    let length = |description: &str| {
+        json!({
+            "type": "string",
+            "pattern": LENGTH_PATTERN,
+            "description": format!("{}: a number with unit in, cm or mm (bare numbers are inches)", description),
+        })
+    };
+    let string = |description: &str| json!({ "type": "string", "description": description });
+
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "PDFMill conversion options",
+        "type": "object",
+        "additionalProperties": false,
+        "properties": {
+            "landscape": boolean("Landscape orientation"),
+            "printBackground": boolean("Print background graphics"),
+            "displayHeaderFooter": boolean("Print a header and footer on every page"),
+            "headerTemplate": string("HTML of the page header; elements with class date, title, url, pageNumber or totalPages are filled in"),
+            "footerTemplate": string("HTML of the page footer, like headerTemplate"),
+            "pageRanges": string("Pages to print, e.g. '1-5, 8, 11-13'"),
+            "scale": {
+                "type": ["number", "string"],
+                "minimum": SCALE_RANGE.start(),
+                "maximum": SCALE_RANGE.end(),
+                "description": "Zoom when printing (default 1)",
+            },
+            "preferCSSPageSize": boolean("Let the document's CSS @page size win over pageWidth/pageHeight"),
+            "pageWidth": length("Page width"),
+            "pageHeight": length("Page height"),
+            "marginTop": length("Top margin"),
+            "marginBottom": length("Bottom margin"),
+            "marginLeft": length("Left margin"),
+            "marginRight": length("Right margin"),
+            "pdfFormat": { "type": "string", "enum": PDF_FORMATS },
+            "to": string("Output format (file extension), default pdf"),
+            "viewportWidth": {
+                "type": ["integer", "string"],
+                "minimum": 1,
+                "maximum": MAX_VIEWPORT,
+                "description": "Browser viewport width in CSS pixels",
+            },
+            "viewportHeight": {
+                "type": ["integer", "string"],
+                "minimum": 1,
+                "maximum": MAX_VIEWPORT,
+                "description": "Browser viewport height in CSS pixels",
+            },
+            "dpi": {
+                "type": ["integer", "string"],
+                "minimum": DPI_RANGE.start(),
+                "maximum": DPI_RANGE.end(),
+                "description": "Pixel density of browser renders (default 96)",
+            },
+            "chartWidth": {
+                "type": ["integer", "string"],
+                "minimum": 1,
+                "maximum": MAX_VIEWPORT,
+                "description": "Chart width in CSS pixels",
+            },
+            "chartHeight": {
+                "type": ["integer", "string"],
+                "minimum": 1,
+                "maximum": MAX_VIEWPORT,
+                "description": "Chart height in CSS pixels",
+            },
+            "selector": string("CSS selector of the only element to print"),
+            "waitForSelector": string("CSS selector of an element to wait for before printing"),
+            "waitUntil": {
+                "type": "string",
+                "enum": ["load", "networkidle"],
+                "description": "Page event to wait for before printing (default load)",
+            },
+            "waitForDelayMs": {
+                "type": ["integer", "string"],
+                "minimum": 0,
+                "maximum": MAX_WAIT_DELAY_MS,
+                "description": "Milliseconds to wait after the page is ready, before printing",
+            },
+            "emulateMedia": {
+                "type": "string",
+                "enum": ["print", "screen"],
+                "description": "CSS media type of the page (default print; screen for screenshots)",
+            },
+            "customCss": string("Stylesheet added to the page before printing"),
+            "customJs": string("Script run in the page before printing; a returned promise is awaited"),
+            "markdownTemplate": string("Tera template of the HTML document Markdown is placed in; variables content, title, toc, headings, styles, filename and date"),
+            "forceLightBackground": boolean("Override dark themes"),
+            "toc": boolean("Start the document with a linked table of contents of its h1-h3 headings"),
+            "bookmarks": boolean("Add PDF bookmarks for the document's h1-h3 headings"),
+            "handout": {
+                "type": ["integer", "string"],
+                "enum": HANDOUT_LAYOUTS,
+                "description": "Slides per page for presentation handouts",
+            },
+            "handoutNotes": boolean("Draw note lines next to handout slides"),
+            "speakerNotes": boolean("Include speaker notes below each slide"),
+            "gridlines": boolean("Print spreadsheet cell gridlines"),
+            "sheetHeaders": boolean("Print spreadsheet row and column headers"),
+            "printArea": string("'used' or the name of a named range"),
+            "autoLandscape": boolean("Print wide sheets in landscape"),
+            "trackChanges": { "type": "string", "enum": ["accept", "show"] },
+            "comments": { "type": "string", "enum": ["hide", "margin"] },
+            "drawingScale": string("CAD drawing size: 'fit' to fill the page, or a factor such as '0.5' or '50%'"),
+            "locale": string("Emulated browser locale, e.g. 'de_DE'"),
+            "documentLocale": string("Locale of number and date formats in office documents, e.g. 'de-DE'"),
+            "timezone": string("IANA timezone of printed dates, e.g. 'Europe/Berlin' (default UTC)"),
+            "geolocation": string("'latitude,longitude[,accuracy]'"),
+            "httpUsername": string("Username for HTTP authentication"),
+            "httpPassword": string("Password for HTTP authentication"),
+            "httpAuthOrigin": string("Origin an uploaded document's resources get the HTTP credentials from"),
+            "proxy": string("Proxy URL (http, https, socks4 or socks5)"),
+            "cookies": {
+                "type": ["object", "string"],
+                "additionalProperties": {"type": "string"},
+                "description": "Cookies a remote page is loaded with, by name",
+            },
+            "extraHeaders": {
+                "type": ["object", "string"],
+                "additionalProperties": {"type": "string"},
+                "description": "Headers sent with a remote page's requests to its own origin",
+            },
+            "linkAudit": boolean("Audit hyperlinks and make plain-text URLs clickable"),
+            "convertAttachments": boolean("Append an email's attachments, converted, after the message"),
+            "embedProvenance": boolean("Embed a JSON record of the conversion in the PDF"),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(name: &str, value: &str) -> String {
+        ConvertOptions::default()
+            .set_field(name, value)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_field_errors() {
+        assert_eq!(
+            error("pageWidth", "big"),
+            "pageWidth: expected e.g. '210mm', '21cm' or '8.5in', got 'big'"
+        );
+        assert!(error("landscape", "maybe").contains("landscape: expected true/false"));
+        assert!(error("viewportWidth", "0").contains("viewportWidth"));
+        assert!(error("marginTop", "-1cm").contains("non-negative"));
+        assert!(error("timezone", "Mars/Olympus").contains("timezone"));
+        assert!(error("pdfFormat", "PDF/X").contains("PDF/A-1b"));
+        assert!(error("drawingScale", "0").contains("between 0.01 and 100"));
+        assert!(error("geolocation", "52.5,13.4,NaN").contains("non-negative"));
+        assert!(error("to", ". docx").contains("file extension"));
+        assert!(error("scale", "3").contains("0.1 to 2"));
+        assert!(error("pageWidth", "0.5in").contains("from 1in to 200in"));
+        assert!(error("pageHeight", "\u{2212}5mm").contains("from 1in to 200in"));
+        for malformed in ["1e3mm", "-0", "+1in", "inf", "NaNin", "5.cm", "1.2.3", "in"] {
+            assert!(parse_to_inches(malformed).is_none(), "{}", malformed);
+        }
+        assert_eq!(parse_to_inches(" .5 in "), Some(0.5));
+        assert_eq!(parse_to_inches("25.4mm"), Some(1.0));
+
+        let mut options = ConvertOptions::default();
+        assert!(options.set_field("pdfFormat", "pdf/a-2b").unwrap());
+        assert_eq!(options.pdf_format.as_deref(), Some("PDF/A-2b"));
+        assert!(options.set_field("documentLocale", "de_DE").unwrap());
+        assert_eq!(options.document_locale.as_deref(), Some("de-DE"));
+        assert!(options.set_field("documentLocale", "ZH-hant-tw").unwrap());
+        assert_eq!(options.document_locale.as_deref(), Some("zh-Hant-TW"));
+        assert!(error("documentLocale", "../de").contains("documentLocale"));
+        assert!(options
+            .set_field("httpAuthOrigin", "https://Intranet.example/wiki/")
+            .unwrap());
+        assert_eq!(
+            options.http_auth_origin.as_deref(),
+            Some("https://intranet.example")
+        );
+        assert!(error("httpAuthOrigin", "file:///etc").contains("httpAuthOrigin"));
+        assert!(options.set_field("drawingScale", "50%").unwrap());
+        assert_eq!(
+            options.drawing_scale,
+            Some(crate::DrawingScale::Factor(0.5))
+        );
+        assert!(options.set_field("pageRanges", " 1 - 3, 8").unwrap());
+        assert_eq!(options.page_ranges.as_deref(), Some("1-3,8"));
+        assert!(options
+            .set_field("cookies", r#"{"session": "abc%3D"}"#)
+            .unwrap());
+        assert_eq!(
+            options.cookies,
+            [("session".to_string(), "abc%3D".to_string())]
+        );
+        assert!(error("cookies", r#"{"session": "a b"}"#).contains("URL encoding"));
+        assert!(error("cookies", "session=abc").contains("JSON object"));
+        assert!(error("extraHeaders", r#"{"Cookie": "a=b"}"#).contains("Cookie cannot be set"));
+        assert!(error("extraHeaders", r#"{"X-Token": "a\nb"}"#).contains("control characters"));
+        assert!(error("extraHeaders", r#"{"x-a": "1", "X-A": "2"}"#).contains("twice"));
+        assert!(!options.set_field("colour", "red").unwrap());
+    }
+
+    #[test]
+    fn test_coercion() {
+        for (value, expected) in [
+            ("TRUE", true),
+            ("Yes", true),
+            ("1", true),
+            ("no", false),
+            ("0", false),
+            ("", false),
+        ] {
+            assert_eq!(
+                parse_bool("landscape", value).unwrap(),
+                expected,
+                "{}",
+                value
+            );
+        }
+        assert_eq!(
+            parse_integer("viewportWidth", " 800 ", 1..=MAX_VIEWPORT).unwrap(),
+            800
+        );
+        assert_eq!(
+            parse_integer("viewportWidth", "800.0", 1..=MAX_VIEWPORT).unwrap(),
+            800
+        );
+        assert!(parse_integer("viewportWidth", "800.5", 1..=MAX_VIEWPORT).is_err());
+
+        // Every documented option is one the parser knows
+        let schema = options_schema();
+        for name in schema["properties"].as_object().unwrap().keys() {
+            let known = !matches!(ConvertOptions::default().set_field(name, "?"), Ok(false));
+            assert!(known, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let options: ConvertOptions = serde_json::from_value(serde_json::json!({
+            "landscape": true,
+            "viewportWidth": 800,
+            "marginTop": "1cm",
+            "locale": null,
+            "extraHeaders": {"Authorization": "Bearer abc"},
+        }))
+        .unwrap();
+        assert!(options.landscape);
+        assert_eq!(options.extra_headers.len(), 1);
+        assert_eq!(options.viewport_width, Some(800));
+        assert_eq!(options.margin_top.as_deref(), Some("1cm"));
+
+        let e = serde_json::from_value::<ConvertOptions>(serde_json::json!({"pageWidth": "big"}));
+        assert!(e
+            .unwrap_err()
+            .to_string()
+            .starts_with("pageWidth: expected"));
+        let e = serde_json::from_value::<ConvertOptions>(serde_json::json!({"colour": "red"}));
+        assert!(e
+            .unwrap_err()
+            .to_string()
+            .contains("unknown option 'colour'"));
+    }
+
+    #[test]
+    fn test_check_layout() {
+        let options = |fields: &[(&str, &str)]| {
+            let mut options = ConvertOptions::default();
+            for (name, value) in fields {
+                options.set_field(name, value).unwrap();
+            }
+            options.check_layout().map_err(|e| e.to_string())
+        };
+        assert!(options(&[("marginLeft", "4in"), ("marginRight", "4in")]).is_ok());
+        assert_eq!(
+            options(&[("marginLeft", "5in"), ("marginRight", "4in")]).unwrap_err(),
+            "marginLeft 5in and marginRight 4in leave no room on a page 8.5in wide"
+        );
+        assert_eq!(
+            options(&[
+                ("pageWidth", "100mm"),
+                ("landscape", "true"),
+                ("marginTop", "10cm")
+            ])
+            .unwrap_err(),
+            "marginTop 10cm leaves no room on a page 100mm high"
+        );
+        let e = serde_json::from_value::<ConvertOptions>(serde_json::json!({
+            "pageHeight": "2in",
+            "marginTop": "1in",
+            "marginBottom": "1in",
+        }));
+        assert!(e
+            .unwrap_err()
+            .to_string()
+            .contains("no room on a page 2in high"));
+    }
+
+    #[test]
+    fn test_form_fields() {
+        let options = ConvertOptions {
+            landscape: true,
+            page_width: Some("210mm".to_string()),
+            display_header_footer: true,
+            footer_template: Some("<span class=\"pageNumber\"></span>".to_string()),
+            page_ranges: Some("1-3,8".to_string()),
+            scale: Some(0.75),
+            prefer_css_page_size: true,
+            handout: Some(4),
+            gridlines: Some(false),
+            viewport_width: Some(1280),
+            dpi: Some(192),
+            chart_height: Some(300),
+            wait_for_selector: Some("#chart svg".to_string()),
+            wait_until: crate::WaitUntil::NetworkIdle,
+            custom_css: Some("nav { display: none }".to_string()),
+            markdown_template: Some("<main>{{ content | safe }}</main>".to_string()),
+            emulate_media: Some(crate::MediaType::Screen),
+            wait_for_delay_ms: Some(500),
+            geolocation: Some("52.5,13.4".parse().unwrap()),
+            track_changes: crate::TrackChanges::Show,
+            drawing_scale: Some(crate::DrawingScale::Factor(0.25)),
+            http_password: Some("secret".to_string()),
+            http_auth_origin: Some("https://intranet.example:8443".to_string()),
+            cookies: vec![("session".to_string(), "abc".to_string())],
+            extra_headers: vec![("Authorization".to_string(), "Bearer abc".to_string())],
+            convert_attachments: true,
+            embed_provenance: true,
+            bookmarks: true,
+            ..Default::default()
+        };
+        let mut parsed = ConvertOptions::default();
+        for (name, value) in options.form_fields() {
+            assert!(parsed.set_field(name, &value).unwrap(), "{}", name);
+        }
+        assert_eq!(parsed, options);
+        assert!(ConvertOptions::default().form_fields().is_empty());
+    }
+}
@@ -0,0 +1,23 @@
+//! Accepted upload names are a single plain path component, and accepting
+//! a name again leaves it unchanged.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pdfmill_core::{upload_filename, MAX_FILENAME_BYTES};
+use std::path::{Component, Path};
+
+fuzz_target!(|data: &str| {
+    let Ok(name) = upload_filename(data) else {
+        return;
+    };
+    assert!(!name.is_empty() && name.len() <= MAX_FILENAME_BYTES);
+    assert!(!name.contains(['/', '\\']) && !name.chars().any(char::is_control));
+    let components: Vec<_> = Path::new(&name).components().collect();
+    assert!(
+        matches!(components[..], [Component::Normal(_)]),
+        "{:?}",
+        name
+    );
+    assert_eq!(upload_filename(&name).unwrap(), name);
+});
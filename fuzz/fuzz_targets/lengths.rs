@@ -0,0 +1,36 @@
+//! Page lengths parse to a finite, non-negative number of inches exactly
+//! when they match the pattern the options schema publishes.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pdfmill_core::{options_schema, parse_to_inches};
+use regex::Regex;
+use std::sync::LazyLock;
+
+static PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    let schema = options_schema();
+    Regex::new(
+        schema["properties"]["pageWidth"]["pattern"]
+            .as_str()
+            .unwrap(),
+    )
+    .unwrap()
+});
+
+fuzz_target!(|data: &str| {
+    match parse_to_inches(data) {
+        Some(inches) => {
+            assert!(
+                inches.is_finite() && inches >= 0.0,
+                "{:?} -> {}",
+                data,
+                inches
+            );
+            assert!(PATTERN.is_match(data), "{:?}", data);
+        }
+        // Only numbers too large for an f64 match the pattern yet have no size
+        None if PATTERN.is_match(data) => assert!(data.len() > 300, "{:?}", data),
+        None => {}
+    }
+});
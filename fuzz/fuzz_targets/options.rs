@@ -0,0 +1,23 @@
+//! Any field name and value: setting an option never panics, and the
+//! options it produces survive the round trip through their form fields.
+//! Input is the field name, a newline, then the value.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pdfmill_core::ConvertOptions;
+
+fuzz_target!(|data: &str| {
+    let (name, value) = data.split_once('\n').unwrap_or((data, ""));
+    let mut options = ConvertOptions::default();
+    if let Ok(true) = options.set_field(name, value) {
+        let mut parsed = ConvertOptions::default();
+        for (name, value) in options.form_fields() {
+            assert!(parsed.set_field(name, &value).unwrap(), "{}", name);
+        }
+        assert_eq!(parsed, options);
+    }
+
+    // The same parser behind JSON objects
+    let _ = serde_json::from_str::<ConvertOptions>(data);
+});
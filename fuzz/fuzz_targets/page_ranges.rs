@@ -0,0 +1,20 @@
+//! Parsed page ranges are non-empty, 1-based and ascending within each
+//! range.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pdfmill_core::parse_page_ranges;
+
+fuzz_target!(|data: &str| {
+    if let Ok(ranges) = parse_page_ranges("pageRanges", data) {
+        assert!(!ranges.is_empty());
+        for range in ranges {
+            assert!(
+                *range.start() >= 1 && range.start() <= range.end(),
+                "{:?}",
+                range
+            );
+        }
+    }
+});
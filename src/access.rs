@@ -0,0 +1,188 @@
+//! IP-based access control, applied to every request before its body is read
+
+use crate::error::AppError;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Allow and deny lists of networks; an empty allow list allows everyone
+/// not denied
+#[derive(Debug, Default)]
+pub struct AccessPolicy {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+    /// Proxies whose `X-Forwarded-For` entries are believed
+    trusted_proxies: Vec<IpNet>,
+}
+
+impl AccessPolicy {
+    /// Configured by `PDFMILL_ALLOW_IPS`, `PDFMILL_DENY_IPS` and
+    /// `PDFMILL_TRUSTED_PROXIES`, each a comma-separated list of addresses
+    /// or CIDR networks. Invalid entries are fatal so a typo never opens
+    /// the service up.
+    pub fn from_env() -> std::result::Result<Self, String> {
+        let policy = Self {
+            allow: networks_from_env("PDFMILL_ALLOW_IPS")?,
+            deny: networks_from_env("PDFMILL_DENY_IPS")?,
+            trusted_proxies: networks_from_env("PDFMILL_TRUSTED_PROXIES")?,
+        };
+        if policy.is_active() {
+            info!(
+                "IP access control: {} allowed, {} denied networks, {} trusted proxies",
+                policy.allow.len(),
+                policy.deny.len(),
+                policy.trusted_proxies.len()
+            );
+        }
+        Ok(policy)
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.allow.is_empty() || !self.deny.is_empty()
+    }
+
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        let ip = canonical(ip);
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+
+    /// The client address: the peer, or when the peer is a trusted proxy,
+    /// the right-most `X-Forwarded-For` entry not added by a trusted proxy
+    pub fn client_ip(&self, peer: IpAddr, forwarded_for: &[&str]) -> IpAddr {
+        let mut client = canonical(peer);
+        let hops = forwarded_for
+            .iter()
+            .rev()
+            .flat_map(|header| header.rsplit(','))
+            .map(str::trim);
+        for hop in hops {
+            if !self.is_trusted(client) {
+                break;
+            }
+            match hop.parse::<IpAddr>() {
+                Ok(ip) => client = canonical(ip),
+                // An unparsable entry ends the chain that can be believed
+                Err(_) => break,
+            }
+        }
+        client
+    }
+
+    /// The client address of a request from `peer`; see [`Self::client_ip`]
+    pub fn request_client(&self, headers: &HeaderMap, peer: IpAddr) -> IpAddr {
+        let forwarded_for: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        self.client_ip(peer, &forwarded_for)
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// Reject requests from clients the policy does not permit
+pub async fn enforce(
+    State(policy): State<Arc<AccessPolicy>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if policy.is_active() {
+        let client = policy.request_client(request.headers(), peer.ip());
+        if !policy.permits(client) {
+            warn!(
+                "Rejected request from {} to {}",
+                client,
+                request.uri().path()
+            );
+            return AppError::Forbidden(format!("Access denied for {}", client)).into_response();
+        }
+    }
+    next.run(request).await
+}
+
+fn networks_from_env(name: &str) -> std::result::Result<Vec<IpNet>, String> {
+    let Ok(value) = std::env::var(name) else {
+        return Ok(Vec::new());
+    };
+    parse_networks(&value).map_err(|e| format!("{}: {}", name, e))
+}
+
+/// Parse "10.0.0.0/8, 192.168.1.7, ::1"; single addresses are host networks
+fn parse_networks(value: &str) -> std::result::Result<Vec<IpNet>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("invalid address or network '{}'", entry))
+        })
+        .collect()
+}
+
+/// IPv4-mapped IPv6 addresses (from dual-stack sockets) as plain IPv4
+pub(crate) fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        v4 => v4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_allow_deny() {
+        let policy = AccessPolicy {
+            allow: parse_networks("10.0.0.0/8, 192.168.1.7").unwrap(),
+            deny: parse_networks("10.0.13.0/24").unwrap(),
+            ..Default::default()
+        };
+        assert!(policy.permits(ip("10.1.2.3")));
+        assert!(policy.permits(ip("192.168.1.7")));
+        assert!(policy.permits(ip("::ffff:10.1.2.3")));
+        assert!(!policy.permits(ip("10.0.13.5")));
+        assert!(!policy.permits(ip("192.168.1.8")));
+        assert!(parse_networks("10.0.0.0/33").is_err());
+    }
+
+    #[test]
+    fn test_client_ip_behind_proxies() {
+        let policy = AccessPolicy {
+            trusted_proxies: parse_networks("172.16.0.0/12").unwrap(),
+            ..Default::default()
+        };
+        // The client cannot spoof its way past the entry the proxy added
+        let forwarded = ["6.6.6.6, 203.0.113.9", "172.16.0.2"];
+        assert_eq!(
+            policy.client_ip(ip("172.16.0.1"), &forwarded),
+            ip("203.0.113.9")
+        );
+        // Headers from untrusted peers are ignored
+        assert_eq!(
+            policy.client_ip(ip("198.51.100.1"), &forwarded),
+            ip("198.51.100.1")
+        );
+    }
+}
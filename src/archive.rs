@@ -0,0 +1,98 @@
+//! ZIP archives streamed to the client while their entries are produced
+
+use crate::error::{AppError, Result};
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use axum::body::Body;
+use bytes::Bytes;
+use futures::StreamExt;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::io::ReaderStream;
+use tracing::warn;
+
+/// Bytes buffered between the ZIP writer and the response body
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// A named file inside the archive
+pub type Entry = (String, Vec<u8>);
+
+/// Response body streaming a ZIP of `first` and every entry received after
+/// it. Each entry is compressed and sent as soon as it arrives, so memory use
+/// is bounded by the entries in flight rather than the whole archive. A
+/// producer error aborts the body, so the client sees a failed download
+/// instead of a silently truncated archive.
+pub fn zip_body(first: Entry, mut entries: mpsc::Receiver<Result<Entry>>) -> Body {
+    let (writer, reader) = tokio::io::duplex(PIPE_CAPACITY);
+    let (error_tx, error_rx) = oneshot::channel::<String>();
+
+    tokio::spawn(async move {
+        let result: Result<()> = async move {
+            let mut zip = ZipFileWriter::with_tokio(writer);
+            let mut next = Some(Ok(first));
+            while let Some(entry) = next {
+                let (name, data) = entry?;
+                let entry = ZipEntryBuilder::new(name.into(), Compression::Deflate);
+                zip.write_entry_whole(entry, &data)
+                    .await
+                    .map_err(zip_error)?;
+                next = entries.recv().await;
+            }
+            zip.close().await.map_err(zip_error)?;
+            Ok(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            warn!("ZIP stream aborted: {}", e);
+            let _ = error_tx.send(e.to_string());
+        }
+    });
+
+    let failure = futures::stream::once(async move { error_rx.await.ok() })
+        .filter_map(|e| async move { e.map(|e| Err::<Bytes, _>(std::io::Error::other(e))) });
+    Body::from_stream(ReaderStream::new(reader).chain(failure))
+}
+
+fn zip_error(e: async_zip::error::ZipError) -> AppError {
+    AppError::Internal(format!("Failed to write ZIP: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[tokio::test]
+    async fn test_zip_body_streams_entries() {
+        let (tx, rx) = mpsc::channel(2);
+        let body = zip_body(("a.txt".to_string(), b"first".to_vec()), rx);
+        tokio::spawn(async move {
+            tx.send(Ok(("b.txt".to_string(), b"second".to_vec())))
+                .await
+                .unwrap();
+        });
+
+        let data = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data)).unwrap();
+        assert_eq!(archive.len(), 2);
+        let mut contents = String::new();
+        archive
+            .by_name("b.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "second");
+    }
+
+    #[tokio::test]
+    async fn test_zip_body_aborts_on_error() {
+        let (tx, rx) = mpsc::channel(2);
+        let body = zip_body(("a.txt".to_string(), b"first".to_vec()), rx);
+        tx.send(Err(AppError::ConversionFailed("boom".to_string())))
+            .await
+            .unwrap();
+        drop(tx);
+
+        assert!(axum::body::to_bytes(body, usize::MAX).await.is_err());
+    }
+}
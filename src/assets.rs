@@ -0,0 +1,234 @@
+//! Shared static assets (logos, fonts, stylesheets) uploaded once and
+//! referenced from any HTML document or template as `/assets/<name>`,
+//! instead of inlining base64 blobs in every request. Chromium serves these
+//! URLs from the store while rendering.
+
+use crate::error::{AppError, Result};
+use crate::templates::check_name;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock, RwLock};
+use tracing::{info, warn};
+
+/// Path under which documents refer to shared assets
+pub const URL_PREFIX: &str = "/assets/";
+
+struct Asset {
+    data: Bytes,
+    updated_at: DateTime<Utc>,
+}
+
+/// Public view of an asset, as returned by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetInfo {
+    pub name: String,
+    /// URL to use in documents
+    pub url: String,
+    pub content_type: String,
+    pub size: usize,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// In-memory asset store, optionally persisted to a directory
+pub struct AssetStore {
+    assets: RwLock<BTreeMap<String, Asset>>,
+    dir: Option<PathBuf>,
+}
+
+/// The store rendering engines resolve `/assets/` URLs from
+static SHARED: OnceLock<Arc<AssetStore>> = OnceLock::new();
+
+/// Content type of an asset, from its name
+pub fn content_type(name: &str) -> String {
+    mime_guess::from_path(name)
+        .first_or_octet_stream()
+        .to_string()
+}
+
+impl AssetStore {
+    /// Assets are kept in `PDFMILL_ASSETS_DIR` when set, otherwise they are
+    /// lost on restart
+    pub fn from_env() -> Self {
+        let dir = std::env::var("PDFMILL_ASSETS_DIR")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(PathBuf::from);
+        let store = Self {
+            assets: RwLock::new(BTreeMap::new()),
+            dir,
+        };
+        store.load();
+        store
+    }
+
+    fn load(&self) {
+        let Some(dir) = &self.dir else { return };
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                warn!("Failed to read assets directory {}: {}", dir.display(), e);
+                return;
+            }
+        };
+
+        let mut assets = self.assets.write().unwrap();
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if check_name("asset", &name).is_err() {
+                continue;
+            }
+            let read = std::fs::read(entry.path()).and_then(|data| {
+                let modified = entry.metadata()?.modified()?;
+                Ok(Asset {
+                    data: data.into(),
+                    updated_at: modified.into(),
+                })
+            });
+            match read {
+                Ok(asset) => {
+                    assets.insert(name, asset);
+                }
+                Err(e) => warn!("Skipping stored asset {}: {}", name, e),
+            }
+        }
+        info!("Loaded {} assets from {}", assets.len(), dir.display());
+    }
+
+    /// Make this the store `/assets/` URLs are resolved from while rendering
+    pub fn share(self: &Arc<Self>) {
+        let _ = SHARED.set(Arc::clone(self));
+    }
+
+    /// Store an asset, replacing any of the same name; true when it is new
+    pub fn put(&self, name: &str, data: Bytes) -> Result<(AssetInfo, bool)> {
+        check_name("asset", name)?;
+        if let Some(dir) = &self.dir {
+            std::fs::create_dir_all(dir)?;
+            // Replace the file atomically so a render never sees half of it
+            let tmp = dir.join(format!(".{}.tmp", name));
+            std::fs::write(&tmp, &data)?;
+            std::fs::rename(&tmp, dir.join(name))?;
+        }
+        let asset = Asset {
+            data,
+            updated_at: Utc::now(),
+        };
+        let info = info(name, &asset);
+        let created = self
+            .assets
+            .write()
+            .unwrap()
+            .insert(name.to_string(), asset)
+            .is_none();
+        info!("Stored asset {} ({} bytes)", name, info.size);
+        Ok((info, created))
+    }
+
+    /// The asset's content and content type
+    pub fn get(&self, name: &str) -> Result<(Bytes, String)> {
+        self.assets
+            .read()
+            .unwrap()
+            .get(name)
+            .map(|asset| (asset.data.clone(), content_type(name)))
+            .ok_or_else(|| not_found(name))
+    }
+
+    /// All assets, by name
+    pub fn list(&self) -> Vec<AssetInfo> {
+        self.assets
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, asset)| info(name, asset))
+            .collect()
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        self.assets
+            .write()
+            .unwrap()
+            .remove(name)
+            .ok_or_else(|| not_found(name))?;
+        if let Some(dir) = &self.dir {
+            std::fs::remove_file(dir.join(name))?;
+        }
+        info!("Deleted asset {}", name);
+        Ok(())
+    }
+}
+
+fn info(name: &str, asset: &Asset) -> AssetInfo {
+    AssetInfo {
+        name: name.to_string(),
+        url: format!("{}{}", URL_PREFIX, name),
+        content_type: content_type(name),
+        size: asset.data.len(),
+        updated_at: asset.updated_at,
+    }
+}
+
+fn not_found(name: &str) -> AppError {
+    AppError::NotFound(format!("Asset {} not found", name))
+}
+
+/// Whether a store is shared with the rendering engines
+pub fn is_shared() -> bool {
+    SHARED.get().is_some()
+}
+
+/// The shared asset a rendered document's request is for: documents are
+/// loaded from `file://` URLs, so `/assets/logo.png` arrives as
+/// `file:///assets/logo.png`. None when no store is shared or the URL is
+/// not an asset URL; an unknown asset is an error.
+pub fn resolve(url: &str) -> Option<Result<(Bytes, String)>> {
+    let store = SHARED.get()?;
+    let path = url.strip_prefix("file://")?;
+    let name = path.strip_prefix(URL_PREFIX)?;
+    // Ignore query strings and fragments, e.g. font URLs with ?v=2
+    let name = name.split(['?', '#']).next().unwrap_or_default();
+    Some(store.get(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AssetStore {
+            assets: RwLock::new(BTreeMap::new()),
+            dir: Some(dir.path().to_path_buf()),
+        };
+        let (info, created) = store.put("logo.png", Bytes::from_static(b"png")).unwrap();
+        assert!(created);
+        assert_eq!(info.url, "/assets/logo.png");
+        assert_eq!(info.content_type, "image/png");
+        assert!(
+            !store
+                .put("logo.png", Bytes::from_static(b"png2"))
+                .unwrap()
+                .1
+        );
+        assert!(store.put("../logo.png", Bytes::new()).is_err());
+
+        let reloaded = AssetStore {
+            assets: RwLock::new(BTreeMap::new()),
+            dir: Some(dir.path().to_path_buf()),
+        };
+        reloaded.load();
+        assert_eq!(
+            reloaded.get("logo.png").unwrap().0,
+            Bytes::from_static(b"png2")
+        );
+
+        store.delete("logo.png").unwrap();
+        assert!(!dir.path().join("logo.png").exists());
+        assert!(matches!(store.get("logo.png"), Err(AppError::NotFound(_))));
+    }
+}
@@ -0,0 +1,375 @@
+//! Conversion benchmarks: every available engine converts its self-test
+//! fixture a number of times, reporting latency percentiles and the peak
+//! memory of the server with its engine processes. Posting an earlier
+//! report back as the baseline flags engines that got slower or hungrier,
+//! so changes such as a browser pool can be measured on the hardware that
+//! runs them.
+
+use crate::engines::{ConvertEngine, ConvertOptions};
+use crate::error::{AppError, Result};
+use crate::router::SmartRouter;
+use crate::selftest::fixture_for;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Timed conversions per engine unless the request says otherwise
+const DEFAULT_ITERATIONS: u32 = 5;
+
+/// Most timed conversions per engine in one run
+const MAX_ITERATIONS: u32 = 100;
+
+/// Slowdown or memory growth over the baseline reported as a regression
+const DEFAULT_TOLERANCE: f64 = 0.2;
+
+/// How often memory is sampled during a conversion
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Body of a bench request; every field is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BenchRequest {
+    /// Timed conversions per engine, after one untimed warm-up
+    iterations: Option<u32>,
+    /// Names of the engines to run, all available ones when unset
+    engines: Option<Vec<String>>,
+    /// An earlier report to compare against
+    baseline: Option<BenchReport>,
+    /// Allowed slowdown or memory growth over the baseline, e.g. 0.2 for 20%
+    tolerance: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchReport {
+    pub engines: Vec<EngineBench>,
+    /// Engines slower or larger than the baseline allows, one line each
+    #[serde(default)]
+    pub regressions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EngineBench {
+    pub engine: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub fixture: String,
+    pub iterations: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<Latency>,
+    /// Peak resident memory of the server and its child processes while
+    /// the engine converted, in bytes (Linux only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_memory_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Conversion times in milliseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Latency {
+    pub min_ms: u64,
+    pub median_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+    pub mean_ms: u64,
+}
+
+impl Latency {
+    fn from_samples(samples: &mut [u64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let at = |quantile: f64| {
+            let rank = (quantile * samples.len() as f64).ceil() as usize;
+            samples[rank.clamp(1, samples.len()) - 1]
+        };
+        Some(Self {
+            min_ms: samples[0],
+            median_ms: at(0.5),
+            p95_ms: at(0.95),
+            max_ms: samples[samples.len() - 1],
+            mean_ms: samples.iter().sum::<u64>() / samples.len() as u64,
+        })
+    }
+}
+
+/// Benchmark the requested engines one after another
+pub async fn run(router: &SmartRouter, request: BenchRequest) -> Result<BenchReport> {
+    let iterations = request.iterations.unwrap_or(DEFAULT_ITERATIONS);
+    if !(1..=MAX_ITERATIONS).contains(&iterations) {
+        return Err(AppError::InvalidRequest(format!(
+            "iterations: expected 1 to {}, got {}",
+            MAX_ITERATIONS, iterations
+        )));
+    }
+    let tolerance = request.tolerance.unwrap_or(DEFAULT_TOLERANCE);
+    if !(tolerance.is_finite() && tolerance >= 0.0) {
+        return Err(AppError::InvalidRequest(
+            "tolerance: expected a non-negative fraction, e.g. 0.2".to_string(),
+        ));
+    }
+
+    let selected = |engine: &dyn ConvertEngine| match &request.engines {
+        Some(names) => names
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(engine.engine_type().name())),
+        None => true,
+    };
+    let mut engines = Vec::new();
+    for engine in router.engines() {
+        let engine = engine.as_ref();
+        let Some((fixture, data)) = fixture_for(engine) else {
+            continue;
+        };
+        if !selected(engine) || !router.is_available(&engine.engine_type()) {
+            continue;
+        }
+        engines.push(bench_engine(router, engine, fixture, data, iterations).await);
+    }
+
+    let regressions = match &request.baseline {
+        Some(baseline) => compare(&engines, baseline, tolerance),
+        None => Vec::new(),
+    };
+    Ok(BenchReport {
+        engines,
+        regressions,
+    })
+}
+
+async fn bench_engine(
+    router: &SmartRouter,
+    engine: &dyn ConvertEngine,
+    fixture: &str,
+    data: &[u8],
+    iterations: u32,
+) -> EngineBench {
+    let mut bench = EngineBench {
+        engine: engine.engine_type().name().to_string(),
+        version: router.engine_version(&engine.engine_type()),
+        fixture: fixture.to_string(),
+        iterations,
+        latency: None,
+        peak_memory_bytes: None,
+        error: None,
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let sampler = {
+        let stop = Arc::clone(&stop);
+        tokio::task::spawn_blocking(move || {
+            let mut peak = None;
+            while !stop.load(Ordering::Relaxed) {
+                peak = peak.max(process_tree_rss());
+                std::thread::sleep(SAMPLE_INTERVAL);
+            }
+            peak
+        })
+    };
+
+    let mut samples = Vec::new();
+    // The first conversion warms caches and launches tools; it is not timed
+    for i in 0..=iterations {
+        let started = Instant::now();
+        if let Err(e) = convert(router, engine, fixture, data).await {
+            bench.error = Some(e.to_string());
+            break;
+        }
+        if i > 0 {
+            samples.push(started.elapsed().as_millis() as u64);
+        }
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    bench.peak_memory_bytes = sampler.await.ok().flatten();
+    bench.latency = Latency::from_samples(&mut samples);
+    bench
+}
+
+async fn convert(
+    router: &SmartRouter,
+    engine: &dyn ConvertEngine,
+    fixture: &str,
+    data: &[u8],
+) -> Result<()> {
+    let temp_dir = crate::disk::tempdir()?;
+    let input_path = temp_dir.path().join(fixture);
+    tokio::fs::write(&input_path, data).await?;
+    let _slot = router.conversion_slot().await;
+    engine
+        .convert(&input_path, &ConvertOptions::default())
+        .await
+        .map(|_| ())
+}
+
+/// Regressions of `engines` against the baseline's results for the same
+/// engine and fixture
+fn compare(engines: &[EngineBench], baseline: &BenchReport, tolerance: f64) -> Vec<String> {
+    let previous: HashMap<_, _> = baseline
+        .engines
+        .iter()
+        .map(|bench| ((bench.engine.as_str(), bench.fixture.as_str()), bench))
+        .collect();
+    let limit = |before: u64| before as f64 * (1.0 + tolerance);
+    let growth = |before: u64, now: u64| (now as f64 / before.max(1) as f64 - 1.0) * 100.0;
+
+    let mut regressions = Vec::new();
+    for bench in engines {
+        let Some(before) = previous.get(&(bench.engine.as_str(), bench.fixture.as_str())) else {
+            continue;
+        };
+        if let (Some(now), Some(then)) = (&bench.latency, &before.latency) {
+            if now.median_ms as f64 > limit(then.median_ms) {
+                regressions.push(format!(
+                    "{}: median {}ms, baseline {}ms (+{:.0}%)",
+                    bench.engine,
+                    now.median_ms,
+                    then.median_ms,
+                    growth(then.median_ms, now.median_ms)
+                ));
+            }
+        }
+        if let (Some(now), Some(then)) = (bench.peak_memory_bytes, before.peak_memory_bytes) {
+            if now as f64 > limit(then) {
+                regressions.push(format!(
+                    "{}: peak memory {} MiB, baseline {} MiB (+{:.0}%)",
+                    bench.engine,
+                    now / (1024 * 1024),
+                    then / (1024 * 1024),
+                    growth(then, now)
+                ));
+            }
+        }
+        if bench.error.is_some() && before.error.is_none() {
+            regressions.push(format!("{}: failed, baseline succeeded", bench.engine));
+        }
+    }
+    regressions
+}
+
+/// Resident memory of this process and all its descendants (engine tools,
+/// the browser), in bytes; `None` without `/proc`
+fn process_tree_rss() -> Option<u64> {
+    let mut parents = HashMap::new();
+    for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+        let Ok(pid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+            continue;
+        };
+        let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+        // The command name may contain spaces; the fields after it are
+        // state and parent pid
+        let ppid = stat
+            .rsplit_once(')')
+            .and_then(|(_, fields)| fields.split_whitespace().nth(1)?.parse::<u32>().ok());
+        if let Some(ppid) = ppid {
+            parents.insert(pid, ppid);
+        }
+    }
+
+    let root = std::process::id();
+    let in_tree = |mut pid: u32| loop {
+        if pid == root {
+            return true;
+        }
+        match parents.get(&pid) {
+            Some(&ppid) if ppid != 0 && ppid != pid => pid = ppid,
+            _ => return false,
+        }
+    };
+    let total = parents
+        .keys()
+        .filter(|pid| in_tree(**pid))
+        .filter_map(|pid| resident_bytes(*pid))
+        .sum();
+    Some(total)
+}
+
+fn resident_bytes(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bench(engine: &str, median_ms: u64, peak_memory_bytes: u64) -> EngineBench {
+        EngineBench {
+            engine: engine.to_string(),
+            version: None,
+            fixture: "selftest.html".to_string(),
+            iterations: 5,
+            latency: Some(Latency {
+                min_ms: median_ms,
+                median_ms,
+                p95_ms: median_ms,
+                max_ms: median_ms,
+                mean_ms: median_ms,
+            }),
+            peak_memory_bytes: Some(peak_memory_bytes),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_latency() {
+        let latency = Latency::from_samples(&mut [40, 10, 30, 20, 100]).unwrap();
+        assert_eq!(
+            latency,
+            Latency {
+                min_ms: 10,
+                median_ms: 30,
+                p95_ms: 100,
+                max_ms: 100,
+                mean_ms: 40,
+            }
+        );
+        assert!(Latency::from_samples(&mut []).is_none());
+    }
+
+    #[test]
+    fn test_compare() {
+        let mib = 1024 * 1024;
+        let baseline = BenchReport {
+            engines: vec![
+                bench("chromium", 100, 400 * mib),
+                bench("imagemagick", 50, 100 * mib),
+            ],
+            regressions: Vec::new(),
+        };
+        let current = [
+            bench("chromium", 130, 410 * mib),
+            bench("imagemagick", 55, 200 * mib),
+        ];
+        assert_eq!(
+            compare(&current, &baseline, 0.2),
+            [
+                "chromium: median 130ms, baseline 100ms (+30%)",
+                "imagemagick: peak memory 200 MiB, baseline 100 MiB (+100%)",
+            ]
+        );
+        assert!(compare(&current, &baseline, 1.5).is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_process_tree_rss() {
+        assert!(process_tree_rss().unwrap() > 0);
+    }
+}
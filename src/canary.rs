@@ -0,0 +1,316 @@
+//! Canary comparisons: a sample of HTML conversions is rendered again by a
+//! second renderer (e.g. WeasyPrint) and the two PDFs are compared, so
+//! rendering drift after an engine upgrade shows up in `/metrics`.
+
+use crate::error::{AppError, Result};
+use crate::{metrics, pdf};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// Default: compare one in this many HTML conversions
+const DEFAULT_EVERY: u64 = 20;
+
+/// Longest the comparison renderer may take
+const CANARY_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Resolution of the first-page rasters that are compared, in DPI
+const RASTER_DPI: &str = "36";
+
+/// Grey levels two pixels may differ by and still count as equal
+/// (anti-aliasing differs between renderers)
+const PIXEL_TOLERANCE: u8 = 48;
+
+/// Share of differing pixels above which a comparison counts as drift
+const DRIFT_PIXEL_RATIO: f64 = 0.05;
+
+pub struct Canary {
+    /// Renderer command line, with `{input}` and `{output}` placeholders
+    command: Vec<String>,
+    /// Label of the renderer in metrics and logs
+    name: String,
+    every: u64,
+    seen: AtomicU64,
+}
+
+/// How the canary output compared with the primary output
+#[derive(Debug)]
+struct Comparison {
+    primary_pages: usize,
+    canary_pages: usize,
+    /// Canary size divided by primary size
+    size_ratio: f64,
+    /// Share of first-page pixels that differ, when both could be rasterized
+    pixel_diff: Option<f64>,
+}
+
+impl Comparison {
+    fn drifted(&self) -> bool {
+        self.primary_pages != self.canary_pages
+            || self.pixel_diff.is_some_and(|diff| diff > DRIFT_PIXEL_RATIO)
+    }
+}
+
+impl Canary {
+    /// Configured by `PDFMILL_CANARY_COMMAND` (e.g. `weasyprint {input}
+    /// {output}`; off when unset) and `PDFMILL_CANARY_EVERY` (default 20)
+    pub fn from_env() -> std::result::Result<Option<Self>, String> {
+        let Some(command) = std::env::var("PDFMILL_CANARY_COMMAND")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+        let command: Vec<String> = command.split_whitespace().map(str::to_string).collect();
+        if !command.iter().any(|arg| arg.contains("{input}"))
+            || !command.iter().any(|arg| arg.contains("{output}"))
+        {
+            return Err("PDFMILL_CANARY_COMMAND: must contain {input} and {output}".to_string());
+        }
+
+        let every = match std::env::var("PDFMILL_CANARY_EVERY") {
+            Ok(value) => value
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| {
+                    format!(
+                        "PDFMILL_CANARY_EVERY: expected a positive integer, got '{}'",
+                        value
+                    )
+                })?,
+            Err(_) => DEFAULT_EVERY,
+        };
+
+        let name = Path::new(&command[0])
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| command[0].clone());
+        info!(
+            "Canary: comparing 1 in {} HTML conversions with {}",
+            every, name
+        );
+        Ok(Some(Self {
+            command,
+            name,
+            every,
+            seen: AtomicU64::new(0),
+        }))
+    }
+
+    /// Whether this conversion is one of the sampled ones
+    pub fn sample(&self) -> bool {
+        self.seen
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.every)
+    }
+
+    /// Render `input` with the canary renderer and compare the result with
+    /// `primary`, recording metrics. Runs after the response was sent, so
+    /// failures are only logged and counted.
+    pub async fn compare(&self, input: &Path, primary: Vec<u8>, filename: &str) {
+        let labels = [("canary", self.name.as_str())];
+        match self.run(input, primary).await {
+            Ok(comparison) => {
+                let drifted = comparison.drifted();
+                let page_delta = comparison.primary_pages.abs_diff(comparison.canary_pages);
+                metrics::observe("pdfmill_canary_page_delta", &labels, page_delta as f64);
+                metrics::observe("pdfmill_canary_size_ratio", &labels, comparison.size_ratio);
+                if let Some(diff) = comparison.pixel_diff {
+                    metrics::observe("pdfmill_canary_pixel_diff_ratio", &labels, diff);
+                }
+                let outcome = if drifted { "drift" } else { "match" };
+                metrics::increment(
+                    "pdfmill_canary_comparisons_total",
+                    &[("canary", self.name.as_str()), ("outcome", outcome)],
+                );
+                if drifted {
+                    warn!("Canary drift for {}: {:?}", filename, comparison);
+                } else {
+                    info!("Canary match for {}: {:?}", filename, comparison);
+                }
+            }
+            Err(e) => {
+                metrics::increment(
+                    "pdfmill_canary_comparisons_total",
+                    &[("canary", self.name.as_str()), ("outcome", "error")],
+                );
+                warn!("Canary comparison of {} failed: {}", filename, e);
+            }
+        }
+    }
+
+    async fn run(&self, input: &Path, primary: Vec<u8>) -> Result<Comparison> {
+        let dir = input.parent().unwrap_or(input);
+        let output = dir.join("canary.pdf");
+        let args: Vec<String> = self.command[1..]
+            .iter()
+            .map(|arg| {
+                arg.replace("{input}", &input.to_string_lossy())
+                    .replace("{output}", &output.to_string_lossy())
+            })
+            .collect();
+
+        let result = Command::new(&self.command[0])
+            .args(&args)
+            .kill_on_drop(true)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output();
+        let result = tokio::time::timeout(CANARY_TIMEOUT, result)
+            .await
+            .map_err(|_| AppError::ConversionFailed(format!("{} timed out", self.name)))??;
+        if !result.status.success() {
+            return Err(crate::engines::tool_failed(&self.name, &result));
+        }
+        let canary = tokio::fs::read(&output).await?;
+
+        let primary_path = dir.join("primary.pdf");
+        tokio::fs::write(&primary_path, &primary).await?;
+        let pixel_diff = match (rasterize(&primary_path).await, rasterize(&output).await) {
+            (Some(a), Some(b)) => Some(pixel_diff(&a, &b)),
+            _ => None,
+        };
+
+        tokio::task::spawn_blocking(move || {
+            Ok(Comparison {
+                primary_pages: pdf::page_count(&primary)?,
+                canary_pages: pdf::page_count(&canary)?,
+                size_ratio: canary.len() as f64 / primary.len().max(1) as f64,
+                pixel_diff,
+            })
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Canary comparison panicked: {}", e)))?
+    }
+}
+
+/// A greyscale raster: width, height and one byte per pixel
+type Raster = (usize, usize, Vec<u8>);
+
+/// First page as a low-resolution greyscale raster via poppler's
+/// `pdftoppm`; None when it is not installed or fails
+async fn rasterize(pdf: &Path) -> Option<Raster> {
+    let prefix = pdf.with_extension("");
+    let status = Command::new("pdftoppm")
+        .args([
+            "-f",
+            "1",
+            "-l",
+            "1",
+            "-r",
+            RASTER_DPI,
+            "-gray",
+            "-singlefile",
+        ])
+        .arg(pdf)
+        .arg(&prefix)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .ok()?;
+    if !status.success() {
+        return None;
+    }
+    let data = tokio::fs::read(prefix.with_extension("pgm")).await.ok()?;
+    parse_pgm(&data)
+}
+
+/// Parse a binary PGM (P5) with 8-bit samples
+fn parse_pgm(data: &[u8]) -> Option<Raster> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while fields.len() < 4 {
+        while pos < data.len() && data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if data.get(pos) == Some(&b'#') {
+            while pos < data.len() && data[pos] != b'\n' {
+                pos += 1;
+            }
+            continue;
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return None;
+        }
+        fields.push(std::str::from_utf8(&data[start..pos]).ok()?);
+    }
+    // A single whitespace byte separates the header from the samples
+    pos += 1;
+
+    let width: usize = fields[1].parse().ok()?;
+    let height: usize = fields[2].parse().ok()?;
+    if fields[0] != "P5" || fields[3] != "255" {
+        return None;
+    }
+    let pixels = data.get(pos..pos + width * height)?.to_vec();
+    Some((width, height, pixels))
+}
+
+/// Share of pixels that differ by more than the tolerance; pixels outside
+/// the overlap of differently sized rasters all count as different
+fn pixel_diff(a: &Raster, b: &Raster) -> f64 {
+    let (width, height) = (a.0.max(b.0), a.1.max(b.1));
+    if width == 0 || height == 0 {
+        return 0.0;
+    }
+    let overlap_width = a.0.min(b.0);
+    let overlap_height = a.1.min(b.1);
+    let mut same = 0usize;
+    for y in 0..overlap_height {
+        for x in 0..overlap_width {
+            if a.2[y * a.0 + x].abs_diff(b.2[y * b.0 + x]) <= PIXEL_TOLERANCE {
+                same += 1;
+            }
+        }
+    }
+    1.0 - same as f64 / (width * height) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pixel_diff() {
+        let pgm = b"P5\n# pdftoppm\n4 2\n255\n\x00\x00\xff\xff\x00\x00\xff\xff";
+        let a = parse_pgm(pgm).unwrap();
+        assert_eq!((a.0, a.1), (4, 2));
+        assert_eq!(pixel_diff(&a, &a), 0.0);
+
+        let mut b = a.clone();
+        b.2[0] = 0x40;
+        b.2[1] = 0x10;
+        assert_eq!(pixel_diff(&a, &b), 1.0 / 8.0);
+
+        // Half as wide: the missing columns differ
+        let narrow = parse_pgm(b"P5 2 2 255 \x00\x00\x00\x00").unwrap();
+        assert_eq!(pixel_diff(&a, &narrow), 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_compare_page_counts() {
+        let canary = Canary {
+            command: ["cp", "{input}", "{output}"].map(String::from).to_vec(),
+            name: "cp".to_string(),
+            every: 1,
+            seen: AtomicU64::new(0),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("page.html");
+        std::fs::write(&input, pdf::sample_pdf(2)).unwrap();
+
+        let comparison = canary.run(&input, pdf::sample_pdf(3)).await.unwrap();
+        assert_eq!((comparison.primary_pages, comparison.canary_pages), (3, 2));
+        assert!(comparison.drifted());
+    }
+}
@@ -0,0 +1,151 @@
+//! CORS policy from configuration. Without configuration any origin may
+//! call the API (without credentials).
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+
+/// Parsed CORS settings; `None` lists mean "any"
+#[derive(Debug, Default, PartialEq)]
+pub struct CorsConfig {
+    origins: Option<Vec<HeaderValue>>,
+    methods: Option<Vec<Method>>,
+    headers: Option<Vec<HeaderName>>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl CorsConfig {
+    /// Configured by `PDFMILL_CORS_ORIGINS`, `PDFMILL_CORS_METHODS` and
+    /// `PDFMILL_CORS_HEADERS` (comma-separated, `*` for any),
+    /// `PDFMILL_CORS_CREDENTIALS` and `PDFMILL_CORS_MAX_AGE` in seconds
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let origins = list(&lookup, "PDFMILL_CORS_ORIGINS", |origin| {
+            HeaderValue::from_str(origin.trim_end_matches('/')).ok()
+        })?;
+        let methods = list(&lookup, "PDFMILL_CORS_METHODS", |method| {
+            Method::from_bytes(method.to_ascii_uppercase().as_bytes()).ok()
+        })?;
+        let headers = list(&lookup, "PDFMILL_CORS_HEADERS", |header| {
+            HeaderName::from_bytes(header.as_bytes()).ok()
+        })?;
+        let credentials =
+            lookup("PDFMILL_CORS_CREDENTIALS").is_some_and(|v| matches!(v.trim(), "true" | "1"));
+        let max_age = match lookup("PDFMILL_CORS_MAX_AGE") {
+            Some(v) => Some(Duration::from_secs(v.trim().parse().map_err(|_| {
+                format!("PDFMILL_CORS_MAX_AGE: expected seconds, got '{}'", v)
+            })?)),
+            None => None,
+        };
+
+        // Browsers refuse credentialed responses to a wildcard origin
+        if credentials && origins.is_none() {
+            return Err(
+                "PDFMILL_CORS_CREDENTIALS requires an explicit PDFMILL_CORS_ORIGINS list"
+                    .to_string(),
+            );
+        }
+
+        Ok(Self {
+            origins,
+            methods,
+            headers,
+            credentials,
+            max_age,
+        })
+    }
+
+    pub fn layer(self) -> CorsLayer {
+        let mut layer = CorsLayer::new().allow_origin(match self.origins {
+            Some(origins) => AllowOrigin::list(origins),
+            None => Any.into(),
+        });
+
+        // With credentials "any" has to be spelled out per request
+        layer = layer.allow_methods(match self.methods {
+            Some(methods) => AllowMethods::list(methods),
+            None if self.credentials => AllowMethods::mirror_request(),
+            None => Any.into(),
+        });
+        layer = layer.allow_headers(match self.headers {
+            Some(headers) => AllowHeaders::list(headers),
+            None if self.credentials => AllowHeaders::mirror_request(),
+            None => Any.into(),
+        });
+
+        if self.credentials {
+            layer = layer.allow_credentials(true);
+        }
+        if let Some(max_age) = self.max_age {
+            layer = layer.max_age(max_age);
+        }
+        layer
+    }
+}
+
+/// A comma-separated setting; unset, empty or `*` is `None` (any)
+fn list<T>(
+    lookup: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Option<Vec<T>>, String> {
+    let Some(value) = lookup(name).filter(|v| !v.trim().is_empty() && v.trim() != "*") else {
+        return Ok(None);
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| parse(item).ok_or_else(|| format!("{}: invalid entry '{}'", name, item)))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> Result<CorsConfig, String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        CorsConfig::from_lookup(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_cors_config() {
+        assert_eq!(config(&[]).unwrap(), CorsConfig::default());
+
+        let strict = config(&[
+            (
+                "PDFMILL_CORS_ORIGINS",
+                "https://app.example.com/, https://admin.example.com",
+            ),
+            ("PDFMILL_CORS_METHODS", "get,post"),
+            ("PDFMILL_CORS_CREDENTIALS", "true"),
+            ("PDFMILL_CORS_MAX_AGE", "600"),
+        ])
+        .unwrap();
+        assert_eq!(
+            strict.origins.as_deref(),
+            Some(
+                &[
+                    HeaderValue::from_static("https://app.example.com"),
+                    HeaderValue::from_static("https://admin.example.com"),
+                ][..]
+            )
+        );
+        assert_eq!(strict.methods, Some(vec![Method::GET, Method::POST]));
+        assert!(strict.credentials);
+        assert_eq!(strict.max_age, Some(Duration::from_secs(600)));
+
+        assert!(config(&[("PDFMILL_CORS_CREDENTIALS", "true")]).is_err());
+        assert!(config(&[("PDFMILL_CORS_HEADERS", "bad header")]).is_err());
+    }
+}
@@ -0,0 +1,91 @@
+//! Client deadlines. A client sends how long it will wait, as `X-Deadline-Ms`
+//! (milliseconds) or `Request-Timeout` (seconds); a request still running
+//! when that time is up is dropped, which kills engine processes and closes
+//! browser tabs, and answered with 504. Capacity then goes to requests
+//! someone still waits for.
+
+use crate::error::AppError;
+use crate::metrics;
+use axum::{
+    extract::Request, http::HeaderMap, middleware::Next, response::IntoResponse, response::Response,
+};
+use std::time::Duration;
+
+const DEADLINE_HEADER: &str = "x-deadline-ms";
+const REQUEST_TIMEOUT_HEADER: &str = "request-timeout";
+
+/// Run the request within the client's deadline, if it sent one
+pub async fn enforce(request: Request, next: Next) -> Response {
+    let deadline = match deadline(request.headers()) {
+        Ok(Some(deadline)) => deadline,
+        Ok(None) => return next.run(request).await,
+        Err(e) => return e.into_response(),
+    };
+    match tokio::time::timeout(deadline, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            metrics::increment("pdfmill_deadline_exceeded_total", &[]);
+            AppError::DeadlineExceeded(deadline).into_response()
+        }
+    }
+}
+
+fn deadline(headers: &HeaderMap) -> Result<Option<Duration>, AppError> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .map(|v| v.to_str().unwrap_or_default().trim().to_string())
+    };
+    let (name, seconds) = if let Some(value) = header(DEADLINE_HEADER) {
+        (
+            DEADLINE_HEADER,
+            value.parse::<f64>().ok().map(|ms| ms / 1000.0),
+        )
+    } else if let Some(value) = header(REQUEST_TIMEOUT_HEADER) {
+        (REQUEST_TIMEOUT_HEADER, value.parse::<f64>().ok())
+    } else {
+        return Ok(None);
+    };
+    seconds
+        .filter(|s| s.is_finite() && *s > 0.0)
+        .and_then(|s| Duration::try_from_secs_f64(s).ok())
+        .map(Some)
+        .ok_or_else(|| AppError::InvalidRequest(format!("{}: expected a positive number", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_deadline() {
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, HeaderValue::from_static(value));
+            }
+            headers
+        };
+        assert_eq!(deadline(&headers(&[])).unwrap(), None);
+        assert_eq!(
+            deadline(&headers(&[("x-deadline-ms", "1500")])).unwrap(),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            deadline(&headers(&[("request-timeout", "2.5")])).unwrap(),
+            Some(Duration::from_millis(2500))
+        );
+        // The more precise header wins
+        assert_eq!(
+            deadline(&headers(&[
+                ("x-deadline-ms", "100"),
+                ("request-timeout", "9")
+            ]))
+            .unwrap(),
+            Some(Duration::from_millis(100))
+        );
+        assert!(deadline(&headers(&[("x-deadline-ms", "0")])).is_err());
+        assert!(deadline(&headers(&[("request-timeout", "soon")])).is_err());
+    }
+}
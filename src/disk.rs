@@ -0,0 +1,628 @@
+//! Disk used by conversions. Request workspaces, the Chromium profile and
+//! the LibreOffice profiles all live under one work directory, so their size
+//! can be measured for `/metrics` and browser/office caches trimmed before
+//! they fill the disk.
+
+use crate::error::{AppError, Result};
+use crate::{encryption, metrics};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tracing::{info, warn};
+
+/// Default size above which the Chromium and LibreOffice caches are trimmed
+const DEFAULT_CACHE_LIMIT_MB: u64 = 1024;
+
+/// How often disk usage is measured
+const MONITOR_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Cache directories inside the Chromium profile, safe to delete at any time
+const CHROMIUM_CACHES: &[&str] = &[
+    "Default/Cache",
+    "Default/Code Cache",
+    "Default/GPUCache",
+    "Default/Service Worker/CacheStorage",
+    "GrShaderCache",
+    "ShaderCache",
+];
+
+/// Most LibreOffice profiles kept for `documentLocale`; each takes a few MB
+/// and a slow first start. Beyond that the least recently used one not in
+/// use is removed.
+const MAX_LOCALE_PROFILES: usize = 16;
+
+/// Scratch directories inside the LibreOffice profile
+const LIBREOFFICE_CACHES: &[&str] = &[
+    "cache",
+    "user/backup",
+    "user/temp",
+    "user/uno_packages/cache",
+];
+
+/// Root of everything pdfmill writes: `PDFMILL_WORK_DIR`, or `pdfmill`
+/// in the system temp directory
+static WORK_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    std::env::var_os("PDFMILL_WORK_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("pdfmill"))
+});
+
+/// Last measured size of each area, in bytes
+static USAGE: Mutex<Vec<(&'static str, u64)>> = Mutex::new(Vec::new());
+
+/// Directory holding the per-request workspaces
+fn workspaces() -> PathBuf {
+    WORK_DIR.join("tmp")
+}
+
+/// Chromium user data directory (profile and caches)
+pub fn chromium_profile() -> PathBuf {
+    WORK_DIR.join("chromium")
+}
+
+/// Profile of a replacement browser started while the current one keeps
+/// serving; browsers alternate between the two profiles on every swap
+pub fn chromium_standby_profile() -> PathBuf {
+    WORK_DIR.join("chromium-standby")
+}
+
+/// Conversions using each `documentLocale` profile and when one last did;
+/// profiles left from an earlier run are not in it
+static LOCALE_PROFILES: LazyLock<Mutex<HashMap<String, ProfileUse>>> =
+    LazyLock::new(Default::default);
+
+#[derive(Debug, Default)]
+struct ProfileUse {
+    users: usize,
+    last_used: Option<Instant>,
+}
+
+/// A LibreOffice user installation in use by a conversion; a locale's
+/// profile is not removed while it is
+pub struct LibreOfficeProfile {
+    /// The `-env:UserInstallation` argument
+    pub arg: String,
+    locale: Option<String>,
+}
+
+impl Drop for LibreOfficeProfile {
+    fn drop(&mut self) {
+        let Some(locale) = &self.locale else { return };
+        if let Some(profile) = LOCALE_PROFILES.lock().unwrap().get_mut(locale) {
+            profile.users -= 1;
+            profile.last_used = Some(Instant::now());
+        }
+    }
+}
+
+/// LibreOffice user installation for a conversion. With a locale (a
+/// checked BCP 47 tag) it is a profile of its own whose default locale,
+/// which formats numbers and dates that follow the system, is `locale`;
+/// it is created on first use.
+pub fn libreoffice_profile(locale: Option<&str>) -> Result<LibreOfficeProfile> {
+    let path = match locale {
+        Some(locale) => libreoffice_locale_profile(locale)?,
+        None => WORK_DIR.join("libreoffice"),
+    };
+    let url = reqwest::Url::from_directory_path(&path)
+        .map(|url| url.to_string())
+        .unwrap_or_else(|_| format!("file://{}", path.display()));
+    Ok(LibreOfficeProfile {
+        arg: format!("-env:UserInstallation={}", url.trim_end_matches('/')),
+        locale: locale.map(str::to_string),
+    })
+}
+
+/// Directory holding the LibreOffice profiles for `documentLocale`
+fn libreoffice_locales() -> PathBuf {
+    WORK_DIR.join("libreoffice-locales")
+}
+
+/// The profile of `locale`, counted as in use until the returned
+/// [`LibreOfficeProfile`] is dropped
+fn libreoffice_locale_profile(locale: &str) -> Result<PathBuf> {
+    let mut profiles = LOCALE_PROFILES.lock().unwrap();
+    let profile = libreoffice_locales().join(locale);
+    let settings = profile.join("user/registrymodifications.xcu");
+    if !settings.exists() {
+        let existing: Vec<String> = std::fs::read_dir(libreoffice_locales())
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        if existing.len() >= MAX_LOCALE_PROFILES {
+            let evicted = least_recently_used(&existing, &profiles).ok_or_else(|| {
+                AppError::Conflict(format!(
+                    "documentLocale: all {} locale profiles are in use, try '{}' again later",
+                    MAX_LOCALE_PROFILES, locale
+                ))
+            })?;
+            std::fs::remove_dir_all(libreoffice_locales().join(&evicted))?;
+            profiles.remove(&evicted);
+            info!("Removed the LibreOffice profile for locale {}", evicted);
+        }
+        create_locale_profile(&profile, &settings, locale)?;
+    }
+    profiles.entry(locale.to_string()).or_default().users += 1;
+    Ok(profile)
+}
+
+/// The profile among `existing` to make room with: not in use, and unused
+/// the longest, those never used by this process first
+fn least_recently_used(existing: &[String], uses: &HashMap<String, ProfileUse>) -> Option<String> {
+    existing
+        .iter()
+        .filter(|locale| uses.get(*locale).is_none_or(|u| u.users == 0))
+        .min_by_key(|locale| uses.get(*locale).and_then(|u| u.last_used))
+        .cloned()
+}
+
+fn create_locale_profile(profile: &Path, settings: &Path, locale: &str) -> Result<()> {
+    // LibreOffice keeps the settings it finds when it sets up the profile
+    std::fs::create_dir_all(profile.join("user"))?;
+    std::fs::write(
+        settings,
+        format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                "\n",
+                r#"<oor:items xmlns:oor="http://openoffice.org/2001/registry" "#,
+                r#"xmlns:xs="http://www.w3.org/2001/XMLSchema" "#,
+                r#"xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">"#,
+                "\n",
+                r#"<item oor:path="/org.openoffice.Setup/L10N">"#,
+                r#"<prop oor:name="ooSetupSystemLocale" oor:op="fuse"><value>{}</value></prop>"#,
+                "</item>\n</oor:items>\n"
+            ),
+            locale
+        ),
+    )?;
+    info!("Created LibreOffice profile for locale {}", locale);
+    Ok(())
+}
+
+/// A fresh workspace for one conversion, deleted when dropped. The parent
+/// is recreated if a tmp cleaner removed it while the server was running.
+pub fn tempdir() -> std::io::Result<TempDir> {
+    let workspaces = workspaces();
+    std::fs::create_dir_all(&workspaces)?;
+    tempfile::tempdir_in(workspaces)
+}
+
+/// Leading bytes of a spooled upload kept in memory for content sniffing
+const SPOOL_HEAD: usize = 1024;
+
+/// Default size above which an upload is moved from memory to disk
+const DEFAULT_SPOOL_THRESHOLD_MB: u64 = 8;
+
+/// Upload size above which it is spooled to disk: `PDFMILL_SPOOL_THRESHOLD_MB`
+/// (default 8; 0 spools every upload)
+static SPOOL_THRESHOLD: LazyLock<u64> = LazyLock::new(|| {
+    std::env::var("PDFMILL_SPOOL_THRESHOLD_MB")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_SPOOL_THRESHOLD_MB)
+        * 1024
+        * 1024
+});
+
+/// An uploaded file, held in memory or spooled to a workspace on disk
+pub enum Upload {
+    Memory(Vec<u8>),
+    Spooled {
+        /// Workspace holding the file, removed with the upload
+        dir: TempDir,
+        path: PathBuf,
+        head: Vec<u8>,
+        size: u64,
+        sha256: String,
+    },
+}
+
+impl From<Vec<u8>> for Upload {
+    fn from(data: Vec<u8>) -> Self {
+        Self::Memory(data)
+    }
+}
+
+impl Upload {
+    pub fn size(&self) -> u64 {
+        match self {
+            Self::Memory(data) => data.len() as u64,
+            Self::Spooled { size, .. } => *size,
+        }
+    }
+
+    /// The first bytes of the file (all of it when in memory)
+    pub fn head(&self) -> &[u8] {
+        match self {
+            Self::Memory(data) => data,
+            Self::Spooled { head, .. } => head,
+        }
+    }
+
+    /// Lowercase hex SHA-256 of the contents
+    pub fn sha256(&self) -> String {
+        match self {
+            Self::Memory(data) => crate::engines::sha256_hex(data),
+            Self::Spooled { sha256, .. } => sha256.clone(),
+        }
+    }
+
+    /// Store the contents at `path`; a spooled file is moved there, or
+    /// decrypted there when it is encrypted at rest
+    pub async fn save(self, path: &Path) -> std::io::Result<()> {
+        match self {
+            Self::Memory(data) => tokio::fs::write(path, &data).await,
+            Self::Spooled {
+                dir: _dir,
+                path: spooled,
+                ..
+            } if encryption::enabled() => encryption::unseal_to(&spooled, path).await,
+            Self::Spooled {
+                dir: _dir,
+                path: spooled,
+                ..
+            } => tokio::fs::rename(&spooled, path).await,
+        }
+    }
+
+    /// Keep the contents at rest at `path`, encrypted when files at rest
+    /// are, without them ever being written there in plaintext; read it
+    /// back with [`encryption::read`] or [`encryption::Plaintext`]
+    pub async fn store(self, path: &Path) -> std::io::Result<()> {
+        match self {
+            Self::Memory(data) => encryption::write(path, &data).await,
+            // Spooled as it is kept at rest
+            Self::Spooled {
+                dir: _dir,
+                path: spooled,
+                ..
+            } => tokio::fs::rename(&spooled, path).await,
+        }
+    }
+}
+
+/// Writes an upload to disk chunk by chunk as it arrives, hashing it on the
+/// way, so it never has to fit in memory; it is encrypted when files at
+/// rest are
+pub struct Spooler {
+    dir: TempDir,
+    path: PathBuf,
+    file: encryption::Writer,
+    head: Vec<u8>,
+    size: u64,
+    hasher: Sha256,
+}
+
+impl Spooler {
+    pub async fn new() -> std::io::Result<Self> {
+        let dir = tempdir()?;
+        let path = dir.path().join("upload");
+        let file = encryption::Writer::create(&path).await?;
+        Ok(Self {
+            dir,
+            path,
+            file,
+            head: Vec::new(),
+            size: 0,
+            hasher: Sha256::new(),
+        })
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        let missing = SPOOL_HEAD.saturating_sub(self.head.len());
+        self.head
+            .extend_from_slice(&chunk[..missing.min(chunk.len())]);
+        self.hasher.update(chunk);
+        self.size += chunk.len() as u64;
+        self.file.write(chunk).await
+    }
+
+    pub async fn finish(self) -> std::io::Result<Upload> {
+        self.file.finish().await?;
+        Ok(Upload::Spooled {
+            dir: self.dir,
+            path: self.path,
+            head: self.head,
+            size: self.size,
+            sha256: format!("{:x}", self.hasher.finalize()),
+        })
+    }
+}
+
+/// Receives an upload chunk by chunk, in memory while it is small and on
+/// disk once it outgrows the spool threshold, so large uploads never have
+/// to fit in memory while small ones skip the disk
+pub struct UploadWriter {
+    memory: Vec<u8>,
+    spooler: Option<Spooler>,
+    threshold: u64,
+}
+
+impl UploadWriter {
+    /// Spooling above the configured threshold; the low-memory profile
+    /// spools every upload
+    pub fn new() -> Self {
+        let threshold = if crate::profile::low_memory() {
+            0
+        } else {
+            *SPOOL_THRESHOLD
+        };
+        Self::with_threshold(threshold)
+    }
+
+    fn with_threshold(threshold: u64) -> Self {
+        Self {
+            memory: Vec::new(),
+            spooler: None,
+            threshold,
+        }
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        if let Some(spooler) = &mut self.spooler {
+            return spooler.write(chunk).await;
+        }
+        if (self.memory.len() + chunk.len()) as u64 <= self.threshold {
+            self.memory.extend_from_slice(chunk);
+            return Ok(());
+        }
+        let mut spooler = Spooler::new().await?;
+        spooler.write(&std::mem::take(&mut self.memory)).await?;
+        spooler.write(chunk).await?;
+        metrics::increment("pdfmill_uploads_spooled_total", &[]);
+        self.spooler = Some(spooler);
+        Ok(())
+    }
+
+    pub async fn finish(self) -> std::io::Result<Upload> {
+        match self.spooler {
+            Some(spooler) => spooler.finish().await,
+            None => Ok(Upload::Memory(self.memory)),
+        }
+    }
+}
+
+impl Default for UploadWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Create the work directory and remove workspaces a previous run left
+/// behind (nothing in it outlives the process)
+pub fn prepare() -> std::io::Result<()> {
+    let workspaces = workspaces();
+    if workspaces.exists() {
+        let mut removed = 0;
+        for entry in std::fs::read_dir(&workspaces)? {
+            let path = entry?.path();
+            let result = if path.is_dir() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+            match result {
+                Ok(()) => removed += 1,
+                Err(e) => warn!("Failed to remove {}: {}", path.display(), e),
+            }
+        }
+        if removed > 0 {
+            info!(
+                "Removed {} leftover workspaces from a previous run",
+                removed
+            );
+        }
+    }
+    std::fs::create_dir_all(&workspaces)?;
+    info!("Work directory: {}", WORK_DIR.display());
+    Ok(())
+}
+
+/// Measure disk usage every minute and trim caches above
+/// `PDFMILL_CACHE_LIMIT_MB` (default 1024)
+pub fn spawn_monitor() {
+    let limit = std::env::var("PDFMILL_CACHE_LIMIT_MB")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_CACHE_LIMIT_MB)
+        * 1024
+        * 1024;
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(MONITOR_INTERVAL);
+        loop {
+            interval.tick().await;
+            let measured = tokio::task::spawn_blocking(move || check(limit)).await;
+            match measured {
+                Ok(usage) => *USAGE.lock().unwrap() = usage,
+                Err(e) => warn!("Disk usage check panicked: {}", e),
+            }
+        }
+    });
+}
+
+/// Last measured bytes used by each area
+pub fn usage() -> Vec<(&'static str, u64)> {
+    USAGE.lock().unwrap().clone()
+}
+
+fn check(limit: u64) -> Vec<(&'static str, u64)> {
+    let workspace = dir_size(&workspaces());
+    let chromium = trim("chromium", &chromium_profile(), CHROMIUM_CACHES, limit)
+        + trim(
+            "chromium",
+            &chromium_standby_profile(),
+            CHROMIUM_CACHES,
+            limit,
+        );
+    let locale_profiles = std::fs::read_dir(libreoffice_locales())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path());
+    let libreoffice = std::iter::once(WORK_DIR.join("libreoffice"))
+        .chain(locale_profiles)
+        .map(|profile| trim("libreoffice", &profile, LIBREOFFICE_CACHES, limit))
+        .sum();
+    vec![
+        ("workspace", workspace),
+        ("chromium", chromium),
+        ("libreoffice", libreoffice),
+    ]
+}
+
+/// Delete the cache directories of a profile when it exceeds `limit`;
+/// returns its size afterwards
+fn trim(area: &'static str, profile: &Path, caches: &[&str], limit: u64) -> u64 {
+    let size = dir_size(profile);
+    if size <= limit {
+        return size;
+    }
+    warn!(
+        "{} profile uses {} MB (limit {} MB), trimming caches",
+        area,
+        size / 1024 / 1024,
+        limit / 1024 / 1024
+    );
+    for cache in caches {
+        let path = profile.join(cache);
+        if let Err(e) = std::fs::remove_dir_all(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+    metrics::increment("pdfmill_cache_trims_total", &[("area", area)]);
+    dir_size(profile)
+}
+
+/// Total size of the files below `path`, not following symlinks
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_recently_used() {
+        let now = Instant::now();
+        let existing: Vec<String> = ["de-DE", "fr-FR", "ja-JP", "en-GB"]
+            .map(String::from)
+            .to_vec();
+        let uses = |entries: &[(&str, usize, Option<Instant>)]| {
+            entries
+                .iter()
+                .map(|(locale, users, last_used)| {
+                    let last_used = *last_used;
+                    (
+                        locale.to_string(),
+                        ProfileUse {
+                            users: *users,
+                            last_used,
+                        },
+                    )
+                })
+                .collect::<HashMap<_, _>>()
+        };
+
+        // Left from an earlier run before anything used since
+        let used = uses(&[
+            ("de-DE", 0, Some(now)),
+            ("fr-FR", 0, Some(now - Duration::from_secs(60))),
+            ("ja-JP", 1, None),
+        ]);
+        assert_eq!(
+            least_recently_used(&existing, &used).as_deref(),
+            Some("en-GB")
+        );
+
+        let used = uses(&[
+            ("de-DE", 0, Some(now)),
+            ("fr-FR", 0, Some(now - Duration::from_secs(60))),
+            ("ja-JP", 2, Some(now - Duration::from_secs(600))),
+            ("en-GB", 1, None),
+        ]);
+        assert_eq!(
+            least_recently_used(&existing, &used).as_deref(),
+            Some("fr-FR")
+        );
+
+        let busy = uses(
+            &existing
+                .iter()
+                .map(|l| (l.as_str(), 1, None))
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(least_recently_used(&existing, &busy), None);
+    }
+
+    #[test]
+    fn test_trim_caches() {
+        let profile = tempfile::tempdir().unwrap();
+        let cache = profile.path().join("Default/Cache");
+        std::fs::create_dir_all(&cache).unwrap();
+        std::fs::write(cache.join("data_1"), vec![0u8; 4096]).unwrap();
+        std::fs::write(profile.path().join("Local State"), b"{}").unwrap();
+
+        assert_eq!(dir_size(profile.path()), 4098);
+        assert_eq!(
+            trim("chromium", profile.path(), CHROMIUM_CACHES, 1 << 20),
+            4098
+        );
+        assert_eq!(trim("chromium", profile.path(), CHROMIUM_CACHES, 1024), 2);
+        assert!(!cache.exists());
+    }
+
+    #[tokio::test]
+    async fn test_spool_upload() {
+        let mut spooler = Spooler::new().await.unwrap();
+        spooler.write(&[b'a'; 1000]).await.unwrap();
+        spooler.write(&[b'b'; 1000]).await.unwrap();
+        let upload = spooler.finish().await.unwrap();
+
+        let data = [[b'a'; 1000], [b'b'; 1000]].concat();
+        assert_eq!(upload.size(), 2000);
+        assert_eq!(upload.head(), &data[..SPOOL_HEAD]);
+        assert_eq!(upload.sha256(), crate::engines::sha256_hex(&data));
+
+        let target = tempfile::tempdir().unwrap();
+        upload.save(&target.path().join("in.txt")).await.unwrap();
+        assert_eq!(std::fs::read(target.path().join("in.txt")).unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_upload_writer_spills() {
+        let mut small = UploadWriter::with_threshold(1500);
+        small.write(&[b'a'; 1000]).await.unwrap();
+        assert!(
+            matches!(small.finish().await.unwrap(), Upload::Memory(data) if data.len() == 1000)
+        );
+
+        let mut large = UploadWriter::with_threshold(1500);
+        large.write(&[b'a'; 1000]).await.unwrap();
+        large.write(&[b'b'; 1000]).await.unwrap();
+        let upload = large.finish().await.unwrap();
+        let data = [[b'a'; 1000], [b'b'; 1000]].concat();
+        assert!(matches!(upload, Upload::Spooled { .. }));
+        assert_eq!(upload.sha256(), crate::engines::sha256_hex(&data));
+    }
+}
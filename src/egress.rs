@@ -0,0 +1,321 @@
+//! Which remote pages the server may fetch for its callers. URL conversions
+//! and schedules make the browser load any address a caller names, so
+//! without a policy they would reach the server's own network (cloud
+//! metadata endpoints, admin ports, intranet sites).
+
+use crate::access::canonical;
+use crate::error::{AppError, Result};
+use ipnet::IpNet;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+use tracing::info;
+
+static ACTIVE: OnceLock<UrlPolicy> = OnceLock::new();
+
+/// Hosts and networks remote pages may be loaded from. Without an allowlist
+/// any public address may be; with one only the hosts and networks it
+/// names, which may then be private.
+#[derive(Debug, Default)]
+pub struct UrlPolicy {
+    /// Host names, exact or `*.` for any subdomain
+    hosts: Vec<String>,
+    networks: Vec<IpNet>,
+}
+
+impl UrlPolicy {
+    /// Configured by `PDFMILL_URL_ALLOWLIST`, a comma-separated list of host
+    /// names (`example.com`, `*.example.com`), addresses and CIDR networks
+    pub fn from_env() -> std::result::Result<Self, String> {
+        let Ok(value) = std::env::var("PDFMILL_URL_ALLOWLIST") else {
+            return Ok(Self::default());
+        };
+        Self::parse(&value).map_err(|e| format!("PDFMILL_URL_ALLOWLIST: {}", e))
+    }
+
+    fn parse(value: &str) -> std::result::Result<Self, String> {
+        let mut policy = Self::default();
+        for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            if let Ok(net) = entry.parse::<IpNet>() {
+                policy.networks.push(net);
+            } else if let Ok(ip) = entry.parse::<IpAddr>() {
+                policy.networks.push(IpNet::from(ip));
+            } else if is_host_pattern(entry) {
+                policy.hosts.push(entry.to_ascii_lowercase());
+            } else {
+                return Err(format!(
+                    "'{}' is not a host name, address or network",
+                    entry
+                ));
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Make this the policy of every remote page; read by [`check_url`]
+    pub fn activate(self) {
+        if !self.hosts.is_empty() || !self.networks.is_empty() {
+            info!(
+                "Remote pages limited to {} hosts and {} networks",
+                self.hosts.len(),
+                self.networks.len()
+            );
+        }
+        let _ = ACTIVE.set(self);
+    }
+
+    /// Whether `url` may be fetched; host names are resolved and every
+    /// address they resolve to has to be permitted
+    pub async fn check(&self, url: &reqwest::Url) -> std::result::Result<(), String> {
+        let host = url.host_str().ok_or("no host")?;
+        let addresses = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+            Ok(ip) => vec![ip],
+            Err(_) if self.names(host) => return Ok(()),
+            Err(_) => {
+                let port = url.port_or_known_default().unwrap_or(80);
+                tokio::net::lookup_host((host, port))
+                    .await
+                    .map_err(|e| format!("cannot resolve {}: {}", host, e))?
+                    .map(|addr| addr.ip())
+                    .collect::<Vec<_>>()
+            }
+        };
+        match addresses.iter().find(|ip| !self.permits(**ip)) {
+            Some(ip) if self.is_open() => Err(format!("{} is not a public address", ip)),
+            Some(ip) => Err(format!("{} is not in PDFMILL_URL_ALLOWLIST", ip)),
+            None if addresses.is_empty() => Err("host has no addresses".to_string()),
+            None => Ok(()),
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.hosts.is_empty() && self.networks.is_empty()
+    }
+
+    fn names(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.hosts
+            .iter()
+            .any(|pattern| match pattern.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|sub| sub.ends_with('.')),
+                None => *pattern == host,
+            })
+    }
+
+    fn permits(&self, ip: IpAddr) -> bool {
+        let ip = canonical(ip);
+        if self.is_open() {
+            return is_public(ip);
+        }
+        self.networks.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// Check a remote page URL against the active policy
+pub async fn check_url(url: &reqwest::Url) -> Result<()> {
+    static OPEN: UrlPolicy = UrlPolicy {
+        hosts: Vec::new(),
+        networks: Vec::new(),
+    };
+    ACTIVE
+        .get()
+        .unwrap_or(&OPEN)
+        .check(url)
+        .await
+        .map_err(|e| AppError::Forbidden(format!("{} may not be fetched: {}", url, e)))
+}
+
+/// Check a per-request proxy against the active policy: the browser or
+/// [`fetch`] connects to it directly, so it must be an address a page could
+/// be loaded from
+pub async fn check_proxy(proxy: &str) -> Result<()> {
+    let url = reqwest::Url::parse(proxy)
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid proxy '{}': {}", proxy, e)))?;
+    check_url(&url).await.map_err(|e| match e {
+        AppError::Forbidden(reason) => AppError::Forbidden(format!("proxy {}", reason)),
+        e => e,
+    })
+}
+
+/// Redirects followed by [`fetch`]
+const MAX_REDIRECTS: usize = 10;
+
+/// Largest page [`fetch`] downloads
+const MAX_PAGE_SIZE: usize = 50 * 1024 * 1024;
+
+/// Download a remote page for the server itself to process, checking it
+/// and every redirect it takes against the active policy. `headers` (e.g.
+/// credentials) are only sent to the page's own origin. The page is fetched
+/// through `proxy` when given, otherwise through `CHROME_PROXY_SERVER`, as
+/// the browser would load it.
+pub async fn fetch(
+    url: &reqwest::Url,
+    headers: &[(String, String)],
+    proxy: Option<&str>,
+) -> Result<Vec<u8>> {
+    static CLIENT: OnceLock<std::result::Result<reqwest::Client, String>> = OnceLock::new();
+    let client = match proxy {
+        Some(proxy) => {
+            check_proxy(proxy).await?;
+            fetch_client(Some(proxy)).map_err(AppError::InvalidRequest)?
+        }
+        None => CLIENT
+            .get_or_init(|| fetch_client(std::env::var("CHROME_PROXY_SERVER").ok().as_deref()))
+            .clone()
+            .map_err(AppError::ConversionFailed)?,
+    };
+    let failed = |e: reqwest::Error| AppError::ConversionFailed(format!("{}: {}", url, e));
+
+    let origin = url.origin();
+    let mut current = url.clone();
+    for _ in 0..=MAX_REDIRECTS {
+        check_url(&current).await?;
+        let mut request = client.get(current.clone());
+        if current.origin() == origin {
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+        }
+        let response = request.send().await.map_err(failed)?;
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| current.join(location).ok())
+                .ok_or_else(|| {
+                    AppError::ConversionFailed(format!("{}: redirect without a location", current))
+                })?;
+            current = location;
+            continue;
+        }
+        let mut response = response.error_for_status().map_err(failed)?;
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(failed)? {
+            if data.len() + chunk.len() > MAX_PAGE_SIZE {
+                return Err(AppError::InvalidRequest(format!(
+                    "{} is larger than {} MB",
+                    url,
+                    MAX_PAGE_SIZE / 1024 / 1024
+                )));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        return Ok(data);
+    }
+    Err(AppError::ConversionFailed(format!(
+        "{}: more than {} redirects",
+        url, MAX_REDIRECTS
+    )))
+}
+
+/// A client for [`fetch`], which follows redirects itself
+fn fetch_client(proxy: Option<&str>) -> std::result::Result<reqwest::Client, String> {
+    let mut builder = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(std::time::Duration::from_secs(60));
+    if let Some(proxy) = proxy {
+        // Chromium separates bypass rules with semicolons
+        let bypass = std::env::var("CHROME_PROXY_BYPASS")
+            .ok()
+            .and_then(|rules| reqwest::NoProxy::from_string(&rules.replace(';', ",")));
+        let proxy = reqwest::Proxy::all(proxy)
+            .map_err(|e| format!("Proxy '{}' cannot be used to fetch pages: {}", proxy, e))?
+            .no_proxy(bypass);
+        builder = builder.proxy(proxy);
+    }
+    builder.build().map_err(|e| format!("HTTP client: {}", e))
+}
+
+fn is_host_pattern(entry: &str) -> bool {
+    let name = entry.strip_prefix("*.").unwrap_or(entry);
+    !name.is_empty()
+        && name.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Addresses reachable on the internet: not loopback, private, link-local,
+/// shared (CGNAT), multicast, documentation or otherwise reserved
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            !(v4.is_unspecified()
+                || v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_multicast()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (b == 18 || b == 19)))
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            !(v6.is_unspecified()
+                || v6.is_loopback()
+                || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first == 0x2001 && v6.segments()[1] == 0x0db8))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> reqwest::Url {
+        reqwest::Url::parse(url).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let open = UrlPolicy::default();
+        assert!(open.check(&url("https://93.184.215.14/")).await.is_ok());
+        for blocked in [
+            "http://127.0.0.1:9090/metrics",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://10.1.2.3/",
+            "http://[::1]/",
+            "http://[::ffff:192.168.0.1]/",
+            "http://[fd00::1]/",
+            "http://localhost/",
+        ] {
+            assert!(open.check(&url(blocked)).await.is_err(), "{}", blocked);
+        }
+
+        let listed = UrlPolicy::parse("*.corp.example, 10.0.0.0/8, 192.0.2.7").unwrap();
+        assert!(listed
+            .check(&url("https://wiki.corp.example/"))
+            .await
+            .is_ok());
+        assert!(listed.check(&url("http://10.1.2.3/")).await.is_ok());
+        assert!(listed.check(&url("http://192.0.2.7:8080/")).await.is_ok());
+        assert!(listed.check(&url("https://93.184.215.14/")).await.is_err());
+        assert!(!listed.names("corp.example.evil"));
+        assert!(!listed.names("corp.example"));
+
+        assert!(UrlPolicy::parse("exa mple.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_proxy() {
+        for proxy in ["http://10.0.0.5:8080", "socks5://169.254.169.254:1080"] {
+            assert!(matches!(
+                check_proxy(proxy).await,
+                Err(AppError::Forbidden(_))
+            ));
+        }
+        assert!(matches!(
+            check_proxy("proxy.internal").await,
+            Err(AppError::InvalidRequest(_))
+        ));
+    }
+}
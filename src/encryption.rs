@@ -0,0 +1,444 @@
+//! Encryption at rest: with a key configured, uploads spooled to disk, job
+//! inputs and retained job results are kept encrypted (AES-256-GCM) and
+//! decrypted only where an engine reads them or a client downloads them, so
+//! snapshots of the node's disk do not expose documents. The files an engine
+//! works on during a conversion are plaintext and removed with it.
+//!
+//! Files are sealed in 64 KiB segments (the STREAM construction): each
+//! segment's nonce is a random per-file prefix, the segment number and a
+//! last-segment flag, so segments cannot be reordered, dropped or cut off
+//! unnoticed.
+
+use crate::error::{AppError, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bytes::Bytes;
+use futures::stream::{BoxStream, StreamExt};
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tempfile::TempDir;
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use tracing::info;
+
+/// Start of every sealed file, with the format version
+const MAGIC: &[u8] = b"PDFMILL\x01";
+
+/// Random bytes starting every segment nonce of a file
+const PREFIX_LEN: usize = 7;
+
+/// Plaintext bytes per segment
+const SEGMENT: usize = 64 * 1024;
+
+/// GCM authentication tag after every segment
+const TAG_LEN: usize = 16;
+
+/// Bytes of a full segment on disk
+const SEALED_SEGMENT: usize = SEGMENT + TAG_LEN;
+
+/// The key of the process, once activated
+static ACTIVE: OnceLock<Key> = OnceLock::new();
+
+/// A 256-bit AES key
+#[derive(Clone)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    /// Load the base64 key (32 bytes, e.g. from `openssl rand -base64 32`)
+    /// from the file named by `PDFMILL_ENCRYPTION_KEY`; files are stored in
+    /// plaintext when it is unset
+    pub fn from_env() -> std::result::Result<Option<Self>, String> {
+        let Some(path) = std::env::var_os("PDFMILL_ENCRYPTION_KEY") else {
+            return Ok(None);
+        };
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("PDFMILL_ENCRYPTION_KEY: cannot read {:?}: {}", path, e))?;
+        Self::parse(&text)
+            .map(Some)
+            .map_err(|e| format!("PDFMILL_ENCRYPTION_KEY: {}", e))
+    }
+
+    fn parse(text: &str) -> std::result::Result<Self, String> {
+        let bytes = BASE64
+            .decode(text.trim())
+            .map_err(|e| format!("expected a base64 key: {}", e))?;
+        let key = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| format!("expected 32 bytes, got {}", bytes.len()))?;
+        Ok(Self(key))
+    }
+
+    /// Encrypt the files at rest with this key from now on
+    pub fn activate(self) {
+        info!("Encrypting uploads and job files at rest (AES-256-GCM)");
+        let _ = ACTIVE.set(self);
+    }
+}
+
+/// Whether files at rest are encrypted
+pub fn enabled() -> bool {
+    ACTIVE.get().is_some()
+}
+
+fn nonce(prefix: &[u8], counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[..PREFIX_LEN].copy_from_slice(prefix);
+    nonce[PREFIX_LEN..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+fn damaged() -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        "encrypted file is damaged or was sealed with another key",
+    )
+}
+
+/// Writes a file, sealed when a key is active
+pub struct Writer {
+    file: File,
+    seal: Option<Sealer>,
+}
+
+struct Sealer {
+    key: Key,
+    prefix: [u8; PREFIX_LEN],
+    counter: u32,
+    /// Plaintext not yet sealed, up to a segment and one byte
+    buffer: Vec<u8>,
+}
+
+impl Writer {
+    pub async fn create(path: &Path) -> std::io::Result<Self> {
+        Self::with_key(path, ACTIVE.get().cloned()).await
+    }
+
+    async fn with_key(path: &Path, key: Option<Key>) -> std::io::Result<Self> {
+        let mut file = File::create(path).await?;
+        let seal = match key {
+            Some(key) => {
+                let mut prefix = [0; PREFIX_LEN];
+                openssl::rand::rand_bytes(&mut prefix).map_err(Error::other)?;
+                file.write_all(MAGIC).await?;
+                file.write_all(&prefix).await?;
+                Some(Sealer {
+                    key,
+                    prefix,
+                    counter: 0,
+                    buffer: Vec::new(),
+                })
+            }
+            None => None,
+        };
+        Ok(Self { file, seal })
+    }
+
+    pub async fn write(&mut self, mut data: &[u8]) -> std::io::Result<()> {
+        let Some(seal) = &mut self.seal else {
+            return self.file.write_all(data).await;
+        };
+        while !data.is_empty() {
+            // A full segment is only sealed once more data follows, as the
+            // last one is flagged
+            let take = (SEGMENT + 1 - seal.buffer.len()).min(data.len());
+            seal.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if seal.buffer.len() > SEGMENT {
+                let sealed = seal.segment(SEGMENT, false)?;
+                self.file.write_all(&sealed).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn finish(mut self) -> std::io::Result<()> {
+        if let Some(seal) = &mut self.seal {
+            let sealed = seal.segment(seal.buffer.len(), true)?;
+            self.file.write_all(&sealed).await?;
+        }
+        self.file.flush().await
+    }
+}
+
+impl Sealer {
+    /// Seal the first `len` buffered bytes as the next segment
+    fn segment(&mut self, len: usize, last: bool) -> std::io::Result<Vec<u8>> {
+        let nonce = nonce(&self.prefix, self.counter, last);
+        let mut tag = [0; TAG_LEN];
+        let mut sealed = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key.0,
+            Some(&nonce),
+            &[],
+            &self.buffer[..len],
+            &mut tag,
+        )
+        .map_err(Error::other)?;
+        sealed.extend_from_slice(&tag);
+        self.buffer.drain(..len);
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| Error::other("file too large to encrypt"))?;
+        Ok(sealed)
+    }
+}
+
+/// Write `data` to `path`, sealed when a key is active
+pub async fn write(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut writer = Writer::create(path).await?;
+    writer.write(data).await?;
+    writer.finish().await
+}
+
+/// Read a file written by [`write`] or a [`Writer`]
+pub async fn read(path: &Path) -> Result<Vec<u8>> {
+    let mut file = File::open(path).await?;
+    let mut data = Vec::new();
+    match ACTIVE.get() {
+        Some(key) => unseal(key, &mut file, &mut data).await?,
+        None => {
+            file.read_to_end(&mut data).await?;
+        }
+    }
+    Ok(data)
+}
+
+/// Decrypt a sealed stream into `writer`
+async fn unseal<R, W>(key: &Key, reader: &mut R, writer: &mut W) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut opener = Opener::new(key.clone(), reader).await?;
+    while let Some(plain) = opener.segment().await? {
+        writer.write_all(&plain).await?;
+    }
+    writer.flush().await
+}
+
+/// Reads a sealed stream a segment at a time
+struct Opener<R> {
+    key: Key,
+    reader: R,
+    prefix: [u8; PREFIX_LEN],
+    counter: u32,
+    /// The sealed segment to open next, `len` bytes of it
+    current: Vec<u8>,
+    len: usize,
+    next: Vec<u8>,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> Opener<R> {
+    /// Check the header and read the first segment
+    async fn new(key: Key, mut reader: R) -> std::io::Result<Self> {
+        let mut header = [0; MAGIC.len() + PREFIX_LEN];
+        reader
+            .read_exact(&mut header)
+            .await
+            .map_err(|_| damaged())?;
+        let (magic, prefix) = header.split_at(MAGIC.len());
+        if magic != MAGIC {
+            return Err(damaged());
+        }
+        let mut current = vec![0; SEALED_SEGMENT];
+        let len = read_full(&mut reader, &mut current).await?;
+        Ok(Self {
+            key,
+            reader,
+            prefix: prefix.try_into().expect("prefix length"),
+            counter: 0,
+            current,
+            len,
+            next: vec![0; SEALED_SEGMENT],
+            done: false,
+        })
+    }
+
+    /// The next segment's plaintext, `None` after the last
+    async fn segment(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        if self.done {
+            return Ok(None);
+        }
+        // Only a full segment can be followed by another
+        let next_len = match self.len {
+            SEALED_SEGMENT => read_full(&mut self.reader, &mut self.next).await?,
+            _ => 0,
+        };
+        let last = next_len == 0;
+        if self.len < TAG_LEN {
+            return Err(damaged());
+        }
+        let (sealed, tag) = self.current[..self.len].split_at(self.len - TAG_LEN);
+        let nonce = nonce(&self.prefix, self.counter, last);
+        let plain = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key.0,
+            Some(&nonce),
+            &[],
+            sealed,
+            tag,
+        )
+        .map_err(|_| damaged())?;
+        if last {
+            self.done = true;
+        } else {
+            std::mem::swap(&mut self.current, &mut self.next);
+            self.len = next_len;
+            self.counter = self.counter.checked_add(1).ok_or_else(damaged)?;
+        }
+        Ok(Some(plain))
+    }
+}
+
+/// Stream a file written by [`write`] or a [`Writer`], decrypting it a
+/// segment at a time rather than all at once
+pub async fn stream(path: &Path) -> Result<BoxStream<'static, std::io::Result<Bytes>>> {
+    let file = File::open(path).await?;
+    let Some(key) = ACTIVE.get() else {
+        return Ok(ReaderStream::new(file).boxed());
+    };
+    // The header and first segment are checked before anything is sent
+    let opener = Opener::new(key.clone(), file).await?;
+    Ok(
+        futures::stream::try_unfold(opener, |mut opener| async move {
+            Ok(opener
+                .segment()
+                .await?
+                .map(|plain| (Bytes::from(plain), opener)))
+        })
+        .boxed(),
+    )
+}
+
+/// Fill `buffer` unless the stream ends first; returns the bytes read
+async fn read_full<R>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<usize>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// Seal a plaintext file in place, when a key is active
+pub async fn seal_in_place(path: &Path) -> std::io::Result<()> {
+    if !enabled() {
+        return Ok(());
+    }
+    let sealing = path.with_extension("sealing");
+    let sealed = async {
+        let mut plain = File::open(path).await?;
+        let mut writer = Writer::create(&sealing).await?;
+        let mut buffer = vec![0; SEGMENT];
+        loop {
+            match plain.read(&mut buffer).await? {
+                0 => break,
+                n => writer.write(&buffer[..n]).await?,
+            }
+        }
+        writer.finish().await?;
+        tokio::fs::rename(&sealing, path).await
+    }
+    .await;
+    if sealed.is_err() {
+        let _ = tokio::fs::remove_file(&sealing).await;
+    }
+    sealed
+}
+
+/// Decrypt a sealed file into `target`
+pub async fn unseal_to(path: &Path, target: &Path) -> std::io::Result<()> {
+    match ACTIVE.get() {
+        Some(key) => {
+            let mut sealed = File::open(path).await?;
+            let mut plain = File::create(target).await?;
+            unseal(key, &mut sealed, &mut plain).await
+        }
+        None => tokio::fs::copy(path, target).await.map(|_| ()),
+    }
+}
+
+/// A file at rest made readable for an engine: the file itself when
+/// nothing is encrypted, else a decrypted copy under the same name that is
+/// deleted when dropped
+pub struct Plaintext {
+    path: PathBuf,
+    _dir: Option<TempDir>,
+}
+
+impl Plaintext {
+    pub async fn open(path: &Path) -> Result<Self> {
+        if !enabled() {
+            return Ok(Self {
+                path: path.to_path_buf(),
+                _dir: None,
+            });
+        }
+        let name = path
+            .file_name()
+            .ok_or_else(|| AppError::Internal(format!("no file name in {}", path.display())))?;
+        let dir = crate::disk::tempdir()?;
+        let target = dir.path().join(name);
+        unseal_to(path, &target).await?;
+        Ok(Self {
+            path: target,
+            _dir: Some(dir),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_seal_round_trip() {
+        let key = Key::parse(&BASE64.encode([7u8; 32])).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        for size in [0, 10, SEGMENT, SEGMENT + 1, 3 * SEGMENT + 5] {
+            let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+            let path = dir.path().join(format!("{}.bin", size));
+            let mut writer = Writer::with_key(&path, Some(key.clone())).await.unwrap();
+            for chunk in data.chunks(1000) {
+                writer.write(chunk).await.unwrap();
+            }
+            writer.finish().await.unwrap();
+
+            let sealed = std::fs::read(&path).unwrap();
+            assert!(sealed.starts_with(MAGIC));
+            let mut plain = Vec::new();
+            unseal(&key, &mut sealed.as_slice(), &mut plain)
+                .await
+                .unwrap();
+            assert_eq!(plain, data, "{} bytes", size);
+
+            // Cutting off the last segment or changing a byte is detected
+            if size > SEGMENT {
+                let cut = &sealed[..MAGIC.len() + PREFIX_LEN + SEALED_SEGMENT];
+                assert!(unseal(&key, &mut &cut[..], &mut Vec::new()).await.is_err());
+            }
+            let mut tampered = sealed.clone();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert!(unseal(&key, &mut tampered.as_slice(), &mut Vec::new())
+                .await
+                .is_err());
+        }
+
+        assert!(matches!(Key::parse("c2hvcnQ="), Err(e) if e == "expected 32 bytes, got 5"));
+    }
+}
@@ -0,0 +1,215 @@
+//! Chart specifications rendered by the Chromium engine: Vega and
+//! Vega-Lite through vega-embed, Plotly figures through plotly.js, all
+//! loaded from the local library directory so renders need no network.
+
+use crate::error::{AppError, Result};
+use pdfmill_core::ConvertOptions;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// Where the chart libraries are installed, `PDFMILL_CHART_LIBS` or the
+/// directory the Docker image fills
+static LIBRARY_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    std::env::var_os("PDFMILL_CHART_LIBS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/usr/share/pdfmill/charts"))
+});
+
+/// Directory the chart libraries are loaded from, which rendered pages may read
+pub fn library_dir() -> &'static Path {
+    &LIBRARY_DIR
+}
+
+/// Chart size when neither the options nor the specification give one
+const DEFAULT_WIDTH: i64 = 640;
+const DEFAULT_HEIGHT: i64 = 400;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Library {
+    Vega,
+    VegaLite,
+    Plotly,
+}
+
+impl Library {
+    /// Scripts loaded in order
+    fn scripts(self) -> &'static [&'static str] {
+        match self {
+            Library::Vega | Library::VegaLite => {
+                &["vega.min.js", "vega-lite.min.js", "vega-embed.min.js"]
+            }
+            Library::Plotly => &["plotly.min.js"],
+        }
+    }
+
+    /// Recognise a specification by its `$schema`, the `.vl.json`/`.vg.json`
+    /// naming convention or its shape
+    fn detect(filename: &str, spec: &Value) -> Option<Self> {
+        let schema = spec["$schema"].as_str().unwrap_or("");
+        let filename = filename.to_lowercase();
+        if schema.contains("vega-lite") || filename.ends_with(".vl.json") {
+            Some(Library::VegaLite)
+        } else if schema.contains("/vega/") || filename.ends_with(".vg.json") {
+            Some(Library::Vega)
+        } else if spec["data"].is_array() && spec.get("marks").is_none() {
+            Some(Library::Plotly)
+        } else if spec.get("mark").is_some() {
+            Some(Library::VegaLite)
+        } else if spec.get("marks").is_some() {
+            Some(Library::Vega)
+        } else {
+            None
+        }
+    }
+}
+
+/// Build the page rendering a chart specification. The page sizes its
+/// PDF page to the chart once drawn when `fit_page` is set.
+pub fn chart_to_html(
+    src: &str,
+    filename: &str,
+    options: &ConvertOptions,
+    fit_page: bool,
+) -> Result<String> {
+    render(src, filename, options, fit_page, &LIBRARY_DIR)
+}
+
+fn render(
+    src: &str,
+    filename: &str,
+    options: &ConvertOptions,
+    fit_page: bool,
+    library_dir: &Path,
+) -> Result<String> {
+    let mut spec: Value = serde_json::from_str(src)
+        .map_err(|e| AppError::InvalidRequest(format!("file: not valid JSON: {}", e)))?;
+    let library = Library::detect(filename, &spec).ok_or_else(|| {
+        AppError::InvalidRequest(
+            "file: not a Vega, Vega-Lite or Plotly chart specification".to_string(),
+        )
+    })?;
+
+    let mut scripts = String::new();
+    for name in library.scripts() {
+        let path = library_dir.join(name);
+        if !path.is_file() {
+            return Err(AppError::ConversionFailed(format!(
+                "Chart library {} is not installed in {} (set PDFMILL_CHART_LIBS)",
+                name,
+                library_dir.display()
+            )));
+        }
+        scripts.push_str(&format!(
+            "<script src=\"file://{}\"></script>\n",
+            path.display()
+        ));
+    }
+
+    // Sizes the specification leaves open get the defaults, so charts do
+    // not stretch to the viewport
+    let draw = match library {
+        Library::Vega | Library::VegaLite => {
+            let size = |key: &str, option: Option<i64>, default: i64| {
+                option
+                    .map(Value::from)
+                    .or_else(|| spec.get(key).cloned())
+                    .unwrap_or_else(|| default.into())
+            };
+            let width = size("width", options.chart_width, DEFAULT_WIDTH);
+            let height = size("height", options.chart_height, DEFAULT_HEIGHT);
+            spec["width"] = width;
+            spec["height"] = height;
+            "vegaEmbed(chart, spec, { renderer: 'svg', actions: false })"
+        }
+        Library::Plotly => {
+            let layout = &mut spec["layout"];
+            if !layout.is_object() {
+                *layout = Value::Object(Default::default());
+            }
+            let size = |value: &Value, option: Option<i64>, default: i64| {
+                option
+                    .map(Value::from)
+                    .or_else(|| value.as_f64().map(Value::from))
+                    .unwrap_or_else(|| default.into())
+            };
+            layout["width"] = size(&layout["width"], options.chart_width, 700);
+            layout["height"] = size(&layout["height"], options.chart_height, 450);
+            "Plotly.newPlot(chart, spec.data, spec.layout, \
+             Object.assign({ staticPlot: true }, spec.config))"
+        }
+    };
+
+    // Keep "</script>" inside strings from ending the script element
+    let spec = serde_json::to_string(&spec)
+        .unwrap_or_default()
+        .replace("</", "<\\/");
+    Ok(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+{scripts}    <style>
+        html, body {{ margin: 0; padding: 0; background: #fff; }}
+        #chart {{ display: inline-block; }}
+    </style>
+</head>
+<body>
+<div id="chart"></div>
+<script>
+const chart = document.getElementById('chart');
+const spec = {spec};
+window.__pdfmillReady = Promise.resolve({draw}).then(() => {{
+    if (!{fit_page}) return;
+    const box = chart.getBoundingClientRect();
+    const style = document.createElement('style');
+    const size = `${{Math.ceil(box.width)}}px ${{Math.ceil(box.height)}}px`;
+    style.textContent = `@page {{ size: ${{size}}; margin: 0; }}`;
+    document.head.appendChild(style);
+}});
+</script>
+</body>
+</html>"#,
+        scripts = scripts,
+        spec = spec,
+        draw = draw,
+        fit_page = fit_page,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chart_to_html() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["vega.min.js", "vega-lite.min.js", "vega-embed.min.js"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let options = ConvertOptions {
+            chart_width: Some(800),
+            ..Default::default()
+        };
+
+        let spec = r#"{"mark": "bar", "height": 300, "title": "</script>"}"#;
+        let html = render(spec, "sales.json", &options, true, dir.path()).unwrap();
+        assert!(html.contains("vega-embed.min.js"));
+        assert!(html.contains(r#""width":800"#));
+        assert!(html.contains(r#""height":300"#));
+        assert!(html.contains("<\\/script>"));
+        assert!(html.contains("if (!true) return;"));
+
+        // plotly.min.js is missing
+        let figure = r#"{"data": [{"type": "bar", "x": [1, 2], "y": [3, 4]}]}"#;
+        let e = render(figure, "figure.json", &options, false, dir.path()).unwrap_err();
+        assert!(e.to_string().contains("plotly.min.js"), "{}", e);
+
+        let e = render(r#"{"rows": []}"#, "data.json", &options, false, dir.path()).unwrap_err();
+        assert!(matches!(e, AppError::InvalidRequest(_)));
+        assert_eq!(
+            Library::detect("a.vl.json", &serde_json::json!({})),
+            Some(Library::VegaLite)
+        );
+    }
+}
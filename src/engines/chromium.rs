@@ -1,36 +1,309 @@
-use super::{ConvertEngine, ConvertOptions, ConvertResult, EngineType};
+use super::chart;
+use super::email;
+use super::frontmatter::{self, FrontMatter};
+use super::highlight;
+use super::locate::{locate, Located};
+use super::map;
+use super::math;
+use super::org::org_to_html;
+use super::shell;
+use super::{
+    print_timezone, probe_version, ConvertEngine, ConvertOptions, ConvertResult, EngineType,
+};
+use crate::assets;
+use crate::egress;
 use crate::error::{AppError, Result};
+use crate::{metrics, pdf};
 use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use chromiumoxide::browser::{Browser, BrowserConfig};
-use chromiumoxide::cdp::browser_protocol::page::PrintToPdfParams;
+use chromiumoxide::cdp::browser_protocol::browser::{
+    BrowserContextId, PermissionDescriptor, PermissionSetting, SetPermissionParams,
+};
+use chromiumoxide::cdp::browser_protocol::emulation::{
+    MediaFeature, SetDeviceMetricsOverrideParams, SetEmulatedMediaParams,
+    SetGeolocationOverrideParams, SetLocaleOverrideParams, SetTimezoneOverrideParams,
+};
+use chromiumoxide::cdp::browser_protocol::fetch::{
+    self, EventRequestPaused, FulfillRequestParams, HeaderEntry, RequestPattern,
+};
+use chromiumoxide::cdp::browser_protocol::network::{
+    CookieParam, ErrorReason, EventLoadingFailed, EventRequestWillBeSent, LoaderId,
+    SetCookiesParams,
+};
+use chromiumoxide::cdp::browser_protocol::page::{
+    CaptureScreenshotFormat, EventLifecycleEvent, NavigateParams, PrintToPdfParams,
+};
+use chromiumoxide::cdp::browser_protocol::target::{
+    CreateBrowserContextParams, CreateTargetParams,
+};
+use chromiumoxide::cdp::js_protocol::runtime::EvaluateParams;
+use chromiumoxide::error::CdpError;
+use chromiumoxide::page::{Page, ScreenshotParams};
+use chromiumoxide::Binary;
+use chrono::Utc;
+use chrono_tz::Tz;
 use futures::StreamExt;
-use std::path::Path;
+use pdfmill_core::{parse_to_inches, MediaType, WaitUntil};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
 use tokio::process::Command;
-use tokio::sync::Mutex;
-use tracing::info;
+use tokio::sync::{watch, Mutex, MutexGuard};
+use tracing::{info, warn};
 
-const SUPPORTED_EXTENSIONS: &[&str] = &["html", "htm", "xhtml", "md", "markdown"];
+const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "html", "htm", "xhtml", "md", "markdown", "org", "geojson", "kml", "json", "eml",
+];
+
+/// Permissions that are always denied so pages never wait on a prompt
+const DENIED_PERMISSIONS: &[&str] = &["notifications", "camera", "microphone", "clipboard-read"];
+
+/// Layout of the table of contents added for `toc`: a page of its own,
+/// indented by heading level
+const TOC_CSS: &str = r#"
+nav.pdfmill-toc { break-after: page; }
+nav.pdfmill-toc ul { list-style: none; padding: 0; }
+nav.pdfmill-toc li { margin: 0.3em 0; }
+nav.pdfmill-toc .toc-h2 { padding-left: 1.5em; }
+nav.pdfmill-toc .toc-h3 { padding-left: 3em; }
+"#;
+
+/// Overrides dark themes so pages do not print as solid black ink
+const LIGHT_BACKGROUND_CSS: &str = r#"
+:root { color-scheme: light !important; }
+html, body { background: #fff !important; color: #111 !important; }
+*:not(img):not(svg):not(svg *):not(video):not(canvas) {
+    background-color: transparent !important;
+    background-image: none !important;
+    color: #111 !important;
+    border-color: #ccc !important;
+    box-shadow: none !important;
+    text-shadow: none !important;
+}
+a, a * { color: #0645ad !important; }
+"#;
+
+/// Options applied to the page or its printing
+const PAGE_OPTIONS: &[&str] = &[
+    "landscape",
+    "printBackground",
+    "displayHeaderFooter",
+    "headerTemplate",
+    "footerTemplate",
+    "pageRanges",
+    "scale",
+    "preferCSSPageSize",
+    "pageWidth",
+    "pageHeight",
+    "marginTop",
+    "marginBottom",
+    "marginLeft",
+    "marginRight",
+    "viewportWidth",
+    "viewportHeight",
+    "selector",
+    "waitForSelector",
+    "waitForDelayMs",
+    "waitUntil",
+    "emulateMedia",
+    "customCss",
+    "customJs",
+    "forceLightBackground",
+    "toc",
+    "bookmarks",
+    "locale",
+    "timezone",
+    "geolocation",
+    "httpUsername",
+    "httpPassword",
+    "httpAuthOrigin",
+    "proxy",
+    "cookies",
+    "extraHeaders",
+    "dpi",
+];
+
+/// Header or footer template printing nothing; an empty template would
+/// get Chromium's default
+const BLANK_TEMPLATE: &str = "<span></span>";
+
+/// Options that only size chart specifications (`.json` inputs)
+const CHART_OPTIONS: &[&str] = &["chartWidth", "chartHeight"];
+
+/// Largest HTML upload served to the browser from memory; larger ones are
+/// loaded from their file
+const MAX_INLINE_DOCUMENT: usize = 8 * 1024 * 1024;
+
+/// Directory of the file URLs in-memory documents are served at; nothing
+/// is read from it
+const INLINE_DIR: &str = "/pdfmill-inline";
+
+/// Longest wait for `waitForSelector`
+const SELECTOR_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest run of a `customJs` script, including the promise it returns
+const SCRIPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest wait for the network to go idle with `waitUntil=networkidle`,
+/// after which the page is printed anyway
+const NETWORK_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Failed subresource loads reported per conversion
+const MAX_FAILED_LOADS: usize = 10;
+
+/// CSS pixels per inch, the pixel density of renders without `dpi`
+const CSS_DPI: f64 = 96.0;
+
+/// Viewport used when only one dimension is overridden
+const DEFAULT_VIEWPORT_WIDTH: i64 = 1280;
+const DEFAULT_VIEWPORT_HEIGHT: i64 = 800;
 
 pub struct ChromiumEngine {
     /// Persistent browser instance for fast PDF generation via CDP
     browser: Arc<Mutex<Option<Browser>>>,
+    /// Launch a browser for each conversion and close it afterwards,
+    /// trading speed for memory
+    per_request: bool,
+    /// Whether the persistent browser runs on the standby profile
+    on_standby_profile: AtomicBool,
+    /// Held while a replacement browser is launched, so swaps never share
+    /// a profile
+    swapping: Mutex<()>,
 }
 
 impl ChromiumEngine {
     pub fn new() -> Self {
         Self {
             browser: Arc::new(Mutex::new(None)),
+            per_request: false,
+            on_standby_profile: AtomicBool::new(false),
+            swapping: Mutex::new(()),
         }
     }
 
+    /// Do not keep a browser running between conversions
+    pub fn launch_per_request(mut self) -> Self {
+        self.per_request = true;
+        self
+    }
+
     /// Initialize the persistent browser instance
     pub async fn init(&self) -> std::result::Result<(), String> {
-        let chrome_path = get_chrome_path();
+        let browser = self.launch(crate::disk::chromium_profile()).await?;
+
+        let mut guard = self.browser.lock().await;
+        *guard = Some(browser);
+
+        info!("Persistent Chromium browser launched via CDP");
+        Ok(())
+    }
+
+    /// Replace the persistent browser without a gap in service: launch a
+    /// new one on the other profile, warm it up with a blank render, then
+    /// switch to it between conversions and close the old one. Returns how
+    /// long the replacement took to become ready.
+    pub async fn swap(&self) -> Result<Duration> {
+        let result = self.replace_browser().await;
+        let outcome = if result.is_ok() { "ok" } else { "failed" };
+        metrics::increment("pdfmill_browser_swaps_total", &[("outcome", outcome)]);
+        result
+    }
+
+    async fn replace_browser(&self) -> Result<Duration> {
+        if self.per_request {
+            return Err(AppError::InvalidRequest(
+                "the browser is launched for each conversion and needs no swapping".to_string(),
+            ));
+        }
+        let _swapping = self.swapping.lock().await;
+        let started = Instant::now();
+        let standby = !self.on_standby_profile.load(Ordering::Relaxed);
+        let profile = if standby {
+            crate::disk::chromium_standby_profile()
+        } else {
+            crate::disk::chromium_profile()
+        };
+
+        let mut browser = self
+            .launch(profile)
+            .await
+            .map_err(AppError::EngineNotAvailable)?;
+        if let Err(e) = warm_up(&browser).await {
+            let _ = browser.close().await;
+            let _ = browser.wait().await;
+            return Err(AppError::EngineNotAvailable(format!(
+                "Replacement browser failed its warm-up: {}",
+                e
+            )));
+        }
+        let warm = started.elapsed();
+
+        // Waits for the conversion in progress, if any
+        let old = self.browser.lock().await.replace(browser);
+        self.on_standby_profile.store(standby, Ordering::Relaxed);
+        info!(
+            "Switched to a replacement Chromium browser, ready after {:?}",
+            warm
+        );
+
+        if let Some(mut old) = old {
+            tokio::spawn(async move {
+                if let Err(e) = old.close().await {
+                    warn!("Failed to close the replaced browser: {}", e);
+                }
+                let _ = old.wait().await;
+            });
+        }
+        Ok(warm)
+    }
+
+    /// Interval of scheduled swaps from `PDFMILL_BROWSER_SWAP_INTERVAL`
+    /// in seconds; none when unset
+    pub fn swap_interval_from_env() -> std::result::Result<Option<Duration>, String> {
+        match std::env::var("PDFMILL_BROWSER_SWAP_INTERVAL") {
+            Ok(value) if !value.trim().is_empty() => value
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|secs| *secs >= 60)
+                .map(|secs| Some(Duration::from_secs(secs)))
+                .ok_or_else(|| {
+                    format!(
+                        "PDFMILL_BROWSER_SWAP_INTERVAL: expected at least 60 seconds, got '{}'",
+                        value.trim()
+                    )
+                }),
+            _ => Ok(None),
+        }
+    }
+
+    /// Swap the browser every `interval`; failed swaps keep the current
+    /// browser and are retried at the next tick
+    pub fn spawn_recycler(self: Arc<Self>, interval: Duration) {
+        tokio::spawn(async move {
+            let start = tokio::time::Instant::now() + interval;
+            let mut ticks = tokio::time::interval_at(start, interval);
+            loop {
+                ticks.tick().await;
+                if let Err(e) = self.swap().await {
+                    warn!("Scheduled browser swap failed: {}", e);
+                }
+            }
+        });
+    }
+
+    async fn launch(&self, profile: PathBuf) -> std::result::Result<Browser, String> {
+        let chrome = locate_chrome();
 
-        let config = BrowserConfig::builder()
-            .chrome_executable(chrome_path)
+        let mut builder = BrowserConfig::builder()
+            .chrome_executable(&chrome.path)
+            .user_data_dir(profile)
             .no_sandbox()
             .arg("--disable-gpu")
             .arg("--disable-dev-shm-usage")
@@ -39,50 +312,345 @@ impl ChromiumEngine {
             .arg("--disable-sync")
             .arg("--disable-translate")
             .arg("--disable-default-apps")
-            .arg("--headless")
+            .arg("--headless");
+
+        // Keep the browser to a single renderer on small hosts
+        if self.per_request {
+            builder = builder.arg("--renderer-process-limit=1");
+        }
+
+        // Route all browser traffic through the configured proxy
+        if let Ok(proxy) = std::env::var("CHROME_PROXY_SERVER") {
+            builder = builder.arg(format!("--proxy-server={}", proxy));
+            if let Ok(bypass) = std::env::var("CHROME_PROXY_BYPASS") {
+                builder = builder.arg(format!("--proxy-bypass-list={}", bypass));
+            }
+        }
+
+        let config = builder
             .build()
             .map_err(|e| format!("Failed to build browser config: {}", e))?;
 
-        let (browser, mut handler) = Browser::launch(config)
-            .await
-            .map_err(|e| format!("Failed to launch browser: {}", e))?;
+        let (browser, mut handler) = Browser::launch(config).await.map_err(|e| {
+            format!(
+                "Failed to launch browser ({}): {}",
+                chrome.describe_tried(),
+                e
+            )
+        })?;
 
         // Spawn the browser event handler in background
         tokio::spawn(async move {
             while let Some(_event) = handler.next().await {}
         });
 
+        Ok(browser)
+    }
+
+    /// Lock the browser for a conversion, launching it first when it is
+    /// not kept running
+    async fn acquire(&self) -> Result<MutexGuard<'_, Option<Browser>>> {
         let mut guard = self.browser.lock().await;
-        *guard = Some(browser);
+        if guard.is_none() && self.per_request {
+            let browser = self
+                .launch(crate::disk::chromium_profile())
+                .await
+                .map_err(AppError::EngineNotAvailable)?;
+            *guard = Some(browser);
+        }
+        if guard.is_none() {
+            return Err(AppError::EngineNotAvailable(
+                "Chromium browser not initialized".to_string(),
+            ));
+        }
+        Ok(guard)
+    }
 
-        info!("Persistent Chromium browser launched via CDP");
-        Ok(())
+    /// End a conversion, closing a browser launched just for it
+    async fn release(&self, mut guard: MutexGuard<'_, Option<Browser>>) {
+        if !self.per_request {
+            return;
+        }
+        if let Some(mut browser) = guard.take() {
+            if let Err(e) = browser.close().await {
+                warn!("Failed to close browser: {}", e);
+            }
+            let _ = browser.wait().await;
+        }
     }
 
-    async fn convert_html_to_pdf_cdp(
+    /// Open a URL in a new tab, applying page-level emulation first; with
+    /// `content`, that is served as the document at `url`. Every conversion
+    /// gets its own incognito browser context so cookies, storage and cache
+    /// never leak between requests.
+    async fn open_page(
         &self,
-        input_path: &Path,
+        browser: &Browser,
+        url: &str,
+        content: Option<&[u8]>,
         options: &ConvertOptions,
-    ) -> Result<Vec<u8>> {
-        let guard = self.browser.lock().await;
-        let browser = guard.as_ref().ok_or_else(|| {
-            AppError::EngineNotAvailable("Chromium browser not initialized".to_string())
+    ) -> Result<RenderPage> {
+        // The allowlist would mean nothing if a caller could pick any proxy
+        if let Some(ref proxy) = options.proxy {
+            egress::check_proxy(proxy).await?;
+        }
+        let params = CreateBrowserContextParams {
+            dispose_on_detach: Some(true),
+            proxy_server: options.proxy.clone(),
+            proxy_bypass_list: options
+                .proxy
+                .as_ref()
+                .and_then(|_| std::env::var("CHROME_PROXY_BYPASS").ok()),
+            ..Default::default()
+        };
+        let context = browser.create_browser_context(params).await.map_err(|e| {
+            AppError::ConversionFailed(format!("Failed to create browser context: {}", e))
         })?;
 
-        let page = browser.new_page("about:blank").await.map_err(|e| {
-            AppError::ConversionFailed(format!("Failed to create new tab: {}", e))
-        })?;
+        let mut target = CreateTargetParams::new("about:blank");
+        target.browser_context_id = Some(context.clone());
+        let page = match browser.new_page(target).await {
+            Ok(page) => page,
+            Err(e) => {
+                let _ = browser.dispose_browser_context(context).await;
+                return Err(AppError::ConversionFailed(format!(
+                    "Failed to create new tab: {}",
+                    e
+                )));
+            }
+        };
+
+        let render = RenderPage {
+            page,
+            context,
+            browser: Arc::clone(&self.browser),
+            closed: false,
+            failed_loads: Default::default(),
+        };
+        if let Err(e) = self
+            .setup_page(browser, &render, url, content, options)
+            .await
+        {
+            render.close(browser).await;
+            return Err(e);
+        }
+        Ok(render)
+    }
+
+    async fn setup_page(
+        &self,
+        browser: &Browser,
+        render: &RenderPage,
+        url: &str,
+        content: Option<&[u8]>,
+        options: &ConvertOptions,
+    ) -> Result<()> {
+        let page = &render.page;
+
+        if options.viewport_width.is_some()
+            || options.viewport_height.is_some()
+            || options.dpi.is_some()
+        {
+            let metrics = SetDeviceMetricsOverrideParams::new(
+                options.viewport_width.unwrap_or(DEFAULT_VIEWPORT_WIDTH),
+                options.viewport_height.unwrap_or(DEFAULT_VIEWPORT_HEIGHT),
+                options.dpi.map_or(1.0, |dpi| dpi as f64 / CSS_DPI),
+                false,
+            );
+            page.execute(metrics).await.map_err(|e| {
+                AppError::ConversionFailed(format!("Failed to set viewport: {}", e))
+            })?;
+        }
+
+        apply_permissions(browser, &render.context, options.geolocation.is_some()).await;
+
+        let remote = reqwest::Url::parse(url)
+            .ok()
+            .filter(|u| u.scheme() != "file");
+        if let Some(ref remote) = remote {
+            // Also sends the credentials and extra headers, to the page's
+            // own origin only
+            guard_requests(page, remote, content, options).await?;
+            set_cookies(page, remote, &options.cookies).await?;
+        } else {
+            guard_local_requests(page, url, content, options).await?;
+            // Uploaded documents have no origin to send them to
+            if !options.cookies.is_empty() || !options.extra_headers.is_empty() {
+                render.failed_loads.lock().unwrap().push(
+                    "cookies and extraHeaders apply to remote pages only and were ignored"
+                        .to_string(),
+                );
+            }
+            if options.http_username.is_some() && options.http_auth_origin.is_none() {
+                render.failed_loads.lock().unwrap().push(
+                    "httpUsername applies to remote pages, or with httpAuthOrigin to uploaded \
+                     documents, and was ignored"
+                        .to_string(),
+                );
+            }
+        }
+
+        if let Some(ref geo) = options.geolocation {
+            page.emulate_geolocation(SetGeolocationOverrideParams {
+                latitude: Some(geo.latitude),
+                longitude: Some(geo.longitude),
+                accuracy: Some(geo.accuracy),
+            })
+            .await
+            .map_err(|e| AppError::ConversionFailed(format!("Failed to set geolocation: {}", e)))?;
+        }
+
+        if let Some(ref locale) = options.locale {
+            page.emulate_locale(SetLocaleOverrideParams {
+                locale: Some(locale.clone()),
+            })
+            .await
+            .map_err(|e| AppError::InvalidRequest(format!("Invalid locale '{}': {}", locale, e)))?;
+        }
+
+        let timezone = print_timezone(options);
+        page.emulate_timezone(SetTimezoneOverrideParams::new(timezone))
+            .await
+            .map_err(|e| {
+                AppError::InvalidRequest(format!("Invalid timezone '{}': {}", timezone, e))
+            })?;
+
+        // One call sets both: each replaces the emulated media as a whole
+        let media = SetEmulatedMediaParams {
+            media: options.emulate_media.map(|media_type| {
+                match media_type {
+                    MediaType::Print => "print",
+                    MediaType::Screen => "screen",
+                }
+                .to_string()
+            }),
+            // Sites that follow prefers-color-scheme switch themselves
+            features: options
+                .force_light_background
+                .then(|| vec![MediaFeature::new("prefers-color-scheme", "light")]),
+        };
+        if media.media.is_some() || media.features.is_some() {
+            page.execute(media).await.map_err(|e| {
+                AppError::ConversionFailed(format!("Failed to emulate media: {}", e))
+            })?;
+        }
+
+        watch_failed_loads(page, Arc::clone(&render.failed_loads)).await?;
+
+        let idle = match options.wait_until {
+            WaitUntil::NetworkIdle => Some(watch_network_idle(page).await?),
+            WaitUntil::Load => None,
+        };
 
-        // Navigate to the local file (goto waits for load to complete)
-        let input_url = format!("file://{}", input_path.canonicalize()?.display());
-        page.goto(&input_url)
+        // Navigate (waits for the load event)
+        let navigation = page
+            .execute(NavigateParams::new(url))
             .await
             .map_err(|e| AppError::ConversionFailed(format!("Failed to navigate: {}", e)))?;
+        if let Some(ref error) = navigation.result.error_text {
+            return Err(AppError::ConversionFailed(format!(
+                "Failed to navigate: {}",
+                error
+            )));
+        }
+        if let (Some(mut idle), Some(loader)) = (idle, navigation.result.loader_id.clone()) {
+            let wait = idle.wait_for(|loaders| loaders.contains(&loader));
+            if !matches!(
+                tokio::time::timeout(NETWORK_IDLE_TIMEOUT, wait).await,
+                Ok(Ok(_))
+            ) {
+                render.failed_loads.lock().unwrap().push(format!(
+                    "waitUntil: the network was still busy after {}s; printed anyway",
+                    NETWORK_IDLE_TIMEOUT.as_secs()
+                ));
+            }
+        }
+
+        // Pages that draw after the load event (charts) say when they are done
+        page.evaluate("window.__pdfmillReady ? window.__pdfmillReady.then(() => true) : true")
+            .await
+            .map_err(|e| AppError::ConversionFailed(format!("Page failed to render: {}", e)))?;
+
+        if let Some(ref selector) = options.wait_for_selector {
+            wait_for_element(page, selector).await?;
+        }
+        if let Some(delay) = options.wait_for_delay_ms {
+            tokio::time::sleep(Duration::from_millis(delay as u64)).await;
+        }
+
+        if let Some(ref script) = options.custom_js {
+            run_custom_script(page, script).await?;
+        }
+
+        if options.force_light_background {
+            inject_style(page, LIGHT_BACKGROUND_CSS).await?;
+        }
+        // Last, so it wins over the page's styles and ours
+        if let Some(ref css) = options.custom_css {
+            inject_style(page, css).await?;
+        }
+
+        if let Some(ref selector) = options.selector {
+            isolate_element(page, selector).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Print a page to PDF, returning warnings for resources that failed
+    /// to load. With `css_page_size` or the `preferCSSPageSize` option, the
+    /// page's `@page` size rule wins over the paper size.
+    async fn convert_html_to_pdf_cdp(
+        &self,
+        url: &str,
+        content: Option<&[u8]>,
+        options: &ConvertOptions,
+        css_page_size: bool,
+    ) -> Result<(Vec<u8>, Vec<String>)> {
+        let guard = self.acquire().await?;
+        let browser = guard.as_ref().expect("acquired browser");
+
+        let render = match self.open_page(browser, url, content, options).await {
+            Ok(render) => render,
+            Err(e) => {
+                self.release(guard).await;
+                return Err(e);
+            }
+        };
+        let headings = match list_headings(&render.page, options).await {
+            Ok(headings) => headings,
+            Err(e) => {
+                render.close(browser).await;
+                self.release(guard).await;
+                return Err(e);
+            }
+        };
 
         // Build PrintToPDF params
-        let mut params = PrintToPdfParams::default();
-        params.landscape = Some(options.landscape);
-        params.print_background = Some(options.print_background);
+        let mut params = PrintToPdfParams {
+            landscape: Some(options.landscape),
+            print_background: Some(options.print_background),
+            margin_top: options.margin_top.as_deref().and_then(parse_to_inches),
+            margin_bottom: options.margin_bottom.as_deref().and_then(parse_to_inches),
+            margin_left: options.margin_left.as_deref().and_then(parse_to_inches),
+            margin_right: options.margin_right.as_deref().and_then(parse_to_inches),
+            prefer_css_page_size: Some(css_page_size || options.prefer_css_page_size),
+            page_ranges: options.page_ranges.clone(),
+            scale: options.scale,
+            ..Default::default()
+        };
+
+        // A template turns the header and footer on; the one left out is
+        // blank rather than Chromium's default date/title or URL/page line
+        let header = options.header_template.as_deref();
+        let footer = options.footer_template.as_deref();
+        if options.display_header_footer || header.is_some() || footer.is_some() {
+            params.display_header_footer = Some(true);
+            if header.is_some() || footer.is_some() {
+                params.header_template = Some(header.unwrap_or(BLANK_TEMPLATE).to_string());
+                params.footer_template = Some(footer.unwrap_or(BLANK_TEMPLATE).to_string());
+            }
+        }
 
         if let Some(ref width) = options.page_width {
             if let Some(inches) = parse_to_inches(width) {
@@ -96,42 +664,328 @@ impl ChromiumEngine {
         }
 
         // Generate PDF via CDP
-        let pdf_data = page.pdf(params).await.map_err(|e| {
-            AppError::ConversionFailed(format!("PDF generation failed: {}", e))
+        let pdf_data = render.page.pdf(params).await;
+        let warnings = render.failed_loads.lock().unwrap().clone();
+        render.close(browser).await;
+        self.release(guard).await;
+
+        let data = pdf_data.map_err(|e| {
+            let message = e.to_string();
+            // Chromium refuses ranges past the document's last page
+            match options.page_ranges {
+                Some(ref ranges) if message.contains("Page range exceeds page count") => {
+                    AppError::InvalidRequest(format!(
+                        "pageRanges: '{}' exceeds the document's page count",
+                        ranges
+                    ))
+                }
+                _ => AppError::ConversionFailed(format!("PDF generation failed: {}", message)),
+            }
         })?;
+        if options.bookmarks {
+            return add_bookmarks(data, &headings, warnings);
+        }
+        Ok((data, warnings))
+    }
 
-        // Page is automatically cleaned up when dropped
-        Ok(pdf_data)
+    /// Render a web page given by its http(s) URL to PDF. The page and
+    /// everything it loads has to pass the URL policy. With `document`, that
+    /// is rendered as the page's content in its place.
+    pub async fn convert_url(
+        &self,
+        url: &str,
+        document: Option<&[u8]>,
+        options: &ConvertOptions,
+    ) -> Result<ConvertResult> {
+        let parsed = parse_web_url(url)?;
+        egress::check_url(&parsed).await?;
+        info!("Converting {} to PDF using Chromium (CDP)", url);
+        let (data, warnings) = self
+            .convert_html_to_pdf_cdp(parsed.as_str(), document, options, false)
+            .await?;
+        let name = parsed.host_str().unwrap_or("page").replace(['.', ':'], "-");
+        Ok(ConvertResult {
+            data,
+            filename: format!("{}.pdf", name),
+            content_type: "application/pdf".to_string(),
+            warnings,
+        })
+    }
+
+    /// Capture a full-page screenshot of an HTML/Markdown/Org input
+    pub async fn screenshot(
+        &self,
+        input_path: &Path,
+        options: &ConvertOptions,
+        format: CaptureScreenshotFormat,
+        quality: Option<i64>,
+    ) -> Result<ConvertResult> {
+        let (html_path, _temp_dir, _) = self.prepare_html(input_path, options).await?;
+
+        info!(
+            "Capturing screenshot of {} using Chromium (CDP)",
+            html_path.display()
+        );
+        let url = file_url(&html_path)?;
+        let data = self
+            .capture(&url, None, options, format.clone(), quality)
+            .await?;
+
+        let original_name = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        Ok(screenshot_result(data, original_name, &format))
+    }
+
+    /// Capture a full-page screenshot of a remote page, shown with
+    /// `document` as its content when given
+    pub async fn screenshot_url(
+        &self,
+        url: &str,
+        document: Option<&[u8]>,
+        options: &ConvertOptions,
+        format: CaptureScreenshotFormat,
+        quality: Option<i64>,
+    ) -> Result<ConvertResult> {
+        let parsed = parse_web_url(url)?;
+        egress::check_url(&parsed).await?;
+        info!("Capturing screenshot of {} using Chromium (CDP)", url);
+        let data = self
+            .capture(parsed.as_str(), document, options, format.clone(), quality)
+            .await?;
+        let name = parsed.host_str().unwrap_or("page").replace(['.', ':'], "-");
+        Ok(screenshot_result(data, &name, &format))
+    }
+
+    async fn capture(
+        &self,
+        url: &str,
+        content: Option<&[u8]>,
+        options: &ConvertOptions,
+        format: CaptureScreenshotFormat,
+        quality: Option<i64>,
+    ) -> Result<Vec<u8>> {
+        let guard = self.acquire().await?;
+        let browser = guard.as_ref().expect("acquired browser");
+
+        let render = match self.open_page(browser, url, content, options).await {
+            Ok(render) => render,
+            Err(e) => {
+                self.release(guard).await;
+                return Err(e);
+            }
+        };
+
+        let mut params = ScreenshotParams::builder()
+            .format(format.clone())
+            .full_page(true)
+            .omit_background(!options.print_background);
+        // Quality is only meaningful for lossy formats
+        if let Some(quality) = quality.filter(|_| format != CaptureScreenshotFormat::Png) {
+            params = params.quality(quality);
+        }
+
+        let data = render.page.screenshot(params.build()).await;
+        render.close(browser).await;
+        self.release(guard).await;
+
+        data.map_err(|e| AppError::ConversionFailed(format!("Screenshot failed: {}", e)))
+    }
+
+    /// Render markdown/org/map/chart/email inputs to a temporary HTML file;
+    /// HTML is used as-is. Markdown front matter is returned for the print.
+    async fn prepare_html(
+        &self,
+        input_path: &Path,
+        options: &ConvertOptions,
+    ) -> Result<(PathBuf, Option<TempDir>, Option<FrontMatter>)> {
+        let ext = input_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        if ext == "md" || ext == "markdown" {
+            let temp_dir = crate::disk::tempdir()?;
+            let html_path = temp_dir.path().join("input.html");
+            let front_matter = self
+                .convert_markdown_to_html(input_path, &html_path, options)
+                .await?;
+            Ok((html_path, Some(temp_dir), front_matter))
+        } else if ext == "org" {
+            let temp_dir = crate::disk::tempdir()?;
+            let html_path = temp_dir.path().join("input.html");
+            self.convert_org_to_html(input_path, &html_path).await?;
+            Ok((html_path, Some(temp_dir), None))
+        } else if ext == "geojson" || ext == "kml" {
+            let temp_dir = crate::disk::tempdir()?;
+            let html_path = temp_dir.path().join("input.html");
+            self.convert_map_to_html(input_path, &ext, &html_path)
+                .await?;
+            Ok((html_path, Some(temp_dir), None))
+        } else if ext == "json" {
+            let temp_dir = crate::disk::tempdir()?;
+            let html_path = temp_dir.path().join("input.html");
+            let content = tokio::fs::read_to_string(input_path).await?;
+            let filename = input_path
+                .file_name()
+                .and_then(|s| s.to_str())
+                .unwrap_or("");
+            let html = chart::chart_to_html(&content, filename, options, fits_chart(options))?;
+            tokio::fs::write(&html_path, html).await?;
+            Ok((html_path, Some(temp_dir), None))
+        } else if ext == "eml" {
+            let temp_dir = crate::disk::tempdir()?;
+            let html_path = temp_dir.path().join("input.html");
+            let timezone = parse_timezone(options)?;
+            let data = tokio::fs::read(input_path).await?;
+            let html = email::eml_to_html(&data, timezone)
+                .map_err(|e| AppError::InvalidRequest(format!("file: {}", e)))?;
+            tokio::fs::write(&html_path, html).await?;
+            Ok((html_path, Some(temp_dir), None))
+        } else {
+            Ok((input_path.to_path_buf(), None, None))
+        }
     }
 
-    async fn convert_markdown_to_html(&self, input_path: &Path, output_path: &Path) -> Result<()> {
+    async fn convert_markdown_to_html(
+        &self,
+        input_path: &Path,
+        output_path: &Path,
+        options: &ConvertOptions,
+    ) -> Result<Option<FrontMatter>> {
         let content = tokio::fs::read_to_string(input_path).await?;
+        let (front_matter, content) = frontmatter::split(&content)?;
+        let title = front_matter.as_ref().and_then(FrontMatter::title);
 
         // Simple markdown to HTML conversion
         // In production, use a proper markdown parser like pulldown-cmark
-        let html = format!(
-            r#"<!DOCTYPE html>
-<html>
-<head>
-    <meta charset="utf-8">
-    <style>
-        body {{ font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif; margin: 40px; line-height: 1.6; }}
-        pre {{ background: #f4f4f4; padding: 16px; overflow-x: auto; }}
-        code {{ background: #f4f4f4; padding: 2px 6px; }}
-    </style>
-</head>
-<body>
-{}
-</body>
-</html>"#,
-            markdown_to_html_simple(&content)
-        );
+        let body = markdown_to_html_simple(content);
+        let html = match &options.markdown_template {
+            Some(template) => {
+                let timezone = parse_timezone(options)?;
+                let context = shell::ShellContext {
+                    styles: &default_styles(),
+                    title,
+                    name: input_path
+                        .file_stem()
+                        .and_then(|s| s.to_str())
+                        .unwrap_or("document"),
+                    date: Utc::now()
+                        .with_timezone(&timezone)
+                        .format("%Y-%m-%d")
+                        .to_string(),
+                };
+                shell::render(template, &body, &context)?
+            }
+            None => wrap_html_document(title, &body),
+        };
+
+        tokio::fs::write(output_path, html).await?;
+        Ok(front_matter)
+    }
+
+    async fn convert_org_to_html(&self, input_path: &Path, output_path: &Path) -> Result<()> {
+        let content = tokio::fs::read_to_string(input_path).await?;
+        let html = wrap_html_document(None, &org_to_html(&content));
+
+        tokio::fs::write(output_path, html).await?;
+        Ok(())
+    }
+
+    async fn convert_map_to_html(
+        &self,
+        input_path: &Path,
+        ext: &str,
+        output_path: &Path,
+    ) -> Result<()> {
+        let content = tokio::fs::read_to_string(input_path).await?;
+        let title = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("Map");
+        let html = if ext == "kml" {
+            map::kml_to_html(&content, title)
+        } else {
+            map::geojson_to_html(&content, title)
+        }
+        .map_err(|e| AppError::InvalidRequest(format!("file: {}", e)))?;
 
         tokio::fs::write(output_path, html).await?;
         Ok(())
     }
 }
 
+/// The print timezone, for dates the server renders into the document
+fn parse_timezone(options: &ConvertOptions) -> Result<Tz> {
+    print_timezone(options).parse().map_err(|_| {
+        AppError::InvalidRequest(format!("Invalid timezone '{}'", print_timezone(options)))
+    })
+}
+
+/// Whether a chart gets a page of its own size, as no paper size was asked
+/// for
+fn fits_chart(options: &ConvertOptions) -> bool {
+    options.page_width.is_none() && options.page_height.is_none()
+}
+
+/// Render a blank page to PDF so a new browser has its renderer and print
+/// pipeline started before it takes traffic
+async fn warm_up(browser: &Browser) -> std::result::Result<(), String> {
+    let page = browser
+        .new_page("about:blank")
+        .await
+        .map_err(|e| e.to_string())?;
+    let result = page.pdf(PrintToPdfParams::default()).await;
+    let _ = page.close().await;
+    result.map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// A tab opened for a single conversion in its own browser context
+struct RenderPage {
+    page: Page,
+    context: BrowserContextId,
+    browser: Arc<Mutex<Option<Browser>>>,
+    closed: bool,
+    /// Subresources that failed to load, as warnings
+    failed_loads: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl RenderPage {
+    /// Close the tab and dispose its browser context
+    async fn close(mut self, browser: &Browser) {
+        self.closed = true;
+        if let Err(e) = self.page.clone().close().await {
+            warn!("Failed to close tab: {}", e);
+        }
+        if let Err(e) = browser.dispose_browser_context(self.context.clone()).await {
+            warn!("Failed to dispose browser context: {}", e);
+        }
+    }
+}
+
+impl Drop for RenderPage {
+    /// A cancelled conversion drops its page mid-render; clean up in the
+    /// background once the browser lock has been released
+    fn drop(&mut self) {
+        if self.closed {
+            return;
+        }
+        let (page, context, browser) = (
+            self.page.clone(),
+            self.context.clone(),
+            Arc::clone(&self.browser),
+        );
+        tokio::spawn(async move {
+            let _ = page.close().await;
+            if let Some(browser) = browser.lock().await.as_ref() {
+                let _ = browser.dispose_browser_context(context).await;
+            }
+        });
+    }
+}
+
 impl Default for ChromiumEngine {
     fn default() -> Self {
         Self::new()
@@ -164,116 +1018,904 @@ impl ConvertEngine for ChromiumEngine {
             .unwrap_or(false)
     }
 
+    async fn version(&self) -> Option<String> {
+        probe_version(&get_chrome_path(), "--version").await
+    }
+
+    fn searched_paths(&self) -> Vec<String> {
+        locate_chrome().tried
+    }
+
+    fn uses_option(&self, option: &str, ext: &str) -> bool {
+        PAGE_OPTIONS.contains(&option)
+            || (CHART_OPTIONS.contains(&option) && ext == "json")
+            || (option == "convertAttachments" && ext == "eml")
+            || (option == "markdownTemplate" && matches!(ext, "md" | "markdown"))
+    }
+
     async fn convert(&self, input_path: &Path, options: &ConvertOptions) -> Result<ConvertResult> {
-        let ext = input_path
+        // If markdown or org, convert to HTML first
+        let (html_path, _temp_dir, front_matter) = self.prepare_html(input_path, options).await?;
+        let merged;
+        let options = match &front_matter {
+            Some(front_matter) => {
+                merged = front_matter.apply(options)?;
+                &merged
+            }
+            None => options,
+        };
+        let is_chart = input_path
             .extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("")
-            .to_lowercase();
+            .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+        let css_page_size = is_chart && fits_chart(options);
 
-        // If markdown, convert to HTML first
-        let (html_path, _temp_dir) = if ext == "md" || ext == "markdown" {
-            let temp_dir = tempfile::tempdir()?;
-            let html_path = temp_dir.path().join("input.html");
-            self.convert_markdown_to_html(input_path, &html_path)
-                .await?;
-            (html_path, Some(temp_dir))
-        } else {
-            (input_path.to_path_buf(), None)
-        };
+        info!(
+            "Converting {} to PDF using Chromium (CDP)",
+            html_path.display()
+        );
+        let (mut data, warnings) = self
+            .convert_html_to_pdf_cdp(&file_url(&html_path)?, None, options, css_page_size)
+            .await?;
+        let info = front_matter
+            .as_ref()
+            .map(FrontMatter::info)
+            .unwrap_or_default();
+        if !info.is_empty() {
+            data = pdf::set_info(&data, &info)?;
+        }
 
-        info!("Converting {} to PDF using Chromium (CDP)", html_path.display());
-        let data = self.convert_html_to_pdf_cdp(&html_path, options).await?;
+        let original_name = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
 
-        let original_name = input_path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+        Ok(ConvertResult {
+            data,
+            filename: format!("{}.pdf", original_name),
+            content_type: "application/pdf".to_string(),
+            warnings,
+        })
+    }
+
+    fn reads_memory(&self, ext: &str, size: usize, _options: &ConvertOptions) -> bool {
+        matches!(ext, "html" | "htm" | "xhtml") && size <= MAX_INLINE_DOCUMENT
+    }
+
+    async fn convert_memory(
+        &self,
+        filename: &str,
+        data: &[u8],
+        options: &ConvertOptions,
+    ) -> Result<ConvertResult> {
+        let path = Path::new(filename);
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("html")
+            .to_lowercase();
+        let url = format!("file://{}/document.{}", INLINE_DIR, ext);
+
+        info!(
+            "Converting {} to PDF using Chromium (CDP), from memory",
+            filename
+        );
+        let (data, warnings) = self
+            .convert_html_to_pdf_cdp(&url, Some(data), options, false)
+            .await?;
+        let original_name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
 
         Ok(ConvertResult {
             data,
             filename: format!("{}.pdf", original_name),
             content_type: "application/pdf".to_string(),
+            warnings,
         })
     }
 }
 
-fn get_chrome_path() -> String {
-    // Check environment variable first
-    if let Ok(path) = std::env::var("CHROME_PATH") {
-        return path;
+/// Parse a URL that may be rendered: only http(s), so local files are never
+/// reachable through a URL
+pub fn parse_web_url(url: &str) -> Result<reqwest::Url> {
+    let parsed = reqwest::Url::parse(url.trim())
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid URL '{}': {}", url, e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(AppError::InvalidRequest(format!(
+            "Only http and https URLs can be converted, got '{}'",
+            url
+        )));
     }
+    Ok(parsed)
+}
 
-    // Fall back to OS-specific defaults
-    if cfg!(target_os = "macos") {
-        "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome".to_string()
-    } else if cfg!(target_os = "windows") {
-        r"C:\Program Files\Google\Chrome\Application\chrome.exe".to_string()
-    } else {
-        // Try common Linux paths
-        for path in &["/usr/bin/chromium", "/usr/bin/chromium-browser", "/usr/bin/google-chrome"] {
-            if std::path::Path::new(path).exists() {
-                return path.to_string();
+fn screenshot_result(data: Vec<u8>, name: &str, format: &CaptureScreenshotFormat) -> ConvertResult {
+    let ext = format.as_ref();
+    ConvertResult {
+        data,
+        filename: format!("{}.{}", name, ext),
+        content_type: format!("image/{}", ext),
+        warnings: Vec::new(),
+    }
+}
+
+fn file_url(path: &Path) -> Result<String> {
+    Ok(format!("file://{}", path.canonicalize()?.display()))
+}
+
+/// Record subresources that failed to load (timed out, refused, blocked),
+/// as they leave holes in the output. The main document failing fails the
+/// navigation instead.
+async fn watch_failed_loads(page: &Page, failed: Arc<std::sync::Mutex<Vec<String>>>) -> Result<()> {
+    let listen_failed =
+        |e| AppError::ConversionFailed(format!("Failed to watch resource loads: {}", e));
+    let mut requests = page
+        .event_listener::<EventRequestWillBeSent>()
+        .await
+        .map_err(listen_failed)?;
+    let mut failures = page
+        .event_listener::<EventLoadingFailed>()
+        .await
+        .map_err(listen_failed)?;
+
+    // Ends with the page's event streams when the tab is closed
+    tokio::spawn(async move {
+        let mut urls = HashMap::new();
+        loop {
+            tokio::select! {
+                Some(request) = requests.next() => {
+                    urls.insert(request.request_id.clone(), request.request.url.clone());
+                }
+                Some(failure) = failures.next() => {
+                    let mut failed = failed.lock().unwrap();
+                    if failure.canceled == Some(true) || failed.len() >= MAX_FAILED_LOADS {
+                        continue;
+                    }
+                    let url: String = match urls.get(&failure.request_id) {
+                        Some(url) => url.chars().take(200).collect(),
+                        None => "a resource".to_string(),
+                    };
+                    failed.push(format!("Failed to load {}: {}", url, failure.error_text));
+                }
+                else => break,
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Navigations (by loader) of the page that went without network
+/// connections for 500ms, as Chromium reports in its `networkIdle`
+/// lifecycle event
+async fn watch_network_idle(page: &Page) -> Result<watch::Receiver<Vec<LoaderId>>> {
+    let mut events = page
+        .event_listener::<EventLifecycleEvent>()
+        .await
+        .map_err(|e| AppError::ConversionFailed(format!("Failed to watch page events: {}", e)))?;
+    let (idle, receiver) = watch::channel(Vec::new());
+
+    // Ends with the page's event stream when the tab is closed
+    tokio::spawn(async move {
+        while let Some(event) = events.next().await {
+            if event.name == "networkIdle" {
+                idle.send_modify(|loaders| loaders.push(event.loader_id.clone()));
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+/// Hold every request of a local document at `url`: its `/assets/...`
+/// requests are answered from the shared asset store and, for a document
+/// held in memory (`content`), the request for the document itself. Remote
+/// resources, frames and redirects have to pass the URL policy, as they
+/// would for a remote page, and get the caller's HTTP credentials as Basic
+/// authorization on `httpAuthOrigin` only. Local files are only readable
+/// next to the document and in the math and chart library directories.
+async fn guard_local_requests(
+    page: &Page,
+    url: &str,
+    content: Option<&[u8]>,
+    options: &ConvertOptions,
+) -> Result<()> {
+    let intercept_failed =
+        |e| AppError::ConversionFailed(format!("Failed to check page requests: {}", e));
+    let mut paused = page
+        .event_listener::<EventRequestPaused>()
+        .await
+        .map_err(intercept_failed)?;
+    let pattern = RequestPattern {
+        url_pattern: Some("*".to_string()),
+        ..Default::default()
+    };
+    page.execute(fetch::EnableParams {
+        patterns: Some(vec![pattern]),
+        handle_auth_requests: None,
+    })
+    .await
+    .map_err(intercept_failed)?;
+
+    let document = content.map(|html| {
+        let content_type = if url.ends_with(".xhtml") {
+            "application/xhtml+xml"
+        } else {
+            "text/html"
+        };
+        (url.to_string(), content_type, BASE64.encode(html))
+    });
+    // Local files the page may load: the libraries and, for a document on
+    // disk, the files written next to it
+    let mut readable = vec![
+        math::katex_dir().to_path_buf(),
+        chart::library_dir().to_path_buf(),
+    ];
+    if content.is_none() {
+        let dir = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.to_file_path().ok())
+            .and_then(|path| path.parent().map(Path::to_path_buf));
+        readable.extend(dir);
+    }
+    let auth_origin = options.http_auth_origin.clone();
+    let mut credentials = origin_headers(options);
+    credentials.retain(|(name, _)| name.eq_ignore_ascii_case("authorization"));
+    let shared_assets = assets::is_shared();
+    let asset_prefix = format!("file://{}", assets::URL_PREFIX);
+
+    // Ends with the page's event stream when the tab is closed
+    let page = page.clone();
+    tokio::spawn(async move {
+        let mut checked = HashMap::new();
+        while let Some(event) = paused.next().await {
+            let request_id = event.request_id.clone();
+            let request_url = &event.request.url;
+            let answered = if let Some((_, content_type, body)) =
+                document.as_ref().filter(|(url, _, _)| url == request_url)
+            {
+                let mut params = FulfillRequestParams::new(request_id, 200);
+                params.response_headers =
+                    Some(vec![HeaderEntry::new("Content-Type", *content_type)]);
+                params.body = Some(Binary::from(body.clone()));
+                page.execute(params).await.map(|_| ())
+            } else if shared_assets && request_url.starts_with(&asset_prefix) {
+                let params = match assets::resolve(request_url) {
+                    Some(Ok((data, content_type))) => {
+                        let mut params = FulfillRequestParams::new(request_id, 200);
+                        params.response_headers =
+                            Some(vec![HeaderEntry::new("Content-Type", content_type)]);
+                        params.body = Some(Binary::from(BASE64.encode(&data)));
+                        params
+                    }
+                    resolved => {
+                        if let Some(Err(e)) = resolved {
+                            warn!("Document refers to a missing shared asset: {}", e);
+                        }
+                        FulfillRequestParams::new(request_id, 404)
+                    }
+                };
+                page.execute(params).await.map(|_| ())
+            } else {
+                let parsed = reqwest::Url::parse(request_url).ok();
+                let mut authorized = false;
+                let verdict = match &parsed {
+                    Some(url) if matches!(url.scheme(), "http" | "https") => {
+                        let key = url.origin().ascii_serialization();
+                        authorized = auth_origin.as_ref() == Some(&key);
+                        match checked.get(&key) {
+                            Some(verdict) => Clone::clone(verdict),
+                            None => {
+                                let verdict =
+                                    egress::check_url(url).await.map_err(|e| e.to_string());
+                                checked.insert(key, verdict.clone());
+                                verdict
+                            }
+                        }
+                    }
+                    Some(url) if url.scheme() == "file" => match url.to_file_path() {
+                        Ok(path) if readable.iter().any(|dir| path.starts_with(dir)) => Ok(()),
+                        _ => Err(
+                            "local files outside the document's directory are not readable"
+                                .to_string(),
+                        ),
+                    },
+                    Some(url) if matches!(url.scheme(), "data" | "blob" | "about") => Ok(()),
+                    _ => Err("only http(s) and the document's own files may be loaded".to_string()),
+                };
+                match verdict {
+                    Ok(()) => {
+                        let mut params = fetch::ContinueRequestParams::new(request_id);
+                        if authorized && !credentials.is_empty() {
+                            params.headers =
+                                Some(with_headers(event.request.headers.inner(), &credentials));
+                        }
+                        page.execute(params).await.map(|_| ())
+                    }
+                    Err(e) => {
+                        warn!("Blocked {}: {}", request_url, e);
+                        let params =
+                            fetch::FailRequestParams::new(request_id, ErrorReason::BlockedByClient);
+                        page.execute(params).await.map(|_| ())
+                    }
+                }
+            };
+            if let Err(e) = answered {
+                warn!("Failed to answer {}: {}", event.request.url, e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Headers sent to a remote page's own origin: the caller's extra headers
+/// and HTTP credentials, as Basic authorization
+pub fn origin_headers(options: &ConvertOptions) -> Vec<(String, String)> {
+    let mut headers = options.extra_headers.clone();
+    if let Some(ref username) = options.http_username {
+        // Credentials win over an Authorization header among the extra ones
+        headers.retain(|(name, _)| !name.eq_ignore_ascii_case("authorization"));
+        let password = options.http_password.as_deref().unwrap_or_default();
+        let credentials = BASE64.encode(format!("{}:{}", username, password));
+        headers.push((
+            "Authorization".to_string(),
+            format!("Basic {}", credentials),
+        ));
+    }
+    headers
+}
+
+/// Hold every request of a remote page until the URL policy allows it, so
+/// neither redirects nor subresources reach addresses the page URL itself
+/// could not. HTTP credentials, as Basic authorization, and the caller's
+/// extra headers go to the page's own origin only.
+/// With `document`, that is served as the page instead of loading it.
+async fn guard_requests(
+    page: &Page,
+    url: &reqwest::Url,
+    document: Option<&[u8]>,
+    options: &ConvertOptions,
+) -> Result<()> {
+    let intercept_failed =
+        |e| AppError::ConversionFailed(format!("Failed to check page requests: {}", e));
+    let mut paused = page
+        .event_listener::<EventRequestPaused>()
+        .await
+        .map_err(intercept_failed)?;
+    let pattern = RequestPattern {
+        url_pattern: Some("*".to_string()),
+        ..Default::default()
+    };
+    page.execute(fetch::EnableParams {
+        patterns: Some(vec![pattern]),
+        handle_auth_requests: None,
+    })
+    .await
+    .map_err(intercept_failed)?;
+
+    let origin = url.origin();
+    let headers = origin_headers(options);
+    let mut document = document.map(|html| (url.to_string(), BASE64.encode(html)));
+
+    // Ends with the page's event stream when the tab is closed
+    let page = page.clone();
+    tokio::spawn(async move {
+        let mut checked = HashMap::new();
+        while let Some(event) = paused.next().await {
+            let request_id = event.request_id.clone();
+            let request_url = reqwest::Url::parse(&event.request.url);
+            let verdict = match &request_url {
+                Ok(request_url) if matches!(request_url.scheme(), "http" | "https") => {
+                    let key = request_url.origin().ascii_serialization();
+                    match checked.get(&key) {
+                        Some(verdict) => Clone::clone(verdict),
+                        None => {
+                            let verdict = egress::check_url(request_url)
+                                .await
+                                .map_err(|e| e.to_string());
+                            checked.insert(key, verdict.clone());
+                            verdict
+                        }
+                    }
+                }
+                Ok(request_url) if request_url.scheme() == "file" => {
+                    Err("local files cannot be loaded by remote pages".to_string())
+                }
+                _ => Ok(()),
+            };
+
+            // The page itself, already fetched and processed by the server
+            let served = match &document {
+                Some((page_url, _)) if *page_url == event.request.url => document.take(),
+                _ => None,
+            };
+            let answered = match verdict {
+                Ok(()) if served.is_some() => {
+                    let (_, body) = served.expect("served document");
+                    let mut params = FulfillRequestParams::new(request_id, 200);
+                    params.response_headers =
+                        Some(vec![HeaderEntry::new("Content-Type", "text/html")]);
+                    params.body = Some(Binary::from(body));
+                    page.execute(params).await.map(|_| ())
+                }
+                Ok(()) => {
+                    let mut params = fetch::ContinueRequestParams::new(request_id);
+                    let same_origin = request_url.is_ok_and(|u| u.origin() == origin);
+                    if same_origin && !headers.is_empty() {
+                        params.headers =
+                            Some(with_headers(event.request.headers.inner(), &headers));
+                    }
+                    page.execute(params).await.map(|_| ())
+                }
+                Err(e) => {
+                    warn!("Blocked {}: {}", event.request.url, e);
+                    let params =
+                        fetch::FailRequestParams::new(request_id, ErrorReason::BlockedByClient);
+                    page.execute(params).await.map(|_| ())
+                }
+            };
+            if let Err(e) = answered {
+                warn!("Failed to answer {}: {}", event.request.url, e);
             }
         }
-        "chromium".to_string()
+    });
+    Ok(())
+}
+
+/// A request's headers with each of `set` replacing any of the same name
+fn with_headers(headers: &serde_json::Value, set: &[(String, String)]) -> Vec<HeaderEntry> {
+    let mut entries: Vec<HeaderEntry> = headers
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(key, _)| !set.iter().any(|(name, _)| key.eq_ignore_ascii_case(name)))
+        .filter_map(|(key, value)| Some(HeaderEntry::new(key.clone(), value.as_str()?)))
+        .collect();
+    entries.extend(
+        set.iter()
+            .map(|(name, value)| HeaderEntry::new(name, value)),
+    );
+    entries
+}
+
+/// Store the caller's cookies for the remote page's host before it loads,
+/// in the tab's own browser context, so scripts see them too
+async fn set_cookies(page: &Page, url: &reqwest::Url, cookies: &[(String, String)]) -> Result<()> {
+    if cookies.is_empty() {
+        return Ok(());
     }
+    let cookies = cookies
+        .iter()
+        .map(|(name, value)| {
+            let mut cookie = CookieParam::new(name, value);
+            cookie.url = Some(url.to_string());
+            cookie.path = Some("/".to_string());
+            cookie
+        })
+        .collect();
+    page.execute(SetCookiesParams::new(cookies))
+        .await
+        .map_err(|e| AppError::ConversionFailed(format!("Failed to set cookies: {}", e)))?;
+    Ok(())
 }
 
-/// Parse dimension string (e.g., "8.5in", "210mm") to inches
-fn parse_to_inches(s: &str) -> Option<f64> {
-    let s = s.trim();
-    if let Some(val) = s.strip_suffix("in") {
-        val.trim().parse::<f64>().ok()
-    } else if let Some(val) = s.strip_suffix("mm") {
-        val.trim().parse::<f64>().ok().map(|v| v / 25.4)
-    } else if let Some(val) = s.strip_suffix("cm") {
-        val.trim().parse::<f64>().ok().map(|v| v / 2.54)
+/// Deny permission prompts that would otherwise block rendering; geolocation
+/// is only granted when a location is being emulated
+async fn apply_permissions(browser: &Browser, context: &BrowserContextId, grant_geolocation: bool) {
+    let geolocation = if grant_geolocation {
+        PermissionSetting::Granted
     } else {
-        s.parse::<f64>().ok()
+        PermissionSetting::Denied
+    };
+
+    let settings = DENIED_PERMISSIONS
+        .iter()
+        .map(|name| (*name, PermissionSetting::Denied))
+        .chain(std::iter::once(("geolocation", geolocation)));
+
+    for (name, setting) in settings {
+        let mut params = SetPermissionParams::new(PermissionDescriptor::new(name), setting);
+        params.browser_context_id = Some(context.clone());
+        if let Err(e) = browser.execute(params).await {
+            warn!("Failed to set {} permission: {}", name, e);
+        }
+    }
+}
+
+/// Give the page's `<h1>`–`<h3>` headings ids and link to them from a
+/// table of contents at the top of the body: shown for `toc`, unless the
+/// document (a Markdown shell) places one itself, and else hidden, as
+/// Chromium only writes named destinations for link targets. Returns the
+/// headings for `bookmarks`; nothing is changed when neither is asked for.
+async fn list_headings(page: &Page, options: &ConvertOptions) -> Result<Vec<pdf::Bookmark>> {
+    if !options.toc && !options.bookmarks {
+        return Ok(Vec::new());
+    }
+    let script = format!(
+        r#"(() => {{
+    const headings = [];
+    for (const heading of document.querySelectorAll('body h1, body h2, body h3')) {{
+        const title = heading.textContent.replace(/\s+/g, ' ').trim();
+        if (!title || heading.closest('nav.toc')) continue;
+        if (!heading.id) {{
+            const slug = title.toLowerCase().replace(/[^\p{{L}}\p{{N}}]+/gu, '-')
+                .replace(/^-|-$/g, '') || 'section';
+            let id = slug;
+            for (let n = 2; document.getElementById(id); n++) id = slug + '-' + n;
+            heading.id = id;
+        }}
+        headings.push({{ level: Number(heading.tagName[1]), title, destination: heading.id }});
+    }}
+    const show = {show} && !document.querySelector('nav.toc');
+    const nav = document.createElement('nav');
+    nav.className = show ? 'toc pdfmill-toc' : 'pdfmill-toc-links';
+    if (!show) {{
+        nav.style.cssText = 'position: absolute; width: 1px; height: 1px; overflow: hidden;'
+            + ' clip-path: inset(50%)';
+    }}
+    const list = document.createElement('ul');
+    for (const heading of headings) {{
+        const item = document.createElement('li');
+        item.className = 'toc-h' + heading.level;
+        const link = document.createElement('a');
+        link.href = '#' + encodeURIComponent(heading.destination);
+        link.textContent = heading.title;
+        item.appendChild(link);
+        list.appendChild(item);
+    }}
+    nav.appendChild(list);
+    if (document.body && headings.length) document.body.prepend(nav);
+    return headings;
+}})()"#,
+        show = options.toc
+    );
+
+    let headings = page
+        .evaluate(script)
+        .await
+        .map_err(|e| AppError::ConversionFailed(format!("Failed to list headings: {}", e)))?
+        .into_value::<Vec<pdf::Bookmark>>()
+        .map_err(|e| AppError::ConversionFailed(format!("Failed to list headings: {}", e)))?;
+    if options.toc {
+        inject_style(page, TOC_CSS).await?;
+    }
+    Ok(headings)
+}
+
+/// Outline the printed PDF with the document's headings
+fn add_bookmarks(
+    data: Vec<u8>,
+    headings: &[pdf::Bookmark],
+    mut warnings: Vec<String>,
+) -> Result<(Vec<u8>, Vec<String>)> {
+    if headings.is_empty() {
+        warnings.push("bookmarks: the document has no h1-h3 headings".to_string());
+        return Ok((data, warnings));
+    }
+    let (data, missing) = pdf::add_outline(&data, headings)?;
+    if missing > 0 {
+        warnings.push(format!(
+            "bookmarks: {} of {} headings are not in the printed pages and have no bookmark",
+            missing,
+            headings.len()
+        ));
+    }
+    Ok((data, warnings))
+}
+
+/// Append a stylesheet to the loaded document
+async fn inject_style(page: &Page, css: &str) -> Result<()> {
+    let script = format!(
+        r#"(() => {{
+    const style = document.createElement('style');
+    style.textContent = {css};
+    (document.head || document.documentElement).appendChild(style);
+}})()"#,
+        css = serde_json::to_string(css).unwrap_or_default()
+    );
+
+    page.evaluate(script)
+        .await
+        .map_err(|e| AppError::ConversionFailed(format!("Failed to inject CSS: {}", e)))?;
+    Ok(())
+}
+
+/// Run the caller's `customJs` as a classic script, waiting for a promise
+/// it evaluates to; its exceptions are the caller's error
+async fn run_custom_script(page: &Page, script: &str) -> Result<()> {
+    let mut params = EvaluateParams::new(script);
+    params.await_promise = Some(true);
+    // The result may be a DOM node, which cannot be serialized
+    params.return_by_value = Some(false);
+    match tokio::time::timeout(SCRIPT_TIMEOUT, page.evaluate_expression(params)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(CdpError::JavascriptException(details))) => {
+            let message = details
+                .exception
+                .as_ref()
+                .and_then(|exception| exception.description.clone())
+                .unwrap_or_else(|| details.text.clone());
+            Err(AppError::InvalidRequest(format!("customJs: {}", message)))
+        }
+        Ok(Err(e)) => Err(AppError::ConversionFailed(format!(
+            "Failed to run customJs: {}",
+            e
+        ))),
+        Err(_) => Err(AppError::InvalidRequest(format!(
+            "customJs: did not finish within {}s",
+            SCRIPT_TIMEOUT.as_secs()
+        ))),
     }
 }
 
+/// Hide everything except the element matching `selector` (and its ancestors),
+/// keeping the surrounding CSS context intact
+async fn isolate_element(page: &Page, selector: &str) -> Result<()> {
+    let script = format!(
+        r#"(() => {{
+    const target = document.querySelector({selector});
+    if (!target) return false;
+    let node = target;
+    while (node.parentElement) {{
+        for (const sibling of node.parentElement.children) {{
+            if (sibling !== node && !['SCRIPT', 'STYLE', 'LINK'].includes(sibling.tagName)) {{
+                sibling.style.setProperty('display', 'none', 'important');
+            }}
+        }}
+        node = node.parentElement;
+    }}
+    return true;
+}})()"#,
+        selector = serde_json::to_string(selector).unwrap_or_default()
+    );
+
+    let found = page
+        .evaluate(script)
+        .await
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid selector '{}': {}", selector, e)))?
+        .into_value::<bool>()
+        .unwrap_or(false);
+
+    if !found {
+        return Err(AppError::InvalidRequest(format!(
+            "Selector '{}' did not match any element",
+            selector
+        )));
+    }
+    Ok(())
+}
+
+/// Wait until an element matching `selector` exists, for pages that render
+/// after they load
+async fn wait_for_element(page: &Page, selector: &str) -> Result<()> {
+    let script = format!(
+        r#"new Promise((resolve) => {{
+    const deadline = Date.now() + {timeout};
+    (function poll() {{
+        if (document.querySelector({selector})) return resolve(true);
+        if (Date.now() > deadline) return resolve(false);
+        setTimeout(poll, 100);
+    }})();
+}})"#,
+        selector = serde_json::to_string(selector).unwrap_or_default(),
+        timeout = SELECTOR_TIMEOUT.as_millis()
+    );
+
+    let found = page
+        .evaluate(script)
+        .await
+        .map_err(|e| {
+            AppError::InvalidRequest(format!("Invalid waitForSelector '{}': {}", selector, e))
+        })?
+        .into_value::<bool>()
+        .unwrap_or(false);
+
+    if !found {
+        return Err(AppError::ConversionFailed(format!(
+            "waitForSelector: '{}' did not appear within {}s",
+            selector,
+            SELECTOR_TIMEOUT.as_secs()
+        )));
+    }
+    Ok(())
+}
+
+/// Well-known Chrome/Chromium install locations
+#[cfg(target_os = "macos")]
+const CHROME_CANDIDATES: &[&str] = &[
+    "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+    "/Applications/Chromium.app/Contents/MacOS/Chromium",
+];
+#[cfg(target_os = "windows")]
+const CHROME_CANDIDATES: &[&str] = &[r"C:\Program Files\Google\Chrome\Application\chrome.exe"];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const CHROME_CANDIDATES: &[&str] = &[
+    "/usr/bin/chromium",
+    "/usr/bin/chromium-browser",
+    "/usr/bin/google-chrome",
+    // Alpine and Raspberry Pi OS keep the real binary outside /usr/bin
+    "/usr/lib/chromium/chromium",
+    "/usr/lib/chromium/chrome",
+    "/usr/lib/chromium-browser/chromium-browser",
+    "/snap/bin/chromium",
+];
+
+/// Chrome executable: `CHROME_PATH`, a well-known location or `$PATH`
+fn locate_chrome() -> Located {
+    locate(
+        "CHROME_PATH",
+        CHROME_CANDIDATES,
+        &[
+            "chromium",
+            "chromium-browser",
+            "google-chrome",
+            "google-chrome-stable",
+        ],
+    )
+}
+
+fn get_chrome_path() -> String {
+    locate_chrome().path
+}
+
+/// Print styles of converted Markdown and Org documents
+const DEFAULT_STYLES: &str = "        body { font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif; margin: 40px; line-height: 1.6; }
+        pre { background: #f4f4f4; padding: 16px; overflow-x: auto; }
+        code { background: #f4f4f4; padding: 2px 6px; }
+        table { border-collapse: collapse; margin: 12px 0; }
+        th, td { border: 1px solid #ccc; padding: 4px 10px; text-align: left; }
+        th { background: #f4f4f4; }
+        .todo { color: #c0392b; font-weight: bold; }
+        .done { color: #27ae60; font-weight: bold; }
+        .priority { color: #8e44ad; }
+        .tag { float: right; font-size: 0.6em; font-weight: normal; background: #eee; padding: 2px 6px; margin-left: 4px; }
+        .planning { color: #777; font-size: 0.9em; }
+";
+
+/// The default print styles, code highlighting included
+fn default_styles() -> String {
+    format!("{}{}", DEFAULT_STYLES, highlight::THEME_CSS)
+}
+
+/// Wrap an HTML fragment in a standalone document with the default print
+/// styles; the title is also the PDF's
+fn wrap_html_document(title: Option<&str>, body: &str) -> String {
+    let title = title
+        .map(|title| format!("    <title>{}</title>\n", html_escape(title)))
+        .unwrap_or_default();
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+{}    <style>
+{}    </style>
+</head>
+<body>
+{}
+</body>
+</html>"#,
+        title,
+        default_styles(),
+        body
+    )
+}
+
 /// Simple markdown to HTML converter
 /// In production, use pulldown-cmark or similar
 fn markdown_to_html_simple(md: &str) -> String {
     let mut html = String::new();
-    let mut in_code_block = false;
+    // Language and lines of the fenced code block being read
+    let mut code_block: Option<(&str, Vec<&str>)> = None;
+    // Lines of the display formula being read
+    let mut math_block: Option<Vec<&str>> = None;
+    // Heading ids given so far, kept unique for links to them
+    let mut ids = HashSet::new();
 
     for line in md.lines() {
-        if line.starts_with("```") {
-            if in_code_block {
-                html.push_str("</code></pre>\n");
-                in_code_block = false;
-            } else {
-                html.push_str("<pre><code>");
-                in_code_block = true;
+        if let Some(info) = line.strip_prefix("```") {
+            match code_block.take() {
+                Some((language, lines)) => html.push_str(&code_block_html(language, &lines)),
+                None => code_block = Some((info.split_whitespace().next().unwrap_or(""), vec![])),
             }
             continue;
         }
 
-        if in_code_block {
-            html.push_str(&html_escape(line));
-            html.push('\n');
+        if let Some((_, lines)) = &mut code_block {
+            lines.push(line);
             continue;
         }
 
-        if line.starts_with("# ") {
-            html.push_str(&format!("<h1>{}</h1>\n", &line[2..]));
-        } else if line.starts_with("## ") {
-            html.push_str(&format!("<h2>{}</h2>\n", &line[3..]));
-        } else if line.starts_with("### ") {
-            html.push_str(&format!("<h3>{}</h3>\n", &line[4..]));
-        } else if line.starts_with("- ") || line.starts_with("* ") {
-            html.push_str(&format!("<li>{}</li>\n", &line[2..]));
+        // A display formula whose `$$` delimiters are on lines of their own
+        // or start its first line and end its last
+        if let Some(lines) = &mut math_block {
+            match line.trim_end().strip_suffix(math::DISPLAY_DELIMITER) {
+                Some(last) => {
+                    lines.push(last);
+                    html.push_str(&math::display_math(lines));
+                    math_block = None;
+                }
+                None => lines.push(line),
+            }
+            continue;
+        }
+        if let Some(first) = line.trim().strip_prefix(math::DISPLAY_DELIMITER) {
+            if !first.contains(math::DISPLAY_DELIMITER) {
+                math_block = Some(vec![first]);
+                continue;
+            }
+        }
+
+        if let Some(text) = line.strip_prefix("# ") {
+            html.push_str(&heading_html(1, text, &mut ids));
+        } else if let Some(text) = line.strip_prefix("## ") {
+            html.push_str(&heading_html(2, text, &mut ids));
+        } else if let Some(text) = line.strip_prefix("### ") {
+            html.push_str(&heading_html(3, text, &mut ids));
+        } else if let Some(text) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+            html.push_str(&format!("<li>{}</li>\n", math::inline_math(text)));
         } else if line.is_empty() {
             html.push_str("<br>\n");
         } else {
-            html.push_str(&format!("<p>{}</p>\n", line));
+            html.push_str(&format!("<p>{}</p>\n", math::inline_math(line)));
         }
     }
+    // A fence left open runs to the end of the document
+    if let Some((language, lines)) = code_block {
+        html.push_str(&code_block_html(language, &lines));
+    }
+    if let Some(lines) = math_block {
+        html.push_str(&math::display_math(&lines));
+    }
+    if math::has_math(&html) {
+        html.push_str(&math::typeset_html());
+    }
 
     html
 }
 
-fn html_escape(s: &str) -> String {
+/// A heading with an id made from its text, `-2`, `-3`… added to repeats
+fn heading_html(level: u8, text: &str, ids: &mut HashSet<String>) -> String {
+    let mut slug = String::new();
+    for c in text.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = match slug.trim_end_matches('-') {
+        "" => "section".to_string(),
+        slug => slug.to_string(),
+    };
+    let mut id = slug.clone();
+    let mut n = 1;
+    while !ids.insert(id.clone()) {
+        n += 1;
+        id = format!("{}-{}", slug, n);
+    }
+    format!(
+        "<h{0} id=\"{1}\">{2}</h{0}>\n",
+        level,
+        html_escape(&id),
+        math::inline_math(text)
+    )
+}
+
+/// A fenced code block, highlighted when its language is known
+fn code_block_html(language: &str, lines: &[&str]) -> String {
+    let mut code = lines.join("\n");
+    code.push('\n');
+    match highlight::highlight(&code, language) {
+        Some(highlighted) => format!(
+            "<pre><code class=\"language-{}\">{}</code></pre>\n",
+            html_escape(language),
+            highlighted
+        ),
+        None => format!("<pre><code>{}</code></pre>\n", html_escape(&code)),
+    }
+}
+
+pub(super) fn html_escape(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
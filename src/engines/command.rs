@@ -0,0 +1,344 @@
+//! Engines defined by the operator instead of in code: a command line run
+//! for each conversion, so site-specific converters can be added without
+//! changing pdfmill. They are read from the TOML file in
+//! `PDFMILL_ENGINES_FILE`:
+//!
+//! ```toml
+//! [[engine]]
+//! name = "cad"
+//! extensions = ["dwg", "dxf"]
+//! command = ["dwg2pdf", "--quiet", "{input}", "{output}"]
+//! probe = ["dwg2pdf", "--version"]   # availability and version check
+//! timeout = 300                      # seconds
+//! ```
+//!
+//! `{input}` is the uploaded file, `{output}` the PDF to write and
+//! `{outdir}` the command's working directory. Without `{output}` the PDF
+//! is read from stdout. Conversion options are passed as JSON in the
+//! `PDFMILL_OPTIONS` environment variable.
+
+use super::locate::{is_executable, locate_program, Located};
+use super::{
+    parse_version, print_timezone, tool_failed, ConvertEngine, ConvertOptions, ConvertResult,
+    EngineType,
+};
+use crate::error::{AppError, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::info;
+
+/// Time a command may take when the definition sets no timeout
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnginesFile {
+    #[serde(default)]
+    engine: Vec<Definition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definition {
+    name: String,
+    extensions: Vec<String>,
+    command: Vec<String>,
+    #[serde(default)]
+    probe: Option<Vec<String>>,
+    /// Seconds
+    #[serde(default)]
+    timeout: Option<u64>,
+}
+
+pub struct CommandEngine {
+    name: &'static str,
+    extensions: Vec<&'static str>,
+    command: Vec<String>,
+    probe: Option<Vec<String>>,
+    timeout: Duration,
+}
+
+impl CommandEngine {
+    /// Engines defined in `PDFMILL_ENGINES_FILE`; none when unset
+    pub fn from_env() -> std::result::Result<Vec<Self>, String> {
+        let Some(path) = std::env::var_os("PDFMILL_ENGINES_FILE") else {
+            return Ok(Vec::new());
+        };
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("PDFMILL_ENGINES_FILE: cannot read {:?}: {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("PDFMILL_ENGINES_FILE: {}", e))
+    }
+
+    fn parse(text: &str) -> std::result::Result<Vec<Self>, String> {
+        let file: EnginesFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        file.engine.into_iter().map(Self::from_definition).collect()
+    }
+
+    fn from_definition(definition: Definition) -> std::result::Result<Self, String> {
+        let name = definition.name.trim();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "engine name '{}' must be lowercase letters, digits, '-' or '_'",
+                definition.name
+            ));
+        }
+        let extensions: Vec<String> = definition
+            .extensions
+            .iter()
+            .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+            .filter(|ext| !ext.is_empty())
+            .collect();
+        if extensions.is_empty() {
+            return Err(format!("engine '{}': no extensions", name));
+        }
+        if definition
+            .command
+            .first()
+            .is_none_or(|program| program.trim().is_empty())
+        {
+            return Err(format!("engine '{}': empty command", name));
+        }
+        if !definition.command.iter().any(|arg| arg.contains("{input}")) {
+            return Err(format!(
+                "engine '{}': command has no {{input}} placeholder",
+                name
+            ));
+        }
+        if definition
+            .probe
+            .as_ref()
+            .is_some_and(|probe| probe.is_empty())
+        {
+            return Err(format!("engine '{}': empty probe", name));
+        }
+        if definition.timeout == Some(0) {
+            return Err(format!(
+                "engine '{}': timeout must be at least 1 second",
+                name
+            ));
+        }
+
+        // Names and extensions are `'static` like those of the built-in
+        // engines; definitions are loaded once at startup
+        Ok(Self {
+            name: Box::leak(name.to_string().into_boxed_str()),
+            extensions: extensions
+                .into_iter()
+                .map(|ext| &*Box::leak(ext.into_boxed_str()))
+                .collect(),
+            command: definition.command,
+            probe: definition.probe,
+            timeout: definition
+                .timeout
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_TIMEOUT),
+        })
+    }
+
+    fn locate(&self) -> Located {
+        locate_program(&self.command[0])
+    }
+
+    /// Run the probe command, returning its stdout when it succeeds
+    async fn run_probe(&self, probe: &[String]) -> Option<String> {
+        let output = Command::new(locate_program(&probe[0]).path)
+            .args(&probe[1..])
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output();
+        let output = tokio::time::timeout(self.timeout, output)
+            .await
+            .ok()?
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Replace the placeholders of a command line argument
+fn substitute(arg: &str, input: &Path, output: &Path, outdir: &Path) -> String {
+    arg.replace("{input}", &input.to_string_lossy())
+        .replace("{output}", &output.to_string_lossy())
+        .replace("{outdir}", &outdir.to_string_lossy())
+}
+
+#[async_trait]
+impl ConvertEngine for CommandEngine {
+    fn engine_type(&self) -> EngineType {
+        EngineType::Command(self.name)
+    }
+
+    fn supports_extension(&self, ext: &str) -> bool {
+        self.extensions.contains(&ext.to_lowercase().as_str())
+    }
+
+    fn supported_extensions(&self) -> Vec<&'static str> {
+        self.extensions.clone()
+    }
+
+    async fn is_available(&self) -> bool {
+        match &self.probe {
+            Some(probe) => self.run_probe(probe).await.is_some(),
+            None => is_executable(Path::new(&self.locate().path)),
+        }
+    }
+
+    async fn version(&self) -> Option<String> {
+        let probe = self.probe.as_ref()?;
+        parse_version(&self.run_probe(probe).await?)
+    }
+
+    fn searched_paths(&self) -> Vec<String> {
+        self.locate().tried
+    }
+
+    async fn convert(&self, input_path: &Path, options: &ConvertOptions) -> Result<ConvertResult> {
+        let temp_dir = crate::disk::tempdir()?;
+        let output_path = temp_dir.path().join("output.pdf");
+        let writes_output = self.command.iter().any(|arg| arg.contains("{output}"));
+        let args: Vec<String> = self.command[1..]
+            .iter()
+            .map(|arg| substitute(arg, input_path, &output_path, temp_dir.path()))
+            .collect();
+        let options_json = serde_json::to_string(options)
+            .map_err(|e| AppError::Internal(format!("Cannot serialize options: {}", e)))?;
+
+        info!(
+            "Converting {} to PDF using {}",
+            input_path.display(),
+            self.name
+        );
+        let program = self.locate();
+        let output = Command::new(&program.path)
+            .args(&args)
+            .current_dir(temp_dir.path())
+            .env("TZ", print_timezone(options))
+            .env("PDFMILL_OPTIONS", options_json)
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output();
+        let output = tokio::time::timeout(self.timeout, output)
+            .await
+            .map_err(|_| {
+                AppError::ConversionFailed(format!(
+                    "{} timed out after {}s",
+                    self.name,
+                    self.timeout.as_secs()
+                ))
+            })?
+            .map_err(|e| {
+                AppError::EngineNotAvailable(format!(
+                    "{} not found ({}): {}",
+                    self.name,
+                    program.describe_tried(),
+                    e
+                ))
+            })?;
+
+        if !output.status.success() {
+            return Err(tool_failed(self.name, &output));
+        }
+
+        let data = if writes_output {
+            tokio::fs::read(&output_path).await.map_err(|e| {
+                AppError::ConversionFailed(format!("{} wrote no output: {}", self.name, e))
+            })?
+        } else {
+            output.stdout
+        };
+        if !data.starts_with(b"%PDF") {
+            return Err(AppError::ConversionFailed(format!(
+                "{} did not produce a PDF",
+                self.name
+            )));
+        }
+
+        let original_name = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        Ok(ConvertResult {
+            data,
+            filename: format!("{}.pdf", original_name),
+            content_type: "application/pdf".to_string(),
+            warnings: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let engines = CommandEngine::parse(
+            r#"
+            [[engine]]
+            name = "cad"
+            extensions = [".DWG", "dxf"]
+            command = ["dwg2pdf", "{input}", "{output}"]
+            timeout = 60
+            "#,
+        )
+        .unwrap();
+        assert_eq!(engines.len(), 1);
+        assert_eq!(engines[0].engine_type().name(), "cad");
+        assert!(engines[0].supports_extension("DWG"));
+        assert_eq!(engines[0].supported_extensions(), vec!["dwg", "dxf"]);
+        assert_eq!(engines[0].timeout, Duration::from_secs(60));
+
+        let invalid = |text: &str| CommandEngine::parse(text).err().unwrap_or_default();
+        assert!(invalid(
+            "[[engine]]\nname = \"Cad Tool\"\nextensions = [\"dwg\"]\ncommand = [\"x\", \"{input}\"]"
+        )
+        .contains("must be lowercase"));
+        assert!(
+            invalid("[[engine]]\nname = \"cad\"\nextensions = [\"dwg\"]\ncommand = [\"x\"]")
+                .contains("no {input} placeholder")
+        );
+        assert!(invalid(
+            "[[engine]]\nname = \"cad\"\nextensions = [\"dwg\"]\ncommand = [\"x\", \"{input}\"]\nshell = true"
+        )
+        .contains("unknown field"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_convert_from_stdout() {
+        let engine = &CommandEngine::parse(
+            "[[engine]]\nname = \"cat\"\nextensions = [\"txt\"]\ncommand = [\"cat\", \"{input}\"]",
+        )
+        .unwrap()[0];
+        assert!(engine.is_available().await);
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("report.txt");
+        std::fs::write(&input, b"%PDF-1.4 not really").unwrap();
+        let result = engine
+            .convert(&input, &ConvertOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(result.filename, "report.pdf");
+        assert_eq!(result.data, b"%PDF-1.4 not really");
+
+        std::fs::write(&input, b"plain text").unwrap();
+        assert!(engine
+            .convert(&input, &ConvertOptions::default())
+            .await
+            .is_err());
+    }
+}
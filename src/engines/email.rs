@@ -0,0 +1,735 @@
+//! Email messages (`.eml`, RFC 5322 with MIME) as HTML pages: the HTML
+//! body, or else the plain text one, is printed under the message headers.
+//! Images the body embeds by `cid:` reference (RFC 2392) are inlined from
+//! their MIME parts as data URLs, so they print instead of showing as
+//! broken links. The other files attached to a message are listed by
+//! [`attachments`] for `convertAttachments`. Message HTML is sanitized
+//! first, as it comes from whoever sent the message.
+
+use super::chromium::html_escape;
+use super::{file_size, upload_filename};
+use base64::alphabet;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use chrono::DateTime;
+use chrono_tz::Tz;
+use encoding_rs::{Encoding, UTF_8};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// Deepest nesting of multipart entities followed
+const MAX_DEPTH: usize = 16;
+
+/// Headers shown above the body, in this order
+const SHOWN_HEADERS: &[&str] = &["From", "To", "Cc", "Date"];
+
+/// Extensions of attachments sent without a file name, so they can still
+/// be routed to an engine
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("message/rfc822", "eml"),
+    ("text/plain", "txt"),
+    ("text/html", "html"),
+    ("application/pdf", "pdf"),
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+];
+
+/// Tags and attributes older mailers lay out messages with, on top of
+/// ammonia's defaults
+const LAYOUT_TAGS: &[&str] = &["center", "font"];
+const LAYOUT_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("font", &["color", "face", "size"]),
+    (
+        "table",
+        &[
+            "align",
+            "bgcolor",
+            "border",
+            "cellpadding",
+            "cellspacing",
+            "width",
+        ],
+    ),
+    ("td", &["align", "bgcolor", "height", "valign", "width"]),
+    ("th", &["align", "bgcolor", "height", "valign", "width"]),
+    ("tr", &["align", "bgcolor", "valign"]),
+];
+
+/// Keeps a message's markup and inline styles but drops scripts, event
+/// handlers, stylesheets (which would apply to the whole page), frames,
+/// relative URLs and images loaded from anywhere but the message itself
+static SANITIZER: LazyLock<ammonia::Builder<'static>> = LazyLock::new(|| {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_tags(LAYOUT_TAGS)
+        .add_generic_attributes(["style"])
+        .add_url_schemes(["cid", "data"])
+        .url_relative(ammonia::UrlRelative::Deny)
+        .attribute_filter(|_, attribute, value| match attribute {
+            "src" if !(value.starts_with("data:") || value.starts_with("cid:")) => None,
+            _ => Some(Cow::Borrowed(value)),
+        });
+    for (tag, attributes) in LAYOUT_ATTRIBUTES {
+        builder.add_tag_attributes(tag, *attributes);
+    }
+    builder
+});
+
+/// Mailers pad and wrap base64 inconsistently
+const LENIENT_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
+/// A MIME entity: its headers and its body, transfer encoding not undone
+struct Part<'a> {
+    headers: Vec<(String, String)>,
+    body: &'a [u8],
+}
+
+impl<'a> Part<'a> {
+    fn parse(data: &'a [u8]) -> Self {
+        let (head, body) = split_head(data);
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in String::from_utf8_lossy(head).lines() {
+            // Folded headers continue on lines starting with white space
+            if line.starts_with([' ', '\t']) {
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+            } else if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        Self { headers, body }
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Lowercase media type and parameters, text/plain unless given
+    fn content_type(&self) -> (String, HashMap<String, String>) {
+        match self.header("Content-Type") {
+            Some(value) => parse_header_value(value),
+            None => ("text/plain".to_string(), HashMap::new()),
+        }
+    }
+
+    fn is_attachment(&self) -> bool {
+        self.header("Content-Disposition")
+            .is_some_and(|value| parse_header_value(value).0 == "attachment")
+    }
+
+    /// The file name from Content-Disposition, else the legacy `name` of
+    /// Content-Type, with RFC 2231 and RFC 2047 encodings undone
+    fn filename(&self) -> Option<String> {
+        let disposition = self.header("Content-Disposition").map(parse_header_value);
+        let content_type = self.content_type();
+        let named = |params: &HashMap<String, String>, name: &str| {
+            let extended = params
+                .get(&format!("{}*", name))
+                .and_then(|value| decode_extended(value));
+            extended.or_else(|| params.get(name).map(|value| decode_words(value)))
+        };
+        disposition
+            .and_then(|(_, params)| named(&params, "filename"))
+            .or_else(|| named(&content_type.1, "name"))
+            .filter(|name| !name.trim().is_empty())
+    }
+
+    /// The body with its transfer encoding undone
+    fn decoded(&self) -> Vec<u8> {
+        let encoding = self.header("Content-Transfer-Encoding").unwrap_or_default();
+        match encoding.trim().to_ascii_lowercase().as_str() {
+            "base64" => {
+                let compact: Vec<u8> = self
+                    .body
+                    .iter()
+                    .copied()
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .collect();
+                LENIENT_BASE64.decode(compact).unwrap_or_default()
+            }
+            "quoted-printable" => decode_quoted_printable(self.body, false),
+            _ => self.body.to_vec(),
+        }
+    }
+
+    /// The body as text in its declared charset
+    fn text(&self, params: &HashMap<String, String>) -> String {
+        let encoding = params
+            .get("charset")
+            .and_then(|charset| Encoding::for_label(charset.as_bytes()))
+            .unwrap_or(UTF_8);
+        encoding.decode(&self.decoded()).0.into_owned()
+    }
+}
+
+/// What is printed of a message
+#[derive(Default)]
+struct Bodies {
+    html: Option<String>,
+    text: Option<String>,
+    /// Media type and data of the parts with a Content-ID, by that id
+    inline: HashMap<String, (String, Vec<u8>)>,
+    /// Parts that are files rather than bodies, with their Content-ID
+    files: Vec<(Option<String>, Attachment)>,
+}
+
+impl Bodies {
+    /// Collect the first HTML and plain text bodies that are not attachments,
+    /// which also picks HTML out of multipart/alternative
+    fn collect(&mut self, part: &Part, depth: usize) {
+        let (media_type, params) = part.content_type();
+        if media_type.starts_with("multipart/") {
+            if let Some(boundary) = params.get("boundary").filter(|_| depth < MAX_DEPTH) {
+                for data in split_multipart(part.body, boundary) {
+                    self.collect(&Part::parse(data), depth + 1);
+                }
+            }
+            return;
+        }
+        let id = part.header("Content-ID").map(|id| {
+            let id = id.trim().trim_start_matches('<').trim_end_matches('>');
+            self.inline
+                .insert(id.to_string(), (media_type.clone(), part.decoded()));
+            id.to_string()
+        });
+        let filename = part.filename();
+        if !part.is_attachment() {
+            match media_type.as_str() {
+                "text/html" if self.html.is_none() => {
+                    self.html = Some(part.text(&params));
+                    return;
+                }
+                "text/plain" if self.text.is_none() => {
+                    self.text = Some(part.text(&params));
+                    return;
+                }
+                // Parts shown inline without a name, e.g. an alternative
+                // body not chosen, are no files
+                _ if filename.is_none() => return,
+                _ => {}
+            }
+        }
+        let number = self.files.len() + 1;
+        let filename = filename
+            .and_then(|name| upload_filename(&name).ok())
+            .unwrap_or_else(|| {
+                let ext = EXTENSIONS
+                    .iter()
+                    .find(|(known, _)| *known == media_type)
+                    .map_or("bin", |(_, ext)| ext);
+                format!("attachment-{}.{}", number, ext)
+            });
+        let attachment = Attachment {
+            filename,
+            content_type: media_type,
+            data: part.decoded(),
+        };
+        self.files.push((id, attachment));
+    }
+}
+
+/// A file attached to a message
+pub struct Attachment {
+    /// Plain file name, made up from the media type when none was sent
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// The files attached to a message, in order; images the HTML body shows
+/// by `cid:` reference are part of the body, not attachments
+pub fn attachments(data: &[u8]) -> Vec<Attachment> {
+    let mut bodies = Bodies::default();
+    bodies.collect(&Part::parse(data), 0);
+    let html = bodies.html.unwrap_or_default().to_ascii_lowercase();
+    bodies
+        .files
+        .into_iter()
+        .filter(|(id, _)| {
+            id.as_ref().is_none_or(|id| {
+                let id = id.to_ascii_lowercase();
+                !html.contains(&format!("cid:{}", id))
+                    && !html.contains(&format!("cid:{}", id.replace('@', "%40")))
+            })
+        })
+        .map(|(_, attachment)| attachment)
+        .collect()
+}
+
+/// Page put before an attachment appended to its message; one that could
+/// not be converted is named along with the reason
+pub fn attachment_separator(
+    index: usize,
+    count: usize,
+    attachment: &Attachment,
+    failure: Option<&str>,
+) -> String {
+    let failure = failure
+        .map(|reason| {
+            format!(
+                "<p style=\"margin-top: 24px; color: #a00\">Not converted: {}</p>\n",
+                html_escape(reason)
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"></head>\n\
+         <body style=\"font-family: sans-serif; padding-top: 30%; text-align: center\">\n\
+         <p style=\"color: #555\">Attachment {} of {}</p>\n\
+         <h1 style=\"font-size: 1.6em; word-break: break-all\">{}</h1>\n\
+         <p style=\"color: #555\">{}, {}</p>\n{}</body>\n</html>\n",
+        index,
+        count,
+        html_escape(&attachment.filename),
+        html_escape(&attachment.content_type),
+        file_size(attachment.data.len() as u64),
+        failure
+    )
+}
+
+/// Render a message as an HTML document, with dates in `timezone`
+pub fn eml_to_html(data: &[u8], timezone: Tz) -> Result<String, String> {
+    let message = Part::parse(data);
+    if message.header("From").is_none() && message.header("Content-Type").is_none() {
+        return Err("not an email message (no From or Content-Type header)".to_string());
+    }
+    let mut bodies = Bodies::default();
+    bodies.collect(&message, 0);
+
+    let header = |name: &str| message.header(name).map(decode_words);
+    let subject = header("Subject").filter(|s| !s.trim().is_empty());
+    let mut rows = String::new();
+    for name in SHOWN_HEADERS {
+        let Some(value) = header(name) else { continue };
+        let value = match *name {
+            "Date" => local_date(&value, timezone),
+            _ => value,
+        };
+        rows.push_str(&format!(
+            "<tr><th style=\"text-align: left; padding: 2px 16px 2px 0; color: #555; \
+             vertical-align: top\">{}</th><td>{}</td></tr>\n",
+            name,
+            html_escape(&value)
+        ));
+    }
+    let headers = format!(
+        "<div style=\"font-family: sans-serif; margin-bottom: 16px; padding-bottom: 8px; \
+         border-bottom: 1px solid #ccc\">\n<h1 style=\"font-size: 1.4em; margin: 0 0 8px\">{}\
+         </h1>\n<table style=\"border-collapse: collapse; font-size: 0.9em\">\n{}</table>\n\
+         </div>\n",
+        html_escape(subject.as_deref().unwrap_or("(no subject)")),
+        rows
+    );
+
+    // Boxed, so positioned content cannot cover the headers
+    let body = match (bodies.html, bodies.text) {
+        (Some(html), _) => format!(
+            "<div style=\"contain: paint\">{}</div>",
+            sanitize_body(&resolve_cids(&html, &bodies.inline))
+        ),
+        (None, text) => format!(
+            "<pre style=\"white-space: pre-wrap; font-family: inherit\">{}</pre>",
+            html_escape(text.as_deref().unwrap_or_default())
+        ),
+    };
+    let html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"></head>\n<body>\n{}{}\n</body>\n\
+         </html>\n",
+        headers, body
+    );
+    Ok(html)
+}
+
+/// Headers and body of an entity, split at the first empty line
+fn split_head(data: &[u8]) -> (&[u8], &[u8]) {
+    if data.starts_with(b"\r\n") || data.starts_with(b"\n") {
+        let skip = if data[0] == b'\r' { 2 } else { 1 };
+        return (&[], &data[skip..]);
+    }
+    let crlf = find(data, b"\r\n\r\n").map(|i| (i, i + 4));
+    let lf = find(data, b"\n\n").map(|i| (i, i + 2));
+    match (crlf, lf) {
+        (Some(a), Some(b)) => {
+            let (end, start) = a.min(b);
+            (&data[..end], &data[start..])
+        }
+        (Some((end, start)), None) | (None, Some((end, start))) => (&data[..end], &data[start..]),
+        (None, None) => (data, &[]),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// The entities of a multipart body, between its `--boundary` lines
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut pos = 0;
+    while pos < body.len() {
+        let end = body[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(body.len(), |i| pos + i + 1);
+        let line = body[pos..end].trim_ascii_end();
+        if let Some(rest) = line.strip_prefix(delimiter.as_bytes()) {
+            let closing = rest.starts_with(b"--");
+            let rest = if closing { &rest[2..] } else { rest };
+            if rest.trim_ascii().is_empty() {
+                if let Some(start) = start {
+                    // The line break before a delimiter belongs to it
+                    let part = &body[start..pos];
+                    let part = part.strip_suffix(b"\n").unwrap_or(part);
+                    parts.push(part.strip_suffix(b"\r").unwrap_or(part));
+                }
+                if closing {
+                    return parts;
+                }
+                start = Some(end);
+            }
+        }
+        pos = end;
+    }
+    // A message cut short still shows what arrived
+    if let Some(start) = start {
+        parts.push(&body[start..]);
+    }
+    parts
+}
+
+/// `type/subtype; name=value; name="quoted value"` as the lowercase value
+/// and its parameters by lowercase name
+fn parse_header_value(value: &str) -> (String, HashMap<String, String>) {
+    let mut items = value.split(';');
+    let main = items.next().unwrap_or_default().trim().to_ascii_lowercase();
+    let params = items
+        .filter_map(|item| {
+            let (name, value) = item.split_once('=')?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            Some((name.trim().to_ascii_lowercase(), value.to_string()))
+        })
+        .collect();
+    (main, params)
+}
+
+/// Undo quoted-printable encoding; in the Q encoding of headers `_` also
+/// stands for a space
+fn decode_quoted_printable(data: &[u8], underscore_space: bool) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            b'=' => {
+                let rest = &data[i + 1..];
+                if rest.starts_with(b"\r\n") {
+                    i += 3;
+                } else if rest.starts_with(b"\n") {
+                    i += 2;
+                } else if let Some(byte) = rest
+                    .get(..2)
+                    .and_then(|pair| Some(hex(pair[0])? << 4 | hex(pair[1])?))
+                {
+                    out.push(byte);
+                    i += 3;
+                } else {
+                    out.push(b'=');
+                    i += 1;
+                }
+            }
+            b'_' if underscore_space => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Decode the encoded words (RFC 2047) of a header, e.g.
+/// `=?UTF-8?B?w4RwZmVs?=`; white space between two of them is dropped
+fn decode_words(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = rest.split_at(start);
+        match decode_word(candidate) {
+            Some((decoded, len)) => {
+                if !(after_word && before.trim().is_empty()) {
+                    out.push_str(before);
+                }
+                out.push_str(&decoded);
+                rest = &candidate[len..];
+                after_word = true;
+            }
+            None => {
+                out.push_str(before);
+                out.push_str("=?");
+                rest = &candidate[2..];
+                after_word = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// One encoded word at the start of `s`, and its length
+fn decode_word(s: &str) -> Option<(String, usize)> {
+    let (charset, rest) = s.strip_prefix("=?")?.split_once('?')?;
+    let (encoding, rest) = rest.split_once('?')?;
+    let end = rest.find("?=")?;
+    let text = &rest[..end];
+    if text.contains(char::is_whitespace) {
+        return None;
+    }
+    let bytes = match encoding {
+        "B" | "b" => LENIENT_BASE64.decode(text).ok()?,
+        "Q" | "q" => decode_quoted_printable(text.as_bytes(), true),
+        _ => return None,
+    };
+    // A language may follow the charset (RFC 2231)
+    let charset = charset.split('*').next().unwrap_or_default();
+    let encoding = Encoding::for_label(charset.as_bytes())?;
+    let len = s.len() - rest.len() + end + 2;
+    Some((encoding.decode(&bytes).0.into_owned(), len))
+}
+
+/// Replace `cid:` URLs of the message's inline parts with data URLs
+fn resolve_cids(html: &str, inline: &HashMap<String, (String, Vec<u8>)>) -> String {
+    // ASCII lowercasing keeps every byte offset
+    let lower = html.to_ascii_lowercase();
+    let mut out = String::with_capacity(html.len());
+    let mut pos = 0;
+    while let Some(found) = lower[pos..].find("cid:") {
+        let start = pos + found;
+        let id_start = start + 4;
+        let id_end = html[id_start..]
+            .find(|c: char| matches!(c, '"' | '\'' | ')' | '<' | '>') || c.is_whitespace())
+            .map_or(html.len(), |i| id_start + i);
+        let id = &html[id_start..id_end];
+        out.push_str(&html[pos..start]);
+        let decoded = String::from_utf8_lossy(&percent_decode(id)).into_owned();
+        match inline.get(id).or_else(|| inline.get(&decoded)) {
+            Some((media_type, data)) => out.push_str(&format!(
+                "data:{};base64,{}",
+                media_type,
+                BASE64.encode(data)
+            )),
+            None => out.push_str(&html[start..id_end]),
+        }
+        pos = id_end;
+    }
+    out.push_str(&html[pos..]);
+    out
+}
+
+/// Undo percent-encoding, which `cid:` URLs use for characters of the
+/// Content-ID and RFC 2231 for parameter values
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| Some(hex(*bytes.get(i + 1)?)? << 4 | hex(*bytes.get(i + 2)?)?))
+            .flatten();
+        match escaped {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// An RFC 2231 extended parameter value, e.g. `UTF-8''Pr%C3%BCfung.pdf`
+fn decode_extended(value: &str) -> Option<String> {
+    let (charset, rest) = value.split_once('\'')?;
+    let (_language, text) = rest.split_once('\'')?;
+    let encoding = Encoding::for_label(charset.as_bytes()).unwrap_or(UTF_8);
+    Some(encoding.decode(&percent_decode(text)).0.into_owned())
+}
+
+/// The sanitized contents of an HTML message's `<body>` (or of the whole
+/// fragment), to be embedded in a page of ours
+pub fn sanitize_body(html: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let start = lower
+        .find("<body")
+        .and_then(|i| lower[i..].find('>').map(|j| i + j + 1))
+        .unwrap_or(0);
+    let end = lower.rfind("</body>").unwrap_or(html.len()).max(start);
+    SANITIZER.clean(&html[start..end]).to_string()
+}
+
+/// A message date, e.g. "Tue, 1 Jul 2003 10:52:37 +0200", in `timezone`;
+/// dates in another form are kept as they are
+fn local_date(date: &str, timezone: Tz) -> String {
+    // Ignore a trailing comment such as "(CEST)"
+    let date = date.split('(').next().unwrap_or_default().trim();
+    DateTime::parse_from_rfc2822(date)
+        .map(|date| {
+            date.with_timezone(&timezone)
+                .format("%Y-%m-%d %H:%M %Z")
+                .to_string()
+        })
+        .unwrap_or_else(|_| date.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "From: =?UTF-8?Q?J=C3=BCrgen?= <j@example.com>\r
+To: ada@example.com\r
+Subject: =?UTF-8?B?UXVhcnRhbA==?= =?UTF-8?B?c2JlcmljaHQ=?=\r
+Date: Tue, 1 Jul 2003 10:52:37 +0200\r
+MIME-Version: 1.0\r
+Content-Type: multipart/related; boundary=\"rel\"\r
+\r
+--rel\r
+Content-Type: multipart/alternative; boundary=alt\r
+\r
+--alt\r
+Content-Type: text/plain; charset=utf-8\r
+\r
+See the chart.\r
+--alt\r
+Content-Type: text/html; charset=iso-8859-1\r
+Content-Transfer-Encoding: quoted-printable\r
+\r
+<html><head><style>p { color: red }</style></head><body class=3D\"m\"><p onclick=3D\"x()\" =\r
+style=3D\"color: red\">Gr=FC=DFe</p><script>alert(1)</script><img src=3D\"cid:chart%40mail\">=\r
+<img src=3D\"cid:missing\"><img src=3D\"http://169.254.169.254/latest\"></body></html>\r
+--alt--\r
+--rel\r
+Content-Type: image/png\r
+Content-Transfer-Encoding: base64\r
+Content-ID: <chart@mail>\r
+\r
+iVBORw0K\r
+Gg==\r
+--rel--\r
+";
+
+    #[test]
+    fn test_eml_to_html() {
+        let html = eml_to_html(MESSAGE.as_bytes(), "Europe/Berlin".parse().unwrap()).unwrap();
+        // Headers go above the sanitized message
+        let headers = html.find("<h1").unwrap();
+        assert!(html[headers..]
+            .starts_with("<h1 style=\"font-size: 1.4em; margin: 0 0 8px\">Quartalsbericht</h1>"));
+        assert!(headers < html.find("<div style=\"contain: paint\">").unwrap());
+        assert!(html.contains("<td>Jürgen &lt;j@example.com&gt;</td>"));
+        assert!(html.contains("<td>2003-07-01 10:52 CEST</td>"));
+        assert!(html.contains("<p style=\"color: red\">Grüße</p>"));
+        assert!(html.contains("<img src=\"data:image/png;base64,iVBORw0KGg==\">"));
+        assert!(html.contains("<img src=\"cid:missing\">"));
+        for removed in [
+            "<style>",
+            "onclick",
+            "<script",
+            "alert",
+            "169.254.169.254",
+            "class=",
+        ] {
+            assert!(!html.contains(removed), "{}", removed);
+        }
+        assert!(!html.contains("See the chart"));
+
+        let text = "From: a@example.com\nSubject: Hi\n\n1 < 2\n";
+        let html = eml_to_html(text.as_bytes(), chrono_tz::UTC).unwrap();
+        assert!(html.contains(">1 &lt; 2\n</pre>"));
+        assert!(eml_to_html(b"just some text", chrono_tz::UTC).is_err());
+    }
+
+    #[test]
+    fn test_attachments() {
+        // The chart is shown by the body
+        assert!(attachments(MESSAGE.as_bytes()).is_empty());
+
+        let message = "From: a@example.com\n\
+            Content-Type: multipart/mixed; boundary=b\n\
+            \n\
+            --b\n\
+            Content-Type: text/plain\n\
+            \n\
+            See attached.\n\
+            --b\n\
+            Content-Type: application/pdf\n\
+            Content-Disposition: attachment; filename*=UTF-8''Pr%C3%BCfung.pdf\n\
+            Content-Transfer-Encoding: base64\n\
+            \n\
+            JVBERi0=\n\
+            --b\n\
+            Content-Type: image/png; name=\"=?utf-8?q?Logo_neu.png?=\"\n\
+            Content-ID: <logo>\n\
+            \n\
+            png\n\
+            --b\n\
+            Content-Type: message/rfc822\n\
+            Content-Disposition: attachment\n\
+            \n\
+            Subject: Fwd\n\
+            --b--\n";
+        let found = attachments(message.as_bytes());
+        let names: Vec<_> = found.iter().map(|a| a.filename.as_str()).collect();
+        assert_eq!(names, ["Prüfung.pdf", "Logo neu.png", "attachment-3.eml"]);
+        assert_eq!(found[0].data, b"%PDF-");
+        assert_eq!(found[1].content_type, "image/png");
+        assert_eq!(found[2].data, b"Subject: Fwd");
+
+        let separator = attachment_separator(2, 3, &found[1], Some("No engine <png>"));
+        assert!(separator.contains("Attachment 2 of 3"));
+        assert!(separator.contains("Not converted: No engine &lt;png&gt;"));
+    }
+
+    #[test]
+    fn test_decode_words() {
+        assert_eq!(
+            decode_words("=?ISO-8859-1?Q?Caf=E9_au_lait?="),
+            "Café au lait"
+        );
+        assert_eq!(
+            decode_words("Re: =?utf-8?b?w4RwZmVs?= und Birnen"),
+            "Re: Äpfel und Birnen"
+        );
+        assert_eq!(
+            decode_words("=?x-unknown?Q?a?= =?bad"),
+            "=?x-unknown?Q?a?= =?bad"
+        );
+    }
+}
@@ -0,0 +1,320 @@
+//! YAML front matter of Markdown documents: a `---` block at the top whose
+//! keys set the page layout and the PDF's document information, so authors
+//! control the output from the file itself. Options sent with the request
+//! win; keys meant for other tools (static site generators) are ignored.
+//!
+//! Only the part of YAML front matter uses is read: `key: value` lines
+//! with plain or quoted scalars, `[a, b]` and `- item` lists, and comments.
+
+use super::ConvertOptions;
+use crate::error::{AppError, Result};
+
+/// Named page sizes for `page_size`, portrait width and height
+const PAGE_SIZES: &[(&str, &str, &str)] = &[
+    ("A3", "297mm", "420mm"),
+    ("A4", "210mm", "297mm"),
+    ("A5", "148mm", "210mm"),
+    ("B5", "176mm", "250mm"),
+    ("Letter", "8.5in", "11in"),
+    ("Legal", "8.5in", "14in"),
+    ("Tabloid", "11in", "17in"),
+];
+
+/// Keys setting the option of the same name
+const OPTION_KEYS: &[(&str, &str)] = &[
+    ("landscape", "landscape"),
+    ("page_width", "pageWidth"),
+    ("page_height", "pageHeight"),
+    ("margin_top", "marginTop"),
+    ("margin_bottom", "marginBottom"),
+    ("margin_left", "marginLeft"),
+    ("margin_right", "marginRight"),
+    ("print_background", "printBackground"),
+    ("scale", "scale"),
+    ("toc", "toc"),
+    ("bookmarks", "bookmarks"),
+];
+
+/// Keys written to the PDF's document information, by entry
+const INFO_KEYS: &[(&str, &str)] = &[
+    ("title", "Title"),
+    ("author", "Author"),
+    ("subject", "Subject"),
+    ("keywords", "Keywords"),
+];
+
+/// The keys of a front matter block, lowercase with `-` read as `_`;
+/// lists are joined with ", "
+#[derive(Debug, Default, PartialEq)]
+pub struct FrontMatter {
+    entries: Vec<(String, String)>,
+}
+
+/// Split the front matter off a Markdown document. A leading `---` line
+/// without a closing `---` or `...` line is a rule, not front matter.
+pub fn split(content: &str) -> Result<(Option<FrontMatter>, &str)> {
+    let text = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let Some(block) = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+    else {
+        return Ok((None, content));
+    };
+    let mut offset = 0;
+    for line in block.split_inclusive('\n') {
+        let end = offset + line.len();
+        if matches!(line.trim_end(), "---" | "...") {
+            let front_matter = parse(&block[..offset])?;
+            return Ok((Some(front_matter), &block[end..]));
+        }
+        offset = end;
+    }
+    Ok((None, content))
+}
+
+fn parse(block: &str) -> Result<FrontMatter> {
+    let invalid = |number: usize, message: &str| {
+        AppError::InvalidRequest(format!(
+            "file: front matter line {}: {}",
+            number + 2,
+            message
+        ))
+    };
+    let mut entries: Vec<(String, String)> = Vec::new();
+    // Whether the last key has no value on its line, so `- item` lines follow
+    let mut open_list = false;
+    for (number, line) in block.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) || trimmed.starts_with("- ") || trimmed == "-" {
+            // Items of a block list, or nested values of a key we do not read
+            if let Some(item) = trimmed.strip_prefix('-').filter(|_| open_list) {
+                let item = scalar(item.trim()).map_err(|e| invalid(number, &e))?;
+                let (_, value) = entries.last_mut().expect("an open list has a key");
+                if !value.is_empty() {
+                    value.push_str(", ");
+                }
+                value.push_str(&item);
+            }
+            continue;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid(number, "expected 'key: value'"))?;
+        let key = key
+            .trim()
+            .trim_matches(['"', '\''])
+            .to_lowercase()
+            .replace('-', "_");
+        let value = value.trim();
+        open_list = value.is_empty();
+        let value = match value.strip_prefix('[') {
+            Some(list) => {
+                let list = list
+                    .strip_suffix(']')
+                    .ok_or_else(|| invalid(number, "unclosed '['"))?;
+                list.split(',')
+                    .map(|item| scalar(item.trim()))
+                    .filter(|item| item.as_ref().map_or(true, |item| !item.is_empty()))
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(|e| invalid(number, &e))?
+                    .join(", ")
+            }
+            None => scalar(value).map_err(|e| invalid(number, &e))?,
+        };
+        entries.retain(|(existing, _)| *existing != key);
+        entries.push((key, value));
+    }
+    Ok(FrontMatter { entries })
+}
+
+/// A plain or quoted YAML scalar, without a trailing comment
+fn scalar(value: &str) -> std::result::Result<String, String> {
+    if let Some(quoted) = value.strip_prefix('"') {
+        let mut text = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return Ok(text),
+                '\\' => match chars.next() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some(c) => text.push(c),
+                    None => break,
+                },
+                c => text.push(c),
+            }
+        }
+        return Err("unclosed '\"'".to_string());
+    }
+    if let Some(quoted) = value.strip_prefix('\'') {
+        let end = quoted
+            .match_indices('\'')
+            .map(|(i, _)| i)
+            .find(|&i| !quoted[i..].starts_with("''") && !quoted[..i].ends_with('\''))
+            .ok_or("unclosed \"'\"")?;
+        return Ok(quoted[..end].replace("''", "'"));
+    }
+    let value = match value.find(" #") {
+        Some(i) => &value[..i],
+        None => value,
+    };
+    Ok(value.trim().to_string())
+}
+
+impl FrontMatter {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// The document's title, for the page and the PDF
+    pub fn title(&self) -> Option<&str> {
+        self.get("title")
+    }
+
+    /// Document information entries for [`crate::pdf::set_info`]
+    pub fn info(&self) -> Vec<(&'static str, String)> {
+        INFO_KEYS
+            .iter()
+            .filter_map(|(key, entry)| self.get(key).map(|value| (*entry, value.to_string())))
+            .collect()
+    }
+
+    /// `options` with the layout keys applied where the request left the
+    /// option unset. `page_size` is a name (A4, Letter…) or a width and
+    /// height; `margin` takes one to four lengths in CSS order.
+    pub fn apply(&self, options: &ConvertOptions) -> Result<ConvertOptions> {
+        let requested: Vec<&str> = options
+            .form_fields()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        let mut merged = options.clone();
+        let mut set = |field: &str, value: &str| -> Result<()> {
+            if !requested.contains(&field) {
+                merged
+                    .set_field(field, value)
+                    .map_err(|e| AppError::InvalidRequest(format!("file: front matter {}", e)))?;
+            }
+            Ok(())
+        };
+
+        if let Some(size) = self.get("page_size") {
+            let (width, height) = page_size(size)?;
+            set("pageWidth", &width)?;
+            set("pageHeight", &height)?;
+        }
+        if let Some(margin) = self.get("margin") {
+            let values: Vec<&str> = margin.split([' ', ',']).filter(|v| !v.is_empty()).collect();
+            let (top, right, bottom, left) = match values[..] {
+                [all] => (all, all, all, all),
+                [vertical, horizontal] => (vertical, horizontal, vertical, horizontal),
+                [top, horizontal, bottom] => (top, horizontal, bottom, horizontal),
+                [top, right, bottom, left] => (top, right, bottom, left),
+                _ => {
+                    return Err(AppError::InvalidRequest(format!(
+                        "file: front matter margin: expected one to four lengths, got '{}'",
+                        margin
+                    )))
+                }
+            };
+            set("marginTop", top)?;
+            set("marginRight", right)?;
+            set("marginBottom", bottom)?;
+            set("marginLeft", left)?;
+        }
+        for (key, field) in OPTION_KEYS {
+            if let Some(value) = self.get(key) {
+                set(field, value)?;
+            }
+        }
+        merged
+            .check_layout()
+            .map_err(|e| AppError::InvalidRequest(format!("file: front matter {}", e)))?;
+        Ok(merged)
+    }
+}
+
+/// Width and height of a `page_size`: a name, or two lengths
+fn page_size(value: &str) -> Result<(String, String)> {
+    if let Some((_, width, height)) = PAGE_SIZES
+        .iter()
+        .find(|(name, _, _)| name.eq_ignore_ascii_case(value.trim()))
+    {
+        return Ok((width.to_string(), height.to_string()));
+    }
+    let lengths: Vec<&str> = value
+        .split([' ', 'x', ','])
+        .filter(|v| !v.is_empty())
+        .collect();
+    match lengths[..] {
+        [width, height] => Ok((width.to_string(), height.to_string())),
+        _ => Err(AppError::InvalidRequest(format!(
+            "file: front matter page_size: expected one of {} or a width and height, got '{}'",
+            PAGE_SIZES
+                .iter()
+                .map(|(name, _, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_front_matter() {
+        let markdown = "---\ntitle: \"Q3: Report\"\nauthor: 'Ann O''Neil' # finance\n\
+                        keywords: [sales, q3]\ntags:\n  - internal\nlayout:\n  wide: true\n\
+                        page-size: A4\nlandscape: yes\nmargin: 10mm 20mm\n---\n# Body\n";
+        let (front_matter, body) = split(markdown).unwrap();
+        let front_matter = front_matter.unwrap();
+        assert_eq!(body, "# Body\n");
+        assert_eq!(front_matter.title(), Some("Q3: Report"));
+        assert_eq!(
+            front_matter.info(),
+            [
+                ("Title", "Q3: Report".to_string()),
+                ("Author", "Ann O'Neil".to_string()),
+                ("Keywords", "sales, q3".to_string()),
+            ]
+        );
+
+        // Options sent with the request win
+        let requested = ConvertOptions {
+            margin_top: Some("1in".to_string()),
+            ..Default::default()
+        };
+        let options = front_matter.apply(&requested).unwrap();
+        assert!(options.landscape);
+        assert_eq!(options.page_width.as_deref(), Some("210mm"));
+        assert_eq!(options.margin_top.as_deref(), Some("1in"));
+        assert_eq!(options.margin_bottom.as_deref(), Some("10mm"));
+        assert_eq!(options.margin_left.as_deref(), Some("20mm"));
+
+        // A rule at the top of a document is not front matter
+        let rule = "---\nText\n";
+        assert_eq!(split(rule).unwrap(), (None, rule));
+
+        let (invalid, _) = split("---\nmargin: wide\n---\n").unwrap();
+        let error = invalid
+            .unwrap()
+            .apply(&ConvertOptions::default())
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("front matter marginTop"),
+            "{}",
+            error
+        );
+        assert!(split("---\ntitle \"x\"\n---\n").is_err());
+    }
+}
@@ -0,0 +1,433 @@
+//! Syntax highlighting of code blocks in Markdown and Org documents, done
+//! on the server so the PDF needs no script. Code is split into comments,
+//! strings, numbers and keywords by per-language tables, and each token is
+//! wrapped in a `<span class="hl-…">` styled by [`THEME_CSS`].
+
+use super::chromium::html_escape;
+
+/// Colors of the token classes, embedded in the rendered document;
+/// `customCss` can override them
+pub const THEME_CSS: &str = "        .hl-k { color: #cf222e; }
+        .hl-s { color: #0a3069; }
+        .hl-c { color: #6e7781; font-style: italic; }
+        .hl-n { color: #0550ae; }
+";
+
+/// How a language writes the tokens that are highlighted
+struct Syntax {
+    /// Names used after a code fence or `#+BEGIN_SRC`, lowercase
+    names: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    keywords: &'static [&'static str],
+    /// Whether keywords match in any case, as in SQL
+    ignore_case: bool,
+}
+
+const C_KEYWORDS: &[&str] = &[
+    "auto",
+    "break",
+    "case",
+    "char",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extern",
+    "false",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "include",
+    "inline",
+    "int",
+    "long",
+    "namespace",
+    "new",
+    "nullptr",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "template",
+    "this",
+    "true",
+    "typedef",
+    "union",
+    "unsigned",
+    "using",
+    "virtual",
+    "void",
+    "volatile",
+    "while",
+];
+
+const SYNTAXES: &[Syntax] = &[
+    Syntax {
+        names: &["rust", "rs"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+            "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true",
+            "type", "unsafe", "use", "where", "while",
+        ],
+        ignore_case: false,
+    },
+    Syntax {
+        names: &["python", "py"],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: &[
+            "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+            "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+            "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise",
+            "return", "try", "while", "with", "yield",
+        ],
+        ignore_case: false,
+    },
+    Syntax {
+        names: &["javascript", "js", "typescript", "ts", "jsx", "tsx"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+        keywords: &[
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "export",
+            "extends",
+            "false",
+            "finally",
+            "for",
+            "from",
+            "function",
+            "if",
+            "import",
+            "in",
+            "instanceof",
+            "interface",
+            "let",
+            "new",
+            "null",
+            "of",
+            "return",
+            "static",
+            "super",
+            "switch",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "type",
+            "typeof",
+            "undefined",
+            "var",
+            "void",
+            "while",
+            "yield",
+        ],
+        ignore_case: false,
+    },
+    Syntax {
+        names: &["go", "golang"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+        keywords: &[
+            "break",
+            "case",
+            "chan",
+            "const",
+            "continue",
+            "default",
+            "defer",
+            "else",
+            "false",
+            "for",
+            "func",
+            "go",
+            "if",
+            "import",
+            "interface",
+            "map",
+            "nil",
+            "package",
+            "range",
+            "return",
+            "select",
+            "struct",
+            "switch",
+            "true",
+            "type",
+            "var",
+        ],
+        ignore_case: false,
+    },
+    Syntax {
+        names: &["java", "kotlin", "kt", "csharp", "cs", "c#"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        keywords: &[
+            "abstract",
+            "boolean",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "do",
+            "double",
+            "else",
+            "enum",
+            "extends",
+            "false",
+            "final",
+            "finally",
+            "float",
+            "for",
+            "fun",
+            "if",
+            "implements",
+            "import",
+            "int",
+            "interface",
+            "long",
+            "namespace",
+            "new",
+            "null",
+            "override",
+            "package",
+            "private",
+            "protected",
+            "public",
+            "return",
+            "static",
+            "string",
+            "super",
+            "switch",
+            "this",
+            "throw",
+            "throws",
+            "true",
+            "try",
+            "using",
+            "val",
+            "var",
+            "void",
+            "while",
+        ],
+        ignore_case: false,
+    },
+    Syntax {
+        names: &["c", "h", "cpp", "c++", "cc", "hpp"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        keywords: C_KEYWORDS,
+        ignore_case: false,
+    },
+    Syntax {
+        names: &["bash", "sh", "shell", "zsh", "console"],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: &[
+            "case", "do", "done", "echo", "elif", "else", "esac", "export", "fi", "for",
+            "function", "if", "in", "local", "return", "then", "until", "while",
+        ],
+        ignore_case: false,
+    },
+    Syntax {
+        names: &["sql", "postgresql", "mysql", "sqlite"],
+        line_comments: &["--"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['\''],
+        keywords: &[
+            "and", "as", "asc", "by", "case", "create", "delete", "desc", "distinct", "drop",
+            "else", "end", "exists", "from", "group", "having", "in", "index", "inner", "insert",
+            "into", "is", "join", "left", "limit", "not", "null", "on", "or", "order", "outer",
+            "primary", "key", "right", "select", "set", "table", "then", "union", "update",
+            "values", "when", "where", "with",
+        ],
+        ignore_case: true,
+    },
+    Syntax {
+        names: &["json"],
+        line_comments: &[],
+        block_comment: None,
+        quotes: &['"'],
+        keywords: &["true", "false", "null"],
+        ignore_case: false,
+    },
+    Syntax {
+        names: &["yaml", "yml", "toml", "ini"],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: &["true", "false", "null", "yes", "no"],
+        ignore_case: false,
+    },
+];
+
+/// Escaped HTML of `code` with its tokens in highlighting spans, or `None`
+/// for a language without a syntax table
+pub fn highlight(code: &str, language: &str) -> Option<String> {
+    let language = language.trim().to_ascii_lowercase();
+    let syntax = SYNTAXES
+        .iter()
+        .find(|s| s.names.contains(&language.as_str()))?;
+    let mut html = String::with_capacity(code.len() * 2);
+    let mut plain = String::new();
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        let token = if let Some(end) = comment_end(syntax, rest) {
+            Some(("hl-c", end))
+        } else if syntax.quotes.contains(&c) {
+            Some(("hl-s", string_end(rest, c)))
+        } else if c.is_ascii_digit() && !ends_in_word(&plain) {
+            Some(("hl-n", word_end(rest)))
+        } else if is_word_char(c) && !ends_in_word(&plain) {
+            let end = word_end(rest);
+            let word = &rest[..end];
+            let keyword = syntax.keywords.iter().any(|k| match syntax.ignore_case {
+                true => k.eq_ignore_ascii_case(word),
+                false => *k == word,
+            });
+            if keyword {
+                Some(("hl-k", end))
+            } else {
+                plain.push_str(word);
+                rest = &rest[end..];
+                continue;
+            }
+        } else {
+            None
+        };
+        match token {
+            Some((class, end)) => {
+                html.push_str(&html_escape(&plain));
+                plain.clear();
+                html.push_str(&format!(
+                    "<span class=\"{}\">{}</span>",
+                    class,
+                    html_escape(&rest[..end])
+                ));
+                rest = &rest[end..];
+            }
+            None => {
+                plain.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    html.push_str(&html_escape(&plain));
+    Some(html)
+}
+
+/// Length of a comment starting `rest`, if one does
+fn comment_end(syntax: &Syntax, rest: &str) -> Option<usize> {
+    if let Some((open, close)) = syntax.block_comment {
+        if let Some(body) = rest.strip_prefix(open) {
+            return Some(
+                body.find(close)
+                    .map_or(rest.len(), |i| open.len() + i + close.len()),
+            );
+        }
+    }
+    syntax
+        .line_comments
+        .iter()
+        .any(|marker| rest.starts_with(marker))
+        .then(|| rest.find('\n').unwrap_or(rest.len()))
+}
+
+/// Length of the string literal starting `rest` with `quote`, through the
+/// closing quote; strings other than backtick templates end at the line
+fn string_end(rest: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (i, c) in rest.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\n' if quote != '`' => return i,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    rest.len()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Length of the identifier or number starting `rest`; numbers take their
+/// decimal point and suffixes along
+fn word_end(rest: &str) -> usize {
+    let number = rest.starts_with(|c: char| c.is_ascii_digit());
+    rest.char_indices()
+        .find(|&(_, c)| !(is_word_char(c) || (number && c == '.')))
+        .map_or(rest.len(), |(i, _)| i)
+}
+
+/// Whether `text` ends inside an identifier, so a digit or word following
+/// it continues that identifier
+fn ends_in_word(text: &str) -> bool {
+    text.chars().next_back().is_some_and(is_word_char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("let x = 42; // <answer>", "Rust").unwrap(),
+            "<span class=\"hl-k\">let</span> x = <span class=\"hl-n\">42</span>; \
+             <span class=\"hl-c\">// &lt;answer&gt;</span>"
+        );
+        assert_eq!(
+            highlight("print(\"a \\\" b\", v2, 'c')", "py").unwrap(),
+            "print(<span class=\"hl-s\">&quot;a \\&quot; b&quot;</span>, v2, \
+             <span class=\"hl-s\">'c'</span>)"
+        );
+        // Keywords inside identifiers stay plain
+        assert_eq!(highlight("format iffy", "python").unwrap(), "format iffy");
+        assert_eq!(
+            highlight("select * /* all\nrows */ FROM t", "sql").unwrap(),
+            "<span class=\"hl-k\">select</span> * <span class=\"hl-c\">/* all\nrows */</span> \
+             <span class=\"hl-k\">FROM</span> t"
+        );
+        assert_eq!(highlight("a < b", "brainfuck"), None);
+    }
+}
@@ -1,12 +1,20 @@
-use super::{ConvertEngine, ConvertOptions, ConvertResult, EngineType};
+use super::locate::{locate, Located};
+use super::{probe_version, tool_failed, ConvertEngine, ConvertOptions, ConvertResult, EngineType};
 use crate::error::{AppError, Result};
 use async_trait::async_trait;
 use std::path::Path;
 use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 use tracing::info;
 
-const SUPPORTED_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "bmp", "tiff", "tif", "webp", "heic", "heif", "ico", "svg"];
+const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "bmp", "tiff", "tif", "webp", "heic", "heif", "ico", "svg",
+];
+
+/// ImageMagick resource limits in the low-memory profile
+const LOW_MEMORY_LIMITS: &[(&str, &str)] =
+    &[("memory", "64MiB"), ("map", "128MiB"), ("thread", "1")];
 
 pub struct ImageEngine {
     /// Path to ImageMagick convert executable
@@ -20,35 +28,49 @@ impl ImageEngine {
         }
     }
 
+    #[allow(dead_code)]
     pub fn with_convert_path(mut self, path: String) -> Self {
         self.convert_path = Some(path);
         self
     }
 
-    fn get_convert_path(&self) -> String {
-        // First check instance config
-        if let Some(path) = &self.convert_path {
-            return path.clone();
-        }
-        
-        // Then check environment variable
-        if let Ok(path) = std::env::var("CONVERT_PATH") {
-            return path;
+    /// ImageMagick executable: instance config, `CONVERT_PATH` or `$PATH`
+    /// (`magick` for ImageMagick 7 installs without the legacy name)
+    fn locate_convert(&self) -> Located {
+        match &self.convert_path {
+            Some(path) => Located::configured(path),
+            None => locate("CONVERT_PATH", &[], &["convert", "magick"]),
         }
-        
-        // Fall back to default
-        "convert".to_string()
     }
 
+    fn get_convert_path(&self) -> String {
+        self.locate_convert().path
+    }
+
+    /// Run ImageMagick from `input` to `output`, files or `<format>:-` for
+    /// stdin and stdout; `stdin` is fed to the process and its stdout
+    /// returned
     async fn convert_to_pdf(
         &self,
-        input_path: &Path,
-        output_path: &Path,
+        input: &str,
+        output: &str,
         options: &ConvertOptions,
-    ) -> Result<()> {
-        let convert_path = self.get_convert_path();
-
-        let mut args = vec![input_path.to_str().unwrap().to_string()];
+        stdin: Option<&[u8]>,
+    ) -> Result<Vec<u8>> {
+        let convert = self.locate_convert();
+
+        let mut args = Vec::new();
+        if crate::profile::low_memory() {
+            // Spill pixel caches to disk early instead of growing in memory
+            for (resource, value) in LOW_MEMORY_LIMITS {
+                args.extend([
+                    "-limit".to_string(),
+                    resource.to_string(),
+                    value.to_string(),
+                ]);
+            }
+        }
+        args.push(input.to_string());
 
         // Add page size options if specified
         if let (Some(width), Some(height)) = (&options.page_width, &options.page_height) {
@@ -56,27 +78,45 @@ impl ImageEngine {
             args.push(format!("{}x{}", width, height));
         }
 
-        args.push(output_path.to_str().unwrap().to_string());
+        args.push(output.to_string());
 
-        let output = Command::new(convert_path)
+        let mut child = Command::new(&convert.path)
             .args(&args)
+            .kill_on_drop(true)
+            .stdin(if stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .output()
-            .await
+            .spawn()
             .map_err(|e| {
-                AppError::EngineNotAvailable(format!("ImageMagick not found: {}", e))
+                AppError::EngineNotAvailable(format!(
+                    "ImageMagick not found ({}): {}",
+                    convert.describe_tried(),
+                    e
+                ))
             })?;
 
+        // Written while the output is read, so neither pipe fills up
+        let mut pipe = child.stdin.take();
+        let feed = async {
+            if let (Some(pipe), Some(data)) = (pipe.as_mut(), stdin) {
+                pipe.write_all(data).await?;
+            }
+            drop(pipe);
+            std::io::Result::Ok(())
+        };
+        let (fed, output) = tokio::join!(feed, child.wait_with_output());
+        let output = output?;
+
         if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(AppError::ConversionFailed(format!(
-                "ImageMagick conversion failed: {}",
-                stderr
-            )));
+            return Err(tool_failed("ImageMagick", &output));
         }
+        fed?;
 
-        Ok(())
+        Ok(output.stdout)
     }
 }
 
@@ -112,16 +152,33 @@ impl ConvertEngine for ImageEngine {
             .unwrap_or(false)
     }
 
+    async fn version(&self) -> Option<String> {
+        probe_version(&self.get_convert_path(), "--version").await
+    }
+
+    fn searched_paths(&self) -> Vec<String> {
+        self.locate_convert().tried
+    }
+
+    fn uses_option(&self, option: &str, _ext: &str) -> bool {
+        matches!(option, "pageWidth" | "pageHeight")
+    }
+
     async fn convert(&self, input_path: &Path, options: &ConvertOptions) -> Result<ConvertResult> {
-        let temp_dir = tempfile::tempdir()?;
+        let temp_dir = crate::disk::tempdir()?;
         let output_path = temp_dir.path().join("output.pdf");
 
         info!(
             "Converting {} to PDF using ImageMagick",
             input_path.display()
         );
-        self.convert_to_pdf(input_path, &output_path, options)
-            .await?;
+        self.convert_to_pdf(
+            input_path.to_str().unwrap(),
+            output_path.to_str().unwrap(),
+            options,
+            None,
+        )
+        .await?;
 
         let data = tokio::fs::read(&output_path).await?;
         let original_name = input_path
@@ -133,6 +190,42 @@ impl ConvertEngine for ImageEngine {
             data,
             filename: format!("{}.pdf", original_name),
             content_type: "application/pdf".to_string(),
+            warnings: Vec::new(),
+        })
+    }
+
+    fn reads_memory(&self, ext: &str, _size: usize, _options: &ConvertOptions) -> bool {
+        self.supports_extension(ext)
+    }
+
+    async fn convert_memory(
+        &self,
+        filename: &str,
+        data: &[u8],
+        options: &ConvertOptions,
+    ) -> Result<ConvertResult> {
+        let path = Path::new(filename);
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        info!("Converting {} to PDF using ImageMagick, piped", filename);
+        // Images on stdin carry no name, so the format is given explicitly
+        let data = self
+            .convert_to_pdf(&format!("{}:-", ext), "pdf:-", options, Some(data))
+            .await?;
+        let original_name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+
+        Ok(ConvertResult {
+            data,
+            filename: format!("{}.pdf", original_name),
+            content_type: "application/pdf".to_string(),
+            warnings: Vec::new(),
         })
     }
 }
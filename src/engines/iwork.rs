@@ -0,0 +1,73 @@
+//! Apple iWork documents (.pages, .numbers, .key). LibreOffice imports
+//! them through libetonyek; when it cannot, the preview PDF that iWork
+//! saves into the package stands in for the document.
+
+use super::package;
+use crate::error::Result;
+use std::io::Read;
+use std::path::Path;
+
+/// Extensions of the iWork formats
+pub const IWORK_EXTENSIONS: &[&str] = &["pages", "numbers", "key"];
+
+/// Package entries holding a PDF rendering of the whole document, in order
+/// of preference. iWork '09 always writes one; later versions only when
+/// "Include preview in document" is on.
+const PREVIEW_ENTRIES: &[&str] = &["QuickLook/Preview.pdf", "preview.pdf"];
+
+/// The preview PDF embedded in an iWork package, if it has one
+pub fn preview_pdf(path: &Path) -> Result<Option<Vec<u8>>> {
+    let mut archive = package::open(path)?;
+    for name in PREVIEW_ENTRIES {
+        let Ok(mut entry) = archive.by_name(name) else {
+            continue;
+        };
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        if data.starts_with(b"%PDF-") {
+            return Ok(Some(data));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::sample_pdf;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    fn write_package(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut writer = ZipWriter::new(std::fs::File::create(path).unwrap());
+        for (name, data) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_preview_pdf() {
+        let dir = tempfile::tempdir().unwrap();
+        let pdf = sample_pdf(2);
+
+        let with_preview = dir.path().join("report.pages");
+        write_package(
+            &with_preview,
+            &[
+                ("index.xml.gz", b"\x1f\x8b"),
+                ("QuickLook/Preview.pdf", &pdf),
+            ],
+        );
+        assert_eq!(preview_pdf(&with_preview).unwrap(), Some(pdf));
+
+        // Keynote 6 and later keep a JPEG thumbnail only
+        let without_preview = dir.path().join("slides.key");
+        write_package(&without_preview, &[("preview.jpg", b"\xFF\xD8\xFF")]);
+        assert_eq!(preview_pdf(&without_preview).unwrap(), None);
+    }
+}
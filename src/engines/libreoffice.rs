@@ -1,15 +1,70 @@
-use super::{ConvertEngine, ConvertOptions, ConvertResult, EngineType};
+use super::iwork::{self, IWORK_EXTENSIONS};
+use super::locate::{locate, Located};
+use super::mailmerge::{self, Record};
+use super::spreadsheet::{self, PRINT_SETTINGS_EXTENSIONS};
+use super::word::{self, REVIEW_EXTENSIONS};
+use super::{
+    print_timezone, probe_version, tool_failed, ConvertEngine, ConvertOptions, ConvertResult,
+    EngineType,
+};
 use crate::error::{AppError, Result};
+use crate::pdf;
 use async_trait::async_trait;
-use std::path::Path;
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use pdfmill_core::{parse_to_inches, Comments, DrawingScale};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::Arc;
 use tokio::process::Command;
-use tracing::info;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
 
 const SUPPORTED_EXTENSIONS: &[&str] = &[
-    "doc", "docx", "xls", "xlsx", "ppt", "pptx", "odt", "ods", "odp", "rtf",
+    "doc", "docx", "xls", "xlsx", "ppt", "pptx", "odt", "ods", "odp", "rtf", "pages", "numbers",
+    "key", "wpd", "wps", "wri", "lwp", "sxw", "sxc", "sxi", "dxf",
 ];
 
+/// Output formats LibreOffice can export to besides PDF
+const OUTPUT_FORMATS: &[&str] = &[
+    "pdf", "docx", "doc", "odt", "rtf", "txt", "html", "xlsx", "xls", "ods", "csv", "pptx", "ppt",
+    "odp", "png", "jpg", "svg",
+];
+
+/// Image targets: every page (or slide) of a PDF export is rendered by
+/// poppler's `pdftocairo`
+const IMAGE_FORMATS: &[&str] = &["png", "jpg", "svg"];
+
+/// Resolution of page images
+const IMAGE_DPI: &str = "150";
+
+/// Presentation formats that support handout layouts
+const PRESENTATION_EXTENSIONS: &[&str] = &["ppt", "pptx", "odp", "key", "sxi"];
+
+/// CAD drawings, imported by Draw and laid out with `drawingScale` and the
+/// page options
+const DRAWING_EXTENSIONS: &[&str] = &["dxf"];
+
+/// Documents converted per soffice invocation during a mail merge
+const MERGE_BATCH_SIZE: usize = 100;
+
+/// Well-known LibreOffice install locations
+#[cfg(target_os = "macos")]
+const SOFFICE_CANDIDATES: &[&str] = &["/Applications/LibreOffice.app/Contents/MacOS/soffice"];
+#[cfg(target_os = "windows")]
+const SOFFICE_CANDIDATES: &[&str] = &[r"C:\Program Files\LibreOffice\program\soffice.exe"];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const SOFFICE_CANDIDATES: &[&str] = &[
+    "/usr/bin/soffice",
+    "/usr/bin/libreoffice",
+    "/usr/lib/libreoffice/program/soffice",
+    "/snap/bin/libreoffice",
+    "/var/lib/flatpak/exports/bin/org.libreoffice.LibreOffice",
+];
+
+/// Editable formats a PDF can be turned back into via Writer's PDF import
+pub const REVERSE_FORMATS: &[&str] = &["docx", "odt", "rtf", "txt", "html"];
+
 pub struct LibreOfficeEngine {
     /// Path to LibreOffice/soffice executable
     soffice_path: Option<String>,
@@ -17,82 +72,455 @@ pub struct LibreOfficeEngine {
 
 impl LibreOfficeEngine {
     pub fn new() -> Self {
-        Self {
-            soffice_path: None,
-        }
+        Self { soffice_path: None }
     }
 
+    #[allow(dead_code)]
     pub fn with_soffice_path(mut self, path: String) -> Self {
         self.soffice_path = Some(path);
         self
     }
 
-    fn get_soffice_path(&self) -> String {
-        // First check instance config
-        if let Some(path) = &self.soffice_path {
-            return path.clone();
-        }
-        
-        // Then check environment variable
-        if let Ok(path) = std::env::var("SOFFICE_PATH") {
-            return path;
-        }
-        
-        // Fall back to OS-specific defaults
-        if cfg!(target_os = "macos") {
-            "/Applications/LibreOffice.app/Contents/MacOS/soffice".to_string()
-        } else if cfg!(target_os = "windows") {
-            r"C:\Program Files\LibreOffice\program\soffice.exe".to_string()
-        } else {
-            // Try common Linux paths
-            for path in &["/usr/bin/soffice", "/usr/bin/libreoffice"] {
-                if std::path::Path::new(path).exists() {
-                    return path.to_string();
-                }
-            }
-            "soffice".to_string()
+    /// soffice executable: instance config, `SOFFICE_PATH`, a well-known
+    /// location or `$PATH`
+    fn locate_soffice(&self) -> Located {
+        match &self.soffice_path {
+            Some(path) => Located::configured(path),
+            None => locate(
+                "SOFFICE_PATH",
+                SOFFICE_CANDIDATES,
+                &["soffice", "libreoffice"],
+            ),
         }
     }
 
-    async fn convert_to_pdf(
+    fn get_soffice_path(&self) -> String {
+        self.locate_soffice().path
+    }
+
+    /// Run soffice on `input_paths`, in the profile for the options'
+    /// `documentLocale` and with their timezone for date fields
+    async fn convert_with_soffice(
         &self,
-        input_path: &Path,
+        input_paths: &[&Path],
         output_dir: &Path,
-        _options: &ConvertOptions,
+        target: &str,
+        infilter: Option<&str>,
+        options: &ConvertOptions,
     ) -> Result<()> {
-        let soffice_path = self.get_soffice_path();
+        let soffice = self.locate_soffice();
 
-        let args = vec![
-            "--headless",
+        let locale = options.document_locale.as_deref();
+        let profile = crate::disk::libreoffice_profile(locale)?;
+        let infilter_arg = infilter.map(|f| format!("--infilter={}", f));
+        let mut args = vec!["--headless", profile.arg.as_str()];
+        if let Some(ref arg) = infilter_arg {
+            args.push(arg);
+        }
+        args.extend([
             "--convert-to",
-            "pdf",
+            target,
             "--outdir",
             output_dir.to_str().unwrap(),
-            input_path.to_str().unwrap(),
-        ];
+        ]);
+        args.extend(input_paths.iter().map(|p| p.to_str().unwrap()));
 
-        let output = Command::new(soffice_path)
+        let output = Command::new(&soffice.path)
             .args(&args)
+            .env("TZ", print_timezone(options))
+            .kill_on_drop(true)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .output()
             .await
             .map_err(|e| {
-                AppError::EngineNotAvailable(format!("LibreOffice not found: {}", e))
+                AppError::EngineNotAvailable(format!(
+                    "LibreOffice not found ({}): {}",
+                    soffice.describe_tried(),
+                    e
+                ))
             })?;
 
         if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(AppError::ConversionFailed(format!(
-                "LibreOffice conversion failed: {}",
-                stderr
-            )));
+            return Err(tool_failed("LibreOffice", &output));
+        }
+
+        Ok(())
+    }
+
+    /// Settings stored inside the document (spreadsheet page setup, Word
+    /// review marks) are written into a copy of the input before exporting.
+    /// Returns the file LibreOffice should convert, and warnings about what
+    /// the settings hid.
+    async fn prepare_source(
+        &self,
+        input_path: &Path,
+        ext: &str,
+        target: &str,
+        options: &ConvertOptions,
+        temp_dir: &Path,
+    ) -> Result<(PathBuf, Vec<String>)> {
+        if target != "pdf" {
+            return Ok((input_path.to_path_buf(), Vec::new()));
+        }
+
+        let print_settings =
+            PRINT_SETTINGS_EXTENSIONS.contains(&ext) && spreadsheet::wants_print_settings(options);
+        let review_settings =
+            REVIEW_EXTENSIONS.contains(&ext) && word::strips_review_marks(options);
+        if !print_settings && !review_settings {
+            return Ok((input_path.to_path_buf(), Vec::new()));
+        }
+
+        let source_dir = temp_dir.join("source");
+        tokio::fs::create_dir(&source_dir).await?;
+        let mut input = input_path.to_path_buf();
+
+        // Binary DOC files are edited as DOCX
+        if review_settings && ext == "doc" {
+            let docx_dir = temp_dir.join("docx");
+            tokio::fs::create_dir(&docx_dir).await?;
+            self.convert_with_soffice(&[input_path], &docx_dir, "docx", None, options)
+                .await?;
+            input = docx_dir.join(
+                input_path
+                    .with_extension("docx")
+                    .file_name()
+                    .unwrap_or_default(),
+            );
         }
 
+        let output = source_dir.join(input.file_name().unwrap_or_default());
+        let (prepared, options) = (output.clone(), options.clone());
+        let warnings = tokio::task::spawn_blocking(move || {
+            if review_settings {
+                word::apply_review_settings(&input, &output, &options)
+            } else {
+                spreadsheet::apply_print_settings(&input, &output, &options).map(|_| Vec::new())
+            }
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Document setup panicked: {}", e)))??;
+
+        Ok((prepared, warnings))
+    }
+}
+
+/// Sheet and scale for a drawing, when the options ask for a layout other
+/// than the one Draw imports: a page size or margins fit the drawing to the
+/// page unless `drawingScale` says otherwise
+fn drawing_layout(options: &ConvertOptions) -> Option<(pdf::Sheet, DrawingScale)> {
+    let points = |length: &Option<String>| {
+        length
+            .as_deref()
+            .and_then(parse_to_inches)
+            .map(|inches| inches as f32 * 72.0)
+    };
+    let margins = [
+        &options.margin_top,
+        &options.margin_right,
+        &options.margin_bottom,
+        &options.margin_left,
+    ];
+    let sheet = pdf::Sheet {
+        width: points(&options.page_width),
+        height: points(&options.page_height),
+        landscape: options.landscape,
+        margins: margins.map(|margin| points(margin).unwrap_or(0.0)),
+    };
+
+    let laid_out = sheet.width.is_some()
+        || sheet.height.is_some()
+        || sheet.landscape
+        || margins.iter().any(|margin| margin.is_some());
+    match options.drawing_scale {
+        Some(scale) => Some((sheet, scale)),
+        None if laid_out => Some((sheet, DrawingScale::Fit)),
+        None => None,
+    }
+}
+
+/// The preview PDF of an iWork document LibreOffice could not import, or
+/// the import error when the package has none
+async fn iwork_preview(input_path: &Path, error: AppError) -> Result<ConvertResult> {
+    let path = input_path.to_path_buf();
+    let preview = tokio::task::spawn_blocking(move || iwork::preview_pdf(&path))
+        .await
+        .map_err(|e| AppError::Internal(format!("Preview extraction panicked: {}", e)))?;
+    let Ok(Some(data)) = preview else {
+        return Err(error);
+    };
+
+    warn!(
+        "Using the embedded preview of {}: {}",
+        input_path.display(),
+        error
+    );
+    let stem = input_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+    Ok(ConvertResult {
+        data,
+        filename: format!("{}.pdf", stem),
+        content_type: "application/pdf".to_string(),
+        warnings: vec![
+            "LibreOffice could not import the document; its embedded iWork preview was used, \
+             so conversion options were not applied"
+                .to_string(),
+        ],
+    })
+}
+
+/// Render every page of a PDF `document` as a `format` image: the image
+/// itself for a single page, otherwise a ZIP of `<stem>-<page>.<format>`
+async fn page_images(
+    document: Vec<u8>,
+    stem: &str,
+    format: &str,
+    dir: &Path,
+) -> Result<ConvertResult> {
+    let document = crate::router::check_page_limit(document).await?;
+    let input = dir.join("pages.pdf");
+    tokio::fs::write(&input, &document).await?;
+    let pages = tokio::task::spawn_blocking(move || pdf::page_count(&document))
+        .await
+        .map_err(|e| AppError::Internal(format!("Page count panicked: {}", e)))??;
+
+    info!("Rendering {} pages as {}", pages, format.to_uppercase());
+    if pages == 1 {
+        let data = render_page(&input, 1, format, dir).await?;
+        let filename = format!("{}.{}", stem, format);
+        return Ok(ConvertResult {
+            data,
+            content_type: mime_guess::from_path(&filename)
+                .first_or_octet_stream()
+                .to_string(),
+            filename,
+            warnings: Vec::new(),
+        });
+    }
+
+    // Each page goes into the archive as soon as it is rendered, so only
+    // one image is held at a time
+    let archive = dir.join("pages.zip");
+    let mut zip = ZipFileWriter::with_tokio(tokio::fs::File::create(&archive).await?);
+    for page in 1..=pages {
+        let data = render_page(&input, page, format, dir).await?;
+        let entry = ZipEntryBuilder::new(
+            format!("{}-{}.{}", stem, page, format).into(),
+            Compression::Deflate,
+        );
+        zip.write_entry_whole(entry, &data)
+            .await
+            .map_err(zip_error)?;
+    }
+    zip.close().await.map_err(zip_error)?;
+
+    Ok(ConvertResult {
+        data: tokio::fs::read(&archive).await?,
+        filename: format!("{}.zip", stem),
+        content_type: "application/zip".to_string(),
+        warnings: Vec::new(),
+    })
+}
+
+/// Render one page of `input` with pdftocairo, removing the image file
+/// once it is read
+async fn render_page(input: &Path, page: usize, format: &str, dir: &Path) -> Result<Vec<u8>> {
+    let number = page.to_string();
+    let prefix = dir.join(format!("page-{}", page));
+    let mut command = Command::new("pdftocairo");
+    command.args(["-f", &number, "-l", &number, "-r", IMAGE_DPI]);
+    // Raster outputs are named by a prefix, vector outputs in full
+    match format {
+        "png" => command.args(["-png", "-singlefile"]),
+        "jpg" => command.args(["-jpeg", "-singlefile"]),
+        _ => command.arg("-svg"),
+    };
+    let image = prefix.with_extension(format);
+    let output = command
+        .arg(input)
+        .arg(if format == "svg" { &image } else { &prefix })
+        .kill_on_drop(true)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .map_err(|e| {
+            AppError::EngineNotAvailable(format!(
+                "pdftocairo not found for {} output: {}",
+                format, e
+            ))
+        })?;
+    if !output.status.success() {
+        return Err(tool_failed("pdftocairo", &output));
+    }
+    let data = tokio::fs::read(&image).await?;
+    tokio::fs::remove_file(&image).await?;
+    Ok(data)
+}
+
+fn zip_error(e: async_zip::error::ZipError) -> AppError {
+    AppError::Internal(format!("Failed to write ZIP: {}", e))
+}
+
+impl LibreOfficeEngine {
+    /// Fill a DOCX template once per record and convert every copy to PDF,
+    /// sending `(filename, pdf)` pairs in record order as each batch is
+    /// done. Stops early once the receiver is gone.
+    pub async fn mail_merge(
+        &self,
+        template: &Path,
+        records: Vec<Record>,
+        filename_field: Option<&str>,
+        documents: &mpsc::Sender<Result<(String, Vec<u8>)>>,
+    ) -> Result<()> {
+        let temp_dir = crate::disk::tempdir()?;
+        let stem = template
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("document")
+            .to_string();
+        let names = merge_filenames(&stem, &records, filename_field);
+        info!("Filling {} copies of {}", records.len(), template.display());
+
+        let records = Arc::new(records);
+        for (batch, names) in names.chunks(MERGE_BATCH_SIZE).enumerate() {
+            let batch_dir = temp_dir.path().join(batch.to_string());
+            tokio::fs::create_dir(&batch_dir).await?;
+
+            let offset = batch * MERGE_BATCH_SIZE;
+            let (template, dir, records, stems) = (
+                template.to_path_buf(),
+                batch_dir.clone(),
+                Arc::clone(&records),
+                names.to_vec(),
+            );
+            let docs = tokio::task::spawn_blocking(move || {
+                stems
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| {
+                        let doc = dir.join(format!("{}.docx", name));
+                        mailmerge::fill_template(&template, &doc, &records[offset + i])?;
+                        Ok(doc)
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .await
+            .map_err(|e| AppError::Internal(format!("Mail merge panicked: {}", e)))??;
+
+            // One soffice process converts the whole batch
+            let inputs: Vec<&Path> = docs.iter().map(PathBuf::as_path).collect();
+            let defaults = ConvertOptions::default();
+            self.convert_with_soffice(&inputs, &batch_dir, "pdf", None, &defaults)
+                .await?;
+
+            for name in names {
+                let filename = format!("{}.pdf", name);
+                let data = tokio::fs::read(batch_dir.join(&filename)).await?;
+                if documents.send(Ok((filename, data))).await.is_err() {
+                    return Ok(());
+                }
+            }
+            tokio::fs::remove_dir_all(&batch_dir).await?;
+        }
         Ok(())
     }
 }
 
+/// Unique, filesystem-safe base names for merged documents: the value of
+/// `filename_field` when given, otherwise the template name and a number
+fn merge_filenames(stem: &str, records: &[Record], filename_field: Option<&str>) -> Vec<String> {
+    let width = records.len().to_string().len();
+    let mut seen = std::collections::HashSet::new();
+
+    records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            let base = filename_field
+                .and_then(|field| record.get(field))
+                .map(|value| {
+                    value
+                        .chars()
+                        .map(|c| {
+                            if c.is_alphanumeric() || "-_. ".contains(c) {
+                                c
+                            } else {
+                                '_'
+                            }
+                        })
+                        .collect::<String>()
+                        .trim_matches(|c: char| c == '.' || c.is_whitespace())
+                        .to_string()
+                })
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| format!("{}-{:0width$}", stem, i + 1, width = width));
+
+            let mut name = base.clone();
+            let mut n = 2;
+            while !seen.insert(name.to_lowercase()) {
+                name = format!("{}-{}", base, n);
+                n += 1;
+            }
+            name
+        })
+        .collect()
+}
+
+/// A boolean value in LibreOffice's JSON filter options syntax
+fn filter_bool(value: bool) -> serde_json::Value {
+    serde_json::json!({ "type": "boolean", "value": value.to_string() })
+}
+
+impl LibreOfficeEngine {
+    /// Turn a PDF back into an editable document using Writer's PDF import
+    pub async fn convert_from_pdf(&self, input_path: &Path, target: &str) -> Result<ConvertResult> {
+        if !REVERSE_FORMATS.contains(&target) {
+            return Err(AppError::InvalidRequest(format!(
+                "Cannot convert PDF to '{}' (supported: {})",
+                target,
+                REVERSE_FORMATS.join(", ")
+            )));
+        }
+
+        let temp_dir = crate::disk::tempdir()?;
+
+        info!(
+            "Converting {} to {} using LibreOffice PDF import",
+            input_path.display(),
+            target.to_uppercase()
+        );
+        self.convert_with_soffice(
+            &[input_path],
+            temp_dir.path(),
+            target,
+            Some("writer_pdf_import"),
+            &ConvertOptions::default(),
+        )
+        .await?;
+
+        let input_stem = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        let filename = format!("{}.{}", input_stem, target);
+        let data = tokio::fs::read(temp_dir.path().join(&filename)).await?;
+
+        Ok(ConvertResult {
+            data,
+            content_type: mime_guess::from_path(&filename)
+                .first_or_octet_stream()
+                .to_string(),
+            filename,
+            warnings: Vec::new(),
+        })
+    }
+}
+
 impl Default for LibreOfficeEngine {
     fn default() -> Self {
         Self::new()
@@ -125,29 +553,154 @@ impl ConvertEngine for LibreOfficeEngine {
             .unwrap_or(false)
     }
 
+    async fn version(&self) -> Option<String> {
+        probe_version(&self.get_soffice_path(), "--version").await
+    }
+
+    fn searched_paths(&self) -> Vec<String> {
+        self.locate_soffice().tried
+    }
+
+    fn output_formats(&self) -> Vec<&'static str> {
+        OUTPUT_FORMATS.to_vec()
+    }
+
+    fn uses_option(&self, option: &str, ext: &str) -> bool {
+        match option {
+            "landscape" => {
+                PRINT_SETTINGS_EXTENSIONS.contains(&ext) || DRAWING_EXTENSIONS.contains(&ext)
+            }
+            "gridlines" | "sheetHeaders" | "printArea" | "autoLandscape" => {
+                PRINT_SETTINGS_EXTENSIONS.contains(&ext)
+            }
+            "handout" | "handoutNotes" | "speakerNotes" => PRESENTATION_EXTENSIONS.contains(&ext),
+            "trackChanges" | "comments" => REVIEW_EXTENSIONS.contains(&ext),
+            "documentLocale" | "timezone" => true,
+            "drawingScale" | "pageWidth" | "pageHeight" | "marginTop" | "marginBottom"
+            | "marginLeft" | "marginRight" => DRAWING_EXTENSIONS.contains(&ext),
+            _ => false,
+        }
+    }
+
     async fn convert(&self, input_path: &Path, options: &ConvertOptions) -> Result<ConvertResult> {
-        let temp_dir = tempfile::tempdir()?;
+        let temp_dir = crate::disk::tempdir()?;
+        let target = options.output_format.as_deref().unwrap_or("pdf");
+        let ext = input_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let is_presentation = PRESENTATION_EXTENSIONS.contains(&ext.as_str());
+        let is_word = REVIEW_EXTENSIONS.contains(&ext.as_str());
+        let is_image = IMAGE_FORMATS.contains(&target);
+        // LibreOffice's image filters stop after the first page
+        let export = if is_image { "pdf" } else { target };
+
+        // PDF export filter settings are passed inline with the target as
+        // "pdf:<filter>:<json options>"
+        let mut filter_data = serde_json::Map::new();
+        if target == "pdf" && is_presentation && options.speaker_notes {
+            filter_data.insert("ExportNotesPages".into(), filter_bool(true));
+            filter_data.insert("ExportOnlyNotesPages".into(), filter_bool(true));
+        }
+        if target == "pdf" && is_word && options.comments == Comments::Margin {
+            filter_data.insert("ExportNotesInMargin".into(), filter_bool(true));
+        }
+        let convert_to = if filter_data.is_empty() {
+            export.to_string()
+        } else {
+            let filter = if is_presentation {
+                "impress_pdf_Export"
+            } else {
+                "writer_pdf_Export"
+            };
+            format!("pdf:{}:{}", filter, serde_json::Value::Object(filter_data))
+        };
+
+        let (source, mut warnings) = self
+            .prepare_source(input_path, &ext, export, options, temp_dir.path())
+            .await?;
 
         info!(
-            "Converting {} to PDF using LibreOffice",
-            input_path.display()
+            "Converting {} to {} using LibreOffice",
+            input_path.display(),
+            target.to_uppercase()
         );
-        self.convert_to_pdf(input_path, temp_dir.path(), options)
-            .await?;
+        let converted = self
+            .convert_with_soffice(&[&source], temp_dir.path(), &convert_to, None, options)
+            .await;
+        if let Err(e) = converted {
+            if target == "pdf" && IWORK_EXTENSIONS.contains(&ext.as_str()) {
+                return iwork_preview(input_path, e).await;
+            }
+            return Err(e);
+        }
 
-        // LibreOffice creates a PDF with the same base name
+        // LibreOffice creates the output with the same base name
         let input_stem = input_path
             .file_stem()
             .and_then(|s| s.to_str())
             .unwrap_or("output");
-        let output_path = temp_dir.path().join(format!("{}.pdf", input_stem));
+        let filename = format!("{}.{}", input_stem, target);
+        let output_path = temp_dir.path().join(format!("{}.{}", input_stem, export));
+
+        let mut data = tokio::fs::read(&output_path).await?;
+        if is_image {
+            let mut result = page_images(data, input_stem, target, temp_dir.path()).await?;
+            result.warnings = warnings;
+            return Ok(result);
+        }
 
-        let data = tokio::fs::read(&output_path).await?;
+        let is_docx = source
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("docx"));
+        if target == "pdf" && is_docx {
+            let source = source.clone();
+            let checked;
+            (data, checked) = tokio::task::spawn_blocking(move || {
+                let fonts = word::requested_fonts(&source).and_then(|requested| {
+                    Ok(word::substituted_fonts(
+                        &requested,
+                        &pdf::font_names(&data)?,
+                    ))
+                });
+                (data, fonts)
+            })
+            .await
+            .map_err(|e| AppError::Internal(format!("Font check panicked: {}", e)))?;
+            match checked {
+                Ok(substituted) => warnings.extend(substituted),
+                Err(e) => warn!("Font check of {} skipped: {}", input_path.display(), e),
+            }
+        }
+
+        if let Some(per_page) = options
+            .handout
+            .filter(|_| target == "pdf" && is_presentation)
+        {
+            let note_lines = options.handout_notes;
+            data = tokio::task::spawn_blocking(move || {
+                pdf::impose_handout(&data, per_page, note_lines)
+            })
+            .await
+            .map_err(|e| AppError::Internal(format!("Handout layout panicked: {}", e)))??;
+        }
+
+        let is_drawing = DRAWING_EXTENSIONS.contains(&ext.as_str());
+        let layout = drawing_layout(options).filter(|_| target == "pdf" && is_drawing);
+        if let Some((sheet, scale)) = layout {
+            data = tokio::task::spawn_blocking(move || pdf::place_drawing(&data, &sheet, scale))
+                .await
+                .map_err(|e| AppError::Internal(format!("Drawing layout panicked: {}", e)))??;
+        }
 
         Ok(ConvertResult {
             data,
-            filename: format!("{}.pdf", input_stem),
-            content_type: "application/pdf".to_string(),
+            content_type: mime_guess::from_path(&filename)
+                .first_or_octet_stream()
+                .to_string(),
+            filename,
+            warnings,
         })
     }
 }
@@ -0,0 +1,173 @@
+//! External hyperlinks of source documents (DOCX, Markdown, HTML), checked
+//! against the converted PDF when `linkAudit` is set
+
+use super::package::{self, attr, read_entry, tags, xml_unescape};
+use super::word::is_story_part;
+use super::ConvertResult;
+use crate::error::{AppError, Result};
+use crate::pdf::{self, LinkReport};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// Schemes of links worth auditing; anchors and relative links stay inside
+/// the document
+const LINK_SCHEMES: &[&str] = &["http://", "https://", "mailto:"];
+
+/// Distinct external links of a document, in order of appearance; empty
+/// for formats without links to compare
+pub fn source_links(path: &Path) -> Result<Vec<String>> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let links = match ext.as_str() {
+        "docx" => docx_links(path)?,
+        "md" | "markdown" => markdown_links(&std::fs::read_to_string(path)?),
+        "html" | "htm" | "xhtml" => html_links(&std::fs::read_to_string(path)?),
+        _ => Vec::new(),
+    };
+
+    let mut distinct: Vec<String> = Vec::new();
+    for link in links {
+        let link = link.trim();
+        let external = LINK_SCHEMES.iter().any(|scheme| {
+            link.len() > scheme.len() && link[..scheme.len()].eq_ignore_ascii_case(scheme)
+        });
+        if external && !distinct.iter().any(|l| l == link) {
+            distinct.push(link.to_string());
+        }
+    }
+    Ok(distinct)
+}
+
+/// Hyperlink relationships of every part, and HYPERLINK field codes
+fn docx_links(path: &Path) -> Result<Vec<String>> {
+    let mut archive = package::open(path)?;
+    let names: Vec<String> = archive.file_names().map(String::from).collect();
+    let field = Regex::new(r#"HYPERLINK\s+"([^"]+)""#).unwrap();
+    let mut links = Vec::new();
+    for name in names {
+        if name.starts_with("word/_rels/") && name.ends_with(".rels") {
+            let xml = read_entry(&mut archive, &name)?;
+            for relationship in tags(&xml, "Relationship") {
+                let hyperlink =
+                    attr(relationship, "Type").is_some_and(|t| t.ends_with("/hyperlink"));
+                let external = attr(relationship, "TargetMode").as_deref() == Some("External");
+                if let (true, true, Some(target)) =
+                    (hyperlink, external, attr(relationship, "Target"))
+                {
+                    links.push(target);
+                }
+            }
+        } else if is_story_part(&name) {
+            let xml = xml_unescape(&read_entry(&mut archive, &name)?);
+            links.extend(field.captures_iter(&xml).map(|c| c[1].to_string()));
+        }
+    }
+    Ok(links)
+}
+
+/// Inline links, autolinks and reference definitions
+fn markdown_links(text: &str) -> Vec<String> {
+    let pattern = Regex::new(
+        r"(?m)\]\(\s*<?([^)\s>]+)|<([a-zA-Z][a-zA-Z0-9+.-]*:[^>\s]+)>|^\s{0,3}\[[^\]]+\]:\s*<?([^>\s]+)",
+    )
+    .unwrap();
+    pattern
+        .captures_iter(text)
+        .filter_map(|c| {
+            c.iter()
+                .skip(1)
+                .flatten()
+                .next()
+                .map(|m| m.as_str().to_string())
+        })
+        .collect()
+}
+
+/// Targets of `<a href>`
+fn html_links(html: &str) -> Vec<String> {
+    let pattern =
+        Regex::new(r#"(?is)<a\s[^>]*?\bhref\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).unwrap();
+    pattern
+        .captures_iter(html)
+        .filter_map(|c| {
+            c.iter()
+                .skip(1)
+                .flatten()
+                .next()
+                .map(|m| xml_unescape(m.as_str()))
+        })
+        .collect()
+}
+
+/// Audit the links of a PDF result against its source and repair what can
+/// be repaired; other results pass unchanged
+pub async fn audit_links(
+    input_path: &Path,
+    mut result: ConvertResult,
+) -> Result<(ConvertResult, Option<LinkReport>)> {
+    if result.content_type != "application/pdf" {
+        return Ok((result, None));
+    }
+    let input_path: PathBuf = input_path.to_path_buf();
+    let data = std::mem::take(&mut result.data);
+    let (data, report) = tokio::task::spawn_blocking(move || {
+        let links = source_links(&input_path)?;
+        pdf::repair_links(data, &links)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Link audit panicked: {}", e)))??;
+    result.data = data;
+    result.warnings.extend(
+        report
+            .missing
+            .iter()
+            .map(|url| format!("Link to {} was lost in the conversion", url)),
+    );
+    Ok((result, Some(report)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_links() {
+        let html = r##"<a href="https://example.com/?a=1&amp;b=2">x</a> <a class=x HREF='mailto:ada@example.com'>y</a> <a href="#top">z</a>"##;
+        assert_eq!(
+            html_links(html),
+            vec![
+                "https://example.com/?a=1&b=2",
+                "mailto:ada@example.com",
+                "#top"
+            ]
+        );
+
+        let markdown = "See [docs](https://example.com/docs \"title\") and <https://example.org>.\n\n[ref]: https://example.net/ref\n";
+        assert_eq!(
+            markdown_links(markdown),
+            vec![
+                "https://example.com/docs",
+                "https://example.org",
+                "https://example.net/ref"
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.html");
+        std::fs::write(
+            &path,
+            format!(
+                "{}{}",
+                html, r#"<a href="https://example.com/?a=1&b=2">again</a>"#
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            source_links(&path).unwrap(),
+            vec!["https://example.com/?a=1&b=2", "mailto:ada@example.com"]
+        );
+    }
+}
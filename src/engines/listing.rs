@@ -0,0 +1,489 @@
+//! ZIP and tar archives printed as a listing of their contents: path,
+//! size, modification date and SHA-256 of every entry, under a summary of
+//! the archive itself. Records offices send the listing as the transmittal
+//! cover of the archive.
+
+use super::chromium::html_escape;
+use super::{file_size, package, print_timezone, sha256_hex};
+use super::{ChromiumEngine, ConvertEngine, ConvertOptions, ConvertResult, EngineType};
+use crate::error::{AppError, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::info;
+
+const SUPPORTED_EXTENSIONS: &[&str] = &["zip", "tar", "tgz", "gz"];
+
+/// Entries listed before the rest are left out, bounding the page count
+const MAX_ENTRIES: usize = 20_000;
+
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+/// A file, directory or link inside the archive
+struct Entry {
+    path: String,
+    kind: EntryKind,
+    size: u64,
+    /// As stored: ZIP dates are local time without a zone, tar dates UTC
+    modified: Option<String>,
+    sha256: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum EntryKind {
+    File,
+    Directory,
+    /// Symbolic or hard link, with its target
+    Link(String),
+}
+
+/// Archive listing engine: reads the archive itself and prints the listing
+/// via Chromium
+pub struct ArchiveEngine {
+    chromium: Arc<ChromiumEngine>,
+}
+
+impl ArchiveEngine {
+    pub fn new(chromium: Arc<ChromiumEngine>) -> Self {
+        Self { chromium }
+    }
+}
+
+#[async_trait]
+impl ConvertEngine for ArchiveEngine {
+    fn engine_type(&self) -> EngineType {
+        EngineType::Archive
+    }
+
+    fn supports_extension(&self, ext: &str) -> bool {
+        SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+    }
+
+    fn supported_extensions(&self) -> Vec<&'static str> {
+        SUPPORTED_EXTENSIONS.to_vec()
+    }
+
+    /// Archives are read in-process; only the printing needs a tool
+    async fn is_available(&self) -> bool {
+        self.chromium.is_available().await
+    }
+
+    async fn version(&self) -> Option<String> {
+        None
+    }
+
+    fn searched_paths(&self) -> Vec<String> {
+        self.chromium.searched_paths()
+    }
+
+    /// The listing is rendered as an HTML page
+    fn uses_option(&self, option: &str, _ext: &str) -> bool {
+        self.chromium.uses_option(option, "html")
+    }
+
+    async fn convert(&self, input_path: &Path, options: &ConvertOptions) -> Result<ConvertResult> {
+        let timezone: Tz = print_timezone(options).parse().map_err(|_| {
+            AppError::InvalidRequest(format!("Invalid timezone '{}'", print_timezone(options)))
+        })?;
+        let name = input_path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("archive")
+            .to_string();
+
+        info!("Listing archive {}", input_path.display());
+        let path = input_path.to_path_buf();
+        let html = tokio::task::spawn_blocking(move || {
+            let data = std::fs::read(&path)?;
+            let entries = if data.starts_with(b"PK") {
+                read_zip(&path)?
+            } else {
+                read_tar(&data[..], timezone)?
+            };
+            Ok::<_, AppError>(render_listing(&name, &data, &entries, timezone))
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Archive listing panicked: {}", e)))??;
+
+        let temp_dir = crate::disk::tempdir()?;
+        let html_path = temp_dir.path().join("listing.html");
+        tokio::fs::write(&html_path, html).await?;
+
+        let mut result = self.chromium.convert(&html_path, options).await?;
+        let original_name = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .map(|s| s.trim_end_matches(".tar"))
+            .unwrap_or("archive");
+        result.filename = format!("{}.pdf", original_name);
+        Ok(result)
+    }
+}
+
+fn read_zip(path: &Path) -> Result<Vec<Entry>> {
+    let mut archive = package::open(path)?;
+    let mut entries = Vec::new();
+    for i in 0..archive.len().min(MAX_ENTRIES) {
+        let mut file = archive.by_index(i).map_err(|e| {
+            AppError::ConversionFailed(format!("Failed to read archive entry {}: {}", i + 1, e))
+        })?;
+        let modified = file.last_modified().map(|t| {
+            format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}",
+                t.year(),
+                t.month(),
+                t.day(),
+                t.hour(),
+                t.minute()
+            )
+        });
+        let path = file.name().to_string();
+        let size = file.size();
+        let (kind, sha256) = if file.is_dir() {
+            (EntryKind::Directory, None)
+        } else {
+            (EntryKind::File, Some(checksum(&mut file, size)?))
+        };
+        entries.push(Entry {
+            path,
+            kind,
+            size,
+            modified,
+            sha256,
+        });
+    }
+    Ok(entries)
+}
+
+/// Read a tar archive, gzip-compressed or not: ustar and GNU headers with
+/// GNU long names and pax `path`/`linkpath`/`mtime` records
+fn read_tar(data: &[u8], timezone: Tz) -> Result<Vec<Entry>> {
+    let mut reader: Box<dyn Read + '_> = if data.starts_with(GZIP_MAGIC) {
+        Box::new(BufReader::new(GzDecoder::new(data)))
+    } else {
+        Box::new(data)
+    };
+
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    let mut long_link = None;
+    let mut pax: Vec<(String, String)> = Vec::new();
+    let mut header = [0u8; 512];
+    while entries.len() < MAX_ENTRIES {
+        if !read_block(&mut reader, &mut header)? || header.iter().all(|b| *b == 0) {
+            break;
+        }
+        if octal(&header[148..156]) != Some(header_checksum(&header)) {
+            return Err(not_an_archive());
+        }
+        let size = tar_size(&header[124..136]).ok_or_else(not_an_archive)?;
+        let typeflag = header[156];
+
+        // Metadata entries describe the entry that follows them
+        if matches!(typeflag, b'L' | b'K' | b'x') {
+            let mut data = Vec::new();
+            (&mut reader).take(size).read_to_end(&mut data)?;
+            skip_padding(&mut reader, size)?;
+            match typeflag {
+                b'L' => long_name = Some(c_string(&data)),
+                b'K' => long_link = Some(c_string(&data)),
+                _ => pax = pax_records(&data),
+            }
+            continue;
+        }
+        let pax_value = |key: &str| {
+            pax.iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.clone())
+        };
+
+        let path = match (long_name.take(), pax_value("path")) {
+            (_, Some(path)) | (Some(path), None) => path,
+            (None, None) => {
+                let name = c_string(&header[0..100]);
+                let prefix = c_string(&header[345..500]);
+                if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                    format!("{}/{}", prefix, name)
+                } else {
+                    name
+                }
+            }
+        };
+        let link = pax_value("linkpath")
+            .or(long_link.take())
+            .unwrap_or_else(|| c_string(&header[157..257]));
+        let mtime = pax_value("mtime")
+            .and_then(|t| t.split('.').next().and_then(|s| s.parse::<i64>().ok()))
+            .or_else(|| octal(&header[136..148]).map(|t| t as i64));
+        let modified = mtime
+            .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0))
+            .map(|t| {
+                t.with_timezone(&timezone)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            });
+        pax.clear();
+
+        let (kind, sha256) = match typeflag {
+            b'0' | 0 | b'7' => {
+                let sha256 = checksum(&mut (&mut reader).take(size), size)?;
+                skip_padding(&mut reader, size)?;
+                entries.push(Entry {
+                    path,
+                    kind: EntryKind::File,
+                    size,
+                    modified,
+                    sha256: Some(sha256),
+                });
+                continue;
+            }
+            b'5' => (EntryKind::Directory, None),
+            b'1' | b'2' => (EntryKind::Link(link), None),
+            // Devices, FIFOs and global pax headers are not listed
+            _ => {
+                std::io::copy(&mut (&mut reader).take(size), &mut std::io::sink())?;
+                skip_padding(&mut reader, size)?;
+                continue;
+            }
+        };
+        entries.push(Entry {
+            path,
+            kind,
+            size: 0,
+            modified,
+            sha256,
+        });
+    }
+    Ok(entries)
+}
+
+fn not_an_archive() -> AppError {
+    AppError::InvalidRequest("file: not a ZIP or tar archive".to_string())
+}
+
+/// Read one 512-byte block; false at the end of the stream
+fn read_block(reader: &mut impl Read, block: &mut [u8; 512]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < block.len() {
+        match reader.read(&mut block[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(not_an_archive()),
+            Ok(n) => filled += n,
+            Err(_) => return Err(not_an_archive()),
+        }
+    }
+    Ok(true)
+}
+
+fn skip_padding(reader: &mut impl Read, size: u64) -> Result<()> {
+    let padding = (512 - size % 512) % 512;
+    std::io::copy(&mut reader.take(padding), &mut std::io::sink())?;
+    Ok(())
+}
+
+/// SHA-256 of the next `size` bytes, which must all be there
+fn checksum(reader: &mut impl Read, size: u64) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let copied = std::io::copy(reader, &mut hasher)
+        .map_err(|e| AppError::ConversionFailed(format!("Failed to read archive: {}", e)))?;
+    if copied != size {
+        return Err(AppError::ConversionFailed(
+            "The archive is truncated".to_string(),
+        ));
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Header checksum: the byte sum with the checksum field read as spaces
+fn header_checksum(header: &[u8; 512]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, b)| if (148..156).contains(&i) { b' ' } else { *b } as u64)
+        .sum()
+}
+
+fn octal(field: &[u8]) -> Option<u64> {
+    let text = c_string(field);
+    let text = text.trim_matches(|c: char| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return None;
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+/// Entry size: octal, or big-endian binary (GNU) for sizes of 8 GiB and up
+fn tar_size(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        let bytes = &field[field.len() - 8..];
+        return Some(bytes.iter().fold(0u64, |n, b| n << 8 | *b as u64));
+    }
+    Some(octal(field).unwrap_or(0))
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Records of a pax extended header: "<length> <key>=<value>\n"
+fn pax_records(data: &[u8]) -> Vec<(String, String)> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .filter_map(|line| {
+            let (_, record) = line.split_once(' ')?;
+            let (key, value) = record.split_once('=')?;
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+fn render_listing(name: &str, archive: &[u8], entries: &[Entry], timezone: Tz) -> String {
+    let files = entries.iter().filter(|e| e.kind == EntryKind::File).count();
+    let total: u64 = entries.iter().map(|e| e.size).sum();
+    let generated = Utc::now()
+        .with_timezone(&timezone)
+        .format("%Y-%m-%d %H:%M %Z");
+
+    let mut rows = String::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let path = match &entry.kind {
+            EntryKind::Link(target) => format!("{} → {}", entry.path, target),
+            _ => entry.path.clone(),
+        };
+        let size = match entry.kind {
+            EntryKind::File => entry.size.to_string(),
+            EntryKind::Directory => "dir".to_string(),
+            EntryKind::Link(_) => "link".to_string(),
+        };
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td class=\"path\">{}</td><td class=\"size\">{}</td><td>{}</td>\
+             <td class=\"hash\">{}</td></tr>\n",
+            i + 1,
+            html_escape(&path),
+            size,
+            html_escape(entry.modified.as_deref().unwrap_or("")),
+            entry.sha256.as_deref().unwrap_or(""),
+        ));
+    }
+    let truncated = if entries.len() >= MAX_ENTRIES {
+        format!(
+            "<p class=\"note\">Only the first {} entries are listed.</p>\n",
+            MAX_ENTRIES
+        )
+    } else {
+        String::new()
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <style>
+        body {{ font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif; margin: 32px; font-size: 10px; }}
+        h1 {{ font-size: 18px; margin: 0 0 12px; word-break: break-all; }}
+        table {{ border-collapse: collapse; width: 100%; }}
+        th, td {{ border: 1px solid #ccc; padding: 2px 6px; text-align: left; vertical-align: top; }}
+        th {{ background: #f4f4f4; }}
+        thead {{ display: table-header-group; }}
+        tr {{ page-break-inside: avoid; }}
+        .summary {{ margin-bottom: 16px; width: auto; }}
+        .path {{ word-break: break-all; }}
+        .size {{ text-align: right; white-space: nowrap; }}
+        .hash {{ font-family: monospace; font-size: 8px; word-break: break-all; width: 210px; }}
+        .note {{ color: #c0392b; }}
+    </style>
+</head>
+<body>
+<h1>{title}</h1>
+<table class="summary">
+<tr><th>Archive</th><td>{title}</td></tr>
+<tr><th>Archive size</th><td>{archive_size}</td></tr>
+<tr><th>Archive SHA-256</th><td class="hash">{archive_sha256}</td></tr>
+<tr><th>Entries</th><td>{count} ({files} files)</td></tr>
+<tr><th>Total size</th><td>{total}</td></tr>
+<tr><th>Listed</th><td>{generated}</td></tr>
+</table>
+{truncated}<table>
+<thead><tr><th>#</th><th>Path</th><th>Size (bytes)</th><th>Modified</th><th>SHA-256</th></tr></thead>
+<tbody>
+{rows}</tbody>
+</table>
+</body>
+</html>"#,
+        title = html_escape(name),
+        archive_size = file_size(archive.len() as u64),
+        archive_sha256 = sha256_hex(archive),
+        count = entries.len(),
+        files = files,
+        total = file_size(total),
+        generated = generated,
+        truncated = truncated,
+        rows = rows,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    /// A ustar header block for `name`
+    fn tar_header(name: &str, typeflag: u8, size: usize) -> [u8; 512] {
+        let mut header = [0u8; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+        // 2021-01-19 10:21:44 UTC
+        header[136..148].copy_from_slice(format!("{:011o}\0", 1_611_051_704).as_bytes());
+        header[156] = typeflag;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        let sum = header_checksum(&header);
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+        header
+    }
+
+    #[test]
+    fn test_read_tar() {
+        let long_name = format!("records/{}.txt", "x".repeat(120));
+        let mut tar = Vec::new();
+        tar.extend(tar_header("records/", b'5', 0));
+        tar.extend(tar_header("././@LongLink", b'L', long_name.len() + 1));
+        tar.extend(format!("{}\0", long_name).as_bytes());
+        tar.resize(tar.len().next_multiple_of(512), 0);
+        tar.extend(tar_header("ignored", b'0', 5));
+        tar.extend(b"hello");
+        tar.resize(tar.len().next_multiple_of(512), 0);
+        tar.extend([0u8; 1024]);
+
+        let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&tar).unwrap();
+        let gz = gz.finish().unwrap();
+
+        let entries = read_tar(&gz, Tz::Europe__Berlin).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].kind, EntryKind::Directory);
+        assert_eq!(entries[1].path, long_name);
+        assert_eq!(entries[1].size, 5);
+        assert_eq!(entries[1].modified.as_deref(), Some("2021-01-19 11:21"));
+        assert_eq!(
+            entries[1].sha256.as_deref(),
+            Some(sha256_hex(b"hello").as_str())
+        );
+
+        let html = render_listing("transfer.tar.gz", &gz, &entries, Tz::UTC);
+        assert!(html.contains("<td>2 (1 files)</td>"));
+        assert!(html.contains(&sha256_hex(&gz)));
+
+        assert!(read_tar(b"not an archive at all", Tz::UTC).is_err());
+        assert!(read_tar(&tar[..700], Tz::UTC).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+//! Finding engine executables: an explicit setting wins, then well-known
+//! install locations (distribution packages, Alpine/ARM layouts, snap and
+//! flatpak exports), then a `$PATH` search like `which`.
+
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Where a tool was found, and everything that was tried on the way
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Located {
+    pub path: String,
+    /// Candidates in the order they were tried, for error messages
+    pub tried: Vec<String>,
+}
+
+impl Located {
+    /// A path configured explicitly, used as-is
+    pub fn configured(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            tried: vec![path.to_string()],
+        }
+    }
+
+    /// The candidates tried, as a sentence fragment for errors
+    pub fn describe_tried(&self) -> String {
+        format!("tried {}", self.tried.join(", "))
+    }
+}
+
+/// Locate a tool: the path in `env_var` if set, else the first executable
+/// of `candidates`, else the first of `names` found on `$PATH`. When
+/// nothing is found the first name is returned, so spawning fails with the
+/// usual "not found" error.
+pub(crate) fn locate(env_var: &str, candidates: &[&str], names: &[&str]) -> Located {
+    if let Ok(path) = std::env::var(env_var) {
+        return Located::configured(&path);
+    }
+    search(candidates, names, std::env::var_os("PATH"))
+}
+
+/// Locate a program named in configuration: a path is used as-is, a bare
+/// name is searched on `$PATH`
+pub(crate) fn locate_program(program: &str) -> Located {
+    if program.contains(std::path::MAIN_SEPARATOR) {
+        return Located::configured(program);
+    }
+    search(&[], &[program], std::env::var_os("PATH"))
+}
+
+fn search(candidates: &[&str], names: &[&str], path_var: Option<OsString>) -> Located {
+    let mut tried = Vec::new();
+    for candidate in candidates {
+        tried.push(candidate.to_string());
+        if is_executable(Path::new(candidate)) {
+            return Located {
+                path: candidate.to_string(),
+                tried,
+            };
+        }
+    }
+
+    let dirs: Vec<_> = path_var
+        .map(|value| std::env::split_paths(&value).collect())
+        .unwrap_or_default();
+    for name in names {
+        tried.push(format!("{} in $PATH", name));
+        for dir in &dirs {
+            let path = dir.join(name);
+            if is_executable(&path) {
+                return Located {
+                    path: path.to_string_lossy().into_owned(),
+                    tried,
+                };
+            }
+        }
+    }
+
+    Located {
+        path: names.first().copied().unwrap_or_default().to_string(),
+        tried,
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let tool = dir.path().join("soffice");
+        std::fs::write(&tool, "#!/bin/sh\n").unwrap();
+        let plain = dir.path().join("libreoffice");
+        std::fs::write(&plain, "not executable").unwrap();
+
+        let candidates = ["/nonexistent/soffice", plain.to_str().unwrap()];
+        let path_var = Some(dir.path().as_os_str().to_os_string());
+
+        // Not executable yet: everything is tried and the bare name returned
+        let missing = search(&candidates, &["soffice"], path_var.clone());
+        assert_eq!(missing.path, "soffice");
+        assert_eq!(
+            missing.tried,
+            vec![
+                "/nonexistent/soffice".to_string(),
+                plain.to_str().unwrap().to_string(),
+                "soffice in $PATH".to_string(),
+            ]
+        );
+
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let found = search(&candidates, &["soffice"], path_var);
+        assert_eq!(found.path, tool.to_str().unwrap());
+    }
+}
@@ -0,0 +1,372 @@
+//! Mail merge: fills the MERGEFIELD fields of a DOCX template from one
+//! record of a CSV or JSON dataset
+
+use super::package::{self, read_entry, xml_escape};
+use super::word::is_story_part;
+use crate::error::{AppError, Result};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// One row of merge data: field name -> value
+pub type Record = HashMap<String, String>;
+
+/// A merge field or legacy form field of a DOCX template
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TemplateField {
+    pub name: String,
+    /// text (merge fields and text form fields), checkbox or choice
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// Entries of dropdown form fields
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+/// Parse a JSON array of objects or a CSV file with a header row
+pub fn parse_records(data: &str) -> Result<Vec<Record>> {
+    let data = data.trim_start_matches('\u{feff}');
+    let records = if data.trim_start().starts_with('[') {
+        parse_json_records(data)?
+    } else {
+        parse_csv_records(data)?
+    };
+
+    if records.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "data: dataset contains no records".to_string(),
+        ));
+    }
+    Ok(records)
+}
+
+fn parse_json_records(data: &str) -> Result<Vec<Record>> {
+    let rows: Vec<serde_json::Map<String, serde_json::Value>> = serde_json::from_str(data)
+        .map_err(|e| {
+            AppError::InvalidRequest(format!("data: expected an array of objects: {}", e))
+        })?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        serde_json::Value::String(s) => s,
+                        serde_json::Value::Null => String::new(),
+                        other => other.to_string(),
+                    };
+                    (key, value)
+                })
+                .collect()
+        })
+        .collect())
+}
+
+fn parse_csv_records(data: &str) -> Result<Vec<Record>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(data.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| AppError::InvalidRequest(format!("data: invalid CSV header: {}", e)))?
+        .clone();
+
+    reader
+        .records()
+        .map(|row| {
+            let row =
+                row.map_err(|e| AppError::InvalidRequest(format!("data: invalid CSV: {}", e)))?;
+            Ok(headers
+                .iter()
+                .zip(row.iter())
+                .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+                .collect())
+        })
+        .collect()
+}
+
+/// Write a copy of the DOCX `template` to `output` with every merge field
+/// replaced by the record's value
+pub fn fill_template(template: &Path, output: &Path, record: &Record) -> Result<()> {
+    let mut archive = package::open(template)?;
+    let names: Vec<String> = archive.file_names().map(String::from).collect();
+
+    // The data source saved with a mail merge main document is not
+    // available here; without it LibreOffice renders a plain document
+    let data_source = Regex::new(r"(?s)<w:mailMerge>.*?</w:mailMerge>").unwrap();
+
+    let mut edits = HashMap::new();
+    for name in names {
+        if is_story_part(&name) {
+            let xml = read_entry(&mut archive, &name)?;
+            edits.insert(name, fill_fields(&xml, record));
+        } else if name == "word/settings.xml" {
+            let xml = read_entry(&mut archive, &name)?;
+            edits.insert(name, data_source.replace(&xml, "").into_owned());
+        }
+    }
+
+    package::rewrite(archive, &edits, output)
+}
+
+/// The fields of a DOCX template, body first and then headers, footers and
+/// notes; a name used several times is listed once
+pub fn template_fields(template: &Path) -> Result<Vec<TemplateField>> {
+    let mut archive = package::open(template)?;
+    let mut names: Vec<String> = archive
+        .file_names()
+        .filter(|name| is_story_part(name))
+        .map(String::from)
+        .collect();
+    names.sort_by_key(|name| (name != "word/document.xml", name.clone()));
+
+    let mut fields: Vec<TemplateField> = Vec::new();
+    for name in names {
+        let xml = read_entry(&mut archive, &name)?;
+        for field in part_fields(&xml) {
+            // Word matches merge field names case-insensitively
+            if !fields
+                .iter()
+                .any(|f| f.name.eq_ignore_ascii_case(&field.name))
+            {
+                fields.push(field);
+            }
+        }
+    }
+    Ok(fields)
+}
+
+/// Fields of one document part, in document order
+fn part_fields(xml: &str) -> Vec<TemplateField> {
+    // Simple fields carry their instruction in an attribute; complex ones
+    // collect it from the instrText runs between begin and end, and legacy
+    // form fields describe themselves in the begin run's ffData
+    let tokens = Regex::new(
+        r#"(?s)<w:fldSimple\s[^>]*?w:instr="([^"]*)"|w:fldCharType="(begin|end)"|<w:instrText(?:\s[^>]*)?>(.*?)</w:instrText>|<w:ffData>(.*?)</w:ffData>"#,
+    )
+    .unwrap();
+
+    let mut fields = Vec::new();
+    // Instruction and form field data of each open complex field
+    let mut open: Vec<(String, Option<String>)> = Vec::new();
+    for c in tokens.captures_iter(xml) {
+        if let Some(instruction) = c.get(1) {
+            fields.extend(merge_field(instruction.as_str()));
+        } else if let Some(char_type) = c.get(2) {
+            if char_type.as_str() == "begin" {
+                open.push((String::new(), None));
+            } else if let Some((instruction, form_data)) = open.pop() {
+                fields.extend(match form_data {
+                    Some(form_data) => form_field(&instruction, &form_data),
+                    None => merge_field(&instruction),
+                });
+            }
+        } else if let Some((instruction, form_data)) = open.last_mut() {
+            match (c.get(3), c.get(4)) {
+                (Some(text), _) => instruction.push_str(text.as_str()),
+                (_, Some(data)) => *form_data = Some(data.as_str().to_string()),
+                _ => {}
+            }
+        }
+    }
+    fields
+}
+
+fn merge_field(instruction: &str) -> Option<TemplateField> {
+    Some(TemplateField {
+        name: merge_field_name(instruction)?,
+        kind: "text",
+        options: Vec::new(),
+    })
+}
+
+/// A FORMTEXT, FORMCHECKBOX or FORMDROPDOWN field from its ffData
+fn form_field(instruction: &str, form_data: &str) -> Option<TemplateField> {
+    let value = |element: &str| {
+        Regex::new(&format!(r#"<w:{}\s[^>]*?w:val="([^"]*)""#, element))
+            .unwrap()
+            .captures_iter(form_data)
+            .map(|c| package::xml_unescape(&c[1]))
+            .collect::<Vec<_>>()
+    };
+    let name = value("name").into_iter().next().filter(|n| !n.is_empty())?;
+    let (kind, options) = match instruction.split_whitespace().next()? {
+        "FORMTEXT" => ("text", Vec::new()),
+        "FORMCHECKBOX" => ("checkbox", Vec::new()),
+        "FORMDROPDOWN" => ("choice", value("listEntry")),
+        _ => return None,
+    };
+    Some(TemplateField {
+        name,
+        kind,
+        options,
+    })
+}
+
+/// Replace simple and complex MERGEFIELD fields in one document part
+fn fill_fields(xml: &str, record: &Record) -> String {
+    let simple =
+        Regex::new(r#"(?s)<w:fldSimple\s[^>]*?w:instr="([^"]*)"[^>]*>(.*?)</w:fldSimple>"#)
+            .unwrap();
+    let xml = simple.replace_all(xml, |c: &regex::Captures| match merge_field_name(&c[1]) {
+        Some(field) => value_run(first_run_properties(&c[2]), lookup(record, &field)),
+        None => c[0].to_string(),
+    });
+
+    // Complex fields span runs: begin, instruction text, separate, the
+    // current result, end. Only top-level fields inside one paragraph are
+    // replaced; nested fields (e.g. a MERGEFIELD inside an IF) stay as is.
+    let runs = Regex::new(r"(?s)<w:r(?:\s[^>]*)?>.*?</w:r>").unwrap();
+    let instr_text = Regex::new(r"(?s)<w:instrText(?:\s[^>]*)?>(.*?)</w:instrText>").unwrap();
+
+    let mut out = String::new();
+    let mut copied = 0;
+    let mut depth = 0;
+    let mut start = 0;
+    let mut instruction = String::new();
+    let mut properties: Option<String> = None;
+    let mut in_result = false;
+
+    for m in runs.find_iter(&xml) {
+        let run = m.as_str();
+        if run.contains(r#"w:fldCharType="begin""#) {
+            depth += 1;
+            if depth == 1 {
+                start = m.start();
+                instruction.clear();
+                properties = first_run_properties(run).map(String::from);
+                in_result = false;
+            }
+        }
+        if depth == 1 {
+            for c in instr_text.captures_iter(run) {
+                instruction.push_str(&c[1]);
+            }
+            if in_result && !run.contains("w:fldChar") {
+                // Keep the formatting of the displayed result
+                if let Some(rpr) = first_run_properties(run) {
+                    properties = Some(rpr.to_string());
+                }
+            }
+            if run.contains(r#"w:fldCharType="separate""#) {
+                in_result = true;
+            }
+        }
+        if run.contains(r#"w:fldCharType="end""#) && depth > 0 {
+            depth -= 1;
+            if depth == 0 {
+                let span = &xml[start..m.end()];
+                if let Some(field) =
+                    merge_field_name(&instruction).filter(|_| !span.contains("</w:p>"))
+                {
+                    out.push_str(&xml[copied..start]);
+                    out.push_str(&value_run(properties.as_deref(), lookup(record, &field)));
+                    copied = m.end();
+                }
+            }
+        }
+    }
+    out.push_str(&xml[copied..]);
+    out
+}
+
+/// Field name of a ` MERGEFIELD  "First Name" \* MERGEFORMAT ` instruction
+fn merge_field_name(instruction: &str) -> Option<String> {
+    let instruction = package::xml_unescape(instruction);
+    let rest = instruction
+        .trim_start()
+        .strip_prefix("MERGEFIELD")?
+        .trim_start();
+    let name = match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next()?,
+        None => rest.split_whitespace().next()?,
+    };
+    Some(name.to_string()).filter(|n| !n.is_empty())
+}
+
+/// Record value for a field, matching names case-insensitively as Word does
+fn lookup<'a>(record: &'a Record, field: &str) -> &'a str {
+    record
+        .get(field)
+        .or_else(|| {
+            record
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(field))
+                .map(|(_, value)| value)
+        })
+        .map(String::as_str)
+        .unwrap_or("")
+}
+
+fn first_run_properties(xml: &str) -> Option<&str> {
+    let start = xml.find("<w:rPr>")?;
+    let end = xml[start..].find("</w:rPr>")? + start + "</w:rPr>".len();
+    Some(&xml[start..end])
+}
+
+/// A run showing `value`, with line breaks kept
+fn value_run(properties: Option<&str>, value: &str) -> String {
+    let text = value
+        .lines()
+        .map(|line| format!("<w:t xml:space=\"preserve\">{}</w:t>", xml_escape(line)))
+        .collect::<Vec<_>>()
+        .join("<w:br/>");
+    format!("<w:r>{}{}</w:r>", properties.unwrap_or(""), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_records() {
+        let csv = "name,city\nAda,London\n\"Grace, Dr.\",Arlington\n";
+        let records = parse_records(csv).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1]["name"], "Grace, Dr.");
+
+        let json = r#"[{"name": "Ada", "age": 36, "title": null}]"#;
+        let records = parse_records(json).unwrap();
+        assert_eq!(records[0]["age"], "36");
+        assert_eq!(records[0]["title"], "");
+
+        assert!(parse_records("name,city\n").is_err());
+    }
+
+    #[test]
+    fn test_part_fields() {
+        let xml = concat!(
+            r#"<w:p><w:fldSimple w:instr=" MERGEFIELD &quot;First Name&quot; \* MERGEFORMAT "><w:r><w:t>«First Name»</w:t></w:r></w:fldSimple></w:p>"#,
+            r#"<w:p><w:r><w:fldChar w:fldCharType="begin"/></w:r><w:r><w:instrText> MERGE</w:instrText></w:r><w:r><w:instrText>FIELD city </w:instrText></w:r>"#,
+            r#"<w:r><w:fldChar w:fldCharType="separate"/></w:r><w:r><w:t>«city»</w:t></w:r><w:r><w:fldChar w:fldCharType="end"/></w:r></w:p>"#,
+            r#"<w:p><w:r><w:fldChar w:fldCharType="begin"><w:ffData><w:name w:val="Size"/><w:ddList><w:listEntry w:val="S"/><w:listEntry w:val="M"/></w:ddList></w:ffData></w:fldChar></w:r>"#,
+            r#"<w:r><w:instrText> FORMDROPDOWN </w:instrText></w:r><w:r><w:fldChar w:fldCharType="end"/></w:r></w:p>"#,
+        );
+        let fields = part_fields(xml);
+        let names: Vec<(&str, &str)> = fields.iter().map(|f| (f.name.as_str(), f.kind)).collect();
+        assert_eq!(
+            names,
+            vec![("First Name", "text"), ("city", "text"), ("Size", "choice")]
+        );
+        assert_eq!(fields[2].options, vec!["S", "M"]);
+    }
+
+    #[test]
+    fn test_fill_fields() {
+        let record: Record = [("First Name".to_string(), "Ada & co".to_string())].into();
+        let xml = concat!(
+            r#"<w:p><w:fldSimple w:instr=" MERGEFIELD &quot;First Name&quot; \* MERGEFORMAT "><w:r><w:rPr><w:b/></w:rPr><w:t>«First Name»</w:t></w:r></w:fldSimple></w:p>"#,
+            r#"<w:p><w:r><w:fldChar w:fldCharType="begin"/></w:r><w:r><w:instrText xml:space="preserve"> MERGEFIELD city </w:instrText></w:r>"#,
+            r#"<w:r><w:fldChar w:fldCharType="separate"/></w:r><w:r><w:rPr><w:i/></w:rPr><w:t>«city»</w:t></w:r><w:r><w:fldChar w:fldCharType="end"/></w:r></w:p>"#,
+        );
+        assert_eq!(
+            fill_fields(xml, &record),
+            concat!(
+                r#"<w:p><w:r><w:rPr><w:b/></w:rPr><w:t xml:space="preserve">Ada &amp; co</w:t></w:r></w:p>"#,
+                r#"<w:p><w:r><w:rPr><w:i/></w:rPr></w:r></w:p>"#,
+            )
+        );
+    }
+}
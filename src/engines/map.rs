@@ -0,0 +1,603 @@
+//! GeoJSON and KML map pages rendered by the Chromium engine.
+//!
+//! Features are projected with Web Mercator into an inline SVG, drawn over
+//! tiles from `PDFMILL_MAP_TILES` when a tile server is configured, and
+//! listed in a legend below the map.
+
+use super::chromium::html_escape;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+/// Size of the map in SVG units (CSS pixels at 100%)
+const MAP_WIDTH: f64 = 1000.0;
+const MAP_HEIGHT: f64 = 700.0;
+
+/// Share of the map left around the features
+const PADDING: f64 = 0.08;
+
+/// Extent (in world units) shown around a lone point, about a city block
+const MIN_EXTENT: f64 = 0.00002;
+
+/// Positions a file may contain, bounding render time and page size
+const MAX_POSITIONS: usize = 200_000;
+
+/// Tiles drawn per map; the zoom level is lowered until they fit
+const MAX_TILES: usize = 64;
+
+const MAX_ZOOM: i32 = 19;
+
+/// Legend rows before the rest are summarised
+const MAX_LEGEND: usize = 24;
+
+/// Colors of features without one of their own
+const PALETTE: &[&str] = &[
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#17becf",
+    "#7f7f7f", "#bcbd22",
+];
+
+/// Latitude limit of Web Mercator
+const MAX_LATITUDE: f64 = 85.051_128_78;
+
+/// Tile server drawn under the features
+#[derive(Debug, Clone)]
+pub struct Basemap {
+    /// URL template with `{z}`, `{x}` and `{y}`
+    tiles: String,
+    /// Credit printed below the map, as tile licenses usually require
+    attribution: Option<String>,
+}
+
+static BASEMAP: OnceLock<Basemap> = OnceLock::new();
+
+impl Basemap {
+    /// Configured by `PDFMILL_MAP_TILES`, e.g.
+    /// "http://tiles.internal/{z}/{x}/{y}.png", and the optional
+    /// `PDFMILL_MAP_ATTRIBUTION`; maps have a plain background without it
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Some(tiles) = std::env::var("PDFMILL_MAP_TILES")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+        else {
+            return Ok(None);
+        };
+        if !tiles.starts_with("http://") && !tiles.starts_with("https://") {
+            return Err(format!(
+                "PDFMILL_MAP_TILES: expected an http(s) URL, got '{}'",
+                tiles
+            ));
+        }
+        if ["{z}", "{x}", "{y}"].iter().any(|p| !tiles.contains(p)) {
+            return Err(format!(
+                "PDFMILL_MAP_TILES: expected {{z}}, {{x}} and {{y}} placeholders, got '{}'",
+                tiles
+            ));
+        }
+        let attribution = std::env::var("PDFMILL_MAP_ATTRIBUTION")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        Ok(Some(Self { tiles, attribution }))
+    }
+
+    /// Make this the basemap of every map page
+    pub fn activate(self) {
+        let _ = BASEMAP.set(self);
+    }
+
+    fn tile_url(&self, z: i32, x: i64, y: i64) -> String {
+        self.tiles
+            .replace("{z}", &z.to_string())
+            .replace("{x}", &x.to_string())
+            .replace("{y}", &y.to_string())
+    }
+}
+
+/// Longitude and latitude
+type Position = (f64, f64);
+
+enum Shape {
+    Point(Position),
+    Line(Vec<Position>),
+    /// Outer ring followed by holes
+    Polygon(Vec<Vec<Position>>),
+}
+
+struct Feature {
+    label: String,
+    /// `#rgb` or `#rrggbb` from the file
+    color: Option<String>,
+    shapes: Vec<Shape>,
+}
+
+/// Render a GeoJSON document as an HTML map page
+pub fn geojson_to_html(src: &str, title: &str) -> Result<String, String> {
+    let value: Value =
+        serde_json::from_str(src).map_err(|e| format!("not valid GeoJSON: {}", e))?;
+    let mut features = Vec::new();
+    read_geojson(&value, &mut features)?;
+    render(&features, title, BASEMAP.get())
+}
+
+/// Render the placemarks of a KML document as an HTML map page
+pub fn kml_to_html(src: &str, title: &str) -> Result<String, String> {
+    render(&read_kml(src)?, title, BASEMAP.get())
+}
+
+fn read_geojson(value: &Value, out: &mut Vec<Feature>) -> Result<(), String> {
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            let features = value["features"]
+                .as_array()
+                .ok_or("FeatureCollection without a features array")?;
+            for feature in features {
+                read_geojson(feature, out)?;
+            }
+        }
+        Some("Feature") => {
+            let properties = &value["properties"];
+            let text = |key: &str| properties[key].as_str().map(str::to_string);
+            let label = text("name")
+                .or_else(|| text("title"))
+                .unwrap_or_else(|| format!("Feature {}", out.len() + 1));
+            let color = ["stroke", "marker-color", "fill"]
+                .iter()
+                .filter_map(|key| text(key))
+                .find(|color| is_hex_color(color));
+            let shapes = if value["geometry"].is_null() {
+                Vec::new()
+            } else {
+                geometry(&value["geometry"])?
+            };
+            out.push(Feature {
+                label,
+                color,
+                shapes,
+            });
+        }
+        Some(kind) => out.push(Feature {
+            label: kind.to_string(),
+            color: None,
+            shapes: geometry(value)?,
+        }),
+        None => return Err("GeoJSON object without a type".to_string()),
+    }
+    Ok(())
+}
+
+fn geometry(value: &Value) -> Result<Vec<Shape>, String> {
+    let coordinates = &value["coordinates"];
+    let positions = |value: &Value| -> Result<Vec<Position>, String> {
+        array(value)?.iter().map(position).collect()
+    };
+    let rings = |value: &Value| -> Result<Vec<Vec<Position>>, String> {
+        array(value)?.iter().map(positions).collect()
+    };
+
+    Ok(match value["type"].as_str() {
+        Some("Point") => vec![Shape::Point(position(coordinates)?)],
+        Some("MultiPoint") => positions(coordinates)?
+            .into_iter()
+            .map(Shape::Point)
+            .collect(),
+        Some("LineString") => vec![Shape::Line(positions(coordinates)?)],
+        Some("MultiLineString") => rings(coordinates)?.into_iter().map(Shape::Line).collect(),
+        Some("Polygon") => vec![Shape::Polygon(rings(coordinates)?)],
+        Some("MultiPolygon") => array(coordinates)?
+            .iter()
+            .map(|polygon| rings(polygon).map(Shape::Polygon))
+            .collect::<Result<_, _>>()?,
+        Some("GeometryCollection") => {
+            let mut shapes = Vec::new();
+            for member in value["geometries"].as_array().into_iter().flatten() {
+                shapes.extend(geometry(member)?);
+            }
+            shapes
+        }
+        other => {
+            return Err(format!(
+                "unsupported geometry type {:?}",
+                other.unwrap_or("")
+            ))
+        }
+    })
+}
+
+fn array(value: &Value) -> Result<&Vec<Value>, String> {
+    value
+        .as_array()
+        .ok_or_else(|| "coordinates must be arrays".to_string())
+}
+
+fn position(value: &Value) -> Result<Position, String> {
+    let numbers: Vec<f64> = value
+        .as_array()
+        .map(|a| a.iter().filter_map(Value::as_f64).collect())
+        .unwrap_or_default();
+    match numbers[..] {
+        [lon, lat, ..] => checked((lon, lat)),
+        _ => Err(format!("expected [longitude, latitude], got {}", value)),
+    }
+}
+
+fn checked((lon, lat): Position) -> Result<Position, String> {
+    if (-180.0..=180.0).contains(&lon) && (-90.0..=90.0).contains(&lat) {
+        Ok((lon, lat))
+    } else {
+        Err(format!("coordinates out of range: {}, {}", lon, lat))
+    }
+}
+
+fn read_kml(src: &str) -> Result<Vec<Feature>, String> {
+    let placemark = Regex::new(r"(?s)<Placemark\b.*?</Placemark>").unwrap();
+    let name = Regex::new(r"(?s)<name>(.*?)</name>").unwrap();
+    let polygon = Regex::new(r"(?s)<Polygon\b.*?</Polygon>").unwrap();
+    let coordinates = Regex::new(r"(?s)<coordinates>(.*?)</coordinates>").unwrap();
+    let simple =
+        Regex::new(r"(?s)<(Point|LineString)\b.*?<coordinates>(.*?)</coordinates>").unwrap();
+
+    let mut features = Vec::new();
+    for block in placemark.find_iter(src).map(|m| m.as_str()) {
+        let label = name
+            .captures(block)
+            .map(|c| xml_text(&c[1]))
+            .filter(|label| !label.is_empty())
+            .unwrap_or_else(|| format!("Placemark {}", features.len() + 1));
+
+        let mut shapes = Vec::new();
+        for found in polygon.find_iter(block) {
+            let rings = coordinates
+                .captures_iter(found.as_str())
+                .map(|c| kml_positions(&c[1]))
+                .collect::<Result<_, _>>()?;
+            shapes.push(Shape::Polygon(rings));
+        }
+        let rest = polygon.replace_all(block, "");
+        for c in simple.captures_iter(&rest) {
+            let positions = kml_positions(&c[2])?;
+            match (&c[1], positions.first()) {
+                ("Point", Some(&first)) => shapes.push(Shape::Point(first)),
+                ("LineString", _) => shapes.push(Shape::Line(positions)),
+                _ => {}
+            }
+        }
+        features.push(Feature {
+            label,
+            color: None,
+            shapes,
+        });
+    }
+    if features.is_empty() {
+        return Err("KML document without placemarks".to_string());
+    }
+    Ok(features)
+}
+
+/// "lon,lat[,alt] lon,lat[,alt] ..."
+fn kml_positions(text: &str) -> Result<Vec<Position>, String> {
+    text.split_whitespace()
+        .map(|tuple| {
+            let mut parts = tuple.split(',').map(|p| p.parse::<f64>());
+            match (parts.next(), parts.next()) {
+                (Some(Ok(lon)), Some(Ok(lat))) => checked((lon, lat)),
+                _ => Err(format!("invalid KML coordinates '{}'", tuple)),
+            }
+        })
+        .collect()
+}
+
+/// Text content of a KML element: CDATA unwrapped, entities decoded
+fn xml_text(raw: &str) -> String {
+    let raw = raw.trim();
+    if let Some(data) = raw
+        .strip_prefix("<![CDATA[")
+        .and_then(|r| r.strip_suffix("]]>"))
+    {
+        return data.trim().to_string();
+    }
+    raw.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Web Mercator position in world units: 0..1 from west to east and from
+/// north to south
+fn project((lon, lat): Position) -> (f64, f64) {
+    let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    let x = (lon + 180.0) / 360.0;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / std::f64::consts::PI) / 2.0;
+    (x, y)
+}
+
+/// Maps world units to SVG units
+struct View {
+    center: (f64, f64),
+    /// SVG units per world unit
+    scale: f64,
+}
+
+impl View {
+    fn fit(points: &[(f64, f64)]) -> Self {
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (1.0f64, 1.0f64, 0.0f64, 0.0f64);
+        for &(x, y) in points {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        let width = (max_x - min_x).max(MIN_EXTENT);
+        let height = (max_y - min_y).max(MIN_EXTENT);
+        let usable = 1.0 - 2.0 * PADDING;
+        Self {
+            center: ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0),
+            scale: (MAP_WIDTH * usable / width).min(MAP_HEIGHT * usable / height),
+        }
+    }
+
+    fn to_svg(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            (x - self.center.0) * self.scale + MAP_WIDTH / 2.0,
+            (y - self.center.1) * self.scale + MAP_HEIGHT / 2.0,
+        )
+    }
+
+    /// Tiles covering the map at the most detailed zoom level that needs
+    /// no more than `MAX_TILES` of them
+    fn tiles(&self, basemap: &Basemap) -> String {
+        let left = self.center.0 - MAP_WIDTH / 2.0 / self.scale;
+        let top = self.center.1 - MAP_HEIGHT / 2.0 / self.scale;
+        let right = self.center.0 + MAP_WIDTH / 2.0 / self.scale;
+        let bottom = self.center.1 + MAP_HEIGHT / 2.0 / self.scale;
+
+        let mut zoom = ((self.scale / 256.0).log2().ceil() as i32).clamp(0, MAX_ZOOM);
+        let range = |zoom: i32| {
+            let n = 2f64.powi(zoom);
+            let xs = (left * n).floor() as i64..=(right * n).floor() as i64;
+            let ys =
+                ((top * n).floor() as i64).max(0)..=((bottom * n).floor() as i64).min(n as i64 - 1);
+            (n, xs, ys)
+        };
+        let (mut n, mut xs, mut ys) = range(zoom);
+        while zoom > 0 && xs.clone().count() * ys.clone().count() > MAX_TILES {
+            zoom -= 1;
+            (n, xs, ys) = range(zoom);
+        }
+
+        let size = self.scale / n;
+        let mut svg = String::new();
+        for y in ys {
+            for x in xs.clone() {
+                let (left, top) = self.to_svg((x as f64 / n, y as f64 / n));
+                // Longitudes past the antimeridian wrap to the other side
+                let url = basemap.tile_url(zoom, x.rem_euclid(n as i64), y);
+                svg.push_str(&format!(
+                    "<image href=\"{}\" x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\"/>\n",
+                    html_escape(&url),
+                    left,
+                    top,
+                    size + 0.5,
+                    size + 0.5
+                ));
+            }
+        }
+        svg
+    }
+}
+
+/// How a legend row is drawn
+#[derive(Clone, Copy, PartialEq)]
+enum Symbol {
+    Point,
+    Line,
+    Area,
+}
+
+fn render(features: &[Feature], title: &str, basemap: Option<&Basemap>) -> Result<String, String> {
+    let mut points = Vec::new();
+    for shape in features.iter().flat_map(|f| &f.shapes) {
+        match shape {
+            Shape::Point(p) => points.push(project(*p)),
+            Shape::Line(line) => points.extend(line.iter().copied().map(project)),
+            Shape::Polygon(rings) => {
+                points.extend(rings.iter().flatten().copied().map(project));
+            }
+        }
+        if points.len() > MAX_POSITIONS {
+            return Err(format!("more than {} coordinates", MAX_POSITIONS));
+        }
+    }
+    if points.is_empty() {
+        return Err("no features with coordinates".to_string());
+    }
+    let view = View::fit(&points);
+
+    // Features sharing a label share a color and a legend row
+    let mut colors: BTreeMap<&str, String> = BTreeMap::new();
+    let mut legend: Vec<(&str, String, Symbol)> = Vec::new();
+    let mut shapes_svg = String::new();
+    let mut points_svg = String::new();
+    for feature in features.iter().filter(|f| !f.shapes.is_empty()) {
+        let next = PALETTE[colors.len() % PALETTE.len()];
+        let color = colors
+            .entry(&feature.label)
+            .or_insert_with(|| feature.color.clone().unwrap_or_else(|| next.to_string()))
+            .clone();
+        let symbol = match feature.shapes[0] {
+            Shape::Point(_) => Symbol::Point,
+            Shape::Line(_) => Symbol::Line,
+            Shape::Polygon(_) => Symbol::Area,
+        };
+        if !legend.iter().any(|(label, _, _)| *label == feature.label) {
+            legend.push((&feature.label, color.clone(), symbol));
+        }
+
+        let path = |positions: &[Position]| {
+            positions
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    let (x, y) = view.to_svg(project(*p));
+                    format!("{}{:.1},{:.1}", if i == 0 { "M" } else { "L" }, x, y)
+                })
+                .collect::<String>()
+        };
+        for shape in &feature.shapes {
+            match shape {
+                Shape::Point(p) => {
+                    let (x, y) = view.to_svg(project(*p));
+                    points_svg.push_str(&format!(
+                        "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"6\" fill=\"{}\"/>\n",
+                        x, y, color
+                    ));
+                }
+                Shape::Line(line) => shapes_svg.push_str(&format!(
+                    "<path d=\"{}\" class=\"line\" stroke=\"{}\"/>\n",
+                    path(line),
+                    color
+                )),
+                Shape::Polygon(rings) => shapes_svg.push_str(&format!(
+                    "<path d=\"{}\" class=\"area\" stroke=\"{c}\" fill=\"{c}\"/>\n",
+                    rings
+                        .iter()
+                        .map(|ring| path(ring) + "Z")
+                        .collect::<String>(),
+                    c = color
+                )),
+            }
+        }
+    }
+
+    let tiles = basemap.map(|b| view.tiles(b)).unwrap_or_default();
+    let mut rows = String::new();
+    for (label, color, symbol) in legend.iter().take(MAX_LEGEND) {
+        let swatch = match symbol {
+            Symbol::Point => format!("<circle cx=\"9\" cy=\"9\" r=\"6\" fill=\"{}\"/>", color),
+            Symbol::Line => format!(
+                "<path d=\"M1,9L17,9\" class=\"line\" stroke=\"{}\"/>",
+                color
+            ),
+            Symbol::Area => format!(
+                "<rect x=\"2\" y=\"3\" width=\"14\" height=\"12\" class=\"area\" stroke=\"{c}\" fill=\"{c}\"/>",
+                c = color
+            ),
+        };
+        rows.push_str(&format!(
+            "<li><svg width=\"18\" height=\"18\">{}</svg>{}</li>\n",
+            swatch,
+            html_escape(label)
+        ));
+    }
+    if legend.len() > MAX_LEGEND {
+        rows.push_str(&format!(
+            "<li>and {} more</li>\n",
+            legend.len() - MAX_LEGEND
+        ));
+    }
+    let credit = basemap
+        .and_then(|b| b.attribution.as_deref())
+        .map(|a| format!("<p class=\"credit\">{}</p>\n", html_escape(a)))
+        .unwrap_or_default();
+
+    Ok(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <style>
+        body {{ font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif; margin: 24px; }}
+        h1 {{ font-size: 20px; margin: 0 0 12px; }}
+        .map {{ width: 100%; border: 1px solid #999; background: #eef3f7; }}
+        .line {{ fill: none; stroke-width: 3; stroke-linejoin: round; stroke-linecap: round; }}
+        .area {{ fill-opacity: 0.25; fill-rule: evenodd; stroke-width: 2; }}
+        circle {{ stroke: #fff; stroke-width: 2; }}
+        .legend {{ list-style: none; padding: 0; columns: 2; font-size: 13px; }}
+        .legend li {{ display: flex; align-items: center; gap: 8px; margin: 4px 0; break-inside: avoid; }}
+        .credit {{ font-size: 10px; color: #666; text-align: right; margin: 4px 0; }}
+    </style>
+</head>
+<body>
+<h1>{title}</h1>
+<svg class="map" viewBox="0 0 {width} {height}" preserveAspectRatio="xMidYMid slice">
+{tiles}{shapes}{points}</svg>
+{credit}<ul class="legend">
+{rows}</ul>
+</body>
+</html>"#,
+        title = html_escape(title),
+        width = MAP_WIDTH,
+        height = MAP_HEIGHT,
+        tiles = tiles,
+        shapes = shapes_svg,
+        points = points_svg,
+        credit = credit,
+        rows = rows,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_geojson_to_html() {
+        let src = r##"{
+            "type": "FeatureCollection",
+            "features": [
+                {"type": "Feature", "properties": {"name": "Depot", "marker-color": "#00f"},
+                 "geometry": {"type": "Point", "coordinates": [13.40, 52.52]}},
+                {"type": "Feature", "properties": {"name": "Route <A>"},
+                 "geometry": {"type": "LineString", "coordinates": [[13.40, 52.52], [13.45, 52.50]]}},
+                {"type": "Feature", "properties": {},
+                 "geometry": {"type": "Polygon", "coordinates": [[[13.41, 52.51], [13.43, 52.51], [13.42, 52.53], [13.41, 52.51]]]}}
+            ]
+        }"##;
+        let html = geojson_to_html(src, "Site survey").unwrap();
+        assert!(html.contains("<h1>Site survey</h1>"));
+        assert!(html.contains("fill=\"#00f\""));
+        assert!(html.contains("Route &lt;A&gt;</li>"));
+        assert!(html.contains("Feature 3</li>"));
+        assert!(html.contains("class=\"area\""));
+        assert!(!html.contains("<image"));
+
+        assert!(geojson_to_html(r#"{"type": "Point", "coordinates": [200, 0]}"#, "x").is_err());
+        assert!(geojson_to_html(r#"{"type": "FeatureCollection", "features": []}"#, "x").is_err());
+
+        let basemap = Basemap {
+            tiles: "http://tiles.test/{z}/{x}/{y}.png".to_string(),
+            attribution: Some("© Map contributors".to_string()),
+        };
+        let mut features = Vec::new();
+        read_geojson(&serde_json::from_str(src).unwrap(), &mut features).unwrap();
+        let html = render(&features, "Site survey", Some(&basemap)).unwrap();
+        let tiles = html.matches("<image href=\"http://tiles.test/").count();
+        assert!((1..=MAX_TILES).contains(&tiles), "{}", tiles);
+        assert!(html.contains("© Map contributors"));
+    }
+
+    #[test]
+    fn test_kml_to_html() {
+        let src = r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2"><Document>
+  <Placemark><name><![CDATA[Hydrant & valve]]></name>
+    <Point><coordinates>-0.1276,51.5072,0</coordinates></Point></Placemark>
+  <Placemark><name>Plot 7</name><Polygon><outerBoundaryIs><LinearRing><coordinates>
+    -0.128,51.507 -0.127,51.507 -0.127,51.508 -0.128,51.507
+  </coordinates></LinearRing></outerBoundaryIs></Polygon></Placemark>
+</Document></kml>"#;
+        let html = kml_to_html(src, "Inspection").unwrap();
+        assert!(html.contains("Hydrant &amp; valve</li>"));
+        assert!(html.contains("Plot 7</li>"));
+        assert_eq!(html.matches("<circle cx").count(), 2);
+        assert!(kml_to_html("<kml></kml>", "x").is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! TeX math in Markdown: `$...$` and `$$...$$` are cut out of the text
+//! before it becomes HTML and typeset in the page by KaTeX, loaded from the
+//! local library directory so renders need no network. Without KaTeX the
+//! TeX source is printed and the failed load is reported as a warning.
+
+use super::chromium::html_escape;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// Where KaTeX is installed (`katex.min.js`, `katex.min.css` and its
+/// `fonts` directory), `PDFMILL_KATEX_DIR` or the directory the Docker
+/// image fills
+static KATEX_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    std::env::var_os("PDFMILL_KATEX_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/usr/share/pdfmill/katex"))
+});
+
+/// Directory KaTeX is loaded from, which rendered pages may read
+pub fn katex_dir() -> &'static Path {
+    &KATEX_DIR
+}
+
+/// Delimiter of display math, alone on its lines or around a formula
+pub const DISPLAY_DELIMITER: &str = "$$";
+
+/// Replace the math spans of a line of text with elements KaTeX typesets.
+/// As in Pandoc, an opening `$` is not followed by a space and a closing
+/// one neither follows a space nor precedes a digit, so prices stay text;
+/// `\$` is a literal dollar and code spans are left alone.
+pub fn inline_math(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find(['$', '`', '\\']) {
+        html.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if let Some(escaped) = rest.strip_prefix("\\$") {
+            html.push('$');
+            rest = escaped;
+        } else if rest.starts_with('\\') {
+            html.push('\\');
+            rest = &rest[1..];
+        } else if rest.starts_with('`') {
+            let end = rest[1..].find('`').map_or(rest.len(), |j| j + 2);
+            html.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if let Some((tex, after)) = math_span(rest) {
+            let (class, delimiter) = match tex.starts_with(DISPLAY_DELIMITER) {
+                true => ("math display", 2),
+                false => ("math", 1),
+            };
+            html.push_str(&format!(
+                "<span class=\"{}\">{}</span>",
+                class,
+                html_escape(&tex[delimiter..tex.len() - delimiter])
+            ));
+            rest = after;
+        } else {
+            html.push('$');
+            rest = &rest[1..];
+        }
+    }
+    html.push_str(rest);
+    html
+}
+
+/// The math span starting `text` with its delimiters, and what follows it
+fn math_span(text: &str) -> Option<(&str, &str)> {
+    let delimiter = if text.starts_with(DISPLAY_DELIMITER) {
+        2
+    } else {
+        1
+    };
+    let body = &text[delimiter..];
+    if body.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '$' if i > 0 && body[i..].starts_with(&"$$"[..delimiter]) => {
+                let after = &body[i + delimiter..];
+                let closes = !body[..i].ends_with(char::is_whitespace)
+                    && !after.starts_with(|c: char| c.is_ascii_digit());
+                if closes {
+                    let end = delimiter + i + delimiter;
+                    return Some((&text[..end], &text[end..]));
+                }
+                // Inline math does not span a `$$`
+                if delimiter == 1 || body[i..].starts_with(DISPLAY_DELIMITER) {
+                    return None;
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A display formula from the lines between `$$` delimiters
+pub fn display_math(lines: &[&str]) -> String {
+    format!(
+        "<div class=\"math display\">{}</div>\n",
+        html_escape(&lines.join("\n"))
+    )
+}
+
+/// Whether the HTML has math for [`typeset_html`] to render
+pub fn has_math(html: &str) -> bool {
+    html.contains("class=\"math")
+}
+
+/// KaTeX and the script typesetting the document's math, to end its body.
+/// The page is printed once the formulas and their fonts are ready.
+pub fn typeset_html() -> String {
+    typeset_html_from(&KATEX_DIR)
+}
+
+fn typeset_html_from(dir: &Path) -> String {
+    format!(
+        r#"<link rel="stylesheet" href="file://{css}">
+<script src="file://{js}"></script>
+<script>
+window.__pdfmillReady = (async () => {{
+    if (typeof katex === 'undefined') return;
+    for (const el of document.querySelectorAll('.math')) {{
+        katex.render(el.textContent, el, {{
+            displayMode: el.classList.contains('display'),
+            throwOnError: false,
+        }});
+    }}
+    await document.fonts.ready;
+}})();
+</script>
+"#,
+        css = dir.join("katex.min.css").display(),
+        js = dir.join("katex.min.js").display(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_math() {
+        assert_eq!(
+            inline_math("Euler: $e^{i\\pi} + 1 = 0$, so $a<b$."),
+            "Euler: <span class=\"math\">e^{i\\pi} + 1 = 0</span>, so \
+             <span class=\"math\">a&lt;b</span>."
+        );
+        assert_eq!(
+            inline_math("$$\\sum_{k=1}^n k$$ done"),
+            "<span class=\"math display\">\\sum_{k=1}^n k</span> done"
+        );
+        // Prices, escaped dollars and code spans stay text
+        assert_eq!(
+            inline_math("costs $5 or $10 today"),
+            "costs $5 or $10 today"
+        );
+        assert_eq!(inline_math("from $20$30"), "from $20$30");
+        assert_eq!(inline_math("\\$x\\$ and `echo $x$`"), "$x$ and `echo $x$`");
+        assert_eq!(inline_math("$ x$ and $$"), "$ x$ and $$");
+        assert_eq!(inline_math("a \\\\ b"), "a \\\\ b");
+    }
+
+    #[test]
+    fn test_typeset_html() {
+        let html = typeset_html_from(Path::new("/opt/katex"));
+        assert!(html.contains("href=\"file:///opt/katex/katex.min.css\""));
+        assert!(html.contains("src=\"file:///opt/katex/katex.min.js\""));
+        assert!(html.contains("window.__pdfmillReady"));
+    }
+}
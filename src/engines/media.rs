@@ -0,0 +1,552 @@
+//! Audio and video files, which have no printable content of their own,
+//! become a one-page tech sheet: container, duration, streams and tags
+//! from `ffprobe`, and for video a strip of frames taken by `ffmpeg` at
+//! even intervals. Archivists file these sheets with media carriers.
+
+use super::chromium::html_escape;
+use super::locate::{locate, Located};
+use super::{
+    file_size, print_timezone, probe_version, tool_failed, ChromiumEngine, ConvertEngine,
+    ConvertOptions, ConvertResult, EngineType,
+};
+use crate::error::{AppError, Result};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde_json::Value;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+const SUPPORTED_EXTENSIONS: &[&str] = &[
+    // Audio
+    "mp3", "wav", "flac", "ogg", "oga", "opus", "m4a", "aac", "wma", "aif", "aiff",
+    // Video
+    "mp4", "m4v", "mov", "mkv", "webm", "avi", "wmv", "mpg", "mpeg", "ts", "mts", "m2ts", "3gp",
+    "flv", "dv", "mxf",
+];
+
+/// Frames taken from a video, spread evenly over its duration
+const THUMBNAILS: usize = 6;
+
+/// Width of a frame on the sheet, in pixels
+const THUMBNAIL_WIDTH: u32 = 320;
+
+/// Container tags printed on the sheet, with their labels
+const TAGS: &[(&str, &str)] = &[
+    ("title", "Title"),
+    ("artist", "Artist"),
+    ("album", "Album"),
+    ("date", "Date"),
+    ("comment", "Comment"),
+    ("encoder", "Encoder"),
+];
+
+/// Media engine: describes the file with ffprobe and prints the sheet via
+/// Chromium
+pub struct MediaEngine {
+    chromium: Arc<ChromiumEngine>,
+}
+
+/// What ffprobe reports about a file
+struct MediaInfo {
+    format: String,
+    duration: Option<f64>,
+    size: Option<u64>,
+    bit_rate: Option<u64>,
+    /// Creation time in UTC, from the container
+    created: Option<DateTime<Utc>>,
+    tags: Vec<(&'static str, String)>,
+    streams: Vec<StreamInfo>,
+}
+
+struct StreamInfo {
+    kind: String,
+    codec: String,
+    details: Vec<String>,
+    language: Option<String>,
+}
+
+/// A frame of the video and where it was taken
+struct Thumbnail {
+    at: f64,
+    jpeg: Vec<u8>,
+}
+
+impl MediaEngine {
+    pub fn new(chromium: Arc<ChromiumEngine>) -> Self {
+        Self { chromium }
+    }
+
+    /// ffprobe executable: `FFPROBE_PATH` or `$PATH`
+    fn locate_ffprobe(&self) -> Located {
+        locate("FFPROBE_PATH", &[], &["ffprobe"])
+    }
+
+    /// ffmpeg executable: `FFMPEG_PATH` or `$PATH`
+    fn locate_ffmpeg(&self) -> Located {
+        locate("FFMPEG_PATH", &[], &["ffmpeg"])
+    }
+
+    async fn probe(&self, input_path: &Path) -> Result<MediaInfo> {
+        let ffprobe = self.locate_ffprobe();
+        let output = Command::new(&ffprobe.path)
+            .args([
+                "-v",
+                "error",
+                "-print_format",
+                "json",
+                "-show_format",
+                "-show_streams",
+            ])
+            .arg(input_path)
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|e| {
+                AppError::EngineNotAvailable(format!(
+                    "ffprobe not found ({}): {}",
+                    ffprobe.describe_tried(),
+                    e
+                ))
+            })?;
+
+        if !output.status.success() {
+            return Err(tool_failed("ffprobe", &output));
+        }
+        let report: Value = serde_json::from_slice(&output.stdout)
+            .map_err(|e| AppError::ConversionFailed(format!("Unreadable ffprobe output: {}", e)))?;
+        parse_probe(&report)
+    }
+
+    /// Take a frame at `at` seconds, scaled to the sheet's frame width
+    async fn thumbnail(&self, input_path: &Path, output_path: &Path, at: f64) -> Result<Vec<u8>> {
+        let ffmpeg = self.locate_ffmpeg();
+        let output = Command::new(&ffmpeg.path)
+            .args(["-v", "error", "-y", "-ss", &format!("{:.3}", at), "-i"])
+            .arg(input_path)
+            .args([
+                "-frames:v",
+                "1",
+                "-vf",
+                &format!("scale={}:-2", THUMBNAIL_WIDTH),
+            ])
+            .arg(output_path)
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|e| {
+                AppError::EngineNotAvailable(format!(
+                    "ffmpeg not found ({}): {}",
+                    ffmpeg.describe_tried(),
+                    e
+                ))
+            })?;
+
+        if !output.status.success() {
+            return Err(tool_failed("ffmpeg", &output));
+        }
+        Ok(tokio::fs::read(output_path).await?)
+    }
+}
+
+#[async_trait]
+impl ConvertEngine for MediaEngine {
+    fn engine_type(&self) -> EngineType {
+        EngineType::Media
+    }
+
+    fn supports_extension(&self, ext: &str) -> bool {
+        SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+    }
+
+    fn supported_extensions(&self) -> Vec<&'static str> {
+        SUPPORTED_EXTENSIONS.to_vec()
+    }
+
+    async fn is_available(&self) -> bool {
+        for program in [self.locate_ffprobe().path, self.locate_ffmpeg().path] {
+            let found = Command::new(program)
+                .arg("-version")
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .await
+                .map(|s| s.success())
+                .unwrap_or(false);
+            if !found {
+                return false;
+            }
+        }
+        self.chromium.is_available().await
+    }
+
+    async fn version(&self) -> Option<String> {
+        // "ffmpeg version 6.1.1-3ubuntu5 Copyright ..."
+        probe_version(&self.locate_ffmpeg().path, "-version").await
+    }
+
+    fn searched_paths(&self) -> Vec<String> {
+        let mut tried = self.locate_ffprobe().tried;
+        tried.extend(self.locate_ffmpeg().tried);
+        tried.extend(self.chromium.searched_paths());
+        tried
+    }
+
+    /// The sheet is rendered as an HTML page
+    fn uses_option(&self, option: &str, _ext: &str) -> bool {
+        self.chromium.uses_option(option, "html")
+    }
+
+    async fn convert(&self, input_path: &Path, options: &ConvertOptions) -> Result<ConvertResult> {
+        let timezone: Tz = print_timezone(options).parse().map_err(|_| {
+            AppError::InvalidRequest(format!("Invalid timezone '{}'", print_timezone(options)))
+        })?;
+        let temp_dir = crate::disk::tempdir()?;
+
+        info!("Probing {} using ffprobe", input_path.display());
+        let media = self.probe(input_path).await?;
+
+        let mut thumbnails = Vec::new();
+        let mut warnings = Vec::new();
+        let has_video = media.streams.iter().any(|s| s.kind == "video");
+        if let (true, Some(duration)) = (has_video, media.duration) {
+            for i in 0..THUMBNAILS {
+                let at = duration * (i as f64 + 0.5) / THUMBNAILS as f64;
+                let path = temp_dir.path().join(format!("frame-{}.jpg", i));
+                match self.thumbnail(input_path, &path, at).await {
+                    Ok(jpeg) => thumbnails.push(Thumbnail { at, jpeg }),
+                    Err(e) => {
+                        warn!("No frame at {:.3}s of {}: {}", at, input_path.display(), e);
+                        warnings.push(format!("No frame could be taken at {}", timecode(at)));
+                    }
+                }
+            }
+        }
+
+        let name = input_path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("media");
+        let html_path = temp_dir.path().join("sheet.html");
+        tokio::fs::write(
+            &html_path,
+            render_sheet(name, &media, &thumbnails, timezone),
+        )
+        .await?;
+
+        let mut result = self.chromium.convert(&html_path, options).await?;
+        let original_name = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("media");
+        result.filename = format!("{}.pdf", original_name);
+        result.warnings.extend(warnings);
+        Ok(result)
+    }
+}
+
+/// Read the `-show_format -show_streams` JSON of ffprobe
+fn parse_probe(report: &Value) -> Result<MediaInfo> {
+    let format = &report["format"];
+    if !format.is_object() {
+        return Err(AppError::ConversionFailed(
+            "ffprobe did not recognise the media container".to_string(),
+        ));
+    }
+    // ffprobe prints numbers as strings
+    let number = |value: &Value| value.as_str().and_then(|s| s.parse::<f64>().ok());
+    let tag = |tags: &Value, key: &str| {
+        tags.as_object()?
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .and_then(|(_, value)| value.as_str())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    let streams = report["streams"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|stream| {
+            let kind = stream["codec_type"].as_str().unwrap_or("data").to_string();
+            let codec = stream["codec_long_name"]
+                .as_str()
+                .or_else(|| stream["codec_name"].as_str())
+                .unwrap_or("unknown")
+                .to_string();
+            let mut details = Vec::new();
+            match kind.as_str() {
+                "video" => {
+                    if let (Some(w), Some(h)) =
+                        (stream["width"].as_u64(), stream["height"].as_u64())
+                    {
+                        details.push(format!("{}×{}", w, h));
+                    }
+                    if let Some(fps) = stream["avg_frame_rate"].as_str().and_then(frame_rate) {
+                        details.push(format!("{} fps", trim_number(fps)));
+                    }
+                    if let Some(pix_fmt) = stream["pix_fmt"].as_str() {
+                        details.push(pix_fmt.to_string());
+                    }
+                }
+                "audio" => {
+                    if let Some(rate) = number(&stream["sample_rate"]) {
+                        details.push(format!("{} kHz", trim_number(rate / 1000.0)));
+                    }
+                    match (
+                        stream["channel_layout"].as_str(),
+                        stream["channels"].as_u64(),
+                    ) {
+                        (Some(layout), _) => details.push(layout.to_string()),
+                        (None, Some(channels)) => details.push(format!("{} channels", channels)),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+            if let Some(rate) = number(&stream["bit_rate"]) {
+                details.push(bit_rate(rate as u64));
+            }
+            StreamInfo {
+                kind,
+                codec,
+                details,
+                language: tag(&stream["tags"], "language"),
+            }
+        })
+        .collect();
+
+    Ok(MediaInfo {
+        format: format["format_long_name"]
+            .as_str()
+            .or_else(|| format["format_name"].as_str())
+            .unwrap_or("unknown")
+            .to_string(),
+        duration: number(&format["duration"]).filter(|d| *d > 0.0),
+        size: number(&format["size"]).map(|s| s as u64),
+        bit_rate: number(&format["bit_rate"]).map(|r| r as u64),
+        created: tag(&format["tags"], "creation_time")
+            .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+            .map(|t| t.with_timezone(&Utc)),
+        tags: TAGS
+            .iter()
+            .filter_map(|(key, label)| Some((*label, tag(&format["tags"], key)?)))
+            .collect(),
+        streams,
+    })
+}
+
+/// "30000/1001" as 29.97
+fn frame_rate(rate: &str) -> Option<f64> {
+    let (num, den) = rate.split_once('/')?;
+    let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+    (num > 0.0 && den > 0.0).then(|| num / den)
+}
+
+/// A number with at most two decimals and no trailing zeros
+fn trim_number(value: f64) -> String {
+    let text = format!("{:.2}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn bit_rate(bits: u64) -> String {
+    if bits >= 1_000_000 {
+        format!("{} Mb/s", trim_number(bits as f64 / 1_000_000.0))
+    } else {
+        format!("{} kb/s", trim_number(bits as f64 / 1000.0))
+    }
+}
+
+/// Seconds as `H:MM:SS.mmm`
+fn timecode(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+fn render_sheet(name: &str, media: &MediaInfo, thumbnails: &[Thumbnail], timezone: Tz) -> String {
+    let mut general = vec![
+        ("File", name.to_string()),
+        ("Container", media.format.clone()),
+    ];
+    if let Some(duration) = media.duration {
+        general.push(("Duration", timecode(duration)));
+    }
+    if let Some(size) = media.size {
+        general.push(("Size", file_size(size)));
+    }
+    if let Some(rate) = media.bit_rate {
+        general.push(("Overall bit rate", bit_rate(rate)));
+    }
+    if let Some(created) = media.created {
+        let created = created.with_timezone(&timezone);
+        general.push((
+            "Created",
+            created.format("%Y-%m-%d %H:%M:%S %Z").to_string(),
+        ));
+    }
+    general.extend(
+        media
+            .tags
+            .iter()
+            .map(|(label, value)| (*label, value.clone())),
+    );
+
+    let general: String = general
+        .iter()
+        .map(|(label, value)| {
+            format!(
+                "<tr><th>{}</th><td>{}</td></tr>\n",
+                label,
+                html_escape(value)
+            )
+        })
+        .collect();
+
+    let streams: String = media
+        .streams
+        .iter()
+        .enumerate()
+        .map(|(i, stream)| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                i,
+                html_escape(&stream.kind),
+                html_escape(&stream.codec),
+                html_escape(&stream.details.join(", ")),
+                html_escape(stream.language.as_deref().unwrap_or("")),
+            )
+        })
+        .collect();
+
+    let frames: String = thumbnails
+        .iter()
+        .map(|frame| {
+            format!(
+                "<figure><img src=\"data:image/jpeg;base64,{}\">\
+                 <figcaption>{}</figcaption></figure>\n",
+                BASE64.encode(&frame.jpeg),
+                timecode(frame.at),
+            )
+        })
+        .collect();
+    let frames = if frames.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "<h2>Frames</h2>\n<div class=\"frames\">\n{}</div>\n",
+            frames
+        )
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <style>
+        body {{ font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif; margin: 32px; font-size: 11px; }}
+        h1 {{ font-size: 18px; margin: 0 0 12px; word-break: break-all; }}
+        h2 {{ font-size: 13px; margin: 18px 0 6px; }}
+        table {{ border-collapse: collapse; width: 100%; }}
+        th, td {{ border: 1px solid #ccc; padding: 3px 8px; text-align: left; vertical-align: top; }}
+        .general th {{ width: 130px; background: #f4f4f4; }}
+        .streams th {{ background: #f4f4f4; }}
+        .frames {{ display: grid; grid-template-columns: repeat(3, 1fr); gap: 8px; }}
+        figure {{ margin: 0; page-break-inside: avoid; }}
+        figure img {{ width: 100%; display: block; }}
+        figcaption {{ text-align: center; color: #555; font-family: monospace; }}
+    </style>
+</head>
+<body>
+<h1>{title}</h1>
+<table class="general">
+{general}</table>
+<h2>Streams</h2>
+<table class="streams">
+<tr><th>#</th><th>Type</th><th>Codec</th><th>Details</th><th>Language</th></tr>
+{streams}</table>
+{frames}</body>
+</html>"#,
+        title = html_escape(name),
+        general = general,
+        streams = streams,
+        frames = frames,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_sheet() {
+        let report = serde_json::json!({
+            "streams": [
+                {
+                    "codec_type": "video",
+                    "codec_name": "h264",
+                    "codec_long_name": "H.264 / AVC / MPEG-4 AVC / MPEG-4 part 10",
+                    "width": 1920,
+                    "height": 1080,
+                    "avg_frame_rate": "30000/1001",
+                    "pix_fmt": "yuv420p",
+                    "bit_rate": "4500000"
+                },
+                {
+                    "codec_type": "audio",
+                    "codec_name": "aac",
+                    "sample_rate": "48000",
+                    "channels": 2,
+                    "channel_layout": "stereo",
+                    "bit_rate": "128000",
+                    "tags": { "language": "eng" }
+                }
+            ],
+            "format": {
+                "format_long_name": "QuickTime / MOV",
+                "duration": "3725.250000",
+                "size": "2147483648",
+                "bit_rate": "4612345",
+                "tags": { "creation_time": "2021-01-19T10:21:44.000000Z", "TITLE": "Reel 4" }
+            }
+        });
+        let media = parse_probe(&report).unwrap();
+        assert_eq!(
+            media.streams[0].details,
+            ["1920×1080", "29.97 fps", "yuv420p", "4.5 Mb/s"]
+        );
+        assert_eq!(media.streams[1].details, ["48 kHz", "stereo", "128 kb/s"]);
+        assert_eq!(media.tags, [("Title", "Reel 4".to_string())]);
+
+        let frames = [Thumbnail {
+            at: 310.4375,
+            jpeg: b"\xFF\xD8\xFF".to_vec(),
+        }];
+        let html = render_sheet("reel<4>.mov", &media, &frames, Tz::Europe__Berlin);
+        assert!(html.contains("<h1>reel&lt;4&gt;.mov</h1>"));
+        assert!(html.contains("<td>1:02:05.250</td>"));
+        assert!(html.contains("2 GiB (2147483648 bytes)"));
+        assert!(html.contains("2021-01-19 11:21:44 CET"));
+        assert!(html.contains("<figcaption>0:05:10.438</figcaption>"));
+
+        assert!(parse_probe(&serde_json::json!({})).is_err());
+    }
+}
@@ -1,43 +1,92 @@
+mod chart;
 mod chromium;
-mod libreoffice;
+mod command;
+mod email;
+mod frontmatter;
+mod highlight;
 mod image;
+mod iwork;
+mod libreoffice;
+mod links;
+mod listing;
+mod locate;
+mod mailmerge;
+mod map;
+mod math;
+mod media;
+mod org;
+mod package;
+#[cfg(feature = "pst")]
+mod pst;
+mod shell;
+mod spreadsheet;
+mod word;
 
-pub use chromium::ChromiumEngine;
-pub use libreoffice::LibreOfficeEngine;
+pub use chromium::{origin_headers, parse_web_url, ChromiumEngine};
+pub use command::CommandEngine;
+pub use email::{attachment_separator, attachments, Attachment};
 pub use image::ImageEngine;
+pub use libreoffice::{LibreOfficeEngine, REVERSE_FORMATS};
+pub use links::audit_links;
+pub use listing::ArchiveEngine;
+pub(crate) use locate::locate_program;
+pub use mailmerge::{parse_records, template_fields};
+pub use map::Basemap;
+pub use media::MediaEngine;
+#[cfg(feature = "pst")]
+pub use pst::PstEngine;
+pub use spreadsheet::USED_RANGE;
+
+pub use pdfmill_core::{options_schema, parse_integer, upload_filename, ConvertOptions};
 
-use crate::error::Result;
+use crate::error::{AppError, Result};
 use async_trait::async_trait;
 use std::path::Path;
-
-/// Conversion options passed to engines
-#[derive(Debug, Clone, Default)]
-pub struct ConvertOptions {
-    /// Page width (e.g., "8.5in", "210mm")
-    pub page_width: Option<String>,
-    /// Page height (e.g., "11in", "297mm")
-    pub page_height: Option<String>,
-    /// Top margin
-    pub margin_top: Option<String>,
-    /// Bottom margin
-    pub margin_bottom: Option<String>,
-    /// Left margin
-    pub margin_left: Option<String>,
-    /// Right margin
-    pub margin_right: Option<String>,
-    /// Landscape orientation
-    pub landscape: bool,
-    /// Print background
-    pub print_background: bool,
-    /// PDF/A format (e.g., "PDF/A-1b")
-    pub pdf_format: Option<String>,
-}
+use std::process::Stdio;
+use tokio::process::Command;
 
 /// Result of a conversion operation
 pub struct ConvertResult {
     pub data: Vec<u8>,
     pub filename: String,
     pub content_type: String,
+    /// Problems that did not fail the conversion, e.g. a font that was
+    /// substituted
+    pub warnings: Vec<String>,
+}
+
+/// Timezone of the dates a conversion prints when the request names none
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+/// IANA name of the timezone dates are printed in
+pub(crate) fn print_timezone(options: &ConvertOptions) -> &str {
+    options.timezone.as_deref().unwrap_or(DEFAULT_TIMEZONE)
+}
+
+/// Lowercase hex SHA-256 of conversion output, for integrity checks
+pub fn sha256_hex(data: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// A byte count for people, e.g. "2 GiB (2147483648 bytes)"
+pub(crate) fn file_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64;
+    let mut unit = "bytes";
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    let size = format!("{:.2}", size);
+    let size = size.trim_end_matches('0').trim_end_matches('.');
+    format!("{} {} ({} bytes)", size, unit, bytes)
 }
 
 /// Engine capability - what file types an engine can handle
@@ -46,6 +95,30 @@ pub enum EngineType {
     Chromium,
     LibreOffice,
     Image,
+    /// Audio and video tech sheets
+    Media,
+    /// Content listings of ZIP and tar archives
+    Archive,
+    #[cfg(feature = "pst")]
+    Pst,
+    /// Operator-defined command line engine, by name
+    Command(&'static str),
+}
+
+impl EngineType {
+    /// Short lowercase name used in headers and logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            EngineType::Chromium => "chromium",
+            EngineType::LibreOffice => "libreoffice",
+            EngineType::Image => "imagemagick",
+            EngineType::Media => "ffmpeg",
+            EngineType::Archive => "archive",
+            #[cfg(feature = "pst")]
+            EngineType::Pst => "pst",
+            EngineType::Command(name) => name,
+        }
+    }
 }
 
 /// Trait that all conversion engines must implement
@@ -63,10 +136,171 @@ pub trait ConvertEngine: Send + Sync {
     /// Check if the engine is available (dependencies installed)
     async fn is_available(&self) -> bool;
 
+    /// Version of the underlying tool (e.g., "124.0.6367.60"), if it can be detected
+    async fn version(&self) -> Option<String>;
+
+    /// Executable locations tried when looking for the underlying tool,
+    /// reported when the engine is not available
+    fn searched_paths(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Output formats this engine can produce
+    fn output_formats(&self) -> Vec<&'static str> {
+        vec!["pdf"]
+    }
+
+    /// Whether the option (by form field name) affects the conversion of
+    /// a file with this extension (lowercase)
+    fn uses_option(&self, _option: &str, _ext: &str) -> bool {
+        true
+    }
+
     /// Convert the input file to PDF
     async fn convert(
         &self,
         input_path: &Path,
         options: &ConvertOptions,
     ) -> Result<ConvertResult>;
+
+    /// Whether a `size`-byte file with this extension (lowercase) can be
+    /// converted from memory by [`convert_memory`](Self::convert_memory),
+    /// sparing an upload the temporary file
+    fn reads_memory(&self, _ext: &str, _size: usize, _options: &ConvertOptions) -> bool {
+        false
+    }
+
+    /// Convert a file held in memory, piping it to the tool or serving it
+    /// to the browser; only called when [`reads_memory`](Self::reads_memory)
+    /// allows it
+    async fn convert_memory(
+        &self,
+        filename: &str,
+        _data: &[u8],
+        _options: &ConvertOptions,
+    ) -> Result<ConvertResult> {
+        Err(AppError::Internal(format!(
+            "{} cannot convert {} from memory",
+            self.engine_type().name(),
+            filename
+        )))
+    }
+}
+
+/// Options applied after any engine ran, or before it was chosen
+const GENERAL_OPTIONS: &[&str] = &["to", "pdfFormat", "linkAudit", "embedProvenance"];
+
+/// Warnings for the options set in `options` that `engine` ignores for
+/// `input_path`, so a silently dropped option does not look like a bug
+pub fn ignored_options(
+    engine: &dyn ConvertEngine,
+    input_path: &Path,
+    options: &ConvertOptions,
+) -> Vec<String> {
+    let ext = input_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    options
+        .form_fields()
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| !GENERAL_OPTIONS.contains(name) && !engine.uses_option(name, &ext))
+        .map(|name| format!("{} does not apply to .{} files and was ignored", name, ext))
+        .collect()
+}
+
+/// Options, by form field name, that `engine` applies to `ext` files
+pub fn honored_options(engine: &dyn ConvertEngine, ext: &str) -> Vec<String> {
+    let schema = options_schema();
+    let Some(properties) = schema["properties"].as_object() else {
+        return Vec::new();
+    };
+    properties
+        .keys()
+        .filter(|name| GENERAL_OPTIONS.contains(&name.as_str()) || engine.uses_option(name, ext))
+        .cloned()
+        .collect()
+}
+
+/// Run `<program> <arg>` and pick the first version-looking token from its output
+pub(crate) async fn probe_version(program: &str, arg: &str) -> Option<String> {
+    let output = Command::new(program)
+        .arg(arg)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .await
+        .ok()?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_version(&stdout)
+}
+
+/// Error for a tool that exited unsuccessfully, keeping its exit code and
+/// stderr for diagnostics
+pub(crate) fn tool_failed(tool: &str, output: &std::process::Output) -> AppError {
+    AppError::ToolFailed {
+        tool: tool.to_string(),
+        exit_code: output.status.code(),
+        stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    }
+}
+
+fn parse_version(output: &str) -> Option<String> {
+    output
+        .split_whitespace()
+        .find(|token| {
+            token.starts_with(|c: char| c.is_ascii_digit())
+                && token
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+        })
+        .map(|token| token.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            parse_version("Chromium 124.0.6367.60 built on Debian 12.5").as_deref(),
+            Some("124.0.6367.60")
+        );
+        assert_eq!(
+            parse_version("LibreOffice 7.4.7.2 40(Build:2)").as_deref(),
+            Some("7.4.7.2")
+        );
+        assert_eq!(
+            parse_version("Version: ImageMagick 6.9.11-60 Q16 x86_64").as_deref(),
+            Some("6.9.11-60")
+        );
+        assert_eq!(parse_version("no version here"), None);
+    }
+
+    #[test]
+    fn test_ignored_options() {
+        let options = ConvertOptions {
+            landscape: true,
+            selector: Some("#main".to_string()),
+            pdf_format: Some("PDF/A-2b".to_string()),
+            ..Default::default()
+        };
+        let engine = LibreOfficeEngine::new();
+        assert_eq!(
+            ignored_options(&engine, Path::new("a.DOCX"), &options),
+            vec![
+                "landscape does not apply to .docx files and was ignored",
+                "selector does not apply to .docx files and was ignored"
+            ]
+        );
+        assert_eq!(
+            ignored_options(&engine, Path::new("a.xlsx"), &options),
+            vec!["selector does not apply to .xlsx files and was ignored"]
+        );
+        assert!(ignored_options(&ChromiumEngine::new(), Path::new("a.html"), &options).is_empty());
+    }
 }
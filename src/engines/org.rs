@@ -0,0 +1,425 @@
+//! Minimal Org-mode to HTML renderer used by the Chromium engine.
+//!
+//! Covers what lab notebooks exported from Emacs typically use: headlines
+//! with TODO keywords, priorities and tags, tables, lists with checkboxes,
+//! source/example/quote blocks and the common inline markup.
+
+use super::chromium::html_escape;
+use super::highlight::highlight;
+
+const TODO_KEYWORDS: &[&str] = &["TODO", "NEXT", "WAITING", "HOLD", "STARTED"];
+const DONE_KEYWORDS: &[&str] = &["DONE", "CANCELLED", "CANCELED"];
+
+/// Convert an Org document to an HTML fragment
+pub fn org_to_html(src: &str) -> String {
+    let mut html = String::new();
+    let mut lines = src.lines().peekable();
+    let mut list: Option<&'static str> = None;
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+
+        // Close an open list once we leave list items
+        if list.is_some() && list_item(trimmed).is_none() {
+            html.push_str(&format!("</{}>\n", list.take().unwrap()));
+        }
+
+        if let Some(kind) = block_start(trimmed) {
+            let mut body = Vec::new();
+            for inner in lines.by_ref() {
+                if inner
+                    .trim()
+                    .eq_ignore_ascii_case(&format!("#+end_{}", kind.name))
+                {
+                    break;
+                }
+                body.push(inner);
+            }
+            html.push_str(&render_block(&kind, &body));
+            continue;
+        }
+
+        if trimmed.starts_with('|') {
+            let mut rows = vec![trimmed];
+            while let Some(next) = lines.peek() {
+                if !next.trim_start().starts_with('|') {
+                    break;
+                }
+                rows.push(next.trim_start());
+                lines.next();
+            }
+            html.push_str(&render_table(&rows));
+            continue;
+        }
+
+        if trimmed.eq_ignore_ascii_case(":properties:") || trimmed.eq_ignore_ascii_case(":logbook:")
+        {
+            // Drawers carry metadata, not content
+            for inner in lines.by_ref() {
+                if inner.trim().eq_ignore_ascii_case(":end:") {
+                    break;
+                }
+            }
+            continue;
+        }
+
+        if let Some(title) = keyword_value(trimmed, "title") {
+            html.push_str(&format!("<h1 class=\"title\">{}</h1>\n", inline(title)));
+            continue;
+        }
+
+        if trimmed.starts_with("#+") || trimmed == "#" || trimmed.starts_with("# ") {
+            // Other keywords and comments are not rendered
+            continue;
+        }
+
+        if let Some((level, rest)) = headline(line) {
+            html.push_str(&render_headline(level, rest));
+            continue;
+        }
+
+        if is_planning(trimmed) {
+            html.push_str(&format!(
+                "<p class=\"planning\">{}</p>\n",
+                html_escape(trimmed)
+            ));
+            continue;
+        }
+
+        if let Some((tag, text)) = list_item(trimmed) {
+            if list != Some(tag) {
+                if let Some(open) = list.take() {
+                    html.push_str(&format!("</{}>\n", open));
+                }
+                html.push_str(&format!("<{}>\n", tag));
+                list = Some(tag);
+            }
+            html.push_str(&format!("<li>{}</li>\n", render_checkbox(text)));
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        html.push_str(&format!("<p>{}</p>\n", inline(trimmed)));
+    }
+
+    if let Some(open) = list {
+        html.push_str(&format!("</{}>\n", open));
+    }
+
+    html
+}
+
+struct Block {
+    name: String,
+    language: Option<String>,
+}
+
+fn block_start(line: &str) -> Option<Block> {
+    let lower = line.to_ascii_lowercase();
+    let rest = lower.strip_prefix("#+begin_")?;
+    let mut parts = rest.split_whitespace();
+    let name = parts.next()?.to_string();
+    // Keep the language as written (e.g. "Python"), not lowercased
+    let language = line.split_whitespace().nth(1).map(|s| s.to_string());
+    Some(Block { name, language })
+}
+
+fn render_block(block: &Block, body: &[&str]) -> String {
+    let text = body
+        .iter()
+        .map(|l| html_escape(l))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    match block.name.as_str() {
+        "src" => {
+            let lang = block.language.as_deref().unwrap_or("");
+            let code = highlight(&body.join("\n"), lang).unwrap_or(text);
+            format!(
+                "<pre class=\"src src-{0}\"><code class=\"language-{0}\">{1}\n</code></pre>\n",
+                html_escape(lang),
+                code
+            )
+        }
+        "quote" => format!(
+            "<blockquote>{}</blockquote>\n",
+            body.iter()
+                .map(|l| inline(l.trim()))
+                .collect::<Vec<_>>()
+                .join("<br>\n")
+        ),
+        _ => format!("<pre class=\"example\">{}\n</pre>\n", text),
+    }
+}
+
+fn render_table(rows: &[&str]) -> String {
+    let is_rule = |row: &str| row.starts_with("|-") || row.starts_with("|+");
+    let has_header = rows.len() > 1 && rows.iter().skip(1).any(|r| is_rule(r)) && !is_rule(rows[0]);
+
+    let mut html = String::from("<table>\n");
+    let mut in_header = has_header;
+    if in_header {
+        html.push_str("<thead>\n");
+    }
+
+    for row in rows {
+        if is_rule(row) {
+            if in_header {
+                html.push_str("</thead>\n<tbody>\n");
+                in_header = false;
+            }
+            continue;
+        }
+
+        let cell_tag = if in_header { "th" } else { "td" };
+        let inner = row.trim().trim_start_matches('|');
+        let inner = inner.strip_suffix('|').unwrap_or(inner);
+        html.push_str("<tr>");
+        for cell in inner.split('|') {
+            html.push_str(&format!("<{0}>{1}</{0}>", cell_tag, inline(cell.trim())));
+        }
+        html.push_str("</tr>\n");
+    }
+
+    if in_header {
+        html.push_str("</thead>\n");
+    } else if has_header {
+        html.push_str("</tbody>\n");
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+fn headline(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '*').count();
+    if level == 0 {
+        return None;
+    }
+    let rest = line[level..].strip_prefix(' ')?;
+    Some((level, rest.trim()))
+}
+
+fn render_headline(level: usize, text: &str) -> String {
+    let mut text = text;
+    let mut prefix = String::new();
+
+    let first = text.split_whitespace().next().unwrap_or("");
+    if TODO_KEYWORDS.contains(&first) || DONE_KEYWORDS.contains(&first) {
+        let class = if DONE_KEYWORDS.contains(&first) {
+            "done"
+        } else {
+            "todo"
+        };
+        prefix.push_str(&format!(
+            "<span class=\"{} {}\">{}</span> ",
+            class, first, first
+        ));
+        text = text[first.len()..].trim_start();
+    }
+
+    if let Some(rest) = text.strip_prefix("[#") {
+        if let Some((priority, after)) = rest.split_once(']') {
+            prefix.push_str(&format!(
+                "<span class=\"priority\">[#{}]</span> ",
+                html_escape(priority)
+            ));
+            text = after.trim_start();
+        }
+    }
+
+    let mut suffix = String::new();
+    if let Some((title, tags)) = split_tags(text) {
+        text = title;
+        for tag in tags.split(':').filter(|t| !t.is_empty()) {
+            suffix.push_str(&format!(" <span class=\"tag\">{}</span>", html_escape(tag)));
+        }
+    }
+
+    let tag = format!("h{}", (level + 1).min(6));
+    format!("<{0}>{1}{2}{3}</{0}>\n", tag, prefix, inline(text), suffix)
+}
+
+/// Split trailing `:tag1:tag2:` off a headline title
+fn split_tags(text: &str) -> Option<(&str, &str)> {
+    let (title, tags) = text.rsplit_once(char::is_whitespace)?;
+    let valid = tags.len() > 2
+        && tags.starts_with(':')
+        && tags.ends_with(':')
+        && tags
+            .chars()
+            .all(|c| c == ':' || c == '_' || c == '@' || c == '#' || c.is_alphanumeric());
+    valid.then(|| (title.trim_end(), tags))
+}
+
+fn is_planning(line: &str) -> bool {
+    ["SCHEDULED:", "DEADLINE:", "CLOSED:"]
+        .iter()
+        .any(|k| line.starts_with(k))
+}
+
+fn keyword_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix("#+")?;
+    let (name, value) = rest.split_once(':')?;
+    name.eq_ignore_ascii_case(key).then(|| value.trim())
+}
+
+fn list_item(line: &str) -> Option<(&'static str, &str)> {
+    if let Some(rest) = line.strip_prefix("- ").or_else(|| line.strip_prefix("+ ")) {
+        return Some(("ul", rest));
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        let rest = &line[digits..];
+        if let Some(rest) = rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") ")) {
+            return Some(("ol", rest));
+        }
+    }
+    None
+}
+
+fn render_checkbox(text: &str) -> String {
+    for (marker, state) in [
+        ("[ ] ", "off"),
+        ("[X] ", "on"),
+        ("[x] ", "on"),
+        ("[-] ", "trans"),
+    ] {
+        if let Some(rest) = text.strip_prefix(marker) {
+            let glyph = match state {
+                "on" => "&#9745;",
+                "trans" => "&#9635;",
+                _ => "&#9744;",
+            };
+            return format!(
+                "<span class=\"checkbox {}\">{}</span> {}",
+                state,
+                glyph,
+                inline(rest)
+            );
+        }
+    }
+    inline(text)
+}
+
+/// Render inline markup: links, *bold*, /italic/, _underline_, +strike+,
+/// =verbatim= and ~code~
+fn inline(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("[[") {
+        out.push_str(&emphasis(&rest[..start]));
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("]]") else {
+            out.push_str(&emphasis(&rest[start..]));
+            return out;
+        };
+        let link = &after[..end];
+        let (target, label) = match link.split_once("][") {
+            Some((t, l)) => (t, l),
+            None => (link, link),
+        };
+        out.push_str(&format!(
+            "<a href=\"{}\">{}</a>",
+            html_escape(target),
+            emphasis(label)
+        ));
+        rest = &after[end + 2..];
+    }
+
+    out.push_str(&emphasis(rest));
+    out
+}
+
+fn emphasis(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let tag = match c {
+            '*' => Some("strong"),
+            '/' => Some("em"),
+            '_' => Some("u"),
+            '+' => Some("del"),
+            '=' | '~' => Some("code"),
+            _ => None,
+        };
+
+        let boundary_before = i == 0 || !chars[i - 1].is_alphanumeric();
+        if let (Some(tag), true) = (tag, boundary_before) {
+            if let Some(close) = find_closing(&chars, i, c) {
+                let inner: String = chars[i + 1..close].iter().collect();
+                let rendered = if tag == "code" {
+                    html_escape(&inner)
+                } else {
+                    emphasis(&inner)
+                };
+                out.push_str(&format!("<{0}>{1}</{0}>", tag, rendered));
+                i = close + 1;
+                continue;
+            }
+        }
+
+        out.push_str(&html_escape(&c.to_string()));
+        i += 1;
+    }
+
+    out
+}
+
+fn find_closing(chars: &[char], open: usize, marker: char) -> Option<usize> {
+    let first = *chars.get(open + 1)?;
+    if first.is_whitespace() || first == marker {
+        return None;
+    }
+    (open + 2..chars.len()).find(|&j| {
+        chars[j] == marker
+            && !chars[j - 1].is_whitespace()
+            && chars.get(j + 1).is_none_or(|n| !n.is_alphanumeric())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headline_with_todo_priority_and_tags() {
+        let html = org_to_html("** TODO [#A] Run assay :lab:urgent:");
+        assert!(html.starts_with("<h3>"));
+        assert!(html.contains("<span class=\"todo TODO\">TODO</span>"));
+        assert!(html.contains("<span class=\"priority\">[#A]</span>"));
+        assert!(html.contains("Run assay"));
+        assert!(html.contains("<span class=\"tag\">lab</span>"));
+        assert!(html.contains("<span class=\"tag\">urgent</span>"));
+    }
+
+    #[test]
+    fn test_table_with_header() {
+        let html = org_to_html("| Sample | OD |\n|--------+----|\n| A1 | 0.42 |\n");
+        assert!(html.contains("<thead>\n<tr><th>Sample</th><th>OD</th></tr>"));
+        assert!(html.contains("<tbody>\n<tr><td>A1</td><td>0.42</td></tr>"));
+    }
+
+    #[test]
+    fn test_src_block_is_escaped() {
+        let html = org_to_html("#+BEGIN_SRC python\nif a < b:\n    pass\n#+END_SRC");
+        assert!(html.contains("class=\"src src-python\""));
+        assert!(html.contains("<span class=\"hl-k\">if</span> a &lt; b:"));
+        let html = org_to_html("#+BEGIN_SRC unknown\nif a < b:\n#+END_SRC");
+        assert!(html.contains(">if a &lt; b:\n</code>"));
+    }
+
+    #[test]
+    fn test_inline_markup() {
+        let html = org_to_html("Some *bold* and =x<y= with [[https://example.com][a link]]");
+        assert!(html.contains("<strong>bold</strong>"));
+        assert!(html.contains("<code>x&lt;y</code>"));
+        assert!(html.contains("<a href=\"https://example.com\">a link</a>"));
+    }
+}
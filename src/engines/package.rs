@@ -0,0 +1,108 @@
+//! Editing helpers for zipped XML documents (OOXML and ODF packages)
+
+use crate::error::{AppError, Result};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub fn open(path: &Path) -> Result<ZipArchive<File>> {
+    ZipArchive::new(File::open(path)?).map_err(package_error)
+}
+
+pub fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<String> {
+    let mut contents = String::new();
+    archive
+        .by_name(name)
+        .map_err(package_error)?
+        .read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// Write a copy of `archive` to `output`, replacing the entries in `edits`
+pub fn rewrite<R: Read + Seek>(
+    mut archive: ZipArchive<R>,
+    edits: &HashMap<String, String>,
+    output: &Path,
+) -> Result<()> {
+    let mut writer = ZipWriter::new(File::create(output)?);
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i).map_err(package_error)?;
+        match edits.get(file.name()) {
+            Some(contents) => {
+                let name = file.name().to_string();
+                drop(file);
+                let file_options =
+                    SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+                writer
+                    .start_file(name, file_options)
+                    .map_err(package_error)?;
+                writer.write_all(contents.as_bytes())?;
+            }
+            // Untouched entries keep their original compression, which
+            // matters for the stored `mimetype` entry of ODF packages
+            None => writer.raw_copy_file(file).map_err(package_error)?,
+        }
+    }
+    writer.finish().map_err(package_error)?;
+
+    Ok(())
+}
+
+fn package_error(e: zip::result::ZipError) -> AppError {
+    AppError::ConversionFailed(format!("Failed to read document package: {}", e))
+}
+
+/// Opening (or empty) tag `<name ...>`, not matching longer element names
+pub fn tag_regex(name: &str) -> Regex {
+    Regex::new(&format!(r"<{}(?:\s[^>]*)?/?>", regex::escape(name))).unwrap()
+}
+
+pub fn tags<'a>(xml: &'a str, name: &str) -> impl Iterator<Item = &'a str> + 'a {
+    tag_regex(name)
+        .find_iter(xml)
+        .map(|m| m.as_str())
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Unescaped value of attribute `name` in a single tag
+pub fn attr(tag: &str, name: &str) -> Option<String> {
+    let re = Regex::new(&format!(r#"\s{}="([^"]*)""#, regex::escape(name))).unwrap();
+    re.captures(tag).map(|c| xml_unescape(&c[1]))
+}
+
+/// Replace, add or (with `None`) remove an attribute of a single tag.
+/// `value` must already be XML-escaped.
+pub fn set_attr(tag: &str, name: &str, value: Option<&str>) -> String {
+    let re = Regex::new(&format!(r#"\s+{}="[^"]*""#, regex::escape(name))).unwrap();
+    let mut tag = re.replace(tag, "").into_owned();
+    if let Some(value) = value {
+        let end = if tag.ends_with("/>") {
+            tag.len() - 2
+        } else {
+            tag.len() - 1
+        };
+        let end = tag[..end].trim_end().len();
+        tag.insert_str(end, &format!(" {}=\"{}\"", name, value));
+    }
+    tag
+}
+
+pub fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,327 @@
+use super::chromium::html_escape;
+use super::email::sanitize_body;
+use super::locate::{locate, Located};
+use super::{
+    print_timezone, probe_version, tool_failed, ChromiumEngine, ConvertEngine, ConvertOptions,
+    ConvertResult, EngineType,
+};
+use crate::error::{AppError, Result};
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::process::Command;
+use tracing::info;
+
+const SUPPORTED_EXTENSIONS: &[&str] = &["pst", "ost"];
+
+/// Outlook mailbox engine: exports messages with libpff's `pffexport`
+/// and prints them as a single archive PDF with an index via Chromium
+pub struct PstEngine {
+    chromium: Arc<ChromiumEngine>,
+}
+
+/// A single exported message
+struct MailMessage {
+    folder: String,
+    subject: String,
+    sender: String,
+    date: String,
+    body_html: String,
+}
+
+impl PstEngine {
+    pub fn new(chromium: Arc<ChromiumEngine>) -> Self {
+        Self { chromium }
+    }
+
+    /// pffexport executable: `PFFEXPORT_PATH` or `$PATH`
+    fn locate_pffexport(&self) -> Located {
+        locate("PFFEXPORT_PATH", &[], &["pffexport"])
+    }
+
+    fn get_pffexport_path(&self) -> String {
+        self.locate_pffexport().path
+    }
+
+    async fn export_mailbox(&self, input_path: &Path, target: &Path) -> Result<PathBuf> {
+        let pffexport = self.locate_pffexport();
+        let output = Command::new(&pffexport.path)
+            .arg("-q")
+            .args(["-m", "items"])
+            .args(["-f", "html"])
+            .arg("-t")
+            .arg(target)
+            .arg(input_path)
+            .kill_on_drop(true)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .await
+            .map_err(|e| {
+                AppError::EngineNotAvailable(format!(
+                    "pffexport not found ({}): {}",
+                    pffexport.describe_tried(),
+                    e
+                ))
+            })?;
+
+        if !output.status.success() {
+            return Err(tool_failed("pffexport", &output));
+        }
+
+        // pffexport writes into "<target>.export"
+        let mut export_dir = target.as_os_str().to_owned();
+        export_dir.push(".export");
+        Ok(PathBuf::from(export_dir))
+    }
+}
+
+#[async_trait]
+impl ConvertEngine for PstEngine {
+    fn engine_type(&self) -> EngineType {
+        EngineType::Pst
+    }
+
+    fn supports_extension(&self, ext: &str) -> bool {
+        SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+    }
+
+    fn supported_extensions(&self) -> Vec<&'static str> {
+        SUPPORTED_EXTENSIONS.to_vec()
+    }
+
+    async fn is_available(&self) -> bool {
+        let pffexport = Command::new(self.get_pffexport_path())
+            .arg("-V")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .map(|s| s.success())
+            .unwrap_or(false);
+
+        pffexport && self.chromium.is_available().await
+    }
+
+    async fn version(&self) -> Option<String> {
+        probe_version(&self.get_pffexport_path(), "-V").await
+    }
+
+    fn searched_paths(&self) -> Vec<String> {
+        let mut tried = self.locate_pffexport().tried;
+        tried.extend(self.chromium.searched_paths());
+        tried
+    }
+
+    /// Messages are rendered as HTML pages
+    fn uses_option(&self, option: &str, _ext: &str) -> bool {
+        self.chromium.uses_option(option, "html")
+    }
+
+    async fn convert(&self, input_path: &Path, options: &ConvertOptions) -> Result<ConvertResult> {
+        let timezone: Tz = print_timezone(options).parse().map_err(|_| {
+            AppError::InvalidRequest(format!("Invalid timezone '{}'", print_timezone(options)))
+        })?;
+        let temp_dir = crate::disk::tempdir()?;
+
+        info!("Exporting mailbox {} using pffexport", input_path.display());
+        let export_dir = self
+            .export_mailbox(input_path, &temp_dir.path().join("mailbox"))
+            .await?;
+
+        let mut messages = tokio::task::spawn_blocking(move || {
+            let mut messages = Vec::new();
+            collect_messages(&export_dir, &export_dir, &mut messages)?;
+            Ok::<_, std::io::Error>(messages)
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Mailbox walk panicked: {}", e)))??;
+
+        if messages.is_empty() {
+            return Err(AppError::ConversionFailed(
+                "Mailbox does not contain any messages".to_string(),
+            ));
+        }
+
+        for msg in &mut messages {
+            msg.date = local_date(&msg.date, timezone);
+        }
+
+        info!("Rendering {} messages into archive PDF", messages.len());
+        let html_path = temp_dir.path().join("archive.html");
+        tokio::fs::write(&html_path, render_archive(&messages)).await?;
+
+        let mut result = self.chromium.convert(&html_path, options).await?;
+        let original_name = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("mailbox");
+        result.filename = format!("{}.pdf", original_name);
+        Ok(result)
+    }
+}
+
+/// A pffexport submit time, e.g. "Jan 19, 2021 10:21:44.000000000 UTC",
+/// in `timezone`; dates in another form are kept as they are
+fn local_date(date: &str, timezone: Tz) -> String {
+    date.strip_suffix(" UTC")
+        .and_then(|utc| NaiveDateTime::parse_from_str(utc, "%b %d, %Y %H:%M:%S%.f").ok())
+        .map(|utc| {
+            utc.and_utc()
+                .with_timezone(&timezone)
+                .format("%Y-%m-%d %H:%M %Z")
+                .to_string()
+        })
+        .unwrap_or_else(|| date.to_string())
+}
+
+/// Walk the pffexport tree; every directory holding message headers is a message
+fn collect_messages(root: &Path, dir: &Path, out: &mut Vec<MailMessage>) -> std::io::Result<()> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    entries.sort();
+
+    if dir.join("OutlookHeaders.txt").exists() {
+        out.push(read_message(root, dir)?);
+    }
+
+    for entry in entries {
+        // Attachments of a message are not messages themselves
+        if entry.file_name().is_some_and(|n| n == "Attachments") {
+            continue;
+        }
+        collect_messages(root, &entry, out)?;
+    }
+
+    Ok(())
+}
+
+fn read_message(root: &Path, dir: &Path) -> std::io::Result<MailMessage> {
+    let headers = std::fs::read_to_string(dir.join("OutlookHeaders.txt")).unwrap_or_default();
+    let header = |key: &str| {
+        headers
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                (name.trim() == key).then(|| value.trim().to_string())
+            })
+            .unwrap_or_default()
+    };
+
+    let body_html = if let Ok(html) = std::fs::read_to_string(dir.join("Message.html")) {
+        // Messages share the archive's page, so none may script or restyle it
+        sanitize_body(&html)
+    } else {
+        let text = std::fs::read_to_string(dir.join("Message.txt")).unwrap_or_default();
+        format!("<pre class=\"body\">{}</pre>", html_escape(&text))
+    };
+
+    // The folder is the path between the export root and the message directory
+    let folder = dir
+        .parent()
+        .and_then(|p| p.strip_prefix(root).ok())
+        .map(|p| p.display().to_string())
+        .unwrap_or_default();
+
+    let sender = match (header("Sender name"), header("Sender email address")) {
+        (name, email) if email.is_empty() => name,
+        (name, email) if name.is_empty() => email,
+        (name, email) => format!("{} <{}>", name, email),
+    };
+
+    Ok(MailMessage {
+        folder,
+        subject: header("Subject"),
+        sender,
+        date: header("Client submit time"),
+        body_html,
+    })
+}
+
+fn render_archive(messages: &[MailMessage]) -> String {
+    let mut index = String::new();
+    let mut sections = String::new();
+
+    for (i, msg) in messages.iter().enumerate() {
+        let subject = if msg.subject.is_empty() {
+            "(no subject)".to_string()
+        } else {
+            html_escape(&msg.subject)
+        };
+
+        index.push_str(&format!(
+            "<tr><td>{n}</td><td>{folder}</td><td><a href=\"#msg-{n}\">{subject}</a></td><td>{sender}</td><td>{date}</td></tr>\n",
+            n = i + 1,
+            folder = html_escape(&msg.folder),
+            subject = subject,
+            sender = html_escape(&msg.sender),
+            date = html_escape(&msg.date),
+        ));
+
+        sections.push_str(&format!(
+            r#"<section class="message" id="msg-{n}">
+<h2>{subject}</h2>
+<table class="headers">
+<tr><th>From</th><td>{sender}</td></tr>
+<tr><th>Date</th><td>{date}</td></tr>
+<tr><th>Folder</th><td>{folder}</td></tr>
+</table>
+<div class="body">{body}</div>
+</section>
+"#,
+            n = i + 1,
+            subject = subject,
+            sender = html_escape(&msg.sender),
+            date = html_escape(&msg.date),
+            folder = html_escape(&msg.folder),
+            body = msg.body_html,
+        ));
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <style>
+        body {{ font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif; margin: 40px; }}
+        table {{ border-collapse: collapse; width: 100%; }}
+        th, td {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; vertical-align: top; font-size: 0.9em; }}
+        .message {{ page-break-before: always; }}
+        .headers th {{ width: 80px; background: #f4f4f4; }}
+        .body {{ margin-top: 16px; contain: paint; }}
+        pre.body {{ white-space: pre-wrap; font-family: inherit; }}
+    </style>
+</head>
+<body>
+<h1>Mailbox archive ({count} messages)</h1>
+<table class="index">
+<tr><th>#</th><th>Folder</th><th>Subject</th><th>From</th><th>Date</th></tr>
+{index}</table>
+{sections}
+</body>
+</html>"#,
+        count = messages.len(),
+        index = index,
+        sections = sections,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_date() {
+        let date = "Jan 19, 2021 10:21:44.000000000 UTC";
+        assert_eq!(local_date(date, Tz::UTC), "2021-01-19 10:21 UTC");
+        assert_eq!(local_date(date, Tz::Europe__Berlin), "2021-01-19 11:21 CET");
+        assert_eq!(local_date("yesterday", Tz::UTC), "yesterday");
+    }
+}
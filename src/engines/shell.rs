@@ -0,0 +1,157 @@
+//! Markdown rendered into a caller's HTML shell (`markdownTemplate`)
+//! instead of the default document, for branding, cover pages and a table
+//! of contents. The shell is a Tera template, as for `/render`, with the
+//! converted document and what was found in it as variables.
+
+use crate::error::{AppError, Result};
+use crate::templates::tera_message;
+use regex::Regex;
+use serde::Serialize;
+use std::sync::LazyLock;
+use tera::{Context, Tera};
+
+/// Name the shell is registered under; the .html suffix turns on
+/// autoescaping of substituted values
+const SHELL_NAME: &str = "markdown.html";
+
+/// Headings of the converted document, as the Markdown renderer writes them
+static HEADING: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"<h([1-3]) id="([^"]*)">(.*?)</h[1-3]>"#).expect("valid heading pattern")
+});
+
+/// A heading, for the table of contents
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Heading {
+    pub level: u8,
+    pub id: String,
+    /// HTML of the heading's text
+    pub html: String,
+}
+
+/// What the shell can place, besides the document
+pub struct ShellContext<'a> {
+    /// Stylesheet of the default document, for shells that keep its look
+    pub styles: &'a str,
+    /// Title from the document's front matter
+    pub title: Option<&'a str>,
+    /// Input file name without its extension, the title when the document
+    /// has no first-level heading
+    pub name: &'a str,
+    /// Today's date in the print timezone, YYYY-MM-DD
+    pub date: String,
+}
+
+/// Render the document `body` into the shell. Variables: `content` (the
+/// document), `title` (from front matter, else the first `#` heading),
+/// `toc` (a linked list of the headings), `headings` (level, id, html),
+/// `styles`, `filename` and `date`; HTML ones are placed with `| safe`.
+pub fn render(template: &str, body: &str, context: &ShellContext) -> Result<String> {
+    if !template.contains("content") {
+        return Err(AppError::InvalidRequest(
+            "markdownTemplate: the template does not place the document with {{ content | safe }}"
+                .to_string(),
+        ));
+    }
+    let mut tera = Tera::default();
+    tera.add_raw_template(SHELL_NAME, template)
+        .map_err(|e| AppError::InvalidRequest(format!("markdownTemplate: {}", tera_message(&e))))?;
+
+    let headings = headings(body);
+    let title = match context.title {
+        Some(title) => title.to_string(),
+        None => headings
+            .iter()
+            .find(|heading| heading.level == 1)
+            .map(|heading| strip_tags(&heading.html))
+            .unwrap_or_else(|| context.name.to_string()),
+    };
+    let mut variables = Context::new();
+    variables.insert("content", body);
+    variables.insert("title", &title);
+    variables.insert("toc", &toc_html(&headings));
+    variables.insert("headings", &headings);
+    variables.insert("styles", context.styles);
+    variables.insert("filename", context.name);
+    variables.insert("date", &context.date);
+    tera.render(SHELL_NAME, &variables)
+        .map_err(|e| AppError::InvalidRequest(format!("markdownTemplate: {}", tera_message(&e))))
+}
+
+/// The headings of a converted document, in order
+pub fn headings(body: &str) -> Vec<Heading> {
+    HEADING
+        .captures_iter(body)
+        .map(|captures| Heading {
+            level: captures[1].parse().unwrap_or(1),
+            id: captures[2].to_string(),
+            html: captures[3].to_string(),
+        })
+        .collect()
+}
+
+/// A list of links to the headings, each item classed by level
+/// (`toc-h1`…`toc-h3`) for the shell to indent
+fn toc_html(headings: &[Heading]) -> String {
+    let mut html = String::from("<nav class=\"toc\">\n<ul>\n");
+    for heading in headings {
+        html.push_str(&format!(
+            "<li class=\"toc-h{}\"><a href=\"#{}\">{}</a></li>\n",
+            heading.level, heading.id, heading.html
+        ));
+    }
+    html.push_str("</ul>\n</nav>\n");
+    html
+}
+
+/// Text of an HTML fragment, for the title: tags dropped and the entities
+/// the renderer escapes decoded
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let body = "<h1 id=\"guide\">Guide &amp; <em>more</em></h1>\n<p>Hi</p>\n\
+                    <h2 id=\"a-b\">A &amp; B</h2>\n";
+        let context = ShellContext {
+            styles: "body { margin: 0 }",
+            title: None,
+            name: "guide",
+            date: "2024-05-01".to_string(),
+        };
+        let template = "<title>{{ title }}</title><style>{{ styles | safe }}</style>\
+                        <section class=\"cover\">{{ date }}</section>{{ toc | safe }}\
+                        {% for h in headings %}[{{ h.level }}]{% endfor %}{{ content | safe }}";
+        let html = render(template, body, &context).unwrap();
+        assert!(html.starts_with("<title>Guide &amp; more</title><style>body { margin: 0 }"));
+        assert!(html.contains("<li class=\"toc-h2\"><a href=\"#a-b\">A &amp; B</a></li>"));
+        assert!(html.contains("[1][2]"));
+        assert!(html.ends_with(body));
+
+        assert!(matches!(
+            render("<body></body>", body, &context),
+            Err(AppError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            render("{{ content | safe", body, &context),
+            Err(AppError::InvalidRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,493 @@
+//! Calc print settings (gridlines, headers, print area, orientation) written
+//! into XLSX/ODS packages before LibreOffice exports them to PDF
+
+use super::package::{self, attr, read_entry, set_attr, tag_regex, tags, xml_escape, xml_unescape};
+use super::ConvertOptions;
+use crate::error::{AppError, Result};
+use regex::Regex;
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+use std::path::Path;
+use zip::ZipArchive;
+
+/// Spreadsheet formats whose print settings can be rewritten
+pub const PRINT_SETTINGS_EXTENSIONS: &[&str] = &["xlsx", "ods"];
+
+/// `printArea` value that prints each sheet's used range
+pub const USED_RANGE: &str = "used";
+
+/// Sheets using more columns than this do not fit a portrait page at
+/// default column widths
+const WIDE_SHEET_COLUMNS: u32 = 8;
+
+/// SpreadsheetML worksheet elements from `printOptions` on, in schema order
+const WORKSHEET_TAIL: &[&str] = &[
+    "printOptions",
+    "pageMargins",
+    "pageSetup",
+    "headerFooter",
+    "rowBreaks",
+    "colBreaks",
+    "customProperties",
+    "cellWatches",
+    "ignoredErrors",
+    "smartTags",
+    "drawing",
+    "legacyDrawing",
+    "legacyDrawingHF",
+    "drawingHF",
+    "picture",
+    "oleObjects",
+    "controls",
+    "webPublishItems",
+    "tableParts",
+    "extLst",
+];
+
+/// ODF page layout print flags when `style:print` is absent
+const ODS_DEFAULT_PRINT: &str = "charts drawings objects zeros";
+
+/// Whether any spreadsheet print setting was requested
+pub fn wants_print_settings(options: &ConvertOptions) -> bool {
+    options.gridlines.is_some()
+        || options.sheet_headers.is_some()
+        || options.print_area.is_some()
+        || options.auto_landscape
+        || options.landscape
+}
+
+/// Copy the workbook at `input` to `output` with the requested print settings
+pub fn apply_print_settings(input: &Path, output: &Path, options: &ConvertOptions) -> Result<()> {
+    let ext = input
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    let mut archive = package::open(input)?;
+    let edits = match ext.as_str() {
+        "xlsx" => xlsx_edits(&mut archive, options)?,
+        "ods" => ods_edits(&mut archive, options)?,
+        _ => HashMap::new(),
+    };
+
+    package::rewrite(archive, &edits, output)
+}
+
+// ---------------------------------------------------------------------------
+// XLSX
+// ---------------------------------------------------------------------------
+
+fn xlsx_edits<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    options: &ConvertOptions,
+) -> Result<HashMap<String, String>> {
+    let mut workbook = read_entry(archive, "xl/workbook.xml")?;
+    let rels = read_entry(archive, "xl/_rels/workbook.xml.rels")?;
+
+    let targets: HashMap<String, String> = tags(&rels, "Relationship")
+        .filter_map(|tag| Some((attr(tag, "Id")?, attr(tag, "Target")?)))
+        .collect();
+    let sheets: Vec<(String, String)> = tags(&workbook, "sheet")
+        .filter_map(|tag| {
+            let target = targets.get(&attr(tag, "r:id")?)?;
+            let path = match target.strip_prefix('/') {
+                Some(absolute) => absolute.to_string(),
+                None => format!("xl/{}", target),
+            };
+            Some((attr(tag, "name")?, path))
+        })
+        .collect();
+
+    let mut edits = HashMap::new();
+
+    if let Some(area) = &options.print_area {
+        let named = if area == USED_RANGE {
+            None
+        } else {
+            let reference = defined_names(&workbook)
+                .into_iter()
+                .find(|(name, _)| name == area)
+                .map(|(_, reference)| reference)
+                .ok_or_else(|| {
+                    AppError::InvalidRequest(format!(
+                        "printArea: no range named '{}' in workbook",
+                        area
+                    ))
+                })?;
+            let sheet = reference
+                .rsplit_once('!')
+                .map(|(sheet, _)| sheet.trim_matches('\'').replace("''", "'"))
+                .unwrap_or_default();
+            let index = sheets
+                .iter()
+                .position(|(name, _)| *name == sheet)
+                .ok_or_else(|| {
+                    AppError::InvalidRequest(format!(
+                        "printArea: range '{}' does not refer to a sheet",
+                        area
+                    ))
+                })?;
+            Some((index, reference))
+        };
+
+        // Without an explicit print area Calc prints the used range
+        workbook = remove_defined_name(&workbook, "_xlnm.Print_Area");
+        if let Some((index, reference)) = named {
+            workbook = add_defined_name(
+                &workbook,
+                &format!(
+                    "<definedName name=\"_xlnm.Print_Area\" localSheetId=\"{}\">{}</definedName>",
+                    index,
+                    xml_escape(&reference)
+                ),
+            );
+            // Hidden sheets are left out of the PDF
+            workbook = hide_sheets_except(&workbook, index);
+        }
+        edits.insert("xl/workbook.xml".to_string(), workbook);
+    }
+
+    for (_, path) in &sheets {
+        let mut xml = read_entry(archive, path)?;
+        if options.gridlines.is_some() || options.sheet_headers.is_some() {
+            xml = set_xlsx_print_options(&xml, options.gridlines, options.sheet_headers);
+        }
+        let wide = options.auto_landscape && xlsx_used_columns(&xml) > WIDE_SHEET_COLUMNS;
+        if options.landscape || wide {
+            xml = set_xlsx_landscape(&xml);
+        }
+        edits.insert(path.clone(), xml);
+    }
+
+    Ok(edits)
+}
+
+/// `(name, reference)` of every workbook-level defined name
+fn defined_names(workbook: &str) -> Vec<(String, String)> {
+    defined_name_regex()
+        .captures_iter(workbook)
+        .filter_map(|c| Some((attr(&c[1], "name")?, xml_unescape(&c[2]))))
+        .collect()
+}
+
+fn defined_name_regex() -> Regex {
+    Regex::new(r"(?s)(<definedName\b[^>]*>)(.*?)</definedName>").unwrap()
+}
+
+fn remove_defined_name(workbook: &str, name: &str) -> String {
+    defined_name_regex()
+        .replace_all(workbook, |c: &regex::Captures| {
+            if attr(&c[1], "name").as_deref() == Some(name) {
+                String::new()
+            } else {
+                c[0].to_string()
+            }
+        })
+        .into_owned()
+        .replace("<definedNames></definedNames>", "")
+}
+
+fn add_defined_name(workbook: &str, element: &str) -> String {
+    if let Some(pos) = workbook.find("</definedNames>") {
+        format!("{}{}{}", &workbook[..pos], element, &workbook[pos..])
+    } else if workbook.contains("<definedNames/>") {
+        workbook.replacen(
+            "<definedNames/>",
+            &format!("<definedNames>{}</definedNames>", element),
+            1,
+        )
+    } else if let Some(pos) = workbook.find("</sheets>") {
+        let pos = pos + "</sheets>".len();
+        format!(
+            "{}<definedNames>{}</definedNames>{}",
+            &workbook[..pos],
+            element,
+            &workbook[pos..]
+        )
+    } else {
+        workbook.to_string()
+    }
+}
+
+fn hide_sheets_except(workbook: &str, keep: usize) -> String {
+    let mut index = 0;
+    tag_regex("sheet")
+        .replace_all(workbook, |c: &regex::Captures| {
+            let tag = &c[0];
+            let state = (index != keep).then_some("hidden");
+            index += 1;
+            set_attr(tag, "state", state)
+        })
+        .into_owned()
+}
+
+fn set_xlsx_print_options(xml: &str, gridlines: Option<bool>, headers: Option<bool>) -> String {
+    let flag = |value: Option<bool>| value.map(|v| if v { "1" } else { "0" });
+
+    if let Some(m) = tag_regex("printOptions").find(xml) {
+        let mut tag = m.as_str().to_string();
+        if gridlines.is_some() {
+            tag = set_attr(&tag, "gridLines", flag(gridlines));
+        }
+        if headers.is_some() {
+            tag = set_attr(&tag, "headings", flag(headers));
+        }
+        return format!("{}{}{}", &xml[..m.start()], tag, &xml[m.end()..]);
+    }
+
+    let mut tag = "<printOptions/>".to_string();
+    tag = set_attr(&tag, "gridLines", flag(gridlines));
+    tag = set_attr(&tag, "headings", flag(headers));
+    insert_worksheet_element(xml, "printOptions", &tag)
+}
+
+fn set_xlsx_landscape(xml: &str) -> String {
+    match tag_regex("pageSetup").find(xml) {
+        Some(m) => format!(
+            "{}{}{}",
+            &xml[..m.start()],
+            set_attr(m.as_str(), "orientation", Some("landscape")),
+            &xml[m.end()..]
+        ),
+        None => {
+            insert_worksheet_element(xml, "pageSetup", "<pageSetup orientation=\"landscape\"/>")
+        }
+    }
+}
+
+/// Insert a page setup element before the first element that must follow it
+fn insert_worksheet_element(xml: &str, name: &str, element: &str) -> String {
+    let pos = WORKSHEET_TAIL
+        .iter()
+        .skip_while(|n| **n != name)
+        .skip(1)
+        .filter_map(|n| tag_regex(n).find(xml).map(|m| m.start()))
+        .min()
+        .or_else(|| xml.rfind("</worksheet>"));
+
+    match pos {
+        Some(pos) => format!("{}{}{}", &xml[..pos], element, &xml[pos..]),
+        None => xml.to_string(),
+    }
+}
+
+/// Column count of the worksheet's `<dimension ref="A1:K40"/>`
+fn xlsx_used_columns(xml: &str) -> u32 {
+    let Some(reference) = tag_regex("dimension")
+        .find(xml)
+        .and_then(|m| attr(m.as_str(), "ref"))
+    else {
+        return 0;
+    };
+    let (start, end) = reference
+        .split_once(':')
+        .unwrap_or((&reference, &reference));
+    column_number(end).saturating_sub(column_number(start)) + 1
+}
+
+/// "AB12" -> 28
+fn column_number(cell: &str) -> u32 {
+    cell.chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .fold(0, |n, c| {
+            n * 26 + (c.to_ascii_uppercase() as u32 - 'A' as u32 + 1)
+        })
+}
+
+// ---------------------------------------------------------------------------
+// ODS
+// ---------------------------------------------------------------------------
+
+fn ods_edits<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    options: &ConvertOptions,
+) -> Result<HashMap<String, String>> {
+    let mut content = read_entry(archive, "content.xml")?;
+    let mut edits = HashMap::new();
+
+    if let Some(area) = &options.print_area {
+        let named = if area == USED_RANGE {
+            None
+        } else {
+            let address = tags(&content, "table:named-range")
+                .find(|tag| attr(tag, "table:name").as_deref() == Some(area))
+                .and_then(|tag| attr(tag, "table:cell-range-address"))
+                .ok_or_else(|| {
+                    AppError::InvalidRequest(format!(
+                        "printArea: no range named '{}' in workbook",
+                        area
+                    ))
+                })?;
+            Some((ods_range_sheet(&address), address))
+        };
+
+        content = tag_regex("table:table")
+            .replace_all(&content, |c: &regex::Captures| {
+                let tag = set_attr(&c[0], "table:print-ranges", None);
+                match &named {
+                    None => tag,
+                    Some((sheet, address)) if attr(&tag, "table:name").as_ref() == Some(sheet) => {
+                        let tag = set_attr(&tag, "table:print", None);
+                        set_attr(&tag, "table:print-ranges", Some(&xml_escape(address)))
+                    }
+                    Some(_) => set_attr(&tag, "table:print", Some("false")),
+                }
+            })
+            .into_owned();
+        edits.insert("content.xml".to_string(), content.clone());
+    }
+
+    // Page styles are shared between sheets, so orientation applies to the
+    // whole document: landscape as soon as one printed sheet is wide
+    let wide = options.auto_landscape
+        && ods_used_columns(&content)
+            .into_iter()
+            .any(|columns| columns > WIDE_SHEET_COLUMNS);
+    let landscape = options.landscape || wide;
+
+    if landscape || options.gridlines.is_some() || options.sheet_headers.is_some() {
+        let styles = read_entry(archive, "styles.xml")?;
+        let styles = tag_regex("style:page-layout-properties")
+            .replace_all(&styles, |c: &regex::Captures| {
+                set_ods_page_layout(&c[0], options.gridlines, options.sheet_headers, landscape)
+            })
+            .into_owned();
+        edits.insert("styles.xml".to_string(), styles);
+    }
+
+    Ok(edits)
+}
+
+/// Sheet name of an ODF range address such as "$'My Sheet'.$A$1:.$C$5"
+fn ods_range_sheet(address: &str) -> String {
+    let address = address.trim_start_matches('$');
+    if let Some(quoted) = address.strip_prefix('\'') {
+        let mut name = String::new();
+        let mut chars = quoted.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\'' {
+                if chars.peek() == Some(&'\'') {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            name.push(c);
+        }
+        name
+    } else {
+        address.split('.').next().unwrap_or_default().to_string()
+    }
+}
+
+fn set_ods_page_layout(
+    tag: &str,
+    gridlines: Option<bool>,
+    headers: Option<bool>,
+    landscape: bool,
+) -> String {
+    let current = attr(tag, "style:print").unwrap_or_else(|| ODS_DEFAULT_PRINT.to_string());
+    let mut flags: Vec<&str> = current.split_whitespace().collect();
+    for (flag, value) in [("grid", gridlines), ("headers", headers)] {
+        match value {
+            Some(true) if !flags.contains(&flag) => flags.push(flag),
+            Some(false) => flags.retain(|f| *f != flag),
+            _ => {}
+        }
+    }
+    flags.sort_unstable();
+    let mut tag = set_attr(tag, "style:print", Some(&flags.join(" ")));
+
+    if landscape {
+        tag = set_attr(&tag, "style:print-orientation", Some("landscape"));
+        if let (Some(width), Some(height)) =
+            (attr(&tag, "fo:page-width"), attr(&tag, "fo:page-height"))
+        {
+            if length_value(&width) < length_value(&height) {
+                tag = set_attr(&tag, "fo:page-width", Some(&height));
+                tag = set_attr(&tag, "fo:page-height", Some(&width));
+            }
+        }
+    }
+    tag
+}
+
+/// Numeric part of an ODF length such as "21.001cm"
+fn length_value(length: &str) -> f64 {
+    let number: String = length
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    number.parse().unwrap_or(0.0)
+}
+
+/// Highest used column of every table in content.xml, in document order
+fn ods_used_columns(content: &str) -> Vec<u32> {
+    let re =
+        Regex::new(r"<table:(table|table-row|table-cell|covered-table-cell)(?:\s[^>]*)?>").unwrap();
+    let mut tables = Vec::new();
+    let mut column = 0;
+
+    for c in re.captures_iter(content) {
+        let tag = &c[0];
+        match &c[1] {
+            "table" => tables.push(0),
+            "table-row" => column = 0,
+            _ => {
+                let repeat = attr(tag, "table:number-columns-repeated")
+                    .and_then(|n| n.parse::<u32>().ok())
+                    .unwrap_or(1);
+                if attr(tag, "office:value-type").is_some() {
+                    if let Some(used) = tables.last_mut() {
+                        *used = (*used).max(column + repeat);
+                    }
+                }
+                column += repeat;
+            }
+        }
+    }
+    tables
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xlsx_print_options_schema_order() {
+        let xml = r#"<worksheet><dimension ref="B2:K40"/><sheetData/><pageMargins left="0.7"/><pageSetup orientation="portrait"/></worksheet>"#;
+
+        let xml = set_xlsx_print_options(xml, Some(true), Some(true));
+        assert!(
+            xml.contains(r#"<sheetData/><printOptions gridLines="1" headings="1"/><pageMargins"#)
+        );
+
+        let xml = set_xlsx_print_options(&xml, Some(false), None);
+        assert!(xml.contains(r#"<printOptions headings="1" gridLines="0"/>"#));
+
+        let xml = set_xlsx_landscape(&xml);
+        assert!(xml.contains(r#"<pageSetup orientation="landscape"/>"#));
+        assert_eq!(xlsx_used_columns(&xml), 10);
+    }
+
+    #[test]
+    fn test_ods_page_layout() {
+        let tag =
+            r#"<style:page-layout-properties fo:page-width="21.001cm" fo:page-height="29.7cm"/>"#;
+        let tag = set_ods_page_layout(tag, Some(true), Some(false), true);
+        assert_eq!(
+            tag,
+            r#"<style:page-layout-properties style:print="charts drawings grid objects zeros" style:print-orientation="landscape" fo:page-width="29.7cm" fo:page-height="21.001cm"/>"#
+        );
+
+        assert_eq!(ods_range_sheet("$'Q1 ''24'.$A$1:.$C$5"), "Q1 '24");
+        assert_eq!(ods_range_sheet("$Sheet1.$A$1:.$C$5"), "Sheet1");
+    }
+
+    #[test]
+    fn test_ods_used_columns() {
+        let content = r#"<table:table table:name="A"><table:table-row><table:table-cell table:number-columns-repeated="3"/><table:table-cell office:value-type="float"/><table:table-cell table:number-columns-repeated="1020"/></table:table-row></table:table>"#;
+        assert_eq!(ods_used_columns(content), vec![4]);
+    }
+}
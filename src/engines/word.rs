@@ -0,0 +1,229 @@
+//! Review marks (tracked changes and comments) in Word documents. Unless
+//! explicitly asked to show them, changes are accepted and comments removed
+//! in a copy of the DOCX before LibreOffice renders it.
+
+use super::package::{self, attr, read_entry, tags};
+use super::ConvertOptions;
+use crate::error::{AppError, Result};
+use pdfmill_core::{Comments, TrackChanges};
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Word formats whose review marks are handled
+pub const REVIEW_EXTENSIONS: &[&str] = &["doc", "docx"];
+
+/// Whether the document must be rewritten before rendering
+pub fn strips_review_marks(options: &ConvertOptions) -> bool {
+    options.track_changes == TrackChanges::Accept || options.comments == Comments::Hide
+}
+
+/// Copy the DOCX at `input` to `output`, accepting tracked changes and/or
+/// removing comment anchors as requested. Returns warnings for the review
+/// marks the document had, which are now invisible.
+pub fn apply_review_settings(
+    input: &Path,
+    output: &Path,
+    options: &ConvertOptions,
+) -> Result<Vec<String>> {
+    let mut archive = package::open(input)?;
+    let parts: Vec<String> = archive
+        .file_names()
+        .filter(|name| is_story_part(name))
+        .map(String::from)
+        .collect();
+
+    if parts.is_empty() {
+        return Err(AppError::ConversionFailed(
+            "Document package has no word/document.xml".to_string(),
+        ));
+    }
+
+    let mut edits = HashMap::new();
+    let (mut had_changes, mut had_comments) = (false, false);
+    for part in parts {
+        let mut xml = read_entry(&mut archive, &part)?;
+        if options.track_changes == TrackChanges::Accept {
+            let accepted = accept_changes(&xml);
+            had_changes |= accepted != xml;
+            xml = accepted;
+        }
+        if options.comments == Comments::Hide {
+            let removed = remove_comment_anchors(&xml);
+            had_comments |= removed != xml;
+            xml = removed;
+        }
+        edits.insert(part, xml);
+    }
+
+    package::rewrite(archive, &edits, output)?;
+
+    let mut warnings = Vec::new();
+    if had_changes {
+        warnings.push(
+            "The document has tracked changes, printed as accepted (trackChanges=show prints them)"
+                .to_string(),
+        );
+    }
+    if had_comments {
+        warnings.push(
+            "The document has comments, which were left out (comments=margin prints them)"
+                .to_string(),
+        );
+    }
+    Ok(warnings)
+}
+
+/// Fonts the text of a DOCX asks for by name; theme fonts are not resolved
+pub fn requested_fonts(path: &Path) -> Result<Vec<String>> {
+    let mut archive = package::open(path)?;
+    let parts: Vec<String> = archive
+        .file_names()
+        .filter(|name| is_story_part(name))
+        .map(String::from)
+        .collect();
+
+    let mut fonts = Vec::new();
+    for part in parts {
+        let xml = read_entry(&mut archive, &part)?;
+        for tag in tags(&xml, "w:rFonts") {
+            for name in ["w:ascii", "w:hAnsi"] {
+                if let Some(font) = attr(tag, name).filter(|f| !f.is_empty()) {
+                    if !fonts.contains(&font) {
+                        fonts.push(font);
+                    }
+                }
+            }
+        }
+    }
+    Ok(fonts)
+}
+
+/// Warnings for requested fonts missing from the PDF's fonts, which
+/// LibreOffice replaced with an installed one. PDF names may carry a style
+/// ("Calibri-Bold") and drop spaces ("TimesNewRomanPSMT").
+pub fn substituted_fonts(requested: &[String], embedded: &[String]) -> Vec<String> {
+    let normalize = |name: &str| -> String {
+        name.chars()
+            .filter(char::is_ascii_alphanumeric)
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let embedded: Vec<String> = embedded.iter().map(|name| normalize(name)).collect();
+    requested
+        .iter()
+        .filter(|font| {
+            let font = normalize(font);
+            !font.is_empty() && !embedded.iter().any(|name| name.starts_with(&font))
+        })
+        .map(|font| format!("Font '{}' is not installed and was substituted", font))
+        .collect()
+}
+
+/// Parts holding document text: body, headers, footers, foot- and endnotes
+pub fn is_story_part(name: &str) -> bool {
+    let Some(part) = name
+        .strip_prefix("word/")
+        .and_then(|n| n.strip_suffix(".xml"))
+    else {
+        return false;
+    };
+    matches!(part, "document" | "footnotes" | "endnotes")
+        || ["header", "footer"].iter().any(|prefix| {
+            part.strip_prefix(prefix)
+                .is_some_and(|n| n.chars().all(|c| c.is_ascii_digit()))
+        })
+}
+
+/// Drop deleted and moved-away content, keep inserted content and current
+/// formatting
+fn accept_changes(xml: &str) -> String {
+    let mut xml = xml.to_string();
+
+    // Removed content with everything inside it
+    for element in ["w:del", "w:moveFrom"] {
+        xml = paired_element_regex(element)
+            .replace_all(&xml, "")
+            .into_owned();
+    }
+    // Previous formatting kept for review
+    for element in [
+        "w:rPrChange",
+        "w:pPrChange",
+        "w:sectPrChange",
+        "w:tblPrChange",
+        "w:tblPrExChange",
+        "w:tblGridChange",
+        "w:trPrChange",
+        "w:tcPrChange",
+        "w:numberingChange",
+    ] {
+        xml = paired_element_regex(element)
+            .replace_all(&xml, "")
+            .into_owned();
+    }
+    // Markers (paragraph-mark changes, move ranges) and the wrappers of
+    // inserted content, which stays
+    let markers = Regex::new(
+        r"<w:(?:del|ins|moveFrom|moveTo)(?:\s[^>]*)?/>|</?w:(?:ins|moveTo)(?:\s[^>]*)?>|<w:(?:moveFromRangeStart|moveFromRangeEnd|moveToRangeStart|moveToRangeEnd)(?:\s[^>]*)?/>",
+    )
+    .unwrap();
+    markers.replace_all(&xml, "").into_owned()
+}
+
+/// Without anchors and references LibreOffice imports no comments
+fn remove_comment_anchors(xml: &str) -> String {
+    let anchors =
+        Regex::new(r"<w:(?:commentRangeStart|commentRangeEnd|commentReference)(?:\s[^>]*)?/>")
+            .unwrap();
+    anchors.replace_all(xml, "").into_owned()
+}
+
+/// `<name ...>...</name>`, not matching the self-closing form
+fn paired_element_regex(name: &str) -> Regex {
+    let name = regex::escape(name);
+    Regex::new(&format!(r"(?s)<{name}(?:\s[^>]*[^/])?>.*?</{name}>")).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_changes() {
+        let xml = concat!(
+            r#"<w:p><w:pPr><w:rPr><w:del w:id="3" w:author="A"/></w:rPr></w:pPr>"#,
+            r#"<w:r><w:t>Keep </w:t></w:r>"#,
+            r#"<w:del w:id="1" w:author="A"><w:r><w:delText>secret </w:delText></w:r></w:del>"#,
+            r#"<w:ins w:id="2" w:author="A"><w:r><w:rPr><w:b/><w:rPrChange w:id="4"><w:rPr/></w:rPrChange></w:rPr><w:t>new</w:t></w:r></w:ins></w:p>"#,
+        );
+        assert_eq!(
+            accept_changes(xml),
+            r#"<w:p><w:pPr><w:rPr></w:rPr></w:pPr><w:r><w:t>Keep </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t>new</w:t></w:r></w:p>"#
+        );
+    }
+
+    #[test]
+    fn test_story_parts_and_comments() {
+        assert!(is_story_part("word/document.xml"));
+        assert!(is_story_part("word/header2.xml"));
+        assert!(!is_story_part("word/comments.xml"));
+        assert!(!is_story_part("word/headerfoo.xml"));
+
+        let xml = r#"<w:commentRangeStart w:id="0"/><w:r><w:t>x</w:t></w:r><w:commentRangeEnd w:id="0"/><w:r><w:commentReference w:id="0"/></w:r>"#;
+        assert_eq!(
+            remove_comment_anchors(xml),
+            "<w:r><w:t>x</w:t></w:r><w:r></w:r>"
+        );
+    }
+
+    #[test]
+    fn test_substituted_fonts() {
+        let requested = ["Times New Roman", "Calibri", "Liberation Sans"].map(String::from);
+        let embedded = ["TimesNewRomanPSMT", "Carlito-Bold", "LiberationSans"].map(String::from);
+        assert_eq!(
+            substituted_fonts(&requested, &embedded),
+            vec!["Font 'Calibri' is not installed and was substituted"]
+        );
+    }
+}
@@ -17,12 +17,40 @@ pub enum AppError {
     #[error("Conversion failed: {0}")]
     ConversionFailed(String),
 
+    #[error("Conversion failed: {tool} {}: {stderr}", exit_description(.exit_code))]
+    ToolFailed {
+        tool: String,
+        exit_code: Option<i32>,
+        stderr: String,
+    },
+
+    #[error("Output has {pages} pages, more than the limit of {limit}")]
+    PageLimitExceeded { pages: usize, limit: usize },
+
     #[error("Engine not available: {0}")]
     EngineNotAvailable(String),
 
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Not acceptable: {0}")]
+    NotAcceptable(String),
+
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
+    #[error("Deadline of {} ms exceeded", .0.as_millis())]
+    DeadlineExceeded(std::time::Duration),
+
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -30,6 +58,12 @@ pub enum AppError {
     Internal(String),
 }
 
+impl From<pdfmill_core::OptionError> for AppError {
+    fn from(e: pdfmill_core::OptionError) -> Self {
+        AppError::InvalidRequest(e.0)
+    }
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, message) = match &self {
@@ -37,9 +71,19 @@ impl IntoResponse for AppError {
             AppError::NoFileProvided => (StatusCode::BAD_REQUEST, self.to_string()),
             AppError::InvalidRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             AppError::ConversionFailed(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+            AppError::ToolFailed { .. } => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+            AppError::PageLimitExceeded { .. } => {
+                (StatusCode::UNPROCESSABLE_ENTITY, self.to_string())
+            }
             AppError::EngineNotAvailable(_) => {
                 (StatusCode::SERVICE_UNAVAILABLE, self.to_string())
             }
+            AppError::Forbidden(_) => (StatusCode::FORBIDDEN, self.to_string()),
+            AppError::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+            AppError::Conflict(_) => (StatusCode::CONFLICT, self.to_string()),
+            AppError::NotAcceptable(_) => (StatusCode::NOT_ACCEPTABLE, self.to_string()),
+            AppError::PayloadTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
+            AppError::DeadlineExceeded(_) => (StatusCode::GATEWAY_TIMEOUT, self.to_string()),
             AppError::IoError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             AppError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
         };
@@ -52,4 +96,11 @@ impl IntoResponse for AppError {
     }
 }
 
+fn exit_description(exit_code: &Option<i32>) -> String {
+    match exit_code {
+        Some(code) => format!("exited with code {}", code),
+        None => "was terminated by a signal".to_string(),
+    }
+}
+
 pub type Result<T> = std::result::Result<T, AppError>;
@@ -0,0 +1,47 @@
+use super::AppState;
+use crate::error::Result;
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Store a shared asset from the raw request body (e.g. `curl -T logo.png`);
+/// answered with 201 when it is new
+pub async fn put_asset_handler(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Result<Response> {
+    let (asset, created) = state.assets.put(&name, body)?;
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, [(header::LOCATION, asset.url.clone())], Json(asset)).into_response())
+}
+
+pub async fn list_assets_handler(State(state): State<Arc<AppState>>) -> Json<Value> {
+    Json(json!({ "assets": state.assets.list() }))
+}
+
+pub async fn get_asset_handler(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Response> {
+    let (data, content_type) = state.assets.get(&name)?;
+    Ok(([(header::CONTENT_TYPE, content_type)], data).into_response())
+}
+
+pub async fn delete_asset_handler(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode> {
+    state.assets.delete(&name)?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,47 @@
+//! `/demo`: a page with an upload form and the conversion options, built
+//! from the options schema, for trying a deployment from a browser, and
+//! tiny sample inputs it can convert without a file at hand.
+
+use crate::assets::content_type;
+use crate::error::{AppError, Result};
+use axum::{
+    extract::Path,
+    http::header,
+    response::{Html, IntoResponse, Response},
+};
+use std::sync::LazyLock;
+
+/// Sample inputs, by file name
+const FIXTURES: &[(&str, &[u8])] = &[
+    ("sample.md", include_bytes!("demo/fixtures/sample.md")),
+    ("sample.html", include_bytes!("demo/fixtures/sample.html")),
+    (
+        "sample.docx",
+        include_bytes!("../selftest/fixtures/selftest.docx"),
+    ),
+    (
+        "sample.png",
+        include_bytes!("../selftest/fixtures/selftest.png"),
+    ),
+];
+
+/// The page, with the samples listed in its form
+static PAGE: LazyLock<String> = LazyLock::new(|| {
+    let fixtures: String = FIXTURES
+        .iter()
+        .map(|(name, _)| format!("                <option>{}</option>\n", name))
+        .collect();
+    include_str!("demo/index.html").replace("<!-- fixtures -->\n", &fixtures)
+});
+
+pub async fn demo_handler() -> Html<&'static str> {
+    Html(PAGE.as_str())
+}
+
+pub async fn demo_fixture_handler(Path(name): Path<String>) -> Result<Response> {
+    let (name, data) = FIXTURES
+        .iter()
+        .find(|(fixture, _)| *fixture == name)
+        .ok_or_else(|| AppError::NotFound(format!("Sample '{}' not found", name)))?;
+    Ok(([(header::CONTENT_TYPE, content_type(name))], *data).into_response())
+}
@@ -0,0 +1,368 @@
+use super::{
+    api_path, attach_warnings, check_output_format, download_response, parse_convert_form, AppState,
+};
+use crate::encryption;
+use crate::error::{AppError, Result};
+use crate::jobs::{
+    api_key_client, client_key, ip_client, JobFilter, JobInfo, PurgeFilter, DEFAULT_PAGE_SIZE,
+    MAX_PAGE_SIZE,
+};
+use crate::mailer::parse_recipients;
+use crate::manifest::{FileDigest, Manifest, SIGNATURE_HEADER};
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Multipart, OriginalUri, Path, Query, State},
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Submit a conversion job - same form as /convert, answered with 202 and
+/// the job's status URL
+pub async fn submit_job_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    uri: OriginalUri,
+    Query(query): Query<Vec<(String, String)>>,
+    multipart: Multipart,
+) -> Result<Response> {
+    let mut form = parse_convert_form(multipart, &state, &query).await?;
+    let (filename, data) = form.take_file()?;
+
+    // Reject unsupported inputs now rather than as a failed job
+    let engine = state
+        .router
+        .find_engine_for_file(std::path::Path::new(&filename))?;
+    check_output_format(engine.as_ref(), &form.options)?;
+
+    let email_to = match form.extra.get("emailTo").filter(|v| !v.trim().is_empty()) {
+        Some(value) => parse_recipients(value)?,
+        None => Vec::new(),
+    };
+
+    let job = state
+        .jobs
+        .submit(
+            Arc::clone(&state.router),
+            client_key(&headers, state.access.request_client(&headers, peer.ip())),
+            filename,
+            data,
+            form.options,
+            email_to,
+        )
+        .await?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        [(
+            header::LOCATION,
+            api_path(&uri, &format!("/jobs/{}", job.id)),
+        )],
+        Json(job),
+    )
+        .into_response())
+}
+
+/// Query string of the job listing; values are validated by hand so errors
+/// use the usual JSON error body
+#[derive(Debug, Deserialize)]
+pub struct ListJobsQuery {
+    status: Option<String>,
+    since: Option<String>,
+    limit: Option<String>,
+    offset: Option<String>,
+}
+
+/// List jobs, newest first, optionally filtered by status and creation time
+pub async fn list_jobs_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ListJobsQuery>,
+) -> Result<Json<Value>> {
+    let filter = JobFilter {
+        status: query
+            .status
+            .map(|s| s.parse())
+            .transpose()
+            .map_err(|e| AppError::InvalidRequest(format!("status: {}", e)))?,
+        since: query
+            .since
+            .map(|s| DateTime::parse_from_rfc3339(s.trim()).map(|t| t.with_timezone(&Utc)))
+            .transpose()
+            .map_err(|e| {
+                AppError::InvalidRequest(format!("since: expected an RFC 3339 timestamp: {}", e))
+            })?,
+    };
+    let limit = parse_count("limit", query.limit)?
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = parse_count("offset", query.offset)?.unwrap_or(0);
+
+    let (total, jobs) = state.jobs.list(&filter, offset, limit);
+    Ok(Json(json!({
+        "jobs": jobs,
+        "total": total,
+        "limit": limit,
+        "offset": offset,
+    })))
+}
+
+fn parse_count(name: &str, value: Option<String>) -> Result<Option<usize>> {
+    value
+        .map(|v| v.trim().parse::<usize>())
+        .transpose()
+        .map_err(|_| AppError::InvalidRequest(format!("{}: expected a non-negative integer", name)))
+}
+
+/// Re-run a failed job from its stored input. Option fields in the body
+/// replace the previous options; `engine` forces a specific engine.
+pub async fn retry_job_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    multipart: Option<Multipart>,
+) -> Result<Json<JobInfo>> {
+    let (mut options, mut engine_name) = state.jobs.settings(id)?;
+    if let Some(multipart) = multipart {
+        let form = parse_convert_form(multipart, &state, &[]).await?;
+        if !form.files.is_empty() {
+            return Err(AppError::InvalidRequest(
+                "file: a retry reuses the job's stored input".to_string(),
+            ));
+        }
+        let forced = form.extra.get("engine").map(|e| e.trim().to_string());
+        // Every text field that is not an extra one is a conversion option
+        if form.fields > form.extra.len() {
+            options = form.options;
+        }
+        if let Some(name) = forced.filter(|e| !e.is_empty()) {
+            engine_name = Some(name);
+        }
+    }
+
+    let job = state.jobs.get(id)?;
+    let input = std::path::Path::new(&job.filename);
+    let engine = match engine_name.as_deref() {
+        Some(name) => state.router.find_named_engine_for_file(name, input)?,
+        None => state.router.find_engine_for_file(input)?,
+    };
+    check_output_format(engine.as_ref(), &options)?;
+
+    let job = state
+        .jobs
+        .retry(Arc::clone(&state.router), id, Some(options), engine_name)?;
+    Ok(Json(job))
+}
+
+/// Current state of a job
+pub async fn job_status_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JobInfo>> {
+    Ok(Json(state.jobs.get(id)?))
+}
+
+/// Download the output of a succeeded job
+pub async fn job_result_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Response> {
+    let output = state.jobs.output(id)?;
+    let job = state.jobs.get(id)?;
+    let body = Body::from_stream(encryption::stream(&output.path).await?);
+
+    let mut response = download_response(
+        body,
+        &output.content_type,
+        &output.filename,
+        job.engine.as_deref().unwrap_or_default(),
+        job.sha256.as_deref(),
+    );
+    attach_warnings(&mut response, &output.warnings);
+    Ok(response)
+}
+
+/// Signed manifest of a succeeded job's output. The body is exactly the
+/// signed bytes; the signature is in a header.
+pub async fn job_manifest_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Response> {
+    let signer = state
+        .manifests
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("Manifest signing is not configured".to_string()))?;
+    let job = state.jobs.provenance(id)?;
+    let manifest = Manifest {
+        input: FileDigest {
+            filename: &job.info.filename,
+            sha256: &job.input_sha256,
+        },
+        output: FileDigest {
+            filename: &job.output.filename,
+            sha256: &job.output.sha256,
+        },
+        options: &job.options,
+        engine: job.info.engine.as_deref().unwrap_or_default(),
+        engines: state.router.engine_versions(),
+        created_at: job.info.finished_at.unwrap_or(job.info.created_at),
+    };
+    let signed = signer.sign(&manifest)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (
+                HeaderName::from_static(SIGNATURE_HEADER),
+                signed.signature_base64(),
+            ),
+        ],
+        signed.body,
+    )
+        .into_response())
+}
+
+/// Cancel a queued or running job; its files are deleted
+pub async fn cancel_job_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JobInfo>> {
+    Ok(Json(state.jobs.cancel(id)?))
+}
+
+/// Delete a job's input, output and options now rather than when it
+/// expires; unfinished jobs are cancelled
+pub async fn delete_job_data_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JobInfo>> {
+    Ok(Json(state.jobs.delete_data(id)?))
+}
+
+/// Jobs younger than this are only purged by age with `all`, so a zero or
+/// mistyped age cannot remove every job
+const MIN_PURGE_AGE: i64 = 60;
+
+/// Body of a purge; at least one criterion, or `all`, is required so an
+/// empty body cannot remove every job
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PurgeRequest {
+    /// Jobs created before this RFC 3339 time
+    before: Option<String>,
+    /// Jobs created more than this many seconds ago
+    older_than: Option<u64>,
+    /// Jobs submitted with this API key
+    api_key: Option<String>,
+    /// Jobs submitted from this address, with or without an API key
+    ip: Option<IpAddr>,
+    /// Confirms a purge that may remove every job, or ones not a minute old
+    #[serde(default)]
+    all: bool,
+}
+
+impl PurgeRequest {
+    /// Check the criteria, taking ages back from `now`
+    fn filter(self, now: DateTime<Utc>) -> Result<PurgeFilter> {
+        let invalid = |message: &str| AppError::InvalidRequest(message.to_string());
+
+        let before = match (self.before, self.older_than) {
+            (Some(_), Some(_)) => return Err(invalid("give either before or olderThan, not both")),
+            (Some(before), None) => {
+                let before = DateTime::parse_from_rfc3339(before.trim()).map_err(|e| {
+                    AppError::InvalidRequest(format!(
+                        "before: expected an RFC 3339 timestamp: {}",
+                        e
+                    ))
+                })?;
+                Some(before.with_timezone(&Utc))
+            }
+            (None, Some(seconds)) => {
+                let age = i64::try_from(seconds)
+                    .ok()
+                    .and_then(chrono::Duration::try_seconds)
+                    .ok_or_else(|| invalid("olderThan: too large"))?;
+                Some(now - age)
+            }
+            (None, None) => None,
+        };
+        let client = match (self.api_key, self.ip) {
+            (Some(_), Some(_)) => return Err(invalid("give either apiKey or ip, not both")),
+            (Some(key), None) if key.trim().is_empty() => return Err(invalid("apiKey: empty")),
+            (Some(key), None) => Some(api_key_client(&key)),
+            (None, Some(ip)) => Some(ip_client(ip)),
+            (None, None) => None,
+        };
+        if !self.all {
+            if before.is_none() && client.is_none() {
+                return Err(invalid(
+                    "give before or olderThan and/or apiKey or ip, or all: true",
+                ));
+            }
+            if before.is_some_and(|before| now - before < chrono::Duration::seconds(MIN_PURGE_AGE))
+            {
+                return Err(AppError::InvalidRequest(format!(
+                    "purging jobs less than {}s old requires all: true",
+                    MIN_PURGE_AGE
+                )));
+            }
+        }
+        Ok(PurgeFilter { before, client })
+    }
+}
+
+/// Remove jobs and their files by age and/or client, for retention and
+/// erasure processes
+pub async fn purge_jobs_handler(
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
+) -> Result<Json<Value>> {
+    let request: PurgeRequest = serde_json::from_slice(&body)
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid purge request: {}", e)))?;
+    let purged = state.jobs.purge(&request.filter(Utc::now())?);
+    Ok(Json(json!({ "purged": purged.len(), "jobs": purged })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_purge_filter() {
+        let now = Utc::now();
+        let filter = |body: Value| {
+            serde_json::from_value::<PurgeRequest>(body)
+                .unwrap()
+                .filter(now)
+        };
+
+        let aged = filter(json!({"olderThan": 86400, "apiKey": "batch"})).unwrap();
+        assert_eq!(aged.before, Some(now - chrono::Duration::days(1)));
+        assert_eq!(aged.client, Some(api_key_client("batch")));
+
+        // Dual-stack peers are queued under their IPv4 address
+        let by_ip = filter(json!({"olderThan": 86400, "ip": "::ffff:192.0.2.7"})).unwrap();
+        assert_eq!(by_ip.client, Some("ip:192.0.2.7".to_string()));
+
+        // Everything, or everything just submitted, only when asked for
+        for body in [
+            json!({}),
+            json!({"olderThan": 0}),
+            json!({"olderThan": 5}),
+            json!({"before": now.to_rfc3339()}),
+        ] {
+            assert!(matches!(filter(body), Err(AppError::InvalidRequest(_))));
+        }
+        let all = filter(json!({"all": true})).unwrap();
+        assert!(all.before.is_none() && all.client.is_none());
+        assert_eq!(
+            filter(json!({"olderThan": 0, "all": true})).unwrap().before,
+            Some(now)
+        );
+    }
+}
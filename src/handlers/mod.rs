@@ -1,115 +1,1389 @@
-use crate::engines::ConvertOptions;
+use crate::access::AccessPolicy;
+use crate::archive;
+use crate::assets::AssetStore;
+use crate::bench::{self, BenchRequest};
+use crate::canary::Canary;
+use crate::disk::{Upload, UploadWriter};
+use crate::engines::{
+    audit_links, file_size, ignored_options, options_schema, parse_integer, parse_records,
+    sha256_hex, template_fields, upload_filename, ConvertEngine, ConvertOptions, ConvertResult,
+    EngineType, REVERSE_FORMATS, USED_RANGE,
+};
 use crate::error::{AppError, Result};
-use crate::router::SmartRouter;
+use crate::jobs::{client_key, handoff_after, JobManager};
+use crate::manifest::{
+    embed_provenance, FileDigest, Manifest, ManifestSigner, ProvenanceRecord, SignedManifest,
+    MANIFEST_HEADER, SIGNATURE_HEADER,
+};
+use crate::metrics::{self, Exposition};
+use crate::negotiate::{first_page_png, negotiate, Representation, StoredResult};
+use crate::pdf;
+use crate::pipeline::{Pipeline, PIPELINE_FIELD};
+use crate::presets::{Presets, PRESET_FIELD};
+use crate::related::{self, Metadata, Timings};
+use crate::router::{SmartRouter, REMOTE_PAGE};
+use crate::schedules::ScheduleManager;
+use crate::selftest;
+use crate::sniff::ContentCheck;
+use crate::templates::TemplateStore;
 use axum::{
-    body::Bytes,
-    extract::{Multipart, State},
-    http::{header, StatusCode},
+    body::Body,
+    extract::{multipart::Field, ConnectInfo, Multipart, OriginalUri, Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use chromiumoxide::cdp::browser_protocol::page::CaptureScreenshotFormat;
+use chrono::Utc;
+use serde::Deserialize;
 use serde_json::json;
-use std::sync::Arc;
-use tracing::{error, info};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+mod assets;
+mod demo;
+mod jobs;
+mod schedules;
+mod templates;
+
+pub use assets::{delete_asset_handler, get_asset_handler, list_assets_handler, put_asset_handler};
+pub use demo::{demo_fixture_handler, demo_handler};
+pub use jobs::{
+    cancel_job_handler, delete_job_data_handler, job_manifest_handler, job_result_handler,
+    job_status_handler, list_jobs_handler, purge_jobs_handler, retry_job_handler,
+    submit_job_handler,
+};
+pub use schedules::{
+    create_schedule_handler, delete_schedule_handler, get_schedule_handler, list_schedules_handler,
+    update_schedule_handler,
+};
+pub use templates::{
+    delete_template_handler, get_template_asset_handler, get_template_handler,
+    list_templates_handler, put_template_handler, render_handler, rollback_template_handler,
+    template_versions_handler,
+};
 
 pub struct AppState {
-    pub router: SmartRouter,
+    pub router: Arc<SmartRouter>,
+    pub jobs: Arc<JobManager>,
+    pub schedules: Arc<ScheduleManager>,
+    /// Handling of uploads that do not look like their extension
+    pub content_check: ContentCheck,
+    /// Signs provenance manifests, when a key is configured
+    pub manifests: Option<ManifestSigner>,
+    /// Second HTML renderer sampled conversions are compared against
+    pub canary: Option<Arc<Canary>>,
+    /// Named option presets from the operator's config
+    pub presets: Presets,
+    /// Stored templates for /render
+    pub templates: TemplateStore,
+    /// Shared assets documents refer to as /assets/<name>
+    pub assets: Arc<AssetStore>,
+    /// Client IP access control, also naming the client jobs are queued for
+    pub access: Arc<AccessPolicy>,
+    /// False when no engine is available and the operator asked /health to
+    /// say so
+    pub ready: bool,
 }
 
-/// Main conversion endpoint - automatically routes based on file extension
-pub async fn convert_handler(
-    State(state): State<Arc<AppState>>,
+/// A parsed conversion form: the uploaded file, the known options and any
+/// endpoint-specific fields left over
+struct ConvertForm {
+    /// Uploaded files in upload order
+    files: Vec<(String, Upload)>,
+    options: ConvertOptions,
+    extra: HashMap<String, String>,
+    /// Number of text fields, option or extra
+    fields: usize,
+}
+
+impl ConvertForm {
+    /// The file of a single-file endpoint
+    fn take_file(&mut self) -> Result<(String, Upload)> {
+        if self.files.len() > 1 {
+            return Err(AppError::InvalidRequest(format!(
+                "file: expected one file, got {}",
+                self.files.len()
+            )));
+        }
+        self.files.pop().ok_or(AppError::NoFileProvided)
+    }
+}
+
+/// Default limit of a text field's size, in KiB
+const DEFAULT_MAX_FIELD_KB: usize = 1024;
+
+/// Largest text field (option, mail merge data, pipeline) a form may carry:
+/// `PDFMILL_MAX_FIELD_KB` (default 1024)
+static MAX_FIELD_BYTES: LazyLock<usize> = LazyLock::new(|| {
+    std::env::var("PDFMILL_MAX_FIELD_KB")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_FIELD_KB)
+        * 1024
+});
+
+/// Read a text field, failing once it grows past the size limit instead of
+/// buffering it whole
+async fn read_text_field(field: &mut Field<'_>, name: &str) -> Result<String> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(|e| AppError::InvalidRequest(format!("Failed to read {}: {}", name, e)))?
+    {
+        if value.len() + chunk.len() > *MAX_FIELD_BYTES {
+            return Err(AppError::PayloadTooLarge(format!(
+                "{}: field is larger than {}",
+                name,
+                file_size(*MAX_FIELD_BYTES as u64)
+            )));
+        }
+        value.extend_from_slice(&chunk);
+    }
+    String::from_utf8(value)
+        .map_err(|_| AppError::InvalidRequest(format!("{}: expected UTF-8 text", name)))
+}
+
+/// Parse a conversion form. Options start from the selected preset (the
+/// `preset` field, or query parameter), then query parameters and then
+/// form fields override it. Parameters that are not options are ignored.
+async fn parse_convert_form(
     mut multipart: Multipart,
-) -> Result<Response> {
-    let mut file_data: Option<(String, Vec<u8>)> = None;
-    let mut options = ConvertOptions::default();
+    state: &AppState,
+    query: &[(String, String)],
+) -> Result<ConvertForm> {
+    let check = state.content_check;
+    let mut preset = query
+        .iter()
+        .find(|(name, _)| name == PRESET_FIELD)
+        .map(|(_, value)| value.clone());
+    // Reject bad query options and presets before reading the upload
+    state.presets.options(preset.as_deref())?;
+    let mut checked = ConvertOptions::default();
+    for (name, value) in query {
+        checked.set_field(name, value)?;
+    }
+    let mut option_fields = Vec::new();
+    let mut files = Vec::new();
+    let mut extra = HashMap::new();
+    let mut fields = 0;
+    let mut seen = HashSet::new();
 
     // Parse multipart form data
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
-        AppError::InvalidRequest(format!("Failed to parse multipart data: {}", e))
-    })? {
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::InvalidRequest(format!("Failed to parse multipart data: {}", e)))?
+    {
         let name = field.name().unwrap_or("").to_string();
+        if name != "file" {
+            fields += 1;
+            // The same option twice would leave it to the client library
+            // which value counts
+            if !seen.insert(name.clone()) {
+                return Err(AppError::InvalidRequest(format!(
+                    "{}: field sent more than once",
+                    name
+                )));
+            }
+        }
 
         match name.as_str() {
             "file" => {
+                // Stored under this name, so it must not name another path
                 let filename = field
                     .file_name()
-                    .ok_or_else(|| AppError::InvalidRequest("No filename provided".to_string()))?
-                    .to_string();
+                    .ok_or_else(|| AppError::InvalidRequest("No filename provided".to_string()))
+                    .and_then(|raw| Ok(upload_filename(raw)?))?;
+                let content_type = field.content_type().map(str::to_string);
 
-                let data = field.bytes().await.map_err(|e| {
-                    AppError::InvalidRequest(format!("Failed to read file data: {}", e))
-                })?;
+                let read_error =
+                    |e| AppError::InvalidRequest(format!("Failed to read file data: {}", e));
+                // Large files go to disk as they arrive, never held whole
+                let mut writer = UploadWriter::new();
+                while let Some(chunk) = field.chunk().await.map_err(read_error)? {
+                    writer.write(&chunk).await?;
+                }
+                let data = writer.finish().await?;
 
-                info!("Received file: {} ({} bytes)", filename, data.len());
-                file_data = Some((filename, data.to_vec()));
+                info!("Received file: {} ({} bytes)", filename, data.size());
+                check.verify(&filename, content_type.as_deref(), data.head())?;
+                files.push((filename, data));
             }
-            "landscape" => {
-                if let Ok(value) = field.text().await {
-                    options.landscape = value == "true" || value == "1";
-                }
+            PRESET_FIELD => {
+                let value = read_text_field(&mut field, &name).await?;
+                state.presets.options(Some(&value))?;
+                preset = Some(value);
             }
-            "printBackground" => {
-                if let Ok(value) = field.text().await {
-                    options.print_background = value == "true" || value == "1";
+            _ => {
+                // Keep unknown text fields for endpoint-specific handling
+                let value = read_text_field(&mut field, &name).await?;
+                if checked.set_field(&name, &value)? {
+                    option_fields.push((name, value));
+                } else {
+                    extra.insert(name, value);
                 }
             }
-            "pageWidth" => {
-                if let Ok(value) = field.text().await {
-                    options.page_width = Some(value);
+        }
+    }
+
+    let mut options = state.presets.options(preset.as_deref())?;
+    for (name, value) in query.iter().chain(&option_fields) {
+        options.set_field(name, value)?;
+    }
+    options.check_layout()?;
+
+    Ok(ConvertForm {
+        files,
+        options,
+        extra,
+        fields,
+    })
+}
+
+/// Main conversion endpoint - automatically routes based on file extension
+pub async fn convert_handler(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    uri: OriginalUri,
+    Query(query): Query<Vec<(String, String)>>,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Result<Response> {
+    let started = Instant::now();
+    let mut form = parse_convert_form(multipart, &state, &query).await?;
+    let (filename, data) = form.take_file()?;
+    let options = form.options;
+    let pipeline = form
+        .extra
+        .get(PIPELINE_FIELD)
+        .map(|json| Pipeline::parse(json))
+        .transpose()?;
+    if pipeline.as_ref().is_some_and(Pipeline::has_steps)
+        && options.output_format.as_deref().is_some_and(|f| f != "pdf")
+    {
+        return Err(AppError::InvalidRequest(format!(
+            "{}: steps after convert need PDF output",
+            PIPELINE_FIELD
+        )));
+    }
+    // Refuse an Accept header no response satisfies before converting
+    let file_type = match options.output_format.as_deref() {
+        None | Some("pdf") => "application/pdf".to_string(),
+        Some(format) => mime_guess::from_ext(format)
+            .first_or_octet_stream()
+            .to_string(),
+    };
+    let representation = negotiate(&headers, &file_type)?;
+    // A conversion that may be handed off to a job runs from a file the
+    // job can be retried from
+    let handoff = handoff_after(&headers);
+    let input_sha256 = (state.manifests.is_some()
+        || handoff.is_some()
+        || options.embed_provenance
+        || representation == Representation::Json)
+        .then(|| data.sha256());
+
+    // Find the appropriate engine based on file extension
+    let temp_dir = crate::disk::tempdir()?;
+    let input_path = temp_dir.path().join(&filename);
+    let engine = state.router.find_engine_for_file(&input_path)?;
+    info!("Using {:?} engine for {}", engine.engine_type(), filename);
+
+    // Save to temp file, unless the engine takes the upload from memory and
+    // nothing reads the file afterwards
+    let ext = input_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let in_memory = match data {
+        Upload::Memory(data)
+            if !options.link_audit
+                && state.canary.is_none()
+                && handoff.is_none()
+                && engine.reads_memory(&ext, data.len(), &options) =>
+        {
+            Some(state.router.prepare_input_data(&filename, data).await?)
+        }
+        data => {
+            data.save(&input_path).await?;
+            state.router.prepare_input(&input_path).await?;
+            None
+        }
+    };
+
+    check_output_format(engine.as_ref(), &options)?;
+    let ignored = ignored_options(engine.as_ref(), &input_path, &options);
+    let engine_label = state.router.engine_label(&engine.engine_type());
+
+    // Perform the conversion, along with everything up to the response
+    // that a job taking it over would have to do as well
+    let processing = {
+        let router = Arc::clone(&state.router);
+        let canary = state.canary.clone();
+        let engine = Arc::clone(&engine);
+        let input_path = input_path.clone();
+        let filename = filename.clone();
+        let options = options.clone();
+        let input_sha256 = input_sha256.clone();
+        let engine_label = engine_label.clone();
+        async move {
+            let converting = Instant::now();
+            let slot = router.conversion_slot().await;
+            let converted = match &in_memory {
+                Some(data) => engine.convert_memory(&filename, data, &options).await,
+                None => engine.convert(&input_path, &options).await,
+            };
+            let converted = match converted {
+                Ok(result) => {
+                    router
+                        .append_attachments(&input_path, result, &options)
+                        .await
                 }
-            }
-            "pageHeight" => {
-                if let Ok(value) = field.text().await {
-                    options.page_height = Some(value);
+                Err(e) => Err(e),
+            };
+            let result = match converted {
+                Ok(result) => router.finish(result).await,
+                Err(e) => Err(e),
+            };
+            drop(slot);
+            let conversion_ms = converting.elapsed().as_millis() as u64;
+            metrics::record_conversion(engine.engine_type().name(), result.is_ok());
+            let (mut result, link_report) = match result? {
+                result if options.link_audit => audit_links(&input_path, result).await?,
+                result => (result, None),
+            };
+            result.warnings.splice(0..0, ignored);
+
+            if let Some(canary) = &canary {
+                let is_html = input_path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| matches!(e.to_lowercase().as_str(), "html" | "htm" | "xhtml"));
+                if engine.engine_type() == EngineType::Chromium && is_html && canary.sample() {
+                    spawn_canary(
+                        Arc::clone(canary),
+                        &input_path,
+                        &filename,
+                        result.data.clone(),
+                    )
+                    .await;
                 }
             }
-            "pdfFormat" => {
-                if let Ok(value) = field.text().await {
-                    options.pdf_format = Some(value);
+
+            // Before the pipeline, which may encrypt the document
+            let result = match &input_sha256 {
+                Some(sha256) if options.embed_provenance => {
+                    let record = ProvenanceRecord {
+                        input: FileDigest {
+                            filename: &filename,
+                            sha256,
+                        },
+                        options: &options,
+                        engine: &engine_label,
+                        created_at: Utc::now(),
+                    };
+                    embed_provenance(result, &record).await?
+                }
+                _ => result,
+            };
+
+            let result = match &pipeline {
+                Some(pipeline) => {
+                    info!("Running pipeline {} for {}", pipeline.describe(), filename);
+                    pipeline.run(result).await?
+                }
+                None => result,
+            };
+            Result::Ok((result, link_report, conversion_ms))
+        }
+    };
+    let processed = match handoff {
+        Some(after) => {
+            let mut processing = Box::pin(processing);
+            match tokio::time::timeout(after, &mut processing).await {
+                Ok(processed) => processed,
+                Err(_) => {
+                    // The job carries on with the conversion and the
+                    // workspace holding its input
+                    let job = state.jobs.adopt(
+                        client_key(&headers, state.access.request_client(&headers, peer.ip())),
+                        filename,
+                        temp_dir,
+                        input_sha256.unwrap_or_default(),
+                        options,
+                        engine_label,
+                        async move {
+                            let (result, link_report, _) = processing.await?;
+                            Ok((result, link_report))
+                        },
+                    );
+                    info!("Handed off to job {} after {}s", job.id, after.as_secs());
+                    return Ok((
+                        StatusCode::ACCEPTED,
+                        [(
+                            header::LOCATION,
+                            api_path(&uri, &format!("/jobs/{}", job.id)),
+                        )],
+                        Json(job),
+                    )
+                        .into_response());
                 }
-            }
-            _ => {
-                // Ignore unknown fields
             }
         }
+        None => processing.await,
+    };
+    let (mut result, link_report, conversion_ms) = processed?;
+
+    // Image targets of several pages come back as a ZIP, which the Accept
+    // header was not checked against before converting
+    if representation == Representation::File
+        && result.content_type != file_type
+        && negotiate(&headers, &result.content_type)? != Representation::File
+    {
+        return Err(AppError::NotAcceptable(format!(
+            "the output is {}, which the Accept header does not allow",
+            result.content_type
+        )));
     }
 
-    let (filename, data) = file_data.ok_or(AppError::NoFileProvided)?;
+    if representation == Representation::Png {
+        if result.content_type != "application/pdf" {
+            return Err(AppError::NotAcceptable(format!(
+                "image/png previews PDF output, not {}",
+                result.content_type
+            )));
+        }
+        result = ConvertResult {
+            data: first_page_png(&result.data).await?,
+            filename: Path::new(&result.filename)
+                .with_extension("png")
+                .to_string_lossy()
+                .into_owned(),
+            content_type: "image/png".to_string(),
+            warnings: result.warnings,
+        };
+    }
 
-    // Save to temp file
-    let temp_dir = tempfile::tempdir()?;
-    let input_path = temp_dir.path().join(&filename);
-    tokio::fs::write(&input_path, &data).await?;
+    // Return the result in the representation the client asked for
+    let manifest = match (&state.manifests, &input_sha256) {
+        (Some(signer), Some(input_sha256)) => {
+            let output_sha256 = sha256_hex(&result.data);
+            let manifest = Manifest {
+                input: FileDigest {
+                    filename: &filename,
+                    sha256: input_sha256,
+                },
+                output: FileDigest {
+                    filename: &result.filename,
+                    sha256: &output_sha256,
+                },
+                options: &options,
+                engine: &engine_label,
+                engines: state.router.engine_versions(),
+                created_at: Utc::now(),
+            };
+            Some(signer.sign(&manifest)?)
+        }
+        _ => None,
+    };
 
-    // Find the appropriate engine based on file extension
-    let engine = state.router.find_engine_for_file(&input_path)?;
-    info!("Using {:?} engine for {}", engine.engine_type(), filename);
+    let mut response = match representation {
+        Representation::File | Representation::Png => file_response(result, &engine_label),
+        Representation::Related => related_response(
+            result,
+            &engine_label,
+            conversion_ms,
+            started,
+            link_report.clone(),
+        ),
+        Representation::Json => {
+            let sha256 = sha256_hex(&result.data);
+            let timings = Timings {
+                conversion_ms,
+                total_ms: started.elapsed().as_millis() as u64,
+            };
+            let mut metadata =
+                Metadata::new(&result, sha256, &engine_label, timings, link_report.clone());
+            let job = state
+                .jobs
+                .keep(
+                    client_key(&headers, state.access.request_client(&headers, peer.ip())),
+                    filename,
+                    temp_dir,
+                    input_sha256.unwrap_or_default(),
+                    options,
+                    engine_label.clone(),
+                    result,
+                    link_report.clone(),
+                )
+                .await?;
+            metadata.document = api_path(&uri, &format!("/jobs/{}/result", job.id));
+            let warnings = metadata.warnings.clone();
+            let mut response = (
+                [(HeaderName::from_static(ENGINE_HEADER), engine_label)],
+                Json(StoredResult { metadata, job }),
+            )
+                .into_response();
+            attach_warnings(&mut response, &warnings);
+            response
+        }
+    };
+    // Caches must not hand one representation to a client asking another
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    if let Some(manifest) = manifest {
+        attach_manifest(&mut response, &manifest);
+    }
+    if let Some(report) = link_report {
+        attach_link_report(&mut response, &report);
+    }
+    Ok(response)
+}
+
+/// Return the result after a JSON part describing the conversion, for
+/// clients that accept `multipart/related`
+fn related_response(
+    result: ConvertResult,
+    engine_label: &str,
+    conversion_ms: u64,
+    started: Instant,
+    link_report: Option<pdf::LinkReport>,
+) -> Response {
+    let sha256 = sha256_hex(&result.data);
+    let timings = Timings {
+        conversion_ms,
+        total_ms: started.elapsed().as_millis() as u64,
+    };
+    // The file's SHA-256 goes in the metadata, not in a header that would
+    // seem to describe the whole body
+    let metadata = Metadata::new(&result, sha256, engine_label, timings, link_report);
+    let warnings = metadata.warnings.clone();
+    let (body, content_type) = related::encode(&metadata, result);
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, content_type),
+            (
+                HeaderName::from_static(ENGINE_HEADER),
+                engine_label.to_string(),
+            ),
+        ],
+        body,
+    )
+        .into_response();
+    attach_warnings(&mut response, &warnings);
+    response
+}
 
-    // Perform the conversion
-    let result = engine.convert(&input_path, &options).await?;
+/// Compare a sampled conversion with the canary renderer in the background,
+/// on a copy of the input that outlives the request
+async fn spawn_canary(canary: Arc<Canary>, input_path: &Path, filename: &str, primary: Vec<u8>) {
+    let copied = async {
+        let dir = crate::disk::tempdir()?;
+        let copy = dir.path().join(filename);
+        tokio::fs::copy(input_path, &copy).await?;
+        std::io::Result::Ok((dir, copy))
+    };
+    match copied.await {
+        Ok((dir, copy)) => {
+            let filename = filename.to_string();
+            tokio::spawn(async move {
+                canary.compare(&copy, primary, &filename).await;
+                drop(dir);
+            });
+        }
+        Err(e) => warn!("Canary comparison of {} skipped: {}", filename, e),
+    }
+}
 
-    // Return the PDF
+/// Return a signed manifest next to the file, in response headers
+fn attach_manifest(response: &mut Response, manifest: &SignedManifest) {
+    for (name, value) in [
+        (MANIFEST_HEADER, manifest.body_base64()),
+        (SIGNATURE_HEADER, manifest.signature_base64()),
+    ] {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+/// Public key that manifest signatures verify against
+pub async fn manifest_key_handler(State(state): State<Arc<AppState>>) -> Result<Response> {
+    let signer = state
+        .manifests
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("Manifest signing is not configured".to_string()))?;
     Ok((
+        [(header::CONTENT_TYPE, "application/x-pem-file")],
+        signer.public_key_pem()?,
+    )
+        .into_response())
+}
+
+/// Reject `to` formats the selected engine cannot produce
+fn check_output_format(engine: &dyn ConvertEngine, options: &ConvertOptions) -> Result<()> {
+    if let Some(ref format) = options.output_format {
+        if !engine.output_formats().contains(&format.as_str()) {
+            return Err(AppError::InvalidRequest(format!(
+                "{:?} engine cannot produce '{}' output (supported: {})",
+                engine.engine_type(),
+                format,
+                engine.output_formats().join(", ")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Screenshot endpoint - renders HTML/Markdown/Org input or a web page to a
+/// full-page image
+pub async fn screenshot_handler(
+    State(state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> Result<Response> {
+    let mut form = parse_convert_form(multipart, &state, &[]).await?;
+
+    let format = match form.extra.get("format").map(|f| f.to_lowercase()) {
+        None => CaptureScreenshotFormat::Png,
+        Some(f) if f == "png" => CaptureScreenshotFormat::Png,
+        Some(f) if f == "jpeg" || f == "jpg" => CaptureScreenshotFormat::Jpeg,
+        Some(f) if f == "webp" => CaptureScreenshotFormat::Webp,
+        Some(f) => {
+            return Err(AppError::InvalidRequest(format!(
+                "Unsupported screenshot format: {}",
+                f
+            )))
+        }
+    };
+    let quality = match form.extra.get("quality") {
+        Some(q) => Some(parse_integer("quality", q, 0..=100)?),
+        None => None,
+    };
+
+    let chromium = state.router.chromium()?;
+    let result = if let Some(url) = form.extra.get("url") {
+        if !form.files.is_empty() {
+            return Err(AppError::InvalidRequest(
+                "Send either a file or a url, not both".to_string(),
+            ));
+        }
+        let _slot = state.router.conversion_slot().await;
+        state
+            .router
+            .screenshot_url(url, &form.options, format, quality)
+            .await?
+    } else {
+        let (filename, data) = form.take_file()?;
+        let temp_dir = crate::disk::tempdir()?;
+        let input_path = temp_dir.path().join(&filename);
+        data.save(&input_path).await?;
+
+        let ext = input_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .ok_or_else(|| AppError::InvalidRequest("File has no extension".to_string()))?;
+        if !chromium.supports_extension(ext) {
+            return Err(AppError::UnsupportedFormat(format!(
+                "Screenshots are only supported for {} files",
+                chromium.supported_extensions().join(", ")
+            )));
+        }
+        state.router.prepare_input(&input_path).await?;
+
+        let _slot = state.router.conversion_slot().await;
+        chromium
+            .screenshot(&input_path, &form.options, format, quality)
+            .await?
+    };
+
+    let engine_label = state.router.engine_label(&chromium.engine_type());
+    Ok(file_response(result, &engine_label))
+}
+
+/// Prefix of the current API version. Within a version, paths, fields and
+/// response shapes only change in backwards compatible ways (new optional
+/// fields, headers and endpoints); anything else waits for the next version.
+/// The unversioned paths are aliases of the current version.
+pub const API_PREFIX: &str = "/v1";
+
+/// `path` as the client addressed the API, with the version prefix when the
+/// request carried one
+fn api_path(uri: &OriginalUri, path: &str) -> String {
+    let versioned = uri
+        .path()
+        .strip_prefix(API_PREFIX)
+        .is_some_and(|rest| rest.starts_with('/'));
+    if versioned {
+        format!("{}{}", API_PREFIX, path)
+    } else {
+        path.to_string()
+    }
+}
+
+/// Finished mail merge documents buffered ahead of the response
+const MERGE_CHANNEL_CAPACITY: usize = 4;
+
+/// Header naming the engine (and its version) that produced the output
+const ENGINE_HEADER: &str = "x-pdfmill-engine";
+
+/// Header carrying the SHA-256 of the output, when known before streaming
+const SHA256_HEADER: &str = "x-content-sha256";
+
+/// Header summarizing the hyperlink audit, when `linkAudit` is set
+const LINKS_HEADER: &str = "x-pdfmill-links";
+
+/// Header listing problems that did not fail the conversion
+const WARNINGS_HEADER: &str = "x-pdfmill-warnings";
+
+/// Warnings listed in the header; the JSON metadata and job status have
+/// them all
+const MAX_HEADER_WARNINGS: usize = 20;
+
+fn attach_link_report(response: &mut Response, report: &pdf::LinkReport) {
+    if let Ok(value) = HeaderValue::from_str(&report.header_value()) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(LINKS_HEADER), value);
+    }
+}
+
+/// List warnings as a structured field list of strings (RFC 8941), e.g.
+/// `"selector does not apply to .docx files and was ignored"`
+fn attach_warnings(response: &mut Response, warnings: &[String]) {
+    if warnings.is_empty() {
+        return;
+    }
+    let value = warnings
+        .iter()
+        .take(MAX_HEADER_WARNINGS)
+        .map(|warning| {
+            let printable: String = warning
+                .chars()
+                .map(|c| if matches!(c, ' '..='~') { c } else { '?' })
+                .collect();
+            format!(
+                "\"{}\"",
+                printable.replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(WARNINGS_HEADER), value);
+    }
+}
+
+/// Build a download response for a conversion result
+fn file_response(result: ConvertResult, engine_label: &str) -> Response {
+    let sha256 = sha256_hex(&result.data);
+    let mut response = download_response(
+        Body::from(result.data),
+        &result.content_type,
+        &result.filename,
+        engine_label,
+        Some(&sha256),
+    );
+    attach_warnings(&mut response, &result.warnings);
+    response
+}
+
+/// Build a download response around a (possibly streaming) body
+fn download_response(
+    body: Body,
+    content_type: &str,
+    filename: &str,
+    engine_label: &str,
+    sha256: Option<&str>,
+) -> Response {
+    let mut response = (
         StatusCode::OK,
         [
-            (header::CONTENT_TYPE, result.content_type),
+            (header::CONTENT_TYPE, content_type.to_string()),
             (
                 header::CONTENT_DISPOSITION,
-                format!("attachment; filename=\"{}\"", result.filename),
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+            (
+                HeaderName::from_static(ENGINE_HEADER),
+                engine_label.to_string(),
             ),
         ],
-        result.data,
+        body,
     )
-        .into_response())
+        .into_response();
+    if let Some(value) = sha256.and_then(|s| HeaderValue::from_str(s).ok()) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(SHA256_HEADER), value);
+    }
+    response
+}
+
+/// Reverse conversion endpoint - turns a PDF into an editable document
+pub async fn pdf_convert_handler(
+    State(state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> Result<Response> {
+    let mut form = parse_convert_form(multipart, &state, &[]).await?;
+    let (filename, data) = form.take_file()?;
+    let target = form.options.output_format.ok_or_else(|| {
+        AppError::InvalidRequest(format!(
+            "Missing 'to' field (supported: {})",
+            REVERSE_FORMATS.join(", ")
+        ))
+    })?;
+
+    if !filename.to_lowercase().ends_with(".pdf") {
+        return Err(AppError::UnsupportedFormat(
+            "Reverse conversion expects a .pdf file".to_string(),
+        ));
+    }
+
+    let temp_dir = crate::disk::tempdir()?;
+    let input_path = temp_dir.path().join(&filename);
+    data.save(&input_path).await?;
+
+    let libreoffice = state.router.libreoffice()?;
+    let slot = state.router.conversion_slot().await;
+    let result = libreoffice.convert_from_pdf(&input_path, &target).await?;
+    drop(slot);
+
+    let engine_label = state.router.engine_label(&libreoffice.engine_type());
+    Ok(file_response(result, &engine_label))
+}
+
+/// PDF form endpoint - every AcroForm field with its type, options, current
+/// value, page and position
+pub async fn pdf_fields_handler(
+    State(state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> Result<Json<serde_json::Value>> {
+    let mut form = parse_convert_form(multipart, &state, &[]).await?;
+    let (filename, data) = form.take_file()?;
+    if !filename.to_lowercase().ends_with(".pdf") {
+        return Err(AppError::UnsupportedFormat(
+            "Form field listing expects a .pdf file".to_string(),
+        ));
+    }
+
+    let temp_dir = crate::disk::tempdir()?;
+    let input_path = temp_dir.path().join(&filename);
+    data.save(&input_path).await?;
+    let fields =
+        tokio::task::spawn_blocking(move || pdf::form_fields(&std::fs::read(&input_path)?))
+            .await
+            .map_err(|e| AppError::Internal(format!("Field listing panicked: {}", e)))??;
+    info!("Listed {} form fields of {}", fields.len(), filename);
+    Ok(Json(json!({ "fields": fields })))
+}
+
+/// Engine label of output pdfmill produces itself, without an engine
+fn native_engine_label() -> String {
+    format!("pdfmill/{}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Annotation endpoint - adds highlights, sticky notes and links, given as
+/// a JSON list in the `annotations` field, to a PDF
+pub async fn pdf_annotate_handler(
+    State(state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> Result<Response> {
+    let mut form = parse_convert_form(multipart, &state, &[]).await?;
+    let (filename, data) = form.take_file()?;
+    if !filename.to_lowercase().ends_with(".pdf") {
+        return Err(AppError::UnsupportedFormat(
+            "Annotation expects a .pdf file".to_string(),
+        ));
+    }
+    let annotations: Vec<pdf::Annotation> = form
+        .extra
+        .get("annotations")
+        .ok_or_else(|| {
+            AppError::InvalidRequest("Missing 'annotations' field (JSON list)".to_string())
+        })
+        .and_then(|json| {
+            serde_json::from_str(json)
+                .map_err(|e| AppError::InvalidRequest(format!("annotations: {}", e)))
+        })?;
+
+    let temp_dir = crate::disk::tempdir()?;
+    let input_path = temp_dir.path().join(&filename);
+    data.save(&input_path).await?;
+    let count = annotations.len();
+    let data = tokio::task::spawn_blocking(move || {
+        pdf::annotate(&std::fs::read(&input_path)?, &annotations)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("PDF annotation panicked: {}", e)))??;
+    info!("Added {} annotations to {}", count, filename);
+
+    let result = ConvertResult {
+        data,
+        content_type: "application/pdf".to_string(),
+        filename,
+        warnings: Vec::new(),
+    };
+    Ok(file_response(result, &native_engine_label()))
+}
+
+/// Mail merge endpoint - fills a DOCX template once per CSV/JSON record and
+/// returns a ZIP of PDFs, or a single concatenated PDF with `output=pdf`
+pub async fn mail_merge_handler(
+    State(state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> Result<Response> {
+    let mut form = parse_convert_form(multipart, &state, &[]).await?;
+    let (filename, data) = form.take_file()?;
+    if !filename.to_lowercase().ends_with(".docx") {
+        return Err(AppError::UnsupportedFormat(
+            "Mail merge expects a .docx template".to_string(),
+        ));
+    }
+
+    let dataset = form.extra.get("data").ok_or_else(|| {
+        AppError::InvalidRequest("Missing 'data' field (CSV or JSON)".to_string())
+    })?;
+    let records = parse_records(dataset)?;
+    let concatenate = match form.extra.get("output").map(|o| o.to_lowercase()) {
+        None => false,
+        Some(o) if o == "zip" => false,
+        Some(o) if o == "pdf" => true,
+        Some(o) => {
+            return Err(AppError::InvalidRequest(format!(
+                "Unsupported output: {} (expected zip or pdf)",
+                o
+            )))
+        }
+    };
+
+    let temp_dir = crate::disk::tempdir()?;
+    let template_path = temp_dir.path().join(&filename);
+    data.save(&template_path).await?;
+
+    let libreoffice = state.router.libreoffice()?;
+    let engine_label = state.router.engine_label(&libreoffice.engine_type());
+    info!("Mail merge of {} with {} records", filename, records.len());
+
+    // Documents arrive batch by batch; the channel bound keeps at most a few
+    // finished PDFs in memory while the response is being written
+    let (tx, mut rx) = mpsc::channel(MERGE_CHANNEL_CAPACITY);
+    let filename_field = form.extra.get("filenameField").cloned();
+    let slot = state.router.conversion_slot().await;
+    tokio::spawn(async move {
+        let _temp_dir = temp_dir;
+        let _slot = slot;
+        let merged = libreoffice
+            .mail_merge(&template_path, records, filename_field.as_deref(), &tx)
+            .await;
+        if let Err(e) = merged {
+            let _ = tx.send(Err(e)).await;
+        }
+    });
+
+    // Failures before the first document still get a proper error status
+    let first = rx
+        .recv()
+        .await
+        .ok_or_else(|| AppError::Internal("Mail merge produced no documents".to_string()))??;
+
+    let stem = filename
+        .rsplit_once('.')
+        .map_or(filename.as_str(), |(stem, _)| stem);
+    if concatenate {
+        let mut pdfs = vec![first.1];
+        while let Some(document) = rx.recv().await {
+            pdfs.push(document?.1);
+        }
+        let data = tokio::task::spawn_blocking(move || pdf::merge(&pdfs))
+            .await
+            .map_err(|e| AppError::Internal(format!("PDF merge panicked: {}", e)))??;
+        let data = state.router.check_page_limit(data).await?;
+        let result = ConvertResult {
+            data,
+            content_type: "application/pdf".to_string(),
+            filename: format!("{}.pdf", stem),
+            warnings: Vec::new(),
+        };
+        Ok(file_response(result, &engine_label))
+    } else {
+        Ok(download_response(
+            archive::zip_body(first, rx),
+            "application/zip",
+            &format!("{}.zip", stem),
+            &engine_label,
+            None,
+        ))
+    }
+}
+
+/// Body of a /convert/url request
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UrlRequest {
+    /// http(s) URL of the page to print
+    url: String,
+    /// Conversion options, named like the /convert form fields
+    #[serde(default)]
+    options: HashMap<String, serde_json::Value>,
+}
+
+/// URL conversion endpoint - Chromium loads a remote page and prints it, so
+/// clients need not download it with everything it refers to first
+pub async fn convert_url_handler(
+    State(state): State<Arc<AppState>>,
+    body: axum::body::Bytes,
+) -> Result<Response> {
+    let request: UrlRequest = serde_json::from_slice(&body)
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid URL request: {}", e)))?;
+    let options = ConvertOptions::deserialize(serde_json::Value::Object(
+        request.options.into_iter().collect(),
+    ))
+    .map_err(|e| AppError::InvalidRequest(format!("options: {}", e)))?;
+    if options.output_format.as_deref().is_some_and(|f| f != "pdf") {
+        return Err(AppError::InvalidRequest(
+            "to: remote pages are printed to PDF".to_string(),
+        ));
+    }
+
+    let chromium = state.router.chromium()?;
+    // Remote pages take the options of HTML files
+    let ignored = ignored_options(chromium.as_ref(), Path::new(REMOTE_PAGE), &options);
+
+    let slot = state.router.conversion_slot().await;
+    let result = match state.router.convert_url(&request.url, &options).await {
+        Ok(result) => state.router.finish(result).await,
+        Err(e) => Err(e),
+    };
+    drop(slot);
+    metrics::record_conversion(chromium.engine_type().name(), result.is_ok());
+    let mut result = result?;
+    result.warnings.splice(0..0, ignored);
+
+    let engine_label = state.router.engine_label(&chromium.engine_type());
+    Ok(file_response(result, &engine_label))
+}
+
+/// Body of a /convert/html request
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HtmlRequest {
+    /// The document to print
+    html: String,
+    /// Conversion options, named like the /convert form fields
+    #[serde(default)]
+    options: HashMap<String, serde_json::Value>,
+}
+
+/// HTML string endpoint - converts generated HTML sent as JSON, for clients
+/// that would otherwise build a multipart form around it
+pub async fn convert_html_handler(
+    State(state): State<Arc<AppState>>,
+    body: axum::body::Bytes,
+) -> Result<Response> {
+    let request: HtmlRequest = serde_json::from_slice(&body)
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid HTML request: {}", e)))?;
+    let options = ConvertOptions::deserialize(serde_json::Value::Object(
+        request.options.into_iter().collect(),
+    ))
+    .map_err(|e| AppError::InvalidRequest(format!("options: {}", e)))?;
+
+    let chromium = state.router.chromium()?;
+    check_output_format(chromium.as_ref(), &options)?;
+    let temp_dir = crate::disk::tempdir()?;
+    let input_path = temp_dir.path().join("document.html");
+    // Like an uploaded document.html
+    let html = state
+        .router
+        .prepare_input_data("document.html", request.html.into_bytes())
+        .await?;
+    let html = html.as_slice();
+    // The string is served to the browser as is unless the link audit
+    // reads the file
+    let in_memory = !options.link_audit && chromium.reads_memory("html", html.len(), &options);
+    if !in_memory {
+        tokio::fs::write(&input_path, html).await?;
+    }
+    info!("Converting {} bytes of HTML", html.len());
+    let ignored = ignored_options(chromium.as_ref(), &input_path, &options);
+
+    let slot = state.router.conversion_slot().await;
+    let converted = if in_memory {
+        chromium
+            .convert_memory("document.html", html, &options)
+            .await
+    } else {
+        chromium.convert(&input_path, &options).await
+    };
+    let result = match converted {
+        Ok(result) => state.router.finish(result).await,
+        Err(e) => Err(e),
+    };
+    drop(slot);
+    metrics::record_conversion(chromium.engine_type().name(), result.is_ok());
+
+    let (mut result, link_report) = match result? {
+        result if options.link_audit => audit_links(&input_path, result).await?,
+        result => (result, None),
+    };
+    result.warnings.splice(0..0, ignored);
+
+    let engine_label = state.router.engine_label(&chromium.engine_type());
+    let mut response = file_response(result, &engine_label);
+    if let Some(report) = link_report {
+        attach_link_report(&mut response, &report);
+    }
+    Ok(response)
+}
+
+/// Merge endpoint - converts every uploaded file like /convert, with the
+/// same options, and joins the PDFs into one document in upload order
+pub async fn merge_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<Vec<(String, String)>>,
+    multipart: Multipart,
+) -> Result<Response> {
+    let form = parse_convert_form(multipart, &state, &query).await?;
+    let options = form.options;
+    if form.files.is_empty() {
+        return Err(AppError::NoFileProvided);
+    }
+    if options.output_format.as_deref().is_some_and(|f| f != "pdf") {
+        return Err(AppError::InvalidRequest(
+            "to: merged output is always PDF".to_string(),
+        ));
+    }
+    if options.pdf_format.is_some() {
+        return Err(AppError::InvalidRequest(
+            "pdfFormat: merged documents are not PDF/A".to_string(),
+        ));
+    }
+
+    // Find every engine before converting anything, so an unsupported file
+    // fails the request at once. Each file gets its own directory, as
+    // uploads may share a name.
+    let temp_dir = crate::disk::tempdir()?;
+    let mut inputs = Vec::new();
+    for (i, (filename, data)) in form.files.into_iter().enumerate() {
+        let dir = temp_dir.path().join(i.to_string());
+        tokio::fs::create_dir(&dir).await?;
+        let input_path = dir.join(&filename);
+        data.save(&input_path).await?;
+        state.router.prepare_input(&input_path).await?;
+        let engine = state.router.find_engine_for_file(&input_path)?;
+        inputs.push((filename, input_path, engine));
+    }
+    info!("Merging {} files", inputs.len());
+
+    let mut pdfs = Vec::new();
+    let mut warnings = Vec::new();
+    let mut engine_labels: Vec<String> = Vec::new();
+    for (filename, input_path, engine) in &inputs {
+        let ignored = ignored_options(engine.as_ref(), input_path, &options);
+        let slot = state.router.conversion_slot().await;
+        let converted = match engine.convert(input_path, &options).await {
+            Ok(result) => {
+                state
+                    .router
+                    .append_attachments(input_path, result, &options)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        let result = match converted {
+            Ok(result) => state.router.finish(result).await,
+            Err(e) => Err(e),
+        };
+        drop(slot);
+        metrics::record_conversion(engine.engine_type().name(), result.is_ok());
+        let result = result.map_err(|e| match e {
+            AppError::ConversionFailed(message) => {
+                AppError::ConversionFailed(format!("{}: {}", filename, message))
+            }
+            e => e,
+        })?;
+
+        warnings.extend(
+            ignored
+                .into_iter()
+                .chain(result.warnings)
+                .map(|warning| format!("{}: {}", filename, warning)),
+        );
+        let label = state.router.engine_label(&engine.engine_type());
+        if !engine_labels.contains(&label) {
+            engine_labels.push(label);
+        }
+        pdfs.push(result.data);
+    }
+
+    let data = tokio::task::spawn_blocking(move || pdf::merge(&pdfs))
+        .await
+        .map_err(|e| AppError::Internal(format!("PDF merge panicked: {}", e)))??;
+    let data = state.router.check_page_limit(data).await?;
+    let result = ConvertResult {
+        data,
+        content_type: "application/pdf".to_string(),
+        filename: "merged.pdf".to_string(),
+        warnings,
+    };
+    Ok(file_response(result, &engine_labels.join(", ")))
+}
+
+/// Field listing endpoint - the merge fields of a DOCX template or the form
+/// fields of a PDF, with their types, so clients can build data-entry forms
+pub async fn fields_handler(
+    State(state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> Result<Json<serde_json::Value>> {
+    let mut form = parse_convert_form(multipart, &state, &[]).await?;
+    let (filename, data) = form.take_file()?;
+    let format = match filename.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()) {
+        Some(ext) if ext == "docx" || ext == "pdf" => ext,
+        _ => {
+            return Err(AppError::UnsupportedFormat(
+                "Field listing expects a .docx template or a .pdf form".to_string(),
+            ))
+        }
+    };
+
+    let temp_dir = crate::disk::tempdir()?;
+    let input_path = temp_dir.path().join(&filename);
+    data.save(&input_path).await?;
+
+    let fields = tokio::task::spawn_blocking(move || {
+        if format == "docx" {
+            serde_json::to_value(template_fields(&input_path)?)
+        } else {
+            serde_json::to_value(pdf::form_fields(&std::fs::read(&input_path)?)?)
+        }
+        .map_err(|e| AppError::Internal(format!("Failed to serialize fields: {}", e)))
+        .map(|fields| (format, fields))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Field listing panicked: {}", e)))??;
+    Ok(Json(json!({ "format": fields.0, "fields": fields.1 })))
+}
+
+/// JSON Schema of the conversion options
+pub async fn options_schema_handler() -> impl IntoResponse {
+    Json(options_schema())
+}
+
+/// Per extension, the engines that convert it, their availability and the
+/// options each honors
+pub async fn capabilities_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(json!({ "extensions": state.router.capabilities() }))
+}
+
+/// The configured option presets
+pub async fn presets_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(json!({ "presets": state.presets.definitions() }))
 }
 
 /// Health check endpoint
-pub async fn health_handler() -> impl IntoResponse {
+pub async fn health_handler(State(state): State<Arc<AppState>>) -> Response {
+    if !state.ready {
+        let body = Json(json!({
+            "status": "unavailable",
+            "service": "pdfmill",
+            "error": "No conversion engine is available"
+        }));
+        return (StatusCode::SERVICE_UNAVAILABLE, body).into_response();
+    }
     Json(json!({
         "status": "healthy",
         "service": "pdfmill"
     }))
+    .into_response()
+}
+
+/// Prometheus metrics
+pub async fn metrics_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let mut exposition = Exposition::default();
+    exposition.family(
+        "pdfmill_engine_available",
+        "gauge",
+        "Whether the engine's dependencies are installed",
+        state
+            .router
+            .engine_availability()
+            .into_iter()
+            .map(|(engine, available)| (vec![("engine", engine)], f64::from(u8::from(available)))),
+    );
+    exposition.family(
+        "pdfmill_jobs",
+        "gauge",
+        "Background jobs currently known, by status",
+        state
+            .jobs
+            .status_counts()
+            .into_iter()
+            .map(|(status, count)| (vec![("status", status.name())], count as f64)),
+    );
+    exposition.family(
+        "pdfmill_disk_usage_bytes",
+        "gauge",
+        "Disk used by request workspaces and the Chromium and LibreOffice profiles",
+        crate::disk::usage()
+            .into_iter()
+            .map(|(area, bytes)| (vec![("area", area)], bytes as f64)),
+    );
+    exposition.counters();
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        exposition.finish(),
+    )
+}
+
+/// Replace the persistent Chromium browser with a freshly launched and
+/// warmed-up one, without interrupting conversions
+pub async fn browser_swap_handler(State(state): State<Arc<AppState>>) -> Result<impl IntoResponse> {
+    let elapsed = state.router.chromium()?.swap().await?;
+    Ok(Json(json!({
+        "status": "swapped",
+        "elapsed_ms": elapsed.as_millis() as u64
+    })))
+}
+
+/// Convert a built-in fixture with every available engine; answers 503
+/// unless all of them pass, for post-deploy checks
+pub async fn selftest_handler(State(state): State<Arc<AppState>>) -> Response {
+    let report = selftest::run(&state.router).await;
+    let status = if report.passed {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report)).into_response()
+}
+
+/// Benchmark every available engine on its self-test fixture; the body,
+/// which may be empty, picks engines and iterations and can carry an
+/// earlier report as the baseline
+pub async fn bench_handler(
+    State(state): State<Arc<AppState>>,
+    body: axum::body::Bytes,
+) -> Result<impl IntoResponse> {
+    let request: BenchRequest = if body.iter().all(u8::is_ascii_whitespace) {
+        BenchRequest::default()
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| AppError::InvalidRequest(format!("Invalid bench request: {}", e)))?
+    };
+    Ok(Json(bench::run(&state.router, request).await?))
 }
 
 /// Information endpoint - lists supported formats
@@ -119,22 +1393,336 @@ pub async fn info_handler(State(state): State<Arc<AppState>>) -> impl IntoRespon
     Json(json!({
         "service": "pdfmill",
         "version": env!("CARGO_PKG_VERSION"),
+        "api": {
+            "version": API_PREFIX.trim_start_matches('/'),
+            "prefix": API_PREFIX,
+            "description": format!("Paths below are also served under {}, which only changes in backwards compatible ways; the unversioned paths are aliases of the current version", API_PREFIX)
+        },
         "supported_formats": extensions,
+        "engines": state.router.engine_summaries(),
+        "routes": state.router.routes(),
+        "hooks": state.router.hooks().summaries(),
         "endpoints": {
             "convert": {
                 "path": "/convert",
                 "method": "POST",
-                "description": "Convert any supported file to PDF. The engine is automatically selected based on file extension.",
+                "description": "Convert any supported file to PDF. The engine is automatically selected based on file extension. Options may also be given as query parameters; form fields override them. Invalid option values are rejected with 400 naming the field. Problems that do not fail the conversion (ignored options, substituted fonts, resources that failed to load, hidden review marks) are listed in X-PDFMill-Warnings as quoted strings.",
                 "content_type": "multipart/form-data",
                 "fields": {
                     "file": "The file to convert (required)",
+                    "preset": "Name of an operator-defined option preset (see /presets); other fields override its values (optional)",
                     "landscape": "Boolean - use landscape orientation (optional)",
                     "printBackground": "Boolean - print background graphics (optional, HTML only)",
-                    "pageWidth": "Page width (optional, e.g., '8.5in', '210mm')",
-                    "pageHeight": "Page height (optional, e.g., '11in', '297mm')",
-                    "pdfFormat": "PDF format (optional, e.g., 'PDF/A-1b')"
+                    "displayHeaderFooter": "Boolean - print a header and footer on every page, Chromium's date/title and URL/page number lines unless templates are given (optional, HTML only)",
+                    "headerTemplate": "HTML of the page header; elements with class date, title, url, pageNumber or totalPages are filled in; implies displayHeaderFooter (optional, HTML only)",
+                    "footerTemplate": "HTML of the page footer, like headerTemplate (optional, HTML only)",
+                    "pageRanges": "Pages to print, e.g. '1-5, 8, 11-13'; ranges past the last page are rejected (optional, HTML only)",
+                    "scale": "Zoom when printing, 0.1 to 2, default 1 (optional, HTML only)",
+                    "preferCSSPageSize": "Boolean - let the document's CSS @page size win over pageWidth/pageHeight (optional, HTML only)",
+                    "pageWidth": "Page width from 1in to 200in (optional, e.g., '8.5in', '210mm'; bare numbers are inches)",
+                    "pageHeight": "Page height from 1in to 200in (optional, e.g., '11in', '297mm'; bare numbers are inches)",
+                    "marginTop": "Top margin (optional, e.g., '10mm'; also marginBottom, marginLeft, marginRight; HTML only); opposite margins must leave room on the page",
+                    "pdfFormat": "PDF format: PDF/A-1b, PDF/A-2b or PDF/A-3b (optional)",
+                    "to": "Output format, default 'pdf' (optional; office documents also support docx, odt, rtf, txt, html, xlsx, ods, csv, pptx, odp, png, jpg, svg; image formats give one image per page, zipped when there are several)",
+                    "viewportWidth": "Browser viewport width in pixels (optional, HTML only)",
+                    "viewportHeight": "Browser viewport height in pixels (optional, HTML only)",
+                    "dpi": "Rendering resolution, 48-600, default 96 (optional, HTML and charts)",
+                    "chartWidth": "Chart width in pixels, overriding the specification (optional, Vega/Vega-Lite/Plotly JSON only)",
+                    "chartHeight": "Chart height in pixels, overriding the specification (optional, Vega/Vega-Lite/Plotly JSON only)",
+                    "selector": "CSS selector of the only element to print, e.g. '#invoice' (optional, HTML only)",
+                    "waitForSelector": "CSS selector of an element to wait for (up to 30s) before printing, for pages that render with JavaScript (optional, HTML only)",
+                    "waitUntil": "'load' (default) prints after the load event, 'networkidle' also waits until the page made no requests for 500ms, at most 30s (optional, HTML only)",
+                    "waitForDelayMs": "Milliseconds to wait after the page is ready before printing, at most 30000 (optional, HTML only)",
+                    "emulateMedia": "CSS media type the page is laid out for: 'print' (default for PDFs) or 'screen' (default for screenshots), for pages whose print stylesheet hides content (optional, HTML only)",
+                    "forceLightBackground": "Boolean - override dark themes with a white background and dark text (optional, HTML only)",
+                    "toc": "Boolean - start the document with a page listing its h1-h3 headings, each linked to its heading; a Markdown template placing toc keeps its own (optional, HTML/Markdown only)",
+                    "bookmarks": "Boolean - add PDF bookmarks (the outline) for the h1-h3 headings, nested by level (optional, HTML/Markdown only)",
+                    "customCss": "Stylesheet added to the page after its own, just before printing (optional, HTML only)",
+                    "customJs": "Script run in the page once it is ready, just before printing; a returned promise is awaited, at most 30s, and an exception fails the request (optional, HTML only)",
+                    "markdownTemplate": "Tera template of the HTML document Markdown is placed in, for branding, cover pages and a table of contents; variables content (place with | safe, required), title, toc (linked list of h1-h3), headings (level, id, html), styles (the default stylesheet), filename and date (optional, Markdown only)",
+                    "handout": "Slides per page for presentation handouts: 1, 2, 4 or 6 (optional, PPT/PPTX/ODP only)",
+                    "handoutNotes": "Boolean - draw note lines next to each handout slide (optional)",
+                    "drawingScale": "CAD drawing size: 'fit' to fill the page inside the margins, or a factor such as '0.5' or '50%' (optional, DXF only; pageWidth/pageHeight/landscape/margins set the sheet)",
+                    "speakerNotes": "Boolean - include speaker notes below each slide (optional, PPT/PPTX/ODP only)",
+                    "gridlines": "Boolean - print cell gridlines (optional, XLSX/ODS only)",
+                    "sheetHeaders": "Boolean - print row and column headers (optional, XLSX/ODS only)",
+                    "printArea": format!("'{}' for each sheet's used range, or the name of a named range to print (optional, XLSX/ODS only)", USED_RANGE),
+                    "autoLandscape": "Boolean - print sheets too wide for a portrait page in landscape (optional, XLSX/ODS only)",
+                    "trackChanges": "'accept' (default) renders tracked changes as accepted, 'show' keeps the markup (optional, DOC/DOCX only)",
+                    "comments": "'hide' (default) leaves comments out, 'margin' prints them in the page margin (optional, DOC/DOCX only)",
+                    "locale": "Emulated browser locale, e.g. 'de_DE' (optional, HTML only)",
+                    "documentLocale": "Locale of number and date formats that follow the system, e.g. 'de-DE' (optional, office documents only)",
+                    "timezone": "IANA timezone of printed dates (browser clock, office date fields, mailbox dates), e.g. 'Europe/Berlin' (default: UTC)",
+                    "geolocation": "Emulated position 'latitude,longitude[,accuracy]'; location access is denied otherwise (optional, HTML only)",
+                    "httpUsername": "Username for HTTP Basic authentication of remote resources (optional, HTML only)",
+                    "httpPassword": "Password for HTTP Basic authentication of remote resources (optional, HTML only)",
+                    "httpAuthOrigin": "Origin, e.g. 'https://intranet.example', whose resources get httpUsername/httpPassword when an uploaded document loads them; remote pages send them to their own origin (optional, HTML only)",
+                    "proxy": "HTTP/SOCKS proxy for this request, e.g. 'socks5://proxy:1080' (optional, HTML only)",
+                    "cookies": "JSON object of cookies, e.g. {\"session\": \"abc\"}, set for the page's host before it loads (optional, remote pages only)",
+                    "extraHeaders": "JSON object of headers, e.g. {\"Authorization\": \"Bearer abc\"}, sent with the page's requests to its own origin (optional, remote pages only)",
+                    "linkAudit": "Boolean - check that the source's hyperlinks (DOCX, Markdown, HTML) survived as PDF links and make plain-text URLs clickable; the counts are returned in X-PDFMill-Links as 'source=N, preserved=N, repaired=N, missing=N' (optional, PDF output only)",
+                    "convertAttachments": "Boolean - for .eml files, convert each attachment with the engine its extension routes to and append it after the message behind a separator page; attachments that fail are named on their separator page and in the warnings (optional, PDF output only, not with pdfFormat)",
+                    "embedProvenance": "Boolean - embed a JSON record of the conversion (source filename and SHA-256, options without secrets, engine and version, time) in the PDF as the attached file pdfmill-provenance.json, before any pipeline steps; /convert and jobs only (optional, PDF output only, not with PDF/A-1b or PDF/A-2b)",
+                    "pipeline": "JSON array of steps run after the conversion, starting with {\"step\": \"convert\"}; further steps are watermark (text, opacity, fontSize, angle, color), annotate (annotations), compress and encrypt (userPassword, ownerPassword, allowPrint, allowCopy, allowModify; last step only) (optional, PDF output only)"
+                },
+                "accept": {
+                    "application/pdf": "The converted file (default, also for */*); the type follows 'to' for other outputs",
+                    "image/png": "The first page rendered at 150 dpi (PDF output only, needs poppler's pdftoppm)",
+                    "application/json": "The metadata as in multipart/related, with the file kept as a succeeded job until it expires: 'document' is its /jobs/{id}/result URL and 'job' the job",
+                    "multipart/related": "The file after a JSON part with its filename, content type, size, sha256, engine, page count, timings (conversion_ms, total_ms), link audit and warnings",
+                    "other": "406 when no listed type is acceptable; q-values are honored"
+                }
+            },
+            "screenshot": {
+                "path": "/screenshot",
+                "method": "POST",
+                "description": "Render an HTML, Markdown or Org file or a web page to a full-page image using Chromium.",
+                "content_type": "multipart/form-data",
+                "fields": {
+                    "file": "The file to render (required unless url is given)",
+                    "url": "http(s) URL of a page to render instead of a file, subject to PDFMILL_URL_ALLOWLIST (optional)",
+                    "format": "Image format: png (default), jpeg or webp (optional)",
+                    "quality": "Compression quality 0-100 for jpeg/webp (optional)",
+                    "printBackground": "Boolean - keep the page background (optional)",
+                    "viewportWidth": "Browser viewport width in pixels (optional)",
+                    "viewportHeight": "Browser viewport height in pixels (optional)",
+                    "selector": "CSS selector of the only element to capture (optional)"
+                }
+            },
+            "pdf_convert": {
+                "path": "/pdf/convert",
+                "method": "POST",
+                "description": "Convert a PDF back into an editable document using LibreOffice's PDF import.",
+                "content_type": "multipart/form-data",
+                "fields": {
+                    "file": "The PDF file to convert (required)",
+                    "to": "Target format: docx, odt, rtf, txt or html (required)"
                 }
             },
+            "pdf_fields": {
+                "path": "/pdf/fields",
+                "method": "POST",
+                "description": "List the AcroForm fields of a PDF as {fields: [{name, type, options, value, page, rect}]}. rect is [x1, y1, x2, y2] in points from the lower-left corner of the page; page and rect describe the field's first widget.",
+                "content_type": "multipart/form-data",
+                "fields": {
+                    "file": "The PDF form (required)"
+                }
+            },
+            "pdf_annotate": {
+                "path": "/pdf/annotate",
+                "method": "POST",
+                "description": "Add highlights, sticky notes and links to a PDF and return the annotated PDF. Rectangles are [x1, y1, x2, y2] in points from the lower-left corner of the page, as in /pdf/fields.",
+                "content_type": "multipart/form-data",
+                "fields": {
+                    "file": "The PDF to annotate (required)",
+                    "annotations": format!("JSON list of at most {} annotations (required): {{\"type\": \"highlight\", \"page\", \"rect\", \"contents\", \"author\", \"color\": [r, g, b] from 0 to 1}}, {{\"type\": \"note\", \"page\", \"rect\", \"contents\" (required), \"author\", \"color\"}} or {{\"type\": \"link\", \"page\", \"rect\", \"url\" (http, https or mailto)}}; pages count from 1", pdf::MAX_ANNOTATIONS)
+                }
+            },
+            "mailmerge": {
+                "path": "/mailmerge",
+                "method": "POST",
+                "description": "Fill the MERGEFIELD fields of a DOCX template once per record and convert each copy to PDF.",
+                "content_type": "multipart/form-data",
+                "fields": {
+                    "file": "The DOCX template (required)",
+                    "data": "Records as CSV with a header row or a JSON array of objects (required)",
+                    "output": "'zip' (default) for one PDF per record, or 'pdf' for a single concatenated PDF",
+                    "filenameField": "Record field used to name the PDFs in the ZIP (optional)"
+                }
+            },
+            "convert_html": {
+                "path": "/convert/html",
+                "method": "POST",
+                "description": "Convert an HTML string sent as JSON using Chromium, as /convert would convert it uploaded as an .html file",
+                "content_type": "application/json",
+                "fields": {
+                    "html": "The HTML document (required)",
+                    "options": "Object of /convert options, e.g. {\"marginTop\": \"12mm\"} (optional)"
+                }
+            },
+            "convert_url": {
+                "path": "/convert/url",
+                "method": "POST",
+                "description": "Load a remote http(s) page in Chromium and print it to PDF. The page, its redirects and everything it loads must be allowed by PDFMILL_URL_ALLOWLIST (by default any public address; private and loopback addresses are refused); blocked loads are reported as warnings.",
+                "content_type": "application/json",
+                "fields": {
+                    "url": "http(s) URL of the page (required)",
+                    "options": "Object of /convert options for HTML, e.g. {\"waitForSelector\": \"#chart svg\"} (optional); httpUsername/httpPassword are sent to the page's own origin only"
+                }
+            },
+            "merge": {
+                "path": "/merge",
+                "method": "POST",
+                "description": "Convert several files of any supported format and join the PDFs into one document in upload order. Options apply to every file; warnings name the file they concern.",
+                "content_type": "multipart/form-data",
+                "fields": {
+                    "file": "A file to convert; repeat the field for each file (at least one)",
+                    "options": "Any /convert option except pdfFormat; the output is always PDF"
+                }
+            },
+            "fields": {
+                "path": "/fields",
+                "method": "POST",
+                "description": "List the fields of a DOCX mail merge template (MERGEFIELD and legacy form fields) or of a PDF form (AcroForm) as {format, fields: [{name, type, options}]}. Types are text, checkbox, choice and, for PDFs, radio, button and signature; PDF fields also carry value, page and rect as in /pdf/fields.",
+                "content_type": "multipart/form-data",
+                "fields": {
+                    "file": "The DOCX template or PDF form (required)"
+                }
+            },
+            "jobs": {
+                "path": "/jobs",
+                "method": "POST",
+                "description": "Queue a conversion in the background. Accepts the same fields as /convert and answers 202 with the job and a Location header.",
+                "content_type": "multipart/form-data",
+                "fields": {
+                    "emailTo": "Comma-separated addresses the result is emailed to once converted; requires SMTP configuration (optional)"
+                }
+            },
+            "job_list": {
+                "path": "/jobs",
+                "method": "GET",
+                "description": "List jobs, newest first, paginated as {jobs, total, limit, offset} (on PDFMILL_ADMIN_ADDR, 127.0.0.1:9090 by default)",
+                "query": {
+                    "status": "Only jobs in this state: queued, running, succeeded, failed, cancelled or dead_letter (optional)",
+                    "since": "Only jobs created at or after this RFC 3339 timestamp (optional)",
+                    "limit": "Page size, 1-500 (default: 50)",
+                    "offset": "Number of jobs to skip (default: 0)"
+                }
+            },
+            "job_status": {
+                "path": "/jobs/{id}",
+                "method": "GET",
+                "description": "Job status (queued, running, succeeded, failed, cancelled or dead_letter) with the error, exit code and stderr of every failed attempt, and the link audit counts when linkAudit was set"
+            },
+            "job_result": {
+                "path": "/jobs/{id}/result",
+                "method": "GET",
+                "description": "Download the converted file of a succeeded job"
+            },
+            "job_retry": {
+                "path": "/jobs/{id}/retry",
+                "method": "POST",
+                "description": "Run a failed job again with its stored input. Without a body the previous options are reused. Jobs that failed PDFMILL_JOB_MAX_ATTEMPTS times are dead-lettered and cannot be retried.",
+                "content_type": "multipart/form-data (optional)",
+                "fields": {
+                    "engine": "Force an engine by name instead of automatic selection (optional)",
+                    "...": "Any /convert option; when given, they replace the job's previous options"
+                }
+            },
+            "options_schema": {
+                "path": "/options/schema",
+                "method": "GET",
+                "description": "JSON Schema of the conversion options. Booleans accept true/false, 1/0 or yes/no (case-insensitive); numbers may be sent as strings."
+            },
+            "capabilities": {
+                "path": "/capabilities",
+                "method": "GET",
+                "description": "Per extension, the engines that convert it in the order they are tried, their version and availability, the engine a conversion uses and the options each engine honors, so clients can disable options that do not apply"
+            },
+            "render": {
+                "path": "/render",
+                "method": "POST",
+                "description": "Render a stored template (see /templates) with JSON data and convert it to PDF using Chromium",
+                "content_type": "application/json",
+                "fields": {
+                    "template": "Name of the stored template, latest version; 'invoice@v3' pins a version (required). The rendered version is returned in X-PDFMill-Template",
+                    "data": "Object of template variables (optional)",
+                    "options": "Object of /convert options, e.g. {\"marginTop\": \"12mm\"} (optional)"
+                }
+            },
+            "templates": {
+                "path": "/templates/{name}",
+                "method": "GET, PUT, DELETE",
+                "description": "Template library for /render: PUT stores an HTML/Tera template as a new version (201 + Location of name@vN), GET /templates lists them, GET /templates/{name}/versions lists versions, POST /templates/{name}/rollback with {\"version\": \"v2\"} restores one as a new version, /templates/{name}/assets/{file} serves an asset. {name} may be pinned as name@vN for GET. Kept in PDFMILL_TEMPLATES_DIR when set; on PDFMILL_ADMIN_ADDR, 127.0.0.1:9090 by default.",
+                "content_type": "multipart/form-data",
+                "fields": {
+                    "template": "Template source, Tera syntax with autoescaping (required)",
+                    "...": "Any number of asset files (images, stylesheets, fonts), referenced from the template by file name"
+                }
+            },
+            "assets": {
+                "path": "/assets/{name}",
+                "method": "GET, PUT, DELETE",
+                "description": "Shared assets (logos, fonts, stylesheets): PUT the raw file as the body (201 when new), GET /assets lists them. HTML documents and templates refer to them as /assets/{name}, which Chromium resolves while rendering. Kept in PDFMILL_ASSETS_DIR when set; on PDFMILL_ADMIN_ADDR, 127.0.0.1:9090 by default."
+            },
+            "presets": {
+                "path": "/presets",
+                "method": "GET",
+                "description": "Option presets defined in PDFMILL_PRESETS_FILE, selected with the preset field or query parameter"
+            },
+            "job_manifest": {
+                "path": "/jobs/{id}/manifest",
+                "method": "GET",
+                "description": "Signed provenance manifest of a succeeded job (requires PDFMILL_MANIFEST_KEY). The body is the signed JSON; the base64 signature is in X-PDFMill-Manifest-Signature. /convert returns the same pair as X-PDFMill-Manifest (base64) and X-PDFMill-Manifest-Signature headers."
+            },
+            "manifest_key": {
+                "path": "/manifest/key",
+                "method": "GET",
+                "description": "PEM public key that manifest signatures verify against"
+            },
+            "schedules": {
+                "path": "/schedules",
+                "method": "GET, POST",
+                "description": "List schedules, or create one (201 + Location). A schedule renders a web page to PDF on a cron expression and delivers it over HTTP. On PDFMILL_ADMIN_ADDR when set.",
+                "content_type": "application/json",
+                "fields": {
+                    "name": "Label, also used in the delivered file name (optional)",
+                    "cron": "Cron expression, e.g. '0 6 * * *' (required)",
+                    "timezone": "IANA timezone the expression is evaluated in (default: UTC)",
+                    "url": "http(s) URL of the page to render (required)",
+                    "options": "Object of /convert options, e.g. {\"landscape\": true} (optional)",
+                    "delivery": "{url, method: PUT|POST, headers, email_to}; {date} and {timestamp} in url are replaced by the run time. A url, email_to (comma-separated) or both are required",
+                    "enabled": "false pauses the schedule (default: true)"
+                }
+            },
+            "schedule": {
+                "path": "/schedules/{id}",
+                "method": "GET, PUT, DELETE",
+                "description": "Show a schedule with its next and last run, replace its definition, or delete it"
+            },
+            "job_cancel": {
+                "path": "/jobs/{id}",
+                "method": "DELETE",
+                "description": "Cancel a queued or running job, stopping its conversion and deleting its files"
+            },
+            "job_data_delete": {
+                "path": "/jobs/{id}/data",
+                "method": "DELETE",
+                "description": "Delete a job's input, result and options before the retention period ends, cancelling it if unfinished. The job record stays, with data_deleted_at set, until it expires; repeating the call changes nothing"
+            },
+            "job_purge": {
+                "path": "/admin/jobs/purge",
+                "method": "POST",
+                "description": "Remove jobs and their files by age and/or client, cancelling unfinished ones; ages under a minute or no criteria need all: true; answers {purged, jobs} with the removed ids (on PDFMILL_ADMIN_ADDR, 127.0.0.1:9090 by default)",
+                "content_type": "application/json",
+                "fields": {
+                    "before": "Jobs created before this RFC 3339 timestamp (optional)",
+                    "olderThan": "Jobs created more than this many seconds ago, instead of before (optional)",
+                    "apiKey": "Jobs submitted with this API key (optional)",
+                    "ip": "Jobs submitted from this address, with or without an API key, instead of apiKey (optional)",
+                    "...": "At least one criterion is required"
+                }
+            },
+            "metrics": {
+                "path": "/metrics",
+                "method": "GET",
+                "description": "Prometheus metrics (on PDFMILL_ADMIN_ADDR, 127.0.0.1:9090 by default)"
+            },
+            "selftest": {
+                "path": "/admin/selftest",
+                "method": "POST",
+                "description": "Convert a built-in sample with every available engine and report pass/fail, timings and stderr; 503 unless all pass (on PDFMILL_ADMIN_ADDR, 127.0.0.1:9090 by default)"
+            },
+            "browser_swap": {
+                "path": "/admin/browser/swap",
+                "method": "POST",
+                "description": "Launch and warm up a replacement Chromium browser, switch conversions to it and close the old one; also scheduled by PDFMILL_BROWSER_SWAP_INTERVAL (on PDFMILL_ADMIN_ADDR, 127.0.0.1:9090 by default)"
+            },
+            "demo": {
+                "path": "/demo",
+                "method": "GET",
+                "description": "HTML page with an upload form and the options from /options/schema, for trying conversions from a browser; its samples are served at /demo/fixtures/{name}"
+            },
             "health": {
                 "path": "/health",
                 "method": "GET",
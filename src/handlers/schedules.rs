@@ -0,0 +1,61 @@
+use super::AppState;
+use crate::error::{AppError, Result};
+use crate::schedules::{ScheduleInfo, ScheduleSpec};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Parse a schedule from a JSON body, reporting problems as JSON errors
+fn parse_spec(body: &[u8]) -> Result<ScheduleSpec> {
+    serde_json::from_slice(body)
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid schedule: {}", e)))
+}
+
+/// Create a schedule - answered with 201 and its URL
+pub async fn create_schedule_handler(
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
+) -> Result<Response> {
+    let schedule = state.schedules.create(parse_spec(&body)?).await?;
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, format!("/schedules/{}", schedule.id))],
+        Json(schedule),
+    )
+        .into_response())
+}
+
+pub async fn list_schedules_handler(State(state): State<Arc<AppState>>) -> Json<Value> {
+    Json(json!({ "schedules": state.schedules.list() }))
+}
+
+pub async fn get_schedule_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ScheduleInfo>> {
+    Ok(Json(state.schedules.get(id)?))
+}
+
+/// Replace a schedule's definition
+pub async fn update_schedule_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    body: Bytes,
+) -> Result<Json<ScheduleInfo>> {
+    Ok(Json(state.schedules.update(id, parse_spec(&body)?).await?))
+}
+
+pub async fn delete_schedule_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode> {
+    state.schedules.delete(id)?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,213 @@
+use super::{attach_link_report, check_output_format, file_response, AppState};
+use crate::assets::content_type;
+use crate::engines::{audit_links, ConvertEngine, ConvertOptions};
+use crate::error::{AppError, Result};
+use crate::metrics;
+use crate::templates::{parse_version, TemplateInfo};
+use axum::{
+    extract::{Multipart, Path, State},
+    http::{header, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::info;
+
+/// Store a new version of a template from a multipart form: the `template`
+/// field holds the HTML/Tera source, every other file is an asset named by
+/// its file name. Answered with 201 and the version's URL.
+pub async fn put_template_handler(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    mut multipart: Multipart,
+) -> Result<Response> {
+    let mut source = None;
+    let mut assets = BTreeMap::new();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::InvalidRequest(format!("Failed to parse multipart data: {}", e)))?
+    {
+        let field_name = field.name().unwrap_or("").to_string();
+        let filename = field.file_name().map(str::to_string);
+        let data = field.bytes().await.map_err(|e| {
+            AppError::InvalidRequest(format!("Failed to read {}: {}", field_name, e))
+        })?;
+        match (field_name.as_str(), filename) {
+            ("template", _) => {
+                let text = String::from_utf8(data.to_vec()).map_err(|_| {
+                    AppError::InvalidRequest("template: expected UTF-8 text".to_string())
+                })?;
+                source = Some(text);
+            }
+            (_, Some(filename)) => {
+                assets.insert(filename, data.to_vec());
+            }
+            (_, None) => {
+                return Err(AppError::InvalidRequest(format!(
+                    "{}: unknown field (expected template and asset files)",
+                    field_name
+                )))
+            }
+        }
+    }
+    let source = source
+        .ok_or_else(|| AppError::InvalidRequest("template: field is required".to_string()))?;
+
+    let (template, _) = state.templates.put(&name, source, assets)?;
+    Ok(version_created(template))
+}
+
+fn version_created(template: TemplateInfo) -> Response {
+    (
+        StatusCode::CREATED,
+        [(
+            header::LOCATION,
+            format!("/templates/{}", template.reference),
+        )],
+        Json(template),
+    )
+        .into_response()
+}
+
+/// Every stored version of a template, oldest first
+pub async fn template_versions_handler(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>> {
+    Ok(Json(
+        json!({ "versions": state.templates.versions(&name)? }),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct RollbackRequest {
+    /// Version to restore, e.g. "v2" or 2
+    version: Value,
+}
+
+/// Make an old version current again; it is stored as a new version so
+/// pinned references keep rendering what they did
+pub async fn rollback_template_handler(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    body: axum::body::Bytes,
+) -> Result<Response> {
+    let request: RollbackRequest = serde_json::from_slice(&body)
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid rollback request: {}", e)))?;
+    let version = match &request.version {
+        Value::String(version) => parse_version(version),
+        Value::Number(number) => parse_version(&number.to_string()),
+        _ => None,
+    }
+    .ok_or_else(|| AppError::InvalidRequest("version: expected e.g. 'v2'".to_string()))?;
+    Ok(version_created(state.templates.rollback(&name, version)?))
+}
+
+pub async fn list_templates_handler(State(state): State<Arc<AppState>>) -> Json<Value> {
+    Json(json!({ "templates": state.templates.list() }))
+}
+
+/// A template's details and source: the latest version, or a pinned one
+/// (`invoice@v3`)
+pub async fn get_template_handler(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>> {
+    let template = state.templates.get(&name)?;
+    let mut body = serde_json::to_value(template.info())
+        .map_err(|e| AppError::Internal(format!("Failed to serialize template: {}", e)))?;
+    body["source"] = Value::String(template.source.clone());
+    Ok(Json(body))
+}
+
+pub async fn get_template_asset_handler(
+    State(state): State<Arc<AppState>>,
+    Path((name, asset)): Path<(String, String)>,
+) -> Result<Response> {
+    let template = state.templates.get(&name)?;
+    let data = template.assets.get(&asset).ok_or_else(|| {
+        AppError::NotFound(format!("Asset {} of template {} not found", asset, name))
+    })?;
+    Ok(([(header::CONTENT_TYPE, content_type(&asset))], data.clone()).into_response())
+}
+
+pub async fn delete_template_handler(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode> {
+    state.templates.delete(&name)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Response header naming the template version that was rendered
+const TEMPLATE_HEADER: &str = "x-pdfmill-template";
+
+/// Body of a /render request
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderRequest {
+    /// Name of a stored template, optionally pinned to a version
+    /// (`invoice@v3`)
+    template: String,
+    /// Variables of the template
+    #[serde(default)]
+    data: Option<Value>,
+    /// Conversion options, named like the /convert form fields
+    #[serde(default)]
+    options: HashMap<String, Value>,
+}
+
+/// Render a stored template with JSON data and convert it to PDF
+pub async fn render_handler(
+    State(state): State<Arc<AppState>>,
+    body: axum::body::Bytes,
+) -> Result<Response> {
+    let request: RenderRequest = serde_json::from_slice(&body)
+        .map_err(|e| AppError::InvalidRequest(format!("Invalid render request: {}", e)))?;
+    let options = ConvertOptions::deserialize(Value::Object(request.options.into_iter().collect()))
+        .map_err(|e| AppError::InvalidRequest(format!("options: {}", e)))?;
+
+    let template = state.templates.get(&request.template)?;
+    let data = request.data.unwrap_or_else(|| json!({}));
+    let html = template.render(&data)?;
+
+    let chromium = state.router.chromium()?;
+    check_output_format(chromium.as_ref(), &options)?;
+    let temp_dir = crate::disk::tempdir()?;
+    let input_path = template.write_to(temp_dir.path(), &html).await?;
+    info!(
+        "Rendering template {} ({} bytes of HTML)",
+        template.reference(),
+        html.len()
+    );
+
+    let slot = state.router.conversion_slot().await;
+    let result = match chromium.convert(&input_path, &options).await {
+        Ok(result) => state.router.finish(result).await,
+        Err(e) => Err(e),
+    };
+    drop(slot);
+    metrics::record_conversion(chromium.engine_type().name(), result.is_ok());
+
+    let (result, link_report) = match result? {
+        result if options.link_audit => audit_links(&input_path, result).await?,
+        result => (result, None),
+    };
+
+    let engine_label = state.router.engine_label(&chromium.engine_type());
+    let mut response = file_response(result, &engine_label);
+    if let Some(report) = link_report {
+        attach_link_report(&mut response, &report);
+    }
+    // The exact version, for clients that want to regenerate it later
+    if let Ok(value) = HeaderValue::from_str(&template.reference()) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(TEMPLATE_HEADER), value);
+    }
+    Ok(response)
+}
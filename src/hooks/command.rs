@@ -0,0 +1,137 @@
+//! Hooks that run an external command (exiftool, qpdf, ...) on a copy of
+//! the file. `{input}` is the file to process and `{output}` where to write
+//! the result; without `{output}` the command edits `{input}` in place.
+
+use crate::engines::locate_program;
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Time a hook command may take when the definition sets no timeout
+pub(super) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Longest stderr excerpt kept in errors
+const STDERR_LIMIT: usize = 500;
+
+pub(super) struct CommandHook {
+    command: Vec<String>,
+    timeout: Duration,
+    /// Extra environment variables
+    env: BTreeMap<String, String>,
+}
+
+impl CommandHook {
+    pub fn new(
+        command: Vec<String>,
+        timeout: Duration,
+        env: BTreeMap<String, String>,
+    ) -> Result<Self, String> {
+        if command
+            .first()
+            .is_none_or(|program| program.trim().is_empty())
+        {
+            return Err("empty command".to_string());
+        }
+        if !command.iter().any(|arg| arg.contains("{input}")) {
+            return Err("command has no {input} placeholder".to_string());
+        }
+        Ok(Self {
+            command,
+            timeout,
+            env,
+        })
+    }
+
+    /// Run the command on `data`, saved as `filename` so tools can go by
+    /// its extension
+    pub async fn process(&self, data: Vec<u8>, filename: &str) -> Result<Vec<u8>, String> {
+        let dir = crate::disk::tempdir().map_err(|e| e.to_string())?;
+        let input_path = dir.path().join(filename);
+        let output_path = dir.path().join(format!("processed-{}", filename));
+        tokio::fs::write(&input_path, data)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let args: Vec<String> = self.command[1..]
+            .iter()
+            .map(|arg| {
+                arg.replace("{input}", &input_path.to_string_lossy())
+                    .replace("{output}", &output_path.to_string_lossy())
+            })
+            .collect();
+        let output = Command::new(locate_program(&self.command[0]).path)
+            .args(&args)
+            .envs(&self.env)
+            .current_dir(dir.path())
+            .kill_on_drop(true)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output();
+        let output = tokio::time::timeout(self.timeout, output)
+            .await
+            .map_err(|_| format!("timed out after {}s", self.timeout.as_secs()))?
+            .map_err(|e| format!("cannot run {}: {}", self.command[0], e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stderr: String = stderr.trim().chars().take(STDERR_LIMIT).collect();
+            return Err(format!("{} ({})", output.status, stderr));
+        }
+
+        let writes_output = self.command.iter().any(|arg| arg.contains("{output}"));
+        let result = if writes_output {
+            &output_path
+        } else {
+            &input_path
+        };
+        tokio::fs::read(result)
+            .await
+            .map_err(|e| format!("no output: {}", e))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_command_hook() {
+        let command = |args: &[&str]| {
+            let env = BTreeMap::from([("STAMP".to_string(), "checked".to_string())]);
+            let args = args.iter().map(|a| a.to_string()).collect();
+            CommandHook::new(args, Duration::from_secs(5), env).unwrap()
+        };
+
+        let in_place = command(&["sh", "-c", "printf ' %s' \"$STAMP\" >> \"$0\"", "{input}"]);
+        assert_eq!(
+            in_place.process(b"%PDF".to_vec(), "a.pdf").await.unwrap(),
+            b"%PDF checked"
+        );
+
+        let copy = command(&["cp", "{input}", "{output}"]);
+        assert_eq!(
+            copy.process(b"<p>".to_vec(), "a.html").await.unwrap(),
+            b"<p>"
+        );
+
+        let failing = command(&["sh", "-c", "echo broken >&2; exit 3", "{input}"]);
+        assert!(failing
+            .process(Vec::new(), "a.pdf")
+            .await
+            .unwrap_err()
+            .contains("broken"));
+
+        let slow = CommandHook::new(
+            vec!["sh".into(), "-c".into(), "sleep 5".into(), "{input}".into()],
+            Duration::from_millis(100),
+            BTreeMap::new(),
+        )
+        .unwrap();
+        assert!(slow
+            .process(Vec::new(), "a.pdf")
+            .await
+            .unwrap_err()
+            .contains("timed out"));
+    }
+}
@@ -0,0 +1,321 @@
+//! Operator-defined processing around conversions: input hooks rewrite the
+//! upload before an engine is chosen, output hooks rewrite the PDF before it
+//! is returned (e.g. custom sanitizers or stamping that cannot live in
+//! pdfmill itself). Hooks run in the order of the TOML file in
+//! `PDFMILL_HOOKS_FILE`, either as WebAssembly modules or as external
+//! commands:
+//!
+//! ```toml
+//! [[hook]]
+//! name = "stamp"
+//! stage = "output"
+//! wasm = "hooks/stamp.wasm"   # relative to the hooks file
+//!
+//! [[hook]]
+//! name = "linearize"
+//! stage = "output"
+//! command = ["qpdf", "--linearize", "{input}", "{output}"]
+//! timeout = 30                  # seconds
+//! env = { QPDF_OPTIONS = "" }
+//!
+//! [[hook]]
+//! name = "sanitize"
+//! stage = "input"
+//! extensions = ["html", "htm"]  # every upload when omitted
+//! wasm = "hooks/sanitize.wasm"
+//! ```
+
+mod command;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+use crate::encryption;
+use crate::error::{AppError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::info;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HooksFile {
+    #[serde(default)]
+    hook: Vec<Definition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definition {
+    name: String,
+    stage: Stage,
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    wasm: Option<PathBuf>,
+    #[serde(default)]
+    command: Option<Vec<String>>,
+    /// Seconds, for commands
+    #[serde(default)]
+    timeout: Option<u64>,
+    /// Environment variables, for commands
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// The uploaded file, before engine selection
+    Input,
+    /// The PDF output, before the page limit and the response
+    Output,
+}
+
+enum Processor {
+    Command(command::CommandHook),
+    #[cfg(feature = "wasm")]
+    Wasm(wasm::WasmHook),
+}
+
+impl Processor {
+    fn kind(&self) -> &'static str {
+        match *self {
+            Processor::Command(_) => "command",
+            #[cfg(feature = "wasm")]
+            Processor::Wasm(_) => "wasm",
+        }
+    }
+
+    async fn process(&self, data: Vec<u8>, filename: &str) -> std::result::Result<Vec<u8>, String> {
+        match *self {
+            Processor::Command(ref hook) => hook.process(data, filename).await,
+            #[cfg(feature = "wasm")]
+            Processor::Wasm(ref hook) => hook.process(data).await,
+        }
+    }
+}
+
+struct Hook {
+    name: String,
+    stage: Stage,
+    /// Lowercase extensions an input hook applies to; empty for all
+    extensions: Vec<String>,
+    processor: Processor,
+}
+
+impl Hook {
+    async fn run(&self, data: Vec<u8>, filename: &str) -> Result<Vec<u8>> {
+        self.processor.process(data, filename).await.map_err(|e| {
+            AppError::ConversionFailed(format!(
+                "{} hook '{}': {}",
+                self.processor.kind(),
+                self.name,
+                e
+            ))
+        })
+    }
+}
+
+/// Summary of a configured hook for /info
+#[derive(Serialize)]
+pub struct HookSummary<'a> {
+    name: &'a str,
+    stage: Stage,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    extensions: &'a [String],
+}
+
+#[derive(Default)]
+pub struct Hooks {
+    hooks: Vec<Hook>,
+}
+
+impl Hooks {
+    /// Hooks defined in `PDFMILL_HOOKS_FILE`; none when unset
+    pub fn from_env() -> std::result::Result<Self, String> {
+        let Some(path) = std::env::var_os("PDFMILL_HOOKS_FILE") else {
+            return Ok(Self::default());
+        };
+        let path = PathBuf::from(path);
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("PDFMILL_HOOKS_FILE: cannot read {:?}: {}", path, e))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        let hooks = Self::parse(&text, base).map_err(|e| format!("PDFMILL_HOOKS_FILE: {}", e))?;
+        for hook in &hooks.hooks {
+            info!(
+                "Loaded {} {} hook '{}'",
+                hook.processor.kind(),
+                if hook.stage == Stage::Input {
+                    "input"
+                } else {
+                    "output"
+                },
+                hook.name
+            );
+        }
+        Ok(hooks)
+    }
+
+    fn parse(text: &str, base: &Path) -> std::result::Result<Self, String> {
+        let file: HooksFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let mut hooks: Vec<Hook> = Vec::new();
+        for definition in file.hook {
+            let name = definition.name.trim().to_string();
+            if name.is_empty() {
+                return Err("hook without a name".to_string());
+            }
+            if hooks.iter().any(|hook| hook.name == name) {
+                return Err(format!("hook '{}' is defined twice", name));
+            }
+            if definition.stage == Stage::Output && !definition.extensions.is_empty() {
+                return Err(format!(
+                    "hook '{}': extensions only apply to input hooks",
+                    name
+                ));
+            }
+            if definition.wasm.is_some()
+                && (definition.timeout.is_some() || !definition.env.is_empty())
+            {
+                return Err(format!(
+                    "hook '{}': timeout and env only apply to commands",
+                    name
+                ));
+            }
+            if definition.timeout == Some(0) {
+                return Err(format!(
+                    "hook '{}': timeout must be at least 1 second",
+                    name
+                ));
+            }
+            let processor = match (definition.wasm, definition.command) {
+                (Some(module), None) => wasm_processor(&base.join(module)),
+                (None, Some(command)) => command::CommandHook::new(
+                    command,
+                    definition
+                        .timeout
+                        .map(Duration::from_secs)
+                        .unwrap_or(command::DEFAULT_TIMEOUT),
+                    definition.env,
+                )
+                .map(Processor::Command),
+                _ => Err("exactly one of wasm and command is required".to_string()),
+            }
+            .map_err(|e| format!("hook '{}': {}", name, e))?;
+            hooks.push(Hook {
+                name,
+                stage: definition.stage,
+                extensions: definition
+                    .extensions
+                    .iter()
+                    .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+                    .collect(),
+                processor,
+            });
+        }
+        Ok(Self { hooks })
+    }
+
+    /// Rewrite an uploaded file in place with the input hooks for its
+    /// extension
+    pub async fn process_input(&self, path: &Path) -> Result<()> {
+        let filename = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("input");
+        if self.input_hooks(filename).next().is_none() {
+            return Ok(());
+        }
+        let data = self
+            .process_input_data(filename, tokio::fs::read(path).await?)
+            .await?;
+        tokio::fs::write(path, data).await?;
+        Ok(())
+    }
+
+    /// [`Self::process_input`] for a file at rest, which is only decrypted
+    /// in memory when files at rest are encrypted
+    pub async fn process_stored_input(&self, path: &Path) -> Result<()> {
+        let filename = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("input");
+        if self.input_hooks(filename).next().is_none() {
+            return Ok(());
+        }
+        let data = self
+            .process_input_data(filename, encryption::read(path).await?)
+            .await?;
+        encryption::write(path, &data).await?;
+        Ok(())
+    }
+
+    /// [`Self::process_input`] for an upload held in memory
+    pub async fn process_input_data(&self, filename: &str, mut data: Vec<u8>) -> Result<Vec<u8>> {
+        for hook in self.input_hooks(filename) {
+            data = hook.run(data, filename).await?;
+        }
+        Ok(data)
+    }
+
+    /// Whether any input hook applies to files named like `filename`
+    pub fn rewrites_input(&self, filename: &str) -> bool {
+        self.input_hooks(filename).next().is_some()
+    }
+
+    fn input_hooks<'a>(&'a self, filename: &str) -> impl Iterator<Item = &'a Hook> {
+        let ext = Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        self.stage(Stage::Input)
+            .filter(move |hook| hook.extensions.is_empty() || hook.extensions.contains(&ext))
+    }
+
+    /// Pass PDF output through the output hooks
+    pub async fn process_output(&self, mut data: Vec<u8>) -> Result<Vec<u8>> {
+        for hook in self.stage(Stage::Output) {
+            data = hook.run(data, "output.pdf").await?;
+            if !data.starts_with(b"%PDF") {
+                return Err(AppError::ConversionFailed(format!(
+                    "{} hook '{}' did not return a PDF",
+                    hook.processor.kind(),
+                    hook.name
+                )));
+            }
+        }
+        Ok(data)
+    }
+
+    pub fn summaries(&self) -> Vec<HookSummary<'_>> {
+        self.hooks
+            .iter()
+            .map(|hook| HookSummary {
+                name: &hook.name,
+                stage: hook.stage,
+                kind: hook.processor.kind(),
+                extensions: &hook.extensions,
+            })
+            .collect()
+    }
+
+    fn stage(&self, stage: Stage) -> impl Iterator<Item = &Hook> {
+        self.hooks.iter().filter(move |hook| hook.stage == stage)
+    }
+}
+
+#[cfg(feature = "wasm")]
+fn wasm_processor(path: &Path) -> std::result::Result<Processor, String> {
+    wasm::WasmHook::load(path).map(Processor::Wasm)
+}
+
+#[cfg(not(feature = "wasm"))]
+fn wasm_processor(path: &Path) -> std::result::Result<Processor, String> {
+    Err(format!(
+        "cannot load {:?}: pdfmill was built without the wasm feature",
+        path
+    ))
+}
@@ -0,0 +1,148 @@
+//! WebAssembly hooks, run in wasmtime without any imports: a module sees
+//! only the bytes it is given. It exports its `memory` and
+//!
+//! - `alloc(len: i32) -> i32`: space for the input, returning its offset
+//! - `transform(ptr: i32, len: i32) -> i64`: process the input, returning
+//!   the output as `offset << 32 | length`, or a negative value to reject
+//!   the document
+//!
+//! Each call gets a fresh instance with limited memory and fuel, so a
+//! runaway module fails the conversion instead of hanging it.
+
+use std::path::Path;
+use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// Largest linear memory a module may grow to
+const MAX_MEMORY: usize = 512 * 1024 * 1024;
+
+/// Instructions (roughly) a single call may execute
+const FUEL: u64 = 20_000_000_000;
+
+pub(super) struct WasmHook {
+    engine: Engine,
+    module: Module,
+    fuel: u64,
+}
+
+impl WasmHook {
+    /// Compile a module (binary or text format) and check its interface
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| e.to_string())?;
+        let module = Module::from_file(&engine, path)
+            .map_err(|e| format!("cannot load {:?}: {:#}", path, e))?;
+        if let Some(import) = module.imports().next() {
+            return Err(format!(
+                "{:?} imports {}.{}; hooks may not import anything",
+                path,
+                import.module(),
+                import.name()
+            ));
+        }
+        for export in ["memory", "alloc", "transform"] {
+            if module.get_export(export).is_none() {
+                return Err(format!("{:?} does not export '{}'", path, export));
+            }
+        }
+        Ok(Self {
+            engine,
+            module,
+            fuel: FUEL,
+        })
+    }
+
+    pub async fn process(&self, data: Vec<u8>) -> Result<Vec<u8>, String> {
+        let engine = self.engine.clone();
+        let module = self.module.clone();
+        let fuel = self.fuel;
+        tokio::task::spawn_blocking(move || run(&engine, &module, fuel, &data))
+            .await
+            .map_err(|e| format!("panicked: {}", e))?
+    }
+}
+
+fn run(engine: &Engine, module: &Module, fuel: u64, data: &[u8]) -> Result<Vec<u8>, String> {
+    let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+    let mut store: Store<StoreLimits> = Store::new(engine, limits);
+    store.limiter(|limits| limits);
+    store.set_fuel(fuel).map_err(|e| e.to_string())?;
+
+    let instance = Instance::new(&mut store, module, &[]).map_err(|e| format!("{:#}", e))?;
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .ok_or("'memory' is not a memory")?;
+    let alloc = instance
+        .get_typed_func::<i32, i32>(&mut store, "alloc")
+        .map_err(|e| format!("alloc: {:#}", e))?;
+    let transform = instance
+        .get_typed_func::<(i32, i32), i64>(&mut store, "transform")
+        .map_err(|e| format!("transform: {:#}", e))?;
+
+    let len = i32::try_from(data.len()).map_err(|_| "input too large".to_string())?;
+    let ptr = alloc
+        .call(&mut store, len)
+        .map_err(|e| format!("alloc: {:#}", e))?;
+    memory
+        .write(&mut store, ptr as u32 as usize, data)
+        .map_err(|_| "alloc returned an offset outside memory".to_string())?;
+
+    let packed = transform
+        .call(&mut store, (ptr, len))
+        .map_err(|e| format!("transform: {:#}", e))?;
+    if packed < 0 {
+        return Err("rejected the document".to_string());
+    }
+    let (offset, length) = ((packed >> 32) as usize, (packed as u32) as usize);
+    let mut output = vec![0; length];
+    memory
+        .read(&store, offset, &mut output)
+        .map_err(|_| "transform returned a range outside memory".to_string())?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Echoes its input with the first byte replaced by '#', rejects empty
+    /// input and loops forever on a single byte
+    const MODULE: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+          (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+            (if (i32.eqz (local.get $len)) (then (return (i64.const -1))))
+            (if (i32.eq (local.get $len) (i32.const 1)) (then (loop $forever (br $forever))))
+            (i32.store8 (local.get $ptr) (i32.const 35))
+            (i64.or
+              (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+              (i64.extend_i32_u (local.get $len)))))
+    "#;
+
+    #[tokio::test]
+    async fn test_wasm_hook() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hook.wat");
+        std::fs::write(&path, MODULE).unwrap();
+        let mut hook = WasmHook::load(&path).unwrap();
+        hook.fuel = 1_000_000;
+
+        assert_eq!(hook.process(b"<html>".to_vec()).await.unwrap(), b"#html>");
+        assert_eq!(
+            hook.process(Vec::new()).await.unwrap_err(),
+            "rejected the document"
+        );
+        assert!(hook
+            .process(b"x".to_vec())
+            .await
+            .unwrap_err()
+            .contains("fuel"));
+
+        std::fs::write(&path, r#"(module (import "env" "f" (func)))"#).unwrap();
+        assert!(WasmHook::load(&path)
+            .err()
+            .unwrap()
+            .contains("may not import"));
+    }
+}
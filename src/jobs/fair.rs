@@ -0,0 +1,235 @@
+//! Conversion slots shared fairly between clients: a freed slot goes to the
+//! next client in round-robin order rather than to the oldest waiting job,
+//! so a client that queued hundreds of jobs cannot hold every slot while
+//! others wait behind it. Each client's own jobs still run in order.
+
+use crate::access::canonical;
+use axum::http::HeaderMap;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+/// Header naming the API key a client calls with
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Who a job is queued for: the client address (see
+/// [`AccessPolicy::request_client`](crate::access::AccessPolicy::request_client)),
+/// followed by the hashed API key (`X-API-Key`, else the `Authorization`
+/// header) when one was sent. Keys are not checked, so slots are shared
+/// between addresses and a key only orders jobs within its address's share.
+pub fn client_key(headers: &HeaderMap, client: IpAddr) -> String {
+    let key = [API_KEY_HEADER, "authorization"]
+        .iter()
+        .filter_map(|name| headers.get(*name)?.to_str().ok())
+        .map(str::trim)
+        .find(|value| !value.is_empty());
+    match key {
+        Some(key) => format!("{}/{}", ip_client(client), api_key_client(key)),
+        None => ip_client(client),
+    }
+}
+
+/// Client key of callers from `ip`; IPv4-mapped addresses of dual-stack
+/// sockets count as the IPv4 address
+pub fn ip_client(ip: IpAddr) -> String {
+    format!("ip:{}", canonical(ip))
+}
+
+/// Key of the callers using an API key, within their address
+pub fn api_key_client(key: &str) -> String {
+    // Keys are secrets; only a digest is kept in memory and logs
+    format!(
+        "key:{}",
+        &crate::engines::sha256_hex(key.trim().as_bytes())[..16]
+    )
+}
+
+/// Whether a job of `client` is selected by `filter`: an [`ip_client`]
+/// selects every job from the address, an [`api_key_client`] the jobs sent
+/// with the key from any address
+pub fn matches_client(client: &str, filter: &str) -> bool {
+    let (address, key) = split_client(client);
+    filter == address || key == Some(filter)
+}
+
+/// The address of a [`client_key`] and its API key, if any
+fn split_client(client: &str) -> (&str, Option<&str>) {
+    match client.split_once('/') {
+        Some((address, key)) => (address, Some(key)),
+        None => (client, None),
+    }
+}
+
+/// A bounded number of slots handed out round-robin across clients
+pub struct FairSlots {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    capacity: usize,
+    running: usize,
+    /// Waiting jobs of each client address
+    waiting: HashMap<String, Share>,
+    /// Client addresses with waiting jobs, in the order they are served
+    turns: VecDeque<String>,
+}
+
+/// The waiting jobs of one client address, taking turns by API key
+#[derive(Default)]
+struct Share {
+    /// Waiting jobs of each client, oldest first
+    waiting: HashMap<String, VecDeque<oneshot::Sender<Slot>>>,
+    /// Clients with waiting jobs, in the order they are served
+    turns: VecDeque<String>,
+}
+
+/// A held slot; dropping it passes the slot to the next client's job
+pub struct Slot {
+    state: Arc<Mutex<State>>,
+}
+
+impl FairSlots {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                capacity,
+                running: 0,
+                waiting: HashMap::new(),
+                turns: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Wait for a slot for one of `client`'s jobs
+    pub async fn acquire(&self, client: &str) -> Slot {
+        let rx = {
+            let mut state = self.state.lock().unwrap();
+            if state.running < state.capacity && state.turns.is_empty() {
+                state.running += 1;
+                return Slot {
+                    state: Arc::clone(&self.state),
+                };
+            }
+            let (tx, rx) = oneshot::channel();
+            let (address, _) = split_client(client);
+            if !state.waiting.contains_key(address) {
+                state.turns.push_back(address.to_string());
+            }
+            let share = state.waiting.entry(address.to_string()).or_default();
+            let queue = share.waiting.entry(client.to_string()).or_default();
+            queue.push_back(tx);
+            if queue.len() == 1 {
+                share.turns.push_back(client.to_string());
+            }
+            rx
+        };
+        // The sender lives until the slot is handed over
+        rx.await
+            .expect("waiting jobs are only dropped with their slot")
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut slot = Slot {
+            state: Arc::clone(&self.state),
+        };
+        let mut state = self.state.lock().unwrap();
+        while let Some(address) = state.turns.pop_front() {
+            let share = state
+                .waiting
+                .get_mut(&address)
+                .expect("addresses in turn are waiting");
+            let client = share
+                .turns
+                .pop_front()
+                .expect("waiting addresses have clients in turn");
+            let queue = share
+                .waiting
+                .get_mut(&client)
+                .expect("clients in turn are waiting");
+            let next = queue.pop_front();
+            if queue.is_empty() {
+                share.waiting.remove(&client);
+            } else {
+                share.turns.push_back(client);
+            }
+            if share.turns.is_empty() {
+                state.waiting.remove(&address);
+            } else {
+                state.turns.push_back(address);
+            }
+            // A job cancelled while waiting hands the slot back, and it
+            // goes to the next in turn
+            match next.map(|tx| tx.send(slot)) {
+                Some(Ok(())) => return,
+                Some(Err(returned)) => slot = returned,
+                None => unreachable!("waiting queues are never empty"),
+            }
+        }
+        state.running -= 1;
+        // The slot was not handed over; don't run this again for it
+        drop(state);
+        std::mem::forget(slot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_round_robin() {
+        let slots = Arc::new(FairSlots::new(1));
+        let first = slots.acquire("a").await;
+
+        // Address a queues three jobs, two of them with key x and one with
+        // key y, before b queues one
+        let (order_tx, mut order_rx) = tokio::sync::mpsc::unbounded_channel();
+        for (client, job) in [("a/x", 1), ("a/x", 2), ("a/y", 1), ("b", 1)] {
+            let slots = Arc::clone(&slots);
+            let order_tx = order_tx.clone();
+            tokio::spawn(async move {
+                let _slot = slots.acquire(client).await;
+                order_tx.send(format!("{}{}", client, job)).unwrap();
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            });
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(slots.state.lock().unwrap().turns, ["a", "b"]);
+
+        drop(first);
+        let mut order = Vec::new();
+        for _ in 0..4 {
+            order.push(order_rx.recv().await.unwrap());
+        }
+        // Keys take turns within a's share rather than getting one each
+        assert_eq!(order, ["a/x1", "b1", "a/y1", "a/x2"]);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(slots.state.lock().unwrap().waiting.is_empty());
+        assert_eq!(slots.state.lock().unwrap().running, 0);
+    }
+
+    #[test]
+    fn test_client_key() {
+        let peer: IpAddr = "192.0.2.7".parse().unwrap();
+        assert_eq!(client_key(&HeaderMap::new(), peer), "ip:192.0.2.7");
+        let mapped: IpAddr = "::ffff:192.0.2.7".parse().unwrap();
+        assert_eq!(client_key(&HeaderMap::new(), mapped), "ip:192.0.2.7");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(API_KEY_HEADER, "secret".parse().unwrap());
+        let key = client_key(&headers, peer);
+        assert!(!key.contains("secret"), "{}", key);
+        assert_eq!(key, format!("ip:192.0.2.7/{}", api_key_client("secret")));
+        assert_eq!(api_key_client("secret").len(), 20);
+
+        assert!(matches_client(&key, "ip:192.0.2.7"));
+        assert!(matches_client(&key, &api_key_client("secret")));
+        assert!(!matches_client(&key, &api_key_client("other")));
+        assert!(matches_client("ip:192.0.2.7", "ip:192.0.2.7"));
+        assert!(!matches_client("ip:192.0.2.70", "ip:192.0.2.7"));
+    }
+}
@@ -0,0 +1,97 @@
+//! Hand-off of slow synchronous conversions to the job queue. A `/convert`
+//! request still converting after a threshold is answered with 202 and the
+//! URL of a job that carries on with the same conversion, so clients that
+//! follow `Location` stop holding connections open for minutes. Operators
+//! enable it for everyone with `PDFMILL_ASYNC_AFTER`; a client can ask for
+//! it with `Prefer: respond-async` (RFC 7240), optionally with `wait=<s>`.
+
+use axum::http::HeaderMap;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Threshold for clients that prefer an asynchronous answer but name no
+/// `wait` when the operator configured none
+const DEFAULT_WAIT: Duration = Duration::from_secs(10);
+
+/// `PDFMILL_ASYNC_AFTER`: seconds after which every synchronous conversion
+/// is handed off; unset, only clients that ask are
+static ASYNC_AFTER: LazyLock<Option<Duration>> = LazyLock::new(|| {
+    std::env::var("PDFMILL_ASYNC_AFTER")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+});
+
+/// How long a synchronous conversion may run before it is handed off, if
+/// it may be at all
+pub fn handoff_after(headers: &HeaderMap) -> Option<Duration> {
+    threshold(headers, *ASYNC_AFTER)
+}
+
+fn threshold(headers: &HeaderMap, configured: Option<Duration>) -> Option<Duration> {
+    let mut respond_async = false;
+    let mut wait = None;
+    let preferences = headers
+        .get_all("prefer")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','));
+    for preference in preferences {
+        // Parameters after ';' do not matter to either preference
+        let token = preference.split(';').next().unwrap_or_default();
+        let (name, value) = match token.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+            None => (token.trim(), None),
+        };
+        if name.eq_ignore_ascii_case("respond-async") {
+            respond_async = true;
+        } else if name.eq_ignore_ascii_case("wait") {
+            wait = value
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(Duration::from_secs);
+        }
+    }
+    match respond_async {
+        true => wait.or(configured).or(Some(DEFAULT_WAIT)),
+        false => configured,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_threshold() {
+        let headers = |values: &[&'static str]| {
+            let mut headers = HeaderMap::new();
+            for value in values {
+                headers.append("prefer", HeaderValue::from_static(value));
+            }
+            headers
+        };
+        let secs = Duration::from_secs;
+        assert_eq!(threshold(&headers(&[]), None), None);
+        assert_eq!(threshold(&headers(&[]), Some(secs(30))), Some(secs(30)));
+        assert_eq!(
+            threshold(&headers(&["respond-async"]), None),
+            Some(DEFAULT_WAIT)
+        );
+        assert_eq!(
+            threshold(&headers(&["respond-async"]), Some(secs(30))),
+            Some(secs(30))
+        );
+        assert_eq!(
+            threshold(&headers(&["Respond-Async, wait=5"]), Some(secs(30))),
+            Some(secs(5))
+        );
+        assert_eq!(
+            threshold(&headers(&["wait=\"2\"", "respond-async"]), None),
+            Some(secs(2))
+        );
+        // Waiting alone asks for nothing
+        assert_eq!(threshold(&headers(&["wait=5"]), None), None);
+        assert_eq!(threshold(&headers(&["return=minimal"]), None), None);
+    }
+}
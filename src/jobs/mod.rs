@@ -0,0 +1,1154 @@
+//! Asynchronous conversion jobs: uploads are converted in the background and
+//! their results fetched (or the job cancelled) later by id
+
+mod fair;
+mod handoff;
+
+pub use fair::{api_key_client, client_key, ip_client, matches_client};
+pub use handoff::handoff_after;
+
+use crate::disk::Upload;
+use crate::encryption::{self, Plaintext};
+use crate::engines::{audit_links, ignored_options, sha256_hex, ConvertOptions, ConvertResult};
+use crate::error::{AppError, Result};
+use crate::mailer::Mailer;
+use crate::manifest::{embed_provenance, FileDigest, ProvenanceRecord};
+use crate::metrics;
+use crate::pdf::LinkReport;
+use crate::router::SmartRouter;
+use chrono::{DateTime, Utc};
+use fair::FairSlots;
+use lettre::message::Mailbox;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Page size of job listings unless the client asks otherwise
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Largest page a job listing returns
+pub const MAX_PAGE_SIZE: usize = 500;
+
+/// Finished jobs (and their files) are kept this long unless overridden
+const DEFAULT_RETENTION_SECS: u64 = 3600;
+
+/// Failed attempts after which a job is dead-lettered unless overridden
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// How long a dead-letter webhook may take to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often expired jobs are swept
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    /// Failed too many times to be retried
+    #[serde(rename = "dead_letter")]
+    DeadLetter,
+}
+
+impl JobStatus {
+    pub const ALL: [JobStatus; 6] = [
+        Self::Queued,
+        Self::Running,
+        Self::Succeeded,
+        Self::Failed,
+        Self::Cancelled,
+        Self::DeadLetter,
+    ];
+
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            Self::Succeeded | Self::Failed | Self::Cancelled | Self::DeadLetter
+        )
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+            Self::DeadLetter => "dead_letter",
+        }
+    }
+}
+
+impl FromStr for JobStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim() {
+            "queued" => Ok(Self::Queued),
+            "running" => Ok(Self::Running),
+            "succeeded" => Ok(Self::Succeeded),
+            "failed" => Ok(Self::Failed),
+            "cancelled" => Ok(Self::Cancelled),
+            "dead_letter" => Ok(Self::DeadLetter),
+            other => Err(format!(
+                "expected queued, running, succeeded, failed, cancelled or dead_letter, got '{}'",
+                other
+            )),
+        }
+    }
+}
+
+/// Which jobs a listing includes
+#[derive(Debug, Clone, Default)]
+pub struct JobFilter {
+    pub status: Option<JobStatus>,
+    /// Only jobs created at or after this time
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl JobFilter {
+    fn matches(&self, job: &JobInfo) -> bool {
+        self.status.is_none_or(|status| job.status == status)
+            && self.since.is_none_or(|since| job.created_at >= since)
+    }
+}
+
+/// Which jobs a purge removes; unset criteria match every job
+#[derive(Debug, Clone, Default)]
+pub struct PurgeFilter {
+    /// Only jobs created before this time
+    pub before: Option<DateTime<Utc>>,
+    /// Only jobs of this client, see [`matches_client`]
+    pub client: Option<String>,
+}
+
+impl PurgeFilter {
+    fn matches(&self, job: &JobEntry) -> bool {
+        self.before
+            .is_none_or(|before| job.info.created_at < before)
+            && self
+                .client
+                .as_ref()
+                .is_none_or(|client| matches_client(&job.client, client))
+    }
+}
+
+/// Public view of a job, as returned by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: Uuid,
+    pub status: JobStatus,
+    pub filename: String,
+    /// Engine label (name/version) once the job started
+    pub engine: Option<String>,
+    pub error: Option<String>,
+    /// Number of times the job was started, counting retries
+    pub attempts: u32,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// SHA-256 of the result, once the job succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Diagnostics of every failed attempt, oldest first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<JobFailure>,
+    /// Outcome of the hyperlink audit, when `linkAudit` was set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub links: Option<LinkReport>,
+    /// Problems that did not fail the conversion, once the job succeeded
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// When the job's files and options were deleted on request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_deleted_at: Option<DateTime<Utc>>,
+}
+
+/// What went wrong in one attempt of a job
+#[derive(Debug, Clone, Serialize)]
+pub struct JobFailure {
+    pub attempt: u32,
+    pub engine: Option<String>,
+    pub error: String,
+    /// Exit code of the conversion tool, if it ran and exited
+    pub exit_code: Option<i32>,
+    /// Standard error of the conversion tool
+    pub stderr: Option<String>,
+    pub failed_at: DateTime<Utc>,
+}
+
+impl JobFailure {
+    fn new(attempt: u32, engine: Option<String>, error: &AppError) -> Self {
+        let (exit_code, stderr) = match error {
+            AppError::ToolFailed {
+                exit_code, stderr, ..
+            } => (*exit_code, Some(stderr.clone()).filter(|s| !s.is_empty())),
+            _ => (None, None),
+        };
+        Self {
+            attempt,
+            engine,
+            error: error.to_string(),
+            exit_code,
+            stderr,
+            failed_at: Utc::now(),
+        }
+    }
+}
+
+/// Converted file of a succeeded job
+#[derive(Debug, Clone)]
+pub struct JobOutput {
+    pub path: PathBuf,
+    pub filename: String,
+    pub content_type: String,
+    pub sha256: String,
+    pub links: Option<LinkReport>,
+    pub warnings: Vec<String>,
+}
+
+/// Inputs and output of a succeeded job
+pub struct Provenance {
+    pub info: JobInfo,
+    pub input_sha256: String,
+    pub options: ConvertOptions,
+    pub output: JobOutput,
+}
+
+struct JobEntry {
+    info: JobInfo,
+    cancel: CancellationToken,
+    /// Holds the input and output files; dropping it deletes them
+    dir: Option<TempDir>,
+    input_path: PathBuf,
+    /// SHA-256 of the uploaded input
+    input_sha256: String,
+    options: ConvertOptions,
+    /// Engine name forcing a specific engine instead of automatic routing
+    engine: Option<String>,
+    /// Recipients the result is emailed to once converted
+    email_to: Vec<Mailbox>,
+    /// Client the job was submitted by, see [`client_key`]
+    client: String,
+    output: Option<JobOutput>,
+}
+
+/// In-memory job registry with a bounded number of concurrent conversions,
+/// shared fairly between clients
+pub struct JobManager {
+    jobs: Mutex<HashMap<Uuid, JobEntry>>,
+    slots: FairSlots,
+    retention: Duration,
+    /// Failed attempts after which a job is dead-lettered
+    max_attempts: u32,
+    /// URL notified with a JSON POST when a job is dead-lettered
+    dead_letter_webhook: Option<String>,
+    http: reqwest::Client,
+    mailer: Option<Arc<Mailer>>,
+}
+
+impl JobManager {
+    /// Configured by `PDFMILL_JOB_CONCURRENCY` (default: CPU count, always 1
+    /// in the low-memory profile), `PDFMILL_JOB_RETENTION` in seconds
+    /// (default: one hour), `PDFMILL_JOB_MAX_ATTEMPTS` (default: 3) and
+    /// `PDFMILL_DEAD_LETTER_WEBHOOK`. Results are emailed through `mailer`.
+    pub fn from_env(mailer: Option<Arc<Mailer>>) -> Self {
+        let concurrency = std::env::var("PDFMILL_JOB_CONCURRENCY")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(2)
+            });
+        let concurrency = if crate::profile::low_memory() {
+            1
+        } else {
+            concurrency
+        };
+        let retention = std::env::var("PDFMILL_JOB_RETENTION")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_RETENTION_SECS);
+        let max_attempts = std::env::var("PDFMILL_JOB_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_MAX_ATTEMPTS);
+        let dead_letter_webhook = std::env::var("PDFMILL_DEAD_LETTER_WEBHOOK")
+            .ok()
+            .filter(|v| !v.trim().is_empty());
+
+        Self {
+            max_attempts,
+            dead_letter_webhook,
+            mailer,
+            ..Self::new(concurrency, Duration::from_secs(retention))
+        }
+    }
+
+    pub fn new(concurrency: usize, retention: Duration) -> Self {
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            slots: FairSlots::new(concurrency),
+            retention,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            dead_letter_webhook: None,
+            http: reqwest::Client::new(),
+            mailer: None,
+        }
+    }
+
+    /// Store the upload and queue its conversion for `client`; the result is
+    /// emailed to `email_to` when given
+    pub async fn submit(
+        self: &Arc<Self>,
+        router: Arc<SmartRouter>,
+        client: String,
+        filename: String,
+        data: Upload,
+        options: ConvertOptions,
+        email_to: Vec<Mailbox>,
+    ) -> Result<JobInfo> {
+        if !email_to.is_empty() && self.mailer.is_none() {
+            return Err(email_not_configured());
+        }
+
+        let dir = crate::disk::tempdir()?;
+        tokio::fs::create_dir(dir.path().join("input")).await?;
+        let input_path = dir.path().join("input").join(&filename);
+        let input_sha256 = data.sha256();
+        data.store(&input_path).await?;
+        // Once, so retries start from the same processed input
+        router.prepare_stored_input(&input_path).await?;
+
+        let info = JobInfo {
+            id: Uuid::new_v4(),
+            status: JobStatus::Queued,
+            filename,
+            engine: None,
+            error: None,
+            attempts: 1,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            sha256: None,
+            failures: Vec::new(),
+            links: None,
+            warnings: Vec::new(),
+            data_deleted_at: None,
+        };
+        let cancel = CancellationToken::new();
+
+        self.jobs.lock().unwrap().insert(
+            info.id,
+            JobEntry {
+                info: info.clone(),
+                cancel: cancel.clone(),
+                dir: Some(dir),
+                input_path: input_path.clone(),
+                input_sha256,
+                options: options.clone(),
+                engine: None,
+                email_to,
+                client: client.clone(),
+                output: None,
+            },
+        );
+        info!("Queued job {} for {}", info.id, info.filename);
+
+        self.start(router, info.id, client, input_path, options, None, cancel);
+        Ok(info)
+    }
+
+    /// Queue a failed job again with its stored input, optionally with new
+    /// options and/or a specific engine
+    pub fn retry(
+        self: &Arc<Self>,
+        router: Arc<SmartRouter>,
+        id: Uuid,
+        options: Option<ConvertOptions>,
+        engine: Option<String>,
+    ) -> Result<JobInfo> {
+        let (info, client, input_path, options, engine, cancel) = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let job = jobs.get_mut(&id).ok_or_else(|| not_found(id))?;
+            if job.info.status == JobStatus::DeadLetter {
+                return Err(AppError::Conflict(format!(
+                    "Job {} was dead-lettered after {} failed attempts",
+                    id, job.info.attempts
+                )));
+            }
+            if job.info.status != JobStatus::Failed {
+                return Err(AppError::Conflict(format!(
+                    "Job {} is {}; only failed jobs can be retried",
+                    id,
+                    job.info.status.name()
+                )));
+            }
+            if job.dir.is_none() {
+                return Err(AppError::Conflict(format!(
+                    "Input of job {} is no longer available",
+                    id
+                )));
+            }
+
+            if let Some(options) = options {
+                job.options = options;
+            }
+            if engine.is_some() {
+                job.engine = engine;
+            }
+            job.cancel = CancellationToken::new();
+            job.info.status = JobStatus::Queued;
+            job.info.engine = None;
+            job.info.error = None;
+            job.info.attempts += 1;
+            job.info.started_at = None;
+            job.info.finished_at = None;
+            (
+                job.info.clone(),
+                job.client.clone(),
+                job.input_path.clone(),
+                job.options.clone(),
+                job.engine.clone(),
+                job.cancel.clone(),
+            )
+        };
+        info!("Retrying job {} (attempt {})", id, info.attempts);
+
+        self.start(router, id, client, input_path, options, engine, cancel);
+        Ok(info)
+    }
+
+    /// Run the conversion in the background until it finishes or the job
+    /// is cancelled
+    #[allow(clippy::too_many_arguments)]
+    fn start(
+        self: &Arc<Self>,
+        router: Arc<SmartRouter>,
+        id: Uuid,
+        client: String,
+        input_path: PathBuf,
+        options: ConvertOptions,
+        engine: Option<String>,
+        cancel: CancellationToken,
+    ) {
+        let manager = Arc::clone(self);
+        self.spawn(id, cancel, async move {
+            manager
+                .run(
+                    &router,
+                    id,
+                    &client,
+                    &input_path,
+                    &options,
+                    engine.as_deref(),
+                )
+                .await
+        });
+    }
+
+    /// Take over a synchronous conversion that is already running, keeping
+    /// its workspace `dir` with the upload `filename` in it. The client
+    /// that sent it fetches the result as a job.
+    #[allow(clippy::too_many_arguments)]
+    pub fn adopt<F>(
+        self: &Arc<Self>,
+        client: String,
+        filename: String,
+        dir: TempDir,
+        input_sha256: String,
+        options: ConvertOptions,
+        engine_label: String,
+        conversion: F,
+    ) -> JobInfo
+    where
+        F: Future<Output = Result<(ConvertResult, Option<LinkReport>)>> + Send + 'static,
+    {
+        let (info, cancel) =
+            self.register(client, filename, dir, input_sha256, options, engine_label);
+        info!(
+            "Job {} took over the conversion of {}",
+            info.id, info.filename
+        );
+
+        let manager = Arc::clone(self);
+        let id = info.id;
+        self.spawn(id, cancel, async move {
+            let outcome = conversion.await;
+            manager.seal_input(id).await?;
+            let (result, links) = outcome?;
+            manager.store(id, result, links).await
+        });
+        info
+    }
+
+    /// Keep the result of a finished synchronous conversion as a succeeded
+    /// job, so the client can fetch the file later, until it expires
+    #[allow(clippy::too_many_arguments)]
+    pub async fn keep(
+        &self,
+        client: String,
+        filename: String,
+        dir: TempDir,
+        input_sha256: String,
+        options: ConvertOptions,
+        engine_label: String,
+        result: ConvertResult,
+        links: Option<LinkReport>,
+    ) -> Result<JobInfo> {
+        let (info, _) = self.register(client, filename, dir, input_sha256, options, engine_label);
+        let output = match self.seal_input(info.id).await {
+            Ok(()) => self.store(info.id, result, links).await,
+            Err(e) => Err(e),
+        };
+        if output.is_err() {
+            self.jobs.lock().unwrap().remove(&info.id);
+        }
+        self.finish(info.id, Ok(output?));
+        info!("Kept the result of {} as job {}", info.filename, info.id);
+        self.get(info.id)
+    }
+
+    /// Add a running job for a conversion started outside the queue, in
+    /// workspace `dir` with the upload `filename` in it
+    fn register(
+        &self,
+        client: String,
+        filename: String,
+        dir: TempDir,
+        input_sha256: String,
+        options: ConvertOptions,
+        engine_label: String,
+    ) -> (JobInfo, CancellationToken) {
+        let now = Utc::now();
+        let info = JobInfo {
+            id: Uuid::new_v4(),
+            status: JobStatus::Running,
+            filename,
+            engine: Some(engine_label),
+            error: None,
+            attempts: 1,
+            created_at: now,
+            started_at: Some(now),
+            finished_at: None,
+            sha256: None,
+            failures: Vec::new(),
+            links: None,
+            warnings: Vec::new(),
+            data_deleted_at: None,
+        };
+        let cancel = CancellationToken::new();
+        let input_path = dir.path().join(&info.filename);
+
+        self.jobs.lock().unwrap().insert(
+            info.id,
+            JobEntry {
+                info: info.clone(),
+                cancel: cancel.clone(),
+                dir: Some(dir),
+                input_path,
+                input_sha256,
+                options,
+                engine: None,
+                email_to: Vec::new(),
+                client,
+                output: None,
+            },
+        );
+        (info, cancel)
+    }
+
+    /// Encrypt the input of a conversion started outside the queue, once
+    /// its engine is done with it
+    async fn seal_input(&self, id: Uuid) -> Result<()> {
+        let input_path = {
+            let jobs = self.jobs.lock().unwrap();
+            let job = jobs.get(&id).ok_or_else(|| not_found(id))?;
+            // Gone once the job was cancelled
+            job.dir.as_ref().ok_or_else(|| not_found(id))?;
+            job.input_path.clone()
+        };
+        match encryption::seal_in_place(&input_path).await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                // Better to lose the input than to keep it in plaintext
+                let _ = tokio::fs::remove_file(&input_path).await;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Run a job's work in the background until it finishes or the job is
+    /// cancelled
+    fn spawn(
+        self: &Arc<Self>,
+        id: Uuid,
+        cancel: CancellationToken,
+        work: impl Future<Output = Result<JobOutput>> + Send + 'static,
+    ) {
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            tokio::select! {
+                // Dropping the conversion kills its child process and closes
+                // its browser tab; cancel() already recorded the new state
+                _ = cancel.cancelled() => info!("Job {} cancelled", id),
+                outcome = work => {
+                    if let Some(job) = manager.finish(id, outcome) {
+                        manager.notify_dead_letter(&job).await;
+                    }
+                }
+            }
+        });
+    }
+
+    async fn run(
+        &self,
+        router: &SmartRouter,
+        id: Uuid,
+        client: &str,
+        input_path: &Path,
+        options: &ConvertOptions,
+        engine: Option<&str>,
+    ) -> Result<JobOutput> {
+        let _slot = self.slots.acquire(client).await;
+        let slot = router.conversion_slot().await;
+        // Decrypted only while the engine works on it
+        let input = Plaintext::open(input_path).await?;
+        let input_path = input.path();
+
+        let engine = match engine {
+            Some(name) => router.find_named_engine_for_file(name, input_path)?,
+            None => router.find_engine_for_file(input_path)?,
+        };
+        let label = router.engine_label(&engine.engine_type());
+        self.update(id, |job| {
+            job.info.status = JobStatus::Running;
+            job.info.started_at = Some(Utc::now());
+            job.info.engine = Some(label.clone());
+        });
+
+        let ignored = ignored_options(engine.as_ref(), input_path, options);
+        let converted = match engine.convert(input_path, options).await {
+            Ok(result) => router.append_attachments(input_path, result, options).await,
+            Err(e) => Err(e),
+        };
+        let result = match converted {
+            Ok(result) => router.finish(result).await,
+            Err(e) => Err(e),
+        };
+        metrics::record_conversion(engine.engine_type().name(), result.is_ok());
+        drop(slot);
+        let (mut result, links) = match result? {
+            result if options.link_audit => audit_links(input_path, result).await?,
+            result => (result, None),
+        };
+        result.warnings.splice(0..0, ignored);
+        if options.embed_provenance {
+            let (filename, sha256) = {
+                let jobs = self.jobs.lock().unwrap();
+                let job = jobs.get(&id).ok_or_else(|| not_found(id))?;
+                (job.info.filename.clone(), job.input_sha256.clone())
+            };
+            let record = ProvenanceRecord {
+                input: FileDigest {
+                    filename: &filename,
+                    sha256: &sha256,
+                },
+                options,
+                engine: &label,
+                created_at: Utc::now(),
+            };
+            result = embed_provenance(result, &record).await?;
+        }
+        self.store(id, result, links).await
+    }
+
+    /// Keep a job's converted file in its workspace and email it when asked
+    async fn store(
+        &self,
+        id: Uuid,
+        result: ConvertResult,
+        links: Option<LinkReport>,
+    ) -> Result<JobOutput> {
+        let (output_dir, filename, email_to) = {
+            let jobs = self.jobs.lock().unwrap();
+            let job = jobs.get(&id).ok_or_else(|| not_found(id))?;
+            // Gone once the job was cancelled
+            let dir = job.dir.as_ref().ok_or_else(|| not_found(id))?;
+            (
+                dir.path().join("output"),
+                job.info.filename.clone(),
+                job.email_to.clone(),
+            )
+        };
+        tokio::fs::create_dir_all(&output_dir).await?;
+        let path = output_dir.join(&result.filename);
+        encryption::write(&path, &result.data).await?;
+        let sha256 = sha256_hex(&result.data);
+
+        if !email_to.is_empty() {
+            let mailer = self.mailer.as_ref().ok_or_else(email_not_configured)?;
+            mailer
+                .send(
+                    &email_to,
+                    &filename,
+                    &result.filename,
+                    &result.content_type,
+                    result.data,
+                )
+                .await?;
+        }
+
+        Ok(JobOutput {
+            path,
+            filename: result.filename,
+            content_type: result.content_type,
+            sha256,
+            links,
+            warnings: result.warnings,
+        })
+    }
+
+    /// Record the outcome of an attempt; returns the job if it was just
+    /// dead-lettered
+    fn finish(&self, id: Uuid, outcome: Result<JobOutput>) -> Option<JobInfo> {
+        self.update(id, |job| {
+            if job.info.status.is_finished() {
+                return None;
+            }
+            job.info.finished_at = Some(Utc::now());
+            match outcome {
+                Ok(output) => {
+                    job.info.status = JobStatus::Succeeded;
+                    job.info.sha256 = Some(output.sha256.clone());
+                    job.info.links = output.links.clone();
+                    job.info.warnings = output.warnings.clone();
+                    job.output = Some(output);
+                    None
+                }
+                Err(e) => {
+                    warn!("Job {} failed (attempt {}): {}", id, job.info.attempts, e);
+                    job.info.failures.push(JobFailure::new(
+                        job.info.attempts,
+                        job.info.engine.clone(),
+                        &e,
+                    ));
+                    job.info.error = Some(e.to_string());
+                    if job.info.attempts < self.max_attempts {
+                        job.info.status = JobStatus::Failed;
+                        return None;
+                    }
+                    warn!(
+                        "Job {} dead-lettered after {} attempts",
+                        id, job.info.attempts
+                    );
+                    job.info.status = JobStatus::DeadLetter;
+                    metrics::increment("pdfmill_jobs_dead_lettered_total", &[]);
+                    Some(job.info.clone())
+                }
+            }
+        })
+        .flatten()
+    }
+
+    /// POST the dead-lettered job to the configured webhook, if any
+    async fn notify_dead_letter(&self, job: &JobInfo) {
+        let Some(url) = &self.dead_letter_webhook else {
+            return;
+        };
+        let payload = serde_json::json!({
+            "event": "job.dead_letter",
+            "job": job,
+        });
+        let sent = self
+            .http
+            .post(url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = sent {
+            warn!("Dead-letter webhook for job {} failed: {}", job.id, e);
+        }
+    }
+
+    fn update<T>(&self, id: Uuid, f: impl FnOnce(&mut JobEntry) -> T) -> Option<T> {
+        self.jobs.lock().unwrap().get_mut(&id).map(f)
+    }
+
+    pub fn get(&self, id: Uuid) -> Result<JobInfo> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|job| job.info.clone())
+            .ok_or_else(|| not_found(id))
+    }
+
+    /// Jobs matching `filter`, newest first, along with the number of matches
+    /// before `offset` and `limit` are applied
+    pub fn list(&self, filter: &JobFilter, offset: usize, limit: usize) -> (usize, Vec<JobInfo>) {
+        let mut matching: Vec<JobInfo> = self
+            .jobs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| filter.matches(&job.info))
+            .map(|job| job.info.clone())
+            .collect();
+        matching.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.id.cmp(&b.id)));
+
+        let total = matching.len();
+        let page = matching.into_iter().skip(offset).take(limit).collect();
+        (total, page)
+    }
+
+    /// Options and forced engine the job was last run with
+    pub fn settings(&self, id: Uuid) -> Result<(ConvertOptions, Option<String>)> {
+        let jobs = self.jobs.lock().unwrap();
+        let job = jobs.get(&id).ok_or_else(|| not_found(id))?;
+        Ok((job.options.clone(), job.engine.clone()))
+    }
+
+    /// Number of jobs in each status
+    pub fn status_counts(&self) -> Vec<(JobStatus, usize)> {
+        let jobs = self.jobs.lock().unwrap();
+        JobStatus::ALL
+            .iter()
+            .map(|status| {
+                let count = jobs
+                    .values()
+                    .filter(|job| job.info.status == *status)
+                    .count();
+                (*status, count)
+            })
+            .collect()
+    }
+
+    /// Output file of a succeeded job
+    pub fn output(&self, id: Uuid) -> Result<JobOutput> {
+        let jobs = self.jobs.lock().unwrap();
+        let job = jobs.get(&id).ok_or_else(|| not_found(id))?;
+        if job.info.data_deleted_at.is_some() {
+            return Err(AppError::Conflict(format!(
+                "Data of job {} was deleted",
+                id
+            )));
+        }
+        job.output
+            .clone()
+            .ok_or_else(|| AppError::Conflict(format!("Job {} is {}", id, job.info.status.name())))
+    }
+
+    /// What a succeeded job's output was produced from, for its manifest
+    pub fn provenance(&self, id: Uuid) -> Result<Provenance> {
+        let output = self.output(id)?;
+        let jobs = self.jobs.lock().unwrap();
+        let job = jobs.get(&id).ok_or_else(|| not_found(id))?;
+        Ok(Provenance {
+            info: job.info.clone(),
+            input_sha256: job.input_sha256.clone(),
+            options: job.options.clone(),
+            output,
+        })
+    }
+
+    /// Cancel a queued or running job and delete its files
+    pub fn cancel(&self, id: Uuid) -> Result<JobInfo> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(&id).ok_or_else(|| not_found(id))?;
+        if job.info.status.is_finished() {
+            return Err(AppError::Conflict(format!(
+                "Job {} is already {}",
+                id,
+                job.info.status.name()
+            )));
+        }
+
+        job.info.status = JobStatus::Cancelled;
+        job.info.finished_at = Some(Utc::now());
+        job.cancel.cancel();
+        job.dir = None;
+        job.output = None;
+        Ok(job.info.clone())
+    }
+
+    /// Delete a job's input, output and options before it expires, cancelling
+    /// it if unfinished. The record stays until the retention period ends, so
+    /// clients polling it learn what happened; deleting again changes nothing.
+    pub fn delete_data(&self, id: Uuid) -> Result<JobInfo> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(&id).ok_or_else(|| not_found(id))?;
+        if job.info.data_deleted_at.is_none() {
+            erase(job);
+            info!("Deleted the data of job {}", id);
+        }
+        Ok(job.info.clone())
+    }
+
+    /// Remove the jobs matching `filter` with their files, cancelling the
+    /// unfinished ones; returns the ids removed
+    pub fn purge(&self, filter: &PurgeFilter) -> Vec<Uuid> {
+        let mut purged = Vec::new();
+        self.jobs.lock().unwrap().retain(|id, job| {
+            if !filter.matches(job) {
+                return true;
+            }
+            job.cancel.cancel();
+            purged.push(*id);
+            false
+        });
+        purged.sort();
+        info!("Purged {} jobs", purged.len());
+        purged
+    }
+
+    /// Periodically forget finished jobs older than the retention period
+    pub fn spawn_sweeper(self: &Arc<Self>) {
+        let manager = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                manager.sweep();
+            }
+        });
+    }
+
+    fn sweep(&self) {
+        let Ok(retention) = chrono::Duration::from_std(self.retention) else {
+            return;
+        };
+        let cutoff = Utc::now() - retention;
+        self.jobs.lock().unwrap().retain(|_, job| {
+            job.info
+                .finished_at
+                .is_none_or(|finished| finished > cutoff)
+        });
+    }
+}
+
+/// Cancel the job if unfinished and drop its files, options and recipients
+fn erase(job: &mut JobEntry) {
+    let now = Utc::now();
+    if !job.info.status.is_finished() {
+        job.info.status = JobStatus::Cancelled;
+        job.info.finished_at = Some(now);
+        job.cancel.cancel();
+    }
+    job.dir = None;
+    job.output = None;
+    job.options = ConvertOptions::default();
+    job.email_to.clear();
+    job.info.data_deleted_at = Some(now);
+}
+
+fn email_not_configured() -> AppError {
+    AppError::InvalidRequest(
+        "emailTo: email delivery is not configured (PDFMILL_SMTP_URL, PDFMILL_SMTP_FROM)"
+            .to_string(),
+    )
+}
+
+fn not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Job {} not found", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_queued_job() {
+        // No conversion slots, so the job stays queued
+        let manager = Arc::new(JobManager::new(0, Duration::from_secs(60)));
+        let router = Arc::new(SmartRouter::new().await);
+        let job = manager
+            .submit(
+                Arc::clone(&router),
+                "ip:127.0.0.1".to_string(),
+                "page.html".to_string(),
+                b"<p>hi</p>".to_vec().into(),
+                ConvertOptions::default(),
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(manager.get(job.id).unwrap().status, JobStatus::Queued);
+        assert!(matches!(manager.output(job.id), Err(AppError::Conflict(_))));
+
+        let cancelled = manager.cancel(job.id).unwrap();
+        assert_eq!(cancelled.status, JobStatus::Cancelled);
+        assert!(cancelled.finished_at.is_some());
+        assert!(matches!(manager.cancel(job.id), Err(AppError::Conflict(_))));
+        assert!(matches!(
+            manager.get(Uuid::new_v4()),
+            Err(AppError::NotFound(_))
+        ));
+
+        let cancelled_only = JobFilter {
+            status: Some(JobStatus::Cancelled),
+            since: Some(job.created_at),
+        };
+        let (total, page) = manager.list(&cancelled_only, 0, DEFAULT_PAGE_SIZE);
+        assert_eq!(total, 1);
+        assert_eq!(page[0].id, job.id);
+        assert!(manager
+            .list(&cancelled_only, 1, DEFAULT_PAGE_SIZE)
+            .1
+            .is_empty());
+
+        let failed_only = JobFilter {
+            status: Some(JobStatus::Failed),
+            since: None,
+        };
+        assert_eq!(manager.list(&failed_only, 0, DEFAULT_PAGE_SIZE).0, 0);
+        assert!(matches!(
+            manager.retry(router, job.id, None, None),
+            Err(AppError::Conflict(_))
+        ));
+    }
+
+    async fn wait_until_finished(manager: &JobManager, id: Uuid) -> JobInfo {
+        for _ in 0..100 {
+            let job = manager.get(id).unwrap();
+            if job.status.is_finished() {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("job {} did not finish", id);
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_dead_letter() {
+        let manager = Arc::new(JobManager {
+            max_attempts: 2,
+            ..JobManager::new(1, Duration::from_secs(60))
+        });
+        let router = Arc::new(SmartRouter::new().await);
+        // No engine handles the extension, so every attempt fails
+        let job = manager
+            .submit(
+                Arc::clone(&router),
+                "ip:127.0.0.1".to_string(),
+                "data.xyz".to_string(),
+                vec![0].into(),
+                ConvertOptions::default(),
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        let failed = wait_until_finished(&manager, job.id).await;
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert!(failed.error.is_some());
+
+        let retried = manager
+            .retry(Arc::clone(&router), job.id, None, None)
+            .unwrap();
+        assert_eq!(retried.attempts, 2);
+        assert!(retried.error.is_none());
+
+        let dead = wait_until_finished(&manager, job.id).await;
+        assert_eq!(dead.status, JobStatus::DeadLetter);
+        assert_eq!(dead.attempts, 2);
+        assert_eq!(dead.failures.len(), 2);
+        assert_eq!(dead.failures[1].attempt, 2);
+        assert!(matches!(
+            manager.retry(router, job.id, None, None),
+            Err(AppError::Conflict(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_keep_result() {
+        let manager = JobManager::new(1, Duration::from_secs(60));
+        let result = ConvertResult {
+            data: b"%PDF-1.7".to_vec(),
+            filename: "page.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            warnings: vec!["font substituted".to_string()],
+        };
+        let job = manager
+            .keep(
+                "ip:127.0.0.1".to_string(),
+                "page.html".to_string(),
+                crate::disk::tempdir().unwrap(),
+                "ab".to_string(),
+                ConvertOptions::default(),
+                "chromium/1".to_string(),
+                result,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(job.warnings, ["font substituted"]);
+        let output = manager.output(job.id).unwrap();
+        assert_eq!(std::fs::read(&output.path).unwrap(), b"%PDF-1.7");
+
+        let deleted = manager.delete_data(job.id).unwrap();
+        assert!(deleted.data_deleted_at.is_some());
+        assert_eq!(deleted.status, JobStatus::Succeeded);
+        assert!(!output.path.exists());
+        assert!(matches!(manager.output(job.id), Err(AppError::Conflict(_))));
+        assert_eq!(
+            manager.delete_data(job.id).unwrap().data_deleted_at,
+            deleted.data_deleted_at
+        );
+    }
+
+    #[tokio::test]
+    async fn test_purge() {
+        // No conversion slots, so the jobs stay queued
+        let manager = Arc::new(JobManager::new(0, Duration::from_secs(60)));
+        let router = Arc::new(SmartRouter::new().await);
+        let mut ids = Vec::new();
+        for client in ["ip:192.0.2.7/key:a", "ip:192.0.2.7/key:b"] {
+            let job = manager
+                .submit(
+                    Arc::clone(&router),
+                    client.to_string(),
+                    "page.html".to_string(),
+                    b"<p>hi</p>".to_vec().into(),
+                    ConvertOptions::default(),
+                    Vec::new(),
+                )
+                .await
+                .unwrap();
+            ids.push(job.id);
+        }
+
+        let before_all = PurgeFilter {
+            before: Some(manager.get(ids[0]).unwrap().created_at),
+            client: None,
+        };
+        assert!(manager.purge(&before_all).is_empty());
+        let tenant = PurgeFilter {
+            before: None,
+            client: Some("key:b".to_string()),
+        };
+        assert_eq!(manager.purge(&tenant), [ids[1]]);
+        assert!(matches!(manager.get(ids[1]), Err(AppError::NotFound(_))));
+        assert_eq!(manager.get(ids[0]).unwrap().status, JobStatus::Queued);
+    }
+}
@@ -1,22 +1,99 @@
+#![recursion_limit = "256"]
+
+mod access;
+mod archive;
+mod assets;
+mod bench;
+mod canary;
+mod cors;
+mod deadline;
+mod disk;
+mod egress;
+mod encryption;
 mod engines;
 mod error;
 mod handlers;
+mod hooks;
+mod jobs;
+mod mailer;
+mod manifest;
+mod metrics;
+mod negotiate;
+mod pdf;
+mod pipeline;
+mod presets;
+mod profile;
+mod readiness;
+mod related;
 mod router;
+mod schedules;
+mod selftest;
+mod sniff;
+mod systemd;
+mod templates;
+mod winservice;
 
+use access::AccessPolicy;
+use assets::AssetStore;
 use axum::{
     extract::DefaultBodyLimit,
-    routing::{get, post},
+    middleware,
+    routing::{delete, get, post},
     Router,
 };
-use handlers::{convert_handler, health_handler, info_handler, AppState};
-use router::SmartRouter;
+use canary::Canary;
+use cors::CorsConfig;
+use egress::UrlPolicy;
+use engines::{Basemap, ChromiumEngine, CommandEngine};
+use handlers::{
+    bench_handler, browser_swap_handler, cancel_job_handler, capabilities_handler, convert_handler,
+    convert_html_handler, convert_url_handler, create_schedule_handler, delete_asset_handler,
+    delete_job_data_handler, delete_schedule_handler, delete_template_handler,
+    demo_fixture_handler, demo_handler, fields_handler, get_asset_handler, get_schedule_handler,
+    get_template_asset_handler, get_template_handler, health_handler, info_handler,
+    job_manifest_handler, job_result_handler, job_status_handler, list_assets_handler,
+    list_jobs_handler, list_schedules_handler, list_templates_handler, mail_merge_handler,
+    manifest_key_handler, merge_handler, metrics_handler, options_schema_handler,
+    pdf_annotate_handler, pdf_convert_handler, pdf_fields_handler, presets_handler,
+    purge_jobs_handler, put_asset_handler, put_template_handler, render_handler, retry_job_handler,
+    rollback_template_handler, screenshot_handler, selftest_handler, submit_job_handler,
+    template_versions_handler, update_schedule_handler, AppState, API_PREFIX,
+};
+use hooks::Hooks;
+use jobs::JobManager;
+use mailer::Mailer;
+use manifest::ManifestSigner;
+use presets::Presets;
+use profile::Profile;
+use readiness::NoEnginePolicy;
+use router::{Routes, SmartRouter};
+use schedules::ScheduleManager;
+use sniff::ContentCheck;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tower_http::cors::{Any, CorsLayer};
+use templates::TemplateStore;
 use tower_http::trace::TraceLayer;
 use tracing::info;
 
-#[tokio::main]
-async fn main() {
+/// Where the operator endpoints listen without `PDFMILL_ADMIN_ADDR`
+const DEFAULT_ADMIN_ADDR: &str = "127.0.0.1:9090";
+
+fn main() {
+    // Under the Windows service control manager the service runs the server
+    if winservice::dispatch() {
+        return;
+    }
+    tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(serve(systemd::notify_ready, std::future::pending()));
+}
+
+/// Run the server until `shutdown` completes, calling `notify_ready` once
+/// it accepts requests
+async fn serve(
+    notify_ready: impl FnOnce(),
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) {
     // Initialize logging
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -25,37 +102,376 @@ async fn main() {
         )
         .init();
 
+    // Deployment profile, read by engines and job limits below
+    match Profile::from_env() {
+        Ok(profile) => profile.activate(),
+        Err(e) => {
+            tracing::error!("Invalid profile: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Client IP allow/deny lists
+    let access = match AccessPolicy::from_env() {
+        Ok(policy) => Arc::new(policy),
+        Err(e) => {
+            tracing::error!("Invalid access control configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Hosts remote pages may be loaded from
+    match UrlPolicy::from_env() {
+        Ok(policy) => policy.activate(),
+        Err(e) => {
+            tracing::error!("Invalid URL allowlist: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Key encrypting uploads and job files on disk
+    match encryption::Key::from_env() {
+        Ok(Some(key)) => key.activate(),
+        Ok(None) => {}
+        Err(e) => {
+            tracing::error!("Invalid encryption key: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Allowed cross-origin callers
+    let cors = match CorsConfig::from_env() {
+        Ok(config) => config.layer(),
+        Err(e) => {
+            tracing::error!("Invalid CORS configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Uploads whose content does not match their extension
+    let content_check = match ContentCheck::from_env() {
+        Ok(check) => check,
+        Err(e) => {
+            tracing::error!("Invalid content check configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Signing key for provenance manifests
+    let manifests = match ManifestSigner::from_env() {
+        Ok(signer) => signer,
+        Err(e) => {
+            tracing::error!("Invalid manifest configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Work directory for request workspaces and browser/office profiles
+    if let Err(e) = disk::prepare() {
+        tracing::error!("Cannot prepare work directory: {}", e);
+        std::process::exit(1);
+    }
+    disk::spawn_monitor();
+
+    // Second HTML renderer for drift detection
+    let canary = match Canary::from_env() {
+        Ok(canary) => canary.map(Arc::new),
+        Err(e) => {
+            tracing::error!("Invalid canary configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Named option presets
+    let presets = match Presets::from_env() {
+        Ok(presets) => presets,
+        Err(e) => {
+            tracing::error!("Invalid presets configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Shared assets, resolved by Chromium while rendering
+    let assets = Arc::new(AssetStore::from_env());
+    assets.share();
+
+    // Engines preferred for particular extensions
+    let routes = match Routes::from_env() {
+        Ok(routes) => routes,
+        Err(e) => {
+            tracing::error!("Invalid engine routes configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Operator-defined command line engines
+    let command_engines = match CommandEngine::from_env() {
+        Ok(engines) => engines,
+        Err(e) => {
+            tracing::error!("Invalid engines configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Processing of uploads and PDF output
+    let hooks = match Hooks::from_env() {
+        Ok(hooks) => hooks,
+        Err(e) => {
+            tracing::error!("Invalid hooks configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Scheduled replacement of the persistent browser
+    let browser_swap_interval = match ChromiumEngine::swap_interval_from_env() {
+        Ok(interval) => interval,
+        Err(e) => {
+            tracing::error!("Invalid browser swap configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Tile server under GeoJSON/KML maps
+    match Basemap::from_env() {
+        Ok(Some(basemap)) => basemap.activate(),
+        Ok(None) => {}
+        Err(e) => {
+            tracing::error!("Invalid map configuration: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Startup without any usable engine
+    let no_engine_policy = match NoEnginePolicy::from_env() {
+        Ok(policy) => policy,
+        Err(e) => {
+            tracing::error!("Invalid startup configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     // Create the smart router (detects available engines)
-    let smart_router = SmartRouter::new().await;
+    let mut smart_router = SmartRouter::new().await;
+    for engine in command_engines {
+        if let Err(e) = smart_router.register(Arc::new(engine)).await {
+            tracing::error!("Invalid engines configuration: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Err(e) = smart_router.set_routes(routes) {
+        tracing::error!("Invalid engine routes configuration: {}", e);
+        std::process::exit(1);
+    }
+    smart_router.set_hooks(hooks);
+    let smart_router = Arc::new(smart_router);
+
+    if let Some(interval) = browser_swap_interval {
+        match smart_router.chromium() {
+            Ok(chromium) => {
+                info!(
+                    "Swapping the Chromium browser every {}s",
+                    interval.as_secs()
+                );
+                chromium.spawn_recycler(interval);
+            }
+            Err(e) => tracing::warn!("Browser swaps disabled: {}", e),
+        }
+    }
+
+    let ready = smart_router
+        .engine_availability()
+        .iter()
+        .any(|(_, available)| *available);
+    if !ready {
+        match no_engine_policy {
+            NoEnginePolicy::Serve => {
+                tracing::warn!("No conversion engine is available; conversions will fail")
+            }
+            NoEnginePolicy::Exit => {
+                tracing::error!("No conversion engine is available, exiting");
+                std::process::exit(1);
+            }
+            NoEnginePolicy::Unready => {
+                tracing::error!("No conversion engine is available; /health reports 503")
+            }
+        }
+    }
+
+    // Email delivery of results, when SMTP is configured
+    let mailer = Mailer::from_env().map(Arc::new);
+
+    // Background conversion jobs
+    let jobs = Arc::new(JobManager::from_env(mailer.clone()));
+    jobs.spawn_sweeper();
+
+    // Recurring conversions
+    let schedules = Arc::new(ScheduleManager::from_env(mailer));
+    schedules.spawn_runner(Arc::clone(&smart_router));
 
     let state = Arc::new(AppState {
         router: smart_router,
+        jobs,
+        schedules,
+        content_check,
+        manifests,
+        canary,
+        presets,
+        templates: TemplateStore::from_env(),
+        assets,
+        access: Arc::clone(&access),
+        ready: ready || no_engine_policy == NoEnginePolicy::Serve,
     });
 
-    // CORS configuration
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
-
-    // Build the application router
-    let app = Router::new()
+    // Conversion API, served under /v1 and, as aliases, without a prefix
+    let v1 = Router::new()
         .route("/convert", post(convert_handler))
+        .route("/convert/html", post(convert_html_handler))
+        .route("/convert/url", post(convert_url_handler))
+        .route("/screenshot", post(screenshot_handler))
+        .route("/pdf/convert", post(pdf_convert_handler))
+        .route("/pdf/fields", post(pdf_fields_handler))
+        .route("/pdf/annotate", post(pdf_annotate_handler))
+        .route("/mailmerge", post(mail_merge_handler))
+        .route("/merge", post(merge_handler))
+        .route("/fields", post(fields_handler))
+        .route("/jobs", post(submit_job_handler))
+        .route(
+            "/jobs/:id",
+            get(job_status_handler).delete(cancel_job_handler),
+        )
+        .route("/jobs/:id/result", get(job_result_handler))
+        .route("/jobs/:id/data", delete(delete_job_data_handler))
+        .route("/jobs/:id/retry", post(retry_job_handler))
+        .route("/jobs/:id/manifest", get(job_manifest_handler))
+        .route("/manifest/key", get(manifest_key_handler))
+        .route("/options/schema", get(options_schema_handler))
+        .route("/capabilities", get(capabilities_handler))
+        .route("/presets", get(presets_handler))
+        .route("/render", post(render_handler))
+        .route("/info", get(info_handler));
+    let api = Router::new()
+        .nest(API_PREFIX, v1.clone())
+        .merge(v1)
         .route("/health", get(health_handler))
-        .route("/info", get(info_handler))
         .route("/", get(info_handler))
+        .route("/demo", get(demo_handler))
+        .route("/demo/fixtures/:name", get(demo_fixture_handler))
+        .layer(middleware::from_fn(deadline::enforce))
         .layer(DefaultBodyLimit::max(200 * 1024 * 1024)) // 200MB
-        .layer(TraceLayer::new_for_http())
-        .layer(cors)
-        .with_state(state);
+        .layer(cors);
 
-    // Start the server
-    let addr = std::env::var("PDFMILL_ADDR").unwrap_or_else(|_| "0.0.0.0:3000".to_string());
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+    // Operator endpoints: metrics, job listing, schedules, templates, shared
+    // assets and the self-test
+    let admin = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/admin/selftest", post(selftest_handler))
+        .route("/admin/bench", post(bench_handler))
+        .route("/admin/browser/swap", post(browser_swap_handler))
+        .route("/jobs", get(list_jobs_handler))
+        .route("/admin/jobs/purge", post(purge_jobs_handler))
+        .route(
+            "/schedules",
+            get(list_schedules_handler).post(create_schedule_handler),
+        )
+        .route(
+            "/schedules/:id",
+            get(get_schedule_handler)
+                .put(update_schedule_handler)
+                .delete(delete_schedule_handler),
+        )
+        .route("/templates", get(list_templates_handler))
+        .route(
+            "/templates/:name",
+            get(get_template_handler)
+                .put(put_template_handler)
+                .delete(delete_template_handler),
+        )
+        .route("/templates/:name/versions", get(template_versions_handler))
+        .route("/templates/:name/rollback", post(rollback_template_handler))
+        .route(
+            "/templates/:name/assets/:asset",
+            get(get_template_asset_handler),
+        )
+        .route("/assets", get(list_assets_handler))
+        .route(
+            "/assets/:name",
+            get(get_asset_handler)
+                .put(put_asset_handler)
+                .delete(delete_asset_handler),
+        );
+
+    // Start the server, on the socket systemd passed if socket-activated
+    let listener = match systemd::activated_listener() {
+        Some(listener) => {
+            listener.set_nonblocking(true).unwrap();
+            tokio::net::TcpListener::from_std(listener).unwrap()
+        }
+        None => {
+            let addr = std::env::var("PDFMILL_ADDR").unwrap_or_else(|_| "0.0.0.0:3000".to_string());
+            tokio::net::TcpListener::bind(&addr).await.unwrap()
+        }
+    };
+    let addr = listener.local_addr().unwrap();
+
+    // The operator endpoints have no authentication of their own, so they
+    // are served on an address of their own, loopback unless configured;
+    // the public listener only carries them when explicitly asked to
+    let admin_public =
+        std::env::var("PDFMILL_ADMIN_PUBLIC").is_ok_and(|v| matches!(v.trim(), "true" | "1"));
+    let admin_addr = (!admin_public).then(|| {
+        std::env::var("PDFMILL_ADMIN_ADDR")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_ADMIN_ADDR.to_string())
+    });
+    let app = match admin_addr {
+        Some(admin_addr) => {
+            let admin_listener = tokio::net::TcpListener::bind(&admin_addr).await.unwrap();
+            let admin_app = admin
+                .route("/health", get(health_handler))
+                .layer(middleware::from_fn_with_state(
+                    Arc::clone(&access),
+                    access::enforce,
+                ))
+                .layer(TraceLayer::new_for_http())
+                .with_state(Arc::clone(&state));
+            info!(
+                "🔧 Admin endpoints on http://{}",
+                admin_listener.local_addr().unwrap()
+            );
+            tokio::spawn(async move {
+                axum::serve(
+                    admin_listener,
+                    admin_app.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .await
+                .unwrap()
+            });
+            api
+        }
+        None => {
+            tracing::warn!(
+                "⚠️  PDFMILL_ADMIN_PUBLIC: operator endpoints (/metrics, GET /jobs, /schedules, \
+                 /templates, /assets, /admin/...) are served on {} without authentication",
+                addr
+            );
+            api.merge(admin)
+        }
+    }
+    .layer(middleware::from_fn_with_state(access, access::enforce))
+    .layer(TraceLayer::new_for_http())
+    .with_state(state);
 
     info!("🚀 PDFMill server running on http://{}", addr);
     info!("📖 API documentation: http://{}/info", addr);
     info!("🔄 Convert files: POST http://{}/convert", addr);
 
-    axum::serve(listener, app).await.unwrap();
+    notify_ready();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown)
+    .await
+    .unwrap();
 }
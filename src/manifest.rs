@@ -0,0 +1,233 @@
+//! Signed provenance manifests: a JSON record of how an output was produced
+//! (input and output hashes, options, engine versions, time), signed with
+//! the server key so downstream systems can verify it.
+
+use crate::engines::{ConvertOptions, ConvertResult};
+use crate::error::{AppError, Result};
+use crate::pdf::{self, EmbeddedFile};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::sign::Signer;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tracing::info;
+
+/// Header carrying the base64 manifest next to a converted file
+pub const MANIFEST_HEADER: &str = "x-pdfmill-manifest";
+
+/// Header carrying the base64 signature over the manifest bytes
+pub const SIGNATURE_HEADER: &str = "x-pdfmill-manifest-signature";
+
+/// Name of the file `embedProvenance` attaches to the PDF
+pub const PROVENANCE_FILENAME: &str = "pdfmill-provenance.json";
+
+/// PDF/A levels that forbid embedded files other than PDF/A documents
+const NO_ATTACHMENTS: &[&str] = &["PDF/A-1b", "PDF/A-2b"];
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest<'a> {
+    pub input: FileDigest<'a>,
+    pub output: FileDigest<'a>,
+    pub options: &'a ConvertOptions,
+    /// Engine that produced the output, with its version
+    pub engine: &'a str,
+    /// Versions of every engine installed on the server
+    pub engines: BTreeMap<&'static str, String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Conversion record embedded in the output PDF with `embedProvenance`.
+/// Unlike a manifest it cannot hold the hash of the file containing it,
+/// and it is not signed.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProvenanceRecord<'a> {
+    pub input: FileDigest<'a>,
+    pub options: &'a ConvertOptions,
+    /// Engine that produced the output, with its version
+    pub engine: &'a str,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileDigest<'a> {
+    pub filename: &'a str,
+    pub sha256: &'a str,
+}
+
+/// The exact manifest bytes that were signed, and the signature
+pub struct SignedManifest {
+    pub body: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedManifest {
+    pub fn body_base64(&self) -> String {
+        BASE64.encode(&self.body)
+    }
+
+    pub fn signature_base64(&self) -> String {
+        BASE64.encode(&self.signature)
+    }
+}
+
+/// Private key manifests are signed with
+pub struct ManifestSigner {
+    key: PKey<Private>,
+    /// First 16 hex digits of the SHA-256 of the public key (DER)
+    key_id: String,
+}
+
+impl ManifestSigner {
+    /// Load the PEM private key (Ed25519, EC or RSA) named by
+    /// `PDFMILL_MANIFEST_KEY`; manifests are off when it is unset
+    pub fn from_env() -> std::result::Result<Option<Self>, String> {
+        let Some(path) = std::env::var_os("PDFMILL_MANIFEST_KEY") else {
+            return Ok(None);
+        };
+        let pem = std::fs::read(&path)
+            .map_err(|e| format!("PDFMILL_MANIFEST_KEY: cannot read {:?}: {}", path, e))?;
+        let signer = Self::from_pem(&pem).map_err(|e| format!("PDFMILL_MANIFEST_KEY: {}", e))?;
+        info!(
+            "Signing output manifests with {} key {}",
+            signer.algorithm(),
+            signer.key_id
+        );
+        Ok(Some(signer))
+    }
+
+    fn from_pem(pem: &[u8]) -> std::result::Result<Self, String> {
+        let key = PKey::private_key_from_pem(pem).map_err(|e| format!("invalid key: {}", e))?;
+        if !matches!(key.id(), Id::ED25519 | Id::EC | Id::RSA) {
+            return Err("unsupported key type (expected Ed25519, EC or RSA)".to_string());
+        }
+        let public = key.public_key_to_der().map_err(|e| e.to_string())?;
+        let key_id = crate::engines::sha256_hex(&public)[..16].to_string();
+        Ok(Self { key, key_id })
+    }
+
+    /// Signature scheme, as named in the manifest
+    pub fn algorithm(&self) -> &'static str {
+        match self.key.id() {
+            Id::ED25519 => "ed25519",
+            Id::EC => "ecdsa-sha256",
+            _ => "rsa-sha256",
+        }
+    }
+
+    /// Public key for verifiers, PEM encoded
+    pub fn public_key_pem(&self) -> Result<String> {
+        let pem = self.key.public_key_to_pem().map_err(signing_error)?;
+        String::from_utf8(pem).map_err(|e| AppError::Internal(e.to_string()))
+    }
+
+    /// Serialize the manifest, with the signer named inside, and sign it
+    pub fn sign(&self, manifest: &Manifest) -> Result<SignedManifest> {
+        let body = serde_json::to_vec(&serde_json::json!({
+            "version": 1,
+            "manifest": manifest,
+            "signer": { "algorithm": self.algorithm(), "keyId": self.key_id },
+        }))
+        .map_err(|e| AppError::Internal(format!("Failed to serialize manifest: {}", e)))?;
+
+        let mut signer = match self.key.id() {
+            Id::ED25519 => Signer::new_without_digest(&self.key),
+            _ => Signer::new(MessageDigest::sha256(), &self.key),
+        }
+        .map_err(signing_error)?;
+        let signature = signer.sign_oneshot_to_vec(&body).map_err(signing_error)?;
+        Ok(SignedManifest { body, signature })
+    }
+}
+
+/// Attach the conversion record to PDF output as [`PROVENANCE_FILENAME`];
+/// other outputs are returned as they are
+pub async fn embed_provenance(
+    mut result: ConvertResult,
+    record: &ProvenanceRecord<'_>,
+) -> Result<ConvertResult> {
+    if result.content_type != "application/pdf" {
+        return Ok(result);
+    }
+    if let Some(format) = record
+        .options
+        .pdf_format
+        .as_deref()
+        .filter(|format| NO_ATTACHMENTS.contains(format))
+    {
+        return Err(AppError::InvalidRequest(format!(
+            "embedProvenance: {} does not allow embedded files, use PDF/A-3b",
+            format
+        )));
+    }
+    let body = serde_json::to_vec_pretty(&serde_json::json!({
+        "version": 1,
+        "provenance": record,
+        "generator": format!("pdfmill/{}", env!("CARGO_PKG_VERSION")),
+    }))
+    .map_err(|e| AppError::Internal(format!("Failed to serialize provenance: {}", e)))?;
+
+    let data = std::mem::take(&mut result.data);
+    result.data = tokio::task::spawn_blocking(move || {
+        let file = EmbeddedFile {
+            name: PROVENANCE_FILENAME,
+            content_type: "application/json",
+            description: "Conversion provenance",
+            data: &body,
+        };
+        pdf::attach_file(&data, &file)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Provenance embedding panicked: {}", e)))??;
+    Ok(result)
+}
+
+fn signing_error(e: openssl::error::ErrorStack) -> AppError {
+    AppError::Internal(format!("Failed to sign manifest: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::sign::Verifier;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = PKey::generate_ed25519().unwrap();
+        let signer = ManifestSigner::from_pem(&key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        let options = ConvertOptions {
+            http_password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        let manifest = Manifest {
+            input: FileDigest {
+                filename: "in.html",
+                sha256: "ab",
+            },
+            output: FileDigest {
+                filename: "in.pdf",
+                sha256: "cd",
+            },
+            options: &options,
+            engine: "chromium/124.0",
+            engines: BTreeMap::new(),
+            created_at: Utc::now(),
+        };
+        let signed = signer.sign(&manifest).unwrap();
+
+        let public =
+            PKey::public_key_from_pem(signer.public_key_pem().unwrap().as_bytes()).unwrap();
+        let mut verifier = Verifier::new_without_digest(&public).unwrap();
+        assert!(verifier
+            .verify_oneshot(&signed.signature, &signed.body)
+            .unwrap());
+
+        let text = String::from_utf8(signed.body).unwrap();
+        assert!(text.contains("\"algorithm\":\"ed25519\""));
+        assert!(!text.contains("secret"));
+    }
+}
@@ -0,0 +1,146 @@
+//! Process-wide counters and summaries, and the Prometheus text exposition
+//! of `/metrics`
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{LazyLock, Mutex};
+
+/// Counter values keyed by metric name, then by rendered label set
+static COUNTERS: LazyLock<Mutex<BTreeMap<&'static str, BTreeMap<String, u64>>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+/// Sum and count of observed values, per rendered label set
+type Summary = BTreeMap<String, (f64, u64)>;
+
+/// Summaries keyed by metric name
+static SUMMARIES: LazyLock<Mutex<BTreeMap<&'static str, Summary>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+/// Add one to the counter `name` with the given labels
+pub fn increment(name: &'static str, labels: &[(&str, &str)]) {
+    let mut counters = COUNTERS.lock().unwrap();
+    *counters
+        .entry(name)
+        .or_default()
+        .entry(render_labels(labels))
+        .or_default() += 1;
+}
+
+/// Record a value of the summary `name`, exposed as `name_sum` and
+/// `name_count`
+pub fn observe(name: &'static str, labels: &[(&str, &str)], value: f64) {
+    let mut summaries = SUMMARIES.lock().unwrap();
+    let (sum, count) = summaries
+        .entry(name)
+        .or_default()
+        .entry(render_labels(labels))
+        .or_default();
+    *sum += value;
+    *count += 1;
+}
+
+/// Count a finished conversion by engine and outcome
+pub fn record_conversion(engine: &str, succeeded: bool) {
+    let outcome = if succeeded { "success" } else { "failure" };
+    increment(
+        "pdfmill_conversions_total",
+        &[("engine", engine), ("outcome", outcome)],
+    );
+}
+
+/// Prometheus text format builder
+#[derive(Default)]
+pub struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    /// Append a metric family with its samples
+    pub fn family<'a>(
+        &mut self,
+        name: &str,
+        kind: &str,
+        help: &str,
+        samples: impl IntoIterator<Item = (Vec<(&'a str, &'a str)>, f64)>,
+    ) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(self.text, "{}{} {}", name, render_labels(&labels), value);
+        }
+    }
+
+    /// Append every counter recorded with [`increment`] and summary
+    /// recorded with [`observe`]
+    pub fn counters(&mut self) {
+        let counters = COUNTERS.lock().unwrap();
+        for (name, series) in counters.iter() {
+            let _ = writeln!(self.text, "# TYPE {} counter", name);
+            for (labels, value) in series {
+                let _ = writeln!(self.text, "{}{} {}", name, labels, value);
+            }
+        }
+        let summaries = SUMMARIES.lock().unwrap();
+        for (name, series) in summaries.iter() {
+            let _ = writeln!(self.text, "# TYPE {} summary", name);
+            for (labels, (sum, count)) in series {
+                let _ = writeln!(self.text, "{}_sum{} {}", name, labels, sum);
+                let _ = writeln!(self.text, "{}_count{} {}", name, labels, count);
+            }
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.text
+    }
+}
+
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", key, value)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exposition() {
+        increment(
+            "pdfmill_test_total",
+            &[("engine", "chromium"), ("outcome", "ok")],
+        );
+        increment(
+            "pdfmill_test_total",
+            &[("engine", "chromium"), ("outcome", "ok")],
+        );
+        observe("pdfmill_test_ratio", &[], 0.25);
+        observe("pdfmill_test_ratio", &[], 0.5);
+
+        let mut exposition = Exposition::default();
+        exposition.family(
+            "pdfmill_up",
+            "gauge",
+            "Always 1",
+            [(vec![("note", "a\"b")], 1.0)],
+        );
+        exposition.counters();
+        let text = exposition.finish();
+
+        assert!(text.contains("pdfmill_up{note=\"a\\\"b\"} 1\n"));
+        assert!(text.contains("pdfmill_test_total{engine=\"chromium\",outcome=\"ok\"} 2\n"));
+        assert!(text.contains("pdfmill_test_ratio_sum 0.75\npdfmill_test_ratio_count 2\n"));
+    }
+}
@@ -0,0 +1,220 @@
+//! Content negotiation on `/convert`: the `Accept` header picks what the
+//! response carries, so one endpoint serves clients that want the document,
+//! a preview image or only a reference to the stored result.
+//!
+//! - the converted file (`application/pdf` unless `to` says otherwise), the
+//!   default and the answer to `*/*`
+//! - `image/png`: the first page of PDF output, rendered by poppler's
+//!   `pdftoppm`
+//! - `application/json`: the conversion metadata, with the file kept as a
+//!   finished job to download later
+//! - `multipart/related`: the metadata followed by the file, see
+//!   [`crate::related`]
+
+use crate::engines::tool_failed;
+use crate::error::{AppError, Result};
+use crate::jobs::JobInfo;
+use crate::related::{Metadata, MULTIPART_RELATED};
+use axum::http::{header, HeaderMap};
+use serde::Serialize;
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// Resolution of the first-page preview
+const PREVIEW_DPI: &str = "150";
+
+/// What the response to a conversion carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    /// The converted file
+    File,
+    /// The first page as a PNG image
+    Png,
+    /// The metadata, with a reference to the stored file
+    Json,
+    /// The metadata followed by the file
+    Related,
+}
+
+impl Representation {
+    fn media_type(self, file_type: &str) -> &str {
+        match self {
+            Self::File => file_type,
+            Self::Png => "image/png",
+            Self::Json => "application/json",
+            Self::Related => MULTIPART_RELATED,
+        }
+    }
+}
+
+/// Pick the representation the request's Accept header prefers for output
+/// of `file_type`; no header means the file. Ties go to the file, then the
+/// order of [`Representation`]. A PNG is only offered for PDF output.
+pub fn negotiate(headers: &HeaderMap, file_type: &str) -> Result<Representation> {
+    let ranges: Vec<(String, f32)> = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(parse_range)
+        .collect();
+    if ranges.is_empty() {
+        return Ok(Representation::File);
+    }
+
+    let mut offered = vec![Representation::File];
+    if file_type == "application/pdf" {
+        offered.push(Representation::Png);
+    }
+    offered.extend([Representation::Json, Representation::Related]);
+
+    let mut best = None;
+    for representation in offered {
+        let quality = quality(&ranges, representation.media_type(file_type));
+        if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+            best = Some((representation, quality));
+        }
+    }
+    best.map(|(representation, _)| representation)
+        .ok_or_else(|| {
+            let accept: Vec<&str> = ranges.iter().map(|(range, _)| range.as_str()).collect();
+            AppError::NotAcceptable(format!(
+                "cannot produce {}; available: {}, image/png (PDF output), application/json, {}",
+                accept.join(", "),
+                file_type,
+                MULTIPART_RELATED
+            ))
+        })
+}
+
+/// Media range (lowercase) and quality of one Accept element
+fn parse_range(element: &str) -> Option<(String, f32)> {
+    let mut params = element.split(';');
+    let range = params.next()?.trim().to_ascii_lowercase();
+    if !range.contains('/') {
+        return None;
+    }
+    let quality = params
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+        .map_or(1.0, |(_, value)| value.trim().parse::<f32>().unwrap_or(0.0));
+    Some((range, quality.clamp(0.0, 1.0)))
+}
+
+/// Quality the most specific matching range gives `media_type`
+fn quality(ranges: &[(String, f32)], media_type: &str) -> f32 {
+    let top = media_type.split('/').next().unwrap_or_default();
+    let type_wildcard = format!("{}/*", top);
+    let specificity = |range: &str| {
+        if range.eq_ignore_ascii_case(media_type) {
+            Some(2)
+        } else if range == type_wildcard {
+            Some(1)
+        } else if range == "*/*" {
+            Some(0)
+        } else {
+            None
+        }
+    };
+    ranges
+        .iter()
+        .filter_map(|(range, quality)| specificity(range).map(|s| (s, *quality)))
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map_or(0.0, |(_, quality)| quality)
+}
+
+/// The JSON answer: the conversion metadata and the job holding the file,
+/// which `document` points at
+#[derive(Debug, Serialize)]
+pub struct StoredResult {
+    #[serde(flatten)]
+    pub metadata: Metadata,
+    pub job: JobInfo,
+}
+
+/// Render the first page of a PDF as a PNG
+pub async fn first_page_png(pdf: &[u8]) -> Result<Vec<u8>> {
+    let temp_dir = crate::disk::tempdir()?;
+    let input = temp_dir.path().join("document.pdf");
+    let prefix = temp_dir.path().join("page");
+    tokio::fs::write(&input, pdf).await?;
+
+    let output = Command::new("pdftoppm")
+        .args([
+            "-png",
+            "-f",
+            "1",
+            "-l",
+            "1",
+            "-r",
+            PREVIEW_DPI,
+            "-singlefile",
+        ])
+        .arg(&input)
+        .arg(&prefix)
+        .kill_on_drop(true)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .map_err(|e| {
+            AppError::EngineNotAvailable(format!("pdftoppm not found for image/png: {}", e))
+        })?;
+    if !output.status.success() {
+        return Err(tool_failed("pdftoppm", &output));
+    }
+    Ok(tokio::fs::read(prefix.with_extension("png")).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_negotiate() {
+        let pick = |accept: &str, file_type: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+            negotiate(&headers, file_type)
+        };
+        let pdf = "application/pdf";
+        assert_eq!(
+            negotiate(&HeaderMap::new(), pdf).unwrap(),
+            Representation::File
+        );
+        assert_eq!(pick("*/*", pdf).unwrap(), Representation::File);
+        assert_eq!(
+            pick("text/html, */*;q=0.8", pdf).unwrap(),
+            Representation::File
+        );
+        assert_eq!(
+            pick("application/pdf;q=0.5, Multipart/Related", pdf).unwrap(),
+            Representation::Related
+        );
+        assert_eq!(pick("image/png", pdf).unwrap(), Representation::Png);
+        assert_eq!(
+            pick("image/*, application/json;q=0.9", pdf).unwrap(),
+            Representation::Png
+        );
+        assert_eq!(pick("application/json", pdf).unwrap(), Representation::Json);
+        // The more specific range wins over the wildcard excluding it
+        assert_eq!(
+            pick("*/*;q=0, application/json", pdf).unwrap(),
+            Representation::Json
+        );
+        assert_eq!(pick("application/*", pdf).unwrap(), Representation::File);
+
+        // Only PDF output has a preview
+        let docx = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+        assert!(matches!(
+            pick("image/png", docx),
+            Err(AppError::NotAcceptable(_))
+        ));
+        assert!(matches!(
+            pick("text/html", pdf),
+            Err(AppError::NotAcceptable(_))
+        ));
+    }
+}
@@ -0,0 +1,351 @@
+//! Annotations added to an uploaded PDF: highlights, sticky notes and links,
+//! e.g. review comments pushed back into the document
+
+use super::{load_input, save};
+use crate::error::{AppError, Result};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream, StringFormat};
+use serde::Deserialize;
+
+/// Most annotations accepted in one request
+pub const MAX_ANNOTATIONS: usize = 10_000;
+
+/// Highlight color when none is given: yellow
+const HIGHLIGHT_COLOR: [f32; 3] = [1.0, 0.92, 0.23];
+
+/// Note color when none is given: pale yellow
+const NOTE_COLOR: [f32; 3] = [1.0, 0.85, 0.4];
+
+/// Annotation flag: print the annotation with the page
+const FLAG_PRINT: i64 = 4;
+
+/// An annotation to add. Rectangles are [x1, y1, x2, y2] in points from the
+/// page's lower-left corner, as reported by /pdf/fields.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Annotation {
+    /// Translucent marker over the area, optionally with a comment
+    Highlight {
+        page: u32,
+        rect: [f32; 4],
+        #[serde(default)]
+        contents: Option<String>,
+        #[serde(default)]
+        author: Option<String>,
+        #[serde(default)]
+        color: Option<[f32; 3]>,
+    },
+    /// Sticky note whose icon sits at the top-left of the area
+    Note {
+        page: u32,
+        rect: [f32; 4],
+        contents: String,
+        #[serde(default)]
+        author: Option<String>,
+        #[serde(default)]
+        color: Option<[f32; 3]>,
+    },
+    /// Clickable area opening a URL
+    Link {
+        page: u32,
+        rect: [f32; 4],
+        url: String,
+    },
+}
+
+impl Annotation {
+    fn page(&self) -> u32 {
+        match self {
+            Annotation::Highlight { page, .. }
+            | Annotation::Note { page, .. }
+            | Annotation::Link { page, .. } => *page,
+        }
+    }
+
+    fn rect(&self) -> [f32; 4] {
+        match self {
+            Annotation::Highlight { rect, .. }
+            | Annotation::Note { rect, .. }
+            | Annotation::Link { rect, .. } => *rect,
+        }
+    }
+
+    fn color(&self) -> Option<[f32; 3]> {
+        match self {
+            Annotation::Highlight { color, .. } | Annotation::Note { color, .. } => *color,
+            Annotation::Link { .. } => None,
+        }
+    }
+}
+
+/// Add annotations to a PDF, returning the annotated document
+pub fn annotate(data: &[u8], annotations: &[Annotation]) -> Result<Vec<u8>> {
+    if annotations.len() > MAX_ANNOTATIONS {
+        return Err(AppError::InvalidRequest(format!(
+            "annotations: at most {} per request",
+            MAX_ANNOTATIONS
+        )));
+    }
+    let mut doc = load_input(data)?;
+    let pages = doc.get_pages();
+
+    // Check everything before changing the document
+    for (index, annotation) in annotations.iter().enumerate() {
+        let field = format!("annotations[{}]", index);
+        if !pages.contains_key(&annotation.page()) {
+            return Err(AppError::InvalidRequest(format!(
+                "{}: page {} out of range (document has {} pages)",
+                field,
+                annotation.page(),
+                pages.len()
+            )));
+        }
+        let rect = annotation.rect();
+        if rect.iter().any(|v| !v.is_finite()) || rect[0] == rect[2] || rect[1] == rect[3] {
+            return Err(AppError::InvalidRequest(format!(
+                "{}: rect must be [x1, y1, x2, y2] with a non-empty area",
+                field
+            )));
+        }
+        if let Some(color) = annotation.color() {
+            if color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+                return Err(AppError::InvalidRequest(format!(
+                    "{}: color components must be between 0 and 1",
+                    field
+                )));
+            }
+        }
+        if let Annotation::Link { url, .. } = annotation {
+            check_url(&field, url)?;
+        }
+    }
+
+    for annotation in annotations {
+        add_annotation(&mut doc, annotation, pages[&annotation.page()])?;
+    }
+    save(&mut doc)
+}
+
+/// Add a checked annotation to a page
+pub(super) fn add_annotation(
+    doc: &mut Document,
+    annotation: &Annotation,
+    page_id: ObjectId,
+) -> Result<()> {
+    let dict = annotation_dict(doc, annotation, page_id);
+    let annotation_id = doc.add_object(dict);
+    add_to_page(doc, page_id, annotation_id)
+}
+
+/// Links may open web pages and mail addresses, not scripts or local files
+fn check_url(field: &str, url: &str) -> Result<()> {
+    let scheme = url
+        .split_once(':')
+        .map(|(scheme, _)| scheme.to_ascii_lowercase());
+    match scheme.as_deref() {
+        Some("http" | "https" | "mailto") => Ok(()),
+        _ => Err(AppError::InvalidRequest(format!(
+            "{}: url must be an http, https or mailto URL",
+            field
+        ))),
+    }
+}
+
+fn annotation_dict(doc: &mut Document, annotation: &Annotation, page_id: ObjectId) -> Dictionary {
+    let [x1, y1, x2, y2] = annotation.rect();
+    let (x1, x2) = (x1.min(x2), x1.max(x2));
+    let (y1, y2) = (y1.min(y2), y1.max(y2));
+    let rect = vec![x1.into(), y1.into(), x2.into(), y2.into()];
+    let mut dict = dictionary! {
+        "Type" => "Annot",
+        "Rect" => rect,
+        "P" => page_id,
+    };
+
+    match annotation {
+        Annotation::Highlight {
+            contents,
+            author,
+            color,
+            ..
+        } => {
+            let color = color.unwrap_or(HIGHLIGHT_COLOR);
+            dict.set("Subtype", "Highlight");
+            dict.set("F", FLAG_PRINT);
+            dict.set("C", color_array(color));
+            // Corners in the order viewers expect: top-left, top-right,
+            // bottom-left, bottom-right
+            let quad = [x1, y2, x2, y2, x1, y1, x2, y1];
+            dict.set(
+                "QuadPoints",
+                quad.iter().map(|&v| Object::from(v)).collect::<Vec<_>>(),
+            );
+            let appearance = highlight_appearance(doc, [x1, y1, x2, y2], color);
+            dict.set("AP", dictionary! { "N" => appearance });
+            set_comment(&mut dict, contents.as_deref(), author.as_deref());
+        }
+        Annotation::Note {
+            contents,
+            author,
+            color,
+            ..
+        } => {
+            dict.set("Subtype", "Text");
+            dict.set("F", FLAG_PRINT);
+            dict.set("Name", "Comment");
+            dict.set("C", color_array(color.unwrap_or(NOTE_COLOR)));
+            set_comment(&mut dict, Some(contents), author.as_deref());
+        }
+        Annotation::Link { url, .. } => {
+            dict.set("Subtype", "Link");
+            dict.set("Border", vec![0.into(), 0.into(), 0.into()]);
+            dict.set(
+                "A",
+                dictionary! {
+                    "S" => "URI",
+                    "URI" => Object::String(url.as_bytes().to_vec(), StringFormat::Literal),
+                },
+            );
+        }
+    }
+    dict
+}
+
+fn set_comment(dict: &mut Dictionary, contents: Option<&str>, author: Option<&str>) {
+    if let Some(contents) = contents {
+        dict.set("Contents", text_object(contents));
+    }
+    if let Some(author) = author {
+        dict.set("T", text_object(author));
+    }
+}
+
+/// A multiplied color fill over the area, so the text below stays readable;
+/// not every viewer draws highlights without an appearance
+fn highlight_appearance(doc: &mut Document, rect: [f32; 4], color: [f32; 3]) -> ObjectId {
+    let [x1, y1, x2, y2] = rect;
+    let content = format!(
+        "q /Highlight gs {} {} {} rg {} {} {} {} re f Q",
+        color[0],
+        color[1],
+        color[2],
+        x1,
+        y1,
+        x2 - x1,
+        y2 - y1
+    );
+    let resources = dictionary! {
+        "ExtGState" => dictionary! {
+            "Highlight" => dictionary! { "Type" => "ExtGState", "BM" => "Multiply" },
+        },
+    };
+    let stream = Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => vec![x1.into(), y1.into(), x2.into(), y2.into()],
+            "Resources" => resources,
+        },
+        content.into_bytes(),
+    );
+    doc.add_object(stream)
+}
+
+fn color_array(color: [f32; 3]) -> Vec<Object> {
+    color.iter().map(|&c| Object::from(c)).collect()
+}
+
+/// A PDF text string: literal when ASCII, otherwise UTF-16BE with a byte
+/// order mark
+pub(super) fn text_object(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::String(text.as_bytes().to_vec(), StringFormat::Literal);
+    }
+    let mut bytes = vec![0xFE, 0xFF];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+/// Append an annotation to a page's Annots, which may be missing, inline or
+/// a reference to an array
+fn add_to_page(doc: &mut Document, page_id: ObjectId, annotation_id: ObjectId) -> Result<()> {
+    let page = doc
+        .get_dictionary(page_id)
+        .map_err(|e| AppError::InvalidRequest(format!("file: unreadable page: {}", e)))?;
+    if let Ok(Object::Reference(array_id)) = page.get(b"Annots") {
+        let array_id = *array_id;
+        if let Ok(Object::Array(annots)) = doc.get_object_mut(array_id) {
+            annots.push(annotation_id.into());
+            return Ok(());
+        }
+    }
+
+    let page = doc
+        .get_dictionary_mut(page_id)
+        .map_err(|e| AppError::InvalidRequest(format!("file: unreadable page: {}", e)))?;
+    match page.get_mut(b"Annots") {
+        Ok(Object::Array(annots)) => annots.push(annotation_id.into()),
+        _ => page.set("Annots", vec![Object::from(annotation_id)]),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::sample_pdf;
+
+    #[test]
+    fn test_annotate() {
+        let annotations: Vec<Annotation> = serde_json::from_str(
+            r#"[
+                {"type": "highlight", "page": 1, "rect": [72, 700, 300, 720], "contents": "Check this", "author": "Ada"},
+                {"type": "note", "page": 2, "rect": [10, 10, 30, 30], "contents": "Größe?"},
+                {"type": "link", "page": 2, "rect": [100, 100, 200, 120], "url": "https://example.com"}
+            ]"#,
+        )
+        .unwrap();
+        let data = annotate(&sample_pdf(2), &annotations).unwrap();
+
+        let doc = Document::load_mem(&data).unwrap();
+        let pages = doc.get_pages();
+        let subtypes = |number: u32| -> Vec<String> {
+            let page = doc.get_dictionary(pages[&number]).unwrap();
+            page.get(b"Annots")
+                .and_then(Object::as_array)
+                .map(|annots| {
+                    annots
+                        .iter()
+                        .map(|a| {
+                            let annot = doc.get_dictionary(a.as_reference().unwrap()).unwrap();
+                            String::from_utf8_lossy(
+                                annot.get(b"Subtype").unwrap().as_name().unwrap(),
+                            )
+                            .into_owned()
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        assert_eq!(subtypes(1), vec!["Highlight"]);
+        assert_eq!(subtypes(2), vec!["Text", "Link"]);
+
+        let invalid = |json: &str| {
+            let annotations: Vec<Annotation> = serde_json::from_str(json).unwrap();
+            annotate(&sample_pdf(1), &annotations)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(
+            invalid(r#"[{"type": "note", "page": 3, "rect": [0, 0, 1, 1], "contents": "x"}]"#)
+                .contains("annotations[0]: page 3 out of range")
+        );
+        assert!(invalid(
+            r#"[{"type": "link", "page": 1, "rect": [0, 0, 1, 1], "url": "javascript:alert(1)"}]"#
+        )
+        .contains("url must be"));
+        assert!(serde_json::from_str::<Vec<Annotation>>(
+            r#"[{"type": "stamp", "page": 1, "rect": [0, 0, 1, 1]}]"#
+        )
+        .is_err());
+    }
+}
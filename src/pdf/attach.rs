@@ -0,0 +1,206 @@
+//! Files embedded in a PDF (ISO 32000 7.11.4), listed in the document's
+//! EmbeddedFiles name tree so viewers show them as attachments
+
+use super::annotate::text_object;
+use super::{load, save};
+use crate::error::{AppError, Result};
+use lopdf::{dictionary, Dictionary, Document, Object, Stream, StringFormat};
+
+/// Deepest name tree followed when collecting the files already embedded
+const MAX_TREE_DEPTH: usize = 8;
+
+/// A file to embed
+pub struct EmbeddedFile<'a> {
+    /// File name shown by viewers
+    pub name: &'a str,
+    pub content_type: &'a str,
+    /// Shown by viewers next to the name
+    pub description: &'a str,
+    pub data: &'a [u8],
+}
+
+/// Embed a file in a PDF, replacing one embedded under the same name. The
+/// file is also associated with the document (`AF`), as PDF/A-3 requires.
+pub fn attach_file(data: &[u8], file: &EmbeddedFile) -> Result<Vec<u8>> {
+    let mut doc = load(data)?;
+    let modified = chrono::Utc::now().format("D:%Y%m%d%H%M%SZ").to_string();
+
+    let stream = Stream::new(
+        dictionary! {
+            "Type" => "EmbeddedFile",
+            "Subtype" => Object::Name(file.content_type.as_bytes().to_vec()),
+            "Params" => dictionary! {
+                "Size" => file.data.len() as i64,
+                "ModDate" => Object::string_literal(modified),
+            },
+        },
+        file.data.to_vec(),
+    );
+    let stream_id = doc.add_object(stream);
+    let filespec_id = doc.add_object(dictionary! {
+        "Type" => "Filespec",
+        "F" => Object::string_literal(ascii_name(file.name)),
+        "UF" => text_object(file.name),
+        "Desc" => text_object(file.description),
+        "EF" => dictionary! { "F" => stream_id, "UF" => stream_id },
+        "AFRelationship" => "Data",
+    });
+
+    // Rebuild the name tree flat, its keys sorted as the format requires
+    let key = text_object(file.name)
+        .as_str()
+        .map(<[u8]>::to_vec)
+        .unwrap_or_default();
+    let (replaced, mut entries): (Vec<_>, Vec<_>) = embedded_files(&doc)
+        .into_iter()
+        .partition(|(name, _)| *name == key);
+    entries.push((key, Object::Reference(filespec_id)));
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    let names: Vec<Object> = entries
+        .into_iter()
+        .flat_map(|(name, spec)| [Object::String(name, StringFormat::Hexadecimal), spec])
+        .collect();
+
+    let mut tree = match names_dictionary(&doc) {
+        Some(names) => names,
+        None => Dictionary::new(),
+    };
+    tree.set("EmbeddedFiles", dictionary! { "Names" => names });
+    let catalog = doc
+        .catalog_mut()
+        .map_err(|e| AppError::ConversionFailed(format!("Invalid PDF catalog: {}", e)))?;
+    catalog.set("Names", tree);
+    let mut associated = match catalog.get(b"AF") {
+        Ok(Object::Array(files)) => files.clone(),
+        _ => Vec::new(),
+    };
+    associated.retain(|spec| !replaced.iter().any(|(_, old)| old == spec));
+    associated.push(Object::Reference(filespec_id));
+    catalog.set("AF", associated);
+
+    save(&mut doc)
+}
+
+/// The catalog's Names dictionary, resolved
+pub(super) fn names_dictionary(doc: &Document) -> Option<Dictionary> {
+    let names = doc.catalog().ok()?.get(b"Names").ok()?;
+    let (_, names) = doc.dereference(names).ok()?;
+    names.as_dict().ok().cloned()
+}
+
+/// Name (the string's bytes) and file specification of every file
+/// already embedded
+fn embedded_files(doc: &Document) -> Vec<(Vec<u8>, Object)> {
+    let mut entries = Vec::new();
+    let root = names_dictionary(doc).and_then(|names| names.get(b"EmbeddedFiles").ok().cloned());
+    if let Some(root) = root {
+        collect_entries(doc, &root, 0, &mut entries);
+    }
+    entries
+}
+
+pub(super) fn collect_entries(
+    doc: &Document,
+    node: &Object,
+    depth: usize,
+    out: &mut Vec<(Vec<u8>, Object)>,
+) {
+    let Ok((_, node)) = doc.dereference(node) else {
+        return;
+    };
+    let Ok(node) = node.as_dict() else { return };
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        for pair in names.chunks_exact(2) {
+            if let Ok(name) = pair[0].as_str() {
+                out.push((name.to_vec(), pair[1].clone()));
+            }
+        }
+    }
+    if depth < MAX_TREE_DEPTH {
+        if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+            for kid in kids {
+                collect_entries(doc, kid, depth + 1, out);
+            }
+        }
+    }
+}
+
+/// The name for readers of the legacy `F` entry, non-ASCII replaced
+fn ascii_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sample_pdf;
+    use super::*;
+
+    #[test]
+    fn test_attach_file() {
+        let file = |data: &'static [u8]| EmbeddedFile {
+            name: "provenance.json",
+            content_type: "application/json",
+            description: "Conversion record",
+            data,
+        };
+        let data = attach_file(&sample_pdf(1), &file(b"{}")).unwrap();
+        // Embedding again under the same name replaces the file
+        let data = attach_file(&data, &file(b"{\"a\":1}")).unwrap();
+        let data = attach_file(
+            &data,
+            &EmbeddedFile {
+                name: "Äpfel.txt",
+                ..file(b"a")
+            },
+        )
+        .unwrap();
+
+        let doc = load(&data).unwrap();
+        let entries = embedded_files(&doc);
+        let names: Vec<_> = entries.iter().map(|(name, _)| name.clone()).collect();
+        assert_eq!(
+            names,
+            [
+                b"provenance.json".to_vec(),
+                lopdf::encode_utf16_be("Äpfel.txt")
+            ]
+        );
+        let (_, spec) = doc.dereference(&entries[0].1).unwrap();
+        let spec = spec.as_dict().unwrap();
+        assert_eq!(
+            spec.get(b"F").unwrap().as_str().unwrap(),
+            b"provenance.json"
+        );
+        let stream = spec
+            .get(b"EF")
+            .unwrap()
+            .as_dict()
+            .unwrap()
+            .get(b"F")
+            .unwrap();
+        let stream = doc.get_object(stream.as_reference().unwrap()).unwrap();
+        let stream = stream.as_stream().unwrap();
+        assert_eq!(stream.get_plain_content().unwrap(), b"{\"a\":1}");
+        assert_eq!(
+            stream.dict.get(b"Subtype").unwrap().as_name().unwrap(),
+            b"application/json"
+        );
+
+        let af = doc
+            .catalog()
+            .unwrap()
+            .get(b"AF")
+            .unwrap()
+            .as_array()
+            .unwrap();
+        assert_eq!(af.len(), 2);
+    }
+}
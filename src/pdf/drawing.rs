@@ -0,0 +1,133 @@
+//! Sizing of CAD drawings on the page: each page of the imported drawing
+//! is scaled onto a sheet of the requested size and centered inside the
+//! margins
+
+use super::handout::page_to_xobject;
+use super::{load, save};
+use crate::error::{AppError, Result};
+use lopdf::{dictionary, Dictionary, Object, Stream};
+use pdfmill_core::DrawingScale;
+
+/// Page boxes dropped from placed pages, which get a new MediaBox
+const PAGE_BOXES: &[&[u8]] = &[b"CropBox", b"BleedBox", b"TrimBox", b"ArtBox"];
+
+/// The sheet a drawing is placed on, in points
+#[derive(Debug, Clone, Default)]
+pub struct Sheet {
+    /// Sheet width; the drawing page's own width when unset
+    pub width: Option<f32>,
+    /// Sheet height; the drawing page's own height when unset
+    pub height: Option<f32>,
+    /// Turn the sheet so its longer side is horizontal
+    pub landscape: bool,
+    /// Top, right, bottom and left margins
+    pub margins: [f32; 4],
+}
+
+/// Scale every page onto the sheet: to fill the space inside the margins,
+/// or by a fixed factor, clipping what falls outside them
+pub fn place_drawing(data: &[u8], sheet: &Sheet, scale: DrawingScale) -> Result<Vec<u8>> {
+    let mut doc = load(data)?;
+    for page_id in doc.get_pages().into_values().collect::<Vec<_>>() {
+        let (xobject_id, bbox) = page_to_xobject(&mut doc, page_id)?;
+        let drawing_width = (bbox[2] - bbox[0]).max(1.0);
+        let drawing_height = (bbox[3] - bbox[1]).max(1.0);
+
+        let mut width = sheet.width.unwrap_or(drawing_width);
+        let mut height = sheet.height.unwrap_or(drawing_height);
+        if sheet.landscape && height > width {
+            std::mem::swap(&mut width, &mut height);
+        }
+        let [top, right, bottom, left] = sheet.margins;
+        let area_width = width - left - right;
+        let area_height = height - top - bottom;
+        if area_width <= 0.0 || area_height <= 0.0 {
+            return Err(AppError::InvalidRequest(
+                "The margins leave no room for the drawing".to_string(),
+            ));
+        }
+
+        let factor = match scale {
+            DrawingScale::Fit => (area_width / drawing_width).min(area_height / drawing_height),
+            DrawingScale::Factor(factor) => factor as f32,
+        };
+        let x = left + (area_width - drawing_width * factor) / 2.0 - bbox[0] * factor;
+        let y = bottom + (area_height - drawing_height * factor) / 2.0 - bbox[1] * factor;
+        let content = format!(
+            "q {left:.2} {bottom:.2} {w:.2} {h:.2} re W n \
+             {s:.4} 0 0 {s:.4} {x:.2} {y:.2} cm /PdfmillDrawing Do Q\n",
+            left = left,
+            bottom = bottom,
+            w = area_width,
+            h = area_height,
+            s = factor,
+            x = x,
+            y = y,
+        );
+
+        let content_id = doc.add_object(Stream::new(Dictionary::new(), content.into_bytes()));
+        let page = doc
+            .get_dictionary_mut(page_id)
+            .map_err(|e| AppError::ConversionFailed(format!("Failed to read page: {}", e)))?;
+        for name in PAGE_BOXES {
+            page.remove(name);
+        }
+        page.remove(b"Rotate");
+        page.set(
+            "MediaBox",
+            vec![
+                0.into(),
+                0.into(),
+                Object::Real(width),
+                Object::Real(height),
+            ],
+        );
+        page.set("Contents", content_id);
+        page.set(
+            "Resources",
+            dictionary! { "XObject" => dictionary! { "PdfmillDrawing" => xobject_id } },
+        );
+    }
+    save(&mut doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::{media_box, sample_pdf};
+
+    #[test]
+    fn test_place_drawing() {
+        // A4 landscape with 10mm margins around a 16:9 drawing
+        let sheet = Sheet {
+            width: Some(595.28),
+            height: Some(841.89),
+            landscape: true,
+            margins: [28.35; 4],
+        };
+        let data = place_drawing(&sample_pdf(2), &sheet, DrawingScale::Fit).unwrap();
+        let doc = load(&data).unwrap();
+        let pages = doc.get_pages();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(media_box(&doc, pages[&1]), [0.0, 0.0, 841.89, 595.28]);
+        let content =
+            String::from_utf8_lossy(&doc.get_page_content(pages[&1]).unwrap()).into_owned();
+        assert!(content.contains("/PdfmillDrawing Do"), "{}", content);
+
+        // Without a sheet size the drawing keeps its page
+        let data =
+            place_drawing(&sample_pdf(1), &Sheet::default(), DrawingScale::Factor(0.5)).unwrap();
+        let doc = load(&data).unwrap();
+        let original = load(&sample_pdf(1)).unwrap();
+        assert_eq!(
+            media_box(&doc, doc.get_pages()[&1]),
+            media_box(&original, original.get_pages()[&1])
+        );
+
+        let cramped = Sheet {
+            margins: [500.0; 4],
+            ..Default::default()
+        };
+        assert!(place_drawing(&sample_pdf(1), &cramped, DrawingScale::Fit).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+//! AcroForm fields of a PDF: names, types, choices, values and positions,
+//! for clients that build data-entry forms from a document
+
+use super::load_input;
+use crate::error::Result;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Field flags (PDF 32000-1, 12.7.4)
+const FLAG_RADIO: i64 = 1 << 15;
+const FLAG_PUSHBUTTON: i64 = 1 << 16;
+
+/// Deepest field hierarchy followed, so reference cycles cannot loop forever
+const MAX_DEPTH: usize = 32;
+
+/// A terminal form field
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FormField {
+    /// Fully qualified name, parts joined with dots
+    pub name: String,
+    /// text, checkbox, radio, choice, button or signature
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// Choices of list, combo box and radio fields
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+    /// Current value; checkboxes and radios hold the selected state, "Off"
+    /// when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<FieldValue>,
+    /// 1-based page of the field's first widget
+    pub page: Option<u32>,
+    /// Rectangle of the field's first widget in PDF points, [x1, y1, x2, y2]
+    /// from the page's lower-left corner
+    pub rect: Option<[f32; 4]>,
+}
+
+/// A field value: text, or the selections of a multi-select list
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Text(String),
+    List(Vec<String>),
+}
+
+/// The form fields of a PDF, in document order; empty without a form
+pub fn form_fields(data: &[u8]) -> Result<Vec<FormField>> {
+    let doc = load_input(data)?;
+    let fields = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| resolve_dict(&doc, form))
+        .and_then(|form| form.get(b"Fields").ok())
+        .and_then(|fields| doc.dereference(fields).ok())
+        .and_then(|(_, fields)| fields.as_array().ok());
+
+    let form = Form::new(&doc);
+    let mut found = Vec::new();
+    for field in fields.into_iter().flatten() {
+        form.collect(field, "", &Dictionary::new(), 0, &mut found);
+    }
+    Ok(found)
+}
+
+/// A document with its page numbers, for locating widgets
+struct Form<'a> {
+    doc: &'a Document,
+    /// Page object to page number
+    pages: HashMap<ObjectId, u32>,
+    /// Annotation object to the number of the page listing it
+    annotations: HashMap<ObjectId, u32>,
+}
+
+impl<'a> Form<'a> {
+    fn new(doc: &'a Document) -> Self {
+        let mut pages = HashMap::new();
+        let mut annotations = HashMap::new();
+        for (number, id) in doc.get_pages() {
+            pages.insert(id, number);
+            let annots = doc
+                .get_dictionary(id)
+                .ok()
+                .and_then(|page| page.get(b"Annots").ok())
+                .and_then(|annots| doc.dereference(annots).ok())
+                .and_then(|(_, annots)| annots.as_array().ok());
+            for annot in annots.into_iter().flatten() {
+                if let Ok(annot) = annot.as_reference() {
+                    annotations.entry(annot).or_insert(number);
+                }
+            }
+        }
+        Self {
+            doc,
+            pages,
+            annotations,
+        }
+    }
+
+    /// Walk a field and its kids. Inheritable attributes (FT, Ff, Opt, V)
+    /// are passed down in `inherited`.
+    fn collect(
+        &self,
+        node: &Object,
+        parent_name: &str,
+        inherited: &Dictionary,
+        depth: usize,
+        found: &mut Vec<FormField>,
+    ) {
+        let doc = self.doc;
+        let Some(dict) = resolve_dict(doc, node).filter(|_| depth < MAX_DEPTH) else {
+            return;
+        };
+        let name = match dict.get(b"T").and_then(Object::as_str) {
+            Ok(partial) if parent_name.is_empty() => text_string(partial),
+            Ok(partial) => format!("{}.{}", parent_name, text_string(partial)),
+            Err(_) => parent_name.to_string(),
+        };
+        let mut attributes = inherited.clone();
+        for key in [&b"FT"[..], b"Ff", b"Opt", b"V"] {
+            if let Ok(value) = dict.get(key) {
+                attributes.set(key, value.clone());
+            }
+        }
+
+        // Kids with their own names are fields; kids without are the widgets
+        // of this field
+        let kids: Vec<&Object> = dict
+            .get(b"Kids")
+            .ok()
+            .and_then(|kids| doc.dereference(kids).ok())
+            .and_then(|(_, kids)| kids.as_array().ok())
+            .map(|kids| kids.iter().collect())
+            .unwrap_or_default();
+        let child_fields: Vec<&Object> = kids
+            .iter()
+            .copied()
+            .filter(|kid| resolve_dict(doc, kid).is_some_and(|d| d.has(b"T")))
+            .collect();
+        if !child_fields.is_empty() {
+            for kid in child_fields {
+                self.collect(kid, &name, &attributes, depth + 1, found);
+            }
+            return;
+        }
+        if name.is_empty() {
+            return;
+        }
+
+        let widgets = if kids.is_empty() { vec![node] } else { kids };
+        let kind = field_kind(&attributes);
+        let options = match kind {
+            "choice" => choice_options(doc, &attributes),
+            "radio" => radio_states(doc, &widgets),
+            _ => Vec::new(),
+        };
+        let widget = widgets.first().copied();
+        found.push(FormField {
+            name,
+            kind,
+            options,
+            value: field_value(doc, &attributes),
+            page: widget.and_then(|widget| self.page_of(widget)),
+            rect: widget.and_then(|widget| rect(doc, widget)),
+        });
+    }
+
+    /// The page a widget is on: its P entry, or else the page whose
+    /// annotations list it
+    fn page_of(&self, widget: &Object) -> Option<u32> {
+        let by_parent = resolve_dict(self.doc, widget)
+            .and_then(|w| w.get(b"P").ok())
+            .and_then(|page| page.as_reference().ok())
+            .and_then(|page| self.pages.get(&page));
+        let by_annotations = || {
+            widget
+                .as_reference()
+                .ok()
+                .and_then(|id| self.annotations.get(&id))
+        };
+        by_parent.or_else(by_annotations).copied()
+    }
+}
+
+fn field_value(doc: &Document, attributes: &Dictionary) -> Option<FieldValue> {
+    let single = |value: &Object| match value {
+        Object::String(text, _) => Some(text_string(text)),
+        Object::Name(name) => Some(String::from_utf8_lossy(name).into_owned()),
+        _ => None,
+    };
+    match doc.dereference(attributes.get(b"V").ok()?).ok()?.1 {
+        Object::Array(values) => Some(FieldValue::List(values.iter().filter_map(single).collect())),
+        value => single(value).map(FieldValue::Text),
+    }
+}
+
+fn rect(doc: &Document, widget: &Object) -> Option<[f32; 4]> {
+    let rect = resolve_dict(doc, widget)?.get(b"Rect").ok()?;
+    let rect = doc.dereference(rect).ok()?.1.as_array().ok()?;
+    let mut corners = [0.0; 4];
+    for (corner, value) in corners.iter_mut().zip(rect) {
+        *corner = value.as_float().ok()?;
+    }
+    (rect.len() == 4).then_some(corners)
+}
+
+fn field_kind(attributes: &Dictionary) -> &'static str {
+    let flags = attributes.get(b"Ff").and_then(Object::as_i64).unwrap_or(0);
+    match attributes.get(b"FT").and_then(Object::as_name) {
+        Ok(b"Tx") => "text",
+        Ok(b"Ch") => "choice",
+        Ok(b"Sig") => "signature",
+        Ok(b"Btn") if flags & FLAG_PUSHBUTTON != 0 => "button",
+        Ok(b"Btn") if flags & FLAG_RADIO != 0 => "radio",
+        Ok(b"Btn") => "checkbox",
+        _ => "text",
+    }
+}
+
+/// Display values of a choice field's Opt: strings, or [export, display]
+/// pairs
+fn choice_options(doc: &Document, attributes: &Dictionary) -> Vec<String> {
+    let Some(options) = attributes
+        .get(b"Opt")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_array().ok())
+    else {
+        return Vec::new();
+    };
+    options
+        .iter()
+        .filter_map(|option| match doc.dereference(option).ok()?.1 {
+            Object::String(text, _) => Some(text_string(text)),
+            Object::Array(pair) => pair
+                .last()
+                .and_then(|display| display.as_str().ok())
+                .map(text_string),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Export values of a radio group: the "on" appearance state of each
+/// widget
+fn radio_states(doc: &Document, widgets: &[&Object]) -> Vec<String> {
+    let mut states = Vec::new();
+    for widget in widgets {
+        let normal = resolve_dict(doc, widget)
+            .and_then(|w| w.get(b"AP").ok())
+            .and_then(|ap| resolve_dict(doc, ap))
+            .and_then(|ap| ap.get(b"N").ok())
+            .and_then(|n| resolve_dict(doc, n));
+        for (state, _) in normal.into_iter().flat_map(|n| n.iter()) {
+            let state = String::from_utf8_lossy(state).into_owned();
+            if state != "Off" && !states.contains(&state) {
+                states.push(state);
+            }
+        }
+    }
+    states
+}
+
+fn resolve_dict<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Dictionary> {
+    doc.dereference(object).ok()?.1.as_dict().ok()
+}
+
+/// Decode a PDF text string: UTF-16BE with a byte order mark, otherwise
+/// PDFDocEncoding (read as Latin-1, which it matches for common text)
+pub(crate) fn text_string(bytes: &[u8]) -> String {
+    match bytes.strip_prefix(&[0xFE, 0xFF]) {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, StringFormat};
+
+    /// A one-page PDF whose form has the given fields, as annotations of
+    /// the page
+    pub(crate) fn form_pdf(fields: Vec<Dictionary>) -> Vec<u8> {
+        let mut doc = load_input(&crate::pdf::sample_pdf(1)).unwrap();
+        let field_refs: Vec<Object> = fields
+            .into_iter()
+            .map(|field| Object::Reference(doc.add_object(field)))
+            .collect();
+        let page = doc.get_pages()[&1];
+        doc.get_dictionary_mut(page)
+            .unwrap()
+            .set("Annots", field_refs.clone());
+        let form = doc.add_object(dictionary! { "Fields" => field_refs });
+        doc.catalog_mut().unwrap().set("AcroForm", form);
+        let mut data = Vec::new();
+        doc.save_to(&mut data).unwrap();
+        data
+    }
+
+    fn text(value: &str) -> Object {
+        Object::String(value.as_bytes().to_vec(), StringFormat::Literal)
+    }
+
+    #[test]
+    fn test_form_fields() {
+        let mut utf16_name = vec![0xFE, 0xFF];
+        utf16_name.extend("Straße".encode_utf16().flat_map(u16::to_be_bytes));
+        let data = form_pdf(vec![
+            dictionary! { "FT" => "Tx", "T" => Object::String(utf16_name, StringFormat::Hexadecimal) },
+            dictionary! { "FT" => "Btn", "T" => text("agree"), "V" => "Yes" },
+            dictionary! {
+                "FT" => "Ch", "T" => text("country"), "V" => text("France"),
+                "Rect" => vec![10.into(), 20.into(), Object::Real(110.5), 40.into()],
+                "Opt" => vec![text("Germany"), Object::Array(vec![text("fr"), text("France")])],
+            },
+            dictionary! {
+                "T" => text("address"),
+                "Kids" => vec![Object::Dictionary(dictionary! { "FT" => "Tx", "T" => text("city") })],
+            },
+            dictionary! {
+                "FT" => "Btn", "Ff" => FLAG_RADIO, "T" => text("size"),
+                "Kids" => vec![
+                    Object::Dictionary(dictionary! { "AP" => dictionary! { "N" => dictionary! { "S" => 0, "Off" => 0 } } }),
+                    Object::Dictionary(dictionary! { "AP" => dictionary! { "N" => dictionary! { "L" => 0, "Off" => 0 } } }),
+                ],
+            },
+        ]);
+
+        let fields = form_fields(&data).unwrap();
+        let summary: Vec<(&str, &str)> = fields.iter().map(|f| (f.name.as_str(), f.kind)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Straße", "text"),
+                ("agree", "checkbox"),
+                ("country", "choice"),
+                ("address.city", "text"),
+                ("size", "radio"),
+            ]
+        );
+        assert_eq!(fields[2].options, vec!["Germany", "France"]);
+        assert_eq!(fields[4].options, vec!["S", "L"]);
+        assert_eq!(fields[0].value, None);
+        assert_eq!(fields[1].value, Some(FieldValue::Text("Yes".to_string())));
+        assert_eq!(
+            fields[2].value,
+            Some(FieldValue::Text("France".to_string()))
+        );
+        assert_eq!(fields[2].rect, Some([10.0, 20.0, 110.5, 40.0]));
+        assert_eq!(fields[2].page, Some(1));
+
+        assert!(form_fields(&crate::pdf::sample_pdf(1)).unwrap().is_empty());
+    }
+}
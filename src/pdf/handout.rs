@@ -0,0 +1,189 @@
+use super::{inherited, load, media_box, save};
+use crate::error::{AppError, Result};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use pdfmill_core::HANDOUT_LAYOUTS;
+
+/// A4 portrait, in points
+const SHEET_WIDTH: f32 = 595.28;
+const SHEET_HEIGHT: f32 = 841.89;
+const MARGIN: f32 = 36.0;
+const GAP: f32 = 18.0;
+const NOTE_LINE_SPACING: f32 = 20.0;
+
+/// Place `per_page` slides on each A4 sheet. With `note_lines`, slides are
+/// stacked on the left and ruled lines for notes are drawn on the right.
+pub fn impose_handout(data: &[u8], per_page: u32, note_lines: bool) -> Result<Vec<u8>> {
+    if !HANDOUT_LAYOUTS.contains(&per_page) {
+        return Err(AppError::InvalidRequest(format!(
+            "Unsupported handout layout: {} slides per page",
+            per_page
+        )));
+    }
+
+    let mut doc = load(data)?;
+    let slides: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    let xobjects = slides
+        .iter()
+        .map(|&page_id| page_to_xobject(&mut doc, page_id))
+        .collect::<Result<Vec<_>>>()?;
+
+    let (cols, rows) = match (per_page, note_lines) {
+        (n, true) => (1, n),
+        (1, false) => (1, 1),
+        (2, false) => (1, 2),
+        (4, false) => (2, 2),
+        _ => (2, 3),
+    };
+
+    let pages_id = doc.new_object_id();
+    let mut kids = Vec::new();
+
+    for chunk in xobjects.chunks(per_page as usize) {
+        let mut content = String::new();
+        let mut xobject_dict = Dictionary::new();
+
+        for (i, (xobject_id, bbox)) in chunk.iter().enumerate() {
+            let name = format!("S{}", i);
+            xobject_dict.set(name.as_bytes(), Object::Reference(*xobject_id));
+
+            let cell = cell_rect(i as u32, cols, rows, note_lines);
+            content.push_str(&place_slide(&name, bbox, cell, !note_lines));
+            if note_lines {
+                content.push_str(&note_lines_for(cell));
+            }
+        }
+
+        let content_id = doc.add_object(Stream::new(Dictionary::new(), content.into_bytes()));
+        let page = dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), SHEET_WIDTH.into(), SHEET_HEIGHT.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "XObject" => xobject_dict },
+        };
+        kids.push(Object::Reference(doc.add_object(page)));
+    }
+
+    let count = kids.len() as i64;
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count,
+        }),
+    );
+    doc.catalog_mut()
+        .map_err(|e| AppError::ConversionFailed(format!("Invalid PDF catalog: {}", e)))?
+        .set("Pages", pages_id);
+
+    save(&mut doc)
+}
+
+/// Wrap a page's content and resources into a Form XObject
+pub(super) fn page_to_xobject(
+    doc: &mut Document,
+    page_id: ObjectId,
+) -> Result<(ObjectId, [f32; 4])> {
+    let bbox = media_box(doc, page_id);
+    let content = doc
+        .get_page_content(page_id)
+        .map_err(|e| AppError::ConversionFailed(format!("Failed to read page: {}", e)))?;
+    let resources = inherited(doc, page_id, b"Resources")
+        .cloned()
+        .unwrap_or_else(|| Object::Dictionary(Dictionary::new()));
+
+    let dict = dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Form",
+        "BBox" => bbox.iter().map(|v| Object::Real(*v)).collect::<Vec<_>>(),
+        "Resources" => resources,
+    };
+    Ok((doc.add_object(Stream::new(dict, content)), bbox))
+}
+
+/// Cell for slide `index` as (x, y, width, height), origin bottom-left
+fn cell_rect(index: u32, cols: u32, rows: u32, note_lines: bool) -> (f32, f32, f32, f32) {
+    let usable_width = SHEET_WIDTH - 2.0 * MARGIN;
+    let usable_height = SHEET_HEIGHT - 2.0 * MARGIN;
+    let width = if note_lines {
+        (usable_width - GAP) / 2.0
+    } else {
+        (usable_width - GAP * (cols - 1) as f32) / cols as f32
+    };
+    let height = (usable_height - GAP * (rows - 1) as f32) / rows as f32;
+
+    let col = index % cols;
+    let row = index / cols;
+    let x = MARGIN + col as f32 * (width + GAP);
+    let y = SHEET_HEIGHT - MARGIN - (row + 1) as f32 * height - row as f32 * GAP;
+    (x, y, width, height)
+}
+
+/// Scale a slide into its cell, keeping the aspect ratio, and frame it
+fn place_slide(name: &str, bbox: &[f32; 4], cell: (f32, f32, f32, f32), center: bool) -> String {
+    let (x, y, width, height) = cell;
+    let slide_width = (bbox[2] - bbox[0]).max(1.0);
+    let slide_height = (bbox[3] - bbox[1]).max(1.0);
+    let scale = (width / slide_width).min(height / slide_height);
+
+    let placed_width = slide_width * scale;
+    let placed_height = slide_height * scale;
+    let left = if center {
+        x + (width - placed_width) / 2.0
+    } else {
+        x
+    };
+    let bottom = y + (height - placed_height) / 2.0;
+
+    format!(
+        "q {s:.4} 0 0 {s:.4} {tx:.2} {ty:.2} cm /{name} Do Q\n\
+         q 0.6 G 0.5 w {left:.2} {bottom:.2} {w:.2} {h:.2} re S Q\n",
+        s = scale,
+        tx = left - bbox[0] * scale,
+        ty = bottom - bbox[1] * scale,
+        name = name,
+        left = left,
+        bottom = bottom,
+        w = placed_width,
+        h = placed_height,
+    )
+}
+
+/// Ruled lines in the right half of a cell
+fn note_lines_for(cell: (f32, f32, f32, f32)) -> String {
+    let (x, y, width, height) = cell;
+    let left = x + width + GAP;
+    let right = SHEET_WIDTH - MARGIN;
+
+    let mut ops = String::from("q 0.75 G 0.5 w\n");
+    let mut line_y = y + height - NOTE_LINE_SPACING;
+    while line_y > y {
+        ops.push_str(&format!(
+            "{:.2} {:.2} m {:.2} {:.2} l S\n",
+            left, line_y, right, line_y
+        ));
+        line_y -= NOTE_LINE_SPACING;
+    }
+    ops.push_str("Q\n");
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sample_pdf;
+    use super::*;
+
+    #[test]
+    fn test_impose_handout_page_count() {
+        let data = sample_pdf(5);
+        for (per_page, expected) in [(1, 5), (2, 3), (4, 2), (6, 1)] {
+            let out = impose_handout(&data, per_page, false).unwrap();
+            assert_eq!(load(&out).unwrap().get_pages().len(), expected);
+        }
+
+        let out = impose_handout(&data, 4, true).unwrap();
+        assert_eq!(load(&out).unwrap().get_pages().len(), 2);
+        assert!(impose_handout(&data, 3, false).is_err());
+    }
+}
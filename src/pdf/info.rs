@@ -0,0 +1,49 @@
+//! The document information dictionary (ISO 32000 14.3.3): title, author
+//! and the other fields viewers show as the document's properties
+
+use super::annotate::text_object;
+use super::{load, save};
+use crate::error::Result;
+use lopdf::{Dictionary, Object};
+
+/// Set document information entries (e.g. `("Title", "Q3 report")`),
+/// keeping the others the engine wrote
+pub fn set_info(data: &[u8], entries: &[(&str, String)]) -> Result<Vec<u8>> {
+    let mut doc = load(data)?;
+    let existing = match doc.trailer.get(b"Info") {
+        Ok(Object::Reference(id)) => doc.get_dictionary(*id).ok().cloned(),
+        Ok(Object::Dictionary(info)) => Some(info.clone()),
+        _ => None,
+    };
+    let mut info = existing.unwrap_or_else(Dictionary::new);
+    for (key, value) in entries {
+        info.set(key.as_bytes().to_vec(), text_object(value));
+    }
+    let info_id = doc.add_object(info);
+    doc.trailer.set("Info", info_id);
+    save(&mut doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::forms::text_string;
+    use super::super::sample_pdf;
+    use super::*;
+
+    #[test]
+    fn test_set_info() {
+        let data = set_info(&sample_pdf(1), &[("Title", "Report".to_string())]).unwrap();
+        let data = set_info(&data, &[("Author", "Zoë".to_string())]).unwrap();
+
+        let doc = load(&data).unwrap();
+        let id = doc
+            .trailer
+            .get(b"Info")
+            .and_then(Object::as_reference)
+            .unwrap();
+        let info = doc.get_dictionary(id).unwrap();
+        let entry = |key: &[u8]| text_string(info.get(key).unwrap().as_str().unwrap());
+        assert_eq!(entry(b"Title"), "Report");
+        assert_eq!(entry(b"Author"), "Zoë");
+    }
+}
@@ -0,0 +1,661 @@
+//! Hyperlink audit of converted documents: compare the source's links with
+//! the link annotations that made it into the PDF, and turn URLs printed as
+//! plain text into clickable links. LibreOffice in particular sometimes
+//! drops hyperlinks on export.
+
+use super::annotate::{add_annotation, Annotation};
+use super::{inherited, load, save};
+use crate::error::Result;
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Encoding, Object, ObjectId};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Deepest nesting of form XObjects followed when reading page text
+const MAX_XOBJECT_DEPTH: usize = 8;
+
+/// Glyph width when a font does not say, in thousandths of the font size
+const DEFAULT_WIDTH: f32 = 500.0;
+
+/// Outcome of a link audit
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LinkReport {
+    /// Distinct external links in the source document
+    pub source: usize,
+    /// Source links present as link annotations in the PDF
+    pub preserved: usize,
+    /// Plain-text URLs in the PDF that were made clickable
+    pub repaired: usize,
+    /// Source links the PDF has lost
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing: Vec<String>,
+}
+
+impl LinkReport {
+    /// Summary for a response header, e.g. "source=3, preserved=2, repaired=1, missing=0"
+    pub fn header_value(&self) -> String {
+        format!(
+            "source={}, preserved={}, repaired={}, missing={}",
+            self.source,
+            self.preserved,
+            self.repaired,
+            self.missing.len()
+        )
+    }
+}
+
+/// Audit a converted PDF against the links of its source, adding link
+/// annotations over plain-text URLs that have none. The document is only
+/// rewritten when something was repaired.
+pub fn repair_links(data: Vec<u8>, source_links: &[String]) -> Result<(Vec<u8>, LinkReport)> {
+    let mut doc = load(&data)?;
+    let pages = doc.get_pages();
+
+    let mut existing: Vec<String> = Vec::new();
+    let mut repairs: Vec<(ObjectId, Annotation)> = Vec::new();
+    for (&number, &page_id) in &pages {
+        let links = page_links(&doc, page_id);
+        for url in find_urls(&page_text(&doc, page_id)) {
+            let covered = links.iter().any(|(_, rect)| overlaps(rect, &url.rect));
+            let repeated = repairs.iter().any(|(id, a)| {
+                *id == page_id
+                    && matches!(a, Annotation::Link { rect, .. } if overlaps(rect, &url.rect))
+            });
+            if !covered && !repeated {
+                let annotation = Annotation::Link {
+                    page: number,
+                    rect: url.rect,
+                    url: url.url,
+                };
+                repairs.push((page_id, annotation));
+            }
+        }
+        existing.extend(links.into_iter().map(|(uri, _)| uri));
+    }
+
+    let repaired_urls: Vec<&str> = repairs
+        .iter()
+        .filter_map(|(_, a)| match a {
+            Annotation::Link { url, .. } => Some(url.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut report = LinkReport {
+        source: source_links.len(),
+        repaired: repairs.len(),
+        ..Default::default()
+    };
+    for link in source_links {
+        let same = |other: &str| same_url(link, other);
+        if existing.iter().any(|uri| same(uri)) {
+            report.preserved += 1;
+        } else if !repaired_urls.iter().any(|uri| same(uri)) {
+            report.missing.push(link.clone());
+        }
+    }
+
+    if repairs.is_empty() {
+        return Ok((data, report));
+    }
+    for (page_id, annotation) in &repairs {
+        add_annotation(&mut doc, annotation, *page_id)?;
+    }
+    Ok((save(&mut doc)?, report))
+}
+
+/// Two URLs that lead to the same place, ignoring case of the scheme and
+/// host and a trailing slash
+fn same_url(a: &str, b: &str) -> bool {
+    let normalize = |url: &str| {
+        let url = url.trim().trim_end_matches('/');
+        match url.split_once("://") {
+            Some((scheme, rest)) => {
+                let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+                format!(
+                    "{}://{}{}",
+                    scheme.to_lowercase(),
+                    host.to_lowercase(),
+                    path
+                )
+            }
+            None => url.to_string(),
+        }
+    };
+    normalize(a) == normalize(b)
+}
+
+fn overlaps(a: &[f32; 4], b: &[f32; 4]) -> bool {
+    a[0] < b[2] && b[0] < a[2] && a[1] < b[3] && b[1] < a[3]
+}
+
+/// URI and rectangle of each link annotation on a page
+fn page_links(doc: &Document, page_id: ObjectId) -> Vec<(String, [f32; 4])> {
+    let annots = doc
+        .get_dictionary(page_id)
+        .ok()
+        .and_then(|page| page.get(b"Annots").ok())
+        .and_then(|annots| doc.dereference(annots).ok())
+        .and_then(|(_, annots)| annots.as_array().ok());
+    let mut links = Vec::new();
+    for annot in annots.into_iter().flatten() {
+        let Some(annot) = resolve_dict(doc, annot) else {
+            continue;
+        };
+        if annot.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Link") {
+            continue;
+        }
+        let uri = annot
+            .get(b"A")
+            .ok()
+            .and_then(|action| resolve_dict(doc, action))
+            .and_then(|action| action.get(b"URI").ok())
+            .and_then(|uri| doc.dereference(uri).ok())
+            .and_then(|(_, uri)| uri.as_str().ok())
+            .map(|uri| String::from_utf8_lossy(uri).into_owned());
+        let rect = annot.get(b"Rect").and_then(Object::as_array).ok().map(|r| {
+            r.iter()
+                .filter_map(|v| v.as_float().ok())
+                .collect::<Vec<_>>()
+        });
+        if let (Some(uri), Some([x1, y1, x2, y2])) = (uri, rect.as_deref()) {
+            links.push((uri, [x1.min(*x2), y1.min(*y2), x1.max(*x2), y1.max(*y2)]));
+        }
+    }
+    links
+}
+
+fn resolve_dict<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Dictionary> {
+    doc.dereference(object).ok()?.1.as_dict().ok()
+}
+
+/// A URL printed on a page and the area it covers
+struct FoundUrl {
+    url: String,
+    rect: [f32; 4],
+}
+
+/// Web URLs in a page's text, with the union of their glyph boxes
+fn find_urls(text: &PageText) -> Vec<FoundUrl> {
+    let pattern = Regex::new(r#"(?i)\b(?:https?://|www\.)[^\s<>"']+"#).unwrap();
+    let mut found = Vec::new();
+    for m in pattern.find_iter(&text.text) {
+        let mut url = m.as_str().trim_end_matches(['.', ',', ';', ':', '!', '?']);
+        // A closing parenthesis belongs to the URL only if it opened one
+        while url.ends_with(')') && url.matches('(').count() < url.matches(')').count() {
+            url = &url[..url.len() - 1];
+        }
+        let end = m.start() + url.len();
+        let boxes: Vec<&[f32; 4]> = text
+            .glyphs
+            .iter()
+            .filter(|(offset, _)| (m.start()..end).contains(offset))
+            .map(|(_, rect)| rect)
+            .collect();
+        let Some(first) = boxes.first() else { continue };
+        let rect = boxes.iter().fold(**first, |acc, r| {
+            [
+                acc[0].min(r[0]),
+                acc[1].min(r[1]),
+                acc[2].max(r[2]),
+                acc[3].max(r[3]),
+            ]
+        });
+        let url = if url.to_lowercase().starts_with("www.") {
+            format!("http://{}", url)
+        } else {
+            url.to_string()
+        };
+        found.push(FoundUrl { url, rect });
+    }
+    found
+}
+
+/// Text of a page with the box of every glyph: (byte offset in `text`,
+/// [x1, y1, x2, y2] in user space)
+#[derive(Default)]
+struct PageText {
+    text: String,
+    glyphs: Vec<(usize, [f32; 4])>,
+}
+
+impl PageText {
+    /// Append a glyph, separating it from the previous one by a space or a
+    /// line break when it does not follow on directly
+    fn push(&mut self, text: &str, rect: [f32; 4]) {
+        if let Some((_, last)) = self.glyphs.last() {
+            let height = (last[3] - last[1]).min(rect[3] - rect[1]).max(0.1);
+            let overlap = last[3].min(rect[3]) - last[1].max(rect[1]);
+            let gap = rect[0] - last[2];
+            if overlap < height / 2.0 || gap < -height {
+                self.text.push('\n');
+            } else if gap > height * 0.2 {
+                self.text.push(' ');
+            }
+        }
+        self.glyphs.push((self.text.len(), rect));
+        self.text.push_str(text);
+    }
+}
+
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// `m` then `n`, as PDF concatenates matrices
+fn multiply(m: &Matrix, n: &Matrix) -> Matrix {
+    [
+        m[0] * n[0] + m[1] * n[2],
+        m[0] * n[1] + m[1] * n[3],
+        m[2] * n[0] + m[3] * n[2],
+        m[2] * n[1] + m[3] * n[3],
+        m[4] * n[0] + m[5] * n[2] + n[4],
+        m[4] * n[1] + m[5] * n[3] + n[5],
+    ]
+}
+
+fn transform(m: &Matrix, x: f32, y: f32) -> (f32, f32) {
+    (x * m[0] + y * m[2] + m[4], x * m[1] + y * m[3] + m[5])
+}
+
+/// What a text-showing operator needs to know about a font
+struct Font<'a> {
+    encoding: Option<Encoding<'a>>,
+    /// Composite fonts use two-byte codes
+    two_byte: bool,
+    widths: HashMap<u32, f32>,
+    default_width: f32,
+}
+
+impl<'a> Font<'a> {
+    fn new(doc: &'a Document, dict: &'a Dictionary) -> Self {
+        let encoding = dict.get_font_encoding(doc).ok();
+        let number = |o: &Object| doc.dereference(o).ok().and_then(|(_, o)| o.as_float().ok());
+        let array = |o: &'a Object| doc.dereference(o).ok().and_then(|(_, o)| o.as_array().ok());
+        let mut widths = HashMap::new();
+
+        if dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Type0") {
+            let descendant = dict
+                .get(b"DescendantFonts")
+                .ok()
+                .and_then(array)
+                .and_then(|fonts| fonts.first())
+                .and_then(|font| resolve_dict(doc, font));
+            let default_width = descendant
+                .and_then(|d| d.get(b"DW").ok())
+                .and_then(number)
+                .unwrap_or(1000.0);
+            // W is a list of `first [w1 w2 ...]` and `first last w` entries
+            let w = descendant.and_then(|d| d.get(b"W").ok()).and_then(array);
+            let mut entries = w.into_iter().flatten().peekable();
+            while let Some(first) = entries.next().and_then(number) {
+                let first = first as u32;
+                match entries.next().map(|o| doc.dereference(o).map(|(_, o)| o)) {
+                    Some(Ok(Object::Array(list))) => {
+                        for (i, width) in list.iter().enumerate() {
+                            if let Some(width) = number(width) {
+                                widths.insert(first + i as u32, width);
+                            }
+                        }
+                    }
+                    Some(Ok(last)) => {
+                        let (Some(last), Some(width)) =
+                            (last.as_float().ok(), entries.next().and_then(number))
+                        else {
+                            break;
+                        };
+                        for code in first..=(last as u32).min(first + 0xFFFF) {
+                            widths.insert(code, width);
+                        }
+                    }
+                    _ => break,
+                }
+            }
+            return Self {
+                encoding,
+                two_byte: true,
+                widths,
+                default_width,
+            };
+        }
+
+        let first_char = dict.get(b"FirstChar").ok().and_then(number).unwrap_or(0.0) as u32;
+        let list = dict.get(b"Widths").ok().and_then(array);
+        for (i, width) in list.into_iter().flatten().enumerate() {
+            if let Some(width) = number(width) {
+                widths.insert(first_char + i as u32, width);
+            }
+        }
+        let default_width = dict
+            .get(b"FontDescriptor")
+            .ok()
+            .and_then(|d| resolve_dict(doc, d))
+            .and_then(|d| d.get(b"MissingWidth").ok())
+            .and_then(number)
+            .filter(|w| *w > 0.0)
+            .unwrap_or(DEFAULT_WIDTH);
+        Self {
+            encoding,
+            two_byte: false,
+            widths,
+            default_width,
+        }
+    }
+
+    /// Split a string operand into (code, width in thousandths, text)
+    fn glyphs(&self, bytes: &[u8]) -> Vec<(u32, f32, String)> {
+        let size = if self.two_byte { 2 } else { 1 };
+        bytes
+            .chunks(size)
+            .map(|chunk| {
+                let code = chunk.iter().fold(0u32, |code, b| code << 8 | *b as u32);
+                let text = self
+                    .encoding
+                    .as_ref()
+                    .and_then(|e| e.bytes_to_string(chunk).ok())
+                    .unwrap_or_else(|| {
+                        if self.two_byte {
+                            "\u{FFFD}".to_string()
+                        } else {
+                            (chunk[0] as char).to_string()
+                        }
+                    });
+                let width = self
+                    .widths
+                    .get(&code)
+                    .copied()
+                    .unwrap_or(self.default_width);
+                (code, width, text)
+            })
+            .collect()
+    }
+}
+
+/// Graphics and text state that q/Q save and restore
+#[derive(Clone)]
+struct State {
+    ctm: Matrix,
+    font: Option<Vec<u8>>,
+    size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    scale: f32,
+    leading: f32,
+    rise: f32,
+}
+
+fn page_text(doc: &Document, page_id: ObjectId) -> PageText {
+    let mut text = PageText::default();
+    let resources = inherited(doc, page_id, b"Resources").and_then(|r| resolve_dict(doc, r));
+    let Ok(content) = doc.get_page_content(page_id) else {
+        return text;
+    };
+    let state = State {
+        ctm: IDENTITY,
+        font: None,
+        size: 0.0,
+        char_spacing: 0.0,
+        word_spacing: 0.0,
+        scale: 1.0,
+        leading: 0.0,
+        rise: 0.0,
+    };
+    read_content(doc, &content, resources, state, 0, &mut text);
+    text
+}
+
+/// Follow the text operators of a content stream, recording every glyph
+fn read_content(
+    doc: &Document,
+    content: &[u8],
+    resources: Option<&Dictionary>,
+    mut state: State,
+    depth: usize,
+    text: &mut PageText,
+) {
+    let Ok(content) = Content::decode(content) else {
+        return;
+    };
+    let fonts: BTreeMap<Vec<u8>, Font> = resources
+        .and_then(|r| r.get(b"Font").ok())
+        .and_then(|f| resolve_dict(doc, f))
+        .into_iter()
+        .flat_map(|fonts| fonts.iter())
+        .filter_map(|(name, font)| Some((name.clone(), Font::new(doc, resolve_dict(doc, font)?))))
+        .collect();
+
+    let mut stack = Vec::new();
+    let mut tm = IDENTITY;
+    let mut tlm = IDENTITY;
+    for op in &content.operations {
+        let num = |i: usize| {
+            op.operands
+                .get(i)
+                .and_then(|o| o.as_float().ok())
+                .unwrap_or(0.0)
+        };
+        let matrix = || -> Matrix { [num(0), num(1), num(2), num(3), num(4), num(5)] };
+        match op.operator.as_str() {
+            "q" => stack.push(state.clone()),
+            "Q" => state = stack.pop().unwrap_or(state),
+            "cm" => state.ctm = multiply(&matrix(), &state.ctm),
+            "BT" => {
+                tm = IDENTITY;
+                tlm = IDENTITY;
+            }
+            "Tf" => {
+                state.font = op
+                    .operands
+                    .first()
+                    .and_then(|o| o.as_name().ok())
+                    .map(<[u8]>::to_vec);
+                state.size = num(1);
+            }
+            "Tc" => state.char_spacing = num(0),
+            "Tw" => state.word_spacing = num(0),
+            "Tz" => state.scale = num(0) / 100.0,
+            "TL" => state.leading = num(0),
+            "Ts" => state.rise = num(0),
+            "Td" | "TD" => {
+                if op.operator == "TD" {
+                    state.leading = -num(1);
+                }
+                tlm = multiply(&[1.0, 0.0, 0.0, 1.0, num(0), num(1)], &tlm);
+                tm = tlm;
+            }
+            "Tm" => {
+                tlm = matrix();
+                tm = tlm;
+            }
+            "T*" | "'" | "\"" => {
+                if op.operator == "\"" {
+                    state.word_spacing = num(0);
+                    state.char_spacing = num(1);
+                }
+                tlm = multiply(&[1.0, 0.0, 0.0, 1.0, 0.0, -state.leading], &tlm);
+                tm = tlm;
+                if let Some(Object::String(bytes, _)) = op.operands.last() {
+                    show(bytes, &state, &fonts, &mut tm, text);
+                }
+            }
+            "Tj" => {
+                if let Some(Object::String(bytes, _)) = op.operands.first() {
+                    show(bytes, &state, &fonts, &mut tm, text);
+                }
+            }
+            "TJ" => {
+                let items = op.operands.first().and_then(|o| o.as_array().ok());
+                for item in items.into_iter().flatten() {
+                    match item {
+                        Object::String(bytes, _) => show(bytes, &state, &fonts, &mut tm, text),
+                        item => {
+                            let adjust = item.as_float().unwrap_or(0.0);
+                            let tx = -adjust / 1000.0 * state.size * state.scale;
+                            tm = multiply(&[1.0, 0.0, 0.0, 1.0, tx, 0.0], &tm);
+                        }
+                    }
+                }
+            }
+            "Do" if depth < MAX_XOBJECT_DEPTH => {
+                let xobject = op
+                    .operands
+                    .first()
+                    .and_then(|o| o.as_name().ok())
+                    .and_then(|name| {
+                        let xobjects = resolve_dict(doc, resources?.get(b"XObject").ok()?)?;
+                        doc.dereference(xobjects.get(name).ok()?)
+                            .ok()?
+                            .1
+                            .as_stream()
+                            .ok()
+                    });
+                let Some(xobject) = xobject else { continue };
+                if xobject.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Form") {
+                    continue;
+                }
+                let content = xobject
+                    .decompressed_content()
+                    .unwrap_or_else(|_| xobject.content.clone());
+                let form_matrix = xobject
+                    .dict
+                    .get(b"Matrix")
+                    .and_then(Object::as_array)
+                    .ok()
+                    .map(|m| {
+                        m.iter()
+                            .filter_map(|v| v.as_float().ok())
+                            .collect::<Vec<_>>()
+                    })
+                    .and_then(|m| <Matrix>::try_from(m.as_slice()).ok())
+                    .unwrap_or(IDENTITY);
+                let form_resources = xobject
+                    .dict
+                    .get(b"Resources")
+                    .ok()
+                    .and_then(|r| resolve_dict(doc, r))
+                    .or(resources);
+                let mut form_state = state.clone();
+                form_state.ctm = multiply(&form_matrix, &state.ctm);
+                read_content(doc, &content, form_resources, form_state, depth + 1, text);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Record the glyphs of a string operand and advance the text matrix
+fn show(
+    bytes: &[u8],
+    state: &State,
+    fonts: &BTreeMap<Vec<u8>, Font>,
+    tm: &mut Matrix,
+    text: &mut PageText,
+) {
+    let Some(font) = state.font.as_ref().and_then(|name| fonts.get(name)) else {
+        return;
+    };
+    for (code, width, glyph) in font.glyphs(bytes) {
+        let advance = width / 1000.0 * state.size * state.scale;
+        let to_user = multiply(tm, &state.ctm);
+        // The box spans a typical descender to ascender height
+        let (bottom, top) = (state.rise - 0.2 * state.size, state.rise + 0.8 * state.size);
+        let corners = [(0.0, bottom), (advance, bottom), (0.0, top), (advance, top)]
+            .map(|(x, y)| transform(&to_user, x, y));
+        let rect = corners
+            .iter()
+            .fold([f32::MAX, f32::MAX, f32::MIN, f32::MIN], |r, &(x, y)| {
+                [r[0].min(x), r[1].min(y), r[2].max(x), r[3].max(y)]
+            });
+        if rect.iter().all(|v| v.is_finite()) {
+            text.push(&glyph, rect);
+        }
+
+        let word_spacing = if !font.two_byte && code == 32 {
+            state.word_spacing
+        } else {
+            0.0
+        };
+        let tx = advance + (state.char_spacing + word_spacing) * state.scale;
+        *tm = multiply(&[1.0, 0.0, 0.0, 1.0, tx, 0.0], tm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Stream};
+
+    /// A one-page PDF printing `lines` in Helvetica, one line each, with a
+    /// link annotation over the first line
+    fn text_pdf(lines: &[&str], link: &str) -> Vec<u8> {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        });
+        let mut content = String::from("BT /F1 10 Tf 12 TL 72 700 Td ");
+        for line in lines {
+            content.push_str(&format!("({}) Tj T* ", line));
+        }
+        content.push_str("ET");
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.into_bytes()));
+        let annot_id = doc.add_object(dictionary! {
+            "Type" => "Annot", "Subtype" => "Link",
+            "Rect" => vec![72.into(), 698.into(), 300.into(), 710.into()],
+            "A" => dictionary! { "S" => "URI", "URI" => Object::string_literal(link) },
+        });
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page", "Parent" => pages_id, "Contents" => content_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+            "Annots" => vec![annot_id.into()],
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(
+                dictionary! { "Type" => "Pages", "Kids" => vec![page_id.into()], "Count" => 1 },
+            ),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        let mut data = Vec::new();
+        doc.save_to(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_repair_links() {
+        let data = text_pdf(
+            &[
+                "Home: https://example.com/",
+                "Docs (see www.example.org/docs).",
+            ],
+            "https://EXAMPLE.com",
+        );
+        let source = vec![
+            "https://example.com".to_string(),
+            "http://www.example.org/docs".to_string(),
+            "https://lost.example.net".to_string(),
+        ];
+        let (repaired, report) = repair_links(data, &source).unwrap();
+        assert_eq!(report.source, 3);
+        assert_eq!(report.preserved, 1);
+        assert_eq!(report.repaired, 1);
+        assert_eq!(report.missing, vec!["https://lost.example.net"]);
+
+        let doc = Document::load_mem(&repaired).unwrap();
+        let page_id = doc.get_pages()[&1];
+        let links = page_links(&doc, page_id);
+        assert_eq!(links.len(), 2);
+        let (uri, rect) = &links[1];
+        assert_eq!(uri, "http://www.example.org/docs");
+        // On the second line, after "Docs (see "
+        assert!(rect[1] < 698.0 && rect[0] > 100.0, "{:?}", rect);
+
+        // Nothing to repair leaves the document untouched
+        let (again, report) = repair_links(repaired.clone(), &[]).unwrap();
+        assert_eq!(report.repaired, 0);
+        assert_eq!(again, repaired);
+    }
+}
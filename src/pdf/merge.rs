@@ -0,0 +1,83 @@
+use super::{inherited, load, save};
+use crate::error::{AppError, Result};
+use lopdf::{dictionary, Document, Object};
+
+/// Page attributes that may be inherited from the page tree and must be
+/// copied onto each page before it moves to a new tree
+const INHERITABLE_KEYS: &[&[u8]] = &[b"MediaBox", b"CropBox", b"Resources", b"Rotate"];
+
+/// Concatenate PDFs, in order, into a single document
+pub fn merge(documents: &[Vec<u8>]) -> Result<Vec<u8>> {
+    if documents.is_empty() {
+        return Err(AppError::InvalidRequest("No PDFs to merge".to_string()));
+    }
+
+    let mut merged = Document::with_version("1.7");
+    let mut kids = Vec::new();
+
+    for data in documents {
+        let mut doc = load(data)?;
+        doc.renumber_objects_with(merged.max_id + 1);
+        merged.max_id = doc.max_id;
+
+        for page_id in doc.get_pages().into_values() {
+            let inherited_values: Vec<(&[u8], Object)> = INHERITABLE_KEYS
+                .iter()
+                .filter_map(|key| Some((*key, inherited(&doc, page_id, key)?.clone())))
+                .collect();
+            if let Ok(page) = doc.get_dictionary_mut(page_id) {
+                for (key, value) in inherited_values {
+                    if !page.has(key) {
+                        page.set(key, value);
+                    }
+                }
+            }
+            kids.push(page_id);
+        }
+        merged.objects.extend(doc.objects);
+    }
+
+    let pages_id = merged.new_object_id();
+    for &page_id in &kids {
+        if let Ok(page) = merged.get_dictionary_mut(page_id) {
+            page.set("Parent", pages_id);
+        }
+    }
+    let count = kids.len() as i64;
+    merged.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids.into_iter().map(Object::Reference).collect::<Vec<_>>(),
+            "Count" => count,
+        }),
+    );
+    let catalog_id = merged.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    merged.trailer.set("Root", catalog_id);
+
+    save(&mut merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sample_pdf;
+    use super::*;
+
+    #[test]
+    fn test_merge_page_order() {
+        let merged = merge(&[sample_pdf(2), sample_pdf(3)]).unwrap();
+        let doc = load(&merged).unwrap();
+        let pages = doc.get_pages();
+        assert_eq!(pages.len(), 5);
+
+        // Every page keeps its MediaBox in the new page tree
+        for page_id in pages.into_values() {
+            let page = doc.get_dictionary(page_id).unwrap();
+            assert!(page.has(b"MediaBox"));
+        }
+        assert!(merge(&[]).is_err());
+    }
+}
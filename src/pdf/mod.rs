@@ -0,0 +1,156 @@
+//! PDF post-processing applied to engine output (built on lopdf)
+
+mod annotate;
+mod attach;
+mod drawing;
+mod forms;
+mod handout;
+mod info;
+mod links;
+mod merge;
+mod outline;
+mod security;
+mod watermark;
+
+pub use annotate::{annotate, Annotation, MAX_ANNOTATIONS};
+pub use attach::{attach_file, EmbeddedFile};
+pub use drawing::{place_drawing, Sheet};
+pub use forms::form_fields;
+pub use handout::impose_handout;
+pub use info::set_info;
+pub use links::{repair_links, LinkReport};
+pub use merge::merge;
+pub use outline::{add_outline, Bookmark};
+pub use security::{encrypt, Encryption};
+pub use watermark::{watermark, Watermark};
+
+use crate::error::{AppError, Result};
+#[cfg(test)]
+use lopdf::{dictionary, Dictionary, Stream};
+use lopdf::{Document, Object, ObjectId};
+
+/// Parse PDF bytes produced by an engine
+fn load(data: &[u8]) -> Result<Document> {
+    Document::load_mem(data)
+        .map_err(|e| AppError::ConversionFailed(format!("Failed to parse PDF: {}", e)))
+}
+
+/// Parse a PDF uploaded by a client, where a broken file is a bad request
+fn load_input(data: &[u8]) -> Result<Document> {
+    Document::load_mem(data)
+        .map_err(|e| AppError::InvalidRequest(format!("file: not a readable PDF: {}", e)))
+}
+
+/// Number of pages in a PDF
+pub fn page_count(data: &[u8]) -> Result<usize> {
+    Ok(load(data)?.get_pages().len())
+}
+
+/// Names of the fonts a PDF uses, without subset prefixes: "Carlito-Bold"
+/// for "ABCDEF+Carlito-Bold"
+pub fn font_names(data: &[u8]) -> Result<Vec<String>> {
+    let doc = load(data)?;
+    let mut names: Vec<String> = doc
+        .objects
+        .values()
+        .filter_map(|object| object.as_dict().ok())
+        .filter(|dict| dict.has_type(b"Font"))
+        .filter_map(|dict| dict.get(b"BaseFont").and_then(Object::as_name).ok())
+        .map(|name| {
+            let name = String::from_utf8_lossy(name);
+            match name.split_once('+') {
+                Some((tag, base)) if tag.len() == 6 => base.to_string(),
+                _ => name.into_owned(),
+            }
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Serialize a document back to bytes, dropping unreferenced objects
+fn save(doc: &mut Document) -> Result<Vec<u8>> {
+    doc.prune_objects();
+    doc.compress();
+
+    let mut data = Vec::new();
+    doc.save_to(&mut data)
+        .map_err(|e| AppError::ConversionFailed(format!("Failed to write PDF: {}", e)))?;
+    Ok(data)
+}
+
+/// Rewrite a PDF as small as lopdf can: unreferenced objects and empty
+/// streams dropped, streams deflated and objects packed into object streams
+pub fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut doc = load(data)?;
+    doc.prune_objects();
+    doc.delete_zero_length_streams();
+    doc.compress();
+
+    let mut output = Vec::new();
+    doc.save_modern(&mut output)
+        .map_err(|e| AppError::ConversionFailed(format!("Failed to write PDF: {}", e)))?;
+    Ok(output)
+}
+
+/// Look up a page attribute, following the page tree for inheritable keys
+/// such as MediaBox and Resources
+fn inherited<'a>(doc: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    // Bound the walk so malformed parent cycles cannot loop forever
+    for _ in 0..32 {
+        if let Ok(value) = node.get(key) {
+            return Some(value);
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = doc.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+/// Page box as [x0, y0, x1, y1], defaulting to US Letter
+fn media_box(doc: &Document, page_id: ObjectId) -> [f32; 4] {
+    let values: Option<Vec<f32>> = inherited(doc, page_id, b"MediaBox")
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_array().ok())
+        .map(|a| a.iter().filter_map(|v| v.as_float().ok()).collect());
+
+    match values.as_deref() {
+        Some([x0, y0, x1, y1]) => [*x0, *y0, *x1, *y1],
+        _ => [0.0, 0.0, 612.0, 792.0],
+    }
+}
+
+/// Build a PDF with `count` blank 16:9 pages
+#[cfg(test)]
+pub(crate) fn sample_pdf(count: usize) -> Vec<u8> {
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let kids: Vec<Object> = (0..count)
+        .map(|_| {
+            let content_id = doc.add_object(Stream::new(Dictionary::new(), b"0 0 m".to_vec()));
+            let page = dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), 960.into(), 540.into()],
+                "Contents" => content_id,
+            };
+            Object::Reference(doc.add_object(page))
+        })
+        .collect();
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => count as i64,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    doc.trailer.set("Root", catalog_id);
+
+    let mut data = Vec::new();
+    doc.save_to(&mut data).unwrap();
+    data
+}
@@ -0,0 +1,215 @@
+//! The document outline (ISO 32000 12.3.3), shown by viewers as bookmarks,
+//! built from the headings of a browser-rendered document. Chromium writes
+//! a named destination for every element a link in the page points to, so
+//! each bookmark jumps to where its heading was printed.
+
+use super::annotate::text_object;
+use super::attach::{collect_entries, names_dictionary};
+use super::{load, save};
+use crate::error::{AppError, Result};
+use lopdf::{dictionary, Document, Object, ObjectId};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// A heading to bookmark
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Bookmark {
+    /// 1 for `<h1>`…; deeper levels nest under the bookmark before them
+    pub level: u8,
+    pub title: String,
+    /// Named destination of the heading: its element id
+    pub destination: String,
+}
+
+/// A bookmark with its resolved destination, and those nested under it
+struct Entry {
+    level: u8,
+    title: String,
+    destination: Object,
+    children: Vec<Entry>,
+}
+
+/// Replace the PDF's outline with `bookmarks`, nested by level with the
+/// top level expanded. Returns the PDF and how many bookmarks were left
+/// out because their destination is not in it (e.g. on a page not
+/// printed); the PDF is returned as it was when none is found.
+pub fn add_outline(data: &[u8], bookmarks: &[Bookmark]) -> Result<(Vec<u8>, usize)> {
+    let mut doc = load(data)?;
+    let destinations = named_destinations(&doc);
+    let mut entries = Vec::new();
+    let mut missing = 0;
+    for bookmark in bookmarks {
+        match destinations.get(bookmark.destination.as_bytes()) {
+            Some(destination) => nest(
+                &mut entries,
+                Entry {
+                    level: bookmark.level,
+                    title: bookmark.title.clone(),
+                    destination: destination.clone(),
+                    children: Vec::new(),
+                },
+            ),
+            None => missing += 1,
+        }
+    }
+    if entries.is_empty() {
+        return Ok((data.to_vec(), missing));
+    }
+
+    let outlines_id = doc.new_object_id();
+    let count = entries.len() as i64;
+    let (first, last) = write_entries(&mut doc, outlines_id, entries);
+    doc.objects.insert(
+        outlines_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Outlines",
+            "First" => first,
+            "Last" => last,
+            "Count" => count,
+        }),
+    );
+    let catalog = doc
+        .catalog_mut()
+        .map_err(|e| AppError::ConversionFailed(format!("Invalid PDF catalog: {}", e)))?;
+    catalog.set("Outlines", outlines_id);
+    catalog.set("PageMode", "UseOutlines");
+    Ok((save(&mut doc)?, missing))
+}
+
+/// Add `entry` under the last entry of a lower level, or after it
+fn nest(entries: &mut Vec<Entry>, entry: Entry) {
+    match entries.last_mut() {
+        Some(last) if last.level < entry.level => nest(&mut last.children, entry),
+        _ => entries.push(entry),
+    }
+}
+
+/// Write sibling outline items under `parent`; returns the first and last
+fn write_entries(
+    doc: &mut Document,
+    parent: ObjectId,
+    entries: Vec<Entry>,
+) -> (ObjectId, ObjectId) {
+    let ids: Vec<ObjectId> = entries.iter().map(|_| doc.new_object_id()).collect();
+    for (i, entry) in entries.into_iter().enumerate() {
+        let mut item = dictionary! {
+            "Title" => text_object(&entry.title),
+            "Parent" => parent,
+            "Dest" => entry.destination,
+        };
+        if i > 0 {
+            item.set("Prev", ids[i - 1]);
+        }
+        if let Some(next) = ids.get(i + 1) {
+            item.set("Next", *next);
+        }
+        if !entry.children.is_empty() {
+            // Negative: collapsed, below the expanded top level
+            let count = entry.children.len() as i64;
+            let (first, last) = write_entries(doc, ids[i], entry.children);
+            item.set("First", first);
+            item.set("Last", last);
+            item.set("Count", -count);
+        }
+        doc.objects.insert(ids[i], Object::Dictionary(item));
+    }
+    (ids[0], *ids.last().expect("at least one entry"))
+}
+
+/// Explicit destinations by name, from the catalog's `Dests` dictionary
+/// (where Chromium writes them) and the `Dests` name tree
+fn named_destinations(doc: &Document) -> HashMap<Vec<u8>, Object> {
+    let mut entries = Vec::new();
+    let root = names_dictionary(doc).and_then(|names| names.get(b"Dests").ok().cloned());
+    if let Some(root) = root {
+        collect_entries(doc, &root, 0, &mut entries);
+    }
+    let dests = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Dests").ok())
+        .and_then(|dests| doc.dereference(dests).ok())
+        .and_then(|(_, dests)| dests.as_dict().ok());
+    if let Some(dests) = dests {
+        entries.extend(
+            dests
+                .iter()
+                .map(|(name, dest)| (name.clone(), dest.clone())),
+        );
+    }
+
+    entries
+        .into_iter()
+        .filter_map(|(name, dest)| {
+            let (_, dest) = doc.dereference(&dest).ok()?;
+            // Either the destination array or a dictionary holding it
+            let dest = match dest {
+                Object::Dictionary(dict) => doc.dereference(dict.get(b"D").ok()?).ok()?.1,
+                dest => dest,
+            };
+            dest.as_array().ok()?;
+            Some((name, dest.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::forms::text_string;
+    use super::super::sample_pdf;
+    use super::*;
+    use lopdf::Dictionary;
+
+    #[test]
+    fn test_add_outline() {
+        // Named destinations as Chromium writes them
+        let mut doc = load(&sample_pdf(2)).unwrap();
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let at = |page: ObjectId| -> Object {
+            vec![page.into(), "XYZ".into(), 0.into(), 500.into(), 0.into()].into()
+        };
+        let dests = dictionary! { "intro" => at(pages[0]), "setup" => at(pages[1]) };
+        doc.catalog_mut()
+            .unwrap()
+            .set("Dests", Object::Dictionary(dests));
+        let data = save(&mut doc).unwrap();
+
+        let bookmark = |level, title: &str, destination: &str| Bookmark {
+            level,
+            title: title.to_string(),
+            destination: destination.to_string(),
+        };
+        let bookmarks = [
+            bookmark(1, "Intro", "intro"),
+            bookmark(3, "Gone", "gone"),
+            bookmark(2, "Setup", "setup"),
+        ];
+        let (data, missing) = add_outline(&data, &bookmarks).unwrap();
+        assert_eq!(missing, 1);
+
+        let doc = load(&data).unwrap();
+        let catalog = doc.catalog().unwrap();
+        let outlines = catalog
+            .get(b"Outlines")
+            .and_then(Object::as_reference)
+            .unwrap();
+        let outlines = doc.get_dictionary(outlines).unwrap();
+        assert_eq!(outlines.get(b"Count").and_then(Object::as_i64).unwrap(), 1);
+        let item = |dict: &Dictionary, key: &[u8]| {
+            doc.get_dictionary(dict.get(key).and_then(Object::as_reference).unwrap())
+                .unwrap()
+        };
+        let title = |dict: &Dictionary| text_string(dict.get(b"Title").unwrap().as_str().unwrap());
+        let intro = item(outlines, b"First");
+        assert_eq!(title(intro), "Intro");
+        assert_eq!(intro.get(b"Count").and_then(Object::as_i64).unwrap(), -1);
+        let setup = item(intro, b"First");
+        assert_eq!(title(setup), "Setup");
+        let page = setup.get(b"Dest").and_then(Object::as_array).unwrap()[0].as_reference();
+        assert_eq!(page.unwrap(), pages[1]);
+
+        // Nothing to point to leaves the PDF as it was
+        let (unchanged, missing) = add_outline(&data, &[bookmark(1, "Gone", "gone")]).unwrap();
+        assert_eq!((unchanged, missing), (data, 1));
+    }
+}
@@ -0,0 +1,150 @@
+//! Password protection with AES-256 (PDF 2.0 standard security handler)
+
+use super::load;
+use crate::error::{AppError, Result};
+use lopdf::encryption::crypt_filters::{Aes256CryptFilter, CryptFilter};
+use lopdf::xref::XrefType;
+use lopdf::{EncryptionState, EncryptionVersion, Permissions};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+fn allowed() -> bool {
+    true
+}
+
+/// Passwords and what readers may do without the owner password
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Encryption {
+    /// Needed to open the document; empty opens without a prompt
+    #[serde(default)]
+    pub user_password: String,
+    /// Lifts the restrictions; random when not given
+    #[serde(default)]
+    pub owner_password: Option<String>,
+    #[serde(default = "allowed")]
+    pub allow_print: bool,
+    #[serde(default = "allowed")]
+    pub allow_copy: bool,
+    #[serde(default = "allowed")]
+    pub allow_modify: bool,
+}
+
+impl Encryption {
+    /// Check the values, naming `field` in errors
+    pub fn validate(&self, field: &str) -> Result<()> {
+        let restricted = !(self.allow_print && self.allow_copy && self.allow_modify);
+        if self.user_password.is_empty() && !restricted {
+            return Err(AppError::InvalidRequest(format!(
+                "{}: set a userPassword or restrict a permission",
+                field
+            )));
+        }
+        let too_long = |password: &str| password.len() > 127;
+        if too_long(&self.user_password) || self.owner_password.as_deref().is_some_and(too_long) {
+            return Err(AppError::InvalidRequest(format!(
+                "{}: passwords are limited to 127 bytes",
+                field
+            )));
+        }
+        Ok(())
+    }
+
+    fn permissions(&self) -> Permissions {
+        let mut permissions = Permissions::COPYABLE_FOR_ACCESSIBILITY | Permissions::FILLABLE;
+        if self.allow_print {
+            permissions |= Permissions::PRINTABLE | Permissions::PRINTABLE_IN_HIGH_QUALITY;
+        }
+        if self.allow_copy {
+            permissions |= Permissions::COPYABLE;
+        }
+        if self.allow_modify {
+            permissions |=
+                Permissions::MODIFIABLE | Permissions::ANNOTABLE | Permissions::ASSEMBLABLE;
+        }
+        permissions
+    }
+}
+
+/// Encrypt a PDF with checked settings. Streams are compressed first, as
+/// filters apply to the plain data.
+pub fn encrypt(data: &[u8], encryption: &Encryption) -> Result<Vec<u8>> {
+    let mut doc = load(data)?;
+    if doc.is_encrypted() {
+        return Err(AppError::InvalidRequest(
+            "file: the PDF is already encrypted".to_string(),
+        ));
+    }
+    doc.prune_objects();
+    // lopdf's table writer mislabels entries after gaps in the numbering
+    doc.renumber_objects();
+    doc.compress();
+
+    let random = |len: usize| -> Result<Vec<u8>> {
+        let mut bytes = vec![0; len];
+        openssl::rand::rand_bytes(&mut bytes)
+            .map_err(|e| AppError::Internal(format!("No random bytes for encryption: {}", e)))?;
+        Ok(bytes)
+    };
+    let file_key = random(32)?;
+    let owner_password = match &encryption.owner_password {
+        Some(password) => password.clone(),
+        None => random(24)?.iter().map(|b| format!("{:02x}", b)).collect(),
+    };
+
+    let filter: Arc<dyn CryptFilter> = Arc::new(Aes256CryptFilter);
+    let version = EncryptionVersion::V5 {
+        encrypt_metadata: true,
+        crypt_filters: BTreeMap::from([(b"StdCF".to_vec(), filter)]),
+        file_encryption_key: &file_key,
+        stream_filter: b"StdCF".to_vec(),
+        string_filter: b"StdCF".to_vec(),
+        owner_password: &owner_password,
+        user_password: &encryption.user_password,
+        permissions: encryption.permissions(),
+    };
+    let failed =
+        |e: lopdf::Error| AppError::ConversionFailed(format!("Failed to encrypt PDF: {}", e));
+    let state = EncryptionState::try_from(version).map_err(failed)?;
+    doc.encrypt(&state).map_err(failed)?;
+    // A cross-reference stream would go through the stream filter as well
+    // and become unreadable, so write a classic table
+    doc.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
+    doc.trailer.remove(b"Type");
+
+    let mut output = Vec::new();
+    doc.save_to(&mut output)
+        .map_err(|e| AppError::ConversionFailed(format!("Failed to write PDF: {}", e)))?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::sample_pdf;
+    use lopdf::Document;
+
+    #[test]
+    fn test_encrypt() {
+        let settings: Encryption =
+            serde_json::from_str(r#"{"userPassword": "open sesame", "allowCopy": false}"#).unwrap();
+        settings.validate("encrypt").unwrap();
+        let data = encrypt(&sample_pdf(2), &settings).unwrap();
+        let doc = Document::load_mem(&data).unwrap();
+        assert!(doc.is_encrypted());
+        doc.authenticate_password("open sesame").unwrap();
+        assert!(doc.authenticate_password("guess").is_err());
+
+        // Without a user password readers open the file directly
+        let settings: Encryption = serde_json::from_str(r#"{"allowModify": false}"#).unwrap();
+        settings.validate("encrypt").unwrap();
+        let data = encrypt(&sample_pdf(2), &settings).unwrap();
+        let doc = Document::load_mem(&data).unwrap();
+        assert!(doc.is_encrypted());
+        assert_eq!(doc.get_pages().len(), 2);
+
+        let open: Encryption = serde_json::from_str("{}").unwrap();
+        assert!(open.validate("encrypt").is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! Text watermarks drawn across every page, e.g. "DRAFT" or
+//! "CONFIDENTIAL"
+
+use super::handout::page_to_xobject;
+use super::{load, save};
+use crate::error::{AppError, Result};
+use lopdf::{dictionary, Dictionary, Stream};
+use serde::Deserialize;
+
+/// Helvetica advance widths (per 1000 em) of the printable ASCII range;
+/// other Latin-1 characters are measured as 556
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Longest watermark text accepted
+const MAX_TEXT: usize = 200;
+
+fn default_opacity() -> f32 {
+    0.15
+}
+
+fn default_font_size() -> f32 {
+    72.0
+}
+
+fn default_angle() -> f32 {
+    45.0
+}
+
+fn default_color() -> [f32; 3] {
+    [0.5, 0.5, 0.5]
+}
+
+/// A watermark: `text` centered on each page, rotated by `angle` degrees
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Watermark {
+    pub text: String,
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    #[serde(default = "default_font_size")]
+    pub font_size: f32,
+    #[serde(default = "default_angle")]
+    pub angle: f32,
+    #[serde(default = "default_color")]
+    pub color: [f32; 3],
+}
+
+impl Watermark {
+    /// Check the values, naming `field` in errors
+    pub fn validate(&self, field: &str) -> Result<()> {
+        let invalid =
+            |message: &str| Err(AppError::InvalidRequest(format!("{}: {}", field, message)));
+        if self.text.trim().is_empty() || self.text.chars().count() > MAX_TEXT {
+            return invalid(&format!("text must be 1 to {} characters", MAX_TEXT));
+        }
+        if self.text.chars().any(|c| c as u32 > 0xFF || c.is_control()) {
+            return invalid("text must use Latin-1 characters");
+        }
+        if !(0.0..=1.0).contains(&self.opacity) {
+            return invalid("opacity must be between 0 and 1");
+        }
+        if !(1.0..=500.0).contains(&self.font_size) {
+            return invalid("fontSize must be between 1 and 500");
+        }
+        if !self.angle.is_finite() {
+            return invalid("angle must be a number");
+        }
+        if self.color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+            return invalid("color components must be between 0 and 1");
+        }
+        Ok(())
+    }
+
+    /// Width of the text in points
+    fn width(&self) -> f32 {
+        let units: u32 = self
+            .text
+            .chars()
+            .map(|c| match c as u32 {
+                code @ 32..=126 => HELVETICA_WIDTHS[(code - 32) as usize] as u32,
+                _ => 556,
+            })
+            .sum();
+        units as f32 * self.font_size / 1000.0
+    }
+}
+
+/// Draw a checked watermark over every page. Each page's content becomes a
+/// form XObject, so the page gets fresh resources for the watermark.
+pub fn watermark(data: &[u8], watermark: &Watermark) -> Result<Vec<u8>> {
+    let mut doc = load(data)?;
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let state_id = doc.add_object(dictionary! {
+        "Type" => "ExtGState",
+        "ca" => watermark.opacity,
+        "CA" => watermark.opacity,
+    });
+    // Latin-1 and WinAnsi agree outside 0x80-0x9F, which holds control
+    // characters in Latin-1 and is rejected by the check above
+    let text: Vec<u8> = watermark.text.chars().map(|c| c as u8).collect();
+
+    for page_id in doc.get_pages().into_values().collect::<Vec<_>>() {
+        let (xobject_id, bbox) = page_to_xobject(&mut doc, page_id)?;
+        let (angle_sin, angle_cos) = watermark.angle.to_radians().sin_cos();
+        // Start of the baseline so the text's center lands on the page's
+        // center: rotate (width / 2, x-height / 2) and step back by it
+        let (half_width, half_height) = (watermark.width() / 2.0, watermark.font_size * 0.35);
+        let center_x = (bbox[0] + bbox[2]) / 2.0;
+        let center_y = (bbox[1] + bbox[3]) / 2.0;
+        let x = center_x - (half_width * angle_cos - half_height * angle_sin);
+        let y = center_y - (half_width * angle_sin + half_height * angle_cos);
+
+        let mut content = b"q /PdfmillPage Do Q\n".to_vec();
+        content.extend(
+            format!(
+                "q /PdfmillWatermark gs {} {} {} rg BT /PdfmillFont {} Tf {} {} {} {} {} {} Tm (",
+                watermark.color[0],
+                watermark.color[1],
+                watermark.color[2],
+                watermark.font_size,
+                angle_cos,
+                angle_sin,
+                -angle_sin,
+                angle_cos,
+                x,
+                y
+            )
+            .into_bytes(),
+        );
+        for &byte in &text {
+            if matches!(byte, b'(' | b')' | b'\\') {
+                content.push(b'\\');
+            }
+            content.push(byte);
+        }
+        content.extend_from_slice(b") Tj ET Q\n");
+
+        let content_id = doc.add_object(Stream::new(Dictionary::new(), content));
+        let resources = dictionary! {
+            "XObject" => dictionary! { "PdfmillPage" => xobject_id },
+            "Font" => dictionary! { "PdfmillFont" => font_id },
+            "ExtGState" => dictionary! { "PdfmillWatermark" => state_id },
+        };
+        let page = doc
+            .get_dictionary_mut(page_id)
+            .map_err(|e| AppError::ConversionFailed(format!("Failed to read page: {}", e)))?;
+        page.set("Contents", content_id);
+        page.set("Resources", resources);
+    }
+    save(&mut doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::sample_pdf;
+    use lopdf::Document;
+
+    #[test]
+    fn test_watermark() {
+        let mark: Watermark = serde_json::from_str(r#"{"text": "DRAFT (v2)"}"#).unwrap();
+        mark.validate("watermark").unwrap();
+        let data = watermark(&sample_pdf(2), &mark).unwrap();
+
+        let doc = Document::load_mem(&data).unwrap();
+        let pages = doc.get_pages();
+        assert_eq!(pages.len(), 2);
+        let content = doc.get_page_content(pages[&2]).unwrap();
+        let content = String::from_utf8_lossy(&content);
+        assert!(content.starts_with("q /PdfmillPage Do Q"));
+        assert!(content.contains("(DRAFT \\(v2\\)) Tj"));
+
+        let invalid: Watermark = serde_json::from_str(r#"{"text": "草稿"}"#).unwrap();
+        assert!(invalid
+            .validate("watermark")
+            .unwrap_err()
+            .to_string()
+            .contains("Latin-1"));
+        assert!(serde_json::from_str::<Watermark>(r#"{"text": "x", "size": 3}"#).is_err());
+    }
+}
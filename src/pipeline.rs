@@ -0,0 +1,185 @@
+//! Chains of steps run on the server in one /convert request, instead of a
+//! round-trip per step:
+//! `pipeline=[{"step": "convert"}, {"step": "watermark", "text": "DRAFT"},
+//! {"step": "compress"}, {"step": "encrypt", "userPassword": "..."}]`
+
+use crate::engines::ConvertResult;
+use crate::error::{AppError, Result};
+use crate::pdf::{self, Annotation, Encryption, Watermark};
+use serde::Deserialize;
+
+/// Form field holding the pipeline as JSON
+pub const PIPELINE_FIELD: &str = "pipeline";
+
+/// Most steps in one pipeline
+const MAX_STEPS: usize = 16;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+enum Step {
+    /// Convert the upload with the request's options; always first
+    Convert,
+    Watermark(Watermark),
+    Annotate {
+        annotations: Vec<Annotation>,
+    },
+    Compress,
+    /// Always last: nothing can read the document afterwards
+    Encrypt(Encryption),
+}
+
+impl Step {
+    fn name(&self) -> &'static str {
+        match self {
+            Step::Convert => "convert",
+            Step::Watermark(_) => "watermark",
+            Step::Annotate { .. } => "annotate",
+            Step::Compress => "compress",
+            Step::Encrypt(_) => "encrypt",
+        }
+    }
+
+    fn apply(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Step::Convert => Ok(data),
+            Step::Watermark(watermark) => pdf::watermark(&data, watermark),
+            Step::Annotate { annotations } => pdf::annotate(&data, annotations),
+            Step::Compress => pdf::compress(&data),
+            Step::Encrypt(encryption) => pdf::encrypt(&data, encryption),
+        }
+    }
+}
+
+/// The steps after conversion, checked before anything is converted
+#[derive(Debug)]
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+impl Pipeline {
+    pub fn parse(json: &str) -> Result<Self> {
+        let invalid =
+            |message: String| AppError::InvalidRequest(format!("{}: {}", PIPELINE_FIELD, message));
+        let steps: Vec<Step> = serde_json::from_str(json).map_err(|e| invalid(e.to_string()))?;
+        if !matches!(steps.first(), Some(Step::Convert)) {
+            return Err(invalid(
+                "the first step must be {\"step\": \"convert\"}".to_string(),
+            ));
+        }
+        if steps.len() > MAX_STEPS {
+            return Err(invalid(format!("at most {} steps", MAX_STEPS)));
+        }
+
+        for (index, step) in steps.iter().enumerate().skip(1) {
+            let field = format!("{}[{}]", PIPELINE_FIELD, index);
+            match step {
+                Step::Convert => {
+                    return Err(invalid(format!("{}: convert may only come first", field)))
+                }
+                Step::Encrypt(encryption) => {
+                    if index + 1 != steps.len() {
+                        return Err(invalid(format!("{}: encrypt must be the last step", field)));
+                    }
+                    encryption.validate(&field)?;
+                }
+                Step::Watermark(watermark) => watermark.validate(&field)?,
+                Step::Annotate { annotations } if annotations.len() > pdf::MAX_ANNOTATIONS => {
+                    return Err(invalid(format!(
+                        "{}: at most {} annotations",
+                        field,
+                        pdf::MAX_ANNOTATIONS
+                    )));
+                }
+                Step::Annotate { .. } | Step::Compress => {}
+            }
+        }
+
+        Ok(Self {
+            steps: steps.into_iter().skip(1).collect(),
+        })
+    }
+
+    /// Whether steps follow the conversion, which then has to produce a PDF
+    pub fn has_steps(&self) -> bool {
+        !self.steps.is_empty()
+    }
+
+    /// Names of all steps, conversion included, e.g. "convert,watermark"
+    pub fn describe(&self) -> String {
+        std::iter::once("convert")
+            .chain(self.steps.iter().map(Step::name))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Run the steps after conversion on a PDF result
+    pub async fn run(&self, mut result: ConvertResult) -> Result<ConvertResult> {
+        if !self.has_steps() {
+            return Ok(result);
+        }
+        if result.content_type != "application/pdf" {
+            return Err(AppError::InvalidRequest(format!(
+                "{}: steps after convert need PDF output",
+                PIPELINE_FIELD
+            )));
+        }
+        let steps = self.steps.clone();
+        let data = std::mem::take(&mut result.data);
+        result.data = tokio::task::spawn_blocking(move || {
+            steps.iter().try_fold(data, |data, step| step.apply(data))
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Pipeline panicked: {}", e)))??;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::sample_pdf;
+
+    #[tokio::test]
+    async fn test_pipeline() {
+        let pipeline = Pipeline::parse(
+            r#"[
+                {"step": "convert"},
+                {"step": "watermark", "text": "DRAFT"},
+                {"step": "annotate", "annotations": [{"type": "note", "page": 1, "rect": [0, 0, 20, 20], "contents": "Check"}]},
+                {"step": "compress"},
+                {"step": "encrypt", "allowPrint": false}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            pipeline.describe(),
+            "convert,watermark,annotate,compress,encrypt"
+        );
+
+        let result = ConvertResult {
+            data: sample_pdf(1),
+            filename: "report.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            warnings: Vec::new(),
+        };
+        let result = pipeline.run(result).await.unwrap();
+        let doc = lopdf::Document::load_mem(&result.data).unwrap();
+        assert!(doc.is_encrypted());
+        let page = doc.get_pages()[&1];
+        let content = doc.get_page_content(page).unwrap();
+        assert!(String::from_utf8_lossy(&content).contains("(DRAFT) Tj"));
+        assert_eq!(doc.get_page_annotations(page).unwrap().len(), 1);
+
+        let invalid = |json: &str| Pipeline::parse(json).unwrap_err().to_string();
+        assert!(invalid(r#"[{"step": "compress"}]"#).contains("first step must be"));
+        assert!(invalid(r#"[{"step": "convert"}, {"step": "ocr"}]"#).contains("unknown variant"));
+        assert!(invalid(
+            r#"[{"step": "convert"}, {"step": "encrypt", "userPassword": "x"}, {"step": "compress"}]"#
+        )
+        .contains("pipeline[1]: encrypt must be the last step"));
+        assert!(invalid(
+            r#"[{"step": "convert"}, {"step": "watermark", "text": "x", "opacity": 2}]"#
+        )
+        .contains("pipeline[1]: opacity"));
+    }
+}
@@ -0,0 +1,129 @@
+//! Named option presets defined by the operator (e.g. "invoice" = A4,
+//! 12mm margins, PDF/A-2b), selected by clients with a single `preset`
+//! field. Fields sent with the request override the preset's values.
+
+use crate::engines::ConvertOptions;
+use crate::error::{AppError, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use tracing::info;
+
+/// Form field and query parameter selecting a preset
+pub const PRESET_FIELD: &str = "preset";
+
+struct Preset {
+    /// The options as written in the presets file
+    definition: Map<String, Value>,
+    options: ConvertOptions,
+}
+
+#[derive(Default)]
+pub struct Presets {
+    presets: BTreeMap<String, Preset>,
+}
+
+impl Presets {
+    /// Load the JSON object of presets (name to options, in the same form
+    /// as schedule options) from `PDFMILL_PRESETS_FILE`; none when unset
+    pub fn from_env() -> std::result::Result<Self, String> {
+        let Some(path) = std::env::var_os("PDFMILL_PRESETS_FILE") else {
+            return Ok(Self::default());
+        };
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("PDFMILL_PRESETS_FILE: cannot read {:?}: {}", path, e))?;
+        let presets = Self::parse(&text).map_err(|e| format!("PDFMILL_PRESETS_FILE: {}", e))?;
+        info!(
+            "Loaded {} option presets: {}",
+            presets.presets.len(),
+            presets
+                .presets
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        Ok(presets)
+    }
+
+    fn parse(text: &str) -> std::result::Result<Self, String> {
+        let definitions: BTreeMap<String, Map<String, Value>> = serde_json::from_str(text)
+            .map_err(|e| format!("expected an object of presets: {}", e))?;
+        let mut presets = BTreeMap::new();
+        for (name, definition) in definitions {
+            if name.trim().is_empty() || name.trim() != name {
+                return Err(format!("invalid preset name '{}'", name));
+            }
+            let options = ConvertOptions::deserialize(Value::Object(definition.clone()))
+                .map_err(|e| format!("preset '{}': {}", name, e))?;
+            presets.insert(
+                name,
+                Preset {
+                    definition,
+                    options,
+                },
+            );
+        }
+        Ok(Self { presets })
+    }
+
+    /// Options of the named preset, or the defaults when none is selected
+    pub fn options(&self, name: Option<&str>) -> Result<ConvertOptions> {
+        let Some(name) = name.map(str::trim).filter(|n| !n.is_empty()) else {
+            return Ok(ConvertOptions::default());
+        };
+        match self.presets.get(name) {
+            Some(preset) => Ok(preset.options.clone()),
+            None => Err(AppError::InvalidRequest(format!(
+                "{}: unknown preset '{}' (available: {})",
+                PRESET_FIELD,
+                name,
+                if self.presets.is_empty() {
+                    "none".to_string()
+                } else {
+                    self.presets.keys().cloned().collect::<Vec<_>>().join(", ")
+                }
+            ))),
+        }
+    }
+
+    /// Every preset with its options as defined
+    pub fn definitions(&self) -> BTreeMap<&str, &Map<String, Value>> {
+        self.presets
+            .iter()
+            .map(|(name, preset)| (name.as_str(), &preset.definition))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        let presets = Presets::parse(
+            r#"{"invoice": {"pageWidth": "210mm", "pageHeight": "297mm",
+                "marginTop": "12mm", "pdfFormat": "PDF/A-2b", "printBackground": true}}"#,
+        )
+        .unwrap();
+
+        let options = presets.options(Some("invoice")).unwrap();
+        assert_eq!(options.page_width.as_deref(), Some("210mm"));
+        assert_eq!(options.pdf_format.as_deref(), Some("PDF/A-2b"));
+        assert!(options.print_background);
+        assert!(presets.options(None).unwrap().page_width.is_none());
+
+        let unknown = presets.options(Some("receipt")).unwrap_err().to_string();
+        assert!(unknown.contains("unknown preset 'receipt' (available: invoice)"));
+
+        let invalid = Presets::parse(r#"{"bad": {"marginTop": "wide"}}"#)
+            .err()
+            .unwrap();
+        assert!(
+            invalid.starts_with("preset 'bad': marginTop"),
+            "{}",
+            invalid
+        );
+    }
+}
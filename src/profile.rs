@@ -0,0 +1,47 @@
+//! Deployment profiles: one setting that adjusts several others for a kind
+//! of host.
+
+use std::sync::OnceLock;
+use tracing::info;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Profile {
+    #[default]
+    Standard,
+    /// For hosts with around 512 MB of memory: Chromium is launched for
+    /// each conversion instead of kept running, one conversion runs at a
+    /// time, uploads are spooled to disk and ImageMagick gets small limits
+    LowMemory,
+}
+
+static ACTIVE: OnceLock<Profile> = OnceLock::new();
+
+impl Profile {
+    /// Configured by `PDFMILL_PROFILE` (`standard` or `lowMemory`)
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("PDFMILL_PROFILE") {
+            Ok(value) => match value.trim().to_lowercase().as_str() {
+                "" | "standard" => Ok(Self::Standard),
+                "lowmemory" | "low-memory" => Ok(Self::LowMemory),
+                _ => Err(format!(
+                    "PDFMILL_PROFILE: expected standard or lowMemory, got '{}'",
+                    value.trim()
+                )),
+            },
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Make this the profile of the process; read by [`low_memory`]
+    pub fn activate(self) {
+        if self == Self::LowMemory {
+            info!("Low-memory profile: per-conversion browser, one conversion at a time");
+        }
+        let _ = ACTIVE.set(self);
+    }
+}
+
+/// Whether the low-memory profile is active
+pub fn low_memory() -> bool {
+    ACTIVE.get() == Some(&Profile::LowMemory)
+}
@@ -0,0 +1,33 @@
+//! What to do when no conversion engine is available at startup. By
+//! default the server still starts and conversions answer 503; orchestrators
+//! that should restart or flag such an instance can make it exit or report
+//! itself unhealthy instead.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum NoEnginePolicy {
+    /// Serve requests anyway
+    #[default]
+    Serve,
+    /// Exit with a nonzero status
+    Exit,
+    /// Keep running with /health answering 503
+    Unready,
+}
+
+impl NoEnginePolicy {
+    /// Configured by `PDFMILL_NO_ENGINE` (`serve`, `exit` or `unready`)
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("PDFMILL_NO_ENGINE") {
+            Ok(value) => match value.trim().to_lowercase().as_str() {
+                "" | "serve" => Ok(Self::Serve),
+                "exit" => Ok(Self::Exit),
+                "unready" => Ok(Self::Unready),
+                _ => Err(format!(
+                    "PDFMILL_NO_ENGINE: expected serve, exit or unready, got '{}'",
+                    value.trim()
+                )),
+            },
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
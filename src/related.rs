@@ -0,0 +1,188 @@
+//! `multipart/related` responses (RFC 2387): a JSON part describing the
+//! conversion, followed by the converted file, so clients get structured
+//! details in the same response instead of a second request or a growing
+//! set of headers. Clients ask for it with `Accept: multipart/related`, see
+//! [`crate::negotiate`].
+
+use crate::engines::ConvertResult;
+use crate::pdf::LinkReport;
+use serde::Serialize;
+
+/// Media type clients put in Accept to get the metadata along with the file
+pub const MULTIPART_RELATED: &str = "multipart/related";
+
+/// Content-ID of the metadata part, the root of the response
+const METADATA_ID: &str = "metadata";
+
+/// Content-ID of the converted file, referenced by the metadata as
+/// `cid:document`
+const DOCUMENT_ID: &str = "document";
+
+#[derive(Debug, Serialize)]
+pub struct Timings {
+    /// Time spent in the engine, queueing included
+    pub conversion_ms: u64,
+    /// Time from the start of the request to the response
+    pub total_ms: u64,
+}
+
+/// Details of a conversion, sent as the first part
+#[derive(Debug, Serialize)]
+pub struct Metadata {
+    pub filename: String,
+    pub content_type: String,
+    pub size: usize,
+    pub sha256: String,
+    /// Engine label (name/version) that produced the file
+    pub engine: String,
+    /// Page count of PDF output, when it can be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<usize>,
+    pub timings: Timings,
+    /// Hyperlink audit, when `linkAudit` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub links: Option<LinkReport>,
+    /// Problems that did not fail the conversion, as in X-PDFMill-Warnings
+    pub warnings: Vec<String>,
+    /// Reference to the part holding the file
+    pub document: String,
+}
+
+impl Metadata {
+    pub fn new(
+        result: &ConvertResult,
+        sha256: String,
+        engine: &str,
+        timings: Timings,
+        links: Option<LinkReport>,
+    ) -> Self {
+        let pages = (result.content_type == "application/pdf")
+            .then(|| crate::pdf::page_count(&result.data).ok())
+            .flatten();
+        Self {
+            filename: result.filename.clone(),
+            content_type: result.content_type.clone(),
+            size: result.data.len(),
+            sha256,
+            engine: engine.to_string(),
+            pages,
+            timings,
+            links,
+            warnings: result.warnings.clone(),
+            document: format!("cid:{}", DOCUMENT_ID),
+        }
+    }
+}
+
+/// Encode the metadata and file as a `multipart/related` body, returning the
+/// body and its Content-Type
+pub fn encode(metadata: &Metadata, result: ConvertResult) -> (Vec<u8>, String) {
+    let boundary = format!("pdfmill-{}", uuid::Uuid::new_v4().simple());
+    let json = serde_json::to_vec(metadata).unwrap_or_default();
+
+    let mut body = Vec::with_capacity(json.len() + result.data.len() + 512);
+    body.extend(
+        format!(
+            "--{}\r\nContent-Type: application/json\r\nContent-ID: <{}>\r\n\r\n",
+            boundary, METADATA_ID
+        )
+        .into_bytes(),
+    );
+    body.extend(json);
+    body.extend(
+        format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-ID: <{}>\r\n\
+             Content-Disposition: attachment; filename=\"{}\"\r\n\r\n",
+            boundary, result.content_type, DOCUMENT_ID, result.filename
+        )
+        .into_bytes(),
+    );
+    body.extend(result.data);
+    body.extend(format!("\r\n--{}--\r\n", boundary).into_bytes());
+
+    let content_type = format!(
+        "{}; boundary=\"{}\"; type=\"application/json\"; start=\"<{}>\"",
+        MULTIPART_RELATED, boundary, METADATA_ID
+    );
+    (body, content_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::sample_pdf;
+
+    #[test]
+    fn test_encode() {
+        let result = ConvertResult {
+            data: sample_pdf(3),
+            filename: "report.pdf".to_string(),
+            content_type: "application/pdf".to_string(),
+            warnings: vec!["Link to https://example.com/ was lost in the conversion".to_string()],
+        };
+        let links = LinkReport {
+            source: 1,
+            missing: vec!["https://example.com/".to_string()],
+            ..Default::default()
+        };
+        let timings = Timings {
+            conversion_ms: 40,
+            total_ms: 45,
+        };
+        let metadata = Metadata::new(
+            &result,
+            "ab".to_string(),
+            "chromium/1",
+            timings,
+            Some(links),
+        );
+        assert_eq!(metadata.pages, Some(3));
+        assert_eq!(metadata.warnings.len(), 1);
+
+        let pdf = result.data.clone();
+        let (body, content_type) = encode(&metadata, result);
+        let boundary = content_type
+            .split("boundary=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap();
+        let delimiter = format!("\r\n--{}", boundary);
+        let parts: Vec<&[u8]> = split(&body[2..], delimiter.as_bytes());
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[2], b"--\r\n");
+
+        let (head, json) = split_head(parts[0]);
+        assert!(head.contains("Content-ID: <metadata>"));
+        let json: serde_json::Value = serde_json::from_slice(json).unwrap();
+        assert_eq!(json["pages"], 3);
+        assert_eq!(json["engine"], "chromium/1");
+        assert_eq!(json["timings"]["total_ms"], 45);
+        assert_eq!(json["document"], "cid:document");
+        assert_eq!(json["links"]["missing"][0], "https://example.com/");
+
+        let (head, data) = split_head(parts[1]);
+        assert!(head.contains("Content-Type: application/pdf"));
+        assert!(head.contains("filename=\"report.pdf\""));
+        assert_eq!(data, &pdf[..]);
+    }
+
+    fn split<'a>(data: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+        let mut parts = Vec::new();
+        let mut rest = data;
+        while let Some(at) = rest.windows(delimiter.len()).position(|w| w == delimiter) {
+            parts.push(&rest[..at]);
+            rest = &rest[at + delimiter.len()..];
+        }
+        parts.push(rest);
+        parts
+    }
+
+    /// Part headers and content of a part, after the boundary line
+    fn split_head(part: &[u8]) -> (String, &[u8]) {
+        let at = part.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        (
+            String::from_utf8_lossy(&part[..at]).into_owned(),
+            &part[at + 4..],
+        )
+    }
+}
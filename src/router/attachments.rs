@@ -0,0 +1,149 @@
+//! Email attachments appended to the message PDF when `convertAttachments`
+//! is set: every attachment is converted by the engine its extension routes
+//! to and follows the message behind a separator page, so one PDF holds
+//! the whole email.
+
+use super::SmartRouter;
+use crate::engines::{
+    attachment_separator, attachments, Attachment, ConvertEngine, ConvertOptions, ConvertResult,
+};
+use crate::error::{AppError, Result};
+use crate::{metrics, pdf};
+use std::path::Path;
+use tracing::info;
+
+/// Most attachments converted for one message
+const MAX_ATTACHMENTS: usize = 50;
+
+impl SmartRouter {
+    /// Append the converted attachments of an `.eml` input to its PDF. An
+    /// attachment that cannot be converted is named on its separator page
+    /// and in the warnings instead of failing the message.
+    pub async fn append_attachments(
+        &self,
+        input_path: &Path,
+        mut result: ConvertResult,
+        options: &ConvertOptions,
+    ) -> Result<ConvertResult> {
+        let is_email = input_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("eml"));
+        if !options.convert_attachments || !is_email || result.content_type != "application/pdf" {
+            return Ok(result);
+        }
+        if options.pdf_format.is_some() {
+            return Err(AppError::InvalidRequest(
+                "convertAttachments: documents with appended attachments are not PDF/A".to_string(),
+            ));
+        }
+
+        let data = tokio::fs::read(input_path).await?;
+        let mut attachments = tokio::task::spawn_blocking(move || attachments(&data))
+            .await
+            .map_err(|e| AppError::Internal(format!("Attachment listing panicked: {}", e)))?;
+        if attachments.is_empty() {
+            return Ok(result);
+        }
+        if attachments.len() > MAX_ATTACHMENTS {
+            result.warnings.push(format!(
+                "Only the first {} of {} attachments were converted",
+                MAX_ATTACHMENTS,
+                attachments.len()
+            ));
+            attachments.truncate(MAX_ATTACHMENTS);
+        }
+        info!(
+            "Converting {} attachments of {}",
+            attachments.len(),
+            input_path.display()
+        );
+
+        let chromium = self.chromium()?;
+        // Attached messages do not append their own attachments; separator
+        // pages only take the page layout of the request
+        let attachment_options = ConvertOptions {
+            convert_attachments: false,
+            ..options.clone()
+        };
+        let separator_options = ConvertOptions {
+            page_width: options.page_width.clone(),
+            page_height: options.page_height.clone(),
+            margin_top: options.margin_top.clone(),
+            margin_bottom: options.margin_bottom.clone(),
+            margin_left: options.margin_left.clone(),
+            margin_right: options.margin_right.clone(),
+            landscape: options.landscape,
+            ..Default::default()
+        };
+
+        // Each attachment gets its own directory, as names may repeat
+        let temp_dir = crate::disk::tempdir()?;
+        let count = attachments.len();
+        let mut documents = vec![std::mem::take(&mut result.data)];
+        for (i, attachment) in attachments.iter().enumerate() {
+            let dir = temp_dir.path().join(i.to_string());
+            tokio::fs::create_dir(&dir).await?;
+            let converted = self
+                .convert_attachment(&dir, attachment, &attachment_options)
+                .await;
+            let failure = converted.as_ref().err().map(ToString::to_string);
+
+            let separator_path = dir.join("separator.html");
+            let separator = attachment_separator(i + 1, count, attachment, failure.as_deref());
+            tokio::fs::write(&separator_path, separator).await?;
+            documents.push(
+                chromium
+                    .convert(&separator_path, &separator_options)
+                    .await?
+                    .data,
+            );
+
+            match converted {
+                Ok(converted) => {
+                    result.warnings.extend(
+                        converted
+                            .warnings
+                            .into_iter()
+                            .map(|warning| format!("{}: {}", attachment.filename, warning)),
+                    );
+                    documents.push(converted.data);
+                }
+                Err(e) => result.warnings.push(format!(
+                    "Attachment {} was not converted: {}",
+                    attachment.filename, e
+                )),
+            }
+        }
+
+        result.data = tokio::task::spawn_blocking(move || pdf::merge(&documents))
+            .await
+            .map_err(|e| AppError::Internal(format!("PDF merge panicked: {}", e)))??;
+        Ok(result)
+    }
+
+    /// Convert one attachment like an upload, run through the input hooks
+    /// and routed by its extension
+    async fn convert_attachment(
+        &self,
+        dir: &Path,
+        attachment: &Attachment,
+        options: &ConvertOptions,
+    ) -> Result<ConvertResult> {
+        let input_path = dir.join(&attachment.filename);
+        tokio::fs::write(&input_path, &attachment.data).await?;
+        self.prepare_input(&input_path).await?;
+        let engine = self.find_engine_for_file(&input_path)?;
+        let result = engine.convert(&input_path, options).await;
+        metrics::record_conversion(engine.engine_type().name(), result.is_ok());
+        let result = result?;
+        if result.content_type != "application/pdf" {
+            return Err(AppError::ConversionFailed(format!(
+                "{} produced {}, not a PDF",
+                engine.engine_type().name(),
+                result.content_type
+            )));
+        }
+        Ok(result)
+    }
+}
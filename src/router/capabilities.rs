@@ -0,0 +1,61 @@
+//! What each extension can be converted with: the engines that accept it
+//! in the order they are tried, whether they are installed and which
+//! options they honor, so clients can offer only the options that apply.
+
+use super::SmartRouter;
+use crate::engines::honored_options;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How files of one extension are converted
+#[derive(Debug, Serialize)]
+pub struct ExtensionCapabilities {
+    /// Whether any of the engines is installed
+    pub available: bool,
+    /// Engine a conversion uses, the first available one
+    pub engine: Option<&'static str>,
+    /// Engines accepting the extension, in the order they are tried
+    pub engines: Vec<EngineCapabilities>,
+}
+
+/// One engine's support for an extension
+#[derive(Debug, Serialize)]
+pub struct EngineCapabilities {
+    pub name: &'static str,
+    pub version: Option<String>,
+    pub available: bool,
+    /// Options applied to the extension's files, by form field name
+    pub options: Vec<String>,
+}
+
+impl SmartRouter {
+    /// Candidate engines, their availability and honored options for every
+    /// supported extension
+    pub fn capabilities(&self) -> BTreeMap<String, ExtensionCapabilities> {
+        self.supported_extensions()
+            .into_iter()
+            .map(|ext| {
+                let engines: Vec<EngineCapabilities> = self
+                    .candidates(&ext)
+                    .into_iter()
+                    .map(|engine| {
+                        let engine_type = engine.engine_type();
+                        EngineCapabilities {
+                            name: engine_type.name(),
+                            version: self.engine_version(&engine_type),
+                            available: self.is_available(&engine_type),
+                            options: honored_options(engine.as_ref(), &ext),
+                        }
+                    })
+                    .collect();
+                let engine = engines.iter().find(|e| e.available).map(|e| e.name);
+                let capabilities = ExtensionCapabilities {
+                    available: engine.is_some(),
+                    engine,
+                    engines,
+                };
+                (ext, capabilities)
+            })
+            .collect()
+    }
+}
@@ -1,49 +1,412 @@
-use crate::engines::{ChromiumEngine, ConvertEngine, ImageEngine, LibreOfficeEngine};
+use crate::engines::{
+    origin_headers, parse_web_url, ArchiveEngine, ChromiumEngine, ConvertEngine, ConvertOptions,
+    ConvertResult, ImageEngine, LibreOfficeEngine, MediaEngine,
+};
 use crate::error::{AppError, Result};
-use std::collections::HashMap;
+use crate::hooks::Hooks;
+use crate::{egress, metrics, pdf, profile};
+use chromiumoxide::cdp::browser_protocol::page::CaptureScreenshotFormat;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, LazyLock};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 use crate::engines::EngineType;
 
+/// Name remote pages are given for input hooks and option checks
+pub const REMOTE_PAGE: &str = "page.html";
+
+/// Largest PDF output accepted, in pages (`PDFMILL_MAX_PAGES`)
+static MAX_PAGES: LazyLock<Option<usize>> = LazyLock::new(|| {
+    std::env::var("PDFMILL_MAX_PAGES")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|n| *n > 0)
+});
+
+/// Fail a PDF that has more pages than `PDFMILL_MAX_PAGES`. The count needs
+/// a full parse, so it runs off the async workers. Engines that render a
+/// PDF further, page by page, check it before starting.
+pub async fn check_page_limit(data: Vec<u8>) -> Result<Vec<u8>> {
+    let Some(limit) = *MAX_PAGES else {
+        return Ok(data);
+    };
+    let (data, pages) = tokio::task::spawn_blocking(move || {
+        let pages = pdf::page_count(&data);
+        (data, pages)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Page count panicked: {}", e)))?;
+
+    let pages = pages?;
+    if pages > limit {
+        metrics::increment("pdfmill_page_limit_exceeded_total", &[]);
+        return Err(AppError::PageLimitExceeded { pages, limit });
+    }
+    Ok(data)
+}
+
+mod attachments;
+mod capabilities;
+mod routes;
+
+pub use routes::Routes;
+
 /// Smart router that automatically selects the appropriate engine
 /// based on file extension
 pub struct SmartRouter {
     engines: Vec<Arc<dyn ConvertEngine>>,
+    /// Chromium is also used directly for non-PDF outputs (screenshots)
+    chromium: Arc<ChromiumEngine>,
+    /// LibreOffice is also used directly for PDF import (reverse conversion)
+    libreoffice: Arc<LibreOfficeEngine>,
     /// Cached availability results from startup
     availability: HashMap<EngineType, bool>,
+    /// Cached tool versions from startup
+    versions: HashMap<EngineType, String>,
+    /// Single conversion slot shared by requests, jobs and schedules in the
+    /// low-memory profile
+    slot: Option<Arc<Semaphore>>,
+    /// Engines preferred for particular extensions
+    routes: Routes,
+    /// Operator processing of uploads and PDF output
+    hooks: Hooks,
 }
 
 impl SmartRouter {
     pub async fn new() -> Self {
-        let chromium = Arc::new(ChromiumEngine::new());
+        let low_memory = profile::low_memory();
+        let libreoffice = Arc::new(LibreOfficeEngine::new());
+        let chromium = if low_memory {
+            Arc::new(ChromiumEngine::new().launch_per_request())
+        } else {
+            let chromium = Arc::new(ChromiumEngine::new());
+            // Initialize persistent Chromium browser via CDP
+            if let Err(e) = chromium.init().await {
+                tracing::warn!("Failed to initialize Chromium CDP: {}", e);
+            }
+            chromium
+        };
 
-        // Initialize persistent Chromium browser via CDP
-        if let Err(e) = chromium.init().await {
-            tracing::warn!("Failed to initialize Chromium CDP: {}", e);
-        }
-
-        let engines: Vec<Arc<dyn ConvertEngine>> = vec![
-            chromium,
-            Arc::new(LibreOfficeEngine::new()),
+        #[allow(unused_mut)]
+        let mut builtin: Vec<Arc<dyn ConvertEngine>> = vec![
+            chromium.clone(),
+            libreoffice.clone(),
             Arc::new(ImageEngine::new()),
+            Arc::new(MediaEngine::new(chromium.clone())),
+            Arc::new(ArchiveEngine::new(chromium.clone())),
         ];
 
-        // Cache engine availability at startup
-        let mut availability = HashMap::new();
-        for engine in &engines {
-            let available = engine.is_available().await;
-            let status = if available { "✓" } else { "✗" };
+        #[cfg(feature = "pst")]
+        builtin.push(Arc::new(crate::engines::PstEngine::new(chromium.clone())));
+
+        let mut router = Self {
+            engines: Vec::new(),
+            chromium,
+            libreoffice,
+            availability: HashMap::new(),
+            versions: HashMap::new(),
+            slot: low_memory.then(|| Arc::new(Semaphore::new(1))),
+            routes: Routes::default(),
+            hooks: Hooks::default(),
+        };
+        for engine in builtin {
+            router
+                .register(engine)
+                .await
+                .expect("built-in engine names are distinct");
+        }
+        router
+    }
+
+    /// Add an engine after the registered ones, caching its availability
+    /// and version. Engines registered earlier win for shared extensions
+    /// unless routes say otherwise.
+    pub async fn register(
+        &mut self,
+        engine: Arc<dyn ConvertEngine>,
+    ) -> std::result::Result<(), String> {
+        let engine_type = engine.engine_type();
+        if self
+            .engines
+            .iter()
+            .any(|e| e.engine_type().name() == engine_type.name())
+        {
+            return Err(format!(
+                "engine '{}' is already registered",
+                engine_type.name()
+            ));
+        }
+
+        let available = engine.is_available().await;
+        let version = if available {
+            engine.version().await
+        } else {
+            None
+        };
+        let status = if available { "✓" } else { "✗" };
+        tracing::info!(
+            "{} {:?} engine {}- supports: {}",
+            status,
+            engine_type,
+            version
+                .as_deref()
+                .map(|v| format!("{} ", v))
+                .unwrap_or_default(),
+            engine.supported_extensions().join(", ")
+        );
+        if !available {
             tracing::info!(
-                "{} {:?} engine - supports: {}",
-                status,
-                engine.engine_type(),
-                engine.supported_extensions().join(", ")
+                "  {:?} not usable{}",
+                engine_type,
+                describe_search(engine.as_ref())
             );
-            availability.insert(engine.engine_type(), available);
         }
+        self.availability.insert(engine_type.clone(), available);
+        if let Some(version) = version {
+            self.versions.insert(engine_type, version);
+        }
+        self.engines.push(engine);
+        Ok(())
+    }
+
+    /// Prefer the configured engines for their extensions; each must be a
+    /// registered engine that converts the extension
+    pub fn set_routes(&mut self, routes: Routes) -> std::result::Result<(), String> {
+        for (ext, name) in routes.iter() {
+            let engine = self
+                .engines
+                .iter()
+                .find(|e| e.engine_type().name() == name)
+                .ok_or_else(|| {
+                    format!(
+                        "PDFMILL_ENGINE_ROUTES: unknown engine '{}' for .{} (available: {})",
+                        name,
+                        ext,
+                        self.engine_names().join(", ")
+                    )
+                })?;
+            if !engine.supports_extension(ext) {
+                return Err(format!(
+                    "PDFMILL_ENGINE_ROUTES: {} does not convert .{} files",
+                    name, ext
+                ));
+            }
+            tracing::info!("Routing .{} files to {} first", ext, name);
+        }
+        self.routes = routes;
+        Ok(())
+    }
+
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        self.hooks = hooks;
+    }
+
+    /// The configured processing hooks
+    pub fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    /// Run the input hooks on an upload before its engine is chosen
+    pub async fn prepare_input(&self, input_path: &Path) -> Result<()> {
+        self.hooks.process_input(input_path).await
+    }
 
-        Self { engines, availability }
+    /// [`Self::prepare_input`] for an upload kept at rest, see
+    /// [`crate::disk::Upload::store`]
+    pub async fn prepare_stored_input(&self, input_path: &Path) -> Result<()> {
+        self.hooks.process_stored_input(input_path).await
+    }
+
+    /// Render a remote page with Chromium. When input hooks apply to HTML
+    /// the page is fetched first and run through them, as an upload would
+    /// be, and the browser is given their result as the page.
+    pub async fn convert_url(&self, url: &str, options: &ConvertOptions) -> Result<ConvertResult> {
+        let page = self.remote_page(url, options).await?;
+        self.chromium()?
+            .convert_url(url, page.as_deref(), options)
+            .await
+    }
+
+    /// Capture a remote page with Chromium, through the input hooks like
+    /// [`Self::convert_url`]
+    pub async fn screenshot_url(
+        &self,
+        url: &str,
+        options: &ConvertOptions,
+        format: CaptureScreenshotFormat,
+        quality: Option<i64>,
+    ) -> Result<ConvertResult> {
+        let page = self.remote_page(url, options).await?;
+        self.chromium()?
+            .screenshot_url(url, page.as_deref(), options, format, quality)
+            .await
+    }
+
+    /// A remote page fetched and run through the input hooks, or None when
+    /// no hook applies to HTML and the browser may load it itself
+    async fn remote_page(&self, url: &str, options: &ConvertOptions) -> Result<Option<Vec<u8>>> {
+        if !self.hooks.rewrites_input(REMOTE_PAGE) {
+            return Ok(None);
+        }
+        let mut headers = origin_headers(options);
+        if !options.cookies.is_empty() {
+            let cookies: Vec<String> = options
+                .cookies
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            headers.push(("Cookie".to_string(), cookies.join("; ")));
+        }
+        let page = egress::fetch(&parse_web_url(url)?, &headers, options.proxy.as_deref()).await?;
+        Ok(Some(self.prepare_input_data(REMOTE_PAGE, page).await?))
+    }
+
+    /// [`Self::prepare_input`] for an upload held in memory
+    pub async fn prepare_input_data(&self, filename: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+        self.hooks.process_input_data(filename, data).await
+    }
+
+    /// The configured per-extension engine preferences
+    pub fn routes(&self) -> &Routes {
+        &self.routes
+    }
+
+    /// Engines supporting an extension (lowercase), the configured
+    /// preference first
+    fn candidates(&self, ext: &str) -> Vec<&Arc<dyn ConvertEngine>> {
+        let mut candidates: Vec<_> = self
+            .engines
+            .iter()
+            .filter(|e| e.supports_extension(ext))
+            .collect();
+        if let Some(preferred) = self.routes.preferred(ext) {
+            candidates.sort_by_key(|e| e.engine_type().name() != preferred);
+        }
+        candidates
+    }
+
+    fn engine_names(&self) -> Vec<&'static str> {
+        self.engines
+            .iter()
+            .map(|e| e.engine_type().name())
+            .collect()
+    }
+
+    /// Wait for a free conversion slot; conversions are only limited in
+    /// the low-memory profile. Hold the permit until the output is produced.
+    pub async fn conversion_slot(&self) -> Option<OwnedSemaphorePermit> {
+        match &self.slot {
+            Some(slot) => Arc::clone(slot).acquire_owned().await.ok(),
+            None => None,
+        }
+    }
+
+    /// Fail a conversion whose PDF output has more pages than
+    /// `PDFMILL_MAX_PAGES`, see [`check_page_limit`]
+    pub async fn check_page_limit(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        check_page_limit(data).await
+    }
+
+    /// Pass PDF output through the output hooks, then
+    /// [`Self::check_page_limit`]
+    pub async fn finish_pdf(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        let data = self.hooks.process_output(data).await?;
+        self.check_page_limit(data).await
+    }
+
+    /// [`Self::finish_pdf`] for PDF results; other outputs pass
+    pub async fn finish(&self, mut result: ConvertResult) -> Result<ConvertResult> {
+        if result.content_type == "application/pdf" {
+            result.data = self.finish_pdf(result.data).await?;
+        }
+        Ok(result)
+    }
+
+    /// Engine name and version for response headers, e.g. "chromium/124.0.6367.60"
+    pub fn engine_label(&self, engine_type: &EngineType) -> String {
+        match self.versions.get(engine_type) {
+            Some(version) => format!("{}/{}", engine_type.name(), version),
+            None => engine_type.name().to_string(),
+        }
+    }
+
+    /// Name, version and availability of every registered engine
+    pub fn engine_summaries(&self) -> Vec<serde_json::Value> {
+        self.engines
+            .iter()
+            .map(|e| {
+                let engine_type = e.engine_type();
+                serde_json::json!({
+                    "name": engine_type.name(),
+                    "version": self.versions.get(&engine_type),
+                    "available": *self.availability.get(&engine_type).unwrap_or(&false),
+                })
+            })
+            .collect()
+    }
+
+    /// Version of every installed engine, by engine name
+    pub fn engine_versions(&self) -> BTreeMap<&'static str, String> {
+        self.versions
+            .iter()
+            .map(|(engine_type, version)| (engine_type.name(), version.clone()))
+            .collect()
+    }
+
+    /// Every registered engine, available or not
+    pub fn engines(&self) -> &[Arc<dyn ConvertEngine>] {
+        &self.engines
+    }
+
+    /// Cached availability of an engine
+    pub fn is_available(&self, engine_type: &EngineType) -> bool {
+        *self.availability.get(engine_type).unwrap_or(&false)
+    }
+
+    /// Cached tool version of an engine
+    pub fn engine_version(&self, engine_type: &EngineType) -> Option<String> {
+        self.versions.get(engine_type).cloned()
+    }
+
+    /// Name and cached availability of every registered engine
+    pub fn engine_availability(&self) -> Vec<(&'static str, bool)> {
+        self.engines
+            .iter()
+            .map(|e| {
+                let engine_type = e.engine_type();
+                let available = *self.availability.get(&engine_type).unwrap_or(&false);
+                (engine_type.name(), available)
+            })
+            .collect()
+    }
+
+    /// Get the Chromium engine, if its dependencies are installed
+    pub fn chromium(&self) -> Result<Arc<ChromiumEngine>> {
+        self.require(EngineType::Chromium)?;
+        Ok(Arc::clone(&self.chromium))
+    }
+
+    /// Get the LibreOffice engine, if its dependencies are installed
+    pub fn libreoffice(&self) -> Result<Arc<LibreOfficeEngine>> {
+        self.require(EngineType::LibreOffice)?;
+        Ok(Arc::clone(&self.libreoffice))
+    }
+
+    fn require(&self, engine_type: EngineType) -> Result<()> {
+        if !*self.availability.get(&engine_type).unwrap_or(&false) {
+            let tried = self
+                .engines
+                .iter()
+                .find(|e| e.engine_type() == engine_type)
+                .map(|e| describe_search(e.as_ref()))
+                .unwrap_or_default();
+            return Err(AppError::EngineNotAvailable(format!(
+                "{:?} is required but not installed{}",
+                engine_type, tried
+            )));
+        }
+        Ok(())
     }
 
     /// Find the appropriate engine for a given file extension
@@ -53,12 +416,7 @@ impl SmartRouter {
     ) -> Result<Arc<dyn ConvertEngine>> {
         let ext_lower = ext.to_lowercase();
 
-        // Find all engines that support this extension
-        let candidates: Vec<_> = self
-            .engines
-            .iter()
-            .filter(|e| e.supports_extension(&ext_lower))
-            .collect();
+        let candidates = self.candidates(&ext_lower);
 
         if candidates.is_empty() {
             return Err(AppError::UnsupportedFormat(format!(
@@ -76,7 +434,7 @@ impl SmartRouter {
         if available.is_empty() {
             let supported_by = candidates
                 .iter()
-                .map(|e| format!("{:?}", e.engine_type()))
+                .map(|e| format!("{:?}{}", e.engine_type(), describe_search(e.as_ref())))
                 .collect::<Vec<_>>()
                 .join(", ");
 
@@ -100,16 +458,46 @@ impl SmartRouter {
         self.find_engine_for_extension(ext)
     }
 
+    /// Find an engine by name (as reported in `engine_summaries`) that can
+    /// convert the given file, bypassing automatic selection
+    pub fn find_named_engine_for_file(
+        &self,
+        name: &str,
+        path: &Path,
+    ) -> Result<Arc<dyn ConvertEngine>> {
+        let engine = self
+            .engines
+            .iter()
+            .find(|e| e.engine_type().name().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| {
+                AppError::InvalidRequest(format!(
+                    "engine: unknown engine '{}' (available: {})",
+                    name,
+                    self.engine_names().join(", ")
+                ))
+            })?;
+
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .ok_or_else(|| AppError::InvalidRequest("File has no extension".to_string()))?;
+        if !engine.supports_extension(&ext.to_lowercase()) {
+            return Err(AppError::UnsupportedFormat(format!(
+                "{:?} engine does not support .{} files",
+                engine.engine_type(),
+                ext
+            )));
+        }
+
+        self.require(engine.engine_type())?;
+        Ok(Arc::clone(engine))
+    }
+
     /// Get a list of all supported extensions
     pub fn supported_extensions(&self) -> Vec<String> {
         let mut extensions = Vec::new();
         for engine in &self.engines {
-            extensions.extend(
-                engine
-                    .supported_extensions()
-                    .iter()
-                    .map(|s| s.to_string()),
-            );
+            extensions.extend(engine.supported_extensions().iter().map(|s| s.to_string()));
         }
         extensions.sort();
         extensions.dedup();
@@ -117,6 +505,7 @@ impl SmartRouter {
     }
 
     /// Check if an extension is supported
+    #[allow(dead_code)]
     pub fn is_extension_supported(&self, ext: &str) -> bool {
         let ext_lower = ext.to_lowercase();
         self.engines
@@ -125,6 +514,16 @@ impl SmartRouter {
     }
 }
 
+/// " (tried a, b, c)" for an engine whose tool was searched for
+pub fn describe_search(engine: &dyn ConvertEngine) -> String {
+    let tried = engine.searched_paths();
+    if tried.is_empty() {
+        String::new()
+    } else {
+        format!(" (tried {})", tried.join(", "))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -155,4 +554,49 @@ mod tests {
         assert!(router.is_extension_supported("docx"));
         assert!(!router.is_extension_supported("xyz"));
     }
+
+    #[tokio::test]
+    async fn test_routes() {
+        let mut router = SmartRouter::new().await;
+        router
+            .set_routes(Routes::parse("svg=imagemagick").unwrap())
+            .unwrap();
+        assert_eq!(
+            router.candidates("svg")[0].engine_type().name(),
+            "imagemagick"
+        );
+        assert_eq!(router.routes().preferred("svg"), Some("imagemagick"));
+
+        assert!(router
+            .set_routes(Routes::parse("svg=weasyprint").unwrap())
+            .unwrap_err()
+            .contains("unknown engine 'weasyprint'"));
+        assert!(router
+            .set_routes(Routes::parse("svg=libreoffice").unwrap())
+            .unwrap_err()
+            .contains("libreoffice does not convert .svg"));
+        // A rejected configuration leaves the previous one in place
+        assert_eq!(router.routes().preferred("svg"), Some("imagemagick"));
+    }
+
+    #[tokio::test]
+    async fn test_capabilities() {
+        let mut router = SmartRouter::new().await;
+        router
+            .set_routes(Routes::parse("svg=imagemagick").unwrap())
+            .unwrap();
+        let capabilities = router.capabilities();
+
+        assert_eq!(capabilities["svg"].engines[0].name, "imagemagick");
+        let markdown = &capabilities["md"].engines[0];
+        assert!(markdown.options.iter().any(|o| o == "markdownTemplate"));
+        assert!(markdown.options.iter().any(|o| o == "pdfFormat"));
+        let docx = &capabilities["docx"].engines[0];
+        assert_eq!(docx.name, "libreoffice");
+        assert!(!docx.options.iter().any(|o| o == "markdownTemplate"));
+        assert_eq!(
+            capabilities["docx"].engine.is_some(),
+            router.is_available(&EngineType::LibreOffice)
+        );
+    }
 }
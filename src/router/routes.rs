@@ -0,0 +1,73 @@
+//! Per-extension engine preferences, overriding the built-in candidate
+//! order: `PDFMILL_ENGINE_ROUTES=html=chromium,svg=imagemagick`. The preferred
+//! engine is tried first; when it is not installed the remaining candidates
+//! are used in their usual order.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Routes {
+    /// Extension (lowercase, without the dot) to engine name
+    preferred: BTreeMap<String, String>,
+}
+
+impl Routes {
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("PDFMILL_ENGINE_ROUTES") {
+            Ok(spec) => Self::parse(&spec).map_err(|e| format!("PDFMILL_ENGINE_ROUTES: {}", e)),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Comma-separated `extension=engine` pairs
+    pub(super) fn parse(spec: &str) -> Result<Self, String> {
+        let mut preferred = BTreeMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (ext, engine) = entry
+                .split_once('=')
+                .map(|(ext, engine)| (ext.trim().trim_start_matches('.'), engine.trim()))
+                .filter(|(ext, engine)| !ext.is_empty() && !engine.is_empty())
+                .ok_or_else(|| format!("expected extension=engine, got '{}'", entry))?;
+            let ext = ext.to_lowercase();
+            if preferred
+                .insert(ext.clone(), engine.to_lowercase())
+                .is_some()
+            {
+                return Err(format!(".{} is routed twice", ext));
+            }
+        }
+        Ok(Self { preferred })
+    }
+
+    /// Engine name preferred for an extension (lowercase)
+    pub fn preferred(&self, ext: &str) -> Option<&str> {
+        self.preferred.get(ext).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.preferred
+            .iter()
+            .map(|(ext, engine)| (ext.as_str(), engine.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let routes = Routes::parse(" html=Chromium, .SVG = imagemagick ,").unwrap();
+        assert_eq!(routes.preferred("html"), Some("chromium"));
+        assert_eq!(routes.preferred("svg"), Some("imagemagick"));
+        assert_eq!(routes.preferred("docx"), None);
+
+        assert!(Routes::parse("html").is_err());
+        assert!(Routes::parse("html=").is_err());
+        assert!(Routes::parse("html=chromium,HTML=libreoffice")
+            .unwrap_err()
+            .contains(".html is routed twice"));
+    }
+}
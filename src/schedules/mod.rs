@@ -0,0 +1,665 @@
+//! Recurring conversions: on a cron schedule a web page is rendered to PDF
+//! and delivered over HTTP (e.g. PUT to an object storage bucket) and/or
+//! by email
+
+use crate::egress;
+use crate::engines::{parse_web_url, sha256_hex, ConvertOptions, EngineType};
+use crate::error::{AppError, Result};
+use crate::mailer::{parse_recipients, Mailer};
+use crate::metrics;
+use crate::router::SmartRouter;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use croner::Cron;
+use lettre::message::Mailbox;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// How often due schedules are looked for
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a delivery may take
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A schedule as submitted by clients (and persisted)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleSpec {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Cron expression: minute hour day-of-month month day-of-week, with an
+    /// optional leading seconds field
+    pub cron: String,
+    /// IANA timezone the cron expression is evaluated in
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// Web page to render
+    pub url: String,
+    /// Conversion options, named like the /convert form fields
+    #[serde(default)]
+    pub options: HashMap<String, serde_json::Value>,
+    pub delivery: Delivery,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// Where each rendered PDF is sent: an HTTP URL, email recipients or both
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delivery {
+    /// Target URL; `{date}` and `{timestamp}` are replaced with the run time
+    /// in the schedule's timezone
+    #[serde(default)]
+    pub url: Option<String>,
+    /// PUT (default) or POST
+    #[serde(default = "default_method")]
+    pub method: String,
+    /// Extra request headers, e.g. Authorization
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Comma-separated email recipients
+    #[serde(default)]
+    pub email_to: Option<String>,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_method() -> String {
+    "PUT".to_string()
+}
+
+/// Outcome of the latest run of a schedule
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleRun {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub succeeded: Option<bool>,
+    pub error: Option<String>,
+}
+
+/// Public view of a schedule, as returned by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleInfo {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub spec: ScheduleSpec,
+    pub created_at: DateTime<Utc>,
+    pub next_run: Option<DateTime<Utc>>,
+    pub last_run: Option<ScheduleRun>,
+}
+
+/// Persisted form of a schedule
+#[derive(Serialize, Deserialize)]
+struct StoredSchedule {
+    id: Uuid,
+    created_at: DateTime<Utc>,
+    spec: ScheduleSpec,
+}
+
+/// A spec checked and turned into what a run needs
+struct Compiled {
+    cron: Cron,
+    timezone: Tz,
+    options: ConvertOptions,
+    method: Method,
+    headers: HeaderMap,
+    email_to: Vec<Mailbox>,
+}
+
+struct ScheduleEntry {
+    info: ScheduleInfo,
+    compiled: Compiled,
+    running: bool,
+}
+
+/// In-memory schedule registry, optionally persisted to a JSON file
+pub struct ScheduleManager {
+    schedules: Mutex<HashMap<Uuid, ScheduleEntry>>,
+    store: Option<PathBuf>,
+    http: reqwest::Client,
+    mailer: Option<Arc<Mailer>>,
+}
+
+impl ScheduleManager {
+    /// Schedules are kept in `PDFMILL_SCHEDULES_FILE` when set, otherwise
+    /// they are lost on restart. Email deliveries go through `mailer`.
+    pub fn from_env(mailer: Option<Arc<Mailer>>) -> Self {
+        let store = std::env::var("PDFMILL_SCHEDULES_FILE")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(PathBuf::from);
+        let manager = Self {
+            schedules: Mutex::new(HashMap::new()),
+            store,
+            // A redirect could lead deliveries past the egress policy
+            http: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .expect("HTTP client"),
+            mailer,
+        };
+        manager.load();
+        manager
+    }
+
+    fn load(&self) {
+        let Some(path) = &self.store else { return };
+        let stored: Vec<StoredSchedule> = match std::fs::read(path) {
+            Ok(data) => match serde_json::from_slice(&data) {
+                Ok(stored) => stored,
+                Err(e) => {
+                    warn!(
+                        "Ignoring unreadable schedules file {}: {}",
+                        path.display(),
+                        e
+                    );
+                    return;
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                warn!("Failed to read schedules file {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        let mut schedules = self.schedules.lock().unwrap();
+        for schedule in stored {
+            match self.compile(&schedule.spec) {
+                Ok(compiled) => {
+                    let info = ScheduleInfo {
+                        id: schedule.id,
+                        next_run: next_run(&schedule.spec, &compiled),
+                        spec: schedule.spec,
+                        created_at: schedule.created_at,
+                        last_run: None,
+                    };
+                    schedules.insert(
+                        info.id,
+                        ScheduleEntry {
+                            info,
+                            compiled,
+                            running: false,
+                        },
+                    );
+                }
+                Err(e) => warn!("Skipping stored schedule {}: {}", schedule.id, e),
+            }
+        }
+        info!(
+            "Loaded {} schedules from {}",
+            schedules.len(),
+            path.display()
+        );
+    }
+
+    /// Write all schedules to the store, if one is configured
+    fn save(&self, schedules: &HashMap<Uuid, ScheduleEntry>) -> Result<()> {
+        let Some(path) = &self.store else {
+            return Ok(());
+        };
+        let stored: Vec<StoredSchedule> = schedules
+            .values()
+            .map(|entry| StoredSchedule {
+                id: entry.info.id,
+                created_at: entry.info.created_at,
+                spec: entry.info.spec.clone(),
+            })
+            .collect();
+        let data = serde_json::to_vec_pretty(&stored)
+            .map_err(|e| AppError::Internal(format!("Failed to serialize schedules: {}", e)))?;
+
+        // Replace the file atomically so a crash never leaves it truncated
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub async fn create(&self, spec: ScheduleSpec) -> Result<ScheduleInfo> {
+        let compiled = self.compile(&spec)?;
+        check_delivery(&spec).await?;
+        let info = ScheduleInfo {
+            id: Uuid::new_v4(),
+            next_run: next_run(&spec, &compiled),
+            spec,
+            created_at: Utc::now(),
+            last_run: None,
+        };
+
+        let mut schedules = self.schedules.lock().unwrap();
+        schedules.insert(
+            info.id,
+            ScheduleEntry {
+                info: info.clone(),
+                compiled,
+                running: false,
+            },
+        );
+        self.save(&schedules)?;
+        info!("Created schedule {} ({})", info.id, info.spec.cron);
+        Ok(info)
+    }
+
+    /// Replace the spec of a schedule, keeping its id and run history
+    pub async fn update(&self, id: Uuid, spec: ScheduleSpec) -> Result<ScheduleInfo> {
+        let compiled = self.compile(&spec)?;
+        check_delivery(&spec).await?;
+        let mut schedules = self.schedules.lock().unwrap();
+        let entry = schedules.get_mut(&id).ok_or_else(|| not_found(id))?;
+        entry.info.next_run = next_run(&spec, &compiled);
+        entry.info.spec = spec;
+        entry.compiled = compiled;
+        let info = entry.info.clone();
+        self.save(&schedules)?;
+        Ok(info)
+    }
+
+    pub fn delete(&self, id: Uuid) -> Result<()> {
+        let mut schedules = self.schedules.lock().unwrap();
+        schedules.remove(&id).ok_or_else(|| not_found(id))?;
+        self.save(&schedules)?;
+        info!("Deleted schedule {}", id);
+        Ok(())
+    }
+
+    pub fn get(&self, id: Uuid) -> Result<ScheduleInfo> {
+        self.schedules
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|entry| entry.info.clone())
+            .ok_or_else(|| not_found(id))
+    }
+
+    /// All schedules, oldest first
+    pub fn list(&self) -> Vec<ScheduleInfo> {
+        let mut schedules: Vec<ScheduleInfo> = self
+            .schedules
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.info.clone())
+            .collect();
+        schedules.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+        schedules
+    }
+
+    /// Like [`compile`], also checking that email can be sent
+    fn compile(&self, spec: &ScheduleSpec) -> Result<Compiled> {
+        let compiled = compile(spec)?;
+        if !compiled.email_to.is_empty() && self.mailer.is_none() {
+            return Err(AppError::InvalidRequest(
+                "delivery.email_to: email delivery is not configured (PDFMILL_SMTP_URL, PDFMILL_SMTP_FROM)"
+                    .to_string(),
+            ));
+        }
+        Ok(compiled)
+    }
+
+    /// Start runs of due schedules until the manager is dropped
+    pub fn spawn_runner(self: &Arc<Self>, router: Arc<SmartRouter>) {
+        let manager = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                for id in manager.take_due(Utc::now()) {
+                    let manager = Arc::clone(&manager);
+                    let router = Arc::clone(&router);
+                    tokio::spawn(async move { manager.run(&router, id).await });
+                }
+            }
+        });
+    }
+
+    /// Mark due schedules as running and advance their next run. A schedule
+    /// whose previous run is still going skips this occurrence.
+    fn take_due(&self, now: DateTime<Utc>) -> Vec<Uuid> {
+        let mut due = Vec::new();
+        for entry in self.schedules.lock().unwrap().values_mut() {
+            if entry.info.next_run.is_none_or(|next| next > now) {
+                continue;
+            }
+            entry.info.next_run = next_run(&entry.info.spec, &entry.compiled);
+            if entry.running {
+                warn!(
+                    "Schedule {} is still running; skipping this occurrence",
+                    entry.info.id
+                );
+                continue;
+            }
+            entry.running = true;
+            entry.info.last_run = Some(ScheduleRun {
+                started_at: now,
+                finished_at: None,
+                succeeded: None,
+                error: None,
+            });
+            due.push(entry.info.id);
+        }
+        due
+    }
+
+    async fn run(&self, router: &SmartRouter, id: Uuid) {
+        let outcome = self.render_and_deliver(router, id).await;
+        if let Err(ref e) = outcome {
+            warn!("Schedule {} failed: {}", id, e);
+        }
+
+        if let Some(entry) = self.schedules.lock().unwrap().get_mut(&id) {
+            entry.running = false;
+            if let Some(run) = entry.info.last_run.as_mut() {
+                run.finished_at = Some(Utc::now());
+                run.succeeded = Some(outcome.is_ok());
+                run.error = outcome.err().map(|e| e.to_string());
+            }
+        }
+    }
+
+    async fn render_and_deliver(&self, router: &SmartRouter, id: Uuid) -> Result<()> {
+        let (spec, options, method, headers, timezone, email_to) = {
+            let schedules = self.schedules.lock().unwrap();
+            let entry = schedules.get(&id).ok_or_else(|| not_found(id))?;
+            (
+                entry.info.spec.clone(),
+                entry.compiled.options.clone(),
+                entry.compiled.method.clone(),
+                entry.compiled.headers.clone(),
+                entry.compiled.timezone,
+                entry.compiled.email_to.clone(),
+            )
+        };
+
+        let slot = router.conversion_slot().await;
+        let data = match router.convert_url(&spec.url, &options).await {
+            Ok(result) => router.finish_pdf(result.data).await,
+            Err(e) => Err(e),
+        };
+        drop(slot);
+        metrics::record_conversion(EngineType::Chromium.name(), data.is_ok());
+        let data = data?;
+
+        let now = Utc::now().with_timezone(&timezone);
+        let filename = format!(
+            "{}-{}.pdf",
+            spec.name
+                .as_deref()
+                .map(sanitize_name)
+                .unwrap_or_else(|| "schedule".to_string()),
+            now.format("%Y%m%dT%H%M%S")
+        );
+
+        if let Some(url) = &spec.delivery.url {
+            let url = expand_delivery_url(url, &now);
+            // Checked again on every run: the host may resolve elsewhere now
+            egress::check_url(
+                &reqwest::Url::parse(&url).map_err(|e| {
+                    AppError::Internal(format!("Delivery to {} failed: {}", url, e))
+                })?,
+            )
+            .await?;
+            let response = self
+                .http
+                .request(method, &url)
+                .timeout(DELIVERY_TIMEOUT)
+                .headers(headers)
+                .header(CONTENT_TYPE, "application/pdf")
+                .header("x-content-sha256", sha256_hex(&data))
+                .header(
+                    CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", filename),
+                )
+                .body(data.clone())
+                .send()
+                .await
+                .map_err(|e| AppError::Internal(format!("Delivery to {} failed: {}", url, e)))?;
+            // Redirects are not followed, so they fail the delivery too
+            if !response.status().is_success() {
+                return Err(AppError::Internal(format!(
+                    "Delivery to {} failed: {}",
+                    url,
+                    response.status()
+                )));
+            }
+            info!("Schedule {} delivered {} to {}", id, filename, url);
+        }
+
+        if !email_to.is_empty() {
+            let mailer = self.mailer.as_ref().ok_or_else(|| {
+                AppError::Internal("Email delivery is not configured".to_string())
+            })?;
+            mailer
+                .send(&email_to, &spec.url, &filename, "application/pdf", data)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Fill in the `{date}` and `{timestamp}` placeholders of a delivery URL
+fn expand_delivery_url(url: &str, now: &DateTime<Tz>) -> String {
+    url.replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{timestamp}", &now.format("%Y%m%dT%H%M%S").to_string())
+}
+
+/// Check that the delivery URL of a new or changed spec may be reached
+/// under the egress policy
+async fn check_delivery(spec: &ScheduleSpec) -> Result<()> {
+    let Some(url) = &spec.delivery.url else {
+        return Ok(());
+    };
+    let url = expand_delivery_url(url, &Utc::now().with_timezone(&Tz::UTC));
+    let url = reqwest::Url::parse(&url)
+        .map_err(|e| AppError::InvalidRequest(format!("delivery.url: {}", e)))?;
+    egress::check_url(&url).await
+}
+
+/// Validate a spec and prepare it for running
+fn compile(spec: &ScheduleSpec) -> Result<Compiled> {
+    let cron = Cron::new(&spec.cron)
+        .with_seconds_optional()
+        .parse()
+        .map_err(|e| AppError::InvalidRequest(format!("cron: {}", e)))?;
+    let timezone: Tz = spec.timezone.parse().map_err(|_| {
+        AppError::InvalidRequest(format!("timezone: unknown timezone '{}'", spec.timezone))
+    })?;
+
+    parse_web_url(&spec.url)?;
+    if let Some(url) = &spec.delivery.url {
+        let url = reqwest::Url::parse(url)
+            .map_err(|e| AppError::InvalidRequest(format!("delivery.url: {}", e)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(AppError::InvalidRequest(
+                "delivery.url: only http and https are supported".to_string(),
+            ));
+        }
+    }
+    let email_to = match &spec.delivery.email_to {
+        Some(to) => parse_recipients(to)
+            .map_err(|e| AppError::InvalidRequest(format!("delivery.email_to: {}", e)))?,
+        None => Vec::new(),
+    };
+    if spec.delivery.url.is_none() && email_to.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "delivery: a url or email_to is required".to_string(),
+        ));
+    }
+
+    let method = match spec.delivery.method.to_ascii_uppercase().as_str() {
+        "PUT" => Method::PUT,
+        "POST" => Method::POST,
+        other => {
+            return Err(AppError::InvalidRequest(format!(
+                "delivery.method: expected PUT or POST, got '{}'",
+                other
+            )))
+        }
+    };
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in &spec.delivery.headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+            AppError::InvalidRequest(format!("delivery.headers: invalid name '{}'", name))
+        })?;
+        let value = HeaderValue::from_str(value).map_err(|_| {
+            AppError::InvalidRequest(format!("delivery.headers: invalid value for '{}'", name))
+        })?;
+        headers.insert(name, value);
+    }
+
+    let options = ConvertOptions::deserialize(serde_json::Value::Object(
+        spec.options.clone().into_iter().collect(),
+    ))
+    .map_err(|e| AppError::InvalidRequest(format!("options: {}", e)))?;
+
+    Ok(Compiled {
+        cron,
+        timezone,
+        options,
+        method,
+        headers,
+        email_to,
+    })
+}
+
+/// Next occurrence after now, or None while the schedule is disabled
+fn next_run(spec: &ScheduleSpec, compiled: &Compiled) -> Option<DateTime<Utc>> {
+    if !spec.enabled {
+        return None;
+    }
+    let now = Utc::now().with_timezone(&compiled.timezone);
+    compiled
+        .cron
+        .find_next_occurrence(&now, false)
+        .ok()
+        .map(|next| next.with_timezone(&Utc))
+}
+
+/// Keep file names portable
+fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        "schedule".to_string()
+    } else {
+        name
+    }
+}
+
+fn not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Schedule {} not found", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(json: serde_json::Value) -> ScheduleSpec {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_compile_and_next_run() {
+        let daily = spec(serde_json::json!({
+            "cron": "0 6 * * *",
+            "timezone": "Europe/Berlin",
+            "url": "https://dashboard.example.com/daily",
+            "options": {"landscape": true, "printBackground": "true"},
+            "delivery": {"url": "https://bucket.example.com/reports/{date}.pdf"}
+        }));
+        let compiled = compile(&daily).unwrap();
+        assert!(compiled.options.landscape && compiled.options.print_background);
+        assert_eq!(compiled.method, Method::PUT);
+
+        let next = next_run(&daily, &compiled)
+            .unwrap()
+            .with_timezone(&compiled.timezone);
+        assert_eq!(next.format("%H:%M").to_string(), "06:00");
+
+        let disabled = ScheduleSpec {
+            enabled: false,
+            ..daily.clone()
+        };
+        assert!(next_run(&disabled, &compiled).is_none());
+
+        for invalid in [
+            ScheduleSpec {
+                cron: "every morning".to_string(),
+                ..daily.clone()
+            },
+            ScheduleSpec {
+                timezone: "Mars/Olympus".to_string(),
+                ..daily.clone()
+            },
+            ScheduleSpec {
+                url: "file:///etc/passwd".to_string(),
+                ..daily.clone()
+            },
+            ScheduleSpec {
+                options: [("colour".to_string(), serde_json::json!("red"))].into(),
+                ..daily.clone()
+            },
+            ScheduleSpec {
+                delivery: Delivery {
+                    url: None,
+                    method: default_method(),
+                    headers: HashMap::new(),
+                    email_to: None,
+                },
+                ..daily.clone()
+            },
+        ] {
+            assert!(matches!(
+                compile(&invalid),
+                Err(AppError::InvalidRequest(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_delivery() {
+        let delivery = |url: &str| {
+            spec(serde_json::json!({
+                "cron": "0 6 * * *",
+                "url": "https://dashboard.example.com/daily",
+                "delivery": {"url": url}
+            }))
+        };
+        check_delivery(&delivery("https://93.184.215.14/reports/{date}.pdf"))
+            .await
+            .unwrap();
+        for internal in [
+            "http://169.254.169.254/latest/meta-data",
+            "http://127.0.0.1:9090/admin/jobs/purge",
+            "http://[::1]/{timestamp}.pdf",
+        ] {
+            assert!(matches!(
+                check_delivery(&delivery(internal)).await,
+                Err(AppError::Forbidden(_))
+            ));
+        }
+    }
+}
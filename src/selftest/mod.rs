@@ -0,0 +1,185 @@
+//! Post-deploy self-test: a tiny fixture baked into the binary is converted
+//! by every available engine, reporting pass/fail with timings and the
+//! tool's stderr.
+
+use crate::engines::{ConvertEngine, ConvertOptions};
+use crate::error::AppError;
+use crate::pdf;
+use crate::router::{describe_search, SmartRouter};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Longest a single engine may take before it is reported as failed
+const SELFTEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Canned inputs, by file name; each engine is tested with the first one
+/// it supports
+const FIXTURES: &[(&str, &[u8])] = &[
+    ("selftest.html", include_bytes!("fixtures/selftest.html")),
+    ("selftest.docx", include_bytes!("fixtures/selftest.docx")),
+    ("selftest.png", include_bytes!("fixtures/selftest.png")),
+];
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfTestReport {
+    /// Every tested engine passed, and at least one was tested
+    pub passed: bool,
+    pub engines: Vec<EngineResult>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Pass,
+    Fail,
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EngineResult {
+    pub engine: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub status: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fixture: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<usize>,
+    /// Why the engine failed or was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+}
+
+impl EngineResult {
+    fn new(engine: &dyn ConvertEngine, status: Outcome) -> Self {
+        Self {
+            engine: engine.engine_type().name(),
+            version: None,
+            status,
+            fixture: None,
+            duration_ms: None,
+            output_bytes: None,
+            pages: None,
+            error: None,
+            stderr: None,
+        }
+    }
+}
+
+/// Convert a fixture with every registered engine, one at a time
+pub async fn run(router: &SmartRouter) -> SelfTestReport {
+    let mut engines = Vec::new();
+    for engine in router.engines() {
+        engines.push(test_engine(router, engine.as_ref()).await);
+    }
+
+    let tested: Vec<_> = engines
+        .iter()
+        .filter(|r| r.status != Outcome::Skipped)
+        .collect();
+    let passed = !tested.is_empty() && tested.iter().all(|r| r.status == Outcome::Pass);
+    SelfTestReport { passed, engines }
+}
+
+async fn test_engine(router: &SmartRouter, engine: &dyn ConvertEngine) -> EngineResult {
+    let engine_type = engine.engine_type();
+    if !router.is_available(&engine_type) {
+        let mut result = EngineResult::new(engine, Outcome::Skipped);
+        result.error = Some(format!("not installed{}", describe_search(engine)));
+        return result;
+    }
+
+    let Some((name, data)) = fixture_for(engine) else {
+        let mut result = EngineResult::new(engine, Outcome::Skipped);
+        result.error = Some("no self-test fixture for this engine".to_string());
+        return result;
+    };
+
+    let mut result = EngineResult::new(engine, Outcome::Fail);
+    result.version = router.engine_version(&engine_type);
+    result.fixture = Some(name);
+
+    let started = Instant::now();
+    let outcome = convert_fixture(router, engine, name, data).await;
+    result.duration_ms = Some(started.elapsed().as_millis());
+
+    match outcome {
+        Ok((bytes, pages)) => {
+            result.status = Outcome::Pass;
+            result.output_bytes = Some(bytes);
+            result.pages = Some(pages);
+        }
+        Err(e) => {
+            if let AppError::ToolFailed { stderr, .. } = &e {
+                result.stderr = Some(stderr.clone()).filter(|s| !s.is_empty());
+            }
+            result.error = Some(e.to_string());
+        }
+    }
+    result
+}
+
+/// The first fixture `engine` supports, by name
+pub(crate) fn fixture_for(engine: &dyn ConvertEngine) -> Option<(&'static str, &'static [u8])> {
+    FIXTURES
+        .iter()
+        .find(|(name, _)| {
+            let ext = name.rsplit('.').next().unwrap_or_default();
+            engine.supports_extension(ext)
+        })
+        .copied()
+}
+
+/// Convert one fixture and check the output is a PDF with pages
+async fn convert_fixture(
+    router: &SmartRouter,
+    engine: &dyn ConvertEngine,
+    name: &str,
+    data: &[u8],
+) -> crate::error::Result<(usize, usize)> {
+    let temp_dir = crate::disk::tempdir()?;
+    let input_path = temp_dir.path().join(name);
+    tokio::fs::write(&input_path, data).await?;
+
+    let _slot = router.conversion_slot().await;
+    let options = ConvertOptions::default();
+    let conversion = engine.convert(&input_path, &options);
+    let output = tokio::time::timeout(SELFTEST_TIMEOUT, conversion)
+        .await
+        .map_err(|_| {
+            AppError::ConversionFailed(format!("timed out after {}s", SELFTEST_TIMEOUT.as_secs()))
+        })??;
+
+    let pages = pdf::page_count(&output.data)?;
+    if pages == 0 {
+        return Err(AppError::ConversionFailed(
+            "output has no pages".to_string(),
+        ));
+    }
+    Ok((output.data.len(), pages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sniff::ContentCheck;
+
+    #[test]
+    fn test_fixtures_match_their_extensions() {
+        for (name, data) in FIXTURES {
+            assert!(
+                ContentCheck::Reject.verify(name, None, data).is_ok(),
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! Upload verification: the part's declared content type and the file's
+//! leading bytes must agree with its extension, so a failed download saved
+//! as `report.docx` is caught before it reaches an engine.
+
+use crate::error::{AppError, Result};
+use crate::metrics;
+use tracing::warn;
+
+/// What to do when an upload does not look like its extension
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ContentCheck {
+    Reject,
+    #[default]
+    Warn,
+    Ignore,
+}
+
+impl ContentCheck {
+    /// Configured by `PDFMILL_CONTENT_CHECK` (`reject`, `warn` or `ignore`)
+    pub fn from_env() -> std::result::Result<Self, String> {
+        match std::env::var("PDFMILL_CONTENT_CHECK") {
+            Ok(value) => match value.trim().to_lowercase().as_str() {
+                "reject" => Ok(Self::Reject),
+                "warn" => Ok(Self::Warn),
+                "ignore" => Ok(Self::Ignore),
+                other => Err(format!(
+                    "PDFMILL_CONTENT_CHECK: expected reject, warn or ignore, got '{}'",
+                    other
+                )),
+            },
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Check an uploaded file, failing only in `Reject` mode
+    pub fn verify(self, filename: &str, declared: Option<&str>, data: &[u8]) -> Result<()> {
+        if self == Self::Ignore {
+            return Ok(());
+        }
+        let Some(problem) = mismatch(filename, declared, data) else {
+            return Ok(());
+        };
+
+        metrics::increment("pdfmill_content_mismatches_total", &[]);
+        if self == Self::Reject {
+            return Err(AppError::UnsupportedFormat(problem));
+        }
+        warn!("Accepting suspicious upload: {}", problem);
+        Ok(())
+    }
+}
+
+/// File families recognisable from their first bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Pdf,
+    /// OOXML and OpenDocument packages
+    Zip,
+    /// Legacy Office compound documents
+    Ole,
+    Rtf,
+    WordPerfect,
+    Png,
+    Jpeg,
+    Gif,
+    Bmp,
+    Tiff,
+    Webp,
+    Heif,
+    Ico,
+    Pst,
+    Gzip,
+    Tar,
+    Html,
+    /// Anything else without NUL bytes
+    Text,
+}
+
+impl Kind {
+    fn describe(self) -> &'static str {
+        match self {
+            Kind::Pdf => "a PDF document",
+            Kind::Zip => "a ZIP package",
+            Kind::Ole => "a legacy Office document",
+            Kind::Rtf => "an RTF document",
+            Kind::WordPerfect => "a WordPerfect document",
+            Kind::Png => "a PNG image",
+            Kind::Jpeg => "a JPEG image",
+            Kind::Gif => "a GIF image",
+            Kind::Bmp => "a BMP image",
+            Kind::Tiff => "a TIFF image",
+            Kind::Webp => "a WebP image",
+            Kind::Heif => "a HEIF image",
+            Kind::Ico => "an icon",
+            Kind::Pst => "an Outlook mailbox",
+            Kind::Gzip => "a gzip file",
+            Kind::Tar => "a tar archive",
+            Kind::Html => "an HTML page",
+            Kind::Text => "plain text",
+        }
+    }
+}
+
+/// The family an extension promises and the content types it may be
+/// declared with
+fn expected(extension: &str) -> Option<(&'static [Kind], &'static [&'static str])> {
+    const TEXT: &[Kind] = &[Kind::Text, Kind::Html];
+
+    Some(match extension {
+        "pdf" => (&[Kind::Pdf], &["application/pdf", "application/x-pdf"]),
+        "docx" => (
+            &[Kind::Zip],
+            &[
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+                "application/zip",
+                "application/x-zip-compressed",
+            ],
+        ),
+        "xlsx" => (
+            &[Kind::Zip],
+            &[
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                "application/zip",
+                "application/x-zip-compressed",
+            ],
+        ),
+        "pptx" => (
+            &[Kind::Zip],
+            &[
+                "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+                "application/zip",
+                "application/x-zip-compressed",
+            ],
+        ),
+        "odt" => (&[Kind::Zip], &["application/vnd.oasis.opendocument.text"]),
+        "ods" => (
+            &[Kind::Zip],
+            &["application/vnd.oasis.opendocument.spreadsheet"],
+        ),
+        "odp" => (
+            &[Kind::Zip],
+            &["application/vnd.oasis.opendocument.presentation"],
+        ),
+        "pages" => (
+            &[Kind::Zip],
+            &[
+                "application/vnd.apple.pages",
+                "application/x-iwork-pages-sffpages",
+                "application/zip",
+            ],
+        ),
+        "numbers" => (
+            &[Kind::Zip],
+            &[
+                "application/vnd.apple.numbers",
+                "application/x-iwork-numbers-sffnumbers",
+                "application/zip",
+            ],
+        ),
+        "key" => (
+            &[Kind::Zip],
+            &[
+                "application/vnd.apple.keynote",
+                "application/x-iwork-keynote-sffkey",
+                "application/zip",
+            ],
+        ),
+        "sxw" => (&[Kind::Zip], &["application/vnd.sun.xml.writer"]),
+        "sxc" => (&[Kind::Zip], &["application/vnd.sun.xml.calc"]),
+        "sxi" => (&[Kind::Zip], &["application/vnd.sun.xml.impress"]),
+        "doc" => (&[Kind::Ole], &["application/msword"]),
+        "xls" => (
+            &[Kind::Ole],
+            &["application/vnd.ms-excel", "application/msexcel"],
+        ),
+        "ppt" => (
+            &[Kind::Ole],
+            &["application/vnd.ms-powerpoint", "application/mspowerpoint"],
+        ),
+        "rtf" => (
+            &[Kind::Rtf],
+            &["application/rtf", "text/rtf", "application/msword"],
+        ),
+        "wps" => (&[Kind::Ole], &["application/vnd.ms-works"]),
+        "wpd" => (
+            &[Kind::WordPerfect],
+            &["application/vnd.wordperfect", "application/wordperfect"],
+        ),
+        "png" => (&[Kind::Png], &["image/png"]),
+        "jpg" | "jpeg" => (&[Kind::Jpeg], &["image/jpeg", "image/pjpeg"]),
+        "gif" => (&[Kind::Gif], &["image/gif"]),
+        "bmp" => (
+            &[Kind::Bmp],
+            &["image/bmp", "image/x-bmp", "image/x-ms-bmp"],
+        ),
+        "tif" | "tiff" => (&[Kind::Tiff], &["image/tiff"]),
+        "webp" => (&[Kind::Webp], &["image/webp"]),
+        "heic" | "heif" => (&[Kind::Heif], &["image/heic", "image/heif"]),
+        "ico" => (&[Kind::Ico], &["image/x-icon", "image/vnd.microsoft.icon"]),
+        "pst" | "ost" => (&[Kind::Pst], &["application/vnd.ms-outlook"]),
+        "zip" => (
+            &[Kind::Zip],
+            &["application/zip", "application/x-zip-compressed"],
+        ),
+        "tar" => (&[Kind::Tar], &["application/x-tar"]),
+        "tgz" | "gz" => (
+            &[Kind::Gzip],
+            &[
+                "application/gzip",
+                "application/x-gzip",
+                "application/x-compressed-tar",
+            ],
+        ),
+        "svg" => (
+            TEXT,
+            &["image/svg+xml", "text/xml", "application/xml", "text/plain"],
+        ),
+        "html" | "htm" | "xhtml" => (TEXT, &["text/html", "application/xhtml+xml"]),
+        "md" | "markdown" => (TEXT, &["text/markdown", "text/x-markdown", "text/plain"]),
+        "org" => (TEXT, &["text/org", "text/x-org", "text/plain"]),
+        "geojson" => (
+            TEXT,
+            &["application/geo+json", "application/json", "text/plain"],
+        ),
+        "json" => (TEXT, &["application/json", "text/plain"]),
+        "eml" => (TEXT, &["message/rfc822", "text/plain"]),
+        "kml" => (
+            TEXT,
+            &[
+                "application/vnd.google-earth.kml+xml",
+                "application/xml",
+                "text/xml",
+            ],
+        ),
+        _ => return None,
+    })
+}
+
+/// Recognise a file from its leading bytes
+fn sniff(data: &[u8]) -> Option<Kind> {
+    let signatures: &[(&[u8], Kind)] = &[
+        (b"%PDF-", Kind::Pdf),
+        (b"PK\x03\x04", Kind::Zip),
+        (b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1", Kind::Ole),
+        (b"{\\rtf", Kind::Rtf),
+        (b"\xFFWPC", Kind::WordPerfect),
+        (b"\x89PNG\r\n\x1A\n", Kind::Png),
+        (b"\xFF\xD8\xFF", Kind::Jpeg),
+        (b"GIF87a", Kind::Gif),
+        (b"GIF89a", Kind::Gif),
+        (b"II*\0", Kind::Tiff),
+        (b"MM\0*", Kind::Tiff),
+        (b"\0\0\x01\0", Kind::Ico),
+        (b"!BDN", Kind::Pst),
+        (b"\x1F\x8B", Kind::Gzip),
+    ];
+    if let Some((_, kind)) = signatures.iter().find(|(magic, _)| data.starts_with(magic)) {
+        return Some(*kind);
+    }
+    // "BM" alone is too common at the start of text; check the reserved field
+    if data.len() >= 14 && data.starts_with(b"BM") && data[6..10] == [0; 4] {
+        return Some(Kind::Bmp);
+    }
+    if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return Some(Kind::Webp);
+    }
+    if data.len() >= 262 && &data[257..262] == b"ustar" {
+        return Some(Kind::Tar);
+    }
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        return Some(Kind::Heif);
+    }
+
+    // Text in any ASCII-compatible encoding has no NUL bytes
+    let head = &data[..data.len().min(1024)];
+    if head.contains(&0) {
+        return None;
+    }
+    let text = String::from_utf8_lossy(head);
+    let start = text
+        .trim_start_matches('\u{FEFF}')
+        .trim_start()
+        .to_lowercase();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        return Some(Kind::Html);
+    }
+    Some(Kind::Text)
+}
+
+/// Why the upload does not look like its extension, if it doesn't
+fn mismatch(filename: &str, declared: Option<&str>, data: &[u8]) -> Option<String> {
+    let extension = std::path::Path::new(filename)
+        .extension()?
+        .to_str()?
+        .to_lowercase();
+    let (kinds, content_types) = expected(&extension)?;
+
+    if !data.is_empty() {
+        match sniff(data) {
+            Some(kind) if kinds.contains(&kind) => {}
+            Some(kind) => {
+                return Some(format!(
+                    "{} looks like {}, not a .{} file",
+                    filename,
+                    kind.describe(),
+                    extension
+                ))
+            }
+            None => {
+                return Some(format!(
+                    "{} does not look like a .{} file",
+                    filename, extension
+                ))
+            }
+        }
+    }
+
+    // Clients that do not know the type send a generic one
+    let declared = declared?
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_lowercase();
+    let generic = [
+        "",
+        "application/octet-stream",
+        "binary/octet-stream",
+        "application/binary",
+    ];
+    if generic.contains(&declared.as_str()) || content_types.contains(&declared.as_str()) {
+        return None;
+    }
+    Some(format!(
+        "{} was sent as {}, which does not match a .{} file",
+        filename, declared, extension
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mismatch() {
+        let html = b"<!DOCTYPE html><html><body>Session expired</body></html>";
+        let docx = b"PK\x03\x04\x14\0\x06\0";
+
+        assert!(mismatch("report.docx", None, docx).is_none());
+        assert!(mismatch(
+            "report.docx",
+            Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+            docx
+        )
+        .is_none());
+        assert!(mismatch("page.html", Some("text/html; charset=utf-8"), html).is_none());
+        assert!(mismatch(
+            "notes.md",
+            Some("application/octet-stream"),
+            "# Grüße".as_bytes()
+        )
+        .is_none());
+
+        let problem = mismatch("report.docx", None, html).unwrap();
+        assert!(problem.contains("an HTML page"), "{}", problem);
+        assert!(mismatch("report.docx", Some("text/html"), docx).is_some());
+        assert!(mismatch("scan.png", None, b"\xFF\xD8\xFF\xE0").is_some());
+        assert!(mismatch("page.html", None, b"\x89PNG\r\n\x1A\n\0\0").is_some());
+        assert!(mismatch("letter.wpd", None, b"\xFFWPC\x10\0\0\0").is_none());
+        assert!(mismatch("letter.wpd", None, docx).is_some());
+        let mut tar = vec![0u8; 512];
+        tar[257..263].copy_from_slice(b"ustar\0");
+        assert!(mismatch("transfer.tar", None, &tar).is_none());
+        assert!(mismatch("transfer.tar.gz", None, b"\x1F\x8B\x08\0").is_none());
+
+        // Unknown extensions are left to the router
+        assert!(mismatch("data.xyz", Some("text/html"), html).is_none());
+        assert!(ContentCheck::Reject
+            .verify("report.docx", None, html)
+            .is_err());
+        assert!(ContentCheck::Warn.verify("report.docx", None, html).is_ok());
+    }
+}
@@ -0,0 +1,79 @@
+//! systemd integration: socket activation (`LISTEN_FDS`) and readiness
+//! notification for `Type=notify` services (`NOTIFY_SOCKET`). Both are
+//! no-ops when the process was not started by systemd.
+
+use tracing::{info, warn};
+
+/// First file descriptor passed by socket activation (SD_LISTEN_FDS_START)
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// The listening socket handed over by systemd socket activation, if any
+#[cfg(unix)]
+pub fn activated_listener() -> Option<std::net::TcpListener> {
+    use std::os::fd::FromRawFd;
+
+    let pid = std::env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+    if pid != std::process::id() {
+        return None;
+    }
+    let count = std::env::var("LISTEN_FDS").ok()?.parse::<i32>().ok()?;
+    if count < 1 {
+        return None;
+    }
+    if count > 1 {
+        warn!("systemd passed {} sockets; only the first is used", count);
+    }
+
+    // Child processes (LibreOffice, ImageMagick, ...) must not see these
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    // SAFETY: systemd guarantees the descriptor is an open socket owned by
+    // this process, and it is wrapped exactly once
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    info!("Using socket passed by systemd socket activation");
+    Some(listener)
+}
+
+#[cfg(not(unix))]
+pub fn activated_listener() -> Option<std::net::TcpListener> {
+    None
+}
+
+/// Tell systemd the service is ready to accept requests
+pub fn notify_ready() {
+    if let Err(e) = notify("READY=1") {
+        warn!("Failed to notify systemd: {}", e);
+    }
+}
+
+#[cfg(unix)]
+fn notify(state: &str) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let socket = UnixDatagram::unbound()?;
+    let path = path.as_bytes();
+
+    // "@name" refers to the Linux abstract socket namespace
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.strip_prefix(b"@") {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        socket.send_to_addr(state.as_bytes(), &addr)?;
+        return Ok(());
+    }
+
+    socket.send_to(state.as_bytes(), std::ffi::OsStr::from_bytes(path))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn notify(_state: &str) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,504 @@
+//! Server-side template library: named HTML/Tera templates with their
+//! assets (images, stylesheets, fonts), so `/render` can be called with a
+//! template name and JSON data instead of the full document every time.
+//! Every upload is kept as a new version; `invoice@v3` pins one, so an old
+//! document can be regenerated with its original layout.
+
+use crate::error::{AppError, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tera::Tera;
+use tracing::{info, warn};
+
+/// File the template source is kept in, inside a version's directory
+const SOURCE_FILE: &str = "template.html";
+
+/// Directory the assets are kept in, inside a version's directory
+const ASSETS_DIR: &str = "assets";
+
+/// Longest template or asset name
+const MAX_NAME_LEN: usize = 100;
+
+/// Separates a template name from a pinned version: `invoice@v3`
+const VERSION_SEPARATOR: char = '@';
+
+/// A stored template version, parsed and ready to render
+pub struct Template {
+    pub name: String,
+    /// Numbered from 1, in upload order
+    pub version: u32,
+    pub source: String,
+    /// Files the template refers to by relative URL, e.g. `logo.png`
+    pub assets: BTreeMap<String, Vec<u8>>,
+    pub created_at: DateTime<Utc>,
+    tera: Tera,
+}
+
+/// Public view of a template version, as returned by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateInfo {
+    pub name: String,
+    pub version: u32,
+    /// `name@vN`, the reference that renders exactly this version
+    pub reference: String,
+    pub size: usize,
+    pub assets: Vec<AssetInfo>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetInfo {
+    pub name: String,
+    pub size: usize,
+}
+
+/// A template in listings: its latest version and how many there are
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateSummary {
+    #[serde(flatten)]
+    pub latest: TemplateInfo,
+    pub versions: usize,
+}
+
+impl Template {
+    fn new(
+        name: &str,
+        version: u32,
+        source: String,
+        assets: BTreeMap<String, Vec<u8>>,
+        created_at: DateTime<Utc>,
+    ) -> Result<Self> {
+        check_name("name", name)?;
+        for asset in assets.keys() {
+            check_name("asset", asset)?;
+        }
+        let mut tera = Tera::default();
+        // The .html name turns on autoescaping of substituted values
+        tera.add_raw_template(SOURCE_FILE, &source)
+            .map_err(|e| AppError::InvalidRequest(format!("template: {}", tera_message(&e))))?;
+        Ok(Self {
+            name: name.to_string(),
+            version,
+            source,
+            assets,
+            created_at,
+            tera,
+        })
+    }
+
+    /// `name@vN`
+    pub fn reference(&self) -> String {
+        format!("{}{}v{}", self.name, VERSION_SEPARATOR, self.version)
+    }
+
+    pub fn info(&self) -> TemplateInfo {
+        TemplateInfo {
+            name: self.name.clone(),
+            version: self.version,
+            reference: self.reference(),
+            size: self.source.len(),
+            assets: self
+                .assets
+                .iter()
+                .map(|(name, data)| AssetInfo {
+                    name: name.clone(),
+                    size: data.len(),
+                })
+                .collect(),
+            created_at: self.created_at,
+        }
+    }
+
+    /// Render the template with `data`, a JSON object of variables
+    pub fn render(&self, data: &Value) -> Result<String> {
+        let context = tera::Context::from_value(data.clone())
+            .map_err(|_| AppError::InvalidRequest("data: expected a JSON object".to_string()))?;
+        self.tera.render(SOURCE_FILE, &context).map_err(|e| {
+            AppError::InvalidRequest(format!(
+                "template {}: {}",
+                self.reference(),
+                tera_message(&e)
+            ))
+        })
+    }
+
+    /// Write the rendered document and the assets into `dir`, returning the
+    /// document's path; the assets resolve as relative URLs
+    pub async fn write_to(&self, dir: &Path, html: &str) -> Result<PathBuf> {
+        for (name, data) in &self.assets {
+            tokio::fs::write(dir.join(name), data).await?;
+        }
+        let path = dir.join(format!("{}.html", self.name));
+        tokio::fs::write(&path, html).await?;
+        Ok(path)
+    }
+}
+
+/// Template names and asset file names: letters, digits, `-`, `_` and `.`,
+/// not starting with a dot
+pub(crate) fn check_name(field: &str, name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(AppError::InvalidRequest(format!(
+            "{}: '{}' must be 1-{} letters, digits, '-', '_' or '.', not starting with '.'",
+            field, name, MAX_NAME_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Split `invoice@v3` (or `invoice@3`) into the name and pinned version;
+/// a bare name means the latest version
+pub fn parse_reference(reference: &str) -> Result<(&str, Option<u32>)> {
+    let Some((name, version)) = reference.split_once(VERSION_SEPARATOR) else {
+        return Ok((reference, None));
+    };
+    match parse_version(version) {
+        Some(number) => Ok((name, Some(number))),
+        None => Err(AppError::InvalidRequest(format!(
+            "template: invalid version '{}' (expected e.g. {}{}v3)",
+            version, name, VERSION_SEPARATOR
+        ))),
+    }
+}
+
+/// A version number written `v3` or `3`
+pub fn parse_version(version: &str) -> Option<u32> {
+    let number = version.strip_prefix('v').unwrap_or(version);
+    number.parse::<u32>().ok().filter(|n| *n > 0)
+}
+
+/// A Tera error with its causes, which hold the useful part
+pub(crate) fn tera_message(error: &tera::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+fn not_found(name: &str) -> AppError {
+    AppError::NotFound(format!("Template {} not found", name))
+}
+
+/// In-memory template library, optionally persisted to a directory
+pub struct TemplateStore {
+    /// Versions of each template, oldest first
+    templates: Mutex<HashMap<String, Vec<Arc<Template>>>>,
+    dir: Option<PathBuf>,
+}
+
+impl TemplateStore {
+    /// Templates are kept in `PDFMILL_TEMPLATES_DIR` when set (a directory
+    /// per template, holding one per version), otherwise they are lost on
+    /// restart
+    pub fn from_env() -> Self {
+        let dir = std::env::var("PDFMILL_TEMPLATES_DIR")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(PathBuf::from);
+        let store = Self {
+            templates: Mutex::new(HashMap::new()),
+            dir,
+        };
+        store.load();
+        store
+    }
+
+    fn load(&self) {
+        let Some(dir) = &self.dir else { return };
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => {
+                warn!(
+                    "Failed to read templates directory {}: {}",
+                    dir.display(),
+                    e
+                );
+                return;
+            }
+        };
+
+        let mut templates = self.templates.lock().unwrap();
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') || !entry.path().is_dir() {
+                continue;
+            }
+            let versions = read_versions(&entry.path(), &name);
+            if !versions.is_empty() {
+                templates.insert(name, versions);
+            }
+        }
+        info!(
+            "Loaded {} templates from {}",
+            templates.len(),
+            dir.display()
+        );
+    }
+
+    /// Store a new version of a template; true when the template is new
+    pub fn put(
+        &self,
+        name: &str,
+        source: String,
+        assets: BTreeMap<String, Vec<u8>>,
+    ) -> Result<(TemplateInfo, bool)> {
+        let mut templates = self.templates.lock().unwrap();
+        let versions = templates.get(name);
+        let created = versions.is_none();
+        let version = versions.and_then(|v| v.last()).map_or(1, |t| t.version + 1);
+        let template = Template::new(name, version, source, assets, Utc::now())?;
+        if let Some(dir) = &self.dir {
+            write_version(dir, &template)?;
+        }
+        let info = template.info();
+        templates
+            .entry(name.to_string())
+            .or_default()
+            .push(Arc::new(template));
+        info!(
+            "Stored template {} ({} assets)",
+            info.reference,
+            info.assets.len()
+        );
+        Ok((info, created))
+    }
+
+    /// Make an old version current again, as a new version with its content
+    pub fn rollback(&self, name: &str, version: u32) -> Result<TemplateInfo> {
+        let old = self.get(&format!("{}{}v{}", name, VERSION_SEPARATOR, version))?;
+        let (info, _) = self.put(name, old.source.clone(), old.assets.clone())?;
+        info!("Rolled template {} back to v{}", name, version);
+        Ok(info)
+    }
+
+    /// The template `reference` names: `invoice` for the latest version,
+    /// `invoice@v3` for a pinned one
+    pub fn get(&self, reference: &str) -> Result<Arc<Template>> {
+        let (name, version) = parse_reference(reference)?;
+        let templates = self.templates.lock().unwrap();
+        let versions = templates.get(name).ok_or_else(|| not_found(name))?;
+        match version {
+            None => versions.last().cloned().ok_or_else(|| not_found(name)),
+            Some(version) => versions
+                .iter()
+                .find(|t| t.version == version)
+                .cloned()
+                .ok_or_else(|| {
+                    AppError::NotFound(format!("Template {} has no version v{}", name, version))
+                }),
+        }
+    }
+
+    /// Every version of a template, oldest first
+    pub fn versions(&self, name: &str) -> Result<Vec<TemplateInfo>> {
+        let templates = self.templates.lock().unwrap();
+        let versions = templates.get(name).ok_or_else(|| not_found(name))?;
+        Ok(versions.iter().map(|t| t.info()).collect())
+    }
+
+    /// All templates, by name
+    pub fn list(&self) -> Vec<TemplateSummary> {
+        let mut templates: Vec<TemplateSummary> = self
+            .templates
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|versions| {
+                Some(TemplateSummary {
+                    latest: versions.last()?.info(),
+                    versions: versions.len(),
+                })
+            })
+            .collect();
+        templates.sort_by(|a, b| a.latest.name.cmp(&b.latest.name));
+        templates
+    }
+
+    /// Delete a template with all its versions
+    pub fn delete(&self, name: &str) -> Result<()> {
+        if parse_reference(name)?.1.is_some() {
+            return Err(AppError::InvalidRequest(
+                "template: versions cannot be deleted one by one".to_string(),
+            ));
+        }
+        let mut templates = self.templates.lock().unwrap();
+        templates.remove(name).ok_or_else(|| not_found(name))?;
+        if let Some(dir) = &self.dir {
+            std::fs::remove_dir_all(dir.join(name))?;
+        }
+        info!("Deleted template {}", name);
+        Ok(())
+    }
+}
+
+/// The `v1`, `v2`, ... directories of a stored template, oldest first
+fn read_versions(path: &Path, name: &str) -> Vec<Arc<Template>> {
+    let mut versions = Vec::new();
+    // Templates stored before versioning keep their files at the top
+    if path.join(SOURCE_FILE).is_file() {
+        match read_version(path, name, 1) {
+            Ok(template) => versions.push(Arc::new(template)),
+            Err(e) => warn!("Skipping stored template {}: {}", name, e),
+        }
+    }
+    for entry in std::fs::read_dir(path).into_iter().flatten().flatten() {
+        let dir_name = entry.file_name().to_string_lossy().into_owned();
+        let Some(version) = dir_name
+            .strip_prefix('v')
+            .and_then(|v| v.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        match read_version(&entry.path(), name, version) {
+            Ok(template) => versions.push(Arc::new(template)),
+            Err(e) => warn!("Skipping stored template {}@v{}: {}", name, version, e),
+        }
+    }
+    versions.sort_by_key(|t| t.version);
+    versions.dedup_by_key(|t| t.version);
+    versions
+}
+
+fn read_version(path: &Path, name: &str, version: u32) -> Result<Template> {
+    let source_path = path.join(SOURCE_FILE);
+    let source = std::fs::read_to_string(&source_path)?;
+    let created_at = std::fs::metadata(&source_path)?.modified()?.into();
+    let mut assets = BTreeMap::new();
+    if let Ok(entries) = std::fs::read_dir(path.join(ASSETS_DIR)) {
+        for entry in entries.flatten() {
+            let asset = entry.file_name().to_string_lossy().into_owned();
+            assets.insert(asset, std::fs::read(entry.path())?);
+        }
+    }
+    Template::new(name, version, source, assets, created_at)
+}
+
+/// Write a version's directory under a temporary name, then rename it so a
+/// crash never leaves it half written
+fn write_version(dir: &Path, template: &Template) -> Result<()> {
+    let template_dir = dir.join(&template.name);
+    let staging = template_dir.join(format!(".v{}.new", template.version));
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    std::fs::create_dir_all(staging.join(ASSETS_DIR))?;
+    std::fs::write(staging.join(SOURCE_FILE), &template.source)?;
+    for (name, data) in &template.assets {
+        std::fs::write(staging.join(ASSETS_DIR).join(name), data)?;
+    }
+    std::fs::rename(
+        &staging,
+        template_dir.join(format!("v{}", template.version)),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_store_and_render() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = TemplateStore {
+            templates: Mutex::new(HashMap::new()),
+            dir: Some(dir.path().to_path_buf()),
+        };
+        let assets = BTreeMap::from([("logo.png".to_string(), b"png".to_vec())]);
+        let source = "<img src=\"logo.png\"><h1>{{ customer }}</h1>{% for item in items %}<li>{{ item }}</li>{% endfor %}";
+        let (info, created) = store.put("invoice", source.to_string(), assets).unwrap();
+        assert!(created);
+        assert_eq!(info.assets[0].name, "logo.png");
+
+        let html = store
+            .get("invoice")
+            .unwrap()
+            .render(&json!({"customer": "<ACME>", "items": ["a", "b"]}))
+            .unwrap();
+        assert_eq!(
+            html,
+            "<img src=\"logo.png\"><h1>&lt;ACME&gt;</h1><li>a</li><li>b</li>"
+        );
+
+        // Persisted: a new store reads it back
+        let reloaded = TemplateStore {
+            templates: Mutex::new(HashMap::new()),
+            dir: Some(dir.path().to_path_buf()),
+        };
+        reloaded.load();
+        assert_eq!(reloaded.get("invoice").unwrap().assets["logo.png"], b"png");
+
+        assert!(store
+            .put("broken", "{% if %}".to_string(), BTreeMap::new())
+            .is_err());
+        assert!(store
+            .put("../etc", "x".to_string(), BTreeMap::new())
+            .is_err());
+        store.delete("invoice").unwrap();
+        assert!(!dir.path().join("invoice").exists());
+    }
+
+    #[test]
+    fn test_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = TemplateStore {
+            templates: Mutex::new(HashMap::new()),
+            dir: Some(dir.path().to_path_buf()),
+        };
+        let render = |reference: &str| store.get(reference).unwrap().render(&json!({})).unwrap();
+        store
+            .put("invoice", "one".to_string(), BTreeMap::new())
+            .unwrap();
+        let (second, created) = store
+            .put("invoice", "two".to_string(), BTreeMap::new())
+            .unwrap();
+        assert!(!created);
+        assert_eq!(second.reference, "invoice@v2");
+
+        assert_eq!(render("invoice"), "two");
+        assert_eq!(render("invoice@v1"), "one");
+        assert_eq!(render("invoice@1"), "one");
+        assert!(matches!(
+            store.get("invoice@v9"),
+            Err(AppError::NotFound(_))
+        ));
+        assert!(matches!(
+            store.get("invoice@latest"),
+            Err(AppError::InvalidRequest(_))
+        ));
+
+        // A rollback adds the old content as the newest version
+        assert_eq!(store.rollback("invoice", 1).unwrap().version, 3);
+        assert_eq!(render("invoice"), "one");
+
+        let reloaded = TemplateStore {
+            templates: Mutex::new(HashMap::new()),
+            dir: Some(dir.path().to_path_buf()),
+        };
+        reloaded.load();
+        let versions: Vec<u32> = reloaded
+            .versions("invoice")
+            .unwrap()
+            .iter()
+            .map(|v| v.version)
+            .collect();
+        assert_eq!(versions, vec![1, 2, 3]);
+        assert!(store.delete("invoice@v2").is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! Windows service integration: when started by the service control manager
+//! the server reports its state to it and stops on a stop request. A no-op
+//! when the process was started from a console.
+
+#[cfg(windows)]
+use std::ffi::OsString;
+#[cfg(windows)]
+use std::sync::Arc;
+#[cfg(windows)]
+use std::time::Duration;
+#[cfg(windows)]
+use tracing::{error, warn};
+#[cfg(windows)]
+use windows_service::{
+    define_windows_service,
+    service::{
+        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+        ServiceType,
+    },
+    service_control_handler::{self, ServiceControlHandlerResult},
+    service_dispatcher,
+};
+
+/// Name passed to the service control manager; an own-process service may
+/// be installed under any name
+#[cfg(windows)]
+const SERVICE_NAME: &str = "pdfmill";
+
+/// ERROR_FAILED_SERVICE_CONTROLLER_CONNECT: the process is not a service
+#[cfg(windows)]
+const NOT_A_SERVICE: i32 = 1063;
+
+/// How long the service control manager waits for the engines to be
+/// detected before it considers the start failed
+#[cfg(windows)]
+const START_WAIT_HINT: Duration = Duration::from_secs(60);
+
+/// Run the server as a Windows service if the service control manager
+/// started the process, returning once the service stopped; false when
+/// started from a console
+#[cfg(windows)]
+pub fn dispatch() -> bool {
+    match service_dispatcher::start(SERVICE_NAME, ffi_service_main) {
+        Ok(()) => true,
+        Err(windows_service::Error::Winapi(e)) if e.raw_os_error() == Some(NOT_A_SERVICE) => false,
+        Err(e) => {
+            eprintln!("Failed to start the Windows service: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub fn dispatch() -> bool {
+    false
+}
+
+#[cfg(windows)]
+define_windows_service!(ffi_service_main, service_main);
+
+/// Called by the service control manager on a thread of its own
+#[cfg(windows)]
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        error!("Windows service failed: {}", e);
+    }
+}
+
+#[cfg(windows)]
+fn run_service() -> windows_service::Result<()> {
+    let stop = Arc::new(tokio::sync::Notify::new());
+    let stop_requested = Arc::clone(&stop);
+    let status_handle =
+        service_control_handler::register(SERVICE_NAME, move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                stop_requested.notify_one();
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+
+    let report = move |state: ServiceState, controls_accepted: ServiceControlAccept| {
+        status_handle.set_service_status(ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint: 0,
+            wait_hint: match state {
+                ServiceState::StartPending => START_WAIT_HINT,
+                _ => Duration::default(),
+            },
+            process_id: None,
+        })
+    };
+    report(ServiceState::StartPending, ServiceControlAccept::empty())?;
+
+    let runtime = tokio::runtime::Runtime::new().map_err(windows_service::Error::Winapi)?;
+    runtime.block_on(crate::serve(
+        move || {
+            let accepted = ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN;
+            if let Err(e) = report(ServiceState::Running, accepted) {
+                warn!("Failed to report the Windows service as running: {}", e);
+            }
+        },
+        async move { stop.notified().await },
+    ));
+    drop(runtime);
+
+    report(ServiceState::Stopped, ServiceControlAccept::empty())
+}